@@ -0,0 +1,41 @@
+//! Build-time validation of config.toml
+//!
+//! Fails the build on invalid resource URLs and SRI strings and on URLs pinned to
+//! conflicting hashes; see `src/buildcheck.rs` for the checks and their tests.
+
+use std::process::ExitCode;
+
+#[allow(dead_code)]
+#[path = "src/sri.rs"]
+mod sri;
+
+#[path = "src/buildcheck.rs"]
+mod buildcheck;
+
+use buildcheck::CONFIG_PATH;
+
+fn main() -> ExitCode {
+    println!("cargo:rerun-if-changed={}", CONFIG_PATH);
+    println!("cargo:rerun-if-changed=src/sri.rs");
+    println!("cargo:rerun-if-changed=src/buildcheck.rs");
+
+    let text = match std::fs::read_to_string(CONFIG_PATH) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", CONFIG_PATH, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let report = buildcheck::check(&text);
+    for warning in &report.warnings {
+        println!("cargo:warning={}", warning);
+    }
+    for error in &report.errors {
+        eprintln!("error: {}", error);
+    }
+    if report.errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
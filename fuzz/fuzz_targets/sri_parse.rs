@@ -0,0 +1,19 @@
+//! Fuzz the SRI parser: `cargo +nightly fuzz run sri_parse`
+//!
+//! Parsing must never panic, and every accepted hash must survive a round trip
+//! through its canonical string form.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use linkkivahti::sri::SriHash;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(hash) = SriHash::parse(input) {
+        assert_eq!(SriHash::parse(&hash.to_string()), Ok(hash.clone()));
+        hash.verify(data);
+    }
+});
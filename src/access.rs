@@ -0,0 +1,242 @@
+//! Access log of incoming requests (`GET /stats`)
+//!
+//! Every HTTP request is counted per endpoint and per caller, with the time it
+//! was last seen, in a single KV document per scope. Callers are identified by
+//! token name; requests without a token count as `anonymous` and requests with an
+//! unknown token as `invalid`. Requests to paths the worker does not serve are
+//! counted under `unmatched`, and the most recent of them are kept verbatim, so
+//! probing clients stand out.
+//!
+//! The document is updated after the response is sent. Concurrent requests can
+//! overwrite each other's update (KV has no atomic increments), so the counters
+//! are approximate under load.
+
+use crate::action;
+use crate::approval;
+use crate::clock;
+use crate::console::console_error;
+use crate::managed;
+use crate::silence;
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+/// KV key of the access log, relative to the scope prefix
+const STATS_KEY: &str = "stats";
+
+/// Endpoint label of requests to paths the worker does not serve
+pub const UNMATCHED: &str = "unmatched";
+
+/// Number of unmatched requests kept verbatim
+const RECENT_UNMATCHED: usize = 20;
+
+/// Endpoints with fixed paths, as `METHOD /path`
+const ENDPOINTS: &[&str] = &[
+    "GET /",
+    "POST /check",
+    "GET /check/nagios",
+    "POST /notify",
+    "GET /history",
+    "POST /history/import",
+    "GET /resources",
+    "POST /resources",
+    "GET /metrics",
+    "GET /stats",
+    "GET /stats/usage",
+    "GET /analytics/alerts",
+    "GET /reports/sla",
+    "GET /zabbix/discovery",
+    "GET /zabbix/items",
+    "GET /notifications/last",
+    "GET /events/log",
+    "GET /silences",
+    "POST /silences",
+    "POST /sri",
+    "GET /sri/pending",
+    "GET /propagation",
+    "POST /config/sync",
+    "POST /config/d1",
+    "GET /config/export",
+    "GET /config/template",
+    "POST /admin/token/rotate",
+    "PUT /admin/monitoring",
+    "POST /callbacks/ack",
+    "POST /slack/interactions",
+    "POST /slack/command",
+    "POST /discord/interactions",
+];
+
+/// Endpoint label of a request, with path parameters replaced by placeholders
+pub fn endpoint(method: &Method, path: &str) -> String {
+    let request = format!("{} {}", method, path);
+    if ENDPOINTS.contains(&request.as_str()) {
+        return request;
+    }
+    let pattern = match method {
+        Method::Delete if silence::parse_silence_path(path).is_some() => "/silences/{id}",
+        Method::Put | Method::Delete if managed::parse_resource_path(path).is_some() => {
+            "/resources/{id}"
+        }
+        Method::Post if approval::parse_decision_path(path).is_some() => {
+            "/sri/pending/{id}/{decision}"
+        }
+        Method::Get if action::parse_action_path(path).is_some() => "/actions/{token}",
+        Method::Get if path.starts_with("/mirror/") && path.len() > "/mirror/".len() => {
+            "/mirror/{name}"
+        }
+        _ => return UNMATCHED.to_string(),
+    };
+    format!("{} {}", method, pattern)
+}
+
+/// Request count and last-seen time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counter {
+    pub count: u64,
+    /// Milliseconds since the Unix epoch
+    pub last_seen: u64,
+}
+
+impl Counter {
+    fn hit(&mut self, now: u64) {
+        self.count += 1;
+        self.last_seen = now;
+    }
+}
+
+/// A request to a path the worker does not serve
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnmatchedRequest {
+    /// `METHOD /path`
+    pub request: String,
+    pub caller: String,
+    pub at: u64,
+}
+
+/// Request counters of a scope
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessLog {
+    /// Per endpoint label (see `endpoint`)
+    #[serde(default)]
+    pub endpoints: BTreeMap<String, Counter>,
+    /// Per token name, `anonymous` or `invalid`
+    #[serde(default)]
+    pub callers: BTreeMap<String, Counter>,
+    /// Most recent unmatched requests, newest last
+    #[serde(default)]
+    pub unmatched: Vec<UnmatchedRequest>,
+}
+
+impl AccessLog {
+    /// Count a request
+    pub fn record(&mut self, endpoint: &str, request: &str, caller: &str, now: u64) {
+        self.endpoints
+            .entry(endpoint.to_string())
+            .or_default()
+            .hit(now);
+        self.callers.entry(caller.to_string()).or_default().hit(now);
+        if endpoint == UNMATCHED {
+            self.unmatched.push(UnmatchedRequest {
+                request: request.to_string(),
+                caller: caller.to_string(),
+                at: now,
+            });
+            if self.unmatched.len() > RECENT_UNMATCHED {
+                let excess = self.unmatched.len() - RECENT_UNMATCHED;
+                self.unmatched.drain(..excess);
+            }
+        }
+    }
+}
+
+/// Count a request in the access log stored under `key` (see `Scope::key`)
+///
+/// Meant to run after the response through `Context::wait_until`; failures are
+/// only logged.
+pub async fn record(env: Env, key: String, endpoint: String, request: String, caller: String) {
+    let Some(store) = Store::from_env(&env) else {
+        return;
+    };
+    let updated = async {
+        let mut log: AccessLog = store.get(&key).await?.unwrap_or_default();
+        log.record(&endpoint, &request, &caller, clock::now_ms());
+        store.put(&key, &log).await
+    };
+    if let Err(e) = updated.await {
+        console_error!("Failed to update access log: {}", e);
+    }
+}
+
+/// KV key of a scope's access log
+pub fn key(scope: &Scope) -> String {
+    scope.key(STATS_KEY)
+}
+
+/// Handle `GET /stats`
+pub async fn handle_stats(env: &Env, scope: &Scope<'_>) -> Result<Response> {
+    let store = Store::require(env)?;
+    let log: AccessLog = store.get(&key(scope)).await?.unwrap_or_default();
+    Response::from_json(&log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(endpoint(&Method::Post, "/check"), "POST /check");
+        assert_eq!(endpoint(&Method::Get, "/check"), UNMATCHED);
+        assert_eq!(endpoint(&Method::Post, "/config/d1"), "POST /config/d1");
+        assert_eq!(
+            endpoint(&Method::Put, "/admin/monitoring"),
+            "PUT /admin/monitoring"
+        );
+        assert_eq!(
+            endpoint(&Method::Delete, "/silences/abc"),
+            "DELETE /silences/{id}"
+        );
+        assert_eq!(
+            endpoint(&Method::Post, "/sri/pending/abc/approve"),
+            "POST /sri/pending/{id}/{decision}"
+        );
+        assert_eq!(
+            endpoint(&Method::Get, "/mirror/widget.js"),
+            "GET /mirror/{name}"
+        );
+        assert_eq!(endpoint(&Method::Get, "/mirror/"), UNMATCHED);
+        assert_eq!(
+            endpoint(&Method::Get, "/actions/abc.def"),
+            "GET /actions/{token}"
+        );
+        assert_eq!(endpoint(&Method::Get, "/wp-login.php"), UNMATCHED);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut log = AccessLog::default();
+        log.record("POST /check", "POST /check", "ci", 1_000);
+        log.record("POST /check", "POST /check", "ci", 2_000);
+        log.record("GET /", "GET /", "anonymous", 3_000);
+        assert_eq!(
+            log.endpoints["POST /check"],
+            Counter {
+                count: 2,
+                last_seen: 2_000
+            }
+        );
+        assert_eq!(log.callers["ci"].count, 2);
+        assert_eq!(log.callers["anonymous"].last_seen, 3_000);
+        assert!(log.unmatched.is_empty());
+
+        for i in 0..25 {
+            log.record(UNMATCHED, &format!("GET /probe{}", i), "invalid", 4_000 + i);
+        }
+        assert_eq!(log.endpoints[UNMATCHED].count, 25);
+        assert_eq!(log.unmatched.len(), RECENT_UNMATCHED);
+        assert_eq!(log.unmatched[0].request, "GET /probe5");
+        assert_eq!(log.unmatched.last().unwrap().caller, "invalid");
+    }
+}
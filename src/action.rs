@@ -0,0 +1,409 @@
+//! Signed one-time action links in chat notifications (`GET /actions/{token}`)
+//!
+//! Firing alerts sent to Discord, Slack or Zulip carry a "Re-check" link. Opening
+//! it checks just that resource right away, through the regular check pipeline,
+//! and posts the outcome to the scope's webhook, so whoever looks at an alert can
+//! confirm a fix without an access token.
+//!
+//! The link holds a token naming the scope, the resource and an expiry
+//! (`ACTION_LINK_TTL_MINUTES`, default 60), signed with HMAC-SHA256 under the
+//! `ACTION_SIGNING_KEY` secret. Each token works once: its ID is kept in the state
+//! store until the token expires. Links are only added when the signing key,
+//! `PUBLIC_URL` (the worker's base URL) and the state store are all configured.
+//!
+//! The buttons of Slack and Discord alerts (see `slack` and `discord`) run the
+//! same actions through `run_button`.
+
+use crate::alert::{self, AlertStream};
+use crate::clock;
+use crate::config::{self, Config, Resource};
+use crate::console::{console_error, console_log};
+use crate::maintenance;
+use crate::notify;
+use crate::run::Deadline;
+use crate::silence::{self, SilenceRequest};
+use crate::store::Store;
+use crate::tenant::Scope;
+use crate::trace;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use worker::*;
+
+const ACTIONS_PATH_PREFIX: &str = "/actions/";
+
+/// KV key prefix of used token IDs, relative to the scope prefix
+const USED_PREFIX: &str = "actions/used/";
+
+/// Default lifetime of action links
+const DEFAULT_TTL_MINUTES: u64 = 60;
+
+/// User agents of chat link previews, which must not use up a token
+const PREVIEW_AGENTS: &[&str] = &["Slackbot", "Discordbot", "Zulip", "facebookexternalhit"];
+
+/// Duration of silences created with the "Silence 1h" button
+const SILENCE_MINUTES: u64 = 60;
+
+/// Buttons on firing chat alerts: action ID and label
+pub const BUTTONS: &[(&str, &str)] = &[
+    ("recheck", "Re-check now"),
+    ("silence_1h", "Silence 1h"),
+    ("ack", "Ack"),
+];
+
+/// What a token allows, signed as part of the link
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    /// Token ID, remembered once used
+    pub id: String,
+    /// Tenant id, or `None` for the default scope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Resource to re-check
+    pub url: String,
+    /// Expiry in milliseconds since the Unix epoch
+    pub exp: u64,
+}
+
+/// Why a token was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    Malformed,
+    BadSignature,
+    Expired,
+}
+
+impl TokenError {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Malformed => "Malformed action link",
+            Self::BadSignature => "Invalid action link",
+            Self::Expired => "This action link has expired",
+        }
+    }
+}
+
+fn mac(key: &[u8]) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length")
+}
+
+/// Sign claims into a token: base64url JSON claims and base64url MAC, joined by `.`
+pub fn sign(key: &[u8], claims: &Claims) -> String {
+    let payload = BASE64URL.encode(serde_json::to_vec(claims).expect("claims serialize"));
+    let mut mac = mac(key);
+    mac.update(payload.as_bytes());
+    let signature = BASE64URL.encode(mac.finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+/// Verify a token's signature and expiry and return its claims
+pub fn verify(key: &[u8], token: &str, now: u64) -> std::result::Result<Claims, TokenError> {
+    let (payload, signature) = token.split_once('.').ok_or(TokenError::Malformed)?;
+    let signature = BASE64URL
+        .decode(signature)
+        .map_err(|_| TokenError::Malformed)?;
+    let mut mac = mac(key);
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature)
+        .map_err(|_| TokenError::BadSignature)?;
+    let claims: Claims = BASE64URL
+        .decode(payload)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or(TokenError::Malformed)?;
+    if now >= claims.exp {
+        return Err(TokenError::Expired);
+    }
+    Ok(claims)
+}
+
+/// Signing key from `ACTION_SIGNING_KEY`
+fn signing_key(env: &Env) -> Option<String> {
+    config::setting(env, "ACTION_SIGNING_KEY")
+}
+
+/// Lifetime of action links from `ACTION_LINK_TTL_MINUTES`
+fn ttl_ms(env: &Env) -> u64 {
+    config::setting(env, "ACTION_LINK_TTL_MINUTES")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TTL_MINUTES)
+        * 60_000
+}
+
+/// Link re-checking a resource of a scope, if action links are configured
+pub fn recheck_link(env: &Env, scope: &Scope<'_>, url: &str) -> Option<String> {
+    let key = signing_key(env)?;
+    let base = config::setting(env, "PUBLIC_URL")?;
+    Store::from_env(env)?;
+    let claims = Claims {
+        id: trace::new_id(),
+        tenant: scope.id().map(str::to_string),
+        url: url.to_string(),
+        exp: clock::now_ms() + ttl_ms(env),
+    };
+    Some(format!(
+        "{}{}{}",
+        base.trim_end_matches('/'),
+        ACTIONS_PATH_PREFIX,
+        sign(key.as_bytes(), &claims)
+    ))
+}
+
+/// Check a single resource right away through the regular pipeline (history,
+/// alert streams, notifications) and describe the outcome
+///
+/// Paused resources (see `maintenance`) are left unchecked.
+pub async fn recheck(env: &Env, scope: &Scope<'_>, resource: &Resource) -> &'static str {
+    if let Some(reason) = maintenance::paused(resource, clock::now_ms()) {
+        console_log!("Not re-checking {}: {}", resource.url, reason);
+        return "not checked while the resource is disabled or in maintenance";
+    }
+    let summary = crate::check_scope(env, scope, vec![resource], Deadline::from_env(env)).await;
+    if !summary.skipped.is_empty() {
+        "could not be completed before the run deadline"
+    } else if summary.problems > 0 {
+        "still failing"
+    } else {
+        "passing"
+    }
+}
+
+/// Alert a chat button acts on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertRef {
+    /// Tenant id, or `None` for the default scope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub url: String,
+    pub stream: AlertStream,
+}
+
+impl AlertRef {
+    pub fn new(scope: &Scope<'_>, url: &str, stream: AlertStream) -> Self {
+        Self {
+            tenant: scope.id().map(str::to_string),
+            url: url.to_string(),
+            stream,
+        }
+    }
+
+    /// Scope of the alert, if its tenant is still configured
+    pub fn scope<'a>(&self, config: &'a Config) -> Option<Scope<'a>> {
+        match self.tenant.as_deref() {
+            Some(id) => Scope::tenant(config, id),
+            None => Some(Scope::root(config)),
+        }
+    }
+}
+
+/// Whoever clicked a chat button
+pub struct Actor {
+    /// Mention in the chat's syntax, e.g. `<@U123>`
+    pub mention: String,
+    /// Name recorded in silences and acknowledgements, e.g. `slack:alice`
+    pub principal: String,
+    /// Chat service the click came from
+    pub service: &'static str,
+}
+
+/// Run the action of a clicked chat button, describing the outcome in a line
+/// for the chat
+pub async fn run_button(
+    env: &Env,
+    config: &Config,
+    action_id: &str,
+    alert: &AlertRef,
+    actor: &Actor,
+) -> String {
+    let who = &actor.mention;
+    let Some((scope, resource)) = alert
+        .scope(config)
+        .and_then(|s| Some((s, s.find_resource(&alert.url)?)))
+    else {
+        return format!("⚠️ {} is no longer configured", alert.url);
+    };
+
+    let outcome = match action_id {
+        "recheck" => {
+            let outcome = recheck(env, &scope, resource).await;
+            Ok(format!("🔁 Re-checked by {}: {}", who, outcome))
+        }
+        "silence_1h" => silence_alert(env, &scope, alert, actor).await.map(|id| {
+            format!(
+                "🔕 {} alerts silenced for 1h by {} (silence {})",
+                alert.stream, who, id
+            )
+        }),
+        "ack" => match Store::require(env) {
+            Ok(store) => alert::acknowledge(
+                &store,
+                &scope,
+                alert.stream,
+                &resource.alert_key(),
+                &actor.principal,
+            )
+            .await
+            .map(|firing| match firing {
+                true => format!("👀 Acknowledged by {}, reminders stopped", who),
+                false => format!("✅ Already resolved when {} acknowledged it", who),
+            }),
+            Err(e) => Err(e),
+        },
+        other => Ok(format!("⚠️ Unknown action '{}'", other)),
+    };
+    outcome.unwrap_or_else(|e| {
+        console_error!("{} action {} failed: {}", actor.service, action_id, e);
+        format!("⚠️ {} failed: {}", action_id, e)
+    })
+}
+
+/// Silence a stream of a resource for an hour, returning the silence ID
+async fn silence_alert(
+    env: &Env,
+    scope: &Scope<'_>,
+    alert: &AlertRef,
+    actor: &Actor,
+) -> Result<String> {
+    let store = Store::require(env)?;
+    let stream = format!("stream={}", alert.stream)
+        .parse()
+        .map_err(Error::RustError)?;
+    let request = SilenceRequest {
+        matchers: vec![stream],
+        url: Some(alert.url.clone()),
+        duration_minutes: Some(SILENCE_MINUTES),
+        comment: Some(format!("Silenced from {}", actor.service)),
+    };
+    let silence = request
+        .into_silence(silence::new_silence_id(), &actor.principal, clock::now_ms())
+        .map_err(Error::RustError)?;
+    let id = silence.id.clone();
+    silence::add(&store, scope, silence).await?;
+    Ok(id)
+}
+
+/// Extract the token from an `/actions/{token}` path
+pub fn parse_action_path(path: &str) -> Option<&str> {
+    path.strip_prefix(ACTIONS_PATH_PREFIX)
+        .filter(|token| !token.is_empty() && !token.contains('/'))
+}
+
+/// Whether a request comes from a chat service rendering a link preview
+fn is_preview(user_agent: Option<&str>) -> bool {
+    user_agent.is_some_and(|agent| PREVIEW_AGENTS.iter().any(|bot| agent.contains(bot)))
+}
+
+/// Handle `GET /actions/{token}`: re-check the resource and report the outcome
+pub async fn handle_action(
+    env: &Env,
+    config: &Config,
+    req: &Request,
+    token: &str,
+) -> Result<Response> {
+    let user_agent = req.headers().get("User-Agent")?;
+    if is_preview(user_agent.as_deref()) {
+        return Response::from_html("Re-check link");
+    }
+    let Some(key) = signing_key(env) else {
+        return Response::error("Action links are not configured", 404);
+    };
+    let now = clock::now_ms();
+    let claims = match verify(key.as_bytes(), token, now) {
+        Ok(claims) => claims,
+        Err(e) => return Response::error(e.as_str(), 403),
+    };
+    let scope = match claims.tenant.as_deref() {
+        Some(id) => Scope::tenant(config, id),
+        None => Some(Scope::root(config)),
+    };
+    let Some(scope) = scope else {
+        return Response::error("Tenant no longer configured", 404);
+    };
+    let Some(resource) = scope.find_resource(&claims.url) else {
+        return Response::error("Resource no longer configured", 404);
+    };
+
+    // Use up the token before checking, so a double click checks once
+    let store = Store::require(env)?;
+    let used_key = scope.key(&format!("{}{}", USED_PREFIX, claims.id));
+    if store.get::<u64>(&used_key).await?.is_some() {
+        return Response::error("This action link has already been used", 410);
+    }
+    let ttl_secs = claims.exp.saturating_sub(now).div_ceil(1000);
+    store.put_with_ttl(&used_key, &now, ttl_secs).await?;
+
+    console_log!("🔁 Re-check of {} requested via action link", resource.url);
+    let outcome = recheck(env, &scope, resource).await;
+    let message = format!("Re-check requested from an alert link: {}", outcome);
+    if let Err(e) = notify::send_recheck_notification(env, &scope, &resource.url, &message).await {
+        console_error!("Failed to send re-check notification: {}", e);
+    }
+    Response::from_html(format!("Re-check of {}: {}", resource.url, outcome))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"action-signing-key";
+
+    fn claims() -> Claims {
+        Claims {
+            id: "18f2c00000001".to_string(),
+            tenant: Some("acme".to_string()),
+            url: "https://example.com/a.js".to_string(),
+            exp: 2_000,
+        }
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let token = sign(KEY, &claims());
+        assert_eq!(verify(KEY, &token, 1_000), Ok(claims()));
+        assert!(parse_action_path(&format!("/actions/{}", token)).is_some());
+
+        assert_eq!(verify(KEY, &token, 2_000), Err(TokenError::Expired));
+        assert_eq!(
+            verify(b"other-key", &token, 1_000),
+            Err(TokenError::BadSignature)
+        );
+        assert_eq!(verify(KEY, "no-dot", 1_000), Err(TokenError::Malformed));
+    }
+
+    #[test]
+    fn test_tampered_claims() {
+        let token = sign(KEY, &claims());
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = Claims {
+            url: "https://example.com/b.js".to_string(),
+            ..claims()
+        };
+        let payload = BASE64URL.encode(serde_json::to_vec(&forged).unwrap());
+        assert_eq!(
+            verify(KEY, &format!("{}.{}", payload, signature), 1_000),
+            Err(TokenError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn test_parse_action_path() {
+        assert_eq!(parse_action_path("/actions/abc.def"), Some("abc.def"));
+        assert_eq!(parse_action_path("/actions/"), None);
+        assert_eq!(parse_action_path("/actions/a/b"), None);
+        assert_eq!(parse_action_path("/check"), None);
+    }
+
+    #[test]
+    fn test_is_preview() {
+        assert!(is_preview(Some(
+            "Slackbot-LinkExpanding 1.0 (+https://api.slack.com/robots)"
+        )));
+        assert!(is_preview(Some("Mozilla/5.0 (compatible; Discordbot/2.0)")));
+        assert!(!is_preview(Some(
+            "Mozilla/5.0 (X11; Linux x86_64) Firefox/128.0"
+        )));
+        assert!(!is_preview(None));
+    }
+}
@@ -0,0 +1,1183 @@
+//! Alert streams and deduplication
+//!
+//! Availability failures (fetch errors, HTTP errors, unexpected responses) and
+//! integrity failures (SRI mismatches) are independent alert streams: each has its
+//! own dedup state, severity and optionally its own webhook destination, so a
+//! security team can follow mismatches while SRE follows downtime.
+//!
+//! Per stream and resource, the state store remembers since when an alert is
+//! firing. Resources are identified by URL, or by name or group when their
+//! `dedup` setting says so (see `config::DedupBy`). A stream notifies when it starts firing, repeats while it keeps firing
+//! (every run by default, or every `ALERT_REPEAT_MINUTES`), and sends a resolved
+//! notification once the resource recovers.
+//!
+//! Streams that oscillate between failing and passing (more than `FLAP_THRESHOLD`
+//! fire/resolve changes within `FLAP_WINDOW_MINUTES`, default 4 per hour) are
+//! damped, like Nagios flap detection: individual alerts are replaced by a periodic
+//! summary labelled "flapping" until no change happened for a whole window.
+//!
+//! A firing alert can be acknowledged (e.g. with the Slack "Ack" button), which
+//! stops its reminders until it resolves.
+//!
+//! Fire and resolve transitions are claimed from a per-subject Durable Object
+//! when one is bound, so overlapping runs notify about each only once (see `gate`).
+
+use crate::artifacts;
+use crate::blackout::{self, HeldAlert};
+use crate::checker::CheckResult;
+use crate::clock;
+use crate::config;
+use crate::console::{console_error, console_log};
+use crate::correlation::Correlation;
+use crate::crawl;
+use crate::escalation;
+use crate::events::{Event, EventKind};
+use crate::fetch::WorkerFetcher;
+use crate::gate;
+use crate::history::{self, History};
+use crate::incident::IncidentLog;
+use crate::notify::{self, AlertNotification};
+use crate::oob;
+use crate::origins;
+use crate::runbook;
+use crate::silence::{self, Silences};
+use crate::store::Store;
+use crate::storm;
+use crate::tenant::Scope;
+use crate::upstream::{self, UpstreamStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+/// KV key of the alert state document, relative to the scope prefix
+const ALERTS_KEY: &str = "alerts";
+
+/// Default number of changes per window above which a stream is flapping
+const DEFAULT_FLAP_THRESHOLD: usize = 4;
+
+/// Default flap detection window, also the default summary interval
+const DEFAULT_FLAP_WINDOW_MINUTES: u64 = 60;
+
+/// An independent stream of alerts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStream {
+    /// The resource is unreachable or responds incorrectly
+    Availability,
+    /// The resource content does not match its SRI hash
+    Integrity,
+}
+
+impl AlertStream {
+    pub const ALL: [Self; 2] = [Self::Availability, Self::Integrity];
+
+    /// Lowercase name, used in dedup keys and payload labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Availability => "availability",
+            Self::Integrity => "integrity",
+        }
+    }
+
+    /// Prefix of the stream-specific webhook secret and severity setting names
+    pub fn setting_prefix(&self) -> &'static str {
+        match self {
+            Self::Availability => "AVAILABILITY",
+            Self::Integrity => "INTEGRITY",
+        }
+    }
+
+    /// Severity used unless overridden by `<STREAM>_SEVERITY`
+    fn default_severity(&self) -> Severity {
+        match self {
+            Self::Availability => Severity::Warning,
+            // SRI mismatch is a security issue
+            Self::Integrity => Severity::Critical,
+        }
+    }
+
+    /// Whether a result says anything about this stream
+    ///
+    /// Integrity cannot be judged without a response body, so a fetch failure
+    /// neither fires nor resolves an integrity alert.
+    fn is_evaluated(&self, result: &CheckResult) -> bool {
+        match self {
+            Self::Availability => true,
+            Self::Integrity => result.sri_valid.is_some(),
+        }
+    }
+
+    /// Whether a result is a failure of this stream
+    fn is_failing(&self, result: &CheckResult) -> bool {
+        match self {
+            Self::Availability => !result.success,
+            Self::Integrity => result.sri_valid == Some(false),
+        }
+    }
+}
+
+impl std::fmt::Display for AlertStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Alert severity, as used by Alertmanager-style receivers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(Self::Info),
+            "warning" => Ok(Self::Warning),
+            "critical" => Ok(Self::Critical),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What to notify about for a stream after evaluating a result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// The stream started failing
+    Fired,
+    /// The stream is still failing and a reminder is due
+    Repeated,
+    /// The stream recovered
+    Resolved,
+    /// The stream is flapping and a periodic summary is due
+    Flapping,
+}
+
+/// Dedup state of a firing alert
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertState {
+    /// When the alert started firing (milliseconds since the Unix epoch)
+    pub since: u64,
+    /// When the last notification was sent
+    pub last_notified: u64,
+    /// Broken links of a crawled page as of the last notification
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+    /// URL of the last failing result, for alerts deduplicated by name or group
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    /// Who acknowledged the alert; acknowledged alerts send no reminders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acked_by: Option<String>,
+    /// Consecutive passing checks since the stream last failed, while waiting to resolve
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub successes: u32,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// Damping state of a flapping stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlapState {
+    /// When flapping was detected
+    pub since: u64,
+    /// When the last summary was sent
+    pub last_summary: u64,
+}
+
+/// Flap detection settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlapPolicy {
+    /// Number of changes within the window above which a stream is flapping
+    pub threshold: usize,
+    pub window_ms: u64,
+    /// Interval of summaries while flapping
+    pub summary_ms: u64,
+}
+
+/// Firing alerts of a scope, keyed by dedup key
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AlertStates {
+    #[serde(default)]
+    pub alerts: BTreeMap<String, AlertState>,
+    /// Times of recent fire/resolve changes, for flap detection
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub changes: BTreeMap<String, Vec<u64>>,
+    /// Streams currently damped because they are flapping
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub flapping: BTreeMap<String, FlapState>,
+}
+
+/// Dedup key of a stream of a resource, given the resource's alert key
+/// (see `Resource::alert_key`)
+pub fn dedup_key(stream: AlertStream, subject: &str) -> String {
+    format!("{}|{}", stream, subject)
+}
+
+impl AlertStates {
+    /// Update the state with a result and return the notifications that are due
+    ///
+    /// `subject` is the alert key of the checked resource. `repeat_ms` is the reminder interval of firing alerts; `None` repeats on every run.
+    /// A crawled page that keeps failing is notified again as soon as it has broken
+    /// links that were not in its last notification. A firing stream resolves after
+    /// `resolve_after` consecutive passing results; a failure in between starts over.
+    pub fn evaluate(
+        &mut self,
+        subject: &str,
+        result: &CheckResult,
+        now: u64,
+        repeat_ms: Option<u64>,
+        resolve_after: u32,
+    ) -> Vec<(AlertStream, Transition)> {
+        let mut due = Vec::new();
+        if result.is_informational() {
+            return due;
+        }
+
+        for stream in AlertStream::ALL {
+            if !stream.is_evaluated(result) {
+                continue;
+            }
+            let key = dedup_key(stream, subject);
+            let url = if subject == result.url.as_ref() {
+                String::new()
+            } else {
+                result.url.to_string()
+            };
+            let links: Vec<String> = result.broken_links.iter().map(|l| l.url.clone()).collect();
+            match (stream.is_failing(result), self.alerts.get_mut(&key)) {
+                (true, None) => {
+                    self.alerts.insert(
+                        key,
+                        AlertState {
+                            since: now,
+                            last_notified: now,
+                            links,
+                            url,
+                            acked_by: None,
+                            successes: 0,
+                        },
+                    );
+                    due.push((stream, Transition::Fired));
+                }
+                (true, Some(state)) => {
+                    let repeat_due = state.acked_by.is_none()
+                        && repeat_ms.is_none_or(|interval| {
+                            now.saturating_sub(state.last_notified) >= interval
+                        });
+                    let new_links = links.iter().any(|link| !state.links.contains(link));
+                    state.url = url;
+                    state.successes = 0;
+                    if repeat_due || new_links {
+                        state.last_notified = now;
+                        state.links = links;
+                        due.push((stream, Transition::Repeated));
+                    }
+                }
+                (false, Some(state)) => {
+                    state.successes += 1;
+                    if state.successes >= resolve_after {
+                        self.alerts.remove(&key);
+                        due.push((stream, Transition::Resolved));
+                    }
+                }
+                (false, None) => {}
+            }
+        }
+        due
+    }
+}
+
+impl AlertStates {
+    /// Load the alert states of a scope, logging read failures
+    pub async fn load(store: &Store, scope: &Scope<'_>) -> Self {
+        match store.get::<Self>(&scope.key(ALERTS_KEY)).await {
+            Ok(states) => states.unwrap_or_default(),
+            Err(e) => {
+                console_error!("Failed to load alert state: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Acknowledge a firing alert, returning `false` if it is not firing
+    pub fn acknowledge(&mut self, stream: AlertStream, subject: &str, by: &str) -> bool {
+        match self.alerts.get_mut(&dedup_key(stream, subject)) {
+            Some(state) => {
+                state.acked_by = Some(by.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Close a firing alert without a recovery, returning `false` if it is not firing
+    ///
+    /// If the resource keeps failing, its next check fires a new alert.
+    pub fn close(&mut self, stream: AlertStream, subject: &str) -> bool {
+        self.alerts.remove(&dedup_key(stream, subject)).is_some()
+    }
+
+    /// Consecutive passing checks of each stream of a resource that is waiting to resolve
+    fn successes(&self, subject: &str) -> Vec<u32> {
+        AlertStream::ALL
+            .iter()
+            .map(|&stream| {
+                self.alerts
+                    .get(&dedup_key(stream, subject))
+                    .map_or(0, |state| state.successes)
+            })
+            .collect()
+    }
+
+    /// Whether a stream of a resource is firing
+    pub fn is_firing(&self, stream: AlertStream, subject: &str) -> bool {
+        self.alerts.contains_key(&dedup_key(stream, subject))
+    }
+
+    /// Apply flap detection to the transitions of a result
+    ///
+    /// Records fire/resolve changes and returns the notifications to send instead:
+    /// unchanged for stable streams, a `Flapping` summary when a stream starts
+    /// flapping and every `summary_ms` after that, and the current state (still
+    /// failing or resolved) once no change happened for a whole window.
+    pub fn damp(
+        &mut self,
+        subject: &str,
+        result: &CheckResult,
+        transitions: Vec<(AlertStream, Transition)>,
+        now: u64,
+        policy: &FlapPolicy,
+    ) -> Vec<(AlertStream, Transition)> {
+        let mut due = Vec::new();
+        if result.is_informational() {
+            return transitions;
+        }
+
+        for stream in AlertStream::ALL {
+            if !stream.is_evaluated(result) {
+                continue;
+            }
+            let key = dedup_key(stream, subject);
+            let transition = transitions
+                .iter()
+                .find(|(s, _)| *s == stream)
+                .map(|(_, t)| *t);
+
+            let changes = self.changes.entry(key.clone()).or_default();
+            if matches!(transition, Some(Transition::Fired | Transition::Resolved)) {
+                changes.push(now);
+            }
+            changes.retain(|&at| now.saturating_sub(at) < policy.window_ms);
+            let recent = changes.len();
+            if recent == 0 {
+                self.changes.remove(&key);
+            }
+
+            match self.flapping.get_mut(&key) {
+                None if recent > policy.threshold => {
+                    self.flapping.insert(
+                        key,
+                        FlapState {
+                            since: now,
+                            last_summary: now,
+                        },
+                    );
+                    due.push((stream, Transition::Flapping));
+                }
+                None => due.extend(transition.map(|t| (stream, t))),
+                Some(_) if recent == 0 => {
+                    self.flapping.remove(&key);
+                    let settled = if self.alerts.contains_key(&key) {
+                        Transition::Repeated
+                    } else {
+                        Transition::Resolved
+                    };
+                    due.push((stream, settled));
+                }
+                Some(state) => {
+                    if now.saturating_sub(state.last_summary) >= policy.summary_ms {
+                        state.last_summary = now;
+                        due.push((stream, Transition::Flapping));
+                    }
+                }
+            }
+        }
+        due
+    }
+
+    /// Summary line of a flapping stream, e.g. `6 state changes in the last 60m, currently failing`
+    pub fn flap_summary(&self, stream: AlertStream, subject: &str, policy: &FlapPolicy) -> String {
+        let key = dedup_key(stream, subject);
+        let changes = self.changes.get(&key).map_or(0, Vec::len);
+        let current = if self.alerts.contains_key(&key) {
+            "failing"
+        } else {
+            "passing"
+        };
+        format!(
+            "{} state changes in the last {}m, currently {}",
+            changes,
+            policy.window_ms / 60_000,
+            current
+        )
+    }
+
+    /// URLs of resources with at least one firing stream
+    pub fn firing_urls(&self) -> Vec<String> {
+        let urls: std::collections::BTreeSet<&str> = self
+            .alerts
+            .iter()
+            .filter_map(|(key, state)| match state.url.as_str() {
+                "" => key.split_once('|').map(|(_, url)| url),
+                url => Some(url),
+            })
+            .collect();
+        urls.into_iter().map(str::to_string).collect()
+    }
+}
+
+/// Acknowledge a firing alert of a scope (see `AlertStates::acknowledge`)
+pub async fn acknowledge(
+    store: &Store,
+    scope: &Scope<'_>,
+    stream: AlertStream,
+    subject: &str,
+    by: &str,
+) -> Result<bool> {
+    let mut states = AlertStates::load(store, scope).await;
+    if !states.acknowledge(stream, subject, by) {
+        return Ok(false);
+    }
+    store.put(&scope.key(ALERTS_KEY), &states).await?;
+    Ok(true)
+}
+
+/// Close a firing alert of a scope and its open incident (see `AlertStates::close`)
+pub async fn close(
+    store: &Store,
+    scope: &Scope<'_>,
+    stream: AlertStream,
+    subject: &str,
+    now: u64,
+) -> Result<bool> {
+    let mut states = AlertStates::load(store, scope).await;
+    if !states.close(stream, subject) {
+        return Ok(false);
+    }
+    store.put(&scope.key(ALERTS_KEY), &states).await?;
+    let mut incidents = IncidentLog::load(store, scope).await?;
+    if incidents.close(subject, stream, now) {
+        incidents.save(store, scope).await?;
+    }
+    Ok(true)
+}
+
+/// Severity of a stream, honouring the `<STREAM>_SEVERITY` setting
+pub fn severity(env: &Env, stream: AlertStream) -> Severity {
+    let name = format!("{}_SEVERITY", stream.setting_prefix());
+    match config::setting(env, &name) {
+        None => stream.default_severity(),
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            console_error!("Unknown {} value '{}', using default", name, value);
+            stream.default_severity()
+        }),
+    }
+}
+
+/// Highest severity of an availability alert while a verified copy of the
+/// resource can be served instead (see `artifacts::mirror_reference`)
+const FALLBACK_SEVERITY: Severity = Severity::Warning;
+
+/// Cap the severity of an alert whose resource has a verified copy, noting the
+/// downgrade next to the copy's location
+///
+/// Only availability alerts look up a copy: mismatching content stays critical
+/// whether or not a known-good version exists.
+fn with_fallback(severity: Severity, mirror: Option<String>) -> (Severity, Option<String>) {
+    match mirror {
+        Some(reference) if severity > FALLBACK_SEVERITY => {
+            let note = format!(
+                "{} - content still obtainable, severity lowered from {} to {}",
+                reference,
+                severity.as_str(),
+                FALLBACK_SEVERITY.as_str()
+            );
+            (FALLBACK_SEVERITY, Some(note))
+        }
+        mirror => (severity, mirror),
+    }
+}
+
+/// Reminder interval of firing alerts from `ALERT_REPEAT_MINUTES`
+fn repeat_interval_ms(env: &Env) -> Option<u64> {
+    let value = config::setting(env, "ALERT_REPEAT_MINUTES")?;
+    match value.parse::<u64>() {
+        Ok(minutes) => Some(minutes * 60_000),
+        Err(_) => {
+            console_error!(
+                "Invalid ALERT_REPEAT_MINUTES '{}', repeating every run",
+                value
+            );
+            None
+        }
+    }
+}
+
+/// Consecutive passing checks before a firing alert resolves, from `RESOLVE_AFTER_SUCCESSES`
+fn resolve_after(env: &Env) -> u32 {
+    let Some(value) = config::setting(env, "RESOLVE_AFTER_SUCCESSES") else {
+        return 1;
+    };
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            console_error!(
+                "Invalid RESOLVE_AFTER_SUCCESSES '{}', resolving on the first success",
+                value
+            );
+            1
+        }
+    }
+}
+
+/// Open and close incidents for the transitions of a run
+async fn record_incidents(
+    store: &Store,
+    scope: &Scope<'_>,
+    transitions: &[(AlertStream, Transition, &CheckResult, String)],
+    now: u64,
+) {
+    if transitions
+        .iter()
+        .all(|(_, t, _, _)| *t == Transition::Repeated)
+    {
+        return;
+    }
+    let mut log = match IncidentLog::load(store, scope).await {
+        Ok(log) => log,
+        Err(e) => {
+            console_error!("Failed to load incident log: {}", e);
+            return;
+        }
+    };
+    let mut changed = false;
+    for (stream, transition, result, subject) in transitions {
+        changed |= log.apply(subject, *stream, *transition, result, now);
+    }
+    if changed {
+        if let Err(e) = log.save(store, scope).await {
+            console_error!("Failed to save incident log: {}", e);
+        }
+    }
+}
+
+/// Flap detection settings from `FLAP_THRESHOLD`, `FLAP_WINDOW_MINUTES` and
+/// `FLAP_SUMMARY_MINUTES`; a threshold of 0 disables it
+fn flap_policy(env: &Env) -> Option<FlapPolicy> {
+    let minutes = |name: &str, default: u64| {
+        config::setting(env, name)
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(default)
+            * 60_000
+    };
+    let threshold = config::setting(env, "FLAP_THRESHOLD")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_FLAP_THRESHOLD);
+    (threshold > 0).then(|| FlapPolicy {
+        threshold,
+        window_ms: minutes("FLAP_WINDOW_MINUTES", DEFAULT_FLAP_WINDOW_MINUTES),
+        summary_ms: minutes("FLAP_SUMMARY_MINUTES", DEFAULT_FLAP_WINDOW_MINUTES),
+    })
+}
+
+/// Evaluate the results of a run and send the due notifications
+///
+/// Failures, recoveries and notifications are added to `events`.
+///
+/// Returns the URLs of the scope's resources that are still failing. Without the
+/// state store there is no dedup state: every failure is notified on every run, no
+/// resolved notifications are sent, and only this run's failures are returned.
+pub async fn process(
+    env: &Env,
+    scope: &Scope<'_>,
+    results: &[CheckResult],
+    history: &History,
+    events: &mut Vec<Event>,
+) -> Vec<String> {
+    let store = Store::from_env(env);
+    let key = scope.key(ALERTS_KEY);
+    let mut states = match &store {
+        Some(store) => AlertStates::load(store, scope).await,
+        None => AlertStates::default(),
+    };
+    let silences = match &store {
+        Some(store) => Silences::load(store, scope).await,
+        None => Silences::default(),
+    };
+    let now = clock::now_ms();
+    let blackouts = blackout::active(store.as_ref(), scope, now).await;
+    let mut held = Vec::new();
+
+    let mut upstream = UpstreamStatus::default();
+    let upstream_severity = upstream::incident_severity(env);
+
+    let correlated = Correlation::new(results);
+    let repeat_ms = repeat_interval_ms(env);
+    let resolve_after = resolve_after(env);
+    let flap = flap_policy(env);
+    let mut changed = false;
+    let mut transitions = Vec::new();
+    let mut outgoing = Vec::new();
+
+    for result in results {
+        let resource = scope.find_resource(&result.url);
+        let subject = resource.map_or_else(|| result.url.to_string(), |r| r.alert_key());
+        let successes = states.successes(&subject);
+        let due = states.evaluate(&subject, result, now, repeat_ms, resolve_after);
+        // Recovering streams stay firing, so their progress needs a write of its own
+        changed |= !due.is_empty() || states.successes(&subject) != successes;
+        // Another run may have opened or closed the stream at the same time
+        let mut due = gate::claim(env, scope, &subject, due, now).await;
+        transitions.extend(
+            due.iter()
+                .map(|&(stream, t)| (stream, t, result, subject.clone())),
+        );
+        for &(stream, transition) in &due {
+            let kind = match transition {
+                Transition::Fired => EventKind::Failed,
+                Transition::Resolved => EventKind::Recovered,
+                Transition::Repeated | Transition::Flapping => continue,
+            };
+            events.push(
+                Event::alert(kind, now, &result.url, stream).with_detail(result.description()),
+            );
+        }
+        if let Some(policy) = &flap {
+            // Pruning old changes alone needs no write, it is repeated on every run
+            due = states.damp(&subject, result, due, now, policy);
+            changed |= !due.is_empty();
+        }
+
+        for (stream, transition) in due {
+            let flapping = match (transition, &flap) {
+                (Transition::Flapping, Some(policy)) => {
+                    Some(states.flap_summary(stream, &subject, policy))
+                }
+                _ => None,
+            };
+            if transition == Transition::Resolved {
+                console_log!("Resolved ({}): {}", stream, result.url);
+            } else if let Some(summary) = &flapping {
+                console_error!("Flapping ({}): {} - {}", stream, result.url, summary);
+            } else {
+                console_error!(
+                    "Problem detected ({}): {} - {}",
+                    stream,
+                    result.url,
+                    result.description()
+                );
+            }
+
+            let labels = silence::labels(scope, resource, &result.url, stream);
+            if let Some(silence) = silences.find(&labels, now) {
+                console_log!("Silenced by {} ({}): {}", silence.id, stream, result.url);
+                continue;
+            }
+
+            let resolved = transition == Transition::Resolved;
+            if let Some(blackout) = blackouts.iter().find(|b| b.matches(&labels)) {
+                console_log!(
+                    "Held for the digest of {} ({}): {}",
+                    blackout.name,
+                    stream,
+                    result.url
+                );
+                held.push(HeldAlert {
+                    at: now,
+                    blackout: blackout.name.clone(),
+                    stream,
+                    url: result.url.to_string(),
+                    detail: flapping.unwrap_or_else(|| result.description()),
+                    resolved,
+                });
+                continue;
+            }
+            // Keep the offending content of every alerted mismatch for forensics, and
+            // point consumers of an unavailable resource at its last verified copy
+            let (quarantine, mirror) = match stream {
+                _ if resolved || flapping.is_some() => (None, None),
+                AlertStream::Integrity => (artifacts::quarantine(env, scope, result).await, None),
+                AlertStream::Availability => (
+                    None,
+                    artifacts::mirror_reference(env, scope, &result.url).await,
+                ),
+            };
+            let mut severity = escalation::severity(
+                &scope.config().escalation,
+                resource,
+                stream,
+                severity(env, stream),
+                now,
+            );
+            let upstream_incident = match resource.and_then(|r| r.upstream_status.as_deref()) {
+                Some(page) if !resolved => upstream.incident(&WorkerFetcher, page).await,
+                _ => None,
+            };
+            if let (Some(_), Some(max)) = (&upstream_incident, upstream_severity) {
+                severity = severity.min(max);
+            }
+            let (severity, mirror) = with_fallback(severity, mirror);
+            let correlation = if resolved || flapping.is_some() {
+                None
+            } else {
+                correlated.hint(result)
+            };
+            let alert = AlertNotification {
+                stream,
+                severity,
+                resolved,
+                history: history.summary(&result.url, history::ALERT_CONTEXT_ENTRIES, now),
+                quarantine,
+                mirror,
+                flapping,
+                owner: resource.and_then(|r| origins::owner(r, result)),
+                runbook: match resource {
+                    Some(resource) if !resolved => runbook::link(env, resource),
+                    _ => None,
+                },
+                upstream: upstream_incident.map(|i| i.annotation()),
+                broken_links: if resolved {
+                    None
+                } else {
+                    crawl::summary(&result.broken_links)
+                },
+                origins: origins::details(result),
+                out_of_band: oob::details(result),
+                correlation,
+                broken_at: origins::breakage(result),
+                dedup_key: (subject != result.url.as_ref()).then(|| subject.clone()),
+            };
+            outgoing.push((result, alert));
+        }
+    }
+
+    // A storm of alerts is sent as a sample and a summary
+    let due = outgoing.len();
+    let resolved: Vec<bool> = outgoing.iter().map(|(_, alert)| alert.resolved).collect();
+    let sampled = match storm::policy(env) {
+        Some(policy) => policy.sampled(&resolved),
+        None => vec![true; due],
+    };
+    let mut summarized = Vec::new();
+    for ((result, alert), sampled) in outgoing.into_iter().zip(sampled) {
+        let stream = alert.stream;
+        if !sampled {
+            summarized.push(storm::Summarized {
+                stream,
+                resolved: alert.resolved,
+                url: result.url.to_string(),
+                detail: alert.flapping.unwrap_or_else(|| result.description()),
+            });
+            continue;
+        }
+        let event = match notify::send_alert_notification(env, scope, result, alert).await {
+            Ok(()) => Event::alert(EventKind::Notified, now, &result.url, stream),
+            Err(e) => {
+                console_error!("Failed to send notification: {}", e);
+                Event::alert(EventKind::NotificationFailed, now, &result.url, stream)
+                    .with_detail(e.to_string())
+            }
+        };
+        events.push(event);
+    }
+    if !summarized.is_empty() {
+        storm::send_summaries(env, scope, due, summarized, now, events).await;
+    }
+
+    blackout::flush(env, store.as_ref(), scope, held, &blackouts, now).await;
+    if let (Some(store), true) = (store, changed) {
+        if let Err(e) = store.put(&key, &states).await {
+            console_error!("Failed to save alert state: {}", e);
+        }
+        record_incidents(&store, scope, &transitions, now).await;
+    }
+    states.firing_urls()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::{BrokenLink, CheckError};
+
+    const URL: &str = "https://example.com/a.js";
+
+    fn ok() -> CheckResult {
+        CheckResult::success(URL, 200, true)
+    }
+
+    fn down() -> CheckResult {
+        CheckResult::failure(URL, CheckError::HttpError(503))
+    }
+
+    fn mismatch() -> CheckResult {
+        CheckResult::success(URL, 200, false)
+    }
+
+    fn broken(links: &[&str]) -> CheckResult {
+        let mut result = CheckResult::failure(URL, CheckError::BrokenLinks(links.len()));
+        result.broken_links = links
+            .iter()
+            .map(|url| BrokenLink {
+                url: url.to_string(),
+                error: CheckError::HttpError(404),
+            })
+            .collect();
+        result
+    }
+
+    #[test]
+    fn test_new_broken_links_notify() {
+        let mut states = AlertStates::default();
+        let hour = Some(3_600_000);
+
+        assert_eq!(
+            states.evaluate(URL, &broken(&["/a"]), 0, hour, 1),
+            vec![(AlertStream::Availability, Transition::Fired)]
+        );
+        // Same links: wait for the repeat interval
+        assert!(states
+            .evaluate(URL, &broken(&["/a"]), 1, hour, 1)
+            .is_empty());
+        // A newly dead link is reported right away
+        assert_eq!(
+            states.evaluate(URL, &broken(&["/a", "/b"]), 2, hour, 1),
+            vec![(AlertStream::Availability, Transition::Repeated)]
+        );
+        // Fewer broken links are not news
+        assert!(states
+            .evaluate(URL, &broken(&["/b"]), 3, hour, 1)
+            .is_empty());
+        assert_eq!(
+            states.evaluate(URL, &ok(), 4, hour, 1),
+            vec![(AlertStream::Availability, Transition::Resolved)]
+        );
+    }
+
+    #[test]
+    fn test_streams_are_independent() {
+        let mut states = AlertStates::default();
+
+        assert_eq!(
+            states.evaluate(URL, &mismatch(), 0, None, 1),
+            vec![(AlertStream::Integrity, Transition::Fired)]
+        );
+        // A fetch failure fires availability but leaves the integrity alert alone
+        assert_eq!(
+            states.evaluate(URL, &down(), 1, None, 1),
+            vec![(AlertStream::Availability, Transition::Fired)]
+        );
+        assert_eq!(states.alerts.len(), 2);
+
+        // Recovering availability while the mismatch persists
+        assert_eq!(
+            states.evaluate(URL, &mismatch(), 2, None, 1),
+            vec![
+                (AlertStream::Availability, Transition::Resolved),
+                (AlertStream::Integrity, Transition::Repeated),
+            ]
+        );
+        assert_eq!(
+            states.evaluate(URL, &ok(), 3, None, 1),
+            vec![(AlertStream::Integrity, Transition::Resolved)]
+        );
+        assert!(states.alerts.is_empty());
+        assert!(states.evaluate(URL, &ok(), 4, None, 1).is_empty());
+    }
+
+    #[test]
+    fn test_firing_urls() {
+        let mut states = AlertStates::default();
+        states.evaluate(URL, &mismatch(), 0, None, 1);
+        states.evaluate(URL, &down(), 1, None, 1);
+        states.evaluate(
+            "https://example.com/b.js",
+            &CheckResult::failure("https://example.com/b.js", CheckError::FetchFailed),
+            1,
+            None,
+            1,
+        );
+        assert_eq!(
+            states.firing_urls(),
+            vec!["https://example.com/a.js", "https://example.com/b.js"]
+        );
+    }
+
+    #[test]
+    fn test_rotating_url_keeps_alert() {
+        let mut states = AlertStates::default();
+        let v1 = CheckResult::failure(
+            "https://cdn.example.com/v1.2.3/app.js",
+            CheckError::HttpError(404),
+        );
+        let v2 = CheckResult::failure(
+            "https://cdn.example.com/v1.2.4/app.js",
+            CheckError::HttpError(404),
+        );
+
+        assert_eq!(
+            states.evaluate("name:app", &v1, 0, None, 1),
+            vec![(AlertStream::Availability, Transition::Fired)]
+        );
+        assert_eq!(states.firing_urls(), vec![v1.url.to_string()]);
+        // The bumped version continues the same alert
+        assert_eq!(
+            states.evaluate("name:app", &v2, 1, None, 1),
+            vec![(AlertStream::Availability, Transition::Repeated)]
+        );
+        assert_eq!(states.alerts["availability|name:app"].since, 0);
+        assert_eq!(states.firing_urls(), vec![v2.url.to_string()]);
+        assert!(states.is_firing(AlertStream::Availability, "name:app"));
+        assert_eq!(
+            states.evaluate(
+                "name:app",
+                &CheckResult::success("https://cdn.example.com/v1.2.4/app.js", 200, true),
+                2,
+                None,
+                1
+            ),
+            vec![(AlertStream::Availability, Transition::Resolved)]
+        );
+        assert!(states.alerts.is_empty());
+    }
+
+    #[test]
+    fn test_acknowledge() {
+        let mut states = AlertStates::default();
+        assert!(!states.acknowledge(AlertStream::Availability, URL, "alice"));
+        states.evaluate(URL, &down(), 0, None, 1);
+        assert!(states.acknowledge(AlertStream::Availability, URL, "alice"));
+
+        // No reminders while acknowledged, but new broken links are still news
+        assert!(states.evaluate(URL, &down(), 1, None, 1).is_empty());
+        assert_eq!(
+            states.evaluate(URL, &broken(&["/a"]), 2, None, 1),
+            vec![(AlertStream::Availability, Transition::Repeated)]
+        );
+        assert_eq!(
+            states.evaluate(URL, &ok(), 3, None, 1),
+            vec![(AlertStream::Availability, Transition::Resolved)]
+        );
+        // A new failure starts unacknowledged
+        states.evaluate(URL, &down(), 4, None, 1);
+        assert_eq!(
+            states.evaluate(URL, &down(), 5, None, 1),
+            vec![(AlertStream::Availability, Transition::Repeated)]
+        );
+    }
+
+    #[test]
+    fn test_close() {
+        let mut states = AlertStates::default();
+        assert!(!states.close(AlertStream::Availability, URL));
+        states.evaluate(URL, &down(), 0, None, 1);
+        assert!(states.close(AlertStream::Availability, URL));
+        assert!(!states.is_firing(AlertStream::Availability, URL));
+
+        // A resource that keeps failing fires again
+        assert_eq!(
+            states.evaluate(URL, &down(), 1, None, 1),
+            vec![(AlertStream::Availability, Transition::Fired)]
+        );
+    }
+
+    #[test]
+    fn test_repeat_interval() {
+        let mut states = AlertStates::default();
+        let repeat = Some(30 * 60_000);
+
+        assert_eq!(
+            states.evaluate(URL, &down(), 0, repeat, 1),
+            vec![(AlertStream::Availability, Transition::Fired)]
+        );
+        assert!(states
+            .evaluate(URL, &down(), 10 * 60_000, repeat, 1)
+            .is_empty());
+        assert_eq!(
+            states.evaluate(URL, &down(), 30 * 60_000, repeat, 1),
+            vec![(AlertStream::Availability, Transition::Repeated)]
+        );
+        let state = &states.alerts[&dedup_key(AlertStream::Availability, URL)];
+        assert_eq!(state.since, 0);
+        assert_eq!(state.last_notified, 30 * 60_000);
+    }
+
+    #[test]
+    fn test_resolve_after_successes() {
+        let mut states = AlertStates::default();
+        states.evaluate(URL, &down(), 0, None, 3);
+
+        assert!(states.evaluate(URL, &ok(), 1, None, 3).is_empty());
+        assert!(states.evaluate(URL, &ok(), 2, None, 3).is_empty());
+        // Still firing while recovering, so the re-check keeps verifying it
+        assert_eq!(states.firing_urls(), vec![URL]);
+        assert_eq!(states.successes(URL), vec![2, 0]);
+
+        // A relapse continues the same alert and starts the count over
+        assert_eq!(
+            states.evaluate(URL, &down(), 3, None, 3),
+            vec![(AlertStream::Availability, Transition::Repeated)]
+        );
+        assert_eq!(states.successes(URL), vec![0, 0]);
+        assert!(states.evaluate(URL, &ok(), 4, None, 3).is_empty());
+        assert!(states.evaluate(URL, &ok(), 5, None, 3).is_empty());
+        assert_eq!(
+            states.evaluate(URL, &ok(), 6, None, 3),
+            vec![(AlertStream::Availability, Transition::Resolved)]
+        );
+        assert!(states.alerts.is_empty());
+    }
+
+    #[test]
+    fn test_flap_damping() {
+        let policy = FlapPolicy {
+            threshold: 4,
+            window_ms: 60 * 60_000,
+            summary_ms: 30 * 60_000,
+        };
+        let mut states = AlertStates::default();
+        let run = |states: &mut AlertStates, result: &CheckResult, minute: u64| {
+            let now = minute * 60_000;
+            let due = states.evaluate(URL, result, now, None, 1);
+            states.damp(URL, result, due, now, &policy)
+        };
+        let availability = |t| vec![(AlertStream::Availability, t)];
+
+        // Four changes are still individual alerts
+        assert_eq!(
+            run(&mut states, &down(), 0),
+            availability(Transition::Fired)
+        );
+        assert_eq!(
+            run(&mut states, &ok(), 5),
+            availability(Transition::Resolved)
+        );
+        assert_eq!(
+            run(&mut states, &down(), 10),
+            availability(Transition::Fired)
+        );
+        assert_eq!(
+            run(&mut states, &ok(), 15),
+            availability(Transition::Resolved)
+        );
+        // The fifth change within the hour starts damping
+        assert_eq!(
+            run(&mut states, &down(), 20),
+            availability(Transition::Flapping)
+        );
+        assert_eq!(
+            states.flap_summary(AlertStream::Availability, URL, &policy),
+            "5 state changes in the last 60m, currently failing"
+        );
+        // Changes and repeats are suppressed until the next summary is due
+        assert!(run(&mut states, &ok(), 25).is_empty());
+        assert!(run(&mut states, &down(), 30).is_empty());
+        assert!(run(&mut states, &down(), 35).is_empty());
+        assert_eq!(
+            run(&mut states, &ok(), 50),
+            availability(Transition::Flapping)
+        );
+        // Still damped while recent changes remain in the window
+        assert!(run(&mut states, &ok(), 70).is_empty());
+        assert_eq!(
+            run(&mut states, &ok(), 80),
+            availability(Transition::Flapping)
+        );
+        // Stable for a whole window: back to normal with the current state
+        assert_eq!(
+            run(&mut states, &ok(), 110),
+            availability(Transition::Resolved)
+        );
+        assert!(states.flapping.is_empty());
+        assert!(states.changes.is_empty());
+        assert!(run(&mut states, &ok(), 115).is_empty());
+    }
+
+    #[test]
+    fn test_informational_results_ignored() {
+        let mut states = AlertStates::default();
+        assert!(states
+            .evaluate(
+                URL,
+                &CheckResult::test("Synthetic notification"),
+                0,
+                None,
+                1
+            )
+            .is_empty());
+        assert!(states
+            .evaluate(
+                URL,
+                &CheckResult::notice(URL, "SRI change requested"),
+                0,
+                None,
+                1
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn test_with_fallback() {
+        const COPY: &str = "mirror/example.com/a.js (verified 2025-11-12T09:00:00Z)";
+        assert_eq!(
+            with_fallback(Severity::Critical, Some(COPY.to_string())),
+            (
+                Severity::Warning,
+                Some(format!(
+                    "{} - content still obtainable, severity lowered from critical to warning",
+                    COPY
+                ))
+            )
+        );
+        // Already at or below the cap, the copy is only referenced
+        assert_eq!(
+            with_fallback(Severity::Info, Some(COPY.to_string())),
+            (Severity::Info, Some(COPY.to_string()))
+        );
+        assert_eq!(
+            with_fallback(Severity::Critical, None),
+            (Severity::Critical, None)
+        );
+    }
+
+    #[test]
+    fn test_severity_from_str() {
+        assert_eq!("critical".parse(), Ok(Severity::Critical));
+        assert_eq!("Warning".parse(), Ok(Severity::Warning));
+        assert_eq!("page".parse::<Severity>(), Err(()));
+        assert_eq!(
+            AlertStream::Integrity.default_severity(),
+            Severity::Critical
+        );
+        assert_eq!(
+            AlertStream::Availability.default_severity(),
+            Severity::Warning
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Alert noise analytics (`GET /analytics/alerts`)
+//!
+//! Summarizes the stored incident log over a date range: alert counts per
+//! resource, mean time to recovery, the flappiest resources and the most common
+//! error types, to help tune checks and alerting that are too noisy.
+//!
+//! The range is given as `from` and `to` dates (`YYYY-MM-DD`, UTC, both
+//! inclusive) or as the last `days` days (default 30).
+
+use crate::alert::AlertStream;
+use crate::clock;
+use crate::incident::{Incident, IncidentLog};
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use worker::*;
+
+/// Default length of the range when no dates are given
+const DEFAULT_DAYS: u64 = 30;
+
+/// Number of entries in the flappiest resources and error type rankings
+const TOP_ENTRIES: usize = 5;
+
+const DAY_MS: u64 = 86_400_000;
+
+/// Alert statistics of a single resource
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct ResourceAlerts {
+    /// URL, or the alert key of a resource deduplicated by name or group
+    pub url: String,
+    pub incidents: usize,
+    pub availability: usize,
+    pub integrity: usize,
+    /// Incidents still firing
+    pub open: usize,
+    /// Mean time to recovery of the resolved incidents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mttr_ms: Option<u64>,
+}
+
+/// Number of incidents with an error type
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ErrorCount {
+    pub error: String,
+    pub count: usize,
+}
+
+/// Alert noise summary of a date range
+#[derive(Debug, Serialize, PartialEq)]
+pub struct AlertAnalytics {
+    /// Start of the range (milliseconds since the Unix epoch, inclusive)
+    pub from: u64,
+    /// End of the range (exclusive)
+    pub to: u64,
+    pub incidents: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mttr_ms: Option<u64>,
+    /// Resources by number of incidents, most first
+    pub resources: Vec<ResourceAlerts>,
+    /// Resources with the most alert transitions (fired plus resolved)
+    pub flappiest: Vec<ResourceAlerts>,
+    pub error_types: Vec<ErrorCount>,
+}
+
+/// Mean duration of the resolved incidents
+fn mean_recovery<'a>(incidents: impl IntoIterator<Item = &'a Incident>) -> Option<u64> {
+    let durations: Vec<u64> = incidents
+        .into_iter()
+        .filter_map(Incident::duration_ms)
+        .collect();
+    if durations.is_empty() {
+        return None;
+    }
+    Some(durations.iter().sum::<u64>() / durations.len() as u64)
+}
+
+/// Number of alert transitions of an incident
+fn transitions(incident: &Incident) -> usize {
+    if incident.resolved.is_some() {
+        2
+    } else {
+        1
+    }
+}
+
+impl AlertAnalytics {
+    /// Summarize the incidents that started within `[from, to)`
+    pub fn compute(log: &IncidentLog, from: u64, to: u64) -> Self {
+        let incidents: Vec<&Incident> = log
+            .incidents
+            .iter()
+            .filter(|i| (from..to).contains(&i.started))
+            .collect();
+
+        let mut by_url: BTreeMap<&str, Vec<&Incident>> = BTreeMap::new();
+        let mut errors: BTreeMap<&str, usize> = BTreeMap::new();
+        for incident in &incidents {
+            by_url.entry(incident.subject()).or_default().push(incident);
+            *errors.entry(&incident.error).or_default() += 1;
+        }
+
+        let mut resources: Vec<(usize, ResourceAlerts)> = by_url
+            .into_iter()
+            .map(|(url, list)| {
+                let count = |stream| list.iter().filter(|i| i.stream == stream).count();
+                let stats = ResourceAlerts {
+                    url: url.to_string(),
+                    incidents: list.len(),
+                    availability: count(AlertStream::Availability),
+                    integrity: count(AlertStream::Integrity),
+                    open: list.iter().filter(|i| i.resolved.is_none()).count(),
+                    mttr_ms: mean_recovery(list.iter().copied()),
+                };
+                (list.iter().map(|i| transitions(i)).sum(), stats)
+            })
+            .collect();
+
+        // Stable sorts keep URL order among ties
+        resources.sort_by_key(|(transitions, _)| std::cmp::Reverse(*transitions));
+        let flappiest: Vec<ResourceAlerts> = resources
+            .iter()
+            .take(TOP_ENTRIES)
+            .map(|(_, stats)| stats.clone())
+            .collect();
+        let mut resources: Vec<ResourceAlerts> = resources.into_iter().map(|(_, s)| s).collect();
+        resources.sort_by_key(|s| std::cmp::Reverse(s.incidents));
+
+        let mut error_types: Vec<ErrorCount> = errors
+            .into_iter()
+            .map(|(error, count)| ErrorCount {
+                error: error.to_string(),
+                count,
+            })
+            .collect();
+        error_types.sort_by_key(|e| std::cmp::Reverse(e.count));
+        error_types.truncate(TOP_ENTRIES);
+
+        Self {
+            from,
+            to,
+            incidents: incidents.len(),
+            mttr_ms: mean_recovery(incidents.iter().copied()),
+            resources,
+            flappiest,
+            error_types,
+        }
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date as milliseconds since the Unix epoch (UTC midnight)
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+    Some(clock::days_from_civil(year, month, day) as u64 * DAY_MS)
+}
+
+/// Resolve the requested range from the query parameters
+fn parse_range(
+    from: Option<&str>,
+    to: Option<&str>,
+    days: Option<&str>,
+    now: u64,
+) -> std::result::Result<(u64, u64), String> {
+    let date = |name: &str, value: &str| {
+        parse_date(value)
+            .ok_or_else(|| format!("Invalid {} date '{}', use YYYY-MM-DD", name, value))
+    };
+    let end = match to {
+        Some(value) => date("to", value)? + DAY_MS,
+        None => now,
+    };
+    let start = match (from, days) {
+        (Some(value), _) => date("from", value)?,
+        (None, Some(value)) => {
+            let days: u64 = value
+                .parse()
+                .map_err(|_| format!("Invalid days '{}'", value))?;
+            end.saturating_sub(days * DAY_MS)
+        }
+        (None, None) => end.saturating_sub(DEFAULT_DAYS * DAY_MS),
+    };
+    if start >= end {
+        return Err("The range is empty".to_string());
+    }
+    Ok((start, end))
+}
+
+/// Handle `GET /analytics/alerts`
+pub async fn handle_alerts(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let store = Store::require(env)?;
+    let url = req.url()?;
+    let query: BTreeMap<String, String> = url.query_pairs().into_owned().collect();
+    let range = parse_range(
+        query.get("from").map(String::as_str),
+        query.get("to").map(String::as_str),
+        query.get("days").map(String::as_str),
+        clock::now_ms(),
+    );
+    let (from, to) = match range {
+        Ok(range) => range,
+        Err(message) => return Response::error(message, 400),
+    };
+
+    let log = IncidentLog::load(&store, scope).await?;
+    Response::from_json(&AlertAnalytics::compute(&log, from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn incident(url: &str, stream: AlertStream, started: u64, resolved: Option<u64>) -> Incident {
+        Incident {
+            stream,
+            url: url.to_string(),
+            started,
+            resolved,
+            error: match stream {
+                AlertStream::Availability => "http_503".to_string(),
+                AlertStream::Integrity => "sri_mismatch".to_string(),
+            },
+            run_id: None,
+            key: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2025-11-12"), Some(1_762_905_600_000));
+        assert_eq!(parse_date("2024-02-29"), Some(1_709_164_800_000));
+        assert_eq!(parse_date("2025-13-01"), None);
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let now = 100 * DAY_MS;
+        assert_eq!(parse_range(None, None, None, now), Ok((70 * DAY_MS, now)));
+        assert_eq!(
+            parse_range(None, None, Some("7"), now),
+            Ok((93 * DAY_MS, now))
+        );
+        assert_eq!(
+            parse_range(Some("1970-01-02"), Some("1970-01-02"), None, now),
+            Ok((DAY_MS, 2 * DAY_MS))
+        );
+        assert!(parse_range(Some("1970-01-03"), Some("1970-01-02"), None, now).is_err());
+        assert!(parse_range(None, None, Some("week"), now).is_err());
+    }
+
+    #[test]
+    fn test_compute() {
+        let log = IncidentLog {
+            incidents: vec![
+                incident("a", AlertStream::Availability, 0, Some(50)),
+                incident("a", AlertStream::Availability, 1_000, Some(1_100)),
+                incident("a", AlertStream::Availability, 2_000, Some(2_300)),
+                incident("b", AlertStream::Integrity, 1_500, None),
+                incident("b", AlertStream::Availability, 1_600, None),
+                incident("c", AlertStream::Availability, 5_000, Some(5_100)),
+            ],
+        };
+        let analytics = AlertAnalytics::compute(&log, 1_000, 5_000);
+
+        assert_eq!(analytics.incidents, 4);
+        assert_eq!(analytics.mttr_ms, Some(200));
+        assert_eq!(analytics.resources.len(), 2);
+        assert_eq!(
+            analytics.resources[0],
+            ResourceAlerts {
+                url: "a".to_string(),
+                incidents: 2,
+                availability: 2,
+                integrity: 0,
+                open: 0,
+                mttr_ms: Some(200),
+            }
+        );
+        assert_eq!(analytics.resources[1].open, 2);
+        assert_eq!(analytics.resources[1].mttr_ms, None);
+        // Two resolved incidents are four transitions, two open ones only two
+        assert_eq!(analytics.flappiest[0].url, "a");
+        assert_eq!(
+            analytics.error_types,
+            vec![
+                ErrorCount {
+                    error: "http_503".to_string(),
+                    count: 3
+                },
+                ErrorCount {
+                    error: "sri_mismatch".to_string(),
+                    count: 1
+                },
+            ]
+        );
+    }
+}
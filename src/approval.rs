@@ -0,0 +1,464 @@
+//! SRI change requests with an optional two-person approval rule
+//!
+//! SRI hashes can be updated at runtime via `POST /sri` without redeploying the
+//! worker. Approved changes are stored as overrides in the state store and take
+//! precedence over the hashes in config.toml.
+//!
+//! When `REQUIRE_SRI_APPROVAL=true`, a change is only stored as pending and must be
+//! approved by a *different* access token before it takes effect. Pending changes
+//! are listed at `GET /sri/pending` and announced via the configured webhook.
+//!
+//! With `SRI_ROLLOUT_MINUTES`, a change starts a propagation window during which
+//! content matching the previous hash is still accepted, since CDN caches around
+//! the world take a while to pick up a new deploy. Only content matching neither
+//! hash alerts; once the window ends the override is tightened to the new hash.
+//!
+//! Changes are scoped like everything else: tenants use the same endpoints under
+//! `/t/{tenant}/` and their overrides live under the tenant's state prefix.
+
+use crate::auth::Principal;
+use crate::checker::CheckResult;
+use crate::clock;
+use crate::config;
+use crate::console::{console_error, console_log};
+use crate::notify;
+use crate::sri::SriHash;
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use worker::*;
+
+const PENDING_PREFIX: &str = "sri/pending/";
+const OVERRIDE_PREFIX: &str = "sri/override/";
+const PENDING_PATH_PREFIX: &str = "/sri/pending/";
+
+/// Pending changes expire if nobody approves them within a week
+const PENDING_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Body of a `POST /sri` request
+#[derive(Debug, Deserialize)]
+pub struct SriChangeRequest {
+    pub url: String,
+    pub sri: String,
+}
+
+/// A requested SRI change awaiting approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingChange {
+    pub id: String,
+    pub url: String,
+    pub sri: String,
+    pub previous_sri: String,
+    pub requested_by: String,
+    pub requested_at: String,
+}
+
+/// An SRI change that is in effect, overriding config.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SriOverride {
+    pub url: String,
+    pub sri: String,
+    pub requested_by: String,
+    pub approved_by: String,
+    pub approved_at: String,
+    /// Propagation window of the change, while the previous hash is still accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<Rollout>,
+}
+
+/// Propagation window of an SRI change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rollout {
+    pub previous_sri: String,
+    /// End of the window (milliseconds since the Unix epoch)
+    pub until: u64,
+}
+
+impl Rollout {
+    /// Start a window for a change from `previous_sri` to `sri` from `SRI_ROLLOUT_MINUTES`
+    ///
+    /// Returns `None` when rollouts are disabled or there is no previous hash to accept.
+    fn start(env: &Env, previous_sri: &str, sri: &str, now: u64) -> Option<Self> {
+        let minutes = config::setting(env, "SRI_ROLLOUT_MINUTES")?
+            .parse::<u64>()
+            .ok()
+            .filter(|&m| m > 0)?;
+        (!previous_sri.is_empty() && previous_sri != sri).then(|| Self {
+            previous_sri: previous_sri.to_string(),
+            until: now + minutes * 60_000,
+        })
+    }
+
+    /// Accept an SRI mismatch whose content still matches the previous hash
+    pub fn verify(&self, result: &mut CheckResult) {
+        if result.sri_valid != Some(false) {
+            return;
+        }
+        let Some(artifact) = &result.artifact else {
+            return;
+        };
+        if SriHash::parse(&self.previous_sri).is_ok_and(|h| h.verify(&artifact.content)) {
+            console_log!(
+                "✓ {} - previous SRI still served during rollout",
+                result.url
+            );
+            result.sri_valid = Some(true);
+        }
+    }
+}
+
+/// Decision on a pending change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Approve,
+    Reject,
+}
+
+/// Validate a change request against the resources of a scope
+///
+/// Returns a human-readable reason if the request is not acceptable.
+fn validate_request(scope: &Scope, request: &SriChangeRequest) -> std::result::Result<(), String> {
+    if scope.find_resource(&request.url).is_none() {
+        return Err(format!("Unknown resource: {}", request.url));
+    }
+    SriHash::parse(&request.sri).map_err(|e| e.description().to_string())?;
+    Ok(())
+}
+
+/// Enforce the two-person rule: the approver must not be the requester
+fn check_approver(change: &PendingChange, approver: &Principal) -> std::result::Result<(), String> {
+    if change.requested_by == approver.name {
+        Err(format!(
+            "Change {} must be approved by a different token than '{}'",
+            change.id, change.requested_by
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Parse `/sri/pending/{id}/approve` or `/sri/pending/{id}/reject`
+pub fn parse_decision_path(path: &str) -> Option<(&str, Decision)> {
+    let rest = path.strip_prefix(PENDING_PATH_PREFIX)?;
+    let (id, action) = rest.split_once('/')?;
+    if id.is_empty() {
+        return None;
+    }
+    match action {
+        "approve" => Some((id, Decision::Approve)),
+        "reject" => Some((id, Decision::Reject)),
+        _ => None,
+    }
+}
+
+/// Whether SRI changes require approval by a second token
+fn approval_required(env: &Env) -> bool {
+    config::setting(env, "REQUIRE_SRI_APPROVAL").is_some_and(|v| v == "true" || v == "1")
+}
+
+/// Generate a short, unique-enough identifier for a pending change
+fn new_change_id() -> String {
+    let random = (js_sys::Math::random() * f64::from(u32::MAX)) as u32;
+    format!("{:x}{:08x}", clock::now_ms(), random)
+}
+
+/// Load all approved SRI hashes of a scope, keyed by resource URL
+///
+/// Returns an empty map if the state store is not configured or unreadable,
+/// so checks fall back to the hashes in config.toml.
+pub async fn load_overrides(env: &Env, scope: &Scope<'_>) -> HashMap<String, String> {
+    load(env, scope)
+        .await
+        .into_iter()
+        .map(|(url, over)| (url, over.sri))
+        .collect()
+}
+
+/// Load all approved SRI overrides of a scope with their rollouts, keyed by resource URL
+///
+/// Rollouts whose window has ended are removed from the stored override.
+pub async fn load(env: &Env, scope: &Scope<'_>) -> HashMap<String, SriOverride> {
+    let Some(store) = Store::from_env(env) else {
+        return HashMap::new();
+    };
+    let now = clock::now_ms();
+
+    let mut overrides = HashMap::new();
+    let keys = match store.list(&scope.key(OVERRIDE_PREFIX)).await {
+        Ok(keys) => keys,
+        Err(e) => {
+            console_error!("Failed to list SRI overrides: {}", e);
+            return overrides;
+        }
+    };
+    for key in keys {
+        match store.get::<SriOverride>(&key).await {
+            Ok(Some(mut o)) => {
+                if o.rollout.as_ref().is_some_and(|r| r.until <= now) {
+                    o.rollout = None;
+                    console_log!(
+                        "SRI rollout for {} complete, accepting {} only",
+                        o.url,
+                        o.sri
+                    );
+                    if let Err(e) = store.put(&key, &o).await {
+                        console_error!("Failed to end SRI rollout {}: {}", key, e);
+                    }
+                }
+                overrides.insert(o.url.clone(), o);
+            }
+            Ok(None) => {}
+            Err(e) => console_error!("Failed to read SRI override {}: {}", key, e),
+        }
+    }
+    overrides
+}
+
+/// Store an override so that it takes effect on the next check
+async fn apply_override(store: &Store, scope: &Scope<'_>, over: &SriOverride) -> Result<()> {
+    store
+        .put(
+            &scope.key(&format!("{}{}", OVERRIDE_PREFIX, over.url)),
+            over,
+        )
+        .await
+}
+
+/// Handle `POST /sri`: request an SRI change for a configured resource
+pub async fn handle_request(
+    env: &Env,
+    scope: &Scope<'_>,
+    principal: &Principal,
+    mut req: Request,
+) -> Result<Response> {
+    let mut request: SriChangeRequest = match req.json().await {
+        Ok(r) => r,
+        Err(_) => return Response::error("Expected JSON body with 'url' and 'sri'", 400),
+    };
+    // Key the change by the configured spelling of the URL
+    if let Some(resource) = scope.find_resource(&request.url) {
+        request.url = resource.url.clone();
+    }
+    if let Err(reason) = validate_request(scope, &request) {
+        return Response::error(reason, 400);
+    }
+
+    let store = Store::require(env)?;
+    let previous_sri = match load_overrides(env, scope).await.remove(&request.url) {
+        Some(sri) => sri,
+        None => scope
+            .find_resource(&request.url)
+            .map(|r| r.sri.to_string())
+            .unwrap_or_default(),
+    };
+
+    if !approval_required(env) {
+        let over = SriOverride {
+            rollout: Rollout::start(env, &previous_sri, &request.sri, clock::now_ms()),
+            url: request.url,
+            sri: request.sri,
+            requested_by: principal.name.clone(),
+            approved_by: principal.name.clone(),
+            approved_at: clock::timestamp(),
+        };
+        apply_override(&store, scope, &over).await?;
+        console_log!("SRI for {} updated by {}", over.url, over.approved_by);
+        return Response::from_json(&over);
+    }
+
+    let change = PendingChange {
+        id: new_change_id(),
+        url: request.url,
+        sri: request.sri,
+        previous_sri,
+        requested_by: principal.name.clone(),
+        requested_at: clock::timestamp(),
+    };
+    store
+        .put_with_ttl(
+            &scope.key(&format!("{}{}", PENDING_PREFIX, change.id)),
+            &change,
+            PENDING_TTL_SECS,
+        )
+        .await?;
+    console_log!(
+        "SRI change {} for {} requested by {}",
+        change.id,
+        change.url,
+        change.requested_by
+    );
+
+    let message = format!(
+        "SRI change {} requested by {}: {} → {}. Approve with POST {} using a different token.",
+        change.id,
+        change.requested_by,
+        change.previous_sri,
+        change.sri,
+        scope.path(&format!("{}{}/approve", PENDING_PATH_PREFIX, change.id))
+    );
+    if let Err(e) =
+        notify::send_approval_request_notification(env, scope, &change.url, message).await
+    {
+        console_error!("Failed to send approval request notification: {}", e);
+    }
+
+    Ok(Response::from_json(&change)?.with_status(202))
+}
+
+/// Handle `GET /sri/pending`: list changes awaiting approval
+pub async fn handle_list_pending(env: &Env, scope: &Scope<'_>) -> Result<Response> {
+    let store = Store::require(env)?;
+    let mut pending = Vec::new();
+    for key in store.list(&scope.key(PENDING_PREFIX)).await? {
+        if let Some(change) = store.get::<PendingChange>(&key).await? {
+            pending.push(change);
+        }
+    }
+    pending.sort_by(|a, b| a.requested_at.cmp(&b.requested_at));
+    Response::from_json(&pending)
+}
+
+/// Handle `POST /sri/pending/{id}/approve` and `POST /sri/pending/{id}/reject`
+pub async fn handle_decision(
+    env: &Env,
+    scope: &Scope<'_>,
+    principal: &Principal,
+    id: &str,
+    decision: Decision,
+) -> Result<Response> {
+    let store = Store::require(env)?;
+    let key = scope.key(&format!("{}{}", PENDING_PREFIX, id));
+    let Some(change) = store.get::<PendingChange>(&key).await? else {
+        return Response::error("Pending change not found", 404);
+    };
+
+    match decision {
+        Decision::Reject => {
+            store.delete(&key).await?;
+            console_log!("SRI change {} rejected by {}", change.id, principal.name);
+            Response::from_json(&change)
+        }
+        Decision::Approve => {
+            if let Err(reason) = check_approver(&change, principal) {
+                return Response::error(reason, 403);
+            }
+            let over = SriOverride {
+                rollout: Rollout::start(env, &change.previous_sri, &change.sri, clock::now_ms()),
+                url: change.url,
+                sri: change.sri,
+                requested_by: change.requested_by,
+                approved_by: principal.name.clone(),
+                approved_at: clock::timestamp(),
+            };
+            apply_override(&store, scope, &over).await?;
+            store.delete(&key).await?;
+            console_log!(
+                "SRI change {} for {} approved by {}",
+                change.id,
+                over.url,
+                over.approved_by
+            );
+            Response::from_json(&over)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(requested_by: &str) -> PendingChange {
+        PendingChange {
+            id: "abc".to_string(),
+            url: "https://example.com/app.js".to_string(),
+            sri: "sha256-uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=".to_string(),
+            previous_sri: String::new(),
+            requested_by: requested_by.to_string(),
+            requested_at: "2025-11-12T10:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_check_approver_two_person_rule() {
+        let change = pending("alice");
+        let alice = Principal {
+            name: "alice".to_string(),
+            tenant: None,
+        };
+        let bob = Principal {
+            name: "bob".to_string(),
+            tenant: None,
+        };
+        assert!(check_approver(&change, &alice).is_err());
+        assert!(check_approver(&change, &bob).is_ok());
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let config = config::compiled();
+        let scope = Scope::root(&config);
+        let configured = &scope.resources()[0];
+        let valid = SriChangeRequest {
+            url: configured.url.to_string(),
+            sri: "sha256-uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=".to_string(),
+        };
+        assert!(validate_request(&scope, &valid).is_ok());
+
+        let bad_sri = SriChangeRequest {
+            url: configured.url.to_string(),
+            sri: "sha384-nope".to_string(),
+        };
+        assert!(validate_request(&scope, &bad_sri).is_err());
+
+        let unknown = SriChangeRequest {
+            url: "https://unknown.example/app.js".to_string(),
+            sri: valid.sri.clone(),
+        };
+        assert!(validate_request(&scope, &unknown).is_err());
+    }
+
+    #[test]
+    fn test_parse_decision_path() {
+        assert_eq!(
+            parse_decision_path("/sri/pending/abc123/approve"),
+            Some(("abc123", Decision::Approve))
+        );
+        assert_eq!(
+            parse_decision_path("/sri/pending/abc123/reject"),
+            Some(("abc123", Decision::Reject))
+        );
+        assert_eq!(parse_decision_path("/sri/pending/abc123/delete"), None);
+        assert_eq!(parse_decision_path("/sri/pending//approve"), None);
+        assert_eq!(parse_decision_path("/sri/pending"), None);
+    }
+
+    #[test]
+    fn test_rollout_accepts_previous_hash() {
+        use crate::checker::Artifact;
+        use crate::sri;
+
+        let served = |content: &[u8]| {
+            let mut result = CheckResult::success("https://example.com/app.js", 200, false);
+            result.artifact = Some(Artifact {
+                content: content.to_vec(),
+                content_type: None,
+                expected_sri: sri::sha384(b"v2"),
+            });
+            result
+        };
+        let rollout = Rollout {
+            previous_sri: sri::sha384(b"v1"),
+            until: 0,
+        };
+
+        let mut stale = served(b"v1");
+        rollout.verify(&mut stale);
+        assert_eq!(stale.sri_valid, Some(true));
+
+        let mut tampered = served(b"evil");
+        rollout.verify(&mut tampered);
+        assert_eq!(tampered.sri_valid, Some(false));
+        assert!(tampered.has_problem());
+    }
+}
@@ -0,0 +1,190 @@
+//! Retention of resources removed from config
+//!
+//! Removing a resource from config.toml does not delete what is known about it.
+//! Its history stays in the history document marked as archived, with the time
+//! of removal, and its incidents stay in the incident log, so a postmortem can
+//! still look at them. After `retention_days` both are purged. A resource that is
+//! added back before then simply continues its history.
+//!
+//! Removals are detected by comparing the resources of a run with the URLs
+//! configured at the previous run, which the history document carries along;
+//! results of sitemap and crawl pages are never archived.
+//!
+//! ```toml
+//! [archive]
+//! retention_days = 30
+//! ```
+
+use crate::console::{console_error, console_log};
+use crate::history::History;
+use crate::incident::IncidentLog;
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+const DAY_MS: u64 = 86_400_000;
+
+/// Archive settings of config.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveConfig {
+    /// Days the data of a removed resource is kept
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u64,
+}
+
+fn default_retention_days() -> u64 {
+    30
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: default_retention_days(),
+        }
+    }
+}
+
+impl ArchiveConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.retention_days == 0 {
+            return Err("Archive retention_days must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// When the data of a resource removed at `removed_at` is purged
+    pub fn purge_at(&self, removed_at: u64) -> u64 {
+        removed_at.saturating_add(self.retention_days.saturating_mul(DAY_MS))
+    }
+}
+
+/// Archive resources removed since the previous run and purge expired ones
+///
+/// Returns the URLs whose data was purged from the history.
+pub fn reconcile(history: &mut History, scope: &Scope, now: u64) -> Vec<String> {
+    let configured: BTreeSet<String> = scope.resources().iter().map(|r| r.url.clone()).collect();
+    for url in history.configured.difference(&configured) {
+        history.archived.entry(url.clone()).or_insert(now);
+    }
+    history.archived.retain(|url, _| !configured.contains(url));
+    history.configured = configured;
+
+    let config = &scope.config().archive;
+    let purged: Vec<String> = history
+        .archived
+        .iter()
+        .filter(|(_, &removed_at)| now >= config.purge_at(removed_at))
+        .map(|(url, _)| url.clone())
+        .collect();
+    for url in &purged {
+        history.archived.remove(url);
+        history.resources.remove(url);
+        history.latency.remove(url);
+    }
+    purged
+}
+
+/// Drop the incidents of purged resources from the incident log
+pub async fn purge_incidents(store: &Store, scope: &Scope<'_>, purged: &[String]) {
+    let mut log = match IncidentLog::load(store, scope).await {
+        Ok(log) => log,
+        Err(e) => {
+            console_error!("Failed to load incidents: {}", e);
+            return;
+        }
+    };
+    let before = log.incidents.len();
+    log.incidents.retain(|i| !purged.contains(&i.url));
+    if log.incidents.len() == before {
+        return;
+    }
+    console_log!(
+        "Purged {} incidents of {} archived resources",
+        before - log.incidents.len(),
+        purged.len()
+    );
+    if let Err(e) = log.save(store, scope).await {
+        console_error!("Failed to save incidents: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::CheckResult;
+    use crate::config::Config;
+
+    const A: &str = "https://example.com/a.js";
+    const B: &str = "https://example.com/b.js";
+
+    fn config(urls: &[&str]) -> Config {
+        let mut toml = "version = \"1.0\"\n[archive]\nretention_days = 2\n".to_string();
+        for url in urls {
+            toml.push_str(&format!(
+                "[[resources]]\nurl = \"{}\"\nsri = \"sha384-abc\"\n",
+                url
+            ));
+        }
+        Config::from_toml(&toml).unwrap()
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let mut history = History::default();
+        history.record(
+            &[
+                CheckResult::success(A, 200, true),
+                CheckResult::success(B, 200, true),
+                // A sitemap page, never configured
+                CheckResult::success("https://example.com/page", 200, true),
+            ],
+            0,
+        );
+        let both = config(&[A, B]);
+        assert!(reconcile(&mut history, &Scope::root(&both), 0).is_empty());
+        assert!(history.archived.is_empty());
+
+        // B is removed, and archived at the first run without it
+        let only_a = config(&[A]);
+        assert!(reconcile(&mut history, &Scope::root(&only_a), DAY_MS).is_empty());
+        assert!(reconcile(&mut history, &Scope::root(&only_a), 2 * DAY_MS).is_empty());
+        assert_eq!(history.archived.keys().collect::<Vec<_>>(), [B]);
+        assert_eq!(history.archived[B], DAY_MS);
+        assert_eq!(history.entries(B).len(), 1);
+
+        // Purged once the retention has passed
+        assert_eq!(
+            reconcile(&mut history, &Scope::root(&only_a), 3 * DAY_MS),
+            [B]
+        );
+        assert!(history.archived.is_empty());
+        assert!(history.entries(B).is_empty());
+        assert_eq!(history.entries(A).len(), 1);
+        assert_eq!(history.entries("https://example.com/page").len(), 1);
+    }
+
+    #[test]
+    fn test_reconcile_restored() {
+        let mut history = History::default();
+        history.record(&[CheckResult::success(B, 200, true)], 0);
+        let both = config(&[A, B]);
+        let only_a = config(&[A]);
+        reconcile(&mut history, &Scope::root(&both), 0);
+        reconcile(&mut history, &Scope::root(&only_a), DAY_MS);
+        assert!(history.archived.contains_key(B));
+
+        // Adding the resource back keeps its history
+        reconcile(&mut history, &Scope::root(&both), 2 * DAY_MS);
+        assert!(history.archived.is_empty());
+        assert!(reconcile(&mut history, &Scope::root(&both), 10 * DAY_MS).is_empty());
+        assert_eq!(history.entries(B).len(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ArchiveConfig::default().validate().is_ok());
+        assert!(ArchiveConfig { retention_days: 0 }.validate().is_err());
+        assert_eq!(ArchiveConfig::default().purge_at(0), 30 * DAY_MS);
+    }
+}
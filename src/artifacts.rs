@@ -0,0 +1,429 @@
+//! Artifact storage backed by R2
+//!
+//! An optional R2 bucket bound as `LINKKIVAHTI_ARTIFACTS` keeps copies of checked
+//! content. When an SRI mismatch is alerted, the offending content is quarantined
+//! under a timestamped key, so security can examine it even after the CDN content
+//! changes again. Like the state store, the binding is optional.
+//!
+//! With `MIRROR_ARTIFACTS=true`, every SRI-verified response is also kept as the
+//! known-good copy of its resource, and availability alerts point at that copy so
+//! consumers can fail over. `GET /mirror/{resource-name}` serves these copies, which
+//! makes the worker a verified fallback CDN during upstream outages.
+
+use crate::checker::{Artifact, CheckResult};
+use crate::clock;
+use crate::config;
+use crate::console::{console_error, console_log};
+use crate::sri::{self, SriHash};
+use crate::tenant::Scope;
+use futures::future::join_all;
+use std::collections::HashMap;
+use worker::*;
+
+/// Name of the R2 bucket binding in wrangler.toml
+pub const BINDING: &str = "LINKKIVAHTI_ARTIFACTS";
+
+/// Default limit of quarantined content per object
+const DEFAULT_QUARANTINE_MAX_KB: usize = 1024;
+
+/// Default `Cache-Control` max-age of mirrored artifacts
+const DEFAULT_MIRROR_CACHE_SECONDS: u32 = 300;
+
+/// Path prefix of the mirror endpoint
+const MIRROR_PATH_PREFIX: &str = "/mirror/";
+
+/// Thin wrapper around the artifact bucket
+pub struct Artifacts {
+    bucket: Bucket,
+}
+
+/// Object key path of a URL: host and path without scheme or query
+///
+/// Characters outside a conservative set are replaced, so keys stay readable
+/// in the dashboard and safe to use in URLs.
+fn object_path(url: &str) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = without_scheme.split(['?', '#']).next().unwrap_or_default();
+    path.trim_end_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '/') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Key of a quarantined copy of a resource
+fn quarantine_key(scope: &Scope, url: &str, timestamp: &str) -> String {
+    scope.key(&format!("quarantine/{}/{}", object_path(url), timestamp))
+}
+
+/// Key of the known-good copy of a resource
+pub fn mirror_key(scope: &Scope, url: &str) -> String {
+    scope.key(&format!("mirror/{}", object_path(url)))
+}
+
+/// Whether known-good copies are kept (`MIRROR_ARTIFACTS`)
+fn mirror_enabled(env: &Env) -> bool {
+    config::setting(env, "MIRROR_ARTIFACTS").is_some_and(|v| v == "true" || v == "1")
+}
+
+/// Quarantine size limit from `QUARANTINE_MAX_KB`
+fn quarantine_max_bytes(env: &Env) -> usize {
+    config::setting(env, "QUARANTINE_MAX_KB")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_QUARANTINE_MAX_KB)
+        * 1024
+}
+
+impl Artifacts {
+    /// Open the artifact bucket, or `None` if the binding is not configured
+    pub fn from_env(env: &Env) -> Option<Self> {
+        env.bucket(BINDING).ok().map(|bucket| Self { bucket })
+    }
+
+    /// Open the artifact bucket, failing with a descriptive error if it is not configured
+    pub fn require(env: &Env) -> Result<Self> {
+        Self::from_env(env)
+            .ok_or_else(|| Error::RustError(format!("{} R2 binding not configured", BINDING)))
+    }
+
+    /// Store the content of a mismatching result and return a reference for alerts
+    ///
+    /// Only the first `max_bytes` are stored; the size and hash in the object's
+    /// metadata always describe the complete content.
+    pub async fn quarantine(
+        &self,
+        scope: &Scope<'_>,
+        result: &CheckResult,
+        max_bytes: usize,
+    ) -> Result<Option<String>> {
+        let Some(artifact) = &result.artifact else {
+            return Ok(None);
+        };
+        let key = quarantine_key(scope, &result.url, &clock::timestamp());
+        let size = artifact.content.len();
+        let stored = size.min(max_bytes);
+        let actual_sri = sri::sha384(&artifact.content);
+
+        let metadata = HashMap::from([
+            ("url".to_string(), result.url.to_string()),
+            ("expected-sri".to_string(), artifact.expected_sri.clone()),
+            ("actual-sri".to_string(), actual_sri.clone()),
+            ("size".to_string(), size.to_string()),
+            ("stored-bytes".to_string(), stored.to_string()),
+            (
+                "status".to_string(),
+                result.status_code.unwrap_or_default().to_string(),
+            ),
+        ]);
+        self.bucket
+            .put(&key, artifact.content[..stored].to_vec())
+            .http_metadata(HttpMetadata {
+                content_type: artifact.content_type.clone(),
+                ..Default::default()
+            })
+            .custom_metadata(metadata)
+            .execute()
+            .await?;
+
+        console_log!("Quarantined {} as {}", result.url, key);
+        Ok(Some(if stored < size {
+            format!(
+                "{} (first {} of {} bytes, {})",
+                key, stored, size, actual_sri
+            )
+        } else {
+            format!("{} ({} bytes, {})", key, size, actual_sri)
+        }))
+    }
+
+    /// Store a verified artifact as the known-good copy of a resource
+    ///
+    /// Unchanged content is not uploaded again, so the verification time records
+    /// when the current copy was first verified.
+    pub async fn mirror(&self, key: &str, url: &str, artifact: &Artifact) -> Result<bool> {
+        if let Some(existing) = self.bucket.head(key).await? {
+            let metadata = existing.custom_metadata()?;
+            if metadata.get("sri") == Some(&artifact.expected_sri) {
+                return Ok(false);
+            }
+        }
+
+        let metadata = HashMap::from([
+            ("url".to_string(), url.to_string()),
+            ("sri".to_string(), artifact.expected_sri.clone()),
+            ("verified-at".to_string(), clock::timestamp()),
+        ]);
+        self.bucket
+            .put(key, artifact.content.clone())
+            .http_metadata(HttpMetadata {
+                content_type: artifact.content_type.clone(),
+                ..Default::default()
+            })
+            .custom_metadata(metadata)
+            .execute()
+            .await?;
+        Ok(true)
+    }
+
+    /// Describe the known-good copy of a resource for alerts, if there is one
+    pub async fn mirror_reference(
+        &self,
+        key: &str,
+        public_url: Option<&str>,
+    ) -> Result<Option<String>> {
+        let Some(object) = self.bucket.head(key).await? else {
+            return Ok(None);
+        };
+        let metadata = object.custom_metadata()?;
+        let location = match public_url {
+            Some(base) => format!("{}/{}", base.trim_end_matches('/'), key),
+            None => key.to_string(),
+        };
+        Ok(Some(match metadata.get("verified-at") {
+            Some(verified_at) => format!("{} (verified {})", location, verified_at),
+            None => location,
+        }))
+    }
+}
+
+/// Keep the SRI-verified artifacts of a run as known-good copies
+///
+/// Does nothing unless `MIRROR_ARTIFACTS` is enabled and the bucket is bound.
+pub async fn mirror_verified(env: &Env, scope: &Scope<'_>, results: &[CheckResult]) {
+    if !mirror_enabled(env) {
+        return;
+    }
+    let Some(artifacts) = Artifacts::from_env(env) else {
+        console_error!("MIRROR_ARTIFACTS is set but {} is not bound", BINDING);
+        return;
+    };
+
+    let uploads = results
+        .iter()
+        .filter(|r| r.sri_valid == Some(true))
+        .filter_map(|r| Some((r, r.artifact.as_ref()?)))
+        .map(|(result, artifact)| {
+            let artifacts = &artifacts;
+            async move {
+                let key = mirror_key(scope, &result.url);
+                match artifacts.mirror(&key, &result.url, artifact).await {
+                    Ok(true) => console_log!("Mirrored {} as {}", result.url, key),
+                    Ok(false) => {}
+                    Err(e) => console_error!("Failed to mirror {}: {}", result.url, e),
+                }
+            }
+        });
+    join_all(uploads).await;
+}
+
+/// Describe the known-good copy of a resource for an availability alert
+///
+/// Links use `MIRROR_PUBLIC_URL` (e.g. the bucket's custom domain) as base if set,
+/// otherwise the object key is given.
+pub async fn mirror_reference(env: &Env, scope: &Scope<'_>, url: &str) -> Option<String> {
+    if !mirror_enabled(env) {
+        return None;
+    }
+    let artifacts = Artifacts::from_env(env)?;
+    let public_url = config::setting(env, "MIRROR_PUBLIC_URL");
+    match artifacts
+        .mirror_reference(&mirror_key(scope, url), public_url.as_deref())
+        .await
+    {
+        Ok(reference) => reference,
+        Err(e) => {
+            console_error!("Failed to look up mirror of {}: {}", url, e);
+            None
+        }
+    }
+}
+
+/// Extract the resource name from a `/mirror/{resource-name}` path
+pub fn parse_mirror_path(path: &str) -> Option<String> {
+    let name = path.strip_prefix(MIRROR_PATH_PREFIX)?;
+    (!name.is_empty()).then(|| percent_decode(name))
+}
+
+/// Decode `%XX` escapes of a path segment, leaving malformed escapes as they are
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| segment.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Handle `GET /mirror/{resource-name}`: serve the last SRI-verified copy
+///
+/// The content is verified against its recorded hash again before it is served,
+/// and the hash is returned in `X-Integrity` so clients can pin it.
+pub async fn handle_mirror(
+    env: &Env,
+    scope: &Scope<'_>,
+    req: &Request,
+    name: &str,
+) -> Result<Response> {
+    let Some(resource) = scope
+        .resources()
+        .iter()
+        .find(|r| r.name.as_deref() == Some(name))
+    else {
+        return Response::error("Unknown resource", 404);
+    };
+
+    let artifacts = Artifacts::require(env)?;
+    let key = mirror_key(scope, &resource.url);
+    let Some(object) = artifacts.bucket.get(&key).execute().await? else {
+        return Response::error("No verified copy available", 404);
+    };
+    let metadata = object.custom_metadata()?;
+    let integrity = metadata.get("sri").cloned().unwrap_or_default();
+    let etag = object.http_etag();
+    let content_type = object
+        .http_metadata()
+        .content_type
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let cache_seconds = config::setting(env, "MIRROR_CACHE_SECONDS")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MIRROR_CACHE_SECONDS);
+    let headers = Headers::new();
+    headers.set(
+        "Cache-Control",
+        &format!("public, max-age={}", cache_seconds),
+    )?;
+    headers.set("ETag", &etag)?;
+    headers.set("X-Integrity", &integrity)?;
+    // Scripts loaded with `integrity` need CORS
+    headers.set("Access-Control-Allow-Origin", "*")?;
+    if let Some(verified_at) = metadata.get("verified-at") {
+        headers.set("X-Verified-At", verified_at)?;
+    }
+
+    if req.headers().get("If-None-Match")?.as_deref() == Some(etag.as_str()) {
+        return Ok(Response::empty()?.with_status(304).with_headers(headers));
+    }
+
+    let content = match object.body() {
+        Some(body) => body.bytes().await?,
+        None => Vec::new(),
+    };
+    let verified = SriHash::parse(&integrity).is_ok_and(|sri| sri.verify(&content));
+    if !verified {
+        console_error!("Mirrored copy {} does not match its recorded hash", key);
+        return Response::error("Verified copy is corrupt", 500);
+    }
+
+    headers.set("Content-Type", &content_type)?;
+    Ok(Response::from_bytes(content)?.with_headers(headers))
+}
+
+/// Quarantine the content of a mismatching result, logging failures
+///
+/// Returns the reference to include in the alert, or `None` if the bucket is not
+/// configured or the content could not be stored.
+pub async fn quarantine(env: &Env, scope: &Scope<'_>, result: &CheckResult) -> Option<String> {
+    let artifacts = Artifacts::from_env(env)?;
+    match artifacts
+        .quarantine(scope, result, quarantine_max_bytes(env))
+        .await
+    {
+        Ok(reference) => reference,
+        Err(e) => {
+            console_error!("Failed to quarantine {}: {}", result.url, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_object_path() {
+        assert_eq!(
+            object_path("https://cdn.example.com/lib/app.min.js"),
+            "cdn.example.com/lib/app.min.js"
+        );
+        assert_eq!(
+            object_path("https://example.com/api/?v=1#x"),
+            "example.com/api"
+        );
+        assert_eq!(
+            object_path("https://example.com/a b@2.js"),
+            "example.com/a_b_2.js"
+        );
+    }
+
+    #[test]
+    fn test_parse_mirror_path() {
+        assert_eq!(
+            parse_mirror_path("/mirror/widget"),
+            Some("widget".to_string())
+        );
+        assert_eq!(
+            parse_mirror_path("/mirror/Main%20Widget"),
+            Some("Main Widget".to_string())
+        );
+        assert_eq!(parse_mirror_path("/mirror/"), None);
+        assert_eq!(parse_mirror_path("/mirrors/widget"), None);
+        assert_eq!(percent_decode("100%-%zz%41"), "100%-%zzA");
+    }
+
+    #[test]
+    fn test_mirror_key() {
+        let config = Config::from_toml("version = \"1.0\"").unwrap();
+        assert_eq!(
+            mirror_key(
+                &Scope::root(&config),
+                "https://cdn.example.com/lib/app.js?v=2"
+            ),
+            "mirror/cdn.example.com/lib/app.js"
+        );
+    }
+
+    #[test]
+    fn test_quarantine_key() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[tenants]]
+            id = "web"
+            "#,
+        )
+        .unwrap();
+        let url = "https://example.com/a.js";
+        let timestamp = "2025-11-12T10:00:00Z";
+        assert_eq!(
+            quarantine_key(&Scope::root(&config), url, timestamp),
+            "quarantine/example.com/a.js/2025-11-12T10:00:00Z"
+        );
+        assert_eq!(
+            quarantine_key(&Scope::tenant(&config, "web").unwrap(), url, timestamp),
+            "t/web/quarantine/example.com/a.js/2025-11-12T10:00:00Z"
+        );
+    }
+}
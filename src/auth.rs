@@ -0,0 +1,528 @@
+//! Bearer token authentication for secured endpoints
+//!
+//! Tokens come from two secrets:
+//! - `ACCESS_TOKEN`: a single token, identified as `default`
+//! - `ACCESS_TOKENS`: comma-separated `name=token` pairs for additional named tokens
+//!
+//! Token names identify who performed an action (e.g. who requested or approved
+//! an SRI change) without ever logging the raw secret.
+//!
+//! A name of the form `name@tenant` scopes the token to a single tenant: it can only
+//! access that tenant's endpoints under `/t/{tenant}/...`. Unscoped tokens can access
+//! every scope.
+//!
+//! Tokens are hashed with SHA-256 when loaded and only digests are compared, so a
+//! raw secret never takes part in a comparison. `POST /admin/token/rotate` replaces
+//! a configured token with a generated one whose digest is kept in KV under
+//! `auth/tokens`; the replaced token keeps working for a grace period so clients
+//! can switch over. A rotation applies as long as its name is still configured in
+//! the secrets, so removing the name revokes the rotated token too.
+
+use crate::clock;
+use crate::console::{console_error, console_log};
+use crate::sri;
+use crate::store::Store;
+use crate::tenant::Scope;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+/// KV key of the rotated tokens
+const ROTATIONS_KEY: &str = "auth/tokens";
+
+/// Prefix of generated tokens, telling them apart from other secrets in logs and leaks
+const TOKEN_PREFIX: &str = "lkv_";
+
+/// Grace period of the replaced token when the request does not set one
+const DEFAULT_GRACE_MINUTES: u64 = 60;
+
+/// Longest grace period of a replaced token (one week)
+const MAX_GRACE_MINUTES: u64 = 7 * 24 * 60;
+
+/// Identity of an authenticated caller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// Name of the token used to authenticate
+    pub name: String,
+    /// Tenant the token is restricted to, if any
+    pub tenant: Option<String>,
+}
+
+impl Principal {
+    /// Create a principal from a configured token name (`name` or `name@tenant`)
+    fn from_token_name(token_name: &str) -> Self {
+        match token_name.split_once('@') {
+            Some((name, tenant)) => Self {
+                name: name.to_string(),
+                tenant: Some(tenant.to_string()),
+            },
+            None => Self {
+                name: token_name.to_string(),
+                tenant: None,
+            },
+        }
+    }
+
+    /// Whether this principal may act on the given scope
+    pub fn can_access(&self, scope: &Scope) -> bool {
+        match &self.tenant {
+            None => true,
+            Some(tenant) => scope.id() == Some(tenant.as_str()),
+        }
+    }
+}
+
+/// Parse a comma-separated `name=token` list
+///
+/// Entries without a name are skipped, as are empty tokens.
+fn parse_named_tokens(spec: &str) -> Vec<(String, String)> {
+    spec.split(',')
+        .filter_map(|entry| {
+            let (name, token) = entry.trim().split_once('=')?;
+            let (name, token) = (name.trim(), token.trim());
+            if name.is_empty() || token.is_empty() {
+                None
+            } else {
+                Some((name.to_string(), token.to_string()))
+            }
+        })
+        .collect()
+}
+
+/// SHA-256 hex digest of a token
+fn token_hash(token: &str) -> String {
+    sri::sha256_hex(token.as_bytes())
+}
+
+/// Collect all configured `(name, token digest)` pairs from the secrets
+fn configured_tokens(env: &Env) -> Vec<(String, String)> {
+    let mut tokens = Vec::new();
+
+    if let Ok(token) = env.secret("ACCESS_TOKEN") {
+        let token = token.to_string();
+        if !token.is_empty() {
+            tokens.push(("default".to_string(), token));
+        }
+    }
+    if let Ok(spec) = env.secret("ACCESS_TOKENS") {
+        tokens.extend(parse_named_tokens(&spec.to_string()));
+    }
+
+    tokens
+        .into_iter()
+        .map(|(name, token)| (name, token_hash(&token)))
+        .collect()
+}
+
+/// A configured token replaced by `POST /admin/token/rotate`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Rotation {
+    /// Digest of the current token
+    hash: String,
+    /// Digest of the replaced token, accepted until `previous_until`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    previous: Option<String>,
+    #[serde(default)]
+    previous_until: u64,
+    rotated_at: u64,
+    rotated_by: String,
+}
+
+/// Rotated tokens by configured token name, stored under `auth/tokens`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Rotations {
+    tokens: BTreeMap<String, Rotation>,
+}
+
+impl Rotations {
+    async fn load(store: &Store) -> Self {
+        match store.get(ROTATIONS_KEY).await {
+            Ok(rotations) => rotations.unwrap_or_default(),
+            Err(e) => {
+                console_error!("Failed to load rotated tokens: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Token digests accepted at `now`: rotated tokens replace the configured ones
+    /// of the same name, next to the replaced token during its grace period
+    fn apply(&self, configured: Vec<(String, String)>, now: u64) -> Vec<(String, String)> {
+        let mut tokens = Vec::new();
+        for (name, hash) in configured {
+            match self.tokens.get(&name) {
+                None => tokens.push((name, hash)),
+                Some(rotation) => {
+                    if let Some(previous) = &rotation.previous {
+                        if now < rotation.previous_until {
+                            tokens.push((name.clone(), previous.clone()));
+                        }
+                    }
+                    tokens.push((name, rotation.hash.clone()));
+                }
+            }
+        }
+        tokens
+    }
+
+    /// Replace the token of `name` with the one of digest `hash`, keeping the
+    /// current one valid for `grace_ms`
+    ///
+    /// Returns when the replaced token stops working; a rotation during the
+    /// grace period of an earlier one ends that grace period.
+    fn rotate(
+        &mut self,
+        configured: &[(String, String)],
+        name: &str,
+        hash: String,
+        grace_ms: u64,
+        rotated_by: &str,
+        now: u64,
+    ) -> std::result::Result<u64, String> {
+        let current = match (
+            self.tokens.get(name),
+            configured.iter().find(|(n, _)| n == name),
+        ) {
+            (_, None) => return Err(format!("Unknown token '{}'", name)),
+            (Some(rotation), Some(_)) => rotation.hash.clone(),
+            (None, Some((_, hash))) => hash.clone(),
+        };
+        let previous_until = now + grace_ms;
+        self.tokens.insert(
+            name.to_string(),
+            Rotation {
+                hash,
+                previous: (grace_ms > 0).then_some(current),
+                previous_until,
+                rotated_at: now,
+                rotated_by: rotated_by.to_string(),
+            },
+        );
+        Ok(previous_until)
+    }
+}
+
+/// All accepted `(name, token digest)` pairs: the configured tokens with their
+/// rotations applied
+async fn tokens(env: &Env) -> Vec<(String, String)> {
+    let configured = configured_tokens(env);
+    if configured.is_empty() {
+        return configured;
+    }
+    match Store::from_env(env) {
+        Some(store) => Rotations::load(&store)
+            .await
+            .apply(configured, clock::now_ms()),
+        None => configured,
+    }
+}
+
+/// Match an Authorization header value against the accepted token digests
+fn authenticate(tokens: &[(String, String)], auth_header: &str) -> Option<Principal> {
+    let presented = token_hash(auth_header.strip_prefix("Bearer ")?);
+    tokens
+        .iter()
+        .find(|(_, hash)| *hash == presented)
+        .map(|(name, _)| Principal::from_token_name(name))
+}
+
+/// Name identifying the caller of a request in the access log
+///
+/// The configured token name (`name` or `name@tenant`) for a known token,
+/// `anonymous` without an Authorization header and `invalid` otherwise.
+fn caller_name(tokens: &[(String, String)], auth_header: Option<&str>) -> String {
+    let Some(header) = auth_header else {
+        return "anonymous".to_string();
+    };
+    let presented = token_hash(header.strip_prefix("Bearer ").unwrap_or_default());
+    tokens
+        .iter()
+        .find(|(_, hash)| *hash == presented)
+        .map_or_else(|| "invalid".to_string(), |(name, _)| name.clone())
+}
+
+/// Identify the caller of a request without requiring authentication (see `caller_name`)
+pub async fn caller(env: &Env, req: &Request) -> String {
+    let header = req.headers().get("Authorization").ok().flatten();
+    match header {
+        Some(_) => caller_name(&tokens(env).await, header.as_deref()),
+        None => caller_name(&[], None),
+    }
+}
+
+/// Check Authorization header for secured endpoints to match one of the access tokens
+/// (as configured by the ACCESS_TOKEN and ACCESS_TOKENS secrets, or rotated).
+/// Returns an error if the token is missing or invalid.
+/// # Arguments
+/// * `req` - The incoming HTTP request
+/// # Returns
+/// The authenticated `Principal` if authorized, Err otherwise
+pub async fn check_auth(env: &Env, req: &Request) -> Result<Principal> {
+    let tokens = tokens(env).await;
+    if tokens.is_empty() {
+        return Err(Error::RustError("ACCESS_TOKEN not configured".to_string()));
+    }
+
+    let auth_header = req
+        .headers()
+        .get("Authorization")?
+        .ok_or_else(|| Error::RustError("Missing Authorization header".to_string()))?;
+
+    authenticate(&tokens, &auth_header).ok_or_else(|| Error::RustError("Unauthorized".to_string()))
+}
+
+/// Check authentication like `check_auth` and additionally require access to `scope`
+///
+/// Tenant-scoped tokens are rejected outside their own tenant.
+pub async fn authorize(env: &Env, req: &Request, scope: &Scope<'_>) -> Result<Principal> {
+    let principal = check_auth(env, req).await?;
+    if !principal.can_access(scope) {
+        return Err(Error::RustError(format!(
+            "Token '{}' is not allowed to access scope '{}'",
+            principal.name,
+            scope.label()
+        )));
+    }
+    Ok(principal)
+}
+
+/// Body of `POST /admin/token/rotate`, all fields optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotateRequest {
+    /// Configured token name to rotate, the caller's own token by default
+    name: Option<String>,
+    /// How long the replaced token keeps working
+    grace_minutes: Option<u64>,
+}
+
+/// Response of `POST /admin/token/rotate`; the only time the new token is shown
+#[derive(Debug, Serialize)]
+struct RotateResponse {
+    name: String,
+    token: String,
+    /// When the replaced token stops working
+    previous_valid_until: String,
+}
+
+/// 32 random bytes from the runtime's CSPRNG
+#[cfg(not(test))]
+fn random_bytes() -> Result<[u8; 32]> {
+    use worker::js_sys::{self, Function, Reflect, Uint8Array};
+    use worker::wasm_bindgen::JsCast;
+
+    let crypto = Reflect::get(&js_sys::global(), &"crypto".into())?;
+    let get_random_values: Function = Reflect::get(&crypto, &"getRandomValues".into())?
+        .dyn_into()
+        .map_err(|_| Error::RustError("crypto.getRandomValues unavailable".to_string()))?;
+    let array = Uint8Array::new_with_length(32);
+    get_random_values.call1(&crypto, &array)?;
+    let mut bytes = [0; 32];
+    array.copy_to(&mut bytes);
+    Ok(bytes)
+}
+
+/// Fixed bytes for tests, where `crypto` is unavailable
+#[cfg(test)]
+fn random_bytes() -> Result<[u8; 32]> {
+    Ok([7; 32])
+}
+
+/// Generate a new token
+fn generate_token() -> Result<String> {
+    Ok(format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        BASE64URL.encode(random_bytes()?)
+    ))
+}
+
+/// Handle `POST /admin/token/rotate`: replace a configured token with a new one
+///
+/// Only unscoped tokens may rotate, since a rotation can lock out any caller.
+pub async fn handle_rotate(env: &Env, principal: &Principal, mut req: Request) -> Result<Response> {
+    if principal.tenant.is_some() {
+        return Response::error("Token rotation requires an unscoped token", 403);
+    }
+    let body = req.text().await?;
+    let request: RotateRequest = if body.trim().is_empty() {
+        RotateRequest::default()
+    } else {
+        match serde_json::from_str(&body) {
+            Ok(request) => request,
+            Err(e) => return Response::error(format!("Invalid rotation request: {}", e), 400),
+        }
+    };
+    let grace_minutes = request.grace_minutes.unwrap_or(DEFAULT_GRACE_MINUTES);
+    if grace_minutes > MAX_GRACE_MINUTES {
+        return Response::error(
+            format!("grace_minutes must be at most {}", MAX_GRACE_MINUTES),
+            400,
+        );
+    }
+    let name = request.name.unwrap_or_else(|| principal.name.clone());
+
+    let store = Store::require(env)?;
+    let mut rotations = Rotations::load(&store).await;
+    let token = generate_token()?;
+    let now = clock::now_ms();
+    let previous_until = match rotations.rotate(
+        &configured_tokens(env),
+        &name,
+        token_hash(&token),
+        grace_minutes * 60_000,
+        &principal.name,
+        now,
+    ) {
+        Ok(until) => until,
+        Err(reason) => return Response::error(reason, 404),
+    };
+    store.put(ROTATIONS_KEY, &rotations).await?;
+    console_log!(
+        "Token '{}' rotated by {}, previous token valid until {}",
+        name,
+        principal.name,
+        clock::format_ms(previous_until)
+    );
+
+    Response::from_json(&RotateResponse {
+        name,
+        token,
+        previous_valid_until: clock::format_ms(previous_until),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// Token digests of a `name=token` list, as `configured_tokens` loads them
+    fn hashed(spec: &str) -> Vec<(String, String)> {
+        parse_named_tokens(spec)
+            .into_iter()
+            .map(|(name, token)| (name, token_hash(&token)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_named_tokens() {
+        let tokens = parse_named_tokens("alice=secret1, bob = secret2,,broken,=x,carol=");
+        assert_eq!(
+            tokens,
+            vec![
+                ("alice".to_string(), "secret1".to_string()),
+                ("bob".to_string(), "secret2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let tokens = hashed("default=abc,ci=xyz");
+
+        assert_eq!(
+            authenticate(&tokens, "Bearer xyz").map(|p| p.name),
+            Some("ci".to_string())
+        );
+        assert_eq!(
+            authenticate(&tokens, "Bearer abc").map(|p| p.name),
+            Some("default".to_string())
+        );
+        assert!(authenticate(&tokens, "Bearer nope").is_none());
+        assert!(authenticate(&tokens, "xyz").is_none());
+    }
+
+    #[test]
+    fn test_caller_name() {
+        let tokens = hashed("ci@web=xyz");
+        assert_eq!(caller_name(&tokens, Some("Bearer xyz")), "ci@web");
+        assert_eq!(caller_name(&tokens, Some("Bearer nope")), "invalid");
+        assert_eq!(caller_name(&tokens, Some("xyz")), "invalid");
+        assert_eq!(caller_name(&tokens, None), "anonymous");
+    }
+
+    #[test]
+    fn test_tenant_scoped_tokens() {
+        let config = Config::from_toml(
+            "version = \"1.0\"\n[[tenants]]\nid = \"web\"\n[[tenants]]\nid = \"data\"",
+        )
+        .unwrap();
+        let tokens = hashed("admin=a1,bob@web=b1");
+
+        let admin = authenticate(&tokens, "Bearer a1").unwrap();
+        assert_eq!(admin.tenant, None);
+        assert!(admin.can_access(&Scope::root(&config)));
+        assert!(admin.can_access(&Scope::tenant(&config, "web").unwrap()));
+
+        let bob = authenticate(&tokens, "Bearer b1").unwrap();
+        assert_eq!(bob.name, "bob");
+        assert_eq!(bob.tenant.as_deref(), Some("web"));
+        assert!(bob.can_access(&Scope::tenant(&config, "web").unwrap()));
+        assert!(!bob.can_access(&Scope::tenant(&config, "data").unwrap()));
+        assert!(!bob.can_access(&Scope::root(&config)));
+    }
+
+    #[test]
+    fn test_rotation() {
+        let configured = hashed("default=old,ci=c1");
+        let mut rotations = Rotations::default();
+        let now = 1_000_000;
+        let until = rotations
+            .rotate(
+                &configured,
+                "default",
+                token_hash("new"),
+                60_000,
+                "admin",
+                now,
+            )
+            .unwrap();
+        assert_eq!(until, now + 60_000);
+
+        // Both tokens work during the grace period, only the new one after it
+        let tokens = rotations.apply(configured.clone(), now);
+        assert!(authenticate(&tokens, "Bearer old").is_some());
+        assert!(authenticate(&tokens, "Bearer new").is_some());
+        assert!(authenticate(&tokens, "Bearer c1").is_some());
+        let tokens = rotations.apply(configured.clone(), until);
+        assert!(authenticate(&tokens, "Bearer old").is_none());
+        assert_eq!(
+            authenticate(&tokens, "Bearer new").map(|p| p.name),
+            Some("default".to_string())
+        );
+
+        // Rotating again replaces the rotated token, without grace
+        rotations
+            .rotate(
+                &configured,
+                "default",
+                token_hash("newer"),
+                0,
+                "admin",
+                until,
+            )
+            .unwrap();
+        let tokens = rotations.apply(configured.clone(), until);
+        assert!(authenticate(&tokens, "Bearer new").is_none());
+        assert!(authenticate(&tokens, "Bearer newer").is_some());
+
+        // Removing the name from the secrets revokes its rotated token
+        let tokens = rotations.apply(hashed("ci=c1"), until);
+        assert!(authenticate(&tokens, "Bearer newer").is_none());
+
+        assert_eq!(
+            rotations.rotate(&configured, "bob", token_hash("x"), 0, "admin", now),
+            Err("Unknown token 'bob'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token().unwrap();
+        assert!(token.starts_with("lkv_"));
+        assert_eq!(token.len(), 4 + 43);
+    }
+}
@@ -0,0 +1,642 @@
+//! Blackout periods with digest-only alerts (`[blackouts]`)
+//!
+//! During a release freeze or a provider's planned maintenance, checks keep
+//! running but alerts of the affected resources are held back and sent as one
+//! digest instead of paging for every transition:
+//!
+//! ```toml
+//! [[blackouts.periods]]
+//! name = "Year-end freeze"
+//! start = "2026-12-20"
+//! end = "2027-01-02T08:00:00+02:00"
+//! matchers = ["group=checkout"]
+//!
+//! [[blackouts.calendars]]
+//! url = "https://status.example-cdn.com/maintenance.ics"
+//! matchers = ["url=~https://cdn\\.example-cdn\\.com/.*"]
+//! ```
+//!
+//! Matchers are the silence matchers (see `silence`); a blackout without
+//! matchers covers every alert. Calendar events (`VEVENT`s with `DTSTART` and
+//! `DTEND`) are fetched at most once per `CALENDAR_TTL_SECS`; recurring events are
+//! not expanded and local times with a `TZID` are read as UTC.
+//!
+//! Held alerts are kept per scope and sent as a single digest notification once
+//! none of their blackouts is in effect any more, or after `BLACKOUT_DIGEST_HOURS`
+//! (default 24) during a long blackout.
+
+use crate::alert::AlertStream;
+use crate::clock;
+use crate::config;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse, WorkerFetcher};
+use crate::notify;
+use crate::silence::Matcher;
+use crate::sri;
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+/// KV key of the held alerts, relative to the scope prefix
+const DIGEST_KEY: &str = "blackouts/digest";
+
+/// KV key prefix of fetched calendars, relative to the default scope's prefix
+const CALENDAR_PREFIX: &str = "blackouts/calendars/";
+
+/// How long a fetched calendar is used before fetching it again
+const CALENDAR_TTL_SECS: u64 = 60 * 60;
+
+const DEFAULT_DIGEST_HOURS: u64 = 24;
+
+/// Held alerts listed in a digest notification
+const MAX_DIGEST_LINES: usize = 20;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Blackout settings of config.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Blackouts {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub periods: Vec<Period>,
+    /// iCal feeds whose events are blackouts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calendars: Vec<CalendarFeed>,
+}
+
+/// A fixed blackout period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Period {
+    pub name: String,
+    /// RFC 3339 date-time or `YYYY-MM-DD` (UTC midnight)
+    pub start: String,
+    /// End, exclusive, in the same formats as `start`
+    pub end: String,
+    /// Alerts the blackout applies to (all if empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matchers: Vec<Matcher>,
+}
+
+/// An iCal feed of blackouts, e.g. a provider's maintenance calendar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalendarFeed {
+    pub url: String,
+    /// Alerts the feed's events apply to (all if empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matchers: Vec<Matcher>,
+}
+
+/// A time range of a calendar event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Window {
+    pub name: String,
+    /// Start and end (milliseconds since the Unix epoch)
+    pub start: u64,
+    pub end: u64,
+}
+
+/// A blackout in effect
+#[derive(Debug, Clone)]
+pub struct Active {
+    pub name: String,
+    matchers: Vec<Matcher>,
+}
+
+impl Active {
+    /// Whether the blackout applies to an alert with these labels
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.matchers.iter().all(|m| m.matches_labels(labels))
+    }
+}
+
+/// Parse `YYYY-MM-DD` into days since the Unix epoch
+fn parse_day(date: &str) -> Option<i64> {
+    let year: i64 = date.get(0..4)?.parse().ok()?;
+    let month: i64 = date.get(5..7)?.parse().ok()?;
+    let day: i64 = date.get(8..10)?.parse().ok()?;
+    let separators = date.len() == 10 && &date[4..5] == "-" && &date[7..8] == "-";
+    (separators && (1..=12).contains(&month) && (1..=31).contains(&day))
+        .then(|| clock::days_from_civil(year, month, day))
+}
+
+/// Parse `HH:MM` or `HH:MM:SS` into seconds after midnight
+fn parse_clock(time: &str) -> Option<i64> {
+    let mut parts = time.split(':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: i64 = match parts.next() {
+        Some(s) => s.get(..2)?.parse().ok()?,
+        None => 0,
+    };
+    (parts.next().is_none() && hours < 24 && minutes < 60 && seconds < 60)
+        .then_some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Parse an RFC 3339 date-time (`Z` or `±HH:MM` offset, UTC without one) or a
+/// `YYYY-MM-DD` date into milliseconds since the Unix epoch
+pub fn parse_datetime(value: &str) -> Option<u64> {
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let mut secs = parse_day(date)? * 86_400;
+    if let Some(time) = time {
+        let (clock_part, offset) = match time.find(['Z', 'z', '+', '-']) {
+            Some(i) => time.split_at(i),
+            None => (time, ""),
+        };
+        secs += parse_clock(clock_part)?;
+        secs -= match offset {
+            "" | "Z" | "z" => 0,
+            _ => {
+                let sign = if offset.starts_with('-') { -1 } else { 1 };
+                sign * parse_clock(&offset[1..]).filter(|s| s % 60 == 0)?
+            }
+        };
+    }
+    u64::try_from(secs).ok().map(|s| s * 1000)
+}
+
+/// Parse an iCal `DATE-TIME` (`20261220T080000Z`) or `DATE` (`20261220`) value
+fn parse_ical_time(value: &str) -> Option<(u64, bool)> {
+    let value = value.trim_end_matches(['Z', 'z']);
+    let date = format!(
+        "{}-{}-{}",
+        value.get(0..4)?,
+        value.get(4..6)?,
+        value.get(6..8)?
+    );
+    match value.get(8..) {
+        Some("") => Some((parse_datetime(&date)?, true)),
+        Some(time)
+            if time.len() == 7
+                && time.starts_with('T')
+                && time[1..].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            let time = format!("{}:{}:{}", &time[1..3], &time[3..5], &time[5..7]);
+            Some((parse_datetime(&format!("{}T{}", date, time))?, false))
+        }
+        _ => None,
+    }
+}
+
+/// Unescape an iCal `TEXT` value
+fn unescape(text: &str) -> String {
+    text.replace("\\n", " ")
+        .replace("\\N", " ")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+/// A `VEVENT` being read
+#[derive(Debug, Default)]
+struct Event {
+    /// Start, and whether it is a date without a time
+    start: Option<(u64, bool)>,
+    end: Option<u64>,
+    summary: String,
+}
+
+impl Event {
+    /// Time range of the event; events without an end last a day if they are
+    /// all-day events and are skipped otherwise
+    fn window(self) -> Option<Window> {
+        let (start, end) = match (self.start, self.end) {
+            (Some((start, _)), Some(end)) if end > start => (start, end),
+            (Some((start, true)), None) => (start, start + DAY_MS),
+            _ => return None,
+        };
+        let name = match self.summary.is_empty() {
+            true => "Scheduled maintenance".to_string(),
+            false => self.summary,
+        };
+        Some(Window { name, start, end })
+    }
+}
+
+/// Events of an iCal feed as time ranges
+pub fn parse_ical(text: &str) -> Vec<Window> {
+    // Long lines are folded by starting the continuation with a space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut windows = Vec::new();
+    let mut event: Option<Event> = None;
+    for line in &lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Property parameters such as `;TZID=...` or `;VALUE=DATE` follow the name
+        let property = name.split(';').next().unwrap_or(name).to_ascii_uppercase();
+        let value = value.trim();
+        match (property.as_str(), &mut event) {
+            ("BEGIN", _) if value == "VEVENT" => event = Some(Event::default()),
+            ("END", Some(current)) if value == "VEVENT" => {
+                windows.extend(std::mem::take(current).window());
+                event = None;
+            }
+            ("DTSTART", Some(current)) => current.start = parse_ical_time(value),
+            ("DTEND", Some(current)) => current.end = parse_ical_time(value).map(|(t, _)| t),
+            ("SUMMARY", Some(current)) => current.summary = unescape(value),
+            _ => {}
+        }
+    }
+    windows
+}
+
+impl Blackouts {
+    /// Check the periods and calendar URLs
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        for period in &self.periods {
+            let parse = |value: &str| {
+                parse_datetime(value).ok_or_else(|| {
+                    format!(
+                        "Invalid time '{}' of blackout '{}', use RFC 3339 or YYYY-MM-DD",
+                        value, period.name
+                    )
+                })
+            };
+            if parse(&period.end)? <= parse(&period.start)? {
+                return Err(format!("Blackout '{}' ends before it starts", period.name));
+            }
+        }
+        for calendar in &self.calendars {
+            match Url::parse(&calendar.url) {
+                Ok(url) if url.scheme() == "https" => {}
+                _ => {
+                    return Err(format!(
+                        "Invalid blackout calendar URL '{}', use https",
+                        calendar.url
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Configured periods in effect at `now`
+    fn active_periods(&self, now: u64) -> impl Iterator<Item = Active> + '_ {
+        self.periods
+            .iter()
+            .filter(move |p| {
+                let start = parse_datetime(&p.start).unwrap_or(u64::MAX);
+                let end = parse_datetime(&p.end).unwrap_or(0);
+                (start..end).contains(&now)
+            })
+            .map(|p| Active {
+                name: p.name.clone(),
+                matchers: p.matchers.clone(),
+            })
+    }
+}
+
+/// Fetch and parse an iCal feed
+async fn fetch_calendar(fetcher: &impl Fetcher, url: &str) -> Result<Vec<Window>> {
+    let mut response = fetcher.fetch(HttpRequest::new(Method::Get, url)).await?;
+    match response.status_code() {
+        200..=299 => Ok(parse_ical(&response.text().await?)),
+        status => Err(Error::RustError(format!(
+            "Calendar returned HTTP {}",
+            status
+        ))),
+    }
+}
+
+/// Events of a calendar feed, from the state store if fetched recently
+async fn calendar_windows(store: Option<&Store>, scope: &Scope<'_>, url: &str) -> Vec<Window> {
+    let hash = &sri::sha256_hex(url.as_bytes())[..16];
+    let key = Scope::root(scope.config()).key(&format!("{}{}", CALENDAR_PREFIX, hash));
+    if let Some(store) = store {
+        if let Ok(Some(windows)) = store.get::<Vec<Window>>(&key).await {
+            return windows;
+        }
+    }
+    match fetch_calendar(&WorkerFetcher, url).await {
+        Ok(windows) => {
+            console_log!("🗓️ Loaded {} events from {}", windows.len(), url);
+            if let Some(store) = store {
+                if let Err(e) = store.put_with_ttl(&key, &windows, CALENDAR_TTL_SECS).await {
+                    console_error!("Failed to cache calendar {}: {}", url, e);
+                }
+            }
+            windows
+        }
+        Err(e) => {
+            console_error!("Failed to load blackout calendar {}: {}", url, e);
+            Vec::new()
+        }
+    }
+}
+
+/// Blackouts in effect at `now`, from the config and its calendars
+pub async fn active(store: Option<&Store>, scope: &Scope<'_>, now: u64) -> Vec<Active> {
+    let blackouts = &scope.config().blackouts;
+    let mut active: Vec<Active> = blackouts.active_periods(now).collect();
+    for calendar in &blackouts.calendars {
+        let windows = calendar_windows(store, scope, &calendar.url).await;
+        active.extend(
+            windows
+                .into_iter()
+                .filter(|w| (w.start..w.end).contains(&now))
+                .map(|w| Active {
+                    name: w.name,
+                    matchers: calendar.matchers.clone(),
+                }),
+        );
+    }
+    active
+}
+
+/// An alert held back during a blackout
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeldAlert {
+    /// Milliseconds since the Unix epoch
+    pub at: u64,
+    pub blackout: String,
+    pub stream: AlertStream,
+    pub url: String,
+    pub detail: String,
+    #[serde(default)]
+    pub resolved: bool,
+}
+
+/// Alerts held back during blackouts, waiting for the digest
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Digest {
+    #[serde(default)]
+    pub alerts: Vec<HeldAlert>,
+}
+
+impl Digest {
+    /// Load the held alerts of a scope (empty if none stored yet or unreadable)
+    pub async fn load(store: &Store, scope: &Scope<'_>) -> Self {
+        store
+            .get(&scope.key(DIGEST_KEY))
+            .await
+            .unwrap_or_else(|e| {
+                console_error!("Failed to load blackout digest: {}", e);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether the digest is due: none of its blackouts is in effect any more, or
+    /// its oldest alert has waited `max_age_ms`
+    fn is_due(&self, active: &[Active], now: u64, max_age_ms: u64) -> bool {
+        let Some(oldest) = self.alerts.first() else {
+            return false;
+        };
+        let ongoing = self
+            .alerts
+            .iter()
+            .any(|held| active.iter().any(|a| a.name == held.blackout));
+        !ongoing || now.saturating_sub(oldest.at) >= max_age_ms
+    }
+
+    /// Text of the digest notification: a count line and the held alerts
+    fn message(&self) -> String {
+        let fired = self.alerts.iter().filter(|a| !a.resolved).count();
+        let mut message = format!(
+            "{} alerts held during blackouts ({} fired, {} resolved)",
+            self.alerts.len(),
+            fired,
+            self.alerts.len() - fired
+        );
+        for held in self.alerts.iter().take(MAX_DIGEST_LINES) {
+            let state = if held.resolved { "resolved" } else { "fired" };
+            message.push_str(&format!(
+                "\n• {} {} {} - {} ({})",
+                held.stream, state, held.url, held.detail, held.blackout
+            ));
+        }
+        if self.alerts.len() > MAX_DIGEST_LINES {
+            message.push_str(&format!(
+                "\n…and {} more",
+                self.alerts.len() - MAX_DIGEST_LINES
+            ));
+        }
+        message
+    }
+
+    /// Names of the blackouts of the held alerts, in order of appearance
+    fn blackout_names(&self) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        for held in &self.alerts {
+            if !names.contains(&held.blackout.as_str()) {
+                names.push(&held.blackout);
+            }
+        }
+        names.join(", ")
+    }
+}
+
+/// Maximum wait of held alerts from `BLACKOUT_DIGEST_HOURS`
+fn digest_max_age_ms(env: &Env) -> u64 {
+    config::setting(env, "BLACKOUT_DIGEST_HOURS")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_DIGEST_HOURS)
+        * 60
+        * 60
+        * 1000
+}
+
+/// Store the alerts held in this run and send the digest if it is due
+///
+/// Without the state store nothing can be kept across runs, so the digest of
+/// the run is sent right away.
+pub async fn flush(
+    env: &Env,
+    store: Option<&Store>,
+    scope: &Scope<'_>,
+    held: Vec<HeldAlert>,
+    active: &[Active],
+    now: u64,
+) {
+    let mut digest = match store {
+        Some(store) => Digest::load(store, scope).await,
+        None => Digest::default(),
+    };
+    let changed = !held.is_empty();
+    digest.alerts.extend(held);
+
+    let due = store.is_none() || digest.is_due(active, now, digest_max_age_ms(env));
+    if due && !digest.alerts.is_empty() {
+        let sent = notify::send_digest_notification(
+            env,
+            scope,
+            &digest.blackout_names(),
+            &digest.message(),
+        )
+        .await;
+        match sent {
+            Ok(()) => digest.alerts.clear(),
+            Err(e) => console_error!("Failed to send blackout digest: {}", e),
+        }
+    } else if !changed {
+        return;
+    }
+
+    if let Some(store) = store {
+        if let Err(e) = store.put(&scope.key(DIGEST_KEY), &digest).await {
+            console_error!("Failed to save blackout digest: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active(name: &str, matchers: &[&str]) -> Active {
+        Active {
+            name: name.to_string(),
+            matchers: matchers.iter().map(|m| m.parse().unwrap()).collect(),
+        }
+    }
+
+    fn held(at: u64, blackout: &str, resolved: bool) -> HeldAlert {
+        HeldAlert {
+            at,
+            blackout: blackout.to_string(),
+            stream: AlertStream::Availability,
+            url: "https://example.com/a.js".to_string(),
+            detail: "HTTP 503".to_string(),
+            resolved,
+        }
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        let now = clock::now_ms();
+        assert_eq!(parse_datetime("2025-11-12T10:00:00Z"), Some(now));
+        assert_eq!(parse_datetime("2025-11-12T12:00:00+02:00"), Some(now));
+        assert_eq!(parse_datetime("2025-11-12T05:00-05:00"), Some(now));
+        assert_eq!(parse_datetime("2025-11-12T10:00:00"), Some(now));
+        assert_eq!(parse_datetime("2025-11-12"), Some(now - 10 * 3_600_000));
+        assert_eq!(parse_datetime("2025-11-12T25:00:00Z"), None);
+        assert_eq!(parse_datetime("12.11.2025"), None);
+        assert_eq!(parse_datetime("2025-13-01"), None);
+    }
+
+    #[test]
+    fn test_parse_ical() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+                   VERSION:2.0\r\n\
+                   BEGIN:VEVENT\r\n\
+                   UID:1@example\r\n\
+                   DTSTART:20251112T080000Z\r\n\
+                   DTEND:20251112T120000Z\r\n\
+                   SUMMARY:CDN maintenance\\, EU\r\n  region\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART;VALUE=DATE:20251224\r\n\
+                   SUMMARY:Holiday freeze\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART;TZID=Europe/Helsinki:20251201T100000\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART:20251201T1€00Z\r\n\
+                   END:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+        let now = clock::now_ms();
+        assert_eq!(
+            parse_ical(ics),
+            vec![
+                Window {
+                    name: "CDN maintenance, EU region".to_string(),
+                    start: now - 2 * 3_600_000,
+                    end: now + 2 * 3_600_000,
+                },
+                Window {
+                    name: "Holiday freeze".to_string(),
+                    start: parse_datetime("2025-12-24").unwrap(),
+                    end: parse_datetime("2025-12-25").unwrap(),
+                },
+            ]
+        );
+        // A non-ASCII time from the remote feed must not panic
+        assert_eq!(parse_ical_time("20251201T1€00Z"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let blackouts = |toml: &str| toml::from_str::<Blackouts>(toml).unwrap().validate();
+        assert!(blackouts(
+            "[[periods]]\nname = \"Freeze\"\nstart = \"2025-12-20\"\nend = \"2026-01-02\"\nmatchers = [\"group=checkout\"]"
+        )
+        .is_ok());
+        assert!(blackouts(
+            "[[periods]]\nname = \"Freeze\"\nstart = \"2026-01-02\"\nend = \"2025-12-20\""
+        )
+        .unwrap_err()
+        .contains("ends before it starts"));
+        assert!(blackouts(
+            "[[periods]]\nname = \"Freeze\"\nstart = \"soon\"\nend = \"2025-12-20\""
+        )
+        .unwrap_err()
+        .contains("Invalid time 'soon'"));
+        assert!(blackouts("[[calendars]]\nurl = \"http://example.com/x.ics\"").is_err());
+        assert!(toml::from_str::<Blackouts>(
+            "[[calendars]]\nurl = \"https://example.com/x.ics\"\nmatchers = [\"url=~(\"]"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_active_periods() {
+        let blackouts: Blackouts = toml::from_str(
+            "[[periods]]\nname = \"Now\"\nstart = \"2025-11-12\"\nend = \"2025-11-13\"\nmatchers = [\"group=checkout\"]\n\
+             [[periods]]\nname = \"Later\"\nstart = \"2025-12-20\"\nend = \"2026-01-02\"",
+        )
+        .unwrap();
+        let active: Vec<Active> = blackouts.active_periods(clock::now_ms()).collect();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].name, "Now");
+
+        let mut labels = BTreeMap::from([("group".to_string(), "checkout".to_string())]);
+        assert!(active[0].matches(&labels));
+        labels.insert("group".to_string(), "marketing".to_string());
+        assert!(!active[0].matches(&labels));
+    }
+
+    #[test]
+    fn test_digest_due() {
+        let hour = 3_600_000;
+        let ongoing = [active("Freeze", &[])];
+        let digest = Digest {
+            alerts: vec![held(0, "Freeze", false), held(hour, "Freeze", true)],
+        };
+        assert!(!digest.is_due(&ongoing, 2 * hour, 24 * hour));
+        assert!(digest.is_due(&ongoing, 24 * hour, 24 * hour));
+        assert!(digest.is_due(&[], 2 * hour, 24 * hour));
+        assert!(!Digest::default().is_due(&[], 2 * hour, 24 * hour));
+
+        assert_eq!(digest.blackout_names(), "Freeze");
+        let message = digest.message();
+        assert!(message.starts_with("2 alerts held during blackouts (1 fired, 1 resolved)"));
+        assert!(message
+            .contains("\n• availability resolved https://example.com/a.js - HTTP 503 (Freeze)"));
+    }
+
+    #[test]
+    fn test_fetch_calendar() {
+        use crate::fetch::mock::{MockFetcher, MockResponse};
+
+        let url = "https://example.com/maintenance.ics";
+        let ics = "BEGIN:VEVENT\nDTSTART:20251112T080000Z\nDTEND:20251112T090000Z\nEND:VEVENT\n";
+        let fetcher = MockFetcher::new().respond(url, MockResponse::new(200, ics));
+        let windows = futures::executor::block_on(fetch_calendar(&fetcher, url)).unwrap();
+        assert_eq!(windows[0].name, "Scheduled maintenance");
+
+        let missing = MockFetcher::new().respond(url, MockResponse::new(404, "Not Found"));
+        assert!(futures::executor::block_on(fetch_calendar(&missing, url)).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Checks of config.toml run by `build.rs`
+//!
+//! config.toml is embedded into the binary (see `config::CONFIG_TOML`), so a typo in
+//! a resource's `url` or `sri` would otherwise only surface at runtime as an invalid
+//! config or an `InvalidSri` check result. Every resource URL is parsed like
+//! `config::normalize_url` does and every SRI string with the worker's own `sri`
+//! module, and a URL pinned to different hashes by different resources (see
+//! `config::sri_cross_use`) is rejected; any problem fails the build with its line
+//! and resource index. One hash shared by several URLs is only a warning, as at
+//! runtime.
+//!
+//! `build.rs` includes this file by path; the library compiles it for its tests
+//! only. The rest of the schema is covered by the config unit tests.
+
+use crate::sri;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use toml::Spanned;
+
+pub const CONFIG_PATH: &str = "config.toml";
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    resources: Vec<ResourceEntry>,
+    #[serde(default)]
+    tenants: Vec<TenantEntry>,
+}
+
+#[derive(Deserialize)]
+struct TenantEntry {
+    id: String,
+    #[serde(default)]
+    resources: Vec<ResourceEntry>,
+}
+
+#[derive(Deserialize)]
+struct ResourceEntry {
+    url: Spanned<String>,
+    #[serde(default)]
+    sri: Option<Spanned<String>>,
+}
+
+/// Problems found in a config.toml, as `file:line: message`
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Problems that fail the build
+    pub errors: Vec<String>,
+    /// Suspicious entries reported as build warnings
+    pub warnings: Vec<String>,
+}
+
+/// A pinned SRI hash with where it is configured
+struct Pin<'a> {
+    sri: &'a str,
+    line: usize,
+    location: String,
+}
+
+/// Line number (from 1) of a byte offset
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+/// Problems of the resources of one scope, as `file:line: message`
+fn check_resources(text: &str, path: &str, resources: &[ResourceEntry]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (index, resource) in resources.iter().enumerate() {
+        let url = resource.url.get_ref();
+        if let Err(e) = url::Url::parse(url.trim()) {
+            errors.push(format!(
+                "{}:{}: {}[{}]: invalid URL '{}': {}",
+                CONFIG_PATH,
+                line_of(text, resource.url.span().start),
+                path,
+                index,
+                url,
+                e
+            ));
+        }
+        let Some(sri) = resource.sri.as_ref().filter(|s| !s.get_ref().is_empty()) else {
+            continue;
+        };
+        if let Err(e) = sri::SriHash::parse(sri.get_ref()) {
+            errors.push(format!(
+                "{}:{}: {}[{}] ({}): invalid SRI '{}': {}",
+                CONFIG_PATH,
+                line_of(text, sri.span().start),
+                path,
+                index,
+                url,
+                sri.get_ref(),
+                e.description()
+            ));
+        }
+    }
+    errors
+}
+
+/// URLs pinned to different hashes (errors) and hashes pinned for different
+/// URLs (warnings) across all scopes
+fn check_cross_use(text: &str, scopes: &[(String, &[ResourceEntry])], report: &mut Report) {
+    let mut pins_by_url: BTreeMap<&str, Vec<Pin>> = BTreeMap::new();
+    let mut urls_by_sri: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (path, resources) in scopes {
+        for (index, resource) in resources.iter().enumerate() {
+            let Some(sri) = resource.sri.as_ref().filter(|s| !s.get_ref().is_empty()) else {
+                continue;
+            };
+            let url = resource.url.get_ref().trim();
+            pins_by_url.entry(url).or_default().push(Pin {
+                sri: sri.get_ref(),
+                line: line_of(text, sri.span().start),
+                location: format!("{}[{}]", path, index),
+            });
+            let urls = urls_by_sri.entry(sri.get_ref()).or_default();
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+
+    for (url, pins) in pins_by_url {
+        let first = &pins[0];
+        for pin in pins.iter().filter(|p| p.sri != first.sri) {
+            report.errors.push(format!(
+                "{}:{}: {} ({}): SRI '{}' conflicts with '{}' pinned by {} at line {}",
+                CONFIG_PATH,
+                pin.line,
+                pin.location,
+                url,
+                pin.sri,
+                first.sri,
+                first.location,
+                first.line
+            ));
+        }
+    }
+    for (sri, urls) in urls_by_sri.into_iter().filter(|(_, urls)| urls.len() > 1) {
+        report.warnings.push(format!(
+            "{}: SRI hash {} is configured for {} different URLs: {}",
+            CONFIG_PATH,
+            sri,
+            urls.len(),
+            urls.join(", ")
+        ));
+    }
+}
+
+/// Every problem of a config.toml
+pub fn check(text: &str) -> Report {
+    let config: ConfigFile = match toml::from_str(text) {
+        Ok(config) => config,
+        Err(e) => {
+            return Report {
+                errors: vec![format!("{}: {}", CONFIG_PATH, e)],
+                ..Default::default()
+            }
+        }
+    };
+    let mut scopes = vec![("resources".to_string(), config.resources.as_slice())];
+    for tenant in &config.tenants {
+        let path = format!("tenants.{}.resources", tenant.id);
+        scopes.push((path, tenant.resources.as_slice()));
+    }
+
+    let mut report = Report::default();
+    for (path, resources) in &scopes {
+        report.errors.extend(check_resources(text, path, resources));
+    }
+    check_cross_use(text, &scopes, &mut report);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> String {
+        let mut text = "version = \"1.0\"\n".to_string();
+        for (url, sri) in entries {
+            text.push_str(&format!(
+                "\n[[resources]]\nurl = \"{}\"\nsri = \"{}\"\n",
+                url, sri
+            ));
+        }
+        text
+    }
+
+    #[test]
+    fn test_check() {
+        let a = sri::sha384(b"a");
+        let report = check(&config(&[
+            ("https://cdn.example.com/a.js", &a),
+            ("https://cdn.example.com/b.js", &sri::sha384(b"b")),
+        ]));
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.warnings.is_empty());
+
+        let report = check(&config(&[
+            ("not a url", &a),
+            ("https://cdn.example.com/b.js", "sha384-typo"),
+        ]));
+        assert_eq!(report.errors.len(), 2, "{:?}", report.errors);
+        assert!(
+            report.errors[0].starts_with("config.toml:4: resources[0]: invalid URL 'not a url'")
+        );
+        assert!(report.errors[1].starts_with(
+            "config.toml:9: resources[1] (https://cdn.example.com/b.js): invalid SRI 'sha384-typo'"
+        ));
+
+        assert_eq!(check("version = ").errors.len(), 1);
+    }
+
+    #[test]
+    fn test_cross_use() {
+        let a = sri::sha384(b"a");
+        let b = sri::sha384(b"b");
+        let mut text = config(&[
+            ("https://cdn.example.com/a.js", &a),
+            ("https://cdn.example.com/copy.js", &a),
+        ]);
+        text.push_str(&format!(
+            "\n[[tenants]]\nid = \"web\"\n\n[[tenants.resources]]\nurl = \"https://cdn.example.com/a.js\"\nsri = \"{}\"\n",
+            b
+        ));
+        let report = check(&text);
+        assert_eq!(
+            report.errors,
+            [format!(
+                "config.toml:16: tenants.web.resources[0] (https://cdn.example.com/a.js): SRI '{}' conflicts with '{}' pinned by resources[0] at line 5",
+                b, a
+            )]
+        );
+        assert_eq!(
+            report.warnings,
+            [format!(
+                "config.toml: SRI hash {} is configured for 2 different URLs: https://cdn.example.com/a.js, https://cdn.example.com/copy.js",
+                a
+            )]
+        );
+    }
+}
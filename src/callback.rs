@@ -0,0 +1,308 @@
+//! Signed acknowledgement callbacks from external alerting systems (`POST /callbacks/ack`)
+//!
+//! Alerts go out to Alertmanager, Opsgenie and similar systems as generic
+//! payloads; when someone acknowledges or closes the alert there, the system (or
+//! a small relay in front of it) calls back so linkkivahti stops reminding about
+//! it too:
+//!
+//! ```json
+//! {"action": "ack", "fingerprint": "3f2a9c0d1e4b5a67", "by": "opsgenie:alice"}
+//! ```
+//!
+//! The alert is named by the `fingerprint` of the generic payload or by its
+//! `url` (the `instance` label), optionally narrowed to one `stream`. `ack` marks
+//! the firing alert as acknowledged, which stops its reminders; `close` ends the
+//! alert and its incident without a recovery, so a resource that keeps failing
+//! fires again on its next check.
+//!
+//! Callbacks are signed like Slack requests: `X-Linkkivahti-Timestamp` carries
+//! the Unix time in seconds and `X-Linkkivahti-Signature` is `v1=` followed by
+//! the hex HMAC-SHA256 of `{timestamp}.{body}` under the
+//! `CALLBACK_SIGNING_SECRET` secret. Requests more than five minutes old are
+//! rejected.
+
+use crate::alert::{self, AlertStream};
+use crate::clock;
+use crate::config::{self, Resource};
+use crate::console::console_log;
+use crate::events::{self, Event, EventKind};
+use crate::notify;
+use crate::sri;
+use crate::store::Store;
+use crate::tenant::Scope;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use worker::*;
+
+/// Largest accepted difference between the callback timestamp and now
+const MAX_CLOCK_SKEW_SECS: u64 = 5 * 60;
+
+/// Name recorded for callbacks without `by`
+const DEFAULT_ACTOR: &str = "external";
+
+/// What the external system did with the alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Ack,
+    Close,
+}
+
+/// Body of `POST /callbacks/ack`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Callback {
+    action: Action,
+    /// Fingerprint of the alert in generic payloads
+    #[serde(default)]
+    fingerprint: Option<String>,
+    /// URL of the alerting resource, the `instance` label of generic payloads
+    #[serde(default)]
+    url: Option<String>,
+    /// Stream of the alert; both streams of the resource by default
+    #[serde(default)]
+    stream: Option<AlertStream>,
+    /// Who acted in the external system, e.g. `opsgenie:alice`
+    #[serde(default)]
+    by: Option<String>,
+}
+
+/// Outcome of a callback for one alert
+#[derive(Debug, Serialize)]
+struct Outcome {
+    url: String,
+    stream: AlertStream,
+    /// Whether the alert was firing; callbacks about other alerts change nothing
+    firing: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CallbackResponse {
+    action: Action,
+    alerts: Vec<Outcome>,
+}
+
+/// Verify a `v1=<hex HMAC-SHA256 of "{timestamp}.{body}">` callback signature
+fn verify_signature(
+    secret: &str,
+    timestamp: &str,
+    body: &str,
+    signature: &str,
+    now_secs: u64,
+) -> bool {
+    let Ok(sent) = timestamp.parse::<u64>() else {
+        return false;
+    };
+    if now_secs.abs_diff(sent) > MAX_CLOCK_SKEW_SECS {
+        return false;
+    }
+    let Some(signature) = signature.strip_prefix("v1=").and_then(sri::decode_hex) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Alerts a callback names: the streams of the scope's resources matching its
+/// fingerprint or URL
+///
+/// `fingerprint` computes the generic payload fingerprint of a stream of an alert key.
+fn matching<'a>(
+    resources: &'a [Resource],
+    callback: &Callback,
+    fingerprint: impl Fn(AlertStream, &str) -> String,
+) -> Vec<(AlertStream, &'a Resource)> {
+    let url = callback
+        .url
+        .as_deref()
+        .map(|url| config::normalize_url(url).unwrap_or_else(|_| url.to_string()));
+    let streams: Vec<AlertStream> = match callback.stream {
+        Some(stream) => vec![stream],
+        None => AlertStream::ALL.to_vec(),
+    };
+    let mut alerts = Vec::new();
+    for resource in resources {
+        for &stream in &streams {
+            let by_url = url.as_deref().is_some_and(|url| url == resource.url);
+            let by_fingerprint = callback
+                .fingerprint
+                .as_deref()
+                .is_some_and(|fp| fp == fingerprint(stream, &resource.alert_key()));
+            if by_url || by_fingerprint {
+                alerts.push((stream, resource));
+            }
+        }
+    }
+    alerts
+}
+
+/// Handle `POST /callbacks/ack`: acknowledge or close alerts of the scope
+pub async fn handle_ack(env: &Env, scope: &Scope<'_>, mut req: Request) -> Result<Response> {
+    let Some(secret) = config::setting(env, "CALLBACK_SIGNING_SECRET") else {
+        return Response::error("Callbacks are not configured", 404);
+    };
+    let timestamp = req.headers().get("X-Linkkivahti-Timestamp")?;
+    let signature = req.headers().get("X-Linkkivahti-Signature")?;
+    let body = req.text().await?;
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        return Response::error("Missing callback signature", 401);
+    };
+    let now = clock::now_ms();
+    if !verify_signature(&secret, &timestamp, &body, &signature, now / 1000) {
+        return Response::error("Invalid callback signature", 401);
+    }
+
+    let callback: Callback = match serde_json::from_str(&body) {
+        Ok(callback) => callback,
+        Err(e) => return Response::error(format!("Invalid callback: {}", e), 400),
+    };
+    if callback.fingerprint.is_none() && callback.url.is_none() {
+        return Response::error("Callback needs a fingerprint or url", 400);
+    }
+    let alerts = matching(scope.resources(), &callback, |stream, subject| {
+        notify::alert_fingerprint(env, stream, subject)
+    });
+    if alerts.is_empty() {
+        return Response::error("No configured resource matches the callback", 404);
+    }
+
+    let store = Store::require(env)?;
+    let by = format!(
+        "callback:{}",
+        callback.by.as_deref().unwrap_or(DEFAULT_ACTOR)
+    );
+    let mut outcomes = Vec::new();
+    let mut log = Vec::new();
+    for (stream, resource) in alerts {
+        let subject = resource.alert_key();
+        let firing = match callback.action {
+            Action::Ack => alert::acknowledge(&store, scope, stream, &subject, &by).await?,
+            Action::Close => alert::close(&store, scope, stream, &subject, now).await?,
+        };
+        if firing {
+            let kind = match callback.action {
+                Action::Ack => EventKind::Acknowledged,
+                Action::Close => EventKind::Closed,
+            };
+            console_log!("{:?} {} alert of {} by {}", kind, stream, resource.url, by);
+            log.push(Event::alert(kind, now, &resource.url, stream).with_detail(by.clone()));
+        }
+        outcomes.push(Outcome {
+            url: resource.url.clone(),
+            stream,
+            firing,
+        });
+    }
+    events::record(env, scope, log).await;
+
+    Response::from_json(&CallbackResponse {
+        action: callback.action,
+        alerts: outcomes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const SECRET: &str = "callback-secret";
+
+    fn sign(timestamp: &str, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(format!("{}.{}", timestamp, body).as_bytes());
+        format!("v1={}", sri::encode_hex(&mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let body = r#"{"action":"ack","url":"https://example.com/a.js"}"#;
+        let signature = sign("1762941600", body);
+        assert!(verify_signature(
+            SECRET,
+            "1762941600",
+            body,
+            &signature,
+            1762941700
+        ));
+        // Tampered body, stale timestamp, other scheme
+        assert!(!verify_signature(
+            SECRET,
+            "1762941600",
+            "{}",
+            &signature,
+            1762941600
+        ));
+        assert!(!verify_signature(
+            SECRET,
+            "1762941600",
+            body,
+            &signature,
+            1762941600 + 301
+        ));
+        assert!(!verify_signature(
+            SECRET,
+            "1762941600",
+            body,
+            &signature.replace("v1=", "v0="),
+            1762941600
+        ));
+    }
+
+    #[test]
+    fn test_matching() {
+        let config = Config::from_toml(
+            r#"
+version = "1.0"
+[[resources]]
+url = "https://example.com/a.js"
+sri = "sha384-oqVuAfXRKap7fdgcCY5uykM6+R9GqQ8K/uxy9rx7HNQlGYl1kPzQho1wx4JwY8wC"
+
+[[resources]]
+url = "https://example.com/b.js"
+expected_response_contains = "export"
+"#,
+        )
+        .unwrap();
+        let fingerprint = |stream: AlertStream, subject: &str| format!("{}:{}", stream, subject);
+        let parse = |body: &str| serde_json::from_str::<Callback>(body).unwrap();
+        let names = |alerts: Vec<(AlertStream, &Resource)>| -> Vec<String> {
+            alerts
+                .iter()
+                .map(|(stream, r)| format!("{} {}", stream, r.url))
+                .collect()
+        };
+
+        let callback = parse(r#"{"action":"ack","url":"https://EXAMPLE.com/a.js"}"#);
+        assert_eq!(
+            names(matching(&config.resources, &callback, fingerprint)),
+            [
+                "availability https://example.com/a.js",
+                "integrity https://example.com/a.js"
+            ]
+        );
+
+        let callback = parse(
+            r#"{"action":"close","fingerprint":"integrity:https://example.com/a.js","by":"ops"}"#,
+        );
+        assert_eq!(
+            names(matching(&config.resources, &callback, fingerprint)),
+            ["integrity https://example.com/a.js"]
+        );
+
+        let callback =
+            parse(r#"{"action":"ack","url":"https://example.com/b.js","stream":"availability"}"#);
+        assert_eq!(
+            names(matching(&config.resources, &callback, fingerprint)),
+            ["availability https://example.com/b.js"]
+        );
+
+        let callback = parse(r#"{"action":"ack","url":"https://example.com/c.js"}"#);
+        assert!(matching(&config.resources, &callback, fingerprint).is_empty());
+
+        assert!(serde_json::from_str::<Callback>(r#"{"action":"snooze"}"#).is_err());
+    }
+}
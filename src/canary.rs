@@ -0,0 +1,282 @@
+//! Self-check of the notification path
+//!
+//! A webhook that was revoked, archived or mistyped fails silently until the next
+//! real alert. With `WEBHOOK_CANARY` set, scheduled runs periodically verify every
+//! webhook destination of each scope:
+//! - `preflight`: a silent request the service rejects without posting anything
+//!   (`GET` for Discord, an empty JSON `POST` for Slack and Zulip, `HEAD` otherwise),
+//!   every `WEBHOOK_CANARY_INTERVAL_HOURS` (default 24)
+//! - `notify`: a visible canary notification to the scope's webhook (default weekly)
+//!
+//! Outcomes are stored per scope under the `canary` KV key. A broken channel is
+//! logged as an error on every check and reported as `notifications` by the status
+//! endpoint, since it cannot be alerted through itself.
+
+use crate::clock;
+use crate::config;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Destination, Fetcher, HttpRequest, HttpResponse};
+use crate::notify::{self, WebhookService};
+use crate::store::Store;
+use crate::sync;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// KV key of the channel health, relative to the scope prefix
+const STATE_KEY: &str = "canary";
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+
+/// How the notification channel is verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanaryMode {
+    Off,
+    Preflight,
+    Notify,
+}
+
+impl CanaryMode {
+    /// Default time between checks
+    fn default_interval_hours(&self) -> u64 {
+        match self {
+            Self::Off | Self::Preflight => 24,
+            Self::Notify => 7 * 24,
+        }
+    }
+}
+
+impl std::str::FromStr for CanaryMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "preflight" => Ok(Self::Preflight),
+            "notify" => Ok(Self::Notify),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Outcome of the latest channel check of a scope
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelHealth {
+    /// When the channel was checked (milliseconds since the Unix epoch)
+    pub checked: u64,
+    pub ok: bool,
+    /// Why the channel is considered broken
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Configured mode from `WEBHOOK_CANARY`, off if unset
+fn mode(env: &Env) -> CanaryMode {
+    match config::setting(env, "WEBHOOK_CANARY") {
+        None => CanaryMode::Off,
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            console_error!("Unknown WEBHOOK_CANARY value '{}', canary disabled", value);
+            CanaryMode::Off
+        }),
+    }
+}
+
+/// Time between checks from `WEBHOOK_CANARY_INTERVAL_HOURS` (at least one hour)
+fn interval_ms(env: &Env, mode: CanaryMode) -> u64 {
+    let hours = config::setting(env, "WEBHOOK_CANARY_INTERVAL_HOURS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or_else(|| mode.default_interval_hours());
+    hours.max(1) * HOUR_MS
+}
+
+/// Whether a check is due, given the previous outcome
+fn due(previous: Option<&ChannelHealth>, now: u64, interval_ms: u64) -> bool {
+    previous.is_none_or(|health| now.saturating_sub(health.checked) >= interval_ms)
+}
+
+/// Silent request that a live webhook of the service rejects or ignores
+fn preflight_request(url: &str, service: WebhookService) -> HttpRequest {
+    match service {
+        WebhookService::Discord => HttpRequest::new(Method::Get, url),
+        WebhookService::Slack | WebhookService::Zulip => HttpRequest::post_json(url, "{}"),
+        WebhookService::Generic => HttpRequest::new(Method::Head, url),
+    }
+}
+
+/// Whether a preflight response shows the webhook still accepts requests
+///
+/// Slack and Zulip answer an empty payload with 400 while the webhook exists;
+/// generic receivers may refuse `HEAD`, which still proves the endpoint is there.
+fn accepts(service: WebhookService, status: u16) -> bool {
+    match service {
+        WebhookService::Discord => (200..300).contains(&status),
+        WebhookService::Slack | WebhookService::Zulip => {
+            (200..300).contains(&status) || status == 400
+        }
+        WebhookService::Generic => status < 500 && !matches!(status, 401 | 403 | 404 | 410),
+    }
+}
+
+/// Preflight a webhook, describing why it is broken on failure
+async fn preflight(
+    fetcher: &impl Fetcher,
+    url: &str,
+    service: WebhookService,
+) -> std::result::Result<(), String> {
+    let response = fetcher
+        .fetch(preflight_request(url, service))
+        .await
+        .map_err(|e| format!("{} webhook unreachable: {}", service, e))?;
+    match response.status_code() {
+        status if accepts(service, status) => Ok(()),
+        status => Err(format!("{} webhook returned HTTP {}", service, status)),
+    }
+}
+
+/// Verify the notification channel of a scope
+async fn check_channel(env: &Env, scope: &Scope<'_>, mode: CanaryMode) -> ChannelHealth {
+    let mut error = None;
+    match mode {
+        CanaryMode::Off => {}
+        CanaryMode::Preflight => {
+            for target in notify::destinations(env, scope) {
+                let checked = match Destination::resolve(env, &target.url) {
+                    Ok((fetcher, url)) => preflight(&fetcher, &url, target.service).await,
+                    Err(e) => Err(e.to_string()),
+                };
+                if let Err(e) = checked {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+        CanaryMode::Notify => {
+            if let Err(e) = notify::send_canary_notification(env, scope).await {
+                error = Some(format!("Canary notification failed: {}", e));
+            }
+        }
+    }
+    ChannelHealth {
+        checked: clock::now_ms(),
+        ok: error.is_none(),
+        error,
+    }
+}
+
+/// Latest channel health of a scope, if the canary has run
+pub async fn load(env: &Env, scope: &Scope<'_>) -> Option<ChannelHealth> {
+    let store = Store::from_env(env)?;
+    store.get(&scope.key(STATE_KEY)).await.unwrap_or_else(|e| {
+        console_error!("Failed to load canary state: {}", e);
+        None
+    })
+}
+
+/// Check the notification channels of all scopes that are due
+///
+/// Needs the state store to pace the checks; without it the canary is skipped.
+pub async fn run_scheduled(env: &Env) {
+    let mode = mode(env);
+    if mode == CanaryMode::Off {
+        return;
+    }
+    let Some(store) = Store::from_env(env) else {
+        console_error!("WEBHOOK_CANARY needs the state store, skipping");
+        return;
+    };
+
+    let config = sync::active_config(env).await;
+    let interval = interval_ms(env, mode);
+    for scope in Scope::all(&config) {
+        let key = scope.key(STATE_KEY);
+        let previous: Option<ChannelHealth> = store.get(&key).await.unwrap_or(None);
+        if !due(previous.as_ref(), clock::now_ms(), interval) {
+            continue;
+        }
+
+        let health = check_channel(env, &scope, mode).await;
+        match &health.error {
+            None => console_log!("Notification channel ok ({})", scope.label()),
+            Some(e) => console_error!("🚨 Notification channel broken ({}): {}", scope.label(), e),
+        }
+        if let Err(e) = store.put(&key, &health).await {
+            console_error!("Failed to save canary state: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_mode_from_str() {
+        assert_eq!("Preflight".parse(), Ok(CanaryMode::Preflight));
+        assert_eq!("notify".parse(), Ok(CanaryMode::Notify));
+        assert_eq!("off".parse(), Ok(CanaryMode::Off));
+        assert!("weekly".parse::<CanaryMode>().is_err());
+    }
+
+    #[test]
+    fn test_due() {
+        let health = ChannelHealth {
+            checked: 1_000,
+            ok: true,
+            error: None,
+        };
+        assert!(due(None, 0, HOUR_MS));
+        assert!(!due(Some(&health), 1_000 + HOUR_MS - 1, HOUR_MS));
+        assert!(due(Some(&health), 1_000 + HOUR_MS, HOUR_MS));
+    }
+
+    #[test]
+    fn test_preflight() {
+        let discord = "https://discord.com/api/webhooks/1/a";
+        let slack = "https://hooks.slack.com/services/T/B/x";
+        let generic = "https://alerts.example.com/hook";
+        let fetcher = MockFetcher::new()
+            .respond(discord, MockResponse::new(404, "Unknown Webhook"))
+            .respond(slack, MockResponse::new(400, "no_text"))
+            .respond(generic, MockResponse::new(405, ""));
+
+        assert_eq!(
+            block_on(preflight(&fetcher, discord, WebhookService::Discord)),
+            Err("Discord webhook returned HTTP 404".to_string())
+        );
+        assert_eq!(
+            block_on(preflight(&fetcher, slack, WebhookService::Slack)),
+            Ok(())
+        );
+        assert_eq!(
+            block_on(preflight(&fetcher, generic, WebhookService::Generic)),
+            Ok(())
+        );
+        let unreachable = block_on(preflight(
+            &fetcher,
+            "https://gone.example.com/",
+            WebhookService::Generic,
+        ));
+        assert!(unreachable
+            .unwrap_err()
+            .starts_with("Generic webhook unreachable"));
+
+        let methods: Vec<Method> = fetcher.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(
+            methods,
+            [Method::Get, Method::Post, Method::Head, Method::Head]
+        );
+        assert_eq!(fetcher.requests()[1].body.as_deref(), Some("{}"));
+    }
+
+    #[test]
+    fn test_accepts() {
+        assert!(accepts(WebhookService::Zulip, 400));
+        assert!(!accepts(WebhookService::Slack, 403));
+        assert!(!accepts(WebhookService::Slack, 410));
+        assert!(!accepts(WebhookService::Discord, 401));
+        assert!(!accepts(WebhookService::Generic, 502));
+        assert!(accepts(WebhookService::Generic, 200));
+    }
+}
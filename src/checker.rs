@@ -1,7 +1,17 @@
 //! Link availability and SRI verification module
 
-use crate::sri::SriHash;
+use crate::clock;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::encoding;
+use crate::feed;
+use crate::fetch::{self, Fetcher, HttpRequest, HttpResponse};
+use crate::magic;
+use crate::oob::Verification;
+use crate::sri::{SriCache, SriHash};
+use crate::template;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use worker::*;
 
 /// Typed error for check failures
@@ -11,10 +21,34 @@ pub enum CheckError {
     InvalidSri,
     /// Network request failed
     FetchFailed,
+    /// No response arrived within the resource's `timeout_ms` (or `CHECK_TIMEOUT_MS`)
+    Timeout,
     /// HTTP error response, with code
     HttpError(u16),
     /// Failed to read response body
     BodyReadFailed,
+    /// Response body does not contain the expected text
+    UnexpectedResponse,
+    /// A crawled page links to unavailable URLs, with their number
+    BrokenLinks(usize),
+    /// A published key expires soon, with the days left (negative once expired)
+    KeyExpiring(i64),
+    /// Content does not match its SRI hash; only used for the broken links of a
+    /// result, as the result's own mismatch is `sri_valid = false`
+    SriMismatch,
+    /// JSON response does not match the resource's `json_schema`; the violated
+    /// constraint is in the result's `message`
+    SchemaViolation,
+    /// The newest entry of a feed is too old, with its age in hours
+    FeedStale(u64),
+    /// The body does not start with the resource's `expected_magic`; what was
+    /// served is in the result's `message`
+    WrongFileType,
+    /// The body does not match its `Content-Encoding` (see `encoding`); the
+    /// reason is in the result's `message`
+    EncodingMismatch,
+    /// The content is older than the resource's `max_age`, with its age in hours
+    Stale(u64),
 }
 
 impl CheckError {
@@ -24,8 +58,66 @@ impl CheckError {
         match self {
             Self::InvalidSri => "Invalid SRI format".to_string(),
             Self::FetchFailed => "Fetch failed".to_string(),
+            Self::Timeout => "Request timed out".to_string(),
             Self::HttpError(code) => format!("HTTP error: {}", code),
             Self::BodyReadFailed => "Failed to read response body".to_string(),
+            Self::UnexpectedResponse => "Expected text not found in response".to_string(),
+            Self::BrokenLinks(1) => "1 broken link".to_string(),
+            Self::BrokenLinks(count) => format!("{} broken links", count),
+            Self::KeyExpiring(0) => "Key expires today".to_string(),
+            Self::KeyExpiring(1) => "Key expires in 1 day".to_string(),
+            Self::KeyExpiring(-1) => "Key expired 1 day ago".to_string(),
+            Self::KeyExpiring(days) if *days < 0 => format!("Key expired {} days ago", -days),
+            Self::KeyExpiring(days) => format!("Key expires in {} days", days),
+            Self::SriMismatch => "SRI mismatch".to_string(),
+            Self::SchemaViolation => "Schema violation".to_string(),
+            Self::FeedStale(hours) if *hours < 48 => {
+                format!("Newest feed entry is {} hours old", hours)
+            }
+            Self::FeedStale(hours) => format!("Newest feed entry is {} days old", hours / 24),
+            Self::WrongFileType => "Wrong file type".to_string(),
+            Self::EncodingMismatch => "Content-Encoding mismatch".to_string(),
+            Self::Stale(hours) if *hours < 48 => format!("Last modified {} hours ago", hours),
+            Self::Stale(hours) => format!("Last modified {} days ago", hours / 24),
+        }
+    }
+
+    /// Error of a request that got no response
+    pub fn from_fetch(error: &Error) -> Self {
+        if fetch::is_timeout(error) {
+            Self::Timeout
+        } else {
+            Self::FetchFailed
+        }
+    }
+
+    /// Error of a response whose body could not be read
+    pub fn from_body(error: &Error) -> Self {
+        if fetch::is_timeout(error) {
+            Self::Timeout
+        } else {
+            Self::BodyReadFailed
+        }
+    }
+
+    /// Get a short machine-readable error type, e.g. `http_503`
+    pub fn code(&self) -> String {
+        match self {
+            Self::InvalidSri => "invalid_sri".to_string(),
+            Self::FetchFailed => "fetch_failed".to_string(),
+            Self::Timeout => "timeout".to_string(),
+            Self::HttpError(code) => format!("http_{}", code),
+            Self::BodyReadFailed => "body_read_failed".to_string(),
+            Self::UnexpectedResponse => "unexpected_response".to_string(),
+            Self::BrokenLinks(_) => "broken_links".to_string(),
+            Self::KeyExpiring(days) if *days < 0 => "key_expired".to_string(),
+            Self::KeyExpiring(_) => "key_expiring".to_string(),
+            Self::SriMismatch => "sri_mismatch".to_string(),
+            Self::SchemaViolation => "schema_violation".to_string(),
+            Self::FeedStale(_) => "feed_stale".to_string(),
+            Self::WrongFileType => "wrong_file_type".to_string(),
+            Self::EncodingMismatch => "encoding_mismatch".to_string(),
+            Self::Stale(_) => "stale".to_string(),
         }
     }
 }
@@ -35,6 +127,20 @@ impl CheckError {
 pub enum CheckResultKind {
     Real,
     Test,
+    /// Informational event about a resource (e.g. a pending SRI change)
+    Notice,
+}
+
+/// Response headers recorded with each check, to compare the last good and the
+/// first bad response
+pub const SNAPSHOT_HEADERS: &[&str] = &["etag", "last-modified", "content-length", "server", "via"];
+
+/// Values of the `SNAPSHOT_HEADERS` present in a response
+pub fn header_snapshot(response: &impl HttpResponse) -> BTreeMap<String, String> {
+    SNAPSHOT_HEADERS
+        .iter()
+        .filter_map(|&name| Some((name.to_string(), response.header(name)?)))
+        .collect()
 }
 
 /// Result of a link check operation
@@ -46,32 +152,97 @@ pub struct CheckResult {
     pub error: Option<CheckError>,
     pub sri_valid: Option<bool>,
     pub kind: CheckResultKind,
+    /// Free-form message for `Notice` results, or details of a failure (e.g. the
+    /// violated schema constraint)
+    pub message: Option<String>,
+    /// Time from sending the request until the response was processed
+    pub latency_ms: Option<u64>,
+    /// Response body, kept when it was verified against an SRI hash
+    pub artifact: Option<Artifact>,
+    /// Unavailable links found on a crawled page
+    pub broken_links: Vec<BrokenLink>,
+    /// Results of the same path on the resource's alternate origins
+    pub origins: Vec<OriginCheck>,
+    /// Re-fetch of a mismatching resource through its secondary path (see `oob`)
+    pub out_of_band: Option<Verification>,
+    /// Selected response headers (see `SNAPSHOT_HEADERS`), kept in the history
+    pub headers: BTreeMap<String, String>,
+    /// Text of the response body of a resource with `json_schema`, or of a watched
+    /// resource without an SRI hash (see `schema` and `watch`)
+    pub body: Option<String>,
+    /// Tags of the checked resource, shown in its alerts
+    pub tags: Vec<String>,
+}
+
+/// A link of a crawled page that failed its check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenLink {
+    pub url: String,
+    pub error: CheckError,
+}
+
+/// Result of a resource's path fetched from one of its alternate origins
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginCheck {
+    pub url: String,
+    /// Whether the origin copy passed the resource's checks
+    pub ok: bool,
+    /// Description of the origin's result, e.g. `OK (HTTP 200)`
+    pub detail: String,
+}
+
+/// Response body of a resource that was verified against an SRI hash
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub content: Vec<u8>,
+    /// `Content-Type` of the response
+    pub content_type: Option<String>,
+    /// SRI hash the content was verified against
+    pub expected_sri: String,
 }
 
 impl CheckResult {
     /// Create a successful check result
     #[inline]
-    pub fn success(url: &'static str, status_code: u16, sri_valid: bool) -> Self {
+    pub fn success(url: impl Into<Cow<'static, str>>, status_code: u16, sri_valid: bool) -> Self {
         Self {
-            url: Cow::Borrowed(url),
+            url: url.into(),
             success: true,
             status_code: Some(status_code),
             error: None,
             sri_valid: Some(sri_valid),
             kind: CheckResultKind::Real,
+            message: None,
+            latency_ms: None,
+            artifact: None,
+            broken_links: Vec::new(),
+            origins: Vec::new(),
+            out_of_band: None,
+            headers: BTreeMap::new(),
+            body: None,
+            tags: Vec::new(),
         }
     }
 
     /// Create a failed check result
     #[inline]
-    pub fn failure(url: &'static str, error: CheckError) -> Self {
+    pub fn failure(url: impl Into<Cow<'static, str>>, error: CheckError) -> Self {
         Self {
-            url: Cow::Borrowed(url),
+            url: url.into(),
             success: false,
             status_code: None,
             error: Some(error),
             sri_valid: None,
             kind: CheckResultKind::Real,
+            message: None,
+            latency_ms: None,
+            artifact: None,
+            broken_links: Vec::new(),
+            origins: Vec::new(),
+            out_of_band: None,
+            headers: BTreeMap::new(),
+            body: None,
+            tags: Vec::new(),
         }
     }
 
@@ -85,13 +256,50 @@ impl CheckResult {
             error: None,
             sri_valid: None,
             kind: CheckResultKind::Test,
+            message: None,
+            latency_ms: None,
+            artifact: None,
+            broken_links: Vec::new(),
+            origins: Vec::new(),
+            out_of_band: None,
+            headers: BTreeMap::new(),
+            body: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Create an informational notice about a resource
+    #[inline]
+    pub fn notice(url: impl Into<Cow<'static, str>>, message: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            success: true,
+            status_code: None,
+            error: None,
+            sri_valid: None,
+            kind: CheckResultKind::Notice,
+            message: Some(message.into()),
+            latency_ms: None,
+            artifact: None,
+            broken_links: Vec::new(),
+            origins: Vec::new(),
+            out_of_band: None,
+            headers: BTreeMap::new(),
+            body: None,
+            tags: Vec::new(),
         }
     }
 
+    /// Check if this result is informational (test or notice) rather than a real check
+    #[inline]
+    pub fn is_informational(&self) -> bool {
+        self.kind != CheckResultKind::Real
+    }
+
     /// Check if this result indicates a problem (failure or SRI mismatch)
     #[inline]
     pub fn has_problem(&self) -> bool {
-        if self.kind == CheckResultKind::Test {
+        if self.is_informational() {
             return false;
         }
         !self.success || self.sri_valid == Some(false)
@@ -99,20 +307,28 @@ impl CheckResult {
 
     /// Get a human-readable description of the result
     pub fn description(&self) -> String {
-        if self.kind == CheckResultKind::Test {
-            return "TEST notification".to_string();
+        match self.kind {
+            CheckResultKind::Test => return "TEST notification".to_string(),
+            CheckResultKind::Notice => return self.message.clone().unwrap_or_default(),
+            CheckResultKind::Real => {}
         }
 
         if !self.success {
-            if let Some(error) = &self.error {
+            if let Some(message) = &self.message {
+                format!("Failed: {}", message)
+            } else if let Some(error) = &self.error {
                 format!("Failed: {}", error.description())
             } else {
                 "Failed: Unknown error".to_string()
             }
         } else if self.sri_valid == Some(false) {
-            match self.status_code {
+            let mismatch = match self.status_code {
                 Some(code) => format!("SRI mismatch (HTTP {})", code),
                 None => "SRI mismatch".to_string(),
+            };
+            match &self.message {
+                Some(message) => format!("{}: {}", mismatch, message),
+                None => mismatch,
             }
         } else {
             match self.status_code {
@@ -126,72 +342,247 @@ impl CheckResult {
 /// Check a single resource: verify it's accessible and SRI hash matches
 ///
 /// This performs:
-/// 1. HTTP GET request to fetch the resource content
-/// 2. SRI hash verification against expected hash
+/// 1. HTTP request (GET by default, or the configured probe method and body)
+/// 2. SRI hash verification against expected hash, if one is configured
+/// 3. Response text verification, if `expected_response_contains` is configured
 ///
 /// # Arguments
-/// * `url` - The URL to check
-/// * `expected_sri` - Expected SRI hash in format "sha384-..."
+/// * `fetcher` - HTTP client, `WorkerFetcher` outside of tests
+/// * `resource` - The resource to check
+/// * `expected_sri` - Expected SRI hash in format "sha384-...", empty to skip verification
+/// * `hashes` - Parsed SRI hashes of the config (see `Config::sri_hashes`)
 ///
 /// # Returns
-/// A `CheckResult` containing the outcome of the check
-pub async fn check_resource(url: &'static str, expected_sri: &str) -> CheckResult {
+/// A `CheckResult` containing the outcome of the check, with its latency if a
+/// response was received
+pub async fn check_resource(
+    fetcher: &impl Fetcher,
+    resource: &Resource,
+    expected_sri: &str,
+    hashes: &SriCache,
+) -> CheckResult {
+    let started = clock::now_ms();
+    let mut result = run_check(fetcher, resource, expected_sri, hashes).await;
+    if !matches!(
+        result.error,
+        Some(CheckError::InvalidSri | CheckError::FetchFailed | CheckError::Timeout)
+    ) {
+        result.latency_ms = Some(clock::now_ms().saturating_sub(started));
+    }
+    result
+}
+
+/// Perform the checks of `check_resource`
+async fn run_check(
+    fetcher: &impl Fetcher,
+    resource: &Resource,
+    expected_sri: &str,
+    hashes: &SriCache,
+) -> CheckResult {
+    let url = resource.url.as_str();
     console_log!("Checking: {}", url);
 
     // Parse expected SRI
-    let sri_hash = match SriHash::parse(expected_sri) {
-        Ok(h) => h,
-        Err(_) => {
-            return CheckResult::failure(url, CheckError::InvalidSri);
+    let sri_hash = if expected_sri.is_empty() {
+        None
+    } else {
+        match hashes.parse(expected_sri) {
+            Ok(h) => Some(h),
+            Err(_) => {
+                return CheckResult::failure(url.to_string(), CheckError::InvalidSri);
+            }
         }
     };
 
     // Fetch the resource
-    let mut response = match fetch_resource(url).await {
+    let mut response = match fetcher.fetch(probe_request(resource)).await {
         Ok(r) => r,
-        Err(_) => {
-            return CheckResult::failure(url, CheckError::FetchFailed);
+        Err(e) => {
+            return CheckResult::failure(url.to_string(), CheckError::from_fetch(&e));
         }
     };
 
+    let headers = header_snapshot(&response);
+    let mut result = verify_response(resource, &mut response, sri_hash, expected_sri).await;
+    result.headers = headers;
+    result
+}
+
+/// Check the status and content of a resource's response
+async fn verify_response(
+    resource: &Resource,
+    response: &mut impl HttpResponse,
+    sri_hash: Option<SriHash>,
+    expected_sri: &str,
+) -> CheckResult {
+    let url = resource.url.as_str();
     let status_code = response.status_code();
 
-    // Check if response is successful (2xx status codes)
-    // Fail fast before reading body
-    if !(200..300).contains(&status_code) {
-        return CheckResult::failure(url, CheckError::HttpError(status_code));
+    // Check the status (2xx, or `expected_status`), failing fast before reading
+    // the body
+    if !resource.accepts_status(status_code) {
+        return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+    }
+
+    // A file that should update regularly can be served fine and still be stale.
+    // The content is verified all the same, so a stale file with a tampered hash
+    // also fails its integrity check.
+    let age_failure = resource.max_age_ms().and_then(|max_age_ms| {
+        let Some(modified) = last_modified(response) else {
+            console_error!("✗ {} - no Last-Modified or Date header", url);
+            return Some((
+                CheckError::UnexpectedResponse,
+                Some("No Last-Modified or Date header".to_string()),
+            ));
+        };
+        let age_ms = clock::now_ms().saturating_sub(modified);
+        (age_ms > max_age_ms).then(|| {
+            let hours = age_ms / 3_600_000;
+            console_error!("✗ {} - stale, last modified {} hours ago", url, hours);
+            (CheckError::Stale(hours), None)
+        })
+    });
+    let aged = |mut result: CheckResult| {
+        if let Some((error, message)) = age_failure.clone() {
+            result.success = false;
+            result.error = Some(error);
+            result.message = message;
+        }
+        result
+    };
+
+    if sri_hash.is_none() && resource.expected_response_contains.is_none() {
+        return aged(CheckResult::success(url.to_string(), status_code, true));
     }
 
     // Get response body
     let content = match response.bytes().await {
         Ok(c) => c,
-        Err(_) => {
-            return CheckResult::failure(url, CheckError::BodyReadFailed);
+        Err(e) => {
+            return CheckResult::failure(url.to_string(), CheckError::from_body(&e));
         }
     };
 
+    // A broken encoding or a different kind of file explains an SRI mismatch
+    // better than the hash
+    let failure = |error: CheckError, reason: String| {
+        console_error!("✗ {} - {}", url, reason);
+        let mut result = CheckResult::failure(url.to_string(), error);
+        result.status_code = Some(status_code);
+        result.message = Some(reason);
+        result
+    };
+    let content_type = response.header("Content-Type");
+    let magic = resource
+        .expected_magic
+        .as_deref()
+        .map(|expected| magic::parse(expected).unwrap_or_default());
+    if let Some(reason) = encoding::mismatch(
+        url,
+        response.header("Content-Encoding").as_deref(),
+        content_type.as_deref(),
+        &content,
+        magic.as_deref(),
+    ) {
+        return failure(CheckError::EncodingMismatch, reason);
+    }
+    if let Some(expected) = &magic {
+        if let Some(reason) = magic::mismatch(expected, &content, content_type.as_deref()) {
+            return failure(CheckError::WrongFileType, reason);
+        }
+    }
+
+    if let Some(expected) = &resource.expected_response_contains {
+        if !String::from_utf8_lossy(&content).contains(expected.as_str()) {
+            console_error!("✗ {} - expected text not found in response", url);
+            return CheckResult::failure(url.to_string(), CheckError::UnexpectedResponse);
+        }
+    }
+
+    let keep_body =
+        resource.json_schema.is_some() || (sri_hash.is_none() && resource.watch_headers);
+    let body = keep_body
+        .then(|| String::from_utf8(content.clone()).ok())
+        .flatten();
+
     // Verify SRI hash
-    let sri_valid = if sri_hash.verify(&content) {
+    let Some(sri_hash) = sri_hash else {
+        let mut result = CheckResult::success(url.to_string(), status_code, true);
+        result.body = body;
+        return aged(result);
+    };
+    let sri_valid = sri_hash.verify(&content);
+    if sri_valid {
         console_log!("✓ {} - SRI valid", url);
-        true
     } else {
         console_error!("✗ {} - SRI MISMATCH", url);
-        false
-    };
+    }
+
+    let mut result = CheckResult::success(url.to_string(), status_code, sri_valid);
+    result.body = body;
+    result.artifact = Some(Artifact {
+        content,
+        content_type: response.header("Content-Type"),
+        expected_sri: expected_sri.to_string(),
+    });
+    aged(result)
+}
 
-    CheckResult::success(url, status_code, sri_valid)
+/// When the content of a response was last modified: its `Last-Modified`, or its
+/// `Date` for servers that do not send one (a cached copy keeps the date it was
+/// generated at)
+///
+/// An origin without `Last-Modified` dates its responses when it sends them, so
+/// behind no cache its `Date` is about now and `max_age` never fails.
+fn last_modified(response: &impl HttpResponse) -> Option<u64> {
+    ["Last-Modified", "Date"]
+        .into_iter()
+        .find_map(|name| feed::parse_rfc822(&response.header(name)?))
 }
 
-/// Fetch a resource from the given URL using HTTP GET
-#[inline]
-async fn fetch_resource(url: &str) -> Result<Response> {
-    let url_parsed = url
-        .parse()
-        .map_err(|e| Error::RustError(format!("Invalid URL: {}", e)))?;
+/// Check that a URL responds with a 2xx status, without reading the body
+///
+/// Used for URLs discovered at runtime (e.g. sitemap pages), which have no
+/// configured hash or expected content.
+pub async fn check_availability(fetcher: &impl Fetcher, url: &str, method: Method) -> CheckResult {
+    let started = clock::now_ms();
+    let response = match fetcher.fetch(HttpRequest::new(method, url)).await {
+        Ok(r) => r,
+        Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_fetch(&e)),
+    };
+    let status_code = response.status_code();
+    let mut result = if (200..300).contains(&status_code) {
+        CheckResult::success(url.to_string(), status_code, true)
+    } else {
+        console_error!("✗ {} - HTTP {}", url, status_code);
+        CheckResult::failure(url.to_string(), CheckError::HttpError(status_code))
+    };
+    result.latency_ms = Some(clock::now_ms().saturating_sub(started));
+    result.headers = header_snapshot(&response);
+    result
+}
 
-    let response = Fetch::Url(url_parsed).send().await?;
+/// Render the request body of a probe from its template
+fn render_body(resource: &Resource, template: &str) -> String {
+    template::render(template, |name| match name {
+        "url" => Some(resource.url.clone()),
+        "name" => Some(resource.name.clone().unwrap_or_default()),
+        "timestamp" => Some(clock::timestamp()),
+        "now_ms" => Some(clock::now_ms().to_string()),
+        _ => None,
+    })
+}
 
-    Ok(response)
+/// Request of a resource using its configured method and body
+fn probe_request(resource: &Resource) -> HttpRequest {
+    let mut request = HttpRequest::new(Method::from(resource.method()), &resource.url);
+    if !resource.follows_redirects() {
+        request = request.without_redirects();
+    }
+    match &resource.body {
+        Some(template) => request.with_json(render_body(resource, template)),
+        None => request,
+    }
 }
 
 #[cfg(test)]
@@ -203,6 +594,301 @@ mod tests {
         assert_eq!(CheckError::InvalidSri.description(), "Invalid SRI format");
         assert_eq!(CheckError::FetchFailed.description(), "Fetch failed");
         assert_eq!(CheckError::HttpError(404).description(), "HTTP error: 404");
+        assert_eq!(
+            CheckError::UnexpectedResponse.description(),
+            "Expected text not found in response"
+        );
+        assert_eq!(CheckError::HttpError(503).code(), "http_503");
+        assert_eq!(CheckError::FetchFailed.code(), "fetch_failed");
+        assert_eq!(CheckError::Timeout.code(), "timeout");
+        assert_eq!(
+            CheckError::Stale(30).description(),
+            "Last modified 30 hours ago"
+        );
+        assert_eq!(CheckError::Stale(30).code(), "stale");
+        assert_eq!(
+            CheckError::from_fetch(&fetch::timeout_error()),
+            CheckError::Timeout
+        );
+    }
+
+    #[test]
+    fn test_render_body() {
+        let resource = Resource {
+            url: "https://rpc.example.com/".to_string(),
+            name: Some("RPC".to_string()),
+            body: Some(String::new()),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_body(
+                &resource,
+                r#"{"jsonrpc": "2.0", "method": "health", "id": {{now_ms}}, "src": "{{ name }}"}"#
+            ),
+            r#"{"jsonrpc": "2.0", "method": "health", "id": 1762941600000, "src": "RPC"}"#
+        );
+    }
+
+    mod fetching {
+        use super::*;
+        use crate::config::StatusPattern;
+        use crate::fetch::mock::{MockFetcher, MockResponse};
+        use crate::fetch::WithTimeout;
+        use crate::sri;
+        use futures::executor::block_on;
+
+        const URL: &str = "https://cdn.example.com/a.js";
+        const CONTENT: &[u8] = b"console.log('a');";
+
+        fn check(fetcher: &MockFetcher, resource: &Resource) -> CheckResult {
+            block_on(check_resource(
+                fetcher,
+                resource,
+                &resource.sri,
+                &SriCache::default(),
+            ))
+        }
+
+        fn resource() -> Resource {
+            Resource {
+                url: URL.to_string(),
+                sri: sri::sha384(CONTENT),
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn test_sri_valid() {
+            let fetcher = MockFetcher::new().respond(
+                URL,
+                MockResponse::new(200, CONTENT).with_header("Content-Type", "text/javascript"),
+            );
+            let result = check(&fetcher, &resource());
+            assert!(result.success);
+            assert_eq!(result.sri_valid, Some(true));
+            assert_eq!(result.status_code, Some(200));
+            assert_eq!(result.latency_ms, Some(0));
+            let artifact = result.artifact.unwrap();
+            assert_eq!(artifact.content, CONTENT);
+            assert_eq!(artifact.content_type.as_deref(), Some("text/javascript"));
+
+            let requests = fetcher.requests();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(requests[0].method, Method::Get);
+            assert_eq!(requests[0].body, None);
+        }
+
+        #[test]
+        fn test_max_age() {
+            let nightly = Resource {
+                url: URL.to_string(),
+                max_age: Some("26h".to_string()),
+                ..Default::default()
+            };
+            let modified = |date: &str| {
+                MockFetcher::new().respond(
+                    URL,
+                    MockResponse::new(200, CONTENT)
+                        .with_header("Last-Modified", date)
+                        .with_header("Date", "Wed, 12 Nov 2025 10:00:00 GMT"),
+                )
+            };
+            let fresh = check(&modified("Wed, 12 Nov 2025 02:00:00 GMT"), &nightly);
+            assert!(fresh.success);
+
+            let stale = check(&modified("Sun, 09 Nov 2025 02:00:00 GMT"), &nightly);
+            assert_eq!(stale.error, Some(CheckError::Stale(80)));
+            assert_eq!(stale.status_code, Some(200));
+            assert_eq!(stale.description(), "Failed: Last modified 3 days ago");
+
+            // Without Last-Modified, the Date of a cached copy tells its age
+            let cached = MockFetcher::new().respond(
+                URL,
+                MockResponse::new(200, CONTENT)
+                    .with_header("Date", "Tue, 11 Nov 2025 06:30:00 GMT"),
+            );
+            assert_eq!(check(&cached, &nightly).error, Some(CheckError::Stale(27)));
+
+            // A stale file with a tampered hash fails its integrity check too
+            let tampered = Resource {
+                sri: sri::sha384(b"console.log('b');"),
+                ..nightly.clone()
+            };
+            let result = check(&modified("Sun, 09 Nov 2025 02:00:00 GMT"), &tampered);
+            assert_eq!(result.error, Some(CheckError::Stale(80)));
+            assert_eq!(result.sri_valid, Some(false));
+
+            let undated = MockFetcher::new().respond(URL, MockResponse::new(200, CONTENT));
+            let result = check(&undated, &nightly);
+            assert_eq!(result.error, Some(CheckError::UnexpectedResponse));
+            assert_eq!(
+                result.message.as_deref(),
+                Some("No Last-Modified or Date header")
+            );
+        }
+
+        #[test]
+        fn test_timeout() {
+            let fetcher = MockFetcher::new()
+                .respond(URL, MockResponse::new(200, CONTENT))
+                .time_out(URL);
+            let timed = WithTimeout::new(&fetcher, Some(1000));
+            let result = block_on(check_resource(
+                &timed,
+                &resource(),
+                &resource().sri,
+                &SriCache::default(),
+            ));
+            assert_eq!(result.error, Some(CheckError::Timeout));
+            assert_eq!(result.latency_ms, None);
+            assert_eq!(result.description(), "Failed: Request timed out");
+
+            // A body trickling in past the timeout times out as well
+            let trickling = MockFetcher::new().respond(
+                URL,
+                MockResponse {
+                    status: 200,
+                    body_timeout: true,
+                    ..Default::default()
+                },
+            );
+            let result = check(&trickling, &resource());
+            assert_eq!(result.error, Some(CheckError::Timeout));
+        }
+
+        #[test]
+        fn test_sri_mismatch() {
+            let fetcher = MockFetcher::new().respond(URL, MockResponse::new(200, "tampered"));
+            let result = check(&fetcher, &resource());
+            assert!(result.success);
+            assert_eq!(result.sri_valid, Some(false));
+            assert!(result.has_problem());
+        }
+
+        #[test]
+        fn test_wrong_file_type() {
+            let resource = Resource {
+                expected_magic: Some("console".to_string()),
+                ..resource()
+            };
+            let fetcher = MockFetcher::new().respond(URL, MockResponse::new(200, CONTENT));
+            assert!(!check(&fetcher, &resource).has_problem());
+
+            let fetcher = MockFetcher::new().respond(
+                URL,
+                MockResponse::new(200, "<html>").with_header("Content-Type", "text/html"),
+            );
+            let result = check(&fetcher, &resource);
+            assert_eq!(result.error, Some(CheckError::WrongFileType));
+            assert_eq!(result.sri_valid, None);
+            assert_eq!(
+                result.description(),
+                "Failed: Wrong file type: expected 'console', got '<html>' (text/html)"
+            );
+        }
+
+        #[test]
+        fn test_header_snapshot() {
+            let fetcher = MockFetcher::new().respond(
+                URL,
+                MockResponse::new(503, "")
+                    .with_header("ETag", "\"v2\"")
+                    .with_header("Via", "1.1 varnish")
+                    .with_header("Set-Cookie", "session=secret"),
+            );
+            let result = check(&fetcher, &resource());
+            assert_eq!(result.error, Some(CheckError::HttpError(503)));
+            assert_eq!(
+                result.headers.into_iter().collect::<Vec<_>>(),
+                vec![
+                    ("etag".to_string(), "\"v2\"".to_string()),
+                    ("via".to_string(), "1.1 varnish".to_string())
+                ]
+            );
+        }
+
+        #[test]
+        fn test_failures() {
+            let fetcher = MockFetcher::new();
+            let result = check(&fetcher, &resource());
+            assert_eq!(result.error, Some(CheckError::FetchFailed));
+            assert_eq!(result.latency_ms, None);
+
+            let fetcher = MockFetcher::new().respond(URL, MockResponse::new(503, ""));
+            let result = check(&fetcher, &resource());
+            assert_eq!(result.error, Some(CheckError::HttpError(503)));
+
+            let fetcher = MockFetcher::new().respond(
+                URL,
+                MockResponse {
+                    status: 200,
+                    body_error: true,
+                    ..Default::default()
+                },
+            );
+            let result = check(&fetcher, &resource());
+            assert_eq!(result.error, Some(CheckError::BodyReadFailed));
+
+            let invalid = Resource {
+                sri: "md5-nope".to_string(),
+                ..resource()
+            };
+            let result = check(&fetcher, &invalid);
+            assert_eq!(result.error, Some(CheckError::InvalidSri));
+        }
+
+        #[test]
+        fn test_probe() {
+            let probe = Resource {
+                url: "https://rpc.example.com/".to_string(),
+                sri: String::new(),
+                method: Some("POST".to_string()),
+                body: Some(r#"{"id": {{now_ms}}}"#.to_string()),
+                expected_response_contains: Some("\"ok\"".to_string()),
+                ..Default::default()
+            };
+            let fetcher = MockFetcher::new()
+                .respond(&probe.url, MockResponse::new(200, r#"{"result": "ok"}"#));
+            let result = check(&fetcher, &probe);
+            assert!(!result.has_problem());
+            assert!(result.artifact.is_none());
+            let request = &fetcher.requests()[0];
+            assert_eq!(request.method, Method::Post);
+            assert_eq!(request.body.as_deref(), Some(r#"{"id": 1762941600000}"#));
+            assert!(request
+                .headers
+                .contains(&("Content-Type".to_string(), "application/json".to_string())));
+
+            let fetcher =
+                MockFetcher::new().respond(&probe.url, MockResponse::new(200, r#"{"error": 1}"#));
+            let result = check(&fetcher, &probe);
+            assert_eq!(result.error, Some(CheckError::UnexpectedResponse));
+        }
+
+        #[test]
+        fn test_expected_status() {
+            let probe = Resource {
+                url: "https://example.com/login".to_string(),
+                expected_status: vec![StatusPattern::Code(204), StatusPattern::Code(302)],
+                ..Default::default()
+            };
+            let fetcher = MockFetcher::new().respond(&probe.url, MockResponse::new(302, ""));
+            let result = check(&fetcher, &probe);
+            assert!(!result.has_problem());
+            assert_eq!(result.status_code, Some(302));
+            // The redirect itself is checked, not its target
+            assert!(!fetcher.requests()[0].follow_redirects);
+
+            // Outside the list, even a 2xx is an HTTP error
+            let fetcher = MockFetcher::new().respond(&probe.url, MockResponse::new(200, ""));
+            let result = check(&fetcher, &probe);
+            assert_eq!(result.error, Some(CheckError::HttpError(200)));
+
+            // Without a 3xx status, redirects are followed as before
+            let fetcher = MockFetcher::new().respond(URL, MockResponse::new(200, CONTENT));
+            check(&fetcher, &resource());
+            assert!(fetcher.requests()[0].follow_redirects);
+        }
     }
 
     #[test]
@@ -218,6 +904,9 @@ mod tests {
 
         let test_result = CheckResult::test("Synthetic notification");
         assert!(!test_result.has_problem());
+
+        let notice = CheckResult::notice("https://example.com", "SRI change requested");
+        assert!(!notice.has_problem());
     }
 
     #[test]
@@ -228,6 +917,13 @@ mod tests {
         let sri_fail = CheckResult::success("https://example.com", 200, false);
         assert_eq!(sri_fail.description(), "SRI mismatch (HTTP 200)");
 
+        let mut chunk_fail = CheckResult::success("https://example.com", 206, false);
+        chunk_fail.message = Some("chunk 2 of 3 (bytes 4-7) does not match the manifest".into());
+        assert_eq!(
+            chunk_fail.description(),
+            "SRI mismatch (HTTP 206): chunk 2 of 3 (bytes 4-7) does not match the manifest"
+        );
+
         let failure = CheckResult::failure("https://example.com", CheckError::FetchFailed);
         assert_eq!(failure.description(), "Failed: Fetch failed");
 
@@ -236,5 +932,8 @@ mod tests {
 
         let test_result = CheckResult::test("Synthetic notification");
         assert_eq!(test_result.description(), "TEST notification");
+
+        let notice = CheckResult::notice("https://example.com", "SRI change requested");
+        assert_eq!(notice.description(), "SRI change requested");
     }
 }
@@ -0,0 +1,415 @@
+//! Large artifacts verified chunk by chunk against a hash manifest
+//!
+//! Artifacts of hundreds of megabytes do not fit in a Worker's memory, so they
+//! cannot be checked against a single SRI hash. A resource with a `chunks` table
+//! is instead verified against a published manifest with one hash per byte range:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://downloads.example.com/app-1.2.0.tar.gz"
+//! chunks = { manifest = "https://downloads.example.com/app-1.2.0.tar.gz.chunks.json" }
+//! ```
+//!
+//! The manifest is JSON with the artifact size, the chunk size and an SRI hash per
+//! chunk, in order:
+//!
+//! ```json
+//! { "size": 52428800, "chunk_size": 10485760, "chunks": ["sha256-...", "..."] }
+//! ```
+//!
+//! Each run fetches the chunks one `Range` request at a time and stops at the
+//! first one that does not match, which fails the integrity stream with the chunk
+//! in the result's message. At most `max_chunks` (default 10) are fetched per run;
+//! longer manifests are covered in consecutive windows, one per hour. A
+//! `manifest_sri` pins the manifest itself, which otherwise is trusted over TLS.
+
+use crate::checker::{CheckError, CheckResult};
+use crate::clock;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use crate::sri::SriHash;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use worker::*;
+
+/// Default number of chunks fetched per run
+const DEFAULT_MAX_CHUNKS: usize = 10;
+
+/// Upper bound of `max_chunks`, leaving room for the other resources of a run
+const MAX_CHUNKS_LIMIT: usize = 100;
+
+/// Largest chunk held in memory, well below the Workers memory limit
+const MAX_CHUNK_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Time each window of a long manifest is checked before moving on
+const WINDOW_MS: u64 = 60 * 60 * 1000;
+
+/// Settings of a chunked manifest probe (`chunks = { ... }` on a resource)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunks {
+    /// URL of the hash manifest
+    pub manifest: String,
+    /// SRI hash the manifest itself must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_sri: Option<String>,
+    /// Chunks fetched per run
+    #[serde(default = "default_max_chunks")]
+    pub max_chunks: usize,
+}
+
+fn default_max_chunks() -> usize {
+    DEFAULT_MAX_CHUNKS
+}
+
+impl Chunks {
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        if !(self.manifest.starts_with("https://") || self.manifest.starts_with("http://")) {
+            return Err(format!(
+                "Invalid chunks.manifest '{}' of {}",
+                self.manifest, url
+            ));
+        }
+        if let Some(sri) = &self.manifest_sri {
+            SriHash::parse(sri).map_err(|e| {
+                format!(
+                    "Invalid chunks.manifest_sri of {}: {}",
+                    url,
+                    e.description()
+                )
+            })?;
+        }
+        if !(1..=MAX_CHUNKS_LIMIT).contains(&self.max_chunks) {
+            return Err(format!(
+                "chunks.max_chunks of {} must be between 1 and {}",
+                url, MAX_CHUNKS_LIMIT
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A published manifest of chunk hashes
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    size: u64,
+    chunk_size: u64,
+    chunks: Vec<String>,
+}
+
+impl Manifest {
+    /// Parse and check a manifest, with the hash of each chunk
+    fn parse(body: &[u8]) -> std::result::Result<(Self, Vec<SriHash>), String> {
+        let manifest: Self = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+        if manifest.chunk_size == 0 || manifest.chunk_size > MAX_CHUNK_SIZE {
+            return Err(format!(
+                "chunk_size must be between 1 and {} bytes",
+                MAX_CHUNK_SIZE
+            ));
+        }
+        let expected = manifest.size.div_ceil(manifest.chunk_size);
+        if manifest.chunks.len() as u64 != expected {
+            return Err(format!(
+                "{} chunk hashes for {} chunks",
+                manifest.chunks.len(),
+                expected
+            ));
+        }
+        let hashes = manifest
+            .chunks
+            .iter()
+            .enumerate()
+            .map(|(index, sri)| {
+                SriHash::parse(sri).map_err(|e| format!("chunk {}: {}", index + 1, e.description()))
+            })
+            .collect::<std::result::Result<_, _>>()?;
+        Ok((manifest, hashes))
+    }
+
+    /// Byte range of a chunk, end exclusive
+    fn range(&self, index: usize) -> Range<u64> {
+        let start = index as u64 * self.chunk_size;
+        start..(start + self.chunk_size).min(self.size)
+    }
+}
+
+/// Chunks checked by the run at `now_ms`: all of them, or the window of the
+/// current hour
+fn window(count: usize, max_chunks: usize, now_ms: u64) -> Range<usize> {
+    if count <= max_chunks {
+        return 0..count;
+    }
+    let windows = count.div_ceil(max_chunks) as u64;
+    let start = (now_ms / WINDOW_MS % windows) as usize * max_chunks;
+    start..(start + max_chunks).min(count)
+}
+
+/// Check an artifact against its chunked hash manifest
+pub async fn check(fetcher: &impl Fetcher, resource: &Resource, chunks: &Chunks) -> CheckResult {
+    let started = clock::now_ms();
+    let mut result = run_check(fetcher, resource, chunks).await;
+    if !matches!(
+        result.error,
+        Some(CheckError::FetchFailed | CheckError::Timeout)
+    ) {
+        result.latency_ms = Some(clock::now_ms().saturating_sub(started));
+    }
+    result
+}
+
+async fn run_check(fetcher: &impl Fetcher, resource: &Resource, chunks: &Chunks) -> CheckResult {
+    let url = resource.url.as_str();
+    console_log!("Checking chunked artifact: {}", url);
+    let unexpected = |reason: String| {
+        console_error!("✗ {} - {}", url, reason);
+        let mut result = CheckResult::failure(url.to_string(), CheckError::UnexpectedResponse);
+        result.message = Some(reason);
+        result
+    };
+
+    let mut response = match fetcher
+        .fetch(HttpRequest::new(Method::Get, chunks.manifest.as_str()))
+        .await
+    {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    if !(200..300).contains(&response.status_code()) {
+        console_error!(
+            "✗ {} - manifest HTTP {}",
+            chunks.manifest,
+            response.status_code()
+        );
+        return CheckResult::failure(
+            url.to_string(),
+            CheckError::HttpError(response.status_code()),
+        );
+    }
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_body(&e)),
+    };
+    if let Some(sri) = &chunks.manifest_sri {
+        if !SriHash::parse(sri).is_ok_and(|hash| hash.verify(&body)) {
+            console_error!("✗ {} - manifest does not match manifest_sri", url);
+            let mut result = CheckResult::success(url.to_string(), response.status_code(), false);
+            result.message = Some("manifest does not match manifest_sri".to_string());
+            return result;
+        }
+    }
+    let (manifest, hashes) = match Manifest::parse(&body) {
+        Ok(parsed) => parsed,
+        Err(e) => return unexpected(format!("Invalid chunk manifest: {}", e)),
+    };
+    drop(body);
+
+    let count = hashes.len();
+    let checked = window(count, chunks.max_chunks, clock::now_ms());
+    let mut status_code = 206;
+    for index in checked.clone() {
+        let range = manifest.range(index);
+        let request = HttpRequest::new(Method::Get, url)
+            .with_header("Range", &format!("bytes={}-{}", range.start, range.end - 1));
+        let mut response = match fetcher.fetch(request).await {
+            Ok(r) => r,
+            Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_fetch(&e)),
+        };
+        status_code = response.status_code();
+        // A full response would not fit in memory
+        if status_code == 200 {
+            return unexpected("Server ignores Range requests".to_string());
+        }
+        if !(200..300).contains(&status_code) {
+            return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+        }
+        let content = match response.bytes().await {
+            Ok(content) => content,
+            Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_body(&e)),
+        };
+        let mismatch = if content.len() as u64 != range.end - range.start {
+            Some(format!(
+                "chunk {} of {} is {} bytes, expected {}",
+                index + 1,
+                count,
+                content.len(),
+                range.end - range.start
+            ))
+        } else if !hashes[index].verify(&content) {
+            Some(format!(
+                "chunk {} of {} (bytes {}-{}) does not match the manifest",
+                index + 1,
+                count,
+                range.start,
+                range.end - 1
+            ))
+        } else {
+            None
+        };
+        if let Some(reason) = mismatch {
+            console_error!("✗ {} - {}", url, reason);
+            let mut result = CheckResult::success(url.to_string(), status_code, false);
+            result.message = Some(reason);
+            return result;
+        }
+    }
+    console_log!(
+        "✓ {} - chunks {}-{} of {} match",
+        url,
+        checked.start + 1,
+        checked.end,
+        count
+    );
+    CheckResult::success(url.to_string(), status_code, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use crate::sri;
+    use futures::executor::block_on;
+
+    const ARTIFACT: &str = "https://downloads.example.com/app.tar.gz";
+    const MANIFEST: &str = "https://downloads.example.com/app.tar.gz.chunks.json";
+
+    /// Artifact of 10 bytes in chunks of 4 with its manifest
+    fn artifact() -> (Vec<u8>, String) {
+        let content = b"0123456789".to_vec();
+        let chunks: Vec<String> = content.chunks(4).map(sri::sha384).collect();
+        let manifest = serde_json::json!({ "size": 10, "chunk_size": 4, "chunks": chunks });
+        (content, manifest.to_string())
+    }
+
+    fn fetcher(content: &[u8], manifest: &str) -> MockFetcher {
+        let mut fetcher = MockFetcher::new().respond(MANIFEST, MockResponse::new(200, manifest));
+        for (start, chunk) in (0..).step_by(4).zip(content.chunks(4)) {
+            let range = format!("bytes={}-{}", start, start + chunk.len() - 1);
+            fetcher = fetcher.respond_range(ARTIFACT, &range, MockResponse::new(206, chunk));
+        }
+        fetcher
+    }
+
+    fn run(fetcher: &MockFetcher, chunks: Chunks) -> CheckResult {
+        let resource = Resource {
+            url: ARTIFACT.to_string(),
+            chunks: Some(chunks.clone()),
+            ..Default::default()
+        };
+        block_on(check(fetcher, &resource, &chunks))
+    }
+
+    fn chunks(max_chunks: usize) -> Chunks {
+        Chunks {
+            manifest: MANIFEST.to_string(),
+            manifest_sri: None,
+            max_chunks,
+        }
+    }
+
+    #[test]
+    fn test_window() {
+        assert_eq!(window(3, 10, 0), 0..3);
+        assert_eq!(window(25, 10, 0), 0..10);
+        assert_eq!(window(25, 10, WINDOW_MS), 10..20);
+        assert_eq!(window(25, 10, 2 * WINDOW_MS), 20..25);
+        assert_eq!(window(25, 10, 3 * WINDOW_MS), 0..10);
+    }
+
+    #[test]
+    fn test_manifest() {
+        let (_, manifest) = artifact();
+        let (manifest, hashes) = Manifest::parse(manifest.as_bytes()).unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(manifest.range(0), 0..4);
+        assert_eq!(manifest.range(2), 8..10);
+
+        assert!(Manifest::parse(br#"{"size":10,"chunk_size":4,"chunks":[]}"#).is_err());
+        assert!(Manifest::parse(br#"{"size":0,"chunk_size":0,"chunks":[]}"#).is_err());
+        assert!(Manifest::parse(br#"{"size":1,"chunk_size":4,"chunks":["md5-x"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let (content, manifest) = artifact();
+        let result = run(&fetcher(&content, &manifest), chunks(10));
+        assert!(result.success);
+        assert_eq!(result.sri_valid, Some(true));
+        assert_eq!(result.status_code, Some(206));
+
+        // The first bad chunk ends the check
+        let mut tampered = content.clone();
+        tampered[5] = b'x';
+        let fetcher = fetcher(&tampered, &manifest);
+        let result = run(&fetcher, chunks(10));
+        assert_eq!(result.sri_valid, Some(false));
+        assert_eq!(
+            result.message.as_deref(),
+            Some("chunk 2 of 3 (bytes 4-7) does not match the manifest")
+        );
+        assert_eq!(fetcher.requests().len(), 3);
+
+        let truncated = fetcher_with(&manifest, "bytes=0-3", MockResponse::new(206, "012"));
+        let result = run(&truncated, chunks(10));
+        assert_eq!(
+            result.message.as_deref(),
+            Some("chunk 1 of 3 is 3 bytes, expected 4")
+        );
+    }
+
+    fn fetcher_with(manifest: &str, range: &str, response: MockResponse) -> MockFetcher {
+        MockFetcher::new()
+            .respond(MANIFEST, MockResponse::new(200, manifest))
+            .respond_range(ARTIFACT, range, response)
+    }
+
+    #[test]
+    fn test_check_failures() {
+        let (_, manifest) = artifact();
+        let ignored = fetcher_with(&manifest, "bytes=0-3", MockResponse::new(200, "0123"));
+        let result = run(&ignored, chunks(10));
+        assert_eq!(result.error, Some(CheckError::UnexpectedResponse));
+        assert_eq!(
+            result.message.as_deref(),
+            Some("Server ignores Range requests")
+        );
+
+        let gone = fetcher_with(&manifest, "bytes=0-3", MockResponse::new(404, ""));
+        assert_eq!(
+            run(&gone, chunks(10)).error,
+            Some(CheckError::HttpError(404))
+        );
+
+        let invalid = fetcher_with("{}", "bytes=0-3", MockResponse::new(206, "0123"));
+        assert_eq!(
+            run(&invalid, chunks(10)).error,
+            Some(CheckError::UnexpectedResponse)
+        );
+
+        // A pinned manifest must match before any chunk is fetched
+        let pinned = Chunks {
+            manifest_sri: Some(sri::sha384(b"another manifest")),
+            ..chunks(10)
+        };
+        let fetcher = fetcher_with(&manifest, "bytes=0-3", MockResponse::new(206, "0123"));
+        let result = run(&fetcher, pinned);
+        assert_eq!(result.sri_valid, Some(false));
+        assert_eq!(fetcher.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(chunks(10).validate(ARTIFACT).is_ok());
+        assert!(chunks(0).validate(ARTIFACT).is_err());
+        assert!(chunks(MAX_CHUNKS_LIMIT + 1).validate(ARTIFACT).is_err());
+        let relative = Chunks {
+            manifest: "app.tar.gz.chunks.json".to_string(),
+            ..chunks(10)
+        };
+        assert!(relative.validate(ARTIFACT).is_err());
+        let pinned = Chunks {
+            manifest_sri: Some("sha384-not base64".to_string()),
+            ..chunks(10)
+        };
+        assert!(pinned.validate(ARTIFACT).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Wall-clock access for the Workers runtime
+//!
+//! `js_sys::Date` is only available inside the Workers runtime, so tests get
+//! fixed values instead. Calendar arithmetic is done here by hand to keep
+//! date/time crates out of the Worker bundle.
+
+#[cfg(not(test))]
+use worker::js_sys;
+
+/// Get current timestamp as ISO string
+#[cfg(not(test))]
+pub fn timestamp() -> String {
+    js_sys::Date::new_0()
+        .to_iso_string()
+        .as_string()
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Mock timestamp for tests
+#[cfg(test)]
+pub fn timestamp() -> String {
+    "2025-11-12T10:00:00Z".to_string()
+}
+
+/// Get current time in milliseconds since the Unix epoch
+#[cfg(not(test))]
+pub fn now_ms() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+/// Mock time for tests (2025-11-12T10:00:00Z)
+#[cfg(test)]
+pub fn now_ms() -> u64 {
+    1_762_941_600_000
+}
+
+/// Days since the Unix epoch of a civil date (Howard Hinnant's algorithm)
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Civil date `(year, month, day)` of a day since the Unix epoch
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Day of the week of a day since the Unix epoch, 0 = Monday .. 6 = Sunday
+pub fn weekday(days: i64) -> usize {
+    // 1970-01-01 was a Thursday
+    (days + 3).rem_euclid(7) as usize
+}
+
+/// Parse a duration like `30m`, `2h` or `1d` (bare numbers are minutes) into minutes
+pub fn parse_duration(duration: &str) -> Option<u64> {
+    let (number, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => duration.split_at(i),
+        None => (duration, "m"),
+    };
+    let factor = match unit {
+        "m" => 1,
+        "h" => 60,
+        "d" => 24 * 60,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(factor)
+        .filter(|&minutes| minutes > 0)
+}
+
+/// RFC 3339 UTC form of milliseconds since the Unix epoch, e.g. `2025-11-12T10:00:00.000Z`
+pub fn format_ms(ms: u64) -> String {
+    let (year, month, day) = civil_from_days((ms / 86_400_000) as i64);
+    let of_day = ms % 86_400_000;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        of_day / 3_600_000,
+        of_day / 60_000 % 60,
+        of_day / 1000 % 60,
+        of_day % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar() {
+        let today = (now_ms() / 86_400_000) as i64;
+        assert_eq!(days_from_civil(2025, 11, 12), today);
+        assert_eq!(civil_from_days(today), (2025, 11, 12));
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+        assert_eq!(weekday(0), 3);
+        assert_eq!(weekday(today), 2); // Wednesday
+        assert_eq!(format_ms(now_ms()), "2025-11-12T10:00:00.000Z");
+        assert_eq!(format_ms(951_868_800_123), "2000-03-01T00:00:00.123Z");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45"), Some(45));
+        assert_eq!(parse_duration("30m"), Some(30));
+        assert_eq!(parse_duration("1d"), Some(1440));
+        assert_eq!(parse_duration("0h"), None);
+        assert_eq!(parse_duration("2w"), None);
+        assert_eq!(parse_duration("h"), None);
+    }
+}
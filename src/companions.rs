@@ -0,0 +1,189 @@
+//! Companion files published next to a resource
+//!
+//! A bundle usually ships with files derived from its URL, such as a source map
+//! or an extracted license comment. They are listed as suffixes, optionally with
+//! an SRI hash of their own:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://cdn.example.com/app.min.js"
+//! sri = "sha384-..."
+//! companions = [".map", { suffix = ".LICENSE.txt", sri = "sha384-..." }]
+//! ```
+//!
+//! Each run fetches `app.min.js.map` and `app.min.js.LICENSE.txt` after the
+//! resource itself. Companions have no alert streams or history of their own: a
+//! broken companion is listed among the broken links of the resource's result,
+//! failing its availability check like a broken link of a crawled page.
+
+use crate::checker::{check_resource, BrokenLink, CheckError, CheckResult};
+use crate::config::Resource;
+use crate::console::console_error;
+use crate::fetch::Fetcher;
+use crate::sri::{SriCache, SriHash};
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// A file derived from a resource's URL (`companions`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Companion {
+    /// Suffix appended to the resource's path, e.g. `.map`; checked for availability
+    Suffix(String),
+    /// Suffix with the SRI hash the companion must match
+    Pinned { suffix: String, sri: String },
+}
+
+impl Companion {
+    pub fn suffix(&self) -> &str {
+        match self {
+            Self::Suffix(suffix) | Self::Pinned { suffix, .. } => suffix,
+        }
+    }
+
+    /// Expected SRI hash, empty if only availability is checked
+    pub fn sri(&self) -> &str {
+        match self {
+            Self::Suffix(_) => "",
+            Self::Pinned { sri, .. } => sri,
+        }
+    }
+
+    /// Check the constraints the TOML schema cannot express
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        let suffix = self.suffix();
+        if suffix.is_empty() || suffix.contains(['/', '?', '#']) {
+            return Err(format!("Invalid companion suffix '{}' of {}", suffix, url));
+        }
+        if !self.sri().is_empty() {
+            SriHash::parse(self.sri()).map_err(|e| {
+                format!(
+                    "Invalid SRI of companion '{}' of {}: {}",
+                    suffix,
+                    url,
+                    e.description()
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// URL of a companion: the suffix appended to the path, keeping the query
+pub fn companion_url(url: &str, suffix: &str) -> Option<String> {
+    let mut url = Url::parse(url).ok()?;
+    let path = format!("{}{}", url.path(), suffix);
+    url.set_path(&path);
+    Some(url.to_string())
+}
+
+/// Check the companions of a resource and add the broken ones to its result
+///
+/// A result that was fine turns into a `BrokenLinks` failure; a result that
+/// already failed keeps its own error.
+pub async fn check(
+    fetcher: &impl Fetcher,
+    resource: &Resource,
+    hashes: &SriCache,
+    result: &mut CheckResult,
+) {
+    for companion in &resource.companions {
+        let Some(url) = companion_url(&resource.url, companion.suffix()) else {
+            continue;
+        };
+        let copy = Resource {
+            url,
+            allow_http: resource.allow_http,
+            ..Default::default()
+        };
+        let checked = check_resource(fetcher, &copy, companion.sri(), hashes).await;
+        if !checked.has_problem() {
+            continue;
+        }
+        let error = checked.error.unwrap_or(CheckError::SriMismatch);
+        console_error!("✗ {} - companion broken: {}", copy.url, error.description());
+        result.broken_links.push(BrokenLink {
+            url: copy.url,
+            error,
+        });
+    }
+    if !result.broken_links.is_empty() && !result.has_problem() {
+        result.success = false;
+        result.error = Some(CheckError::BrokenLinks(result.broken_links.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use crate::sri;
+    use futures::executor::block_on;
+
+    const URL: &str = "https://cdn.example.com/app.min.js?v=2";
+    const MAP: &str = "https://cdn.example.com/app.min.js.map?v=2";
+    const LICENSE: &str = "https://cdn.example.com/app.min.js.LICENSE.txt?v=2";
+
+    fn resource() -> Resource {
+        toml::from_str(&format!(
+            "url = \"{}\"\ncompanions = [\".map\", {{ suffix = \".LICENSE.txt\", sri = \"{}\" }}]",
+            URL,
+            sri::sha384(b"MIT")
+        ))
+        .unwrap()
+    }
+
+    fn run(map: MockResponse, license: MockResponse) -> CheckResult {
+        let fetcher = MockFetcher::new()
+            .respond(MAP, map)
+            .respond(LICENSE, license);
+        let mut result = CheckResult::success(URL, 200, true);
+        block_on(check(
+            &fetcher,
+            &resource(),
+            &SriCache::default(),
+            &mut result,
+        ));
+        result
+    }
+
+    #[test]
+    fn test_companion_config() {
+        let resource = resource();
+        assert_eq!(
+            resource.companions[0],
+            Companion::Suffix(".map".to_string())
+        );
+        assert_eq!(resource.companions[1].suffix(), ".LICENSE.txt");
+        assert_eq!(companion_url(URL, ".map").as_deref(), Some(MAP));
+        assert!(Companion::Suffix("/x".to_string()).validate(URL).is_err());
+        assert!(Companion::Pinned {
+            suffix: ".map".to_string(),
+            sri: "sha384-abc".to_string()
+        }
+        .validate(URL)
+        .is_err());
+    }
+
+    #[test]
+    fn test_broken_companions_fail_the_result() {
+        let result = run(MockResponse::new(200, "{}"), MockResponse::new(200, "MIT"));
+        assert!(!result.has_problem());
+
+        let result = run(MockResponse::new(404, ""), MockResponse::new(200, "GPL"));
+        assert_eq!(result.error, Some(CheckError::BrokenLinks(2)));
+        assert_eq!(
+            result.broken_links,
+            vec![
+                BrokenLink {
+                    url: MAP.to_string(),
+                    error: CheckError::HttpError(404),
+                },
+                BrokenLink {
+                    url: LICENSE.to_string(),
+                    error: CheckError::SriMismatch,
+                },
+            ]
+        );
+    }
+}
@@ -1,49 +1,1932 @@
-//! Configuration module with compile-time TOML parsing
+//! Configuration module
 //!
-//! This module uses static_toml to parse config.toml at compile time,
-//! generating native Rust types with zero runtime overhead.
+//! config.toml is embedded into the binary at compile time and deserialized
+//! once per isolate into typed structures. A config.toml stored in the optional
+//! `LINKKIVAHTI_CONFIG` KV namespace or in the config tables of the D1 database
+//! (see `configdb`) replaces it at runtime (see `runtime`), and when config sync
+//! is enabled, a config fetched from Git takes precedence over all of them (see
+//! `sync`). Optional per-resource settings use serde defaults, so
+//! minimal configs only need `url` and `sri`.
+//!
+//! Optional `[[tenants]]` sections add isolated resource sets (see `tenant`).
+
+use crate::archive::ArchiveConfig;
+use crate::blackout::Blackouts;
+use crate::chunks::Chunks;
+use crate::clock;
+use crate::companions::Companion;
+use crate::configdb;
+use crate::console::{console_error, console_log};
+use crate::crawl::Crawl;
+use crate::escalation::Escalation;
+use crate::feed::Feed;
+use crate::gosum::GoModule;
+use crate::health::HealthConfig;
+use crate::hooks::Hooks;
+use crate::idn;
+use crate::magic;
+use crate::maintenance;
+use crate::package::Package;
+use crate::pgp::PgpKey;
+use crate::registry::RegistryImage;
+use crate::runbook;
+use crate::s3::S3Object;
+use crate::schema::SchemaSource;
+use crate::sitemap::Sitemap;
+use crate::sri::SriCache;
+use crate::sync;
+use crate::template;
+use crate::tenant;
+use crate::usage;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
+use worker::{Env, Url};
+
+/// Raw config.toml contents, embedded at compile time
+pub const CONFIG_TOML: &str = include_str!("../config.toml");
+
+/// Parsed configuration, initialized on first access
+static CONFIG: OnceLock<Arc<Config>> = OnceLock::new();
+
+/// KV namespace binding holding the runtime config, managed outside the worker
+pub const CONFIG_BINDING: &str = "LINKKIVAHTI_CONFIG";
+
+/// Key of the config.toml document in the config namespace
+pub const CONFIG_KEY: &str = "config.toml";
+
+/// Parsed runtime config, cached per isolate together with its source text
+static RUNTIME: Mutex<Option<(String, Arc<Config>)>> = Mutex::new(None);
+
+/// Top-level configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub version: String,
+    #[serde(default)]
+    pub resources: Vec<Resource>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tenants: Vec<Tenant>,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub escalation: Escalation,
+    #[serde(default)]
+    pub blackouts: Blackouts,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub archive: ArchiveConfig,
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Parsed SRI hashes, filled on first use (see `sri_hashes`)
+    #[serde(skip)]
+    sri_hashes: OnceLock<SriCache>,
+}
+
+/// Default latency histogram bucket boundaries in milliseconds
+const DEFAULT_BUCKETS_MS: &[u64] = &[50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Settings of the `/metrics` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Latency histogram bucket boundaries in milliseconds
+    #[serde(default = "default_buckets")]
+    pub buckets_ms: Vec<u64>,
+    /// Bucket boundaries per resource group, overriding `buckets_ms`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, MetricsGroup>,
+}
+
+/// Metrics settings of a resource group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsGroup {
+    pub buckets_ms: Vec<u64>,
+}
+
+fn default_buckets() -> Vec<u64> {
+    DEFAULT_BUCKETS_MS.to_vec()
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            buckets_ms: default_buckets(),
+            groups: BTreeMap::new(),
+        }
+    }
+}
+
+impl MetricsConfig {
+    /// Latency bucket boundaries for a resource
+    pub fn buckets_for(&self, resource: &Resource) -> &[u64] {
+        resource
+            .group
+            .as_ref()
+            .and_then(|g| self.groups.get(g))
+            .map(|g| g.buckets_ms.as_slice())
+            .unwrap_or(&self.buckets_ms)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let groups = self
+            .groups
+            .iter()
+            .map(|(name, g)| (name.as_str(), &g.buckets_ms));
+        for (name, buckets) in std::iter::once(("default", &self.buckets_ms)).chain(groups) {
+            if buckets.is_empty() || buckets.windows(2).any(|w| w[0] >= w[1]) {
+                return Err(format!(
+                    "Metrics buckets of '{}' must be non-empty and strictly increasing",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Subrequests kept free for webhooks, config sync and artifact storage by default
+const DEFAULT_RESERVED_SUBREQUESTS: usize = 10;
+
+/// Batching of checks across cron ticks (`[schedule]`)
+///
+/// Without `max_subrequests`, every run checks all resources.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    /// Subrequest cap per invocation of the Workers plan (50 on free, 1000 on paid)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_subrequests: Option<usize>,
+    /// Subrequests kept free for webhooks, config sync and artifact storage
+    #[serde(default = "default_reserved_subrequests")]
+    pub reserved_subrequests: usize,
+    /// Minutes between cron ticks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_minutes: Option<u64>,
+    /// Every resource is checked at least once within this many minutes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cycle_minutes: Option<u64>,
+    /// Cron trigger that checks resources without their own `cron` (default: every trigger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_cron: Option<String>,
+}
+
+fn default_reserved_subrequests() -> usize {
+    DEFAULT_RESERVED_SUBREQUESTS
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            max_subrequests: None,
+            reserved_subrequests: DEFAULT_RESERVED_SUBREQUESTS,
+            interval_minutes: None,
+            cycle_minutes: None,
+            default_cron: None,
+        }
+    }
+}
+
+impl ScheduleConfig {
+    /// Subrequests available to checks per invocation, or `None` to check everything
+    pub fn check_budget(&self) -> Option<usize> {
+        self.max_subrequests
+            .map(|max| max.saturating_sub(self.reserved_subrequests))
+    }
+
+    /// Whether a run started by the cron trigger `cron` checks a resource
+    pub fn is_due(&self, resource: &Resource, cron: &str) -> bool {
+        match resource.cron.as_deref().or(self.default_cron.as_deref()) {
+            Some(expected) => normalize_cron(expected) == normalize_cron(cron),
+            None => true,
+        }
+    }
+
+    /// Make sure the budget can cover every resource within the cycle
+    ///
+    /// `subrequests` holds the subrequests of each resource's check.
+    fn validate(&self, subrequests: &[usize]) -> Result<(), String> {
+        if let Some(cron) = &self.default_cron {
+            validate_cron(cron)?;
+        }
+        let Some(budget) = self.check_budget() else {
+            return Ok(());
+        };
+        if budget == 0 {
+            return Err("schedule.max_subrequests must exceed reserved_subrequests".to_string());
+        }
+        if let Some(&largest) = subrequests.iter().max().filter(|&&n| n > budget) {
+            return Err(format!(
+                "A resource needs {} subrequests, more than the {} available per run",
+                largest, budget
+            ));
+        }
+        let Some(cycle) = self.cycle_minutes else {
+            return Ok(());
+        };
+        let interval = match self.interval_minutes {
+            Some(interval) if interval > 0 => interval,
+            _ => return Err("schedule.cycle_minutes requires interval_minutes".to_string()),
+        };
+        let ticks = (cycle / interval) as usize;
+        let total: usize = subrequests.iter().sum();
+        if budget * ticks < total {
+            return Err(format!(
+                "Schedule cannot check {} resources every {} minutes: {} ticks of {} checks",
+                subrequests.len(),
+                cycle,
+                ticks,
+                budget
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A tenant with its own resources and notification destination
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tenant {
+    /// Identifier used in endpoint paths (`/t/{id}/...`), state keys and token scopes
+    pub id: String,
+    /// Optional human-readable name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Name of the secret holding the tenant's webhook URL (default `WEBHOOK_URL_<ID>`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_secret: Option<String>,
+    /// Webhook service override for the tenant (like `WEBHOOK_SERVICE`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_service: Option<String>,
+    #[serde(default)]
+    pub resources: Vec<Resource>,
+}
 
-use static_toml::static_toml;
+/// Placeholders available in a resource's request `body`
+pub const BODY_PLACEHOLDERS: &[&str] = &["url", "name", "timestamp", "now_ms"];
 
-// Parse config.toml at compile time and generate types
-static_toml! {
-    /// Compile-time parsed configuration from config.toml
-    #[static_toml(prefer_slices = true)]
-    pub static CONFIG = include_toml!("config.toml");
+/// Prefix of the secrets and variables request `headers` can name; other
+/// settings, such as the access tokens, never leave the worker
+pub const HEADER_SECRET_PREFIX: &str = "HEADER_SECRET_";
+
+/// HTTP methods a resource can be probed with
+const PROBE_METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "PATCH", "OPTIONS"];
+
+/// Upper bound of a resource's `timeout_ms`
+const MAX_TIMEOUT_MS: u64 = 60_000;
+
+/// A monitored resource
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Resource {
+    /// URL to fetch
+    pub url: String,
+    /// Expected SRI hash, e.g. "sha384-..."
+    ///
+    /// May be omitted for probes that set `expected_response_contains` instead.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sri: String,
+    /// Optional human-readable name, used where the URL should not be shown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// HTTP method of the check request (default `GET`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Request body template, e.g. a GraphQL query (see `BODY_PLACEHOLDERS`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Extra headers of the check request, e.g. `{ Accept = "application/json" }`;
+    /// `{{HEADER_SECRET_NAME}}` in a value is replaced by that secret or variable
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Time to wait for a response before the check fails with `Timeout`
+    /// (default: `CHECK_TIMEOUT_MS`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Text the response body must contain for the check to pass
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_response_contains: Option<String>,
+    /// Age after which the content is stale, e.g. `"26h"` or `"7d"`, judged by its
+    /// `Last-Modified` (or `Date`, which is about now for an uncached origin) header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<String>,
+    /// Leading bytes the body must start with, e.g. `'\x7fELF'` (see `magic`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_magic: Option<String>,
+    /// Response statuses that pass the check, e.g. `[200, 204]` or `["2xx", 302]`
+    /// (default: any 2xx); redirects are not followed if a 3xx status is listed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expected_status: Vec<StatusPattern>,
+    /// Resource group, used as a metrics label and to select histogram buckets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Who is pinged about alerts of this resource: a Slack user group or user ID,
+    /// a Discord role ID, an email address or a preformatted `<...>` mention
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Runbook of the resource's alerts: a slug resolved against
+    /// `RUNBOOK_BASE_URL`, or a full link (see `runbook`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runbook: Option<String>,
+    /// Status page of the provider serving the resource (Statuspage API), used to
+    /// annotate its alerts with active upstream incidents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<String>,
+    /// `false` to stop checking the resource without removing it (default `true`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Windows in which the resource is not checked, e.g. `"0 2 * * SUN 4h"` or
+    /// `"2026-11-20T22:00:00Z/2026-11-21T02:00:00Z"` (see `maintenance`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub maintenance: Vec<String>,
+    /// Free-form labels, e.g. `{ team = "web" }`, matched by silences
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Tags naming the team or service of the resource, e.g. `["cdn", "prod"]`,
+    /// shown in its alerts and selecting it in `POST /check?tag=` and `GET /?tag=`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Treat `url` as a sitemap and check the availability of its pages (see `sitemap`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sitemap: Option<Sitemap>,
+    /// Treat `url` as an HTML page and check the links on it (see `crawl`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crawl: Option<Crawl>,
+    /// Alternate origins serving the same path, e.g. the origin server behind a CDN
+    /// (see `origins`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub origins: Vec<String>,
+    /// Who is pinged instead of `owner` when the origins are broken too
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_owner: Option<String>,
+    /// Files derived from `url` by a suffix, e.g. `.map`, checked along with it
+    /// (see `companions`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub companions: Vec<Companion>,
+    /// Send a notice when the `ETag`, `Last-Modified` or `Content-Length` of the
+    /// response changes between runs (see `watch`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub watch_headers: bool,
+    /// Check right after a new version of the worker is deployed and include the
+    /// outcome in the deployment notice (see `deploy`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bootstrap: bool,
+    /// JSON Schema the response must match, inline or the name of one stored in
+    /// KV (see `schema`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<SchemaSource>,
+    /// Another route to the same content, e.g. a mirror or a different gateway,
+    /// re-fetched on SRI mismatches (see `oob`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_via: Option<String>,
+    /// Treat `url` as an object in a private S3-compatible bucket (see `s3`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3: Option<S3Object>,
+    /// Treat `url` as an image manifest pinned to a digest (see `registry`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<RegistryImage>,
+    /// Treat `url` as the index metadata of a pinned package version (see `package`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<Package>,
+    /// Treat `url` as a Go module zip pinned to its `go.sum` hash (see `gosum`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub go: Option<GoModule>,
+    /// Treat `url` as a published OpenPGP key pinned to its fingerprint (see `pgp`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pgp: Option<PgpKey>,
+    /// Treat `url` as an RSS or Atom feed that must keep updating (see `feed`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feed: Option<Feed>,
+    /// Verify `url` range by range against a chunked hash manifest (see `chunks`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<Chunks>,
+    /// Allow fetching this resource (and its origins, sitemap pages or checksum
+    /// database) over plain HTTP, which makes SRI guarantees weaker
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_http: bool,
+    /// What alerts of this resource are deduplicated by (default `url`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup: Option<DedupBy>,
+    /// Cron trigger of wrangler.toml that checks this resource, e.g. `"0 * * * *"`
+    /// (default: `schedule.default_cron`, or every trigger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+}
+
+/// A cron expression with its fields separated by single spaces, for comparing
+/// it with the trigger of a scheduled event
+pub fn normalize_cron(cron: &str) -> String {
+    cron.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-// Re-export the generated Resource type
-// static_toml generates: config::resources::values::Values
-pub use config::resources::values::Values as Resource;
+/// Make sure a cron expression has the five fields of a Workers cron trigger
+fn validate_cron(cron: &str) -> Result<(), String> {
+    match cron.split_whitespace().count() {
+        5 => Ok(()),
+        fields => Err(format!(
+            "Cron expression '{}' has {} fields, expected 5",
+            cron, fields
+        )),
+    }
+}
 
-/// Get the configuration version
-pub fn version() -> &'static str {
-    CONFIG.version
+/// Make sure a configured request header can be sent: a token name, a value on
+/// one line, and placeholders naming `HEADER_SECRET_` secrets or variables
+fn validate_header(name: &str, value: &str) -> Result<(), String> {
+    let token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if name.is_empty() || !name.chars().all(token) {
+        return Err(format!("'{}' is not a valid header name", name));
+    }
+    if value.contains(['\r', '\n']) {
+        return Err(format!("value of {} spans several lines", name));
+    }
+    let variable = |p: &str| {
+        p.strip_prefix(HEADER_SECRET_PREFIX).is_some_and(|rest| {
+            !rest.is_empty() && rest.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+    };
+    if let Some(placeholder) = template::placeholders(value).find(|p| !variable(p)) {
+        return Err(format!(
+            "'{{{{{}}}}}' in {} does not name a {}* secret or variable",
+            placeholder, name, HEADER_SECRET_PREFIX
+        ));
+    }
+    Ok(())
 }
 
-/// Get all configured resources to monitor
-pub fn resources() -> &'static [Resource] {
-    &CONFIG.resources
+/// What identifies the alerts of a resource across runs (`dedup`)
+///
+/// Alert state, flap detection, incidents and notification fingerprints follow
+/// this key, so a resource whose URL changes, such as a versioned
+/// `/v1.2.3/app.js` that is bumped to `/v1.2.4/app.js`, keeps one continuous
+/// incident history when deduplicated by name or group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupBy {
+    Url,
+    Name,
+    Group,
 }
 
-/// Get the number of resources configured
-pub fn resource_count() -> usize {
-    CONFIG.resources.len()
+/// A response status accepted by `expected_status`: a code, or an inclusive
+/// range such as `"200-299"` or `"3xx"`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StatusPattern {
+    Code(u16),
+    Range(String),
+}
+
+impl StatusPattern {
+    /// Lowest and highest status of the pattern
+    fn bounds(&self) -> Result<(u16, u16), String> {
+        let (low, high) = match self {
+            Self::Code(code) => (*code, *code),
+            Self::Range(range) => {
+                let range = range.trim();
+                if let Some(class) = range.strip_suffix("xx").or(range.strip_suffix("XX")) {
+                    let class: u16 = class
+                        .parse()
+                        .ok()
+                        .filter(|class| *class <= 5)
+                        .ok_or_else(|| format!("invalid status range '{}'", range))?;
+                    (class * 100, class * 100 + 99)
+                } else {
+                    let parse = |s: &str| s.trim().parse::<u16>().ok();
+                    let bounds = match range.split_once('-') {
+                        Some((low, high)) => parse(low).zip(parse(high)),
+                        None => parse(range).map(|code| (code, code)),
+                    };
+                    bounds.ok_or_else(|| format!("invalid status range '{}'", range))?
+                }
+            }
+        };
+        if low < 100 || high > 599 || low > high {
+            return Err(format!(
+                "status {}-{} is not an ascending range within 100-599",
+                low, high
+            ));
+        }
+        Ok((low, high))
+    }
+
+    /// Whether a response status matches the pattern
+    pub fn matches(&self, status: u16) -> bool {
+        self.bounds()
+            .is_ok_and(|(low, high)| (low..=high).contains(&status))
+    }
+}
+
+impl Resource {
+    /// HTTP method of the check request, uppercased
+    pub fn method(&self) -> String {
+        self.method.as_deref().unwrap_or("GET").to_ascii_uppercase()
+    }
+
+    /// Configured headers of the check request with their `{{NAME}}` placeholders
+    /// replaced by `lookup`, or which secret is missing
+    ///
+    /// Only names with `HEADER_SECRET_PREFIX` are looked up, whatever the config
+    /// was validated with.
+    pub fn request_headers(
+        &self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Vec<(String, String)>, String> {
+        let lookup = |name: &str| {
+            name.starts_with(HEADER_SECRET_PREFIX)
+                .then(|| lookup(name))
+                .flatten()
+        };
+        let mut headers = Vec::with_capacity(self.headers.len());
+        for (name, value) in &self.headers {
+            if let Some(missing) = template::placeholders(value).find(|p| lookup(p).is_none()) {
+                return Err(format!("{} of header {} not set", missing, name));
+            }
+            headers.push((name.clone(), template::render(value, lookup)));
+        }
+        Ok(headers)
+    }
+
+    /// Whether a response status passes the check (see `expected_status`)
+    pub fn accepts_status(&self, status: u16) -> bool {
+        if self.expected_status.is_empty() {
+            (200..300).contains(&status)
+        } else {
+            self.expected_status.iter().any(|p| p.matches(status))
+        }
+    }
+
+    /// Whether the check follows redirects; not when a redirect is an expected
+    /// status, which would otherwise never be seen
+    pub fn follows_redirects(&self) -> bool {
+        !(300..400).any(|status| self.accepts_status(status))
+    }
+
+    /// Subrequests a check of this resource may use: one, plus the pages of a
+    /// sitemap, the links of a crawled page, the alternate origins, the GET
+    /// following the HEAD request of an S3 object, the token request, retry and
+    /// manifest GET of a registry probe, the artifact download of a package, the
+    /// checksum database lookup of a Go module, the out-of-band re-fetch of a
+    /// mismatch or the companion files
+    pub fn subrequests(&self) -> usize {
+        1 + self.sitemap.as_ref().map_or(0, |s| s.max_urls)
+            + self.crawl.as_ref().map_or(0, |c| c.max_links)
+            + self.origins.len()
+            + self.companions.len()
+            + usize::from(self.verify_via.is_some())
+            + usize::from(self.s3.is_some())
+            + self.registry.as_ref().map_or(0, |_| 3)
+            + usize::from(self.package.is_some())
+            + usize::from(self.go.is_some())
+            + self.chunks.as_ref().map_or(0, |c| c.max_chunks)
+    }
+
+    /// Age in milliseconds after which the content is stale, if `max_age` is set
+    pub fn max_age_ms(&self) -> Option<u64> {
+        let minutes = clock::parse_duration(self.max_age.as_deref()?)?;
+        Some(minutes * 60_000)
+    }
+
+    /// Special probe kinds set on this resource; at most one is allowed
+    fn probe_kinds(&self) -> Vec<&'static str> {
+        [
+            ("sitemap", self.sitemap.is_some()),
+            ("crawl", self.crawl.is_some()),
+            ("origins", !self.origins.is_empty()),
+            ("s3", self.s3.is_some()),
+            ("registry", self.registry.is_some()),
+            ("package", self.package.is_some()),
+            ("go", self.go.is_some()),
+            ("pgp", self.pgp.is_some()),
+            ("feed", self.feed.is_some()),
+            ("chunks", self.chunks.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(kind, set)| set.then_some(kind))
+        .collect()
+    }
+
+    /// URLs given in the config that a check fetches
+    fn fetched_urls(&self) -> impl Iterator<Item = &str> {
+        let sumdb = self.go.as_ref().and_then(|m| m.sumdb.as_deref());
+        let manifest = self.chunks.as_ref().map(|c| c.manifest.as_str());
+        std::iter::once(self.url.as_str())
+            .chain(self.origins.iter().map(String::as_str))
+            .chain(self.verify_via.as_deref())
+            .chain(sumdb)
+            .chain(manifest)
+    }
+
+    /// Subject of this resource's alerts: its URL, or `name:<name>` or
+    /// `group:<group>` as selected by `dedup`
+    pub fn alert_key(&self) -> String {
+        match (self.dedup, &self.name, &self.group) {
+            (Some(DedupBy::Name), Some(name), _) => format!("name:{}", name),
+            (Some(DedupBy::Group), _, Some(group)) => format!("group:{}", group),
+            _ => self.url.clone(),
+        }
+    }
+
+    /// Whether a tag of a chat command or `?tag=` filter selects this resource:
+    /// one of its tags, its group, one of its label values, or a `key=value` label
+    pub fn has_tag(&self, tag: &str) -> bool {
+        match tag.split_once('=') {
+            Some((key, value)) => self.labels.get(key).is_some_and(|v| v == value),
+            None => {
+                self.tags.iter().any(|t| t == tag)
+                    || self.group.as_deref() == Some(tag)
+                    || self.labels.values().any(|v| v == tag)
+            }
+        }
+    }
+
+    /// Config warnings about this resource (see `Config::warnings`)
+    pub fn warnings(&self) -> Vec<String> {
+        self.fetched_urls()
+            .filter_map(idn::config_warning)
+            .collect()
+    }
+
+    /// Check per-resource constraints that the TOML schema cannot express
+    fn validate(&self) -> Result<(), String> {
+        if !self.allow_http {
+            if let Some(url) = self.fetched_urls().find(|url| is_plain_http(url)) {
+                let subject = if url == self.url {
+                    format!("Resource {}", url)
+                } else {
+                    format!("{} of {}", url, self.url)
+                };
+                return Err(format!(
+                    "{} uses plain HTTP; use https:// or set allow_http = true",
+                    subject
+                ));
+            }
+        }
+        if let Some(cron) = &self.cron {
+            validate_cron(cron).map_err(|e| format!("Resource {}: {}", self.url, e))?;
+        }
+        if let Some(timeout_ms) = self.timeout_ms {
+            if !(1..=MAX_TIMEOUT_MS).contains(&timeout_ms) {
+                return Err(format!(
+                    "timeout_ms of {} must be between 1 and {}",
+                    self.url, MAX_TIMEOUT_MS
+                ));
+            }
+        }
+        for tag in &self.tags {
+            if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == '=' || c == ',') {
+                return Err(format!("Invalid tag '{}' of {}", tag, self.url));
+            }
+        }
+        match self.dedup {
+            Some(DedupBy::Name) if self.name.is_none() => {
+                return Err(format!(
+                    "Resource {} deduplicated by name needs a name",
+                    self.url
+                ));
+            }
+            Some(DedupBy::Group) if self.group.is_none() => {
+                return Err(format!(
+                    "Resource {} deduplicated by group needs a group",
+                    self.url
+                ));
+            }
+            _ => {}
+        }
+        for origin in &self.origins {
+            if !(origin.starts_with("https://") || origin.starts_with("http://")) {
+                return Err(format!("Invalid origin '{}' of {}", origin, self.url));
+            }
+        }
+        if let Some(via) = &self.verify_via {
+            if !(via.starts_with("https://") || via.starts_with("http://")) {
+                return Err(format!("Invalid verify_via '{}' of {}", via, self.url));
+            }
+        }
+        let kinds = self.probe_kinds();
+        if kinds.len() > 1 {
+            return Err(format!(
+                "Resource {} cannot combine {}",
+                self.url,
+                kinds.join(" and ")
+            ));
+        }
+        // Companions are derived from a plain file URL, and the body is only read
+        // by the plain check, which origins also use
+        let plain_only = [
+            ("companions", !self.companions.is_empty()),
+            ("json_schema", self.json_schema.is_some()),
+            ("expected_magic", self.expected_magic.is_some()),
+            ("expected_status", !self.expected_status.is_empty()),
+            ("headers", !self.headers.is_empty()),
+            ("max_age", self.max_age.is_some()),
+        ];
+        for (field, _) in plain_only.iter().filter(|(_, set)| *set) {
+            if let Some(kind) = kinds.iter().find(|&&kind| kind != "origins") {
+                return Err(format!(
+                    "Resource {} cannot combine {} and {}",
+                    self.url, field, kind
+                ));
+            }
+        }
+        for companion in &self.companions {
+            companion.validate(&self.url)?;
+        }
+        if let Some(schema) = &self.json_schema {
+            schema.validate(&self.url)?;
+        }
+        if let Some(magic) = &self.expected_magic {
+            magic::parse(magic)
+                .map_err(|e| format!("Invalid expected_magic of {}: {}", self.url, e))?;
+        }
+        if let Some(max_age) = &self.max_age {
+            if clock::parse_duration(max_age).is_none() {
+                return Err(format!(
+                    "Invalid max_age '{}' of {}: use e.g. \"90m\", \"26h\" or \"7d\"",
+                    max_age, self.url
+                ));
+            }
+        }
+        if let Some(runbook) = &self.runbook {
+            runbook::validate(runbook)
+                .map_err(|e| format!("Invalid runbook of {}: {}", self.url, e))?;
+        }
+        for entry in &self.maintenance {
+            maintenance::validate(entry)
+                .map_err(|e| format!("Invalid maintenance window of {}: {}", self.url, e))?;
+        }
+        for (name, value) in &self.headers {
+            validate_header(name, value)
+                .map_err(|e| format!("Invalid header of {}: {}", self.url, e))?;
+        }
+        for pattern in &self.expected_status {
+            pattern
+                .bounds()
+                .map_err(|e| format!("Invalid expected_status of {}: {}", self.url, e))?;
+        }
+        if self.registry.is_some()
+            || self.package.is_some()
+            || self.go.is_some()
+            || self.pgp.is_some()
+            || self.feed.is_some()
+            || self.chunks.is_some()
+        {
+            if let Some(image) = &self.registry {
+                image.validate(&self.url)?;
+            }
+            if let Some(package) = &self.package {
+                package.validate(&self.url)?;
+            }
+            if let Some(module) = &self.go {
+                module.validate(&self.url)?;
+            }
+            if let Some(key) = &self.pgp {
+                key.validate(&self.url)?;
+            }
+            if let Some(feed) = &self.feed {
+                feed.validate(&self.url)?;
+            }
+            if let Some(chunks) = &self.chunks {
+                chunks.validate(&self.url)?;
+            }
+            if self.method.is_some() || self.body.is_some() || !self.sri.is_empty() {
+                return Err(format!("{} cannot set sri, method or body", self.url));
+            }
+            return Ok(());
+        }
+        if let Some(object) = &self.s3 {
+            object.validate(&self.url)?;
+            if self.method.is_some() || self.body.is_some() {
+                return Err(format!("S3 object {} cannot set method or body", self.url));
+            }
+            return Ok(());
+        }
+        if self.sitemap.is_some() || self.crawl.is_some() {
+            if let Some(sitemap) = &self.sitemap {
+                sitemap.validate(&self.url)?;
+            }
+            if let Some(crawl) = &self.crawl {
+                crawl.validate(&self.url)?;
+            }
+            if self.method() != "GET" || self.body.is_some() {
+                return Err(format!("{} must be fetched with GET", self.url));
+            }
+            return Ok(());
+        }
+        if self.sri.is_empty()
+            && self.expected_response_contains.is_none()
+            && self.expected_status.is_empty()
+            && self.max_age.is_none()
+        {
+            return Err(format!(
+                "Resource {} needs `sri`, `expected_response_contains`, `expected_status` or `max_age`",
+                self.url
+            ));
+        }
+        let method = self.method();
+        if !PROBE_METHODS.contains(&method.as_str()) {
+            return Err(format!("Unsupported method '{}' for {}", method, self.url));
+        }
+        if let Some(body) = &self.body {
+            if method == "GET" || method == "HEAD" {
+                return Err(format!(
+                    "{} requests cannot have a body ({})",
+                    method, self.url
+                ));
+            }
+            template::validate(body, BODY_PLACEHOLDERS)
+                .map_err(|e| format!("Invalid body for {}: {}", self.url, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Parse configuration from TOML text
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let mut config: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        config.revalidate()?;
+        Ok(config)
+    }
+
+    /// Parse configuration from the JSON form of config.toml
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let mut config: Self = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        config.revalidate()?;
+        Ok(config)
+    }
+
+    /// Parse configuration from TOML or JSON text, told apart by the leading `{`
+    /// of a JSON object (which TOML cannot start with)
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        if text.trim_start().starts_with('{') {
+            Self::from_json(text)
+        } else {
+            Self::from_toml(text)
+        }
+    }
+
+    /// Normalize and validate a config whose resources were changed in code
+    pub fn revalidate(&mut self) -> Result<(), String> {
+        self.normalize()?;
+        self.validate()?;
+        self.reset_sri_hashes();
+        Ok(())
+    }
+
+    /// Parsed SRI hashes of every resource
+    ///
+    /// Parsed once per config: the compiled-in, runtime and synced configs are cached per
+    /// isolate, so the hashes are only decoded again when the config changes.
+    pub fn sri_hashes(&self) -> &SriCache {
+        self.sri_hashes.get_or_init(|| {
+            let tenant_resources = self.tenants.iter().flat_map(|t| &t.resources);
+            SriCache::new(
+                self.resources
+                    .iter()
+                    .chain(tenant_resources)
+                    .map(|r| r.sri.as_str()),
+            )
+        })
+    }
+
+    /// Drop the parsed SRI hashes after changing resources of a copied config
+    pub fn reset_sri_hashes(&mut self) {
+        self.sri_hashes = OnceLock::new();
+    }
+
+    /// Replace resource URLs with their canonical form (see `normalize_url`)
+    fn normalize(&mut self) -> Result<(), String> {
+        let tenant_resources = self.tenants.iter_mut().flat_map(|t| &mut t.resources);
+        for resource in self.resources.iter_mut().chain(tenant_resources) {
+            resource.url = normalize_url(&resource.url)?;
+            if let Some(crawl) = &mut resource.crawl {
+                for domain in &mut crawl.allow_domains {
+                    *domain = idn::to_ascii(domain)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Problems that do not make the config invalid but deserve a look, such as
+    /// hostnames mixing scripts (see `idn`)
+    pub fn warnings(&self) -> Vec<String> {
+        let tenant_resources = self.tenants.iter().flat_map(|t| &t.resources);
+        let resources: Vec<&Resource> = self.resources.iter().chain(tenant_resources).collect();
+        let mut warnings: Vec<String> = resources.iter().flat_map(|r| r.warnings()).collect();
+        warnings.extend(sri_cross_use(resources));
+        warnings
+    }
+
+    /// Check constraints that the TOML schema cannot express
+    fn validate(&self) -> Result<(), String> {
+        let tenant_resources = self.tenants.iter().flat_map(|t| &t.resources);
+        let mut subrequests = Vec::new();
+        for resource in self.resources.iter().chain(tenant_resources) {
+            resource.validate()?;
+            subrequests.push(resource.subrequests());
+        }
+        self.metrics.validate()?;
+        self.schedule.validate(&subrequests)?;
+        self.escalation.validate()?;
+        self.blackouts.validate()?;
+        self.health.validate()?;
+        self.archive.validate()?;
+        self.hooks.validate()?;
+
+        // Normalized URLs identify resources within a scope (state, alerts, fingerprints)
+        let scopes =
+            std::iter::once(&self.resources).chain(self.tenants.iter().map(|t| &t.resources));
+        for resources in scopes {
+            let mut urls = HashSet::new();
+            if let Some(duplicate) = resources.iter().find(|r| !urls.insert(r.url.as_str())) {
+                return Err(format!("Duplicate resource {}", duplicate.url));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for t in &self.tenants {
+            if !tenant::is_valid_id(&t.id) {
+                return Err(format!(
+                    "Invalid tenant id '{}': use lowercase letters, digits, '-' and '_'",
+                    t.id
+                ));
+            }
+            if !seen.insert(t.id.as_str()) {
+                return Err(format!("Duplicate tenant id '{}'", t.id));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Warnings about SRI hashes shared between resources, which usually come from a
+/// copy-pasted resource entry: the same hash configured for different URLs, or
+/// the same URL configured with different hashes in different tenants
+pub fn sri_cross_use<'a>(resources: impl IntoIterator<Item = &'a Resource>) -> Vec<String> {
+    let mut urls_by_sri: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut sris_by_url: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for resource in resources {
+        if resource.sri.is_empty() {
+            continue;
+        }
+        let urls = urls_by_sri.entry(&resource.sri).or_default();
+        if !urls.contains(&resource.url.as_str()) {
+            urls.push(&resource.url);
+        }
+        let sris = sris_by_url.entry(&resource.url).or_default();
+        if !sris.contains(&resource.sri.as_str()) {
+            sris.push(&resource.sri);
+        }
+    }
+
+    let shared_hashes = urls_by_sri
+        .into_iter()
+        .filter(|(_, urls)| urls.len() > 1)
+        .map(|(sri, urls)| {
+            format!(
+                "SRI hash {} is configured for {} different URLs: {}",
+                sri,
+                urls.len(),
+                urls.join(", ")
+            )
+        });
+    let conflicting_urls = sris_by_url
+        .into_iter()
+        .filter(|(_, sris)| sris.len() > 1)
+        .map(|(url, sris)| {
+            format!(
+                "{} is configured with {} different SRI hashes: {}",
+                url,
+                sris.len(),
+                sris.join(", ")
+            )
+        });
+    shared_hashes.chain(conflicting_urls).collect()
+}
+
+/// Get the compiled-in configuration
+pub fn compiled() -> Arc<Config> {
+    let config = CONFIG.get_or_init(|| {
+        // config.toml is covered by unit tests, so this only fails on untested edits
+        let config = Config::from_toml(CONFIG_TOML).expect("config.toml is invalid");
+        for warning in config.warnings() {
+            console_error!("Config warning: {}", warning);
+        }
+        Arc::new(config)
+    });
+    Arc::clone(config)
+}
+
+/// Parse a runtime config, reusing the cached one while its text is unchanged
+fn parse_runtime(text: &str) -> Result<Arc<Config>, String> {
+    let mut cached = RUNTIME.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_text, config)) = cached.as_ref() {
+        if cached_text == text {
+            return Ok(Arc::clone(config));
+        }
+    }
+    let config = Config::from_toml(text)?;
+    sync::validate(&config)?;
+    for warning in config.warnings() {
+        console_error!("Config warning: {}", warning);
+    }
+    console_log!(
+        "Loaded config {} from {} ({} resources)",
+        config.version,
+        CONFIG_BINDING,
+        config.resources.len()
+    );
+    let config = Arc::new(config);
+    *cached = Some((text.to_string(), Arc::clone(&config)));
+    Ok(config)
+}
+
+/// Get the runtime configuration
+///
+/// Returns the config.toml stored under `CONFIG_KEY` in the `LINKKIVAHTI_CONFIG`
+/// namespace, so resources can be changed with `wrangler kv key put` instead of a
+/// redeploy. A config stored in D1 (see `configdb`) takes precedence. Falls back
+/// to the compiled-in config without the binding or the key, and when the stored
+/// config cannot be read or is invalid.
+pub async fn runtime(env: &Env) -> Arc<Config> {
+    if let Some(config) = configdb::load(env).await {
+        return config;
+    }
+    let Ok(kv) = env.kv(CONFIG_BINDING) else {
+        return compiled();
+    };
+    usage::kv_read();
+    let text = match kv.get(CONFIG_KEY).text().await {
+        Ok(Some(text)) => text,
+        Ok(None) => return compiled(),
+        Err(e) => {
+            console_error!(
+                "Failed to read {}: {}",
+                CONFIG_BINDING,
+                worker::Error::from(e)
+            );
+            return compiled();
+        }
+    };
+    parse_runtime(&text).unwrap_or_else(|e| {
+        console_error!(
+            "Config in {} is invalid, using compiled config: {}",
+            CONFIG_BINDING,
+            e
+        );
+        compiled()
+    })
+}
+
+/// Canonical form of a resource URL
+///
+/// Lowercases the scheme and host, converts international domain names to
+/// punycode, drops default ports and resolves `.` and `..` path segments, so
+/// `HTTPS://CDN.example.com:443/js/../a.js` becomes `https://cdn.example.com/a.js`.
+/// An empty path becomes `/`.
+pub fn normalize_url(url: &str) -> Result<String, String> {
+    Url::parse(url.trim())
+        .map(String::from)
+        .map_err(|e| format!("Invalid URL '{}': {}", url, e))
+}
+
+/// Whether a URL uses plain (unencrypted) HTTP
+pub fn is_plain_http(url: &str) -> bool {
+    url.get(..7)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("http://"))
+}
+
+/// Read a runtime setting from an environment variable or secret
+///
+/// Returns `None` if the setting is missing or empty.
+pub fn setting(env: &Env, name: &str) -> Option<String> {
+    env.var(name)
+        .ok()
+        .map(|v| v.to_string().trim().to_string())
+        .filter(|v| !v.is_empty())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tenant::Scope;
 
     #[test]
     fn test_config_loads() {
-        assert_eq!(version(), "1.0");
-        assert!(resource_count() > 0);
+        let config = compiled();
+        assert_eq!(config.version, "1.0");
+        assert!(!config.resources.is_empty());
+    }
+
+    #[test]
+    fn test_compiled_config_has_no_warnings() {
+        assert_eq!(compiled().warnings(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_parse_runtime() {
+        let text = "version = \"2.0\"\n[[resources]]\nurl = \"https://example.com/a.js\"\nsri = \"sha256-uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=\"\n";
+        let config = parse_runtime(text).unwrap();
+        assert_eq!(config.version, "2.0");
+        // Unchanged text reuses the parsed config
+        assert!(Arc::ptr_eq(&config, &parse_runtime(text).unwrap()));
+
+        // Stored configs are validated like synced ones
+        let bad_sri = text.replace(
+            "sha256-uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=",
+            "sha384-abc",
+        );
+        assert!(parse_runtime(&bad_sri).unwrap_err().contains("Invalid SRI"));
+        assert!(parse_runtime("version = \"2.0\"\n").is_err());
+    }
+
+    #[test]
+    fn test_find_resource() {
+        let config = compiled();
+        let scope = Scope::root(&config);
+        let first = &config.resources[0];
+        assert_eq!(
+            scope.find_resource(&first.url).map(|r| r.sri.as_str()),
+            Some(first.sri.as_str())
+        );
+        assert!(scope
+            .find_resource("https://not-configured.example/")
+            .is_none());
+    }
+
+    #[test]
+    fn test_optional_fields_default() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://example.com/a.js"
+            sri = "sha384-abc"
+
+            [[resources]]
+            url = "https://example.com/b.js"
+            sri = "sha384-def"
+            name = "Widget B"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.resources[0].name, None);
+        assert_eq!(config.resources[1].name.as_deref(), Some("Widget B"));
+    }
+
+    #[test]
+    fn test_invalid_config_rejected() {
+        assert!(Config::from_toml("version = 1").is_err());
+        assert!(Config::from_toml("version = \"1.0\"\n[[resources]]\nurl = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_probe_fields() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://api.example.com/graphql"
+            method = "post"
+            body = '{"query": "{ __typename }", "client": "{{name}}"}'
+            expected_response_contains = "__typename"
+            "#,
+        )
+        .unwrap();
+        let probe = &config.resources[0];
+        assert_eq!(probe.method(), "POST");
+        assert!(probe.sri.is_empty());
+
+        let invalid = [
+            // Neither sri nor expected_response_contains
+            "[[resources]]\nurl = \"https://a.example\"\nmethod = \"POST\"",
+            // Body on a GET request
+            "[[resources]]\nurl = \"https://a.example\"\nsri = \"x\"\nbody = \"{}\"",
+            // Unknown method
+            "[[resources]]\nurl = \"https://a.example\"\nsri = \"x\"\nmethod = \"FETCH\"",
+            // Unknown placeholder
+            "[[resources]]\nurl = \"https://a.example\"\nexpected_response_contains = \"ok\"\nmethod = \"POST\"\nbody = \"{{id}}\"",
+        ];
+        for resource in invalid {
+            let text = format!("version = \"1.0\"\n{}", resource);
+            assert!(Config::from_toml(&text).is_err(), "{}", resource);
+        }
+    }
+
+    #[test]
+    fn test_metrics_buckets() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [metrics]
+            buckets_ms = [100, 1000]
+
+            [metrics.groups.api]
+            buckets_ms = [10, 20, 50]
+
+            [[resources]]
+            url = "https://example.com/a.js"
+            sri = "sha384-abc"
+
+            [[resources]]
+            url = "https://api.example.com/"
+            sri = "sha384-def"
+            group = "api"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.metrics.buckets_for(&config.resources[0]),
+            &[100, 1000]
+        );
+        assert_eq!(
+            config.metrics.buckets_for(&config.resources[1]),
+            &[10, 20, 50]
+        );
+
+        let defaults = Config::from_toml("version = \"1.0\"").unwrap();
+        assert_eq!(defaults.metrics.buckets_ms, DEFAULT_BUCKETS_MS);
+
+        assert!(Config::from_toml("version = \"1.0\"\n[metrics]\nbuckets_ms = [100, 50]").is_err());
+        assert!(Config::from_toml("version = \"1.0\"\n[metrics]\nbuckets_ms = []").is_err());
+    }
+
+    #[test]
+    fn test_schedule() {
+        let resources: String = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                format!(
+                    "[[resources]]\nurl = \"https://example.com/{}.js\"\nsri = \"sha384-abc\"\n",
+                    name
+                )
+            })
+            .collect();
+        let parse = |schedule: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[schedule]\n{}\n{}",
+                schedule, resources
+            ))
+        };
+
+        let config = parse("max_subrequests = 12").unwrap();
+        assert_eq!(config.schedule.check_budget(), Some(2));
+        assert_eq!(
+            Config::from_toml("version = \"1.0\"")
+                .unwrap()
+                .schedule
+                .check_budget(),
+            None
+        );
+
+        // 2 checks per tick, 2 ticks per cycle cover 3 resources
+        assert!(parse("max_subrequests = 12\ninterval_minutes = 5\ncycle_minutes = 10").is_ok());
+        assert!(
+            parse("max_subrequests = 12\ninterval_minutes = 5\ncycle_minutes = 5")
+                .unwrap_err()
+                .contains("cannot check 3 resources")
+        );
+        assert!(parse("max_subrequests = 12\ncycle_minutes = 10").is_err());
+        assert!(parse("max_subrequests = 10").is_err());
+
+        assert!(parse("default_cron = \"*/5 * * * *\"").is_ok());
+        assert!(parse("default_cron = \"*/5 * * *\"")
+            .unwrap_err()
+            .contains("expected 5"));
+        let hourly = "[[resources]]\nurl = \"https://example.com/d.tgz\"\nsri = \"sha384-abc\"\n";
+        assert!(parse(&format!("{}cron = \"0 * * * *\"", hourly)).is_ok());
+        assert!(parse(&format!("{}cron = \"hourly\"", hourly)).is_err());
+    }
+
+    #[test]
+    fn test_sitemap_fields() {
+        let sitemap = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://docs.example.com/sitemap.xml\"\n{}",
+                fields
+            ))
+        };
+
+        // Sitemaps need no hash, and default to 50 pages
+        let config = sitemap("sitemap = {}").unwrap();
+        assert_eq!(config.resources[0].subrequests(), 51);
+        assert_eq!(
+            sitemap("sitemap = { max_urls = 5 }").unwrap().resources[0].subrequests(),
+            6
+        );
+
+        assert!(sitemap("sitemap = { max_urls = 0 }").is_err());
+        assert!(sitemap("sitemap = {}\nmethod = \"POST\"").is_err());
+        // Larger than the per-run budget
+        assert!(sitemap("sitemap = { max_urls = 5 }\n[schedule]\nmax_subrequests = 15").is_err());
+        assert!(sitemap("sitemap = { max_urls = 5 }\n[schedule]\nmax_subrequests = 16").is_ok());
+    }
+
+    #[test]
+    fn test_s3_fields() {
+        let object = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://account.r2.cloudflarestorage.com/b/a.js\"\n{}",
+                fields
+            ))
+        };
+        // Existence checks need no hash
+        let config = object("s3 = {}").unwrap();
+        let s3 = config.resources[0].s3.as_ref().unwrap();
+        assert_eq!(
+            (s3.region.as_str(), s3.credentials.as_str()),
+            ("auto", "S3")
+        );
+        assert!(object(
+            "s3 = { region = \"eu-north-1\", credentials = \"RELEASES\", etag = \"abc\" }"
+        )
+        .is_ok());
+
+        assert!(object("s3 = { region = \"\" }").is_err());
+        assert!(object("s3 = {}\nmethod = \"POST\"").is_err());
+        assert!(object("s3 = {}\ncrawl = {}").is_err());
+    }
+
+    #[test]
+    fn test_package_fields() {
+        let pypi = "https://pypi.org/pypi/requests/2.32.3/json";
+        let crates = "https://index.crates.io/se/rd/serde";
+        let package = |url: &str, fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"{}\"\n{}",
+                url,
+                fields.replace("HASH", &"ab".repeat(32))
+            ))
+        };
+        let config = package(
+            pypi,
+            "package = { index = \"pypi\", sha256 = \"HASH\", file = \"requests.whl\" }",
+        )
+        .unwrap();
+        assert_eq!(config.resources[0].subrequests(), 2);
+        assert!(package(
+            crates,
+            "package = { index = \"crates\", sha256 = \"HASH\", version = \"1.0.210\" }"
+        )
+        .is_ok());
+
+        assert!(package(
+            crates,
+            "package = { index = \"crates\", sha256 = \"HASH\" }"
+        )
+        .is_err());
+        assert!(package(crates, "package = { index = \"npm\", sha256 = \"HASH\" }").is_err());
+        // One probe kind per resource
+        assert!(package(
+            pypi,
+            "package = { index = \"pypi\", sha256 = \"HASH\" }\norigins = [\"https://mirror.example.com\"]"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_go_fields() {
+        let module = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://proxy.golang.org/golang.org/x/text/@v/v0.3.0.zip\"\n{}",
+                fields
+            ))
+        };
+        let sum = "h1:g61tztE5qeGQ89tm6NTjjM9VPIm088od1l6aSorWRWg=";
+        let config = module(&format!("go = {{ sum = \"{}\" }}", sum)).unwrap();
+        assert_eq!(config.resources[0].subrequests(), 2);
+        assert!(module(&format!(
+            "go = {{ sum = \"{}\", sumdb = \"https://proxy.golang.org/sumdb/sum.golang.org\" }}",
+            sum
+        ))
+        .is_ok());
+
+        assert!(module("go = { sum = \"g61tztE5\" }").is_err());
+        assert!(module(&format!(
+            "go = {{ sum = \"{}\" }}\npgp = {{ fingerprint = \"{}\" }}",
+            sum,
+            "AB".repeat(20)
+        ))
+        .is_err());
+        assert!(module(&format!("go = {{ sum = \"{}\" }}\nmethod = \"POST\"", sum)).is_err());
+    }
+
+    #[test]
+    fn test_max_age() {
+        let resource = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://downloads.example.com/nightly.tar.gz\"\n{}",
+                fields
+            ))
+        };
+        let config = resource("max_age = \"26h\"").unwrap();
+        assert_eq!(config.resources[0].max_age_ms(), Some(26 * 3_600_000));
+        assert!(resource("max_age = \"7d\"\nsri = \"sha384-abc\"").is_ok());
+
+        assert!(resource("max_age = \"a week\"").is_err());
+        assert!(resource("max_age = \"1d\"\nsitemap = {}").is_err());
+    }
+
+    #[test]
+    fn test_timeout_ms() {
+        let resource = |timeout: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://slow.example.com/a.js\"\nsri = \"sha384-abc\"\ntimeout_ms = {}",
+                timeout
+            ))
+        };
+        let config = resource("2500").unwrap();
+        assert_eq!(config.resources[0].timeout_ms, Some(2500));
+        assert!(resource("0").is_err());
+        assert!(resource("600000").is_err());
+    }
+
+    #[test]
+    fn test_chunks_fields() {
+        let artifact = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://downloads.example.com/app.tar.gz\"\n{}",
+                fields
+            ))
+        };
+        let manifest =
+            "chunks = { manifest = \"https://downloads.example.com/app.tar.gz.chunks.json\" }";
+        let config = artifact(manifest).unwrap();
+        assert_eq!(config.resources[0].subrequests(), 11);
+        assert!(artifact(
+            "chunks = { manifest = \"https://downloads.example.com/app.tar.gz.chunks.json\", max_chunks = 4 }"
+        )
+        .is_ok());
+
+        assert!(
+            artifact("chunks = { manifest = \"http://downloads.example.com/m.json\" }").is_err()
+        );
+        assert!(artifact(&format!("{}\nsri = \"sha384-abc\"", manifest)).is_err());
+        assert!(artifact(&format!("{}\nheaders = {{ Accept = \"*/*\" }}", manifest)).is_err());
+    }
+
+    #[test]
+    fn test_registry_fields() {
+        let digest = format!("sha256:{}", "ab".repeat(32));
+        let image = |url: &str, fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"{}\"\n{}",
+                url, fields
+            ))
+        };
+        let manifest = "https://ghcr.io/v2/kalapaja/linkkivahti/manifests/v1.0.0";
+        let config = image(
+            manifest,
+            &format!("registry = {{ digest = \"{}\" }}", digest),
+        )
+        .unwrap();
+        assert_eq!(config.resources[0].subrequests(), 4);
+        assert!(image(
+            manifest,
+            &format!(
+                "registry = {{ digest = \"{}\", credentials = \"GHCR\" }}",
+                digest
+            )
+        )
+        .is_ok());
+
+        assert!(image(manifest, "registry = { digest = \"latest\" }").is_err());
+        assert!(image(
+            "https://ghcr.io/kalapaja/linkkivahti:v1.0.0",
+            &format!("registry = {{ digest = \"{}\" }}", digest)
+        )
+        .is_err());
+        assert!(image(
+            manifest,
+            &format!(
+                "registry = {{ digest = \"{}\" }}\nsri = \"sha384-abc\"",
+                digest
+            )
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_expected_status() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://example.com/health"
+            expected_status = [204, "300-308"]
+            "#,
+        )
+        .unwrap();
+        let resource = &config.resources[0];
+        assert!(resource.accepts_status(204));
+        assert!(resource.accepts_status(302));
+        assert!(!resource.accepts_status(200));
+        assert!(!resource.follows_redirects());
+
+        let default = Resource::default();
+        assert!(default.accepts_status(299));
+        assert!(!default.accepts_status(304));
+        assert!(default.follows_redirects());
+
+        assert!(StatusPattern::Range("2xx".into()).matches(204));
+        assert!(!StatusPattern::Range("2xx".into()).matches(301));
+        for invalid in [
+            "2",
+            "\"20x\"",
+            "\"299-200\"",
+            "\"6xx\"",
+            "\"656xx\"",
+            "\"abc\"",
+        ] {
+            let text = format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://example.com/\"\nexpected_status = [{}]",
+                invalid
+            );
+            assert!(Config::from_toml(&text).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_headers() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://private.example.com/app.js"
+            sri = "sha384-abc"
+            headers = { Accept = "application/javascript", Authorization = "Bearer {{ HEADER_SECRET_CDN }}" }
+            "#,
+        )
+        .unwrap();
+        let resource = &config.resources[0];
+        let lookup = |name: &str| {
+            name.starts_with("HEADER_SECRET_")
+                .then(|| "t0k3n".to_string())
+        };
+        assert_eq!(
+            resource.request_headers(lookup).unwrap(),
+            vec![
+                ("Accept".to_string(), "application/javascript".to_string()),
+                ("Authorization".to_string(), "Bearer t0k3n".to_string())
+            ]
+        );
+        assert_eq!(
+            resource.request_headers(|_| None).unwrap_err(),
+            "HEADER_SECRET_CDN of header Authorization not set"
+        );
+        // Other settings are never looked up, even in an unvalidated resource
+        let leaky = Resource {
+            headers: [("X-Key".to_string(), "{{ACCESS_TOKEN}}".to_string())].into(),
+            ..resource.clone()
+        };
+        assert_eq!(
+            leaky.request_headers(lookup).unwrap_err(),
+            "ACCESS_TOKEN of header X-Key not set"
+        );
+
+        let invalid = [
+            "headers = { \"X Key\" = \"a\" }",
+            "headers = { X-Key = \"a\\r\\nHost: evil\" }",
+            "headers = { X-Key = \"{{secret.name}}\" }",
+            "headers = { X-Key = \"{{ACCESS_TOKEN}}\" }",
+            "headers = { X-Key = \"{{HEADER_SECRET_}}x\" }",
+            "headers = { X-Key = \"a\" }\nsitemap = { max_urls = 5 }",
+        ];
+        for fields in invalid {
+            let text = format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://a.example\"\nsri = \"x\"\n{}",
+                fields
+            );
+            assert!(Config::from_toml(&text).is_err(), "{}", fields);
+        }
+    }
+
+    #[test]
+    fn test_runbook() {
+        let resource = |runbook: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://cdn.example.com/app.js\"\nsri = \"sha384-abc\"\nrunbook = \"{}\"",
+                runbook
+            ))
+        };
+        let config = resource("cdn-app-js").unwrap();
+        assert_eq!(config.resources[0].runbook.as_deref(), Some("cdn-app-js"));
+        assert!(resource("https://docs.example.com/app").is_ok());
+        let error = resource("cdn app").unwrap_err().to_string();
+        assert!(
+            error.contains("Invalid runbook of https://cdn.example.com/app.js"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_maintenance() {
+        let resource = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://cdn.example.com/app.js\"\nsri = \"sha384-abc\"\n{}",
+                fields
+            ))
+        };
+        let config = resource(
+            "enabled = false\nmaintenance = [\"0 2 * * SUN 4h\", \"2026-11-20T22:00:00Z/2026-11-21T02:00:00Z\"]",
+        )
+        .unwrap();
+        assert_eq!(config.resources[0].enabled, Some(false));
+        assert_eq!(config.resources[0].maintenance.len(), 2);
+        let error = resource("maintenance = [\"0 2 * * SUNDAY 4h\"]")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("Invalid maintenance window of https://cdn.example.com/app.js"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_origin_fields() {
+        let resource = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://cdn.example.com/a.js\"\nsri = \"sha384-abc\"\n{}",
+                fields
+            ))
+        };
+        let config =
+            resource("origins = [\"https://origin.example.com\", \"https://backup.example.com\"]")
+                .unwrap();
+        assert_eq!(config.resources[0].subrequests(), 3);
+        assert!(resource("origins = [\"origin.example.com\"]").is_err());
+
+        let config = resource("verify_via = \"https://mirror.example.net/a.js\"").unwrap();
+        assert_eq!(config.resources[0].subrequests(), 2);
+        assert!(resource("verify_via = \"mirror.example.net/a.js\"").is_err());
+        assert!(resource("verify_via = \"http://mirror.example.net/a.js\"").is_err());
+    }
+
+    #[test]
+    fn test_plain_http() {
+        let resource = |url: &str, fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"{}\"\nsri = \"sha384-abc\"\n{}",
+                url, fields
+            ))
+        };
+        let error = resource("http://cdn.example.com/a.js", "").unwrap_err();
+        assert!(error.contains("allow_http"), "{}", error);
+        assert!(resource("HTTP://cdn.example.com/a.js", "").is_err());
+        assert!(resource(
+            "https://cdn.example.com/a.js",
+            "origins = [\"http://origin.example.com\"]"
+        )
+        .is_err());
+
+        let config = resource("http://cdn.example.com/a.js", "allow_http = true").unwrap();
+        assert!(config.resources[0].allow_http);
+        assert!(resource(
+            "https://cdn.example.com/a.js",
+            "origins = [\"http://origin.example.com\"]\nallow_http = true"
+        )
+        .is_ok());
+
+        assert!(is_plain_http("http://example.com"));
+        assert!(!is_plain_http("https://example.com"));
+        assert!(!is_plain_http("http"));
+    }
+
+    #[test]
+    fn test_alert_key() {
+        let resource = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://cdn.example.com/v1.2.3/app.js\"\nsri = \"sha384-abc\"\n{}",
+                fields
+            ))
+            .map(|config| config.resources[0].alert_key())
+        };
+        assert_eq!(
+            resource("name = \"app\"").unwrap(),
+            "https://cdn.example.com/v1.2.3/app.js"
+        );
+        assert_eq!(
+            resource("name = \"app\"\ndedup = \"name\"").unwrap(),
+            "name:app"
+        );
+        assert_eq!(
+            resource("group = \"frontend\"\ndedup = \"group\"").unwrap(),
+            "group:frontend"
+        );
+        assert_eq!(
+            resource("dedup = \"url\"").unwrap(),
+            "https://cdn.example.com/v1.2.3/app.js"
+        );
+        assert!(resource("dedup = \"name\"")
+            .unwrap_err()
+            .contains("needs a name"));
+        assert!(resource("name = \"app\"\ndedup = \"group\"").is_err());
+        assert!(resource("dedup = \"version\"").is_err());
+    }
+
+    #[test]
+    fn test_has_tag() {
+        let resource = Resource {
+            url: "https://cdn.example.com/app.js".to_string(),
+            group: Some("frontend".to_string()),
+            labels: BTreeMap::from([("team".to_string(), "web".to_string())]),
+            tags: vec!["cdn".to_string(), "prod".to_string()],
+            ..Default::default()
+        };
+        assert!(resource.has_tag("frontend"));
+        assert!(resource.has_tag("web"));
+        assert!(resource.has_tag("team=web"));
+        assert!(resource.has_tag("prod"));
+        assert!(!resource.has_tag("team=data"));
+        assert!(!resource.has_tag("team"));
+        assert!(!resource.has_tag("staging"));
+
+        let tags = |tags: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://cdn.example.com/app.js\"\nsri = \"sha384-abc\"\ntags = {}",
+                tags
+            ))
+        };
+        assert_eq!(
+            tags(r#"["cdn", "prod"]"#).unwrap().resources[0].tags,
+            ["cdn", "prod"]
+        );
+        assert_eq!(
+            tags(r#"["team=web"]"#).unwrap_err(),
+            "Invalid tag 'team=web' of https://cdn.example.com/app.js"
+        );
+        assert!(tags(r#"[""]"#).is_err());
+    }
+
+    #[test]
+    fn test_normalized_urls() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "HTTPS://CDN.Example.com:443/js/../a.js"
+            sri = "sha384-abc"
+
+            [[resources]]
+            url = "https://bücher.example/b.js"
+            sri = "sha384-abc"
+
+            [[resources]]
+            url = "https://cdn.example.com:8443"
+            sri = "sha384-abc"
+            "#,
+        )
+        .unwrap();
+        let urls: Vec<&str> = config.resources.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://cdn.example.com/a.js",
+                "https://xn--bcher-kva.example/b.js",
+                "https://cdn.example.com:8443/",
+            ]
+        );
+
+        // The same resource under two spellings
+        let duplicate = Config::from_toml(
+            "version = \"1.0\"\n\
+             [[resources]]\nurl = \"HTTPS://CDN.example.com:443/a.js\"\nsri = \"sha384-abc\"\n\
+             [[resources]]\nurl = \"https://cdn.example.com/a.js\"\nsri = \"sha384-abc\"",
+        );
+        assert_eq!(
+            duplicate.unwrap_err(),
+            "Duplicate resource https://cdn.example.com/a.js"
+        );
+
+        // Tenants may watch the same URL as the default scope
+        let shared = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://cdn.example.com/a.js"
+            sri = "sha384-abc"
+
+            [[tenants]]
+            id = "web"
+
+            [[tenants.resources]]
+            url = "https://CDN.example.com/a.js"
+            sri = "sha384-abc"
+            "#,
+        );
+        assert!(shared.is_ok());
+
+        assert!(normalize_url("cdn.example.com/a.js").is_err());
+
+        // Lookalike hostnames load, with a warning
+        let lookalike = Config::from_toml(
+            "version = \"1.0\"\n[[resources]]\nurl = \"https://pаypal.com/sdk.js\"\nsri = \"sha384-abc\"\n\
+             crawl = { allow_domains = [\"bücher.example\"] }",
+        );
+        let config = lookalike.unwrap();
+        assert_eq!(config.resources[0].url, "https://xn--pypal-4ve.com/sdk.js");
+        let crawl = config.resources[0].crawl.as_ref().unwrap();
+        assert_eq!(crawl.allow_domains, ["xn--bcher-kva.example"]);
+        assert_eq!(config.warnings().len(), 1);
+        assert!(config.warnings()[0].contains("Cyrillic and Latin"));
+    }
+
+    #[test]
+    fn test_sri_cross_use_warnings() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://cdn.example.com/a.js"
+            sri = "sha384-abc"
+
+            [[resources]]
+            url = "https://cdn.example.com/b.js"
+            sri = "sha384-abc"
+
+            [[tenants]]
+            id = "web"
+
+            [[tenants.resources]]
+            url = "https://cdn.example.com/a.js"
+            sri = "sha384-def"
+
+            [[tenants.resources]]
+            url = "https://cdn.example.com/b.js"
+            sri = "sha384-abc"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.warnings(),
+            vec![
+                "SRI hash sha384-abc is configured for 2 different URLs: \
+                 https://cdn.example.com/a.js, https://cdn.example.com/b.js",
+                "https://cdn.example.com/a.js is configured with 2 different SRI hashes: \
+                 sha384-abc, sha384-def",
+            ]
+        );
+
+        // Within the web tenant, each hash belongs to one URL
+        let tenant = &config.tenants[0].resources;
+        assert!(sri_cross_use(tenant).is_empty());
+
+        // Hashes of every scope are parsed once, by their distinct strings
+        assert_eq!(config.sri_hashes().len(), 2);
+    }
+
+    #[test]
+    fn test_crawl_fields() {
+        let page = |fields: &str| {
+            Config::from_toml(&format!(
+                "version = \"1.0\"\n[[resources]]\nurl = \"https://docs.example.com/\"\n{}",
+                fields
+            ))
+        };
+
+        let config = page("crawl = { allow_domains = [\"cdn.example.com\"] }").unwrap();
+        let crawl = config.resources[0].crawl.as_ref().unwrap();
+        assert_eq!(crawl.allow_domains, vec!["cdn.example.com"]);
+        assert_eq!(config.resources[0].subrequests(), 51);
+
+        assert!(page("crawl = { max_links = 2000 }").is_err());
+        assert!(page("crawl = {}\nsitemap = {}").is_err());
+        assert!(page("crawl = {}\norigins = [\"https://origin.example.com\"]").is_err());
+        assert!(page("crawl = {}\ncompanions = [\".map\"]").is_err());
+        assert!(page("crawl = {}\njson_schema = \"docs\"").is_err());
+        assert!(page("crawl = {}\nexpected_magic = '%PDF'").is_err());
+        assert!(Config::from_toml(
+            "version = \"1.0\"\n[[resources]]\nurl = \"/relative\"\ncrawl = {}"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_tenant_ids_validated() {
+        let duplicate = "version = \"1.0\"\n[[tenants]]\nid = \"web\"\n[[tenants]]\nid = \"web\"";
+        assert!(Config::from_toml(duplicate)
+            .unwrap_err()
+            .contains("Duplicate"));
+        assert!(Config::from_toml("version = \"1.0\"\n[[tenants]]\nid = \"Web Team\"").is_err());
+        assert!(Config::from_toml("version = \"1.0\"\n[[tenants]]\nid = \"web\"").is_ok());
     }
 
     #[test]
     fn test_resources_have_url_and_sri() {
-        for resource in resources() {
+        let config = compiled();
+        let tenant_resources = config.tenants.iter().flat_map(|t| &t.resources);
+        for resource in config.resources.iter().chain(tenant_resources) {
             assert!(!resource.url.is_empty(), "Resource URL should not be empty");
             assert!(!resource.sri.is_empty(), "Resource SRI should not be empty");
             assert!(
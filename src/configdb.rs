@@ -0,0 +1,480 @@
+//! Configuration stored in D1
+//!
+//! With the D1 database bound as `LINKKIVAHTI_DB` (see `database`), the config can
+//! live in SQL instead of a TOML document, so resources, their settings and the
+//! webhook routing of tenants can be queried and edited like any other table:
+//!
+//! - `linkkivahti_config`: a single row with the config `version` and every
+//!   top-level section (`[schedule]`, `[metrics]`, `[hooks]`, ...) as JSON
+//! - `linkkivahti_tenants`: one row per tenant with its name and webhook secret
+//!   and service
+//! - `linkkivahti_resources`: one row per resource of the default scope (tenant
+//!   `''`) or a tenant, with the commonly queried fields as columns and the other
+//!   per-resource settings as JSON
+//!
+//! `POST /config/d1` fills the tables from a config.toml in the request body, or
+//! from the current config if the body is empty. Once the config row exists, the
+//! tables replace the `LINKKIVAHTI_CONFIG` namespace and the compiled-in config
+//! (see `config::runtime`). Rows that do not form a valid config are logged and
+//! ignored, like an invalid config.toml.
+
+use crate::clock;
+use crate::config::{Config, Resource, Tenant};
+use crate::console::{console_error, console_log};
+use crate::database;
+use crate::sync;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex};
+use worker::wasm_bindgen::JsValue;
+use worker::*;
+
+/// Fields of a resource kept as columns rather than in its settings
+const RESOURCE_COLUMNS: &[&str] = &["url", "sri", "name", "group", "owner", "tags"];
+
+/// Parsed config, cached per isolate together with the rows it was assembled from
+static LOADED: Mutex<Option<(String, Arc<Config>)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ConfigRow {
+    version: String,
+    /// Top-level sections as a JSON object
+    settings: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TenantRow {
+    id: String,
+    position: u32,
+    name: Option<String>,
+    webhook_secret: Option<String>,
+    webhook_service: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ResourceRow {
+    /// Tenant id, empty for the default scope
+    tenant: String,
+    position: u32,
+    url: String,
+    sri: String,
+    name: Option<String>,
+    resource_group: Option<String>,
+    owner: Option<String>,
+    /// JSON array
+    tags: String,
+    /// Other settings as a JSON object
+    settings: String,
+}
+
+/// A config as table rows
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Rows {
+    config: ConfigRow,
+    tenants: Vec<TenantRow>,
+    resources: Vec<ResourceRow>,
+}
+
+/// Serialize a value as a JSON object without the given fields
+fn settings<T: Serialize>(value: &T, columns: &[&str]) -> Result<String> {
+    let mut value = serde_json::to_value(value)?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.retain(|field, _| !columns.contains(&field.as_str()));
+    }
+    Ok(value.to_string())
+}
+
+fn resource_row(tenant: &str, position: usize, resource: &Resource) -> Result<ResourceRow> {
+    Ok(ResourceRow {
+        tenant: tenant.to_string(),
+        position: position as u32,
+        url: resource.url.clone(),
+        sri: resource.sri.clone(),
+        name: resource.name.clone(),
+        resource_group: resource.group.clone(),
+        owner: resource.owner.clone(),
+        tags: serde_json::to_string(&resource.tags)?,
+        settings: settings(resource, RESOURCE_COLUMNS)?,
+    })
+}
+
+/// Split a config into table rows
+fn rows(config: &Config) -> Result<Rows> {
+    let mut resources = Vec::new();
+    for (position, resource) in config.resources.iter().enumerate() {
+        resources.push(resource_row("", position, resource)?);
+    }
+    let mut tenants = Vec::new();
+    for (position, tenant) in config.tenants.iter().enumerate() {
+        tenants.push(TenantRow {
+            id: tenant.id.clone(),
+            position: position as u32,
+            name: tenant.name.clone(),
+            webhook_secret: tenant.webhook_secret.clone(),
+            webhook_service: tenant.webhook_service.clone(),
+        });
+        for (position, resource) in tenant.resources.iter().enumerate() {
+            resources.push(resource_row(&tenant.id, position, resource)?);
+        }
+    }
+    Ok(Rows {
+        config: ConfigRow {
+            version: config.version.clone(),
+            settings: settings(config, &["version", "resources", "tenants"])?,
+        },
+        tenants,
+        resources,
+    })
+}
+
+/// Parse a JSON object column
+fn object(column: &str, text: &str) -> std::result::Result<Map<String, Value>, String> {
+    serde_json::from_str(text).map_err(|e| format!("Invalid {} '{}': {}", column, text, e))
+}
+
+fn resource(row: &ResourceRow) -> std::result::Result<Resource, String> {
+    let mut fields = object("settings", &row.settings)?;
+    let tags: Vec<String> = serde_json::from_str(&row.tags)
+        .map_err(|e| format!("Invalid tags of {}: {}", row.url, e))?;
+    fields.insert("url".to_string(), row.url.clone().into());
+    fields.insert("sri".to_string(), row.sri.clone().into());
+    fields.insert("name".to_string(), row.name.clone().into());
+    fields.insert("group".to_string(), row.resource_group.clone().into());
+    fields.insert("owner".to_string(), row.owner.clone().into());
+    fields.insert("tags".to_string(), tags.into());
+    serde_json::from_value(Value::Object(fields))
+        .map_err(|e| format!("Invalid resource {}: {}", row.url, e))
+}
+
+/// Resources of one scope in their configured order
+fn scope_resources(
+    rows: &[ResourceRow],
+    tenant: &str,
+) -> std::result::Result<Vec<Resource>, String> {
+    let mut rows: Vec<&ResourceRow> = rows.iter().filter(|r| r.tenant == tenant).collect();
+    rows.sort_by_key(|r| r.position);
+    rows.into_iter().map(resource).collect()
+}
+
+/// Assemble and validate a config from table rows
+fn assemble(rows: &Rows) -> std::result::Result<Config, String> {
+    if let Some(orphan) = rows
+        .resources
+        .iter()
+        .find(|r| !r.tenant.is_empty() && !rows.tenants.iter().any(|t| t.id == r.tenant))
+    {
+        return Err(format!(
+            "Resource {} belongs to unknown tenant '{}'",
+            orphan.url, orphan.tenant
+        ));
+    }
+    let mut tenant_rows: Vec<&TenantRow> = rows.tenants.iter().collect();
+    tenant_rows.sort_by_key(|t| t.position);
+    let mut tenants = Vec::with_capacity(tenant_rows.len());
+    for row in tenant_rows {
+        tenants.push(Tenant {
+            id: row.id.clone(),
+            name: row.name.clone(),
+            webhook_secret: row.webhook_secret.clone(),
+            webhook_service: row.webhook_service.clone(),
+            resources: scope_resources(&rows.resources, &row.id)?,
+        });
+    }
+
+    let mut fields = object("config settings", &rows.config.settings)?;
+    fields.insert("version".to_string(), rows.config.version.clone().into());
+    let mut config: Config =
+        serde_json::from_value(Value::Object(fields)).map_err(|e| e.to_string())?;
+    config.resources = scope_resources(&rows.resources, "")?;
+    config.tenants = tenants;
+    config.revalidate()?;
+    sync::validate(&config)?;
+    Ok(config)
+}
+
+/// Bind an optional text column
+fn nullable(value: &Option<String>) -> JsValue {
+    value.as_deref().map_or(JsValue::NULL, Into::into)
+}
+
+/// Read the config rows, or `None` if no config is stored
+async fn read(db: &D1Database) -> Result<Option<Rows>> {
+    database::migrate(db).await?;
+    let results = db
+        .batch(vec![
+            db.prepare("SELECT version, settings FROM linkkivahti_config WHERE id = 1"),
+            db.prepare(
+                "SELECT id, position, name, webhook_secret, webhook_service \
+                 FROM linkkivahti_tenants",
+            ),
+            db.prepare(
+                "SELECT tenant, position, url, sri, name, resource_group, owner, tags, settings \
+                 FROM linkkivahti_resources",
+            ),
+        ])
+        .await?;
+    let [config, tenants, resources] = results.as_slice() else {
+        return Err(Error::RustError(format!(
+            "Expected 3 results from {}, got {}",
+            database::BINDING,
+            results.len()
+        )));
+    };
+    let Some(config) = config.results::<ConfigRow>()?.into_iter().next() else {
+        return Ok(None);
+    };
+    Ok(Some(Rows {
+        config,
+        tenants: tenants.results()?,
+        resources: resources.results()?,
+    }))
+}
+
+/// Replace the stored config with the given one in a single transaction
+async fn write(db: &D1Database, config: &Config) -> Result<()> {
+    database::migrate(db).await?;
+    let rows = rows(config)?;
+    let mut batch = vec![
+        db.prepare("DELETE FROM linkkivahti_resources"),
+        db.prepare("DELETE FROM linkkivahti_tenants"),
+        db.prepare(
+            "INSERT INTO linkkivahti_config (id, version, settings, updated_at) \
+             VALUES (1, ?1, ?2, ?3) \
+             ON CONFLICT (id) DO UPDATE SET version = excluded.version, \
+             settings = excluded.settings, updated_at = excluded.updated_at",
+        )
+        .bind(&[
+            rows.config.version.as_str().into(),
+            rows.config.settings.as_str().into(),
+            (clock::now_ms() as f64).into(),
+        ])?,
+    ];
+    for tenant in &rows.tenants {
+        batch.push(
+            db.prepare(
+                "INSERT INTO linkkivahti_tenants \
+                 (id, position, name, webhook_secret, webhook_service) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(&[
+                tenant.id.as_str().into(),
+                tenant.position.into(),
+                nullable(&tenant.name),
+                nullable(&tenant.webhook_secret),
+                nullable(&tenant.webhook_service),
+            ])?,
+        );
+    }
+    for resource in &rows.resources {
+        batch.push(
+            db.prepare(
+                "INSERT INTO linkkivahti_resources \
+                 (tenant, position, url, sri, name, resource_group, owner, tags, settings) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )
+            .bind(&[
+                resource.tenant.as_str().into(),
+                resource.position.into(),
+                resource.url.as_str().into(),
+                resource.sri.as_str().into(),
+                nullable(&resource.name),
+                nullable(&resource.resource_group),
+                nullable(&resource.owner),
+                resource.tags.as_str().into(),
+                resource.settings.as_str().into(),
+            ])?,
+        );
+    }
+    db.batch(batch).await?;
+    Ok(())
+}
+
+/// Get the config stored in D1, if any
+///
+/// Returns `None` without the binding or a stored config, and when the rows
+/// cannot be read or do not form a valid config.
+pub async fn load(env: &Env) -> Option<Arc<Config>> {
+    let db = database::open(env)?;
+    let rows = match read(&db).await {
+        Ok(rows) => rows?,
+        Err(e) => {
+            console_error!("Failed to read config from {}: {}", database::BINDING, e);
+            return None;
+        }
+    };
+    let text = serde_json::to_string(&rows).ok()?;
+
+    let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_text, config)) = loaded.as_ref() {
+        if *cached_text == text {
+            return Some(Arc::clone(config));
+        }
+    }
+    match assemble(&rows) {
+        Ok(config) => {
+            for warning in config.warnings() {
+                console_error!("Config warning: {}", warning);
+            }
+            console_log!(
+                "Loaded config {} from {} ({} resources)",
+                config.version,
+                database::BINDING,
+                config.resources.len()
+            );
+            let config = Arc::new(config);
+            *loaded = Some((text, Arc::clone(&config)));
+            Some(config)
+        }
+        Err(e) => {
+            console_error!(
+                "Config in {} is invalid, ignoring it: {}",
+                database::BINDING,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Summary of a stored config
+#[derive(Debug, Serialize)]
+struct Stored {
+    version: String,
+    resources: usize,
+    tenants: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+/// Handle `POST /config/d1`: store a config.toml (or the current config) in D1
+pub async fn handle_store(env: &Env, mut req: Request) -> Result<Response> {
+    let db = database::require(env)?;
+    let body = req.text().await?;
+    let config = if body.trim().is_empty() {
+        sync::base_config(env).await
+    } else {
+        let config = match Config::from_toml(&body).and_then(|c| sync::validate(&c).map(|_| c)) {
+            Ok(config) => config,
+            Err(e) => return Response::error(format!("Invalid config: {}", e), 400),
+        };
+        Arc::new(config)
+    };
+    write(&db, &config).await?;
+
+    let resources = config.resources.len()
+        + config
+            .tenants
+            .iter()
+            .map(|t| t.resources.len())
+            .sum::<usize>();
+    console_log!(
+        "Stored config {} in {} ({} resources)",
+        config.version,
+        database::BINDING,
+        resources
+    );
+    Response::from_json(&Stored {
+        version: config.version.clone(),
+        resources,
+        tenants: config.tenants.len(),
+        warnings: config.warnings(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    const VALID_SRI: &str = "sha256-uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=";
+
+    fn example() -> Config {
+        Config::from_toml(&format!(
+            r#"
+            version = "3.1"
+
+            [schedule]
+            max_subrequests = 50
+
+            [[resources]]
+            url = "https://cdn.example.com/app.js"
+            sri = "{sri}"
+            name = "App"
+            group = "frontend"
+            tags = ["cdn", "prod"]
+            labels = {{ team = "web" }}
+
+            [[resources]]
+            url = "https://api.example.com/health"
+            expected_response_contains = "ok"
+
+            [[tenants]]
+            id = "shop"
+            webhook_secret = "SHOP_WEBHOOK"
+            webhook_service = "discord"
+
+            [[tenants.resources]]
+            url = "https://shop.example.com/cart.js"
+            sri = "{sri}"
+            owner = "@shop"
+            "#,
+            sri = VALID_SRI
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rows() {
+        let rows = rows(&example()).unwrap();
+        assert_eq!(rows.config.version, "3.1");
+        assert!(rows.config.settings.contains("\"max_subrequests\":50"));
+        assert!(!rows.config.settings.contains("resources"));
+
+        assert_eq!(rows.tenants.len(), 1);
+        assert_eq!(
+            rows.tenants[0].webhook_secret.as_deref(),
+            Some("SHOP_WEBHOOK")
+        );
+        assert_eq!(rows.tenants[0].webhook_service.as_deref(), Some("discord"));
+
+        let app = &rows.resources[0];
+        assert_eq!((app.tenant.as_str(), app.position), ("", 0));
+        assert_eq!(app.resource_group.as_deref(), Some("frontend"));
+        assert_eq!(app.tags, r#"["cdn","prod"]"#);
+        assert_eq!(app.settings, r#"{"labels":{"team":"web"}}"#);
+        let cart = &rows.resources[2];
+        assert_eq!((cart.tenant.as_str(), cart.position), ("shop", 0));
+        assert_eq!(cart.owner.as_deref(), Some("@shop"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for config in [example(), (*config::compiled()).clone()] {
+            let mut rows = rows(&config).unwrap();
+            // Rows come back from SQL in no particular order
+            rows.resources.reverse();
+            let assembled = assemble(&rows).unwrap();
+            assert_eq!(
+                serde_json::to_value(&assembled).unwrap(),
+                serde_json::to_value(&config).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_assemble_invalid() {
+        let mut rows = rows(&example()).unwrap();
+        rows.resources[2].tenant = "gone".to_string();
+        assert!(assemble(&rows)
+            .unwrap_err()
+            .contains("unknown tenant 'gone'"));
+
+        let mut rows = super::rows(&example()).unwrap();
+        rows.resources[0].sri = "sha384-abc".to_string();
+        assert!(assemble(&rows).unwrap_err().contains("Invalid SRI"));
+
+        let mut rows = super::rows(&example()).unwrap();
+        rows.resources[1].settings = "not json".to_string();
+        assert!(assemble(&rows).unwrap_err().contains("Invalid settings"));
+    }
+}
@@ -0,0 +1,61 @@
+//! Console output tagged with the run ID
+//!
+//! Every module imports `console_log!` and `console_error!` from here; the explicit
+//! import takes precedence over the `worker::*` glob import. Lines logged during a
+//! run are prefixed with its ID (see `trace`). `worker`'s macros need the Workers
+//! runtime, so tests print to stdout and stderr instead.
+
+/// A log line with the current run ID prepended
+pub fn tagged(line: std::fmt::Arguments) -> String {
+    match crate::trace::current() {
+        Some(id) => format!("[{}] {}", id, line),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(not(test))]
+macro_rules! stdout_log {
+    ($($t:tt)*) => {
+        worker::console_log!("{}", $crate::console::tagged(format_args!($($t)*)))
+    };
+}
+
+#[cfg(not(test))]
+macro_rules! stderr_log {
+    ($($t:tt)*) => {
+        worker::console_error!("{}", $crate::console::tagged(format_args!($($t)*)))
+    };
+}
+
+#[cfg(test)]
+macro_rules! stdout_log {
+    ($($t:tt)*) => {
+        println!("{}", $crate::console::tagged(format_args!($($t)*)))
+    };
+}
+
+#[cfg(test)]
+macro_rules! stderr_log {
+    ($($t:tt)*) => {
+        eprintln!("{}", $crate::console::tagged(format_args!($($t)*)))
+    };
+}
+
+pub(crate) use stderr_log as console_error;
+pub(crate) use stdout_log as console_log;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_tagged() {
+        assert_eq!(tagged(format_args!("Checking {}", 3)), "Checking 3");
+        let line = block_on(trace::in_run("18f2c".to_string(), async {
+            tagged(format_args!("Checking {}", 3))
+        }));
+        assert_eq!(line, "[18f2c] Checking 3");
+    }
+}
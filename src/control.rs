@@ -0,0 +1,252 @@
+//! Global kill switch and degraded mode
+//!
+//! During incident response an alert storm sometimes has to stop before its cause
+//! is understood, without deleting the cron triggers. With `ENABLED = "false"`, or
+//! the stored flag set through `PUT /admin/monitoring`, monitoring is disabled:
+//! scheduled runs only log that they were skipped, pending re-check alarms are
+//! disarmed and a new deployment is not self-checked (see `deploy`). A manual
+//! `POST /check` still runs.
+//!
+//! The degraded flag (`DEGRADED = "true"` or the stored flag) leaves the checks
+//! alone; it tells the consumers of `GET /` not to rely on the results, e.g. while
+//! the monitoring itself is being repaired. Disabled monitoring always reports as
+//! degraded, as its results go stale.
+//!
+//! The variables can only switch monitoring off or into degraded mode, so a
+//! deployment pinning `ENABLED = "false"` cannot be re-enabled through the API.
+
+use crate::auth::Principal;
+use crate::clock;
+use crate::config;
+use crate::console::{console_error, console_log};
+use crate::store::Store;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// KV key of the stored flags
+const STATE_KEY: &str = "control";
+
+/// Kill switch and degraded flag in force
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Control {
+    /// Whether scheduled runs and re-checks check resources
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub degraded: bool,
+    /// Why the flags were set, as given by the operator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Token name that last changed the stored flags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            degraded: false,
+            reason: None,
+            updated_by: None,
+            updated_at: None,
+        }
+    }
+}
+
+/// Body of `PUT /admin/monitoring`, all fields optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Update {
+    enabled: Option<bool>,
+    degraded: Option<bool>,
+    reason: Option<String>,
+}
+
+impl Control {
+    /// Whether results should be reported as unreliable
+    pub fn is_degraded(&self) -> bool {
+        self.degraded || !self.enabled
+    }
+
+    /// Whether monitoring runs without either flag
+    pub fn is_normal(&self) -> bool {
+        self.enabled && !self.degraded
+    }
+
+    /// Apply the `ENABLED` and `DEGRADED` variables over the stored flags
+    fn with_env(mut self, enabled: Option<bool>, degraded: Option<bool>) -> Self {
+        if enabled == Some(false) {
+            self.enabled = false;
+        }
+        if degraded == Some(true) {
+            self.degraded = true;
+        }
+        self
+    }
+
+    /// Apply an update of the stored flags; the reason is dropped once monitoring
+    /// is back to normal
+    fn update(mut self, update: Update, by: &str, at: String) -> Self {
+        if let Some(enabled) = update.enabled {
+            self.enabled = enabled;
+        }
+        if let Some(degraded) = update.degraded {
+            self.degraded = degraded;
+        }
+        if let Some(reason) = update.reason.filter(|r| !r.trim().is_empty()) {
+            self.reason = Some(reason.trim().to_string());
+        }
+        if self.is_normal() {
+            self.reason = None;
+        }
+        self.updated_by = Some(by.to_string());
+        self.updated_at = Some(at);
+        self
+    }
+
+    /// One-line description for logs and the text status
+    pub fn describe(&self) -> String {
+        let state = match (self.enabled, self.degraded) {
+            (false, _) => "monitoring disabled",
+            (true, true) => "degraded mode",
+            (true, false) => "monitoring enabled",
+        };
+        match &self.reason {
+            Some(reason) => format!("{}: {}", state, reason),
+            None => state.to_string(),
+        }
+    }
+}
+
+/// Boolean variable: `true`/`1`/`on`/`yes` or `false`/`0`/`off`/`no`
+fn flag(value: Option<String>) -> Option<bool> {
+    match value?.to_ascii_lowercase().as_str() {
+        "true" | "1" | "on" | "yes" => Some(true),
+        "false" | "0" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+async fn load_stored(store: &Store) -> Control {
+    store
+        .get(STATE_KEY)
+        .await
+        .unwrap_or_else(|e| {
+            console_error!("Failed to load monitoring flags: {}", e);
+            None
+        })
+        .unwrap_or_default()
+}
+
+/// Flags in force, from the variables and the state store
+pub async fn load(env: &Env) -> Control {
+    let stored = match Store::from_env(env) {
+        Some(store) => load_stored(&store).await,
+        None => Control::default(),
+    };
+    stored.with_env(
+        flag(config::setting(env, "ENABLED")),
+        flag(config::setting(env, "DEGRADED")),
+    )
+}
+
+/// Handle `PUT /admin/monitoring`: change the stored flags
+pub async fn handle_update(env: &Env, principal: &Principal, mut req: Request) -> Result<Response> {
+    let update: Update = match req.json().await {
+        Ok(update) => update,
+        Err(e) => return Response::error(format!("Invalid monitoring update: {}", e), 400),
+    };
+    let store = Store::require(env)?;
+    let stored = load_stored(&store)
+        .await
+        .update(update, &principal.name, clock::timestamp());
+    store.put(STATE_KEY, &stored).await?;
+    console_log!("{} ({})", stored.describe(), principal.name);
+
+    // The variables may keep overriding the stored flags
+    let control = stored.with_env(
+        flag(config::setting(env, "ENABLED")),
+        flag(config::setting(env, "DEGRADED")),
+    );
+    Response::from_json(&control)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag() {
+        assert_eq!(flag(Some("false".into())), Some(false));
+        assert_eq!(flag(Some("OFF".into())), Some(false));
+        assert_eq!(flag(Some("1".into())), Some(true));
+        assert_eq!(flag(Some("maybe".into())), None);
+        assert_eq!(flag(None), None);
+    }
+
+    #[test]
+    fn test_with_env() {
+        let control = Control::default().with_env(None, None);
+        assert!(control.is_normal());
+
+        let control = Control::default().with_env(Some(false), None);
+        assert!(!control.enabled);
+        assert!(control.is_degraded());
+
+        // The variables cannot re-enable what the stored flags disabled
+        let stored = Control {
+            enabled: false,
+            ..Control::default()
+        };
+        assert!(!stored.with_env(Some(true), Some(false)).enabled);
+    }
+
+    #[test]
+    fn test_update() {
+        let update = Update {
+            enabled: Some(false),
+            reason: Some(" CDN incident ".into()),
+            ..Update::default()
+        };
+        let control = Control::default().update(update, "ops", "2025-11-12T10:00:00Z".into());
+        assert_eq!(control.describe(), "monitoring disabled: CDN incident");
+        assert_eq!(control.updated_by.as_deref(), Some("ops"));
+
+        let update = Update {
+            degraded: Some(true),
+            ..Update::default()
+        };
+        let control = control.update(update, "ops", "2025-11-12T10:05:00Z".into());
+        assert!(!control.enabled);
+        assert!(control.degraded);
+        assert_eq!(control.reason.as_deref(), Some("CDN incident"));
+
+        // Back to normal drops the reason
+        let update = Update {
+            enabled: Some(true),
+            degraded: Some(false),
+            reason: None,
+        };
+        let control = control.update(update, "ops", "2025-11-12T11:00:00Z".into());
+        assert!(control.is_normal());
+        assert_eq!(control.reason, None);
+    }
+
+    #[test]
+    fn test_stored_defaults() {
+        let control: Control = serde_json::from_str(r#"{"degraded":true}"#).unwrap();
+        assert!(control.enabled);
+        assert!(control.is_degraded());
+        assert_eq!(
+            serde_json::to_string(&Control::default()).unwrap(),
+            r#"{"enabled":true,"degraded":false}"#
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Correlation hints for alerts of resources failing together
+//!
+//! When a CDN or a network path breaks, many resources fail in the same run and
+//! each alert on its own looks like an isolated problem. Before notifications go
+//! out, the failures of the run are counted by host and by error type, and every
+//! alert is annotated with what it has in common with the others, e.g.
+//! "12 other resources on cdn.example.com also failing; all 14 failures share
+//! error fetch_failed".
+
+use crate::checker::CheckResult;
+use std::collections::HashMap;
+use worker::Url;
+
+/// Failures in a run before hints are given at all
+const MIN_FAILURES: usize = 3;
+
+/// Other failures on the same host before they are mentioned
+const MIN_SAME_HOST: usize = 2;
+
+/// Failures of one run, counted for hints
+#[derive(Debug, Default)]
+pub struct Correlation {
+    failures: usize,
+    by_host: HashMap<String, usize>,
+    by_error: HashMap<String, usize>,
+}
+
+/// Host of a result's URL
+fn host(result: &CheckResult) -> Option<String> {
+    Url::parse(&result.url).ok()?.host_str().map(str::to_string)
+}
+
+/// Error type of a failing result, e.g. `fetch_failed` or `sri_mismatch`
+fn error_type(result: &CheckResult) -> String {
+    match &result.error {
+        Some(error) if !result.success => error.code(),
+        _ => "sri_mismatch".to_string(),
+    }
+}
+
+impl Correlation {
+    /// Count the failures among the results of a run
+    pub fn new(results: &[CheckResult]) -> Self {
+        let mut correlation = Self::default();
+        for result in results.iter().filter(|r| r.has_problem()) {
+            correlation.failures += 1;
+            if let Some(host) = host(result) {
+                *correlation.by_host.entry(host).or_default() += 1;
+            }
+            *correlation.by_error.entry(error_type(result)).or_default() += 1;
+        }
+        correlation
+    }
+
+    /// What a failing result has in common with the other failures of the run
+    pub fn hint(&self, result: &CheckResult) -> Option<String> {
+        if self.failures < MIN_FAILURES || !result.has_problem() {
+            return None;
+        }
+        let mut hints = Vec::new();
+        if let Some(host) = host(result) {
+            let others = self.by_host.get(&host).copied().unwrap_or_default() - 1;
+            if others >= MIN_SAME_HOST {
+                hints.push(format!(
+                    "{} other resources on {} also failing",
+                    others, host
+                ));
+            }
+        }
+        let error = error_type(result);
+        if self.by_error.get(&error) == Some(&self.failures) {
+            hints.push(format!(
+                "all {} failures share error {}",
+                self.failures, error
+            ));
+        }
+        (!hints.is_empty()).then(|| hints.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::CheckError;
+
+    fn failure(url: &str, error: CheckError) -> CheckResult {
+        CheckResult::failure(url.to_string(), error)
+    }
+
+    #[test]
+    fn test_hints() {
+        let results = vec![
+            failure("https://cdn.example.com/a.js", CheckError::FetchFailed),
+            failure("https://cdn.example.com/b.js", CheckError::FetchFailed),
+            failure("https://cdn.example.com/c.js", CheckError::FetchFailed),
+            failure("https://other.example.com/d.js", CheckError::FetchFailed),
+            CheckResult::success("https://cdn.example.com/e.js", 200, true),
+        ];
+        let correlation = Correlation::new(&results);
+        assert_eq!(
+            correlation.hint(&results[0]).as_deref(),
+            Some("2 other resources on cdn.example.com also failing; all 4 failures share error fetch_failed")
+        );
+        assert_eq!(
+            correlation.hint(&results[3]).as_deref(),
+            Some("all 4 failures share error fetch_failed")
+        );
+        assert_eq!(correlation.hint(&results[4]), None);
+
+        // Mixed errors on different hosts have nothing in common
+        let results = vec![
+            failure("https://a.example/1", CheckError::FetchFailed),
+            failure("https://b.example/2", CheckError::HttpError(503)),
+            CheckResult::success("https://c.example/3", 200, false),
+        ];
+        let correlation = Correlation::new(&results);
+        assert!(results.iter().all(|r| correlation.hint(r).is_none()));
+
+        // Too few failures for a hint
+        let results = &results[..2];
+        assert_eq!(Correlation::new(results).hint(&results[0]), None);
+    }
+}
@@ -0,0 +1,439 @@
+//! Broken-link crawling of configured pages
+//!
+//! A resource with a `crawl` table is an HTML page whose links are checked too:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://docs.example.com/install"
+//! crawl = { max_links = 30, allow_domains = ["cdn.example.com"] }
+//! ```
+//!
+//! Each run fetches the page, extracts the links of its `<a>`/`<link>` (`href`) and
+//! `<img>`/`<script>`/`<iframe>`/`<source>` (`src`) tags that point to the same origin
+//! or an allow-listed domain, and checks the first `max_links` of them with a HEAD
+//! request (depth 1, links are not followed further). Dead links make the page's
+//! availability check fail with all of them listed, so a page produces a single
+//! notification; while it keeps failing, links that were not in the last
+//! notification trigger a new one right away (see `alert::AlertStates::evaluate`).
+
+use crate::checker::{check_availability, BrokenLink, CheckError, CheckResult};
+use crate::clock;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use crate::run::Deadline;
+use futures::future;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use worker::*;
+
+/// Default number of links checked per page
+const DEFAULT_MAX_LINKS: usize = 50;
+
+/// Upper bound of `max_links`, the subrequest cap of the paid Workers plan
+const MAX_LINKS_LIMIT: usize = 1000;
+
+/// Number of broken links listed in a notification
+const MAX_LISTED_LINKS: usize = 10;
+
+/// Tags whose attribute holds a link
+const LINK_ATTRIBUTES: &[(&str, &str)] = &[
+    ("a", "href"),
+    ("link", "href"),
+    ("img", "src"),
+    ("script", "src"),
+    ("iframe", "src"),
+    ("source", "src"),
+];
+
+/// `<link rel>` values that name an origin to connect to rather than a resource
+const CONNECTION_HINTS: &[&str] = &["preconnect", "dns-prefetch"];
+
+/// Settings of a crawled page (`crawl = { ... }` on a resource)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crawl {
+    /// Number of links checked per run, in page order
+    #[serde(default = "default_max_links")]
+    pub max_links: usize,
+    /// Hosts besides the page's own origin whose links are checked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_domains: Vec<String>,
+}
+
+fn default_max_links() -> usize {
+    DEFAULT_MAX_LINKS
+}
+
+impl Default for Crawl {
+    fn default() -> Self {
+        Self {
+            max_links: DEFAULT_MAX_LINKS,
+            allow_domains: Vec::new(),
+        }
+    }
+}
+
+impl Crawl {
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        if !(1..=MAX_LINKS_LIMIT).contains(&self.max_links) {
+            return Err(format!(
+                "crawl.max_links of {} must be between 1 and {}",
+                url, MAX_LINKS_LIMIT
+            ));
+        }
+        if Url::parse(url).is_err() {
+            return Err(format!("Crawled page {} is not an absolute URL", url));
+        }
+        Ok(())
+    }
+
+    /// Whether a link is checked: same origin as the page or an allow-listed host
+    fn allows(&self, page: &Url, link: &Url) -> bool {
+        if link.origin() == page.origin() {
+            return true;
+        }
+        link.host_str().is_some_and(|host| {
+            self.allow_domains
+                .iter()
+                .any(|domain| host.eq_ignore_ascii_case(domain))
+        })
+    }
+}
+
+/// Attributes of a tag body such as `a class="x" href='/y'`, names lowercased
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, remaining) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let inner = &after[1..];
+                        let end = inner.find(quote).unwrap_or(inner.len());
+                        (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+                    }
+                    _ => {
+                        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                        after.split_at(end)
+                    }
+                };
+                rest = remaining.trim_start();
+                value.replace("&amp;", "&")
+            }
+            None => String::new(),
+        };
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+    }
+    attributes
+}
+
+/// Raw link targets of an HTML document, in document order
+fn raw_links(html: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = rest[..end].trim_end_matches('/');
+        rest = &rest[end..];
+
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        let Some(&(_, attribute)) = LINK_ATTRIBUTES.iter().find(|(t, _)| *t == name) else {
+            continue;
+        };
+        let attributes = attributes(&tag[name_end..]);
+        let is_hint = attributes.iter().any(|(n, v)| {
+            n == "rel"
+                && v.split_whitespace()
+                    .any(|rel| CONNECTION_HINTS.contains(&rel.to_ascii_lowercase().as_str()))
+        });
+        if is_hint {
+            continue;
+        }
+        if let Some((_, value)) = attributes.into_iter().find(|(n, _)| n == attribute) {
+            links.push(value);
+        }
+    }
+    links
+}
+
+/// Links of a page to check: absolute, http(s), allowed, without fragments or duplicates
+pub fn extract_links(html: &str, page: &Url, crawl: &Crawl) -> Vec<String> {
+    let mut seen = HashSet::new();
+    seen.insert(page.as_str().to_string());
+    raw_links(html)
+        .iter()
+        .map(|raw| raw.trim())
+        .filter(|raw| !raw.is_empty() && !raw.starts_with('#'))
+        .filter_map(|raw| page.join(raw).ok())
+        .filter(|link| matches!(link.scheme(), "http" | "https") && crawl.allows(page, link))
+        .map(|mut link| {
+            link.set_fragment(None);
+            link.to_string()
+        })
+        .filter(|link| seen.insert(link.clone()))
+        .collect()
+}
+
+/// Check a link with HEAD, retrying with GET if the server does not support HEAD
+async fn check_link(fetcher: &impl Fetcher, url: &str) -> CheckResult {
+    let result = check_availability(fetcher, url, Method::Head).await;
+    match result.error {
+        Some(CheckError::HttpError(405 | 501)) => {
+            check_availability(fetcher, url, Method::Get).await
+        }
+        _ => result,
+    }
+}
+
+/// Broken links of a page for notifications, at most `MAX_LISTED_LINKS` of them
+pub fn summary(links: &[BrokenLink]) -> Option<String> {
+    if links.is_empty() {
+        return None;
+    }
+    let mut lines: Vec<String> = links
+        .iter()
+        .take(MAX_LISTED_LINKS)
+        .map(|link| format!("{} ({})", link.url, link.error.description()))
+        .collect();
+    if links.len() > MAX_LISTED_LINKS {
+        lines.push(format!("and {} more", links.len() - MAX_LISTED_LINKS));
+    }
+    Some(lines.join("\n"))
+}
+
+/// Check a page and the first `max_links` of its links
+///
+/// Returns a single result for the page. Links are checked with the given
+/// concurrency and skipped once the deadline passes.
+pub async fn check(
+    fetcher: &impl Fetcher,
+    resource: &Resource,
+    crawl: &Crawl,
+    concurrency: usize,
+    deadline: Deadline,
+) -> CheckResult {
+    let url = resource.url.as_str();
+    console_log!("Crawling: {}", url);
+    let started = clock::now_ms();
+    let request = HttpRequest::new(Method::Get, url).with_header("Accept", "text/html");
+    let mut response = match fetcher.fetch(request).await {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    let status_code = response.status_code();
+    let latency_ms = Some(clock::now_ms().saturating_sub(started));
+    let failure = |error| {
+        let mut result = CheckResult::failure(url.to_string(), error);
+        result.latency_ms = latency_ms;
+        result
+    };
+    if !(200..300).contains(&status_code) {
+        return failure(CheckError::HttpError(status_code));
+    }
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => return failure(CheckError::from_body(&e)),
+    };
+    let Ok(page) = Url::parse(url) else {
+        return failure(CheckError::FetchFailed);
+    };
+
+    let mut links = extract_links(&String::from_utf8_lossy(&body), &page, crawl);
+    if links.len() > crawl.max_links {
+        console_log!(
+            "Page {} has {} links, checking the first {}",
+            url,
+            links.len(),
+            crawl.max_links
+        );
+        links.truncate(crawl.max_links);
+    }
+
+    let broken: Vec<BrokenLink> = stream::iter(links)
+        .map(|link| async move {
+            if deadline.expired() {
+                return None;
+            }
+            let result = check_link(fetcher, &link).await;
+            Some(result.error.map(|error| BrokenLink { url: link, error }))
+        })
+        .buffered(concurrency)
+        .filter_map(|checked| future::ready(checked.flatten()))
+        .collect()
+        .await;
+
+    if broken.is_empty() {
+        let mut result = CheckResult::success(url.to_string(), status_code, true);
+        result.latency_ms = latency_ms;
+        return result;
+    }
+    console_error!("✗ {} - {} broken links", url, broken.len());
+    let mut result = failure(CheckError::BrokenLinks(broken.len()));
+    result.broken_links = broken;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    const PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <link rel="stylesheet" href="/style.css">
+  <link rel="preconnect" href="https://fonts.example.net">
+  <script src='app.js?v=1&amp;b=2'></script>
+</head>
+<body>
+  <!-- <a href="/commented-out">old</a> -->
+  <a class="nav" HREF="/guide#install">Guide</a>
+  <a href="/guide">Guide again</a>
+  <a href=#top>Top</a>
+  <a href="mailto:docs@example.com">Mail</a>
+  <a href="https://github.com/org/repo">Source</a>
+  <a href="https://cdn.example.com/lib.js">CDN</a>
+  <img src="/missing.png" alt="">
+</body>
+</html>"##;
+
+    fn page() -> Url {
+        Url::parse("https://docs.example.com/docs/").unwrap()
+    }
+
+    fn crawl() -> Crawl {
+        Crawl {
+            allow_domains: vec!["cdn.example.com".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_extract_links() {
+        assert_eq!(
+            extract_links(PAGE, &page(), &crawl()),
+            vec![
+                "https://docs.example.com/style.css",
+                "https://docs.example.com/docs/app.js?v=1&b=2",
+                "https://docs.example.com/guide",
+                "https://cdn.example.com/lib.js",
+                "https://docs.example.com/missing.png",
+            ]
+        );
+        // Only the page's own origin without an allow list
+        assert_eq!(extract_links(PAGE, &page(), &Crawl::default()).len(), 4);
+    }
+
+    #[test]
+    fn test_attributes() {
+        assert_eq!(
+            attributes(r#" class="a b" data-x=1 hidden href = '/y' "#),
+            vec![
+                ("class".to_string(), "a b".to_string()),
+                ("data-x".to_string(), "1".to_string()),
+                ("hidden".to_string(), String::new()),
+                ("href".to_string(), "/y".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let fetcher = MockFetcher::new()
+            .respond(
+                "https://docs.example.com/docs/",
+                MockResponse::new(200, PAGE),
+            )
+            .respond(
+                "https://docs.example.com/style.css",
+                MockResponse::new(200, ""),
+            )
+            .respond(
+                "https://docs.example.com/docs/app.js?v=1&b=2",
+                MockResponse::new(200, ""),
+            )
+            .respond("https://docs.example.com/guide", MockResponse::new(200, ""))
+            .respond("https://cdn.example.com/lib.js", MockResponse::new(410, ""))
+            .respond(
+                "https://docs.example.com/missing.png",
+                MockResponse::new(404, ""),
+            );
+        let resource = Resource {
+            url: "https://docs.example.com/docs/".to_string(),
+            crawl: Some(crawl()),
+            ..Default::default()
+        };
+        let deadline = Deadline::new(clock::now_ms(), 25_000);
+
+        let result = block_on(check(&fetcher, &resource, &crawl(), 2, deadline));
+        assert_eq!(result.error, Some(CheckError::BrokenLinks(2)));
+        assert_eq!(
+            result.broken_links,
+            vec![
+                BrokenLink {
+                    url: "https://cdn.example.com/lib.js".to_string(),
+                    error: CheckError::HttpError(410),
+                },
+                BrokenLink {
+                    url: "https://docs.example.com/missing.png".to_string(),
+                    error: CheckError::HttpError(404),
+                },
+            ]
+        );
+        assert_eq!(result.description(), "Failed: 2 broken links");
+        let requests = fetcher.requests();
+        assert_eq!(requests.len(), 6);
+        assert!(requests[1..].iter().all(|r| r.method == Method::Head));
+
+        // Bounded by max_links
+        let limited = Crawl {
+            max_links: 3,
+            ..crawl()
+        };
+        let result = block_on(check(&fetcher, &resource, &limited, 2, deadline));
+        assert!(!result.has_problem());
+    }
+
+    #[test]
+    fn test_head_fallback() {
+        let fetcher = MockFetcher::new().respond(
+            "https://docs.example.com/download",
+            MockResponse::new(405, ""),
+        );
+        let result = block_on(check_link(&fetcher, "https://docs.example.com/download"));
+        assert_eq!(result.error, Some(CheckError::HttpError(405)));
+        let methods: Vec<Method> = fetcher.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(methods, vec![Method::Head, Method::Get]);
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(summary(&[]), None);
+        let links: Vec<BrokenLink> = (0..12)
+            .map(|i| BrokenLink {
+                url: format!("https://docs.example.com/{}", i),
+                error: CheckError::HttpError(404),
+            })
+            .collect();
+        let text = summary(&links).unwrap();
+        assert!(text.starts_with("https://docs.example.com/0 (HTTP error: 404)\n"));
+        assert!(text.ends_with("\nand 2 more"));
+    }
+}
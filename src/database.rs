@@ -0,0 +1,136 @@
+//! D1 database and its migrations
+//!
+//! Features that keep data in SQL share one optional D1 database bound as
+//! `LINKKIVAHTI_DB`: the state store with `STORAGE_BACKEND = "d1"` (see `store`)
+//! and the config tables (see `configdb`). Its schema is created and upgraded by
+//! the migrations below, applied in order on the first use in each isolate and
+//! recorded in `linkkivahti_migrations`; each migration runs with its record in
+//! one batch, so it is applied completely or not at all.
+//!
+//! Migrations are append-only: a schema change is a new entry at the end, never
+//! an edit of an applied one.
+
+use crate::clock;
+use crate::console::console_log;
+use std::sync::atomic::{AtomicBool, Ordering};
+use worker::*;
+
+/// Name of the D1 database binding in wrangler.toml
+pub const BINDING: &str = "LINKKIVAHTI_DB";
+
+/// Table recording the applied migrations
+const MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS linkkivahti_migrations \
+     (id INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at INTEGER NOT NULL)";
+
+/// A schema change
+pub struct Migration {
+    /// Position in `MIGRATIONS`, counting from 1
+    pub id: u32,
+    pub name: &'static str,
+    /// Statements applied together
+    pub statements: &'static [&'static str],
+}
+
+/// Schema of the database, oldest first
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        id: 1,
+        name: "state",
+        statements: &["CREATE TABLE IF NOT EXISTS linkkivahti_state \
+             (key TEXT PRIMARY KEY, value TEXT NOT NULL, expires_at INTEGER)"],
+    },
+    Migration {
+        id: 2,
+        name: "config",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS linkkivahti_config \
+             (id INTEGER PRIMARY KEY CHECK (id = 1), version TEXT NOT NULL, \
+              settings TEXT NOT NULL DEFAULT '{}', updated_at INTEGER NOT NULL)",
+            "CREATE TABLE IF NOT EXISTS linkkivahti_tenants \
+             (id TEXT PRIMARY KEY, position INTEGER NOT NULL, name TEXT, \
+              webhook_secret TEXT, webhook_service TEXT)",
+            "CREATE TABLE IF NOT EXISTS linkkivahti_resources \
+             (tenant TEXT NOT NULL DEFAULT '', position INTEGER NOT NULL, url TEXT NOT NULL, \
+              sri TEXT NOT NULL DEFAULT '', name TEXT, resource_group TEXT, owner TEXT, \
+              tags TEXT NOT NULL DEFAULT '[]', settings TEXT NOT NULL DEFAULT '{}', \
+              PRIMARY KEY (tenant, url))",
+        ],
+    },
+];
+
+/// Whether this isolate brought the schema up to date
+static MIGRATED: AtomicBool = AtomicBool::new(false);
+
+/// Migrations not applied yet, given the last applied one
+fn pending(applied: Option<u32>) -> &'static [Migration] {
+    let applied = applied.unwrap_or(0) as usize;
+    MIGRATIONS.get(applied..).unwrap_or_default()
+}
+
+/// Open the database, or `None` if it is not bound
+pub fn open(env: &Env) -> Option<D1Database> {
+    env.d1(BINDING).ok()
+}
+
+/// Open the database, failing with a descriptive error if it is not bound
+pub fn require(env: &Env) -> Result<D1Database> {
+    open(env).ok_or_else(|| Error::RustError(format!("{} D1 binding not configured", BINDING)))
+}
+
+/// Apply the pending migrations, once per isolate
+pub async fn migrate(db: &D1Database) -> Result<()> {
+    if MIGRATED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    db.prepare(MIGRATIONS_TABLE).run().await?;
+    let applied = db
+        .prepare("SELECT max(id) AS id FROM linkkivahti_migrations")
+        .first::<Option<u32>>(Some("id"))
+        .await?
+        .flatten();
+
+    for migration in pending(applied) {
+        let mut batch: Vec<D1PreparedStatement> = migration
+            .statements
+            .iter()
+            .map(|sql| db.prepare(*sql))
+            .collect();
+        batch.push(
+            db.prepare(
+                "INSERT OR IGNORE INTO linkkivahti_migrations (id, name, applied_at) \
+                 VALUES (?1, ?2, ?3)",
+            )
+            .bind(&[
+                migration.id.into(),
+                migration.name.into(),
+                (clock::now_ms() as f64).into(),
+            ])?,
+        );
+        db.batch(batch).await?;
+        console_log!(
+            "Applied database migration {} ({})",
+            migration.id,
+            migration.name
+        );
+    }
+    MIGRATED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.id as usize, index + 1, "{}", migration.name);
+            assert!(!migration.statements.is_empty());
+        }
+        assert_eq!(pending(None).len(), MIGRATIONS.len());
+        assert_eq!(pending(Some(1))[0].name, "config");
+        assert!(pending(Some(MIGRATIONS.len() as u32)).is_empty());
+        // A database migrated by a newer version of the worker
+        assert!(pending(Some(99)).is_empty());
+    }
+}
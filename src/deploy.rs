@@ -0,0 +1,293 @@
+//! Self-verification of a new deployment
+//!
+//! Resources marked `bootstrap = true` are the ones a release must not break,
+//! e.g. the loader script every page includes. When a new version of the worker
+//! handles its first request or cron run, those resources are checked right
+//! away, through the regular pipeline, instead of at the next cron tick, and a
+//! "🚀 Deployment" notice reports the version with the outcome.
+//!
+//! A version is identified by the `CF_VERSION_METADATA` binding
+//! (`[version_metadata]` in wrangler.toml) when it is configured, otherwise by the
+//! crate version and a digest of the compiled config.toml. Each isolate compares
+//! it once with the last version seen in KV; isolates of the same new version
+//! starting at once may both report it, as KV is eventually consistent.
+//!
+//! While monitoring is disabled (see `control`) nothing is checked or reported,
+//! and the version stays unclaimed: the first invocation after monitoring is
+//! enabled again verifies it.
+
+use crate::clock;
+use crate::config;
+use crate::console::{console_error, console_log};
+use crate::control::{self, Control};
+use crate::maintenance;
+use crate::notify;
+use crate::run::{Deadline, RunLock, RunSummary};
+use crate::sri;
+use crate::store::Store;
+use crate::sync;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use worker::{Env, WorkerVersionMetadata};
+
+/// KV key of the last deployed version seen (global across tenants)
+const DEPLOYED_KEY: &str = "deploy/version";
+
+/// Binding of the version metadata in wrangler.toml
+const VERSION_BINDING: &str = "CF_VERSION_METADATA";
+
+thread_local! {
+    /// Whether this isolate already compared its version with KV
+    static SEEN: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Version of the running worker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deployment {
+    /// Version ID from the version metadata, or `{crate version}-{config digest}`
+    pub id: String,
+    /// Tag given at upload (`wrangler versions upload --tag`), if any
+    pub tag: Option<String>,
+    /// Crate version
+    pub version: String,
+}
+
+impl Deployment {
+    /// Version from the version metadata binding, falling back to the build
+    fn new(metadata: Option<(String, String)>) -> Self {
+        let version = env!("CARGO_PKG_VERSION").to_string();
+        match metadata {
+            Some((id, tag)) => Self {
+                id,
+                tag: (!tag.is_empty()).then_some(tag),
+                version,
+            },
+            None => {
+                let digest = sri::sha256_hex(config::CONFIG_TOML.as_bytes());
+                Self {
+                    id: format!("{}-{}", version, &digest[..12]),
+                    tag: None,
+                    version,
+                }
+            }
+        }
+    }
+
+    /// Version of the running worker
+    pub fn current(env: &Env) -> Self {
+        let metadata = env
+            .get_binding::<WorkerVersionMetadata>(VERSION_BINDING)
+            .ok()
+            .map(|m| (m.id(), m.tag()));
+        Self::new(metadata)
+    }
+
+    /// Short name, e.g. `0.1.0 (release-42, 7b0d6c1e)`
+    pub fn label(&self) -> String {
+        let id = self.id.get(..8).unwrap_or(&self.id);
+        match &self.tag {
+            Some(tag) => format!("{} ({}, {})", self.version, tag, id),
+            None => format!("{} ({})", self.version, id),
+        }
+    }
+}
+
+/// Last deployed version seen, as stored in KV
+#[derive(Debug, Serialize, Deserialize)]
+struct Deployed {
+    id: String,
+    /// When the version was first seen (milliseconds since the Unix epoch)
+    at: u64,
+}
+
+/// Outcome of verifying the bootstrap resources of a scope
+enum Verification {
+    Checked(RunSummary),
+    /// Another run holds the scope's lock and checks its resources anyway
+    Busy,
+}
+
+/// Deployment notice: the version, then one line per scope with bootstrap resources
+fn report(deployment: &Deployment, scopes: &[(String, Verification)]) -> String {
+    let mut lines = vec![format!("Version {} is live.", deployment.label())];
+    for (label, verification) in scopes {
+        let line = match verification {
+            Verification::Checked(summary) if summary.firing.is_empty() => {
+                format!("{}: bootstrap resources {}", label, summary.describe())
+            }
+            Verification::Checked(summary) => format!(
+                "{}: bootstrap resources {}; failing: {}",
+                label,
+                summary.describe(),
+                summary.firing.join(", ")
+            ),
+            Verification::Busy => {
+                format!("{}: already being checked by another run", label)
+            }
+        };
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Whether any bootstrap resource failed its check
+fn failed(scopes: &[(String, Verification)]) -> bool {
+    scopes.iter().any(|(_, verification)| match verification {
+        Verification::Checked(summary) => summary.problems > 0,
+        Verification::Busy => false,
+    })
+}
+
+/// Whether this isolate still has to compare its version with KV, marking it
+/// as done unless monitoring is disabled
+fn claim(control: &Control) -> bool {
+    control.enabled && !SEEN.with(|seen| seen.replace(true))
+}
+
+/// Check the bootstrap resources and report the version, once per new deployment
+///
+/// Only the first invocation of an isolate with monitoring enabled looks at KV.
+/// Nothing happens without the KV namespace or without bootstrap resources.
+pub async fn verify_if_new(env: Env) {
+    if SEEN.get() {
+        return;
+    }
+    let control = control::load(&env).await;
+    if !claim(&control) {
+        return;
+    }
+    let Some(store) = Store::from_env(&env) else {
+        return;
+    };
+    let config = sync::active_config(&env).await;
+    let scopes = Scope::all(&config);
+    if !scopes
+        .iter()
+        .any(|s| s.resources().iter().any(|r| r.bootstrap))
+    {
+        return;
+    }
+
+    let deployment = Deployment::current(&env);
+    match store.get::<Deployed>(DEPLOYED_KEY).await {
+        Ok(Some(deployed)) if deployed.id == deployment.id => return,
+        Ok(_) => {}
+        Err(e) => {
+            console_error!("Failed to read deployed version: {}", e);
+            return;
+        }
+    }
+    // Claim the version before checking, so later isolates leave it alone
+    let deployed = Deployed {
+        id: deployment.id.clone(),
+        at: clock::now_ms(),
+    };
+    if let Err(e) = store.put(DEPLOYED_KEY, &deployed).await {
+        console_error!("Failed to store deployed version: {}", e);
+        return;
+    }
+
+    console_log!("🚀 New deployment {}", deployment.label());
+    let deadline = Deadline::from_env(&env);
+    let mut outcomes = Vec::new();
+    for scope in &scopes {
+        let now = clock::now_ms();
+        let resources: Vec<_> = scope
+            .resources()
+            .iter()
+            .filter(|r| r.bootstrap && maintenance::paused(r, now).is_none())
+            .collect();
+        if resources.is_empty() {
+            continue;
+        }
+        let verification = match RunLock::acquire(&env, scope, &deadline).await {
+            Ok(lock) => {
+                let summary = crate::check_scope(&env, scope, resources, deadline).await;
+                lock.release(&env, scope).await;
+                Verification::Checked(summary)
+            }
+            Err(_) => Verification::Busy,
+        };
+        outcomes.push((scope.label().to_string(), verification));
+    }
+
+    let message = report(&deployment, &outcomes);
+    let root = Scope::root(&config);
+    if let Err(e) =
+        notify::send_deployment_notification(&env, &root, &deployment, &message, failed(&outcomes))
+            .await
+    {
+        console_error!("Failed to send deployment notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deployment() {
+        let tagged = Deployment::new(Some((
+            "7b0d6c1e-44a2-4c1b-9d5e-2f0a1b3c4d5e".to_string(),
+            "release-42".to_string(),
+        )));
+        assert_eq!(
+            tagged.label(),
+            format!("{} (release-42, 7b0d6c1e)", env!("CARGO_PKG_VERSION"))
+        );
+        let untagged = Deployment::new(Some(("7b0d6c1e-44a2".to_string(), String::new())));
+        assert_eq!(untagged.tag, None);
+
+        // Without the binding, a changed config.toml is a new version too
+        let build = Deployment::new(None);
+        let digest = sri::sha256_hex(config::CONFIG_TOML.as_bytes());
+        assert_eq!(
+            build.id,
+            format!("{}-{}", env!("CARGO_PKG_VERSION"), &digest[..12])
+        );
+        assert_eq!(build, Deployment::new(None));
+    }
+
+    #[test]
+    fn test_claim() {
+        let disabled = Control {
+            enabled: false,
+            ..Control::default()
+        };
+        assert!(!claim(&disabled));
+        assert!(!SEEN.get());
+        // Once monitoring is enabled again, the isolate claims its check once
+        assert!(claim(&Control::default()));
+        assert!(!claim(&Control::default()));
+    }
+
+    #[test]
+    fn test_report() {
+        let deployment = Deployment {
+            id: "7b0d6c1e-44a2".to_string(),
+            tag: None,
+            version: "1.2.0".to_string(),
+        };
+        let outcomes = vec![
+            (
+                "default".to_string(),
+                Verification::Checked(RunSummary {
+                    checked: 2,
+                    problems: 1,
+                    firing: vec!["https://cdn.example.com/loader.js".to_string()],
+                    ..Default::default()
+                }),
+            ),
+            ("tenant acme".to_string(), Verification::Busy),
+        ];
+        assert_eq!(
+            report(&deployment, &outcomes),
+            "Version 1.2.0 (7b0d6c1e) is live.\n\
+             default: bootstrap resources 1/2 successful, 1 failed; failing: https://cdn.example.com/loader.js\n\
+             tenant acme: already being checked by another run"
+        );
+        assert!(failed(&outcomes));
+        assert!(!failed(&outcomes[1..]));
+    }
+}
@@ -0,0 +1,494 @@
+//! Discord interactions: alert buttons and `/linkstatus` (`POST /discord/interactions`)
+//!
+//! With `DISCORD_PUBLIC_KEY` set (the public key of a Discord application whose
+//! Interactions Endpoint URL points at `/discord/interactions`), firing alerts
+//! sent to Discord carry the same "Re-check now", "Silence 1h" and "Ack" buttons
+//! as Slack (see `action::run_button`). Discord only shows interactive buttons on
+//! messages of webhooks owned by the application, so the alert webhook must be
+//! created by the application (e.g. through its OAuth2 `webhook.incoming` flow).
+//!
+//! The `/linkstatus` command answers with the latest results of the default
+//! scope, or of the tenant named in its `tenant` option. The command has to be
+//! registered once with Discord's API.
+//!
+//! Discord signs every request with the application's Ed25519 key over the
+//! timestamp and body; requests with a bad signature or a timestamp more than
+//! five minutes off are refused. Button clicks are acknowledged right away and
+//! the outcome is posted as a follow-up message once the action has run.
+
+use crate::action::{self, Actor, AlertRef};
+use crate::alert::AlertStream;
+use crate::clock;
+use crate::config::{self, Config};
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse, WorkerFetcher};
+use crate::sri;
+use crate::status;
+use crate::tenant::Scope;
+use crate::trace;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use worker::*;
+
+/// Largest accepted difference between Discord's request timestamp and our clock
+const MAX_CLOCK_SKEW_SECS: u64 = 5 * 60;
+
+/// Longest `custom_id` Discord accepts on a button
+const MAX_CUSTOM_ID_LEN: usize = 100;
+
+const API_BASE: &str = "https://discord.com/api/v10";
+
+// Interaction types
+const PING: u8 = 1;
+const APPLICATION_COMMAND: u8 = 2;
+const MESSAGE_COMPONENT: u8 = 3;
+
+// Interaction response types
+const PONG: u8 = 1;
+const CHANNEL_MESSAGE_WITH_SOURCE: u8 = 4;
+const DEFERRED_UPDATE_MESSAGE: u8 = 6;
+
+/// Public key of the Discord application from `DISCORD_PUBLIC_KEY`
+fn public_key(env: &Env) -> Option<String> {
+    config::setting(env, "DISCORD_PUBLIC_KEY")
+}
+
+/// Short hash of a resource URL identifying it in button IDs
+fn url_hash(url: &str) -> String {
+    sri::sha256_hex(url.as_bytes())[..16].to_string()
+}
+
+/// Alert reference of the buttons of an alert, `{stream}|{url hash}[|{tenant}]`,
+/// if Discord interactivity is configured
+///
+/// Button IDs are limited to 100 characters, too short for URLs, so the resource
+/// is named by a hash of its configured URL.
+pub fn button_ref(env: &Env, scope: &Scope<'_>, url: &str, stream: AlertStream) -> Option<String> {
+    public_key(env)?;
+    let resource = scope.find_resource(url)?;
+    let mut reference = format!("{}|{}", stream, url_hash(&resource.url));
+    if let Some(tenant) = scope.id() {
+        reference.push('|');
+        reference.push_str(tenant);
+    }
+    let longest_action = action::BUTTONS.iter().map(|(id, _)| id.len()).max()?;
+    (longest_action + 1 + reference.len() <= MAX_CUSTOM_ID_LEN).then_some(reference)
+}
+
+/// Button ID of an action on an alert
+pub fn custom_id(action_id: &str, reference: &str) -> String {
+    format!("{}|{}", action_id, reference)
+}
+
+/// Webhook URL asking Discord to render the message's components
+pub fn with_components(webhook_url: &str) -> String {
+    let separator = if webhook_url.contains('?') { '&' } else { '?' };
+    format!("{}{}with_components=true", webhook_url, separator)
+}
+
+/// Split a button ID into its action and the alert it refers to
+fn parse_custom_id(custom_id: &str) -> Option<(&str, AlertStream, &str, Option<&str>)> {
+    let mut parts = custom_id.splitn(4, '|');
+    let action_id = parts.next()?;
+    let stream = parts.next()?;
+    let stream = AlertStream::ALL
+        .into_iter()
+        .find(|s| s.as_str() == stream)?;
+    let hash = parts.next()?;
+    Some((action_id, stream, hash, parts.next()))
+}
+
+/// Resolve the alert of a button ID against the current config
+fn resolve_alert(
+    config: &Config,
+    stream: AlertStream,
+    hash: &str,
+    tenant: Option<&str>,
+) -> Option<AlertRef> {
+    let scope = match tenant {
+        Some(id) => Scope::tenant(config, id)?,
+        None => Scope::root(config),
+    };
+    let resource = scope
+        .resources()
+        .iter()
+        .find(|r| url_hash(&r.url) == hash)?;
+    Some(AlertRef::new(&scope, &resource.url, stream))
+}
+
+/// Verify Discord's `X-Signature-Ed25519` over `{timestamp}{body}`
+pub fn verify_signature(
+    public_key: &str,
+    timestamp: &str,
+    body: &str,
+    signature: &str,
+    now_secs: u64,
+) -> bool {
+    let Ok(sent) = timestamp.parse::<u64>() else {
+        return false;
+    };
+    if now_secs.abs_diff(sent) > MAX_CLOCK_SKEW_SECS {
+        return false;
+    }
+    let Some(key) = sri::decode_hex(public_key.trim())
+        .and_then(|b| <[u8; 32]>::try_from(b).ok())
+        .and_then(|b| VerifyingKey::from_bytes(&b).ok())
+    else {
+        return false;
+    };
+    let Some(signature) = sri::decode_hex(signature).and_then(|b| <[u8; 64]>::try_from(b).ok())
+    else {
+        return false;
+    };
+    let message = format!("{}{}", timestamp, body);
+    key.verify(message.as_bytes(), &Signature::from_bytes(&signature))
+        .is_ok()
+}
+
+/// Read the body of a request from Discord, or `None` if it is not validly signed
+async fn verified_body(env: &Env, req: &mut Request) -> Result<Option<String>> {
+    let Some(key) = public_key(env) else {
+        return Ok(None);
+    };
+    let timestamp = req.headers().get("X-Signature-Timestamp")?;
+    let signature = req.headers().get("X-Signature-Ed25519")?;
+    let body = req.text().await?;
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        return Ok(None);
+    };
+    let valid = verify_signature(&key, &timestamp, &body, &signature, clock::now_ms() / 1000);
+    Ok(valid.then_some(body))
+}
+
+/// An interaction, reduced to what the buttons and commands need
+#[derive(Debug, Deserialize)]
+struct Interaction {
+    #[serde(rename = "type")]
+    kind: u8,
+    #[serde(default)]
+    application_id: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    data: Option<InteractionData>,
+    /// Member who interacted, in a server channel
+    #[serde(default)]
+    member: Option<Member>,
+    /// User who interacted, in a direct message
+    #[serde(default)]
+    user: Option<DiscordUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InteractionData {
+    /// Command name
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    options: Vec<CommandOption>,
+    /// Button ID
+    #[serde(default)]
+    custom_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandOption {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Member {
+    user: DiscordUser,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscordUser {
+    id: String,
+    #[serde(default)]
+    username: String,
+}
+
+impl Interaction {
+    fn user(&self) -> Option<&DiscordUser> {
+        self.member.as_ref().map(|m| &m.user).or(self.user.as_ref())
+    }
+
+    /// String value of a command option
+    fn option(&self, name: &str) -> Option<&str> {
+        self.data
+            .as_ref()?
+            .options
+            .iter()
+            .find(|o| o.name == name)?
+            .value
+            .as_str()
+    }
+}
+
+/// Answer `/linkstatus` with the latest results of a scope
+async fn linkstatus(env: &Env, config: &Config, tenant: Option<&str>) -> String {
+    let scope = match tenant {
+        Some(id) => match Scope::tenant(config, id) {
+            Some(scope) => scope,
+            None => return format!("⚠️ Unknown tenant '{}'", id),
+        },
+        None => Scope::root(config),
+    };
+    status::chat_summary(env, &scope).await
+}
+
+/// Interaction response showing a message; mentions in it do not ping
+fn message_response(content: &str) -> serde_json::Value {
+    json!({
+        "type": CHANNEL_MESSAGE_WITH_SOURCE,
+        "data": {"content": content, "allowed_mentions": {"parse": []}},
+    })
+}
+
+/// Post a follow-up message to an interaction
+async fn post_followup(
+    fetcher: &impl Fetcher,
+    application_id: &str,
+    token: &str,
+    content: &str,
+) -> Result<()> {
+    let url = format!("{}/webhooks/{}/{}", API_BASE, application_id, token);
+    let body = json!({"content": content, "allowed_mentions": {"parse": []}});
+    let response = fetcher
+        .fetch(HttpRequest::post_json(url, body.to_string()))
+        .await?;
+    match response.status_code() {
+        200..=299 => Ok(()),
+        status => Err(Error::RustError(format!(
+            "Discord follow-up returned HTTP {}",
+            status
+        ))),
+    }
+}
+
+/// Run a clicked button's action and post the outcome
+async fn respond(env: Env, config: Arc<Config>, interaction: Interaction) {
+    let Some(user) = interaction.user() else {
+        return;
+    };
+    let Some(custom_id) = interaction
+        .data
+        .as_ref()
+        .and_then(|d| d.custom_id.as_deref())
+    else {
+        return;
+    };
+    let actor = Actor {
+        mention: format!("<@{}>", user.id),
+        principal: format!("discord:{}", user.username),
+        service: "Discord",
+    };
+    console_log!("Discord action {} by {}", custom_id, actor.principal);
+    let line = match parse_custom_id(custom_id) {
+        Some((action_id, stream, hash, tenant)) => {
+            match resolve_alert(&config, stream, hash, tenant) {
+                Some(alert) => action::run_button(&env, &config, action_id, &alert, &actor).await,
+                None => "⚠️ The resource of this alert is no longer configured".to_string(),
+            }
+        }
+        None => format!("⚠️ Unknown alert for {}", actor.mention),
+    };
+    let sent = post_followup(
+        &WorkerFetcher,
+        &interaction.application_id,
+        &interaction.token,
+        &line,
+    );
+    if let Err(e) = sent.await {
+        console_error!("Failed to post Discord follow-up: {}", e);
+    }
+}
+
+/// Handle `POST /discord/interactions`: verify the request, answer pings and
+/// commands, and run button actions after responding
+pub async fn handle_interaction(
+    env: &Env,
+    config: Arc<Config>,
+    ctx: &Context,
+    mut req: Request,
+) -> Result<Response> {
+    let Some(body) = verified_body(env, &mut req).await? else {
+        return Response::error("Invalid Discord signature", 401);
+    };
+    let Ok(interaction) = serde_json::from_str::<Interaction>(&body) else {
+        return Response::error("Invalid interaction payload", 400);
+    };
+    match interaction.kind {
+        PING => Response::from_json(&json!({"type": PONG})),
+        APPLICATION_COMMAND => {
+            let name = interaction.data.as_ref().and_then(|d| d.name.as_deref());
+            let content = match name {
+                Some("linkstatus") => linkstatus(env, &config, interaction.option("tenant")).await,
+                other => format!("⚠️ Unknown command '{}'", other.unwrap_or_default()),
+            };
+            Response::from_json(&message_response(&content))
+        }
+        MESSAGE_COMPONENT => {
+            ctx.wait_until(trace::in_current_run(respond(
+                env.clone(),
+                config,
+                interaction,
+            )));
+            Response::from_json(&json!({"type": DEFERRED_UPDATE_MESSAGE}))
+        }
+        _ => Response::error("Unsupported interaction type", 400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://example.com/a.js"
+            sri = "sha384-a"
+
+            [[resources]]
+            url = "https://example.com/b.js"
+            sri = "sha384-b"
+
+            [[tenants]]
+            id = "web"
+
+            [[tenants.resources]]
+            url = "https://example.com/c.js"
+            sri = "sha384-c"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let public_key = sri::encode_hex(key.verifying_key().as_bytes());
+        let body = r#"{"type":1}"#;
+        let signature = sri::encode_hex(
+            &key.sign(format!("1700000000{}", body).as_bytes())
+                .to_bytes(),
+        );
+        assert!(verify_signature(
+            &public_key,
+            "1700000000",
+            body,
+            &signature,
+            1700000000 + 60
+        ));
+
+        // Replayed too late, tampered, signed with another key, or not hex
+        assert!(!verify_signature(
+            &public_key,
+            "1700000000",
+            body,
+            &signature,
+            1700000000 + 600
+        ));
+        assert!(!verify_signature(
+            &public_key,
+            "1700000000",
+            r#"{"type":2}"#,
+            &signature,
+            1700000000
+        ));
+        let other = sri::encode_hex(SigningKey::from_bytes(&[8; 32]).verifying_key().as_bytes());
+        assert!(!verify_signature(
+            &other,
+            "1700000000",
+            body,
+            &signature,
+            1700000000
+        ));
+        assert!(!verify_signature(
+            &public_key,
+            "1700000000",
+            body,
+            "zz",
+            1700000000
+        ));
+    }
+
+    #[test]
+    fn test_custom_id() {
+        let config = config();
+        let scope = Scope::tenant(&config, "web").unwrap();
+        let reference = format!("integrity|{}|web", url_hash("https://example.com/c.js"));
+        let id = custom_id("silence_1h", &reference);
+        assert!(id.len() <= MAX_CUSTOM_ID_LEN);
+
+        let (action_id, stream, hash, tenant) = parse_custom_id(&id).unwrap();
+        assert_eq!(action_id, "silence_1h");
+        assert_eq!(stream, AlertStream::Integrity);
+        assert_eq!(tenant, Some("web"));
+        assert_eq!(
+            resolve_alert(&config, stream, hash, tenant),
+            Some(AlertRef::new(
+                &scope,
+                "https://example.com/c.js",
+                AlertStream::Integrity
+            ))
+        );
+        // The hash only resolves within its own scope
+        assert_eq!(resolve_alert(&config, stream, hash, None), None);
+        assert!(parse_custom_id("ack|nonsense|abc").is_none());
+
+        assert_eq!(
+            with_components("https://discord.com/api/webhooks/1/abc"),
+            "https://discord.com/api/webhooks/1/abc?with_components=true"
+        );
+        assert_eq!(
+            with_components("https://discord.com/api/webhooks/1/abc?wait=true"),
+            "https://discord.com/api/webhooks/1/abc?wait=true&with_components=true"
+        );
+    }
+
+    #[test]
+    fn test_parse_interaction() {
+        let interaction: Interaction = serde_json::from_value(json!({
+            "type": APPLICATION_COMMAND,
+            "application_id": "123",
+            "token": "tok",
+            "data": {"name": "linkstatus", "options": [{"name": "tenant", "type": 3, "value": "web"}]},
+            "member": {"user": {"id": "42", "username": "alice"}},
+        }))
+        .unwrap();
+        assert_eq!(interaction.option("tenant"), Some("web"));
+        assert_eq!(interaction.user().unwrap().username, "alice");
+
+        let ping: Interaction = serde_json::from_str(r#"{"type":1}"#).unwrap();
+        assert_eq!(ping.kind, PING);
+        assert!(ping.user().is_none());
+    }
+
+    #[test]
+    fn test_post_followup() {
+        let url = "https://discord.com/api/v10/webhooks/123/tok";
+        let fetcher = MockFetcher::new().respond(url, MockResponse::new(200, "{}"));
+        futures::executor::block_on(post_followup(&fetcher, "123", "tok", "👀 Acknowledged"))
+            .unwrap();
+        let requests = fetcher.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0]
+            .body
+            .as_deref()
+            .unwrap()
+            .contains("Acknowledged"));
+
+        let failing = MockFetcher::new().respond(url, MockResponse::new(404, "Unknown Webhook"));
+        assert!(futures::executor::block_on(post_followup(&failing, "123", "tok", "x")).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Content-Encoding mismatches and double compression
+//!
+//! A recurring CDN misconfiguration compresses a response twice, or labels it
+//! with the wrong `Content-Encoding`. Some clients then save compressed bytes, or
+//! decompress a `.tar.gz` into a plain tar, while a byte hash either still passes
+//! or fails without saying why. Every body the plain check reads is inspected:
+//!
+//! - a compressed file (`.gz`, `.tgz`, `.zst` or `.br` URL, a gzip or zstd
+//!   `Content-Type`, or gzip/zstd `expected_magic`) served with a
+//!   `Content-Encoding` other than `identity`
+//! - a body that is still gzip or zstd after the runtime decoded its
+//!   `Content-Encoding`: double compression
+//! - gzip or zstd bytes served as text (`text/*`, JavaScript, JSON, XML, SVG)
+//!   without a `Content-Encoding`
+//! - a `Content-Encoding` the HTTP registry does not know
+//!
+//! The Workers runtime decodes `gzip`, `deflate` and `br` encodings before the
+//! body is read, so the body seen here is what a decoding client receives.
+//! Binary downloads without a compressed URL or type are not second-guessed.
+
+/// Leading bytes of a gzip stream
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// Leading bytes of a zstd frame
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+
+/// File extensions of compressed files
+const COMPRESSED_EXTENSIONS: &[&str] = &[".gz", ".tgz", ".zst", ".br"];
+
+/// Content types of compressed files
+const COMPRESSED_TYPES: &[&str] = &[
+    "application/gzip",
+    "application/x-gzip",
+    "application/zstd",
+    "application/x-brotli",
+];
+
+/// Registered content codings
+const KNOWN_CODINGS: &[&str] = &[
+    "identity",
+    "gzip",
+    "x-gzip",
+    "deflate",
+    "br",
+    "zstd",
+    "compress",
+    "x-compress",
+    "dcb",
+    "dcz",
+    "aes128gcm",
+    "exi",
+    "pack200-gzip",
+];
+
+/// Compression format at the start of a body, if any
+fn compression(body: &[u8]) -> Option<&'static str> {
+    if body.starts_with(GZIP_MAGIC) {
+        Some("gzip")
+    } else if body.starts_with(ZSTD_MAGIC) {
+        Some("zstd")
+    } else {
+        None
+    }
+}
+
+/// Whether a content type is text a browser renders or runs
+fn is_text_type(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || ["javascript", "json", "xml", "svg"]
+            .iter()
+            .any(|kind| content_type.contains(kind))
+}
+
+/// Why a response's encoding looks wrong, `None` if it looks consistent
+///
+/// `expected_magic` is the decoded `expected_magic` of the resource, which marks
+/// it as a compressed file when it starts with a gzip or zstd header.
+pub fn mismatch(
+    url: &str,
+    content_encoding: Option<&str>,
+    content_type: Option<&str>,
+    body: &[u8],
+    expected_magic: Option<&[u8]>,
+) -> Option<String> {
+    let codings: Vec<String> = content_encoding
+        .unwrap_or_default()
+        .split(',')
+        .map(|c| c.trim().to_ascii_lowercase())
+        .filter(|c| !c.is_empty() && c != "identity")
+        .collect();
+    if let Some(unknown) = codings
+        .iter()
+        .find(|c| !KNOWN_CODINGS.contains(&c.as_str()))
+    {
+        return Some(format!("Unknown Content-Encoding '{}'", unknown));
+    }
+    let content_type = content_type
+        .unwrap_or_default()
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let compressed_file = COMPRESSED_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+        || COMPRESSED_TYPES.contains(&content_type.as_str())
+        || expected_magic.and_then(compression).is_some();
+
+    let encoding = codings.join(", ");
+    if compressed_file && !codings.is_empty() {
+        return Some(format!(
+            "Compressed file served with Content-Encoding: {}; decoding clients save a different file",
+            encoding
+        ));
+    }
+    let format = compression(body)?;
+    if compressed_file {
+        return None;
+    }
+    if !codings.is_empty() {
+        return Some(format!(
+            "Double compression: body is still {} after decoding Content-Encoding: {}",
+            format, encoding
+        ));
+    }
+    is_text_type(&content_type).then(|| {
+        format!(
+            "{} bytes served as {} without Content-Encoding",
+            format, content_type
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GZIP: &[u8] = b"\x1f\x8b\x08\x00rest";
+
+    #[test]
+    fn test_consistent_responses() {
+        let js = Some("text/javascript; charset=utf-8");
+        assert_eq!(
+            mismatch("https://a.example/app.js", Some("gzip"), js, b"let a", None),
+            None
+        );
+        assert_eq!(
+            mismatch("https://a.example/app.js", None, js, b"let a", None),
+            None
+        );
+        // Compressed downloads served as they are
+        assert_eq!(
+            mismatch("https://a.example/x.tar.gz", None, None, GZIP, None),
+            None
+        );
+        assert_eq!(
+            mismatch(
+                "https://a.example/dl?id=1",
+                Some("identity"),
+                Some("application/octet-stream"),
+                GZIP,
+                None
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mismatches() {
+        assert_eq!(
+            mismatch("https://a.example/x.tgz?v=1", Some("gzip"), None, b"ustar", None),
+            Some("Compressed file served with Content-Encoding: gzip; decoding clients save a different file".to_string())
+        );
+        assert_eq!(
+            mismatch("https://a.example/dl", Some("br"), None, b"ustar", Some(GZIP_MAGIC)),
+            Some("Compressed file served with Content-Encoding: br; decoding clients save a different file".to_string())
+        );
+        assert_eq!(
+            mismatch(
+                "https://a.example/app.js",
+                Some("gzip"),
+                Some("text/javascript"),
+                GZIP,
+                None
+            ),
+            Some(
+                "Double compression: body is still gzip after decoding Content-Encoding: gzip"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            mismatch(
+                "https://a.example/app.js",
+                None,
+                Some("application/javascript"),
+                GZIP,
+                None
+            ),
+            Some(
+                "gzip bytes served as application/javascript without Content-Encoding".to_string()
+            )
+        );
+        assert_eq!(
+            mismatch(
+                "https://a.example/app.js",
+                Some("gzip, snappy"),
+                None,
+                b"",
+                None
+            ),
+            Some("Unknown Content-Encoding 'snappy'".to_string())
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Emergency config overrides from environment variables
+//!
+//! When a broken resource entry needs to change before a fixed config can be
+//! deployed or synced, individual values can be overridden with variables or
+//! secrets set in the dashboard or with `wrangler secret put`:
+//!
+//! - `OVERRIDE_RESOURCE_{n}_SRI`: SRI hash of the n-th resource of the default
+//!   scope, counting `[[resources]]` entries from 1
+//! - `DISABLE_RESOURCE`: URLs to stop checking in every scope, separated by commas
+//!   or whitespace
+//!
+//! Overrides apply on top of the active config (synced, else runtime or compiled-in);
+//! approved SRI changes (see `approval`) still take precedence over an overridden
+//! hash. The effective config and the overrides in force are shown by
+//! `GET /config/export`.
+
+use crate::config::{self, Config};
+use crate::console::console_error;
+use crate::sri::SriHash;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use worker::*;
+
+/// Setting listing resources to stop checking
+const DISABLE_SETTING: &str = "DISABLE_RESOURCE";
+
+/// Config with overrides applied, cached per isolate with its base config and overrides
+#[allow(clippy::type_complexity)]
+static EFFECTIVE: Mutex<Option<(Arc<Config>, Vec<Override>, Arc<Config>)>> = Mutex::new(None);
+
+/// An override in force
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Override {
+    /// Replace the SRI hash of a resource of the default scope
+    Sri {
+        setting: String,
+        url: String,
+        sri: String,
+    },
+    /// Stop checking a resource
+    Disable { setting: String, url: String },
+}
+
+/// Name of the setting overriding the SRI hash of the resource at `index`
+fn sri_setting(index: usize) -> String {
+    format!("OVERRIDE_RESOURCE_{}_SRI", index + 1)
+}
+
+/// Overrides set for a config, looking settings up with `setting`
+///
+/// Invalid values are logged and ignored, so a typo in an emergency override
+/// does not take down the checks.
+pub fn collect(config: &Config, setting: impl Fn(&str) -> Option<String>) -> Vec<Override> {
+    let mut overrides = Vec::new();
+    for (index, resource) in config.resources.iter().enumerate() {
+        let name = sri_setting(index);
+        let Some(sri) = setting(&name) else {
+            continue;
+        };
+        if let Err(e) = SriHash::parse(&sri) {
+            console_error!("Ignoring {}: {}", name, e.description());
+            continue;
+        }
+        overrides.push(Override::Sri {
+            setting: name,
+            url: resource.url.clone(),
+            sri,
+        });
+    }
+
+    let disabled = setting(DISABLE_SETTING).unwrap_or_default();
+    for url in disabled.split([',', ' ', '\n']).filter(|u| !u.is_empty()) {
+        match config::normalize_url(url) {
+            Ok(url) => overrides.push(Override::Disable {
+                setting: DISABLE_SETTING.to_string(),
+                url,
+            }),
+            Err(e) => console_error!("Ignoring {} entry '{}': {}", DISABLE_SETTING, url, e),
+        }
+    }
+    overrides
+}
+
+/// Copy of a config with overrides applied
+pub fn apply(config: &Config, overrides: &[Override]) -> Config {
+    let mut config = config.clone();
+    for applied in overrides {
+        match applied {
+            Override::Sri { url, sri, .. } => {
+                if let Some(resource) = config.resources.iter_mut().find(|r| r.url == *url) {
+                    resource.sri = sri.clone();
+                }
+            }
+            Override::Disable { url, .. } => {
+                config.resources.retain(|r| r.url != *url);
+                for tenant in &mut config.tenants {
+                    tenant.resources.retain(|r| r.url != *url);
+                }
+            }
+        }
+    }
+    config.reset_sri_hashes();
+    config
+}
+
+/// Overrides set in the environment for a config
+pub fn overrides(env: &Env, config: &Config) -> Vec<Override> {
+    collect(config, |name| config::setting(env, name))
+}
+
+/// Apply the overrides set in the environment to the active config
+pub fn effective(env: &Env, base: Arc<Config>) -> Arc<Config> {
+    let overrides = overrides(env, &base);
+    if overrides.is_empty() {
+        return base;
+    }
+
+    let mut cached = EFFECTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_base, cached_overrides, config)) = cached.as_ref() {
+        if Arc::ptr_eq(cached_base, &base) && *cached_overrides == overrides {
+            return Arc::clone(config);
+        }
+    }
+    for applied in &overrides {
+        console_error!("⚠ Config override in force: {:?}", applied);
+    }
+    let config = Arc::new(apply(&base, &overrides));
+    *cached = Some((base, overrides, Arc::clone(&config)));
+    config
+}
+
+/// Effective config and the overrides that shaped it
+#[derive(Serialize)]
+struct Export<'a> {
+    overrides: Vec<Override>,
+    config: &'a Config,
+}
+
+/// Handle `GET /config/export`
+///
+/// `config` is the effective config; the overrides are looked up again against
+/// the config they were applied to.
+pub fn handle_export(env: &Env, base: &Config, config: &Config) -> Result<Response> {
+    Response::from_json(&Export {
+        overrides: overrides(env, base),
+        config,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sri;
+    use std::collections::HashMap;
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://cdn.example.com/a.js"
+            sri = "sha384-old"
+
+            [[resources]]
+            url = "https://cdn.example.com/b.js"
+            sri = "sha384-b"
+
+            [[tenants]]
+            id = "web"
+
+            [[tenants.resources]]
+            url = "https://cdn.example.com/b.js"
+            sri = "sha384-b"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn settings(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| map.get(name).cloned()
+    }
+
+    #[test]
+    fn test_collect() {
+        let valid = sri::sha384(b"new");
+        let overrides = collect(
+            &config(),
+            settings(&[
+                ("OVERRIDE_RESOURCE_1_SRI", valid.as_str()),
+                ("OVERRIDE_RESOURCE_2_SRI", "sha384-typo"),
+                ("OVERRIDE_RESOURCE_3_SRI", valid.as_str()),
+                (
+                    "DISABLE_RESOURCE",
+                    "HTTPS://CDN.example.com/b.js, not a url",
+                ),
+            ]),
+        );
+        assert_eq!(
+            overrides,
+            vec![
+                Override::Sri {
+                    setting: "OVERRIDE_RESOURCE_1_SRI".to_string(),
+                    url: "https://cdn.example.com/a.js".to_string(),
+                    sri: valid,
+                },
+                Override::Disable {
+                    setting: "DISABLE_RESOURCE".to_string(),
+                    url: "https://cdn.example.com/b.js".to_string(),
+                },
+            ]
+        );
+        assert!(collect(&config(), settings(&[])).is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let valid = sri::sha384(b"new");
+        let base = config();
+        let overrides = collect(
+            &base,
+            settings(&[
+                ("OVERRIDE_RESOURCE_1_SRI", valid.as_str()),
+                ("DISABLE_RESOURCE", "https://cdn.example.com/b.js"),
+            ]),
+        );
+        let effective = apply(&base, &overrides);
+        assert_eq!(effective.resources.len(), 1);
+        assert_eq!(effective.resources[0].sri, valid);
+        assert!(effective.tenants[0].resources.is_empty());
+        assert!(effective.sri_hashes().parse(&valid).is_ok());
+        // The base config is untouched
+        assert_eq!(base.resources[0].sri, "sha384-old");
+
+        let json = serde_json::to_string(&overrides[0]).unwrap();
+        assert!(json.starts_with(r#"{"kind":"sri","setting":"OVERRIDE_RESOURCE_1_SRI""#));
+    }
+}
@@ -0,0 +1,167 @@
+//! Deployment environment banner (`ENVIRONMENT_NAME`)
+//!
+//! Staging and production deployments of linkkivahti often post to the same
+//! channels. With `ENVIRONMENT_NAME` set, every notification names its
+//! environment: chat titles start with `[STAGING]`, Discord messages lead with a
+//! banner embed in the environment's color, Slack messages with a colored marker,
+//! and generic payloads carry an `environment` label. `GET /` reports it too.
+//!
+//! The tint follows the name: production names are red, staging and other
+//! pre-production names yellow, development and test names green, anything else
+//! blue. `ENVIRONMENT_COLOR` (`#rrggbb`) overrides the color of the banner.
+
+use crate::config;
+use crate::console::console_error;
+use serde::{Serialize, Serializer};
+use worker::Env;
+
+/// Tint of an environment, derived from its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tint {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+}
+
+impl Tint {
+    /// Tint of an environment name
+    ///
+    /// Pre-production names are matched first, so `preprod` is not production.
+    fn of(name: &str) -> Self {
+        let name = name.to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|w| name.contains(w));
+        if has(&["stag", "preprod", "pre-prod", "uat", "qa", "canary", "beta"]) {
+            Self::Yellow
+        } else if has(&["prod", "live", "prd"]) {
+            Self::Red
+        } else if has(&["dev", "test", "local", "sandbox"]) {
+            Self::Green
+        } else {
+            Self::Blue
+        }
+    }
+
+    /// Colored marker shown before the name in chat messages
+    fn marker(self) -> &'static str {
+        match self {
+            Self::Red => "🔴",
+            Self::Yellow => "🟡",
+            Self::Green => "🟢",
+            Self::Blue => "🔵",
+        }
+    }
+
+    /// Discord color of the tint
+    fn color(self) -> u32 {
+        match self {
+            Self::Red => 0xED4245,
+            Self::Yellow => 0xFEE75C,
+            Self::Green => 0x57F287,
+            Self::Blue => 0x3498DB,
+        }
+    }
+}
+
+/// Environment of the deployment, as shown in notifications and on `GET /`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Environment {
+    pub name: String,
+    /// Banner color, serialized as `#rrggbb`
+    #[serde(serialize_with = "hex_color")]
+    pub color: u32,
+    #[serde(skip)]
+    tint: Tint,
+}
+
+fn hex_color<S: Serializer>(color: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("#{:06x}", color))
+}
+
+/// Parse a `#rrggbb` color
+fn parse_color(value: &str) -> Option<u32> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+impl Environment {
+    /// Environment of a name, tinted by the name
+    pub fn new(name: &str) -> Self {
+        let tint = Tint::of(name);
+        Self {
+            name: name.to_string(),
+            color: tint.color(),
+            tint,
+        }
+    }
+
+    /// Environment from `ENVIRONMENT_NAME` and `ENVIRONMENT_COLOR`, `None` if no
+    /// name is set
+    pub fn from_env(env: &Env) -> Option<Self> {
+        let mut environment = Self::new(&config::setting(env, "ENVIRONMENT_NAME")?);
+        if let Some(value) = config::setting(env, "ENVIRONMENT_COLOR") {
+            match parse_color(&value) {
+                Some(color) => environment.color = color,
+                None => console_error!("Invalid ENVIRONMENT_COLOR '{}', expected #rrggbb", value),
+            }
+        }
+        Some(environment)
+    }
+
+    /// Colored marker of the environment's tint, e.g. `🟡`
+    pub fn marker(&self) -> &'static str {
+        self.tint.marker()
+    }
+
+    /// Name in capitals, e.g. `STAGING`
+    pub fn label(&self) -> String {
+        self.name.to_uppercase()
+    }
+
+    /// Colored banner, e.g. `🟡 STAGING`
+    pub fn banner(&self) -> String {
+        format!("{} {}", self.marker(), self.label())
+    }
+
+    /// A title prefixed with the environment, e.g. `[STAGING] 🔗 Link Check Failed`
+    pub fn prefix(&self, title: &str) -> String {
+        format!("[{}] {}", self.label(), title)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tint() {
+        assert_eq!(Tint::of("production"), Tint::Red);
+        assert_eq!(Tint::of("prod-eu"), Tint::Red);
+        assert_eq!(Tint::of("Staging"), Tint::Yellow);
+        assert_eq!(Tint::of("preprod"), Tint::Yellow);
+        assert_eq!(Tint::of("dev"), Tint::Green);
+        assert_eq!(Tint::of("team-a"), Tint::Blue);
+    }
+
+    #[test]
+    fn test_environment() {
+        let staging = Environment::new("staging");
+        assert_eq!(staging.banner(), "🟡 STAGING");
+        assert_eq!(
+            staging.prefix("🔗 Link Check Failed"),
+            "[STAGING] 🔗 Link Check Failed"
+        );
+        assert_eq!(
+            serde_json::to_string(&staging).unwrap(),
+            r##"{"name":"staging","color":"#fee75c"}"##
+        );
+
+        assert_eq!(parse_color("#1abc9c"), Some(0x1ABC9C));
+        assert_eq!(parse_color("1ABC9C"), Some(0x1ABC9C));
+        assert_eq!(parse_color("#abc"), None);
+        assert_eq!(parse_color("#zzzzzz"), None);
+    }
+}
@@ -0,0 +1,396 @@
+//! Time-of-day aware severity rules (`[escalation]`)
+//!
+//! Rules override the configured severity of an alert stream depending on the
+//! resource group and whether the alert fires during business hours, e.g. to page
+//! for a marketing asset only during office hours while SRI mismatches always page:
+//!
+//! ```toml
+//! [escalation]
+//! timezone = "+01:00"
+//! dst = "eu"
+//! business_hours = "09:00-17:00"
+//!
+//! [[escalation.rules]]
+//! stream = "integrity"
+//! severity = "critical"
+//!
+//! [[escalation.rules]]
+//! group = "marketing"
+//! when = "outside_business_hours"
+//! severity = "info"
+//! ```
+//!
+//! The first matching rule wins; without a match the stream's `<STREAM>_SEVERITY`
+//! applies. Timezones are a UTC offset plus an optional EU or US daylight saving
+//! rule, which avoids bundling the tz database into the Worker.
+
+use crate::alert::{AlertStream, Severity};
+use crate::clock;
+use crate::config::Resource;
+use serde::{Deserialize, Serialize};
+
+const MINUTE_MS: i64 = 60_000;
+const DAY_MINUTES: i64 = 24 * 60;
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Escalation settings of config.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Escalation {
+    /// UTC offset of business hours, e.g. `+02:00` (default `UTC`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Daylight saving rule on top of the offset: `eu` or `us`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dst: Option<String>,
+    /// Local business hours, e.g. `08:30-18:00` (default `09:00-17:00`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub business_hours: Option<String>,
+    /// Business days, e.g. `["mon", "tue"]` (default Monday to Friday)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub business_days: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<EscalationRule>,
+}
+
+/// When a rule applies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum When {
+    #[default]
+    Always,
+    BusinessHours,
+    OutsideBusinessHours,
+}
+
+/// A severity override for matching alerts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscalationRule {
+    /// Resource group the rule applies to (any if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Alert stream the rule applies to (any if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<AlertStream>,
+    #[serde(default)]
+    pub when: When,
+    pub severity: Severity,
+}
+
+/// Daylight saving rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dst {
+    /// Last Sunday of March to last Sunday of October, switching at 01:00 UTC
+    Eu,
+    /// Second Sunday of March to first Sunday of November, switching at 02:00 local
+    Us,
+}
+
+/// Parsed business hours
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Calendar {
+    offset_minutes: i64,
+    dst: Option<Dst>,
+    /// Start and end of business hours in minutes after local midnight
+    start: i64,
+    end: i64,
+    /// Business days, indexed from Monday
+    days: [bool; 7],
+}
+
+/// Parse `UTC`, `Z` or `+HH:MM` / `-HH:MM` into minutes east of UTC
+fn parse_offset(timezone: &str) -> Result<i64, String> {
+    if timezone.eq_ignore_ascii_case("utc") || timezone == "Z" {
+        return Ok(0);
+    }
+    let invalid = || format!("Invalid escalation.timezone '{}'", timezone);
+    let (sign, rest) = match timezone.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    match parse_time(rest) {
+        Some(minutes) if minutes <= 14 * 60 => Ok(sign * minutes),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse `HH:MM` (up to `24:00`) into minutes after midnight
+fn parse_time(time: &str) -> Option<i64> {
+    let (hours, minutes) = time.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = minutes.parse().ok()?;
+    let total = hours * 60 + minutes;
+    (minutes < 60 && total <= DAY_MINUTES).then_some(total)
+}
+
+impl Escalation {
+    /// Parse the business hours settings
+    fn calendar(&self) -> Result<Calendar, String> {
+        let offset_minutes = match &self.timezone {
+            Some(timezone) => parse_offset(timezone)?,
+            None => 0,
+        };
+        let dst = match self.dst.as_deref().map(str::to_lowercase).as_deref() {
+            None => None,
+            Some("eu") => Some(Dst::Eu),
+            Some("us") => Some(Dst::Us),
+            Some(other) => return Err(format!("Unknown escalation.dst '{}'", other)),
+        };
+
+        let hours = self.business_hours.as_deref().unwrap_or("09:00-17:00");
+        let (start, end) = hours
+            .split_once('-')
+            .and_then(|(start, end)| Some((parse_time(start)?, parse_time(end)?)))
+            .filter(|(start, end)| start < end)
+            .ok_or_else(|| format!("Invalid escalation.business_hours '{}'", hours))?;
+
+        let mut days = [false; 7];
+        match &self.business_days {
+            None => days[..5].fill(true),
+            Some(names) => {
+                for name in names {
+                    let index = WEEKDAYS
+                        .iter()
+                        .position(|d| name.eq_ignore_ascii_case(d))
+                        .ok_or_else(|| format!("Unknown business day '{}'", name))?;
+                    days[index] = true;
+                }
+            }
+        }
+
+        Ok(Calendar {
+            offset_minutes,
+            dst,
+            start,
+            end,
+            days,
+        })
+    }
+
+    /// Check the settings when the config is loaded
+    pub fn validate(&self) -> Result<(), String> {
+        self.calendar().map(|_| ())
+    }
+}
+
+/// Day since the epoch of the `n`th Sunday of a month, counting from the end if negative
+fn nth_sunday(year: i64, month: i64, n: i64) -> i64 {
+    if n > 0 {
+        let first = clock::days_from_civil(year, month, 1);
+        let until_sunday = (6 - clock::weekday(first) as i64).rem_euclid(7);
+        first + until_sunday + 7 * (n - 1)
+    } else {
+        let (next_year, next_month) = if month == 12 {
+            (year + 1, 1)
+        } else {
+            (year, month + 1)
+        };
+        let last = clock::days_from_civil(next_year, next_month, 1) - 1;
+        last - (clock::weekday(last) as i64 + 1) % 7
+    }
+}
+
+impl Calendar {
+    /// Minutes since the epoch in local time
+    fn local_minutes(&self, now_ms: u64) -> i64 {
+        let utc = now_ms as i64 / MINUTE_MS;
+        let standard = utc + self.offset_minutes;
+        let (year, _, _) = clock::civil_from_days(standard.div_euclid(DAY_MINUTES));
+        let summer = match self.dst {
+            None => false,
+            Some(Dst::Eu) => {
+                let start = nth_sunday(year, 3, -1) * DAY_MINUTES + 60;
+                let end = nth_sunday(year, 10, -1) * DAY_MINUTES + 60;
+                (start..end).contains(&utc)
+            }
+            Some(Dst::Us) => {
+                // 02:00 standard time to 02:00 daylight time (01:00 standard)
+                let start = nth_sunday(year, 3, 2) * DAY_MINUTES + 120;
+                let end = nth_sunday(year, 11, 1) * DAY_MINUTES + 60;
+                (start..end).contains(&standard)
+            }
+        };
+        standard + if summer { 60 } else { 0 }
+    }
+
+    fn is_business_time(&self, now_ms: u64) -> bool {
+        let local = self.local_minutes(now_ms);
+        let day = local.div_euclid(DAY_MINUTES);
+        let minute = local.rem_euclid(DAY_MINUTES);
+        self.days[clock::weekday(day)] && (self.start..self.end).contains(&minute)
+    }
+}
+
+/// Severity of an alert after applying the escalation rules
+///
+/// `base` is the stream's configured severity, used when no rule matches.
+pub fn severity(
+    escalation: &Escalation,
+    resource: Option<&Resource>,
+    stream: AlertStream,
+    base: Severity,
+    now_ms: u64,
+) -> Severity {
+    if escalation.rules.is_empty() {
+        return base;
+    }
+    // Validated with the config, so this only fails for hand-built configs
+    let business = escalation
+        .calendar()
+        .map(|c| c.is_business_time(now_ms))
+        .unwrap_or(true);
+    let group = resource.and_then(|r| r.group.as_deref());
+
+    escalation
+        .rules
+        .iter()
+        .find(|rule| {
+            rule.stream.is_none_or(|s| s == stream)
+                && rule.group.as_deref().is_none_or(|g| Some(g) == group)
+                && match rule.when {
+                    When::Always => true,
+                    When::BusinessHours => business,
+                    When::OutsideBusinessHours => !business,
+                }
+        })
+        .map(|rule| rule.severity)
+        .unwrap_or(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2025-11-12T10:00:00Z, a Wednesday
+    const NOW: u64 = 1_762_941_600_000;
+    const HOUR_MS: u64 = 3_600_000;
+
+    fn escalation(toml: &str) -> Escalation {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_calendar() {
+        let calendar = Escalation::default().calendar().unwrap();
+        assert!(calendar.is_business_time(NOW));
+        assert!(!calendar.is_business_time(NOW + 7 * HOUR_MS));
+        // Saturday
+        assert!(!calendar.is_business_time(NOW + 3 * 24 * HOUR_MS));
+
+        let calendar = escalation(
+            r#"
+            timezone = "-05:00"
+            business_hours = "08:00-12:00"
+            business_days = ["Wed"]
+            "#,
+        )
+        .calendar()
+        .unwrap();
+        // 05:00 local
+        assert!(!calendar.is_business_time(NOW));
+        assert!(calendar.is_business_time(NOW + 3 * HOUR_MS));
+    }
+
+    #[test]
+    fn test_dst() {
+        assert_eq!(nth_sunday(2025, 3, -1), clock::days_from_civil(2025, 3, 30));
+        assert_eq!(
+            nth_sunday(2025, 10, -1),
+            clock::days_from_civil(2025, 10, 26)
+        );
+        assert_eq!(nth_sunday(2025, 3, 2), clock::days_from_civil(2025, 3, 9));
+        assert_eq!(nth_sunday(2025, 11, 1), clock::days_from_civil(2025, 11, 2));
+
+        // 2025-07-01T06:30:00Z is 09:30 in Helsinki summer time, 08:30 without DST
+        let july =
+            clock::days_from_civil(2025, 7, 1) as u64 * 24 * HOUR_MS + 6 * HOUR_MS + HOUR_MS / 2;
+        let helsinki = |dst: Option<&str>| {
+            Escalation {
+                timezone: Some("+02:00".to_string()),
+                dst: dst.map(str::to_string),
+                ..Default::default()
+            }
+            .calendar()
+            .unwrap()
+            .is_business_time(july)
+        };
+        assert!(helsinki(Some("eu")));
+        assert!(!helsinki(None));
+
+        // 2025-07-01T12:30:00Z is 08:30 in New York summer time, 2025-11-12T13:30:00Z is
+        // 08:30 in winter
+        let new_york = escalation(
+            r#"
+            timezone = "-05:00"
+            dst = "us"
+            business_hours = "08:00-09:00"
+            "#,
+        )
+        .calendar()
+        .unwrap();
+        assert!(new_york.is_business_time(july + 6 * HOUR_MS));
+        assert!(!new_york.is_business_time(july + 7 * HOUR_MS));
+        assert!(new_york.is_business_time(NOW + 3 * HOUR_MS + HOUR_MS / 2));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Escalation::default().validate().is_ok());
+        for invalid in [
+            r#"timezone = "Europe/Helsinki""#,
+            r#"timezone = "+15:00""#,
+            r#"dst = "au""#,
+            r#"business_hours = "17:00-09:00""#,
+            r#"business_hours = "9-17""#,
+            r#"business_days = ["monday"]"#,
+        ] {
+            assert!(escalation(invalid).validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_severity() {
+        let escalation = escalation(
+            r#"
+            [[rules]]
+            stream = "integrity"
+            severity = "critical"
+
+            [[rules]]
+            group = "marketing"
+            when = "outside_business_hours"
+            severity = "info"
+            "#,
+        );
+        let marketing = Resource {
+            url: "https://example.com/a.js".to_string(),
+            group: Some("marketing".to_string()),
+            ..Default::default()
+        };
+        let severity = |resource, stream, now| {
+            super::severity(&escalation, resource, stream, Severity::Warning, now)
+        };
+        let evening = NOW + 9 * HOUR_MS;
+
+        assert_eq!(
+            severity(Some(&marketing), AlertStream::Availability, NOW),
+            Severity::Warning
+        );
+        assert_eq!(
+            severity(Some(&marketing), AlertStream::Availability, evening),
+            Severity::Info
+        );
+        assert_eq!(
+            severity(Some(&marketing), AlertStream::Integrity, evening),
+            Severity::Critical
+        );
+        assert_eq!(
+            severity(None, AlertStream::Availability, evening),
+            Severity::Warning
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Event log of state transitions (`GET /events/log`)
+//!
+//! Webhooks push notifications; some consumers prefer to pull. Every run appends
+//! what happened to a per-scope log in KV: the run starting, alert streams
+//! failing and recovering, and notifications going out. `GET /events/log` returns
+//! the log as newline-delimited JSON, one event per line, ready for
+//! `curl | jq` or log shippers:
+//!
+//! ```text
+//! {"at":1762941600000,"time":"2025-11-12T10:00:00.000Z","event":"check_started","detail":"12 resources"}
+//! {"at":1762941600000,"time":"2025-11-12T10:00:00.000Z","event":"failed","url":"https://...","stream":"availability","detail":"HTTP 503"}
+//! ```
+//!
+//! Events of a run are written together once the run is done, with one KV write
+//! per run and scope.
+
+use crate::alert::AlertStream;
+use crate::blackout;
+use crate::clock;
+use crate::console::console_error;
+use crate::store::Store;
+use crate::tenant::Scope;
+use crate::trace;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+/// KV key of the event log, relative to the scope prefix
+const EVENTS_KEY: &str = "events";
+
+/// Number of events retained per scope
+pub const MAX_EVENTS: usize = 2000;
+
+/// What happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A run started checking the scope
+    CheckStarted,
+    /// An alert stream of a resource started failing
+    Failed,
+    /// An alert stream of a resource recovered
+    Recovered,
+    /// A notification was sent to the webhook
+    Notified,
+    /// A notification could not be delivered
+    NotificationFailed,
+    /// An external system acknowledged a firing alert (see `callback`)
+    Acknowledged,
+    /// An external system closed a firing alert (see `callback`)
+    Closed,
+}
+
+/// An entry of the event log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    /// When it happened (milliseconds since the Unix epoch)
+    pub at: u64,
+    /// `at` in RFC 3339 form
+    pub time: String,
+    pub event: EventKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<AlertStream>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// ID of the run it happened in (see `trace`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+}
+
+impl Event {
+    pub fn new(event: EventKind, at: u64) -> Self {
+        Self {
+            at,
+            time: clock::format_ms(at),
+            event,
+            url: None,
+            stream: None,
+            detail: None,
+            run_id: trace::current(),
+        }
+    }
+
+    /// Event about an alert stream of a resource
+    pub fn alert(event: EventKind, at: u64, url: &str, stream: AlertStream) -> Self {
+        Self {
+            url: Some(url.to_string()),
+            stream: Some(stream),
+            ..Self::new(event, at)
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Recent events of a scope, oldest first
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EventLog {
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+impl EventLog {
+    /// Append events, dropping the oldest ones beyond `MAX_EVENTS`
+    pub fn append(&mut self, events: Vec<Event>) {
+        self.events.extend(events);
+        if self.events.len() > MAX_EVENTS {
+            let excess = self.events.len() - MAX_EVENTS;
+            self.events.drain(..excess);
+        }
+    }
+
+    /// Events after `since` as newline-delimited JSON
+    pub fn ndjson(&self, since: u64) -> String {
+        self.events
+            .iter()
+            .filter(|e| e.at > since)
+            .filter_map(|e| serde_json::to_string(e).ok())
+            .map(|line| line + "\n")
+            .collect()
+    }
+}
+
+/// Append the events of a run to the log of a scope, logging failures
+pub async fn record(env: &Env, scope: &Scope<'_>, events: Vec<Event>) {
+    if events.is_empty() {
+        return;
+    }
+    let Some(store) = Store::from_env(env) else {
+        return;
+    };
+    let key = scope.key(EVENTS_KEY);
+    let mut log = match store.get::<EventLog>(&key).await {
+        Ok(log) => log.unwrap_or_default(),
+        Err(e) => {
+            console_error!("Failed to load event log: {}", e);
+            return;
+        }
+    };
+    log.append(events);
+    if let Err(e) = store.put(&key, &log).await {
+        console_error!("Failed to save event log: {}", e);
+    }
+}
+
+/// Parse `since`: milliseconds since the Unix epoch, an RFC 3339 date-time or a date
+fn parse_since(value: &str) -> Option<u64> {
+    value
+        .parse()
+        .ok()
+        .or_else(|| blackout::parse_datetime(value))
+}
+
+/// Handle `GET /events/log?since=...`
+pub async fn handle_log(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let store = Store::require(env)?;
+    let url = req.url()?;
+    let query: BTreeMap<String, String> = url.query_pairs().into_owned().collect();
+    let since = match query.get("since") {
+        None => 0,
+        Some(value) => match parse_since(value) {
+            Some(since) => since,
+            None => {
+                return Response::error(
+                    "Expected 'since' as epoch milliseconds or an RFC 3339 date-time",
+                    400,
+                )
+            }
+        },
+    };
+    let log: EventLog = store.get(&scope.key(EVENTS_KEY)).await?.unwrap_or_default();
+    let mut response = Response::ok(log.ndjson(since))?;
+    response
+        .headers_mut()
+        .set("Content-Type", "application/x-ndjson")?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_ndjson() {
+        let mut log = EventLog::default();
+        log.append(vec![
+            Event::new(EventKind::CheckStarted, 1_000).with_detail("2 resources"),
+            Event::alert(
+                EventKind::Failed,
+                2_000,
+                "https://example.com/a.js",
+                AlertStream::Availability,
+            )
+            .with_detail("HTTP 503"),
+        ]);
+        let ndjson = log.ndjson(0);
+        let lines: Vec<&str> = ndjson.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r#"{"at":1000,"time":"1970-01-01T00:00:01.000Z","event":"check_started","detail":"2 resources"}"#
+        );
+        assert!(lines[1].contains(
+            r#""event":"failed","url":"https://example.com/a.js","stream":"availability""#
+        ));
+        assert_eq!(log.ndjson(1_000).lines().count(), 1);
+
+        log.append(
+            (0..MAX_EVENTS as u64)
+                .map(|at| Event::new(EventKind::Notified, at + 3_000))
+                .collect(),
+        );
+        assert_eq!(log.events.len(), MAX_EVENTS);
+        assert_eq!(log.events[0].at, 3_000);
+    }
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(parse_since("1762941600000"), Some(1_762_941_600_000));
+        assert_eq!(parse_since("2025-11-12T10:00:00Z"), Some(1_762_941_600_000));
+        assert_eq!(parse_since("yesterday"), None);
+    }
+}
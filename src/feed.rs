@@ -0,0 +1,503 @@
+//! RSS and Atom feeds: well-formedness and freshness
+//!
+//! A resource with a `feed` table is a release or news feed that should keep
+//! updating:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://example.com/releases.xml"
+//! feed = { max_age_hours = 336 }
+//! ```
+//!
+//! The document must be well-formed XML with an RSS 2.0 (`<rss>`), RSS 1.0
+//! (`<rdf:RDF>`) or Atom (`<feed>`) root. Its newest entry is found from the
+//! `pubDate`, `dc:date`, `updated` or `published` of each item or entry; a feed
+//! whose newest entry is older than `max_age_hours` fails with `FeedStale`, and a
+//! malformed feed, or one without dated entries, with `UnexpectedResponse`
+//! and the reason in the result's message. The XML is parsed just enough for
+//! that: DTDs, namespaces and entities beyond the predefined ones are not
+//! interpreted.
+
+use crate::blackout;
+use crate::checker::{CheckError, CheckResult};
+use crate::clock;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Age of the newest entry that is still fresh by default: one week
+const DEFAULT_MAX_AGE_HOURS: u32 = 168;
+
+/// Elements holding the date of an item or entry, by local name
+const DATE_ELEMENTS: &[&str] = &["pubDate", "date", "updated", "published"];
+
+/// Settings of a feed probe (`feed = { ... }` on a resource)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Feed {
+    /// Hours after which the newest entry makes the feed stale
+    #[serde(default = "default_max_age_hours")]
+    pub max_age_hours: u32,
+}
+
+fn default_max_age_hours() -> u32 {
+    DEFAULT_MAX_AGE_HOURS
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self {
+            max_age_hours: DEFAULT_MAX_AGE_HOURS,
+        }
+    }
+}
+
+impl Feed {
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        if self.max_age_hours == 0 {
+            return Err(format!("feed.max_age_hours of {} must be at least 1", url));
+        }
+        Ok(())
+    }
+}
+
+/// An XML element with its child elements and text
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    /// Name without a namespace prefix
+    fn local_name(&self) -> &str {
+        self.name.rsplit(':').next().unwrap_or_default()
+    }
+
+    fn children_named<'a>(&'a self, local: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children
+            .iter()
+            .filter(move |c| c.local_name() == local)
+    }
+}
+
+/// Replace the predefined entities and character references of XML text
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let decoded = match &rest[1..end] {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            reference => reference
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| reference.strip_prefix('#')?.parse().ok())
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// End of a start or end tag at the start of `rest`, skipping quoted attribute values
+fn tag_end(rest: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse a well-formed XML document into its root element
+fn parse_xml(body: &str) -> std::result::Result<Element, String> {
+    let body = body.trim_start_matches('\u{feff}');
+    // The document itself, whose only child must be the root element
+    let mut stack = vec![Element::default()];
+    let mut rest = body;
+    loop {
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        let text = &rest[..text_end];
+        if stack.len() > 1 {
+            stack
+                .last_mut()
+                .unwrap()
+                .text
+                .push_str(&decode_entities(text));
+        } else if !text.trim().is_empty() {
+            return Err("text outside the root element".to_string());
+        }
+        rest = &rest[text_end..];
+        if rest.is_empty() {
+            break;
+        }
+
+        let skip = |open: &str, close: &str, what: &str| {
+            rest[open.len()..]
+                .find(close)
+                .map(|end| open.len() + end + close.len())
+                .ok_or_else(|| format!("unterminated {}", what))
+        };
+        if rest.starts_with("<!--") {
+            rest = &rest[skip("<!--", "-->", "comment")?..];
+        } else if rest.starts_with("<![CDATA[") {
+            let end = skip("<![CDATA[", "]]>", "CDATA section")?;
+            if stack.len() == 1 {
+                return Err("CDATA outside the root element".to_string());
+            }
+            let data = &rest["<![CDATA[".len()..end - "]]>".len()];
+            stack.last_mut().unwrap().text.push_str(data);
+            rest = &rest[end..];
+        } else if rest.starts_with("<?") {
+            rest = &rest[skip("<?", "?>", "processing instruction")?..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[skip("<!", ">", "declaration")?..];
+        } else {
+            let end = tag_end(rest).ok_or("unterminated tag")?;
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+            if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim();
+                if stack.len() == 1 {
+                    return Err(format!("unexpected </{}>", name));
+                }
+                let element = stack.pop().unwrap();
+                if element.name != name {
+                    return Err(format!("expected </{}>, found </{}>", element.name, name));
+                }
+                stack.last_mut().unwrap().children.push(element);
+            } else {
+                let self_closing = tag.ends_with('/');
+                let name = tag
+                    .trim_end_matches('/')
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default();
+                if name.is_empty() {
+                    return Err("tag without a name".to_string());
+                }
+                if stack.len() == 1 && !stack[0].children.is_empty() {
+                    return Err(format!("second root element <{}>", name));
+                }
+                let element = Element {
+                    name: name.to_string(),
+                    ..Default::default()
+                };
+                if self_closing {
+                    stack.last_mut().unwrap().children.push(element);
+                } else {
+                    stack.push(element);
+                }
+            }
+        }
+    }
+    if stack.len() > 1 {
+        return Err(format!("unclosed <{}>", stack.last().unwrap().name));
+    }
+    stack
+        .pop()
+        .and_then(|document| document.children.into_iter().next())
+        .ok_or_else(|| "no root element".to_string())
+}
+
+/// Parse an RFC 822 date as used by RSS and HTTP headers, e.g. `Wed, 12 Nov 2025 10:00:00 GMT`
+pub fn parse_rfc822(value: &str) -> Option<u64> {
+    let value = value.split_once(',').map_or(value, |(_, rest)| rest);
+    let mut parts = value.split_whitespace();
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = match parts.next()?.get(..3)?.to_ascii_lowercase().as_str() {
+        "jan" => 1,
+        "feb" => 2,
+        "mar" => 3,
+        "apr" => 4,
+        "may" => 5,
+        "jun" => 6,
+        "jul" => 7,
+        "aug" => 8,
+        "sep" => 9,
+        "oct" => 10,
+        "nov" => 11,
+        "dec" => 12,
+        _ => return None,
+    };
+    let year: u32 = match parts.next()?.parse().ok()? {
+        year @ 0..=49 => 2000 + year,
+        year @ 50..=99 => 1900 + year,
+        year => year,
+    };
+    let mut time = parts.next()?.to_string();
+    if time.len() == 5 {
+        time.push_str(":00");
+    }
+    let offset = match parts.next().unwrap_or("GMT") {
+        "GMT" | "UT" | "UTC" | "Z" => "+00:00".to_string(),
+        "EDT" => "-04:00".to_string(),
+        "EST" | "CDT" => "-05:00".to_string(),
+        "CST" | "MDT" => "-06:00".to_string(),
+        "MST" | "PDT" => "-07:00".to_string(),
+        "PST" => "-08:00".to_string(),
+        zone if zone.len() == 5 && zone.is_ascii() && zone.starts_with(['+', '-']) => {
+            format!("{}:{}", &zone[..3], &zone[3..])
+        }
+        _ => return None,
+    };
+    blackout::parse_datetime(&format!(
+        "{:04}-{:02}-{:02}T{}{}",
+        year, month, day, time, offset
+    ))
+}
+
+/// Parse an RFC 3339 date as used by Atom, ignoring fractions of a second
+fn parse_rfc3339(value: &str) -> Option<u64> {
+    let without_fraction = match value.find('.') {
+        Some(dot) => {
+            let digits = value[dot + 1..]
+                .bytes()
+                .take_while(u8::is_ascii_digit)
+                .count();
+            format!("{}{}", &value[..dot], &value[dot + 1 + digits..])
+        }
+        None => value.to_string(),
+    };
+    blackout::parse_datetime(&without_fraction)
+}
+
+/// Time of the newest entry of a feed, in milliseconds since the Unix epoch
+fn newest_entry(body: &str) -> std::result::Result<u64, String> {
+    let root = parse_xml(body).map_err(|e| format!("Malformed XML: {}", e))?;
+    let entries: Vec<&Element> = match root.local_name() {
+        "rss" => root
+            .children_named("channel")
+            .flat_map(|channel| channel.children_named("item"))
+            .collect(),
+        "RDF" => root.children_named("item").collect(),
+        "feed" => root.children_named("entry").collect(),
+        other => {
+            return Err(format!(
+                "Not an RSS or Atom feed (root element <{}>)",
+                other
+            ))
+        }
+    };
+    if entries.is_empty() {
+        return Err("Feed has no entries".to_string());
+    }
+    entries
+        .iter()
+        .flat_map(|entry| entry.children.iter())
+        .filter(|child| DATE_ELEMENTS.contains(&child.local_name()))
+        .filter_map(|child| {
+            let text = child.text.trim();
+            parse_rfc3339(text).or_else(|| parse_rfc822(text))
+        })
+        .max()
+        .ok_or_else(|| "No feed entry has a readable date".to_string())
+}
+
+/// Check a feed: availability, well-formedness and the age of its newest entry
+pub async fn check(fetcher: &impl Fetcher, resource: &Resource, feed: &Feed) -> CheckResult {
+    let started = clock::now_ms();
+    let mut result = run_check(fetcher, resource, feed).await;
+    if result.error != Some(CheckError::FetchFailed) {
+        result.latency_ms = Some(clock::now_ms().saturating_sub(started));
+    }
+    result
+}
+
+async fn run_check(fetcher: &impl Fetcher, resource: &Resource, feed: &Feed) -> CheckResult {
+    let url = resource.url.as_str();
+    console_log!("Checking feed: {}", url);
+    let mut response = match fetcher.fetch(HttpRequest::new(Method::Get, url)).await {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    let status_code = response.status_code();
+    if !(200..300).contains(&status_code) {
+        return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+    }
+    let content = match response.bytes().await {
+        Ok(content) => content,
+        Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_body(&e)),
+    };
+
+    let failure = |error: CheckError, message: Option<String>| {
+        let mut result = CheckResult::failure(url.to_string(), error);
+        result.status_code = Some(status_code);
+        result.message = message;
+        result
+    };
+    let newest = match std::str::from_utf8(&content)
+        .map_err(|_| "Feed is not UTF-8 text".to_string())
+        .and_then(newest_entry)
+    {
+        Ok(newest) => newest,
+        Err(reason) => {
+            console_error!("✗ {} - {}", url, reason);
+            return failure(CheckError::UnexpectedResponse, Some(reason));
+        }
+    };
+    let age_hours = clock::now_ms().saturating_sub(newest) / 3_600_000;
+    if age_hours >= feed.max_age_hours as u64 {
+        console_error!("✗ {} - newest entry is {} hours old", url, age_hours);
+        return failure(CheckError::FeedStale(age_hours), None);
+    }
+    console_log!("✓ {} - newest entry is {} hours old", url, age_hours);
+    CheckResult::success(url.to_string(), status_code, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    const URL: &str = "https://example.com/releases.xml";
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- generated -->
+<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <channel>
+    <title>Releases &amp; notes</title>
+    <item><title>v1</title><pubDate>Mon, 10 Nov 2025 10:00:00 GMT</pubDate></item>
+    <item>
+      <title><![CDATA[v2 <beta>]]></title>
+      <pubDate>Tue, 11 Nov 2025 07:00:00 -0500</pubDate>
+      <enclosure url="https://example.com/v2.tar.gz" length="1" type="a/b" />
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Releases</title>
+  <updated>2025-11-12T09:00:00Z</updated>
+  <entry><title>v3</title><updated>2025-11-01T08:00:00.250+02:00</updated></entry>
+</feed>"#;
+
+    fn run(body: &str, max_age_hours: u32) -> CheckResult {
+        let fetcher = MockFetcher::new().respond(URL, MockResponse::new(200, body));
+        let resource = Resource {
+            url: URL.to_string(),
+            ..Default::default()
+        };
+        block_on(check(&fetcher, &resource, &Feed { max_age_hours }))
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        let root = parse_xml(RSS).unwrap();
+        assert_eq!(root.name, "rss");
+        let channel = root.children_named("channel").next().unwrap();
+        assert_eq!(channel.children[0].text, "Releases & notes");
+        assert_eq!(channel.children[2].children[0].text, "v2 <beta>");
+
+        assert_eq!(
+            parse_xml("<rss><channel></rss>").unwrap_err(),
+            "expected </channel>, found </rss>"
+        );
+        assert_eq!(
+            parse_xml("<rss><channel>").unwrap_err(),
+            "unclosed <channel>"
+        );
+        assert_eq!(
+            parse_xml("<a/><b/>").unwrap_err(),
+            "second root element <b>"
+        );
+        assert_eq!(
+            parse_xml("<html>\n<body>hi").unwrap_err(),
+            "unclosed <body>"
+        );
+        assert_eq!(
+            parse_xml("oops").unwrap_err(),
+            "text outside the root element"
+        );
+        assert_eq!(decode_entities("&#x41;&#66;&nbsp;"), "AB&nbsp;");
+    }
+
+    #[test]
+    fn test_dates() {
+        // 2025-11-12T10:00:00Z
+        let now = 1_762_941_600_000;
+        assert_eq!(parse_rfc822("Wed, 12 Nov 2025 10:00:00 GMT"), Some(now));
+        assert_eq!(parse_rfc822("12 Nov 25 12:00 +0200"), Some(now));
+        assert_eq!(parse_rfc822("Wed, 12 Nov 2025 05:00:00 EST"), Some(now));
+        assert_eq!(parse_rfc822("2025-11-12"), None);
+        // Remote headers may hold anything; a non-ASCII zone must not panic
+        assert_eq!(parse_rfc822("Wed, 12 Nov 2025 10:00:00 +€1"), None);
+        assert_eq!(parse_rfc3339("2025-11-12T10:00:00.123Z"), Some(now));
+        assert_eq!(parse_rfc3339("2025-11-12T12:00:00+02:00"), Some(now));
+    }
+
+    #[test]
+    fn test_newest_entry() {
+        // The second item, 2025-11-11T12:00:00Z
+        assert_eq!(newest_entry(RSS), Ok(1_762_862_400_000));
+        // The feed's own <updated> does not count, only entries
+        assert_eq!(newest_entry(ATOM), Ok(1_761_976_800_000));
+        assert_eq!(
+            newest_entry("<html><body/></html>").unwrap_err(),
+            "Not an RSS or Atom feed (root element <html>)"
+        );
+        assert_eq!(
+            newest_entry("<rss><channel/></rss>").unwrap_err(),
+            "Feed has no entries"
+        );
+        assert_eq!(
+            newest_entry("<feed><entry><title/></entry></feed>").unwrap_err(),
+            "No feed entry has a readable date"
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let result = run(RSS, 48);
+        assert!(!result.has_problem());
+        assert_eq!(result.status_code, Some(200));
+
+        let result = run(RSS, 12);
+        assert_eq!(result.error, Some(CheckError::FeedStale(22)));
+        assert_eq!(
+            result.description(),
+            "Failed: Newest feed entry is 22 hours old"
+        );
+
+        let result = run(ATOM, 168);
+        assert_eq!(result.error, Some(CheckError::FeedStale(268)));
+        assert_eq!(
+            result.description(),
+            "Failed: Newest feed entry is 11 days old"
+        );
+
+        let result = run("<rss><channel><item></channel></rss>", 48);
+        assert_eq!(result.error, Some(CheckError::UnexpectedResponse));
+        assert_eq!(
+            result.description(),
+            "Failed: Malformed XML: expected </item>, found </channel>"
+        );
+        assert!(Feed { max_age_hours: 0 }.validate(URL).is_err());
+    }
+}
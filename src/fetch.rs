@@ -0,0 +1,553 @@
+//! Outgoing HTTP behind a trait
+//!
+//! Resource checks and webhook deliveries go through `Fetcher` instead of calling
+//! `worker::Fetch` directly. Production code uses `WorkerFetcher`; tests use
+//! `mock::MockFetcher`, which answers from canned responses and records the
+//! requests, so check and notification logic can run outside the Workers runtime.
+
+use crate::usage;
+use futures::future::{self, Either};
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::time::Duration;
+use worker::*;
+
+/// Message of the error of a request that exceeded its timeout
+const TIMEOUT_MESSAGE: &str = "Request timed out";
+
+/// An outgoing HTTP request
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// Whether redirects are followed, or returned as the response
+    pub follow_redirects: bool,
+    /// Time to wait for the response, body included, before aborting the request
+    pub timeout_ms: Option<u64>,
+}
+
+impl HttpRequest {
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: Vec::new(),
+            body: None,
+            follow_redirects: true,
+            timeout_ms: None,
+        }
+    }
+
+    /// A POST request with a JSON body
+    pub fn post_json(url: impl Into<String>, body: impl Into<String>) -> Self {
+        Self::new(Method::Post, url).with_json(body)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Return redirect responses instead of following them
+    pub fn without_redirects(mut self) -> Self {
+        self.follow_redirects = false;
+        self
+    }
+
+    /// Set a JSON body and its content type
+    pub fn with_json(self, body: impl Into<String>) -> Self {
+        let mut request = self.with_header("Content-Type", "application/json");
+        request.body = Some(body.into());
+        request
+    }
+}
+
+/// A response whose body is read on demand
+#[allow(async_fn_in_trait)]
+pub trait HttpResponse {
+    fn status_code(&self) -> u16;
+
+    /// Value of a response header, if present
+    fn header(&self, name: &str) -> Option<String>;
+
+    /// Read the whole body
+    async fn bytes(&mut self) -> Result<Vec<u8>>;
+
+    /// Read the whole body as UTF-8 text
+    async fn text(&mut self) -> Result<String> {
+        String::from_utf8(self.bytes().await?)
+            .map_err(|e| Error::RustError(format!("Response is not UTF-8: {}", e)))
+    }
+}
+
+/// Error of a request aborted after its timeout
+pub fn timeout_error() -> Error {
+    Error::RustError(TIMEOUT_MESSAGE.to_string())
+}
+
+/// Whether a fetch error is the timeout of the request
+pub fn is_timeout(error: &Error) -> bool {
+    matches!(error, Error::RustError(message) if message == TIMEOUT_MESSAGE)
+}
+
+/// Sends HTTP requests
+#[allow(async_fn_in_trait)]
+pub trait Fetcher {
+    type Response: HttpResponse;
+
+    async fn fetch(&self, request: HttpRequest) -> Result<Self::Response>;
+}
+
+/// Convert a request to one of the Workers runtime
+fn to_request(request: HttpRequest) -> Result<Request> {
+    let mut init = RequestInit::new();
+    init.with_method(request.method);
+    if !request.headers.is_empty() {
+        let headers = Headers::new();
+        for (name, value) in &request.headers {
+            headers.set(name, value)?;
+        }
+        init.with_headers(headers);
+    }
+    if let Some(body) = request.body {
+        init.with_body(Some(body.into()));
+    }
+    if !request.follow_redirects {
+        init.with_redirect(RequestRedirect::Manual);
+    }
+    Request::new_with_init(&request.url, &init)
+}
+
+/// `Fetcher` of the Workers runtime
+pub struct WorkerFetcher;
+
+impl Fetcher for WorkerFetcher {
+    type Response = TimedResponse;
+
+    async fn fetch(&self, request: HttpRequest) -> Result<TimedResponse> {
+        let timeout_ms = request.timeout_ms;
+        let request = to_request(request)?;
+        usage::subrequest();
+        match timeout_ms {
+            Some(timeout_ms) => send_with_timeout(request, timeout_ms).await,
+            None => Fetch::Request(request)
+                .send()
+                .await
+                .map(TimedResponse::from),
+        }
+    }
+}
+
+/// Abort handle and expiry of a request's timeout
+struct Timer {
+    /// Taken when the request is aborted
+    controller: Option<AbortController>,
+    expired: Pin<Box<Delay>>,
+}
+
+impl Timer {
+    /// Run `read` unless the timeout expires first, aborting the request then
+    async fn within<T>(&mut self, read: impl Future<Output = Result<T>>) -> Result<T> {
+        match future::select(pin!(read), self.expired.as_mut()).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => {
+                if let Some(controller) = self.controller.take() {
+                    controller.abort();
+                }
+                Err(timeout_error())
+            }
+        }
+    }
+}
+
+/// Send a request, aborting it through an `AbortController` if no response
+/// arrives in time; the same timeout keeps running while the body is read
+async fn send_with_timeout(request: Request, timeout_ms: u64) -> Result<TimedResponse> {
+    let controller = AbortController::default();
+    let signal = controller.signal();
+    let mut timer = Timer {
+        controller: Some(controller),
+        expired: Box::pin(Delay::from(Duration::from_millis(timeout_ms))),
+    };
+    let fetch = Fetch::Request(request);
+    let response = timer.within(fetch.send_with_signal(&signal)).await?;
+    Ok(TimedResponse {
+        response,
+        timer: Some(timer),
+    })
+}
+
+/// Response of the Workers runtime whose body is read within the timeout of
+/// its request, if it has one
+pub struct TimedResponse {
+    response: Response,
+    timer: Option<Timer>,
+}
+
+impl From<Response> for TimedResponse {
+    fn from(response: Response) -> Self {
+        Self {
+            response,
+            timer: None,
+        }
+    }
+}
+
+/// `Fetcher` giving requests without their own timeout a default one
+pub struct WithTimeout<'a, F> {
+    fetcher: &'a F,
+    timeout_ms: Option<u64>,
+}
+
+impl<'a, F: Fetcher> WithTimeout<'a, F> {
+    pub fn new(fetcher: &'a F, timeout_ms: Option<u64>) -> Self {
+        Self {
+            fetcher,
+            timeout_ms,
+        }
+    }
+}
+
+impl<F: Fetcher> Fetcher for WithTimeout<'_, F> {
+    type Response = F::Response;
+
+    async fn fetch(&self, mut request: HttpRequest) -> Result<F::Response> {
+        request.timeout_ms = request.timeout_ms.or(self.timeout_ms);
+        self.fetcher.fetch(request).await
+    }
+}
+
+/// `Fetcher` adding a resource's configured headers to every request
+pub struct WithHeaders<'a, F> {
+    fetcher: &'a F,
+    headers: &'a [(String, String)],
+}
+
+impl<'a, F: Fetcher> WithHeaders<'a, F> {
+    pub fn new(fetcher: &'a F, headers: &'a [(String, String)]) -> Self {
+        Self { fetcher, headers }
+    }
+}
+
+impl<F: Fetcher> Fetcher for WithHeaders<'_, F> {
+    type Response = F::Response;
+
+    async fn fetch(&self, mut request: HttpRequest) -> Result<F::Response> {
+        request.headers.extend(self.headers.iter().cloned());
+        self.fetcher.fetch(request).await
+    }
+}
+
+/// `Fetcher` calling another Worker through a service binding
+///
+/// The request URL only matters to the called Worker; its host is not resolved.
+pub struct ServiceFetcher(pub worker::Fetcher);
+
+impl Fetcher for ServiceFetcher {
+    type Response = TimedResponse;
+
+    async fn fetch(&self, request: HttpRequest) -> Result<TimedResponse> {
+        let request = to_request(request)?;
+        usage::subrequest();
+        self.0.fetch_request(request).await.map(TimedResponse::from)
+    }
+}
+
+/// Prefix of destinations naming a service binding, e.g. `service:ALERT_ROUTER/alerts`
+const SERVICE_PREFIX: &str = "service:";
+
+/// Host of requests to a destination behind a service binding (not resolved)
+const SERVICE_HOST: &str = "https://service.linkkivahti";
+
+/// Binding name and path of a `service:` destination
+pub fn parse_service(destination: &str) -> Option<(&str, &str)> {
+    let target = destination.strip_prefix(SERVICE_PREFIX)?;
+    let (binding, path) = match target.find('/') {
+        Some(slash) => target.split_at(slash),
+        None => (target, "/"),
+    };
+    (!binding.is_empty()).then_some((binding, path))
+}
+
+/// `Fetcher` of a configured destination: a URL, or a Worker behind a service binding
+pub enum Destination {
+    Url(WorkerFetcher),
+    Service(ServiceFetcher),
+}
+
+impl Destination {
+    /// Resolve a destination to its fetcher and the URL to request
+    pub fn resolve(env: &Env, destination: &str) -> Result<(Self, String)> {
+        match parse_service(destination) {
+            Some((binding, path)) => {
+                let service = env.service(binding).map_err(|_| {
+                    Error::RustError(format!("Service binding {} not configured", binding))
+                })?;
+                let url = format!("{}{}", SERVICE_HOST, path);
+                Ok((Self::Service(ServiceFetcher(service)), url))
+            }
+            None => Ok((Self::Url(WorkerFetcher), destination.to_string())),
+        }
+    }
+}
+
+impl Fetcher for Destination {
+    type Response = TimedResponse;
+
+    async fn fetch(&self, request: HttpRequest) -> Result<TimedResponse> {
+        match self {
+            Self::Url(fetcher) => fetcher.fetch(request).await,
+            Self::Service(fetcher) => fetcher.fetch(request).await,
+        }
+    }
+}
+
+impl HttpResponse for TimedResponse {
+    fn status_code(&self) -> u16 {
+        self.response.status_code()
+    }
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.response.headers().get(name).ok().flatten()
+    }
+
+    async fn bytes(&mut self) -> Result<Vec<u8>> {
+        let read = self.response.bytes();
+        let bytes = match &mut self.timer {
+            Some(timer) => timer.within(read).await?,
+            None => read.await?,
+        };
+        usage::downloaded(bytes.len());
+        Ok(bytes)
+    }
+
+    async fn text(&mut self) -> Result<String> {
+        let read = self.response.text();
+        let text = match &mut self.timer {
+            Some(timer) => timer.within(read).await?,
+            None => read.await?,
+        };
+        usage::downloaded(text.len());
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    //! In-memory `Fetcher` for tests
+
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::{HashMap, HashSet};
+
+    /// A canned response
+    #[derive(Debug, Clone, Default)]
+    pub struct MockResponse {
+        pub status: u16,
+        pub headers: Vec<(String, String)>,
+        pub body: Vec<u8>,
+        /// Fail when the body is read
+        pub body_error: bool,
+        /// Time out while the body is read, like a server trickling it
+        pub body_timeout: bool,
+    }
+
+    impl MockResponse {
+        pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+            Self {
+                status,
+                body: body.into(),
+                ..Default::default()
+            }
+        }
+
+        pub fn with_header(mut self, name: &str, value: &str) -> Self {
+            self.headers.push((name.to_string(), value.to_string()));
+            self
+        }
+    }
+
+    impl HttpResponse for MockResponse {
+        fn status_code(&self) -> u16 {
+            self.status
+        }
+
+        fn header(&self, name: &str) -> Option<String> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.clone())
+        }
+
+        async fn bytes(&mut self) -> Result<Vec<u8>> {
+            if self.body_error {
+                return Err(Error::RustError("body read failed".to_string()));
+            }
+            if self.body_timeout {
+                return Err(timeout_error());
+            }
+            Ok(self.body.clone())
+        }
+    }
+
+    /// Answers requests by URL; unknown URLs fail like a network error
+    #[derive(Default)]
+    pub struct MockFetcher {
+        responses: HashMap<String, MockResponse>,
+        authorized: HashMap<(String, String), MockResponse>,
+        ranges: HashMap<(String, String), MockResponse>,
+        slow: HashSet<String>,
+        requests: RefCell<Vec<HttpRequest>>,
+    }
+
+    impl MockFetcher {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn respond(mut self, url: &str, response: MockResponse) -> Self {
+            self.responses.insert(url.to_string(), response);
+            self
+        }
+
+        /// Answer requests to `url` carrying this `Authorization` header differently
+        pub fn respond_authorized(
+            mut self,
+            url: &str,
+            authorization: &str,
+            response: MockResponse,
+        ) -> Self {
+            self.authorized
+                .insert((url.to_string(), authorization.to_string()), response);
+            self
+        }
+
+        /// Answer requests to `url` for this `Range` header differently
+        pub fn respond_range(mut self, url: &str, range: &str, response: MockResponse) -> Self {
+            self.ranges
+                .insert((url.to_string(), range.to_string()), response);
+            self
+        }
+
+        /// Let requests to `url` with a timeout fail as timed out
+        pub fn time_out(mut self, url: &str) -> Self {
+            self.slow.insert(url.to_string());
+            self
+        }
+
+        /// Requests sent so far
+        pub fn requests(&self) -> Vec<HttpRequest> {
+            self.requests.borrow().clone()
+        }
+    }
+
+    impl Fetcher for MockFetcher {
+        type Response = MockResponse;
+
+        async fn fetch(&self, request: HttpRequest) -> Result<MockResponse> {
+            let header = |header: &str| {
+                request
+                    .headers
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(header))
+                    .map(|(_, value)| (request.url.clone(), value.clone()))
+            };
+            let response = header("Authorization")
+                .and_then(|key| self.authorized.get(&key))
+                .or_else(|| header("Range").and_then(|key| self.ranges.get(&key)))
+                .or_else(|| self.responses.get(&request.url))
+                .cloned();
+            let url = request.url.clone();
+            let timed_out = request.timeout_ms.is_some() && self.slow.contains(&url);
+            self.requests.borrow_mut().push(request);
+            if timed_out {
+                return Err(timeout_error());
+            }
+            response.ok_or_else(|| Error::RustError(format!("no route to {}", url)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::*;
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_mock_fetcher() {
+        let fetcher = MockFetcher::new().respond(
+            "https://example.com/a.js",
+            MockResponse::new(200, "ok").with_header("Content-Type", "text/javascript"),
+        );
+
+        let mut response =
+            block_on(fetcher.fetch(HttpRequest::new(Method::Get, "https://example.com/a.js")))
+                .unwrap();
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(
+            response.header("content-type").as_deref(),
+            Some("text/javascript")
+        );
+        assert_eq!(block_on(response.text()).unwrap(), "ok");
+
+        let request = HttpRequest::post_json("https://example.com/missing", "{}");
+        assert!(block_on(fetcher.fetch(request.clone())).is_err());
+        assert_eq!(fetcher.requests().len(), 2);
+        assert_eq!(fetcher.requests()[1], request);
+    }
+
+    #[test]
+    fn test_with_headers() {
+        let fetcher =
+            MockFetcher::new().respond("https://cdn.example.com/a.js", MockResponse::new(200, ""));
+        let headers = vec![("Authorization".to_string(), "Bearer t0k3n".to_string())];
+        let request = HttpRequest::new(Method::Get, "https://cdn.example.com/a.js")
+            .with_header("Accept", "*/*");
+        block_on(WithHeaders::new(&fetcher, &headers).fetch(request)).unwrap();
+        assert_eq!(
+            fetcher.requests()[0].headers,
+            vec![
+                ("Accept".to_string(), "*/*".to_string()),
+                ("Authorization".to_string(), "Bearer t0k3n".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_with_timeout() {
+        let fetcher = MockFetcher::new()
+            .respond("https://slow.example.com/a.js", MockResponse::new(200, ""))
+            .time_out("https://slow.example.com/a.js");
+        let request = HttpRequest::new(Method::Get, "https://slow.example.com/a.js");
+        assert!(block_on(fetcher.fetch(request.clone())).is_ok());
+
+        let error = block_on(WithTimeout::new(&fetcher, Some(5000)).fetch(request.clone()))
+            .err()
+            .unwrap();
+        assert!(is_timeout(&error));
+        assert!(!is_timeout(&Error::RustError("no route".to_string())));
+
+        // A request's own timeout takes precedence
+        let own = HttpRequest {
+            timeout_ms: Some(100),
+            ..request
+        };
+        let _ = block_on(WithTimeout::new(&fetcher, Some(5000)).fetch(own));
+        assert_eq!(fetcher.requests()[2].timeout_ms, Some(100));
+    }
+
+    #[test]
+    fn test_parse_service() {
+        assert_eq!(parse_service("service:ALERTS"), Some(("ALERTS", "/")));
+        assert_eq!(
+            parse_service("service:ALERTS/hooks/linkkivahti"),
+            Some(("ALERTS", "/hooks/linkkivahti"))
+        );
+        assert_eq!(parse_service("service:"), None);
+        assert_eq!(parse_service("service:/alerts"), None);
+        assert_eq!(parse_service("https://hooks.example.com/alerts"), None);
+    }
+}
@@ -0,0 +1,270 @@
+//! Serialized alert transitions
+//!
+//! Alert state lives in one KV document per scope, and KV offers no atomic
+//! read-modify-write. Two runs overlapping on the same scope (a cron tick and a
+//! manual `/check`, or a re-check alarm) can both read the stream as quiet, both
+//! see it fail and both send "opened" notifications and log the incident twice;
+//! the run lock (see `run`) only narrows that window.
+//!
+//! With a Durable Object bound as `LINKKIVAHTI_TRANSITIONS` (class
+//! `TransitionGate`), every fire and resolve transition is first claimed from the
+//! object of its alert subject (one per scope and resource, or dedup name or
+//! group). A Durable Object handles one request at a time, so claims are
+//! serialized: the first run to open (or close) a stream wins, and another run
+//! claiming the same change within `DUPLICATE_WINDOW_MS` is told it is a
+//! duplicate and drops the notification, event and incident entry. Older claims
+//! always win, so the gate cannot lock a stream out if the KV state and the object
+//! ever disagree.
+//!
+//! Without the binding, or if the object cannot be reached, transitions proceed
+//! as before.
+
+use crate::alert::{AlertStream, Transition};
+use crate::console::{console_error, console_log};
+use crate::tenant::Scope;
+use crate::trace;
+use crate::usage;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Durable Object binding serializing the transitions
+const BINDING: &str = "LINKKIVAHTI_TRANSITIONS";
+
+/// How long a transition claimed by one run makes the same claim of another run
+/// a duplicate
+const DUPLICATE_WINDOW_MS: u64 = 15 * 60_000;
+
+/// A fire or resolve transition a run wants to notify about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Claim {
+    stream: AlertStream,
+    /// `true` to open the stream, `false` to close it
+    firing: bool,
+    /// Run making the claim (see `trace`)
+    #[serde(default)]
+    run_id: Option<String>,
+    /// Time of the claim (milliseconds since the Unix epoch)
+    at: u64,
+}
+
+/// Last claimed transition of a stream, as stored by the object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Claimed {
+    firing: bool,
+    #[serde(default)]
+    run_id: Option<String>,
+    at: u64,
+}
+
+/// Answer of the object to a claim
+#[derive(Debug, Serialize, Deserialize)]
+struct Verdict {
+    granted: bool,
+    /// Run that claimed the transition first, if the claim was a duplicate
+    #[serde(default)]
+    claimed_by: Option<String>,
+}
+
+/// Decide a claim against the last claimed transition of its stream
+///
+/// Returns the verdict and, if granted, the new claimed state.
+fn decide(previous: Option<&Claimed>, claim: &Claim) -> (Verdict, Option<Claimed>) {
+    if let Some(previous) = previous {
+        let duplicate = previous.firing == claim.firing
+            && previous.run_id != claim.run_id
+            && claim.at.saturating_sub(previous.at) < DUPLICATE_WINDOW_MS;
+        if duplicate {
+            let verdict = Verdict {
+                granted: false,
+                claimed_by: previous.run_id.clone(),
+            };
+            return (verdict, None);
+        }
+    }
+    let claimed = Claimed {
+        firing: claim.firing,
+        run_id: claim.run_id.clone(),
+        at: claim.at,
+    };
+    let verdict = Verdict {
+        granted: true,
+        claimed_by: None,
+    };
+    (verdict, Some(claimed))
+}
+
+/// Keep the transitions of an alert subject that this run may notify about
+///
+/// Repeats and flap summaries are not claimed: they change no open/closed state
+/// and are already paced by the dedup state.
+pub async fn claim(
+    env: &Env,
+    scope: &Scope<'_>,
+    subject: &str,
+    due: Vec<(AlertStream, Transition)>,
+    now: u64,
+) -> Vec<(AlertStream, Transition)> {
+    let claimed = due
+        .iter()
+        .any(|(_, t)| matches!(t, Transition::Fired | Transition::Resolved));
+    if !claimed {
+        return due;
+    }
+    let Ok(namespace) = env.durable_object(BINDING) else {
+        return due;
+    };
+    let run_id = trace::current();
+
+    let mut granted = Vec::with_capacity(due.len());
+    for (stream, transition) in due {
+        let firing = match transition {
+            Transition::Fired => true,
+            Transition::Resolved => false,
+            Transition::Repeated | Transition::Flapping => {
+                granted.push((stream, transition));
+                continue;
+            }
+        };
+        let claim = Claim {
+            stream,
+            firing,
+            run_id: run_id.clone(),
+            at: now,
+        };
+        match post(&namespace, scope, subject, &claim).await {
+            Ok(verdict) if !verdict.granted => console_log!(
+                "Skipping duplicate {:?} ({}) of {}: claimed by run {}",
+                transition,
+                stream,
+                subject,
+                verdict.claimed_by.as_deref().unwrap_or("unknown")
+            ),
+            Ok(_) => granted.push((stream, transition)),
+            Err(e) => {
+                console_error!("Failed to claim transition of {}: {}", subject, e);
+                granted.push((stream, transition));
+            }
+        }
+    }
+    granted
+}
+
+async fn post(
+    namespace: &ObjectNamespace,
+    scope: &Scope<'_>,
+    subject: &str,
+    claim: &Claim,
+) -> Result<Verdict> {
+    let name = scope.key(&format!("transitions/{}", subject));
+    let stub = namespace.id_from_name(&name)?.get_stub()?;
+
+    let headers = Headers::new();
+    headers.set("Content-Type", "application/json")?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post);
+    init.with_headers(headers);
+    init.with_body(Some(serde_json::to_string(claim)?.into()));
+
+    let request = Request::new_with_init("https://transitions/", &init)?;
+    usage::subrequest();
+    let mut response = stub.fetch_with_request(request).await?;
+    if response.status_code() != 200 {
+        return Err(Error::RustError(format!(
+            "transition object returned {}",
+            response.status_code()
+        )));
+    }
+    response.json().await
+}
+
+/// Durable Object serializing the fire and resolve transitions of one alert subject
+#[durable_object]
+pub struct TransitionGate {
+    state: State,
+}
+
+impl DurableObject for TransitionGate {
+    fn new(state: State, _env: Env) -> Self {
+        Self { state }
+    }
+
+    /// The read and write of a claim happen without other I/O in between, so the
+    /// object's input gate keeps concurrent claims from interleaving.
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        if req.method() != Method::Post {
+            return Response::error("Method Not Allowed", 405);
+        }
+        let claim: Claim = req.json().await?;
+        let storage = self.state.storage();
+        let key = claim.stream.as_str();
+        let previous = storage.get::<Claimed>(key).await.ok();
+        let (verdict, claimed) = decide(previous.as_ref(), &claim);
+        if let Some(claimed) = claimed {
+            storage.put(key, &claimed).await?;
+        }
+        Response::from_json(&verdict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(firing: bool, run_id: &str, at: u64) -> Claim {
+        Claim {
+            stream: AlertStream::Availability,
+            firing,
+            run_id: Some(run_id.to_string()),
+            at,
+        }
+    }
+
+    #[test]
+    fn test_decide() {
+        // The first run opens the stream
+        let (verdict, opened) = decide(None, &claim(true, "run-a", 1000));
+        assert!(verdict.granted);
+        let opened = opened.unwrap();
+
+        // An overlapping run opening it too is a duplicate
+        let (verdict, state) = decide(Some(&opened), &claim(true, "run-b", 2000));
+        assert!(!verdict.granted);
+        assert_eq!(verdict.claimed_by.as_deref(), Some("run-a"));
+        assert_eq!(state, None);
+
+        // A retry of the same run is not
+        assert!(decide(Some(&opened), &claim(true, "run-a", 2000)).0.granted);
+
+        // Closing is granted once, then opening again
+        let (verdict, closed) = decide(Some(&opened), &claim(false, "run-b", 3000));
+        assert!(verdict.granted);
+        let closed = closed.unwrap();
+        assert!(
+            !decide(Some(&closed), &claim(false, "run-c", 4000))
+                .0
+                .granted
+        );
+        assert!(decide(Some(&closed), &claim(true, "run-c", 4000)).0.granted);
+
+        // A stale claim never locks the stream out
+        let later = 1000 + DUPLICATE_WINDOW_MS;
+        assert!(
+            decide(Some(&opened), &claim(true, "run-d", later))
+                .0
+                .granted
+        );
+    }
+
+    #[test]
+    fn test_claim_round_trip() {
+        let json = serde_json::to_string(&claim(true, "run-a", 1000)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"stream":"availability","firing":true,"run_id":"run-a","at":1000}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Claim>(&json).unwrap(),
+            claim(true, "run-a", 1000)
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Go module versions checked against the checksum database
+//!
+//! A resource with a `go` table is the module proxy URL of a pinned module
+//! version's zip, with the `h1:` hash recorded in `go.sum`:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://proxy.golang.org/github.com/!burnt!sushi/toml/@v/v1.4.0.zip"
+//! go = { sum = "h1:..." }
+//! ```
+//!
+//! Each check confirms that the proxy still serves the zip (HEAD) and looks the
+//! version up in the checksum database (`<sumdb>/lookup/<module>@<version>`,
+//! default `https://sum.golang.org`, or a mirror such as
+//! `https://proxy.golang.org/sumdb/sum.golang.org`). A zip hash that differs from the
+//! pinned one is an integrity failure. The signed tree note of the lookup response
+//! is not verified; the database is trusted over TLS.
+
+use crate::checker::{CheckError, CheckResult};
+use crate::clock;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Settings of a Go module probe (`go = { ... }` on a resource)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoModule {
+    /// Pinned zip hash from `go.sum`, e.g. `h1:...=`
+    pub sum: String,
+    /// Checksum database base URL (default `https://sum.golang.org`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sumdb: Option<String>,
+}
+
+const DEFAULT_SUMDB: &str = "https://sum.golang.org";
+
+impl GoModule {
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        if module_version(url).is_none() {
+            return Err(format!(
+                "{} is not a module proxy zip URL (https://<proxy>/<module>/@v/<version>.zip)",
+                url
+            ));
+        }
+        let valid_sum = self
+            .sum
+            .strip_prefix("h1:")
+            .and_then(|hash| BASE64.decode(hash).ok())
+            .is_some_and(|hash| hash.len() == 32);
+        if !valid_sum {
+            return Err(format!(
+                "Invalid go.sum '{}' of {}: expected h1:<base64 SHA-256>",
+                self.sum, url
+            ));
+        }
+        if let Some(sumdb) = &self.sumdb {
+            if !(sumdb.starts_with("https://") || sumdb.starts_with("http://")) {
+                return Err(format!("Invalid go.sumdb '{}' of {}", sumdb, url));
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup_url(&self, module: &str, version: &str) -> String {
+        let sumdb = self.sumdb.as_deref().unwrap_or(DEFAULT_SUMDB);
+        format!(
+            "{}/lookup/{}@{}",
+            sumdb.trim_end_matches('/'),
+            module,
+            version
+        )
+    }
+}
+
+/// Escaped module path and version of a proxy zip URL
+fn module_version(url: &str) -> Option<(String, String)> {
+    let url = Url::parse(url).ok()?;
+    let (module, file) = url.path().trim_start_matches('/').split_once("/@v/")?;
+    let version = file.strip_suffix(".zip")?;
+    if module.is_empty() || version.is_empty() {
+        return None;
+    }
+    Some((module.to_string(), version.to_string()))
+}
+
+/// Undo the module proxy's case encoding (`!a` for `A`)
+fn unescape(path: &str) -> String {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut upper = false;
+    for c in path.chars() {
+        match c {
+            '!' => upper = true,
+            c if upper => {
+                unescaped.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Zip hash of a module version in a checksum database lookup response
+///
+/// The response is a record number, the `go.sum` lines of the zip and of the
+/// `go.mod` file, then a blank line and the signed tree note.
+fn lookup_sum(body: &str, module: &str, version: &str) -> Option<String> {
+    body.lines()
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(m), Some(v), Some(sum)) if m == module && v == version => {
+                    Some(sum.to_string())
+                }
+                _ => None,
+            }
+        })
+}
+
+/// Check that a module version is served by the proxy and has its pinned hash
+pub async fn check(fetcher: &impl Fetcher, resource: &Resource, module: &GoModule) -> CheckResult {
+    let started = clock::now_ms();
+    let mut result = run_check(fetcher, resource, module).await;
+    if result.error != Some(CheckError::FetchFailed) {
+        result.latency_ms = Some(clock::now_ms().saturating_sub(started));
+    }
+    result
+}
+
+async fn run_check(fetcher: &impl Fetcher, resource: &Resource, module: &GoModule) -> CheckResult {
+    let url = resource.url.as_str();
+    console_log!("Checking Go module: {}", url);
+    let Some((path, version)) = module_version(url) else {
+        return CheckResult::failure(url.to_string(), CheckError::FetchFailed);
+    };
+
+    let response = match fetcher.fetch(HttpRequest::new(Method::Head, url)).await {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    let status_code = response.status_code();
+    if !(200..300).contains(&status_code) {
+        return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+    }
+
+    let lookup = module.lookup_url(&path, &version);
+    let mut response = match fetcher
+        .fetch(HttpRequest::new(Method::Get, lookup.as_str()))
+        .await
+    {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    if !(200..300).contains(&response.status_code()) {
+        console_error!(
+            "✗ {} - checksum database HTTP {}",
+            lookup,
+            response.status_code()
+        );
+        return CheckResult::failure(
+            url.to_string(),
+            CheckError::HttpError(response.status_code()),
+        );
+    }
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_body(&e)),
+    };
+    let Some(sum) = lookup_sum(&body, &unescape(&path), &unescape(&version)) else {
+        console_error!("✗ {} - no zip hash in {}", url, lookup);
+        return CheckResult::failure(url.to_string(), CheckError::UnexpectedResponse);
+    };
+    let valid = sum == module.sum;
+    if !valid {
+        console_error!(
+            "✗ {} - checksum database has {}, pinned {}",
+            url,
+            sum,
+            module.sum
+        );
+    }
+    CheckResult::success(url.to_string(), status_code, valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    const ZIP: &str = "https://proxy.golang.org/github.com/!burnt!sushi/toml/@v/v1.4.0.zip";
+    const LOOKUP: &str = "https://sum.golang.org/lookup/github.com/!burnt!sushi/toml@v1.4.0";
+    const SUM: &str = "h1:kNnDGxOt9y4Q5ihXAxVmc9ne3hxakiqbHWQBGuvm2/Y=";
+
+    fn lookup_body(sum: &str) -> String {
+        format!(
+            "25933227\ngithub.com/BurntSushi/toml v1.4.0 {}\n\
+             github.com/BurntSushi/toml v1.4.0/go.mod h1:ukJfTF/6rtPPRCnwkur4qwRxa8vTRFBF0uk2lLoLwho=\n\n\
+             go.sum database tree\n31206033\nXxLm1qO/eK5bTAfD5G+f9m9zD4l2b4t5QCv8vO5k0Vg=\n\n\
+             — sum.golang.org Az3grmFzCn0HfqMxWBuykUPAnm4C4VTjvhCMQAvVUWePG7Yg1bhs=\n",
+            sum
+        )
+    }
+
+    fn run(fetcher: &MockFetcher, module: GoModule) -> CheckResult {
+        let resource = Resource {
+            url: ZIP.to_string(),
+            go: Some(module.clone()),
+            ..Default::default()
+        };
+        block_on(check(fetcher, &resource, &module))
+    }
+
+    fn pinned(sum: &str) -> GoModule {
+        GoModule {
+            sum: sum.to_string(),
+            sumdb: None,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            module_version(ZIP),
+            Some((
+                "github.com/!burnt!sushi/toml".to_string(),
+                "v1.4.0".to_string()
+            ))
+        );
+        assert_eq!(
+            module_version("https://proxy.golang.org/github.com/a/b"),
+            None
+        );
+        assert_eq!(
+            unescape("github.com/!burnt!sushi/toml"),
+            "github.com/BurntSushi/toml"
+        );
+        assert_eq!(
+            lookup_sum(&lookup_body(SUM), "github.com/BurntSushi/toml", "v1.4.0").as_deref(),
+            Some(SUM)
+        );
+        assert_eq!(
+            lookup_sum(&lookup_body(SUM), "github.com/BurntSushi/toml", "v1.3.0"),
+            None
+        );
+        assert_eq!(
+            pinned(SUM).lookup_url("golang.org/x/text", "v0.3.0"),
+            "https://sum.golang.org/lookup/golang.org/x/text@v0.3.0"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(pinned(SUM).validate(ZIP).is_ok());
+        assert!(pinned("h1:abc").validate(ZIP).is_err());
+        assert!(pinned(SUM)
+            .validate("https://proxy.golang.org/github.com/a/b/@v/list")
+            .is_err());
+        let mirror = GoModule {
+            sumdb: Some("sum.golang.org".to_string()),
+            ..pinned(SUM)
+        };
+        assert!(mirror.validate(ZIP).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let fetcher = MockFetcher::new()
+            .respond(ZIP, MockResponse::new(200, ""))
+            .respond(LOOKUP, MockResponse::new(200, lookup_body(SUM)));
+        let result = run(&fetcher, pinned(SUM));
+        assert_eq!(result.sri_valid, Some(true));
+        let requests = fetcher.requests();
+        assert_eq!(requests[0].method, Method::Head);
+        assert_eq!(requests[1].url, LOOKUP);
+
+        // go.sum and the database disagree
+        let other = "h1:ukJfTF/6rtPPRCnwkur4qwRxa8vTRFBF0uk2lLoLwho=";
+        assert_eq!(run(&fetcher, pinned(other)).sri_valid, Some(false));
+
+        // A mirror of the database
+        let mirror = "https://proxy.golang.org/sumdb/sum.golang.org";
+        let fetcher = MockFetcher::new()
+            .respond(ZIP, MockResponse::new(200, ""))
+            .respond(
+                &format!("{}/lookup/github.com/!burnt!sushi/toml@v1.4.0", mirror),
+                MockResponse::new(200, lookup_body(SUM)),
+            );
+        let module = GoModule {
+            sumdb: Some(format!("{}/", mirror)),
+            ..pinned(SUM)
+        };
+        assert_eq!(run(&fetcher, module).sri_valid, Some(true));
+    }
+
+    #[test]
+    fn test_unavailable() {
+        let fetcher = MockFetcher::new()
+            .respond(ZIP, MockResponse::new(410, ""))
+            .respond(LOOKUP, MockResponse::new(200, lookup_body(SUM)));
+        assert_eq!(
+            run(&fetcher, pinned(SUM)).error,
+            Some(CheckError::HttpError(410))
+        );
+        assert_eq!(fetcher.requests().len(), 1);
+
+        let fetcher = MockFetcher::new()
+            .respond(ZIP, MockResponse::new(200, ""))
+            .respond(LOOKUP, MockResponse::new(200, "not found"));
+        assert_eq!(
+            run(&fetcher, pinned(SUM)).error,
+            Some(CheckError::UnexpectedResponse)
+        );
+    }
+}
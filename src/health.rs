@@ -0,0 +1,428 @@
+//! Rolling health score of each resource
+//!
+//! Alerts only fire on hard failures. A resource that is slowly getting slower,
+//! fails every few checks or keeps flipping between valid and mismatching SRI
+//! never crosses that line, yet is clearly unwell. The health score condenses the
+//! recent history of a resource into a number from 0 to 100, a weighted average
+//! of four components over the last `window_hours` of results:
+//!
+//! - availability: share of checks that got a usable response
+//! - latency: whether the median latency of the last `trend_hours` grew against
+//!   the rest of the window (up to 1.5× is fine, 4× or more scores 0)
+//! - integrity: share of checks with a valid SRI hash (1 without SRI)
+//! - stability: how rarely consecutive checks switched between pass and fail
+//!
+//! Scores are shown on `GET /` and exported on `/metrics`. With `threshold` set,
+//! a resource whose score drops below it while its checks still pass is announced
+//! as degraded, and as recovered once the score is back at the threshold; hard
+//! failures are left to the alert streams.
+//!
+//! ```toml
+//! [health]
+//! window_hours = 24
+//! trend_hours = 3
+//! threshold = 70
+//!
+//! [health.weights]
+//! availability = 40
+//! latency = 20
+//! integrity = 25
+//! stability = 15
+//! ```
+
+use crate::checker::CheckResult;
+use crate::clock;
+use crate::console::{console_error, console_log};
+use crate::history::{History, HistoryEntry};
+use crate::notify;
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::Env;
+
+/// KV key of the resources announced as degraded, relative to the scope prefix
+const DEGRADED_KEY: &str = "health/degraded";
+
+/// Fewest results in the window for a score
+const MIN_SAMPLES: usize = 3;
+
+/// Latency growth that still scores fully
+const TREND_TOLERANCE: f64 = 1.5;
+
+/// Latency growth that scores 0
+const TREND_LIMIT: f64 = 4.0;
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+
+/// Health settings of config.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthConfig {
+    /// Results considered for the score
+    #[serde(default = "default_window_hours")]
+    pub window_hours: u64,
+    /// Recent part of the window whose latency is compared with the rest
+    #[serde(default = "default_trend_hours")]
+    pub trend_hours: u64,
+    /// Score below which passing resources are announced as degraded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u8>,
+    #[serde(default)]
+    pub weights: Weights,
+}
+
+/// Relative weights of the score components; unset weights keep their default
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    pub availability: u32,
+    pub latency: u32,
+    pub integrity: u32,
+    pub stability: u32,
+}
+
+fn default_window_hours() -> u64 {
+    24
+}
+
+fn default_trend_hours() -> u64 {
+    3
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            window_hours: default_window_hours(),
+            trend_hours: default_trend_hours(),
+            threshold: None,
+            weights: Weights::default(),
+        }
+    }
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            availability: 40,
+            latency: 20,
+            integrity: 25,
+            stability: 15,
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_hours == 0 {
+            return Err("Health window_hours must be positive".to_string());
+        }
+        if self.trend_hours == 0 || self.trend_hours >= self.window_hours {
+            return Err("Health trend_hours must be positive and shorter than window_hours".into());
+        }
+        if self.threshold.is_some_and(|t| t > 100) {
+            return Err("Health threshold must be between 0 and 100".to_string());
+        }
+        let w = self.weights;
+        if w.availability + w.latency + w.integrity + w.stability == 0 {
+            return Err("Health weights must not all be 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Health score of a resource with its components, each in percent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Health {
+    pub score: u8,
+    pub availability: u8,
+    pub latency: u8,
+    pub integrity: u8,
+    pub stability: u8,
+}
+
+impl Health {
+    /// One-line breakdown, e.g. `62/100 (availability 90%, latency 40%, ...)`
+    pub fn describe(&self) -> String {
+        format!(
+            "{}/100 (availability {}%, latency {}%, integrity {}%, stability {}%)",
+            self.score, self.availability, self.latency, self.integrity, self.stability
+        )
+    }
+}
+
+fn percent(ratio: f64) -> u8 {
+    (ratio.clamp(0.0, 1.0) * 100.0).round() as u8
+}
+
+fn median(mut values: Vec<u64>) -> Option<f64> {
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    match values.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((values[mid - 1] + values[mid]) as f64 / 2.0),
+        _ => Some(values[mid] as f64),
+    }
+}
+
+/// Latency component: median of the recent results against the older ones
+fn latency_trend(entries: &[HistoryEntry], since: u64) -> f64 {
+    let (recent, older): (Vec<&HistoryEntry>, Vec<&HistoryEntry>) =
+        entries.iter().partition(|e| e.at >= since);
+    let latencies =
+        |entries: Vec<&HistoryEntry>| median(entries.iter().filter_map(|e| e.latency_ms).collect());
+    let (Some(recent), Some(older)) = (latencies(recent), latencies(older)) else {
+        return 1.0;
+    };
+    let growth = recent / older.max(1.0);
+    (TREND_LIMIT - growth) / (TREND_LIMIT - TREND_TOLERANCE)
+}
+
+/// Health of a resource from its history, `None` with too few recent results
+pub fn score(entries: &[HistoryEntry], config: &HealthConfig, now: u64) -> Option<Health> {
+    let start = now.saturating_sub(config.window_hours * HOUR_MS);
+    let window: Vec<HistoryEntry> = entries.iter().filter(|e| e.at >= start).cloned().collect();
+    if window.len() < MIN_SAMPLES {
+        return None;
+    }
+    let n = window.len() as f64;
+
+    let available = window
+        .iter()
+        .filter(|e| e.ok || e.sri_valid == Some(false))
+        .count();
+    let verified: Vec<bool> = window.iter().filter_map(|e| e.sri_valid).collect();
+    let integrity = match verified.len() {
+        0 => 1.0,
+        total => verified.iter().filter(|&&valid| valid).count() as f64 / total as f64,
+    };
+    let flips = window.windows(2).filter(|w| w[0].ok != w[1].ok).count();
+    let trend_start = now.saturating_sub(config.trend_hours * HOUR_MS);
+
+    let components = [
+        available as f64 / n,
+        latency_trend(&window, trend_start),
+        integrity,
+        1.0 - flips as f64 / (n - 1.0),
+    ];
+    let w = config.weights;
+    let weights = [w.availability, w.latency, w.integrity, w.stability];
+    let total: u32 = weights.iter().sum();
+    let weighted: f64 = components
+        .iter()
+        .zip(weights)
+        .map(|(c, w)| c.clamp(0.0, 1.0) * w as f64)
+        .sum();
+
+    Some(Health {
+        score: percent(weighted / total.max(1) as f64),
+        availability: percent(components[0]),
+        latency: percent(components[1]),
+        integrity: percent(components[2]),
+        stability: percent(components[3]),
+    })
+}
+
+/// Change of a resource's degraded state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Degraded,
+    Recovered,
+}
+
+/// Update the degraded resources (URL → since) with a score and return the change
+///
+/// Resources whose latest check failed keep their state: the alert streams cover them.
+fn transition(
+    degraded: &mut BTreeMap<String, u64>,
+    url: &str,
+    health: Option<Health>,
+    passing: bool,
+    threshold: u8,
+    now: u64,
+) -> Option<Change> {
+    let health = health?;
+    match (degraded.contains_key(url), health.score < threshold) {
+        (false, true) if passing => {
+            degraded.insert(url.to_string(), now);
+            Some(Change::Degraded)
+        }
+        (true, false) => {
+            degraded.remove(url);
+            Some(Change::Recovered)
+        }
+        _ => None,
+    }
+}
+
+/// Announce resources of a run whose health score crossed the threshold
+pub async fn notify_changes(
+    env: &Env,
+    scope: &Scope<'_>,
+    history: &History,
+    results: &[CheckResult],
+) {
+    let config = &scope.config().health;
+    let (Some(threshold), Some(store)) = (config.threshold, Store::from_env(env)) else {
+        return;
+    };
+    let key = scope.key(DEGRADED_KEY);
+    let mut degraded: BTreeMap<String, u64> = match store.get(&key).await {
+        Ok(degraded) => degraded.unwrap_or_default(),
+        Err(e) => {
+            console_error!("Failed to load degraded resources: {}", e);
+            return;
+        }
+    };
+
+    let now = clock::now_ms();
+    let mut changed = false;
+    for result in results.iter().filter(|r| !r.is_informational()) {
+        let entries = history.entries(&result.url);
+        let health = score(entries, config, now);
+        let passing = !result.has_problem();
+        let Some(change) = transition(&mut degraded, &result.url, health, passing, threshold, now)
+        else {
+            continue;
+        };
+        changed = true;
+        let health = health.expect("transitions need a score");
+        let message = match change {
+            Change::Degraded => format!(
+                "Health score {} fell below {} while checks still pass",
+                health.describe(),
+                threshold
+            ),
+            Change::Recovered => format!("Health score back to {}", health.describe()),
+        };
+        console_log!("{:?}: {} - {}", change, result.url, message);
+        let degraded = change == Change::Degraded;
+        if let Err(e) =
+            notify::send_health_notification(env, scope, &result.url, &message, degraded).await
+        {
+            console_error!("Failed to send health notification: {}", e);
+        }
+    }
+    if changed {
+        if let Err(e) = store.put(&key, &degraded).await {
+            console_error!("Failed to save degraded resources: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 100 * HOUR_MS;
+
+    fn entry(hours_ago: u64, ok: bool, latency_ms: u64, sri_valid: Option<bool>) -> HistoryEntry {
+        HistoryEntry {
+            at: NOW - hours_ago * HOUR_MS,
+            ok,
+            status: Some(200),
+            detail: String::new(),
+            latency_ms: Some(latency_ms),
+            sri_valid,
+            run_id: None,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        let config = HealthConfig::default();
+        let healthy: Vec<HistoryEntry> = (0..10)
+            .rev()
+            .map(|h| entry(h, true, 100, Some(true)))
+            .collect();
+        let health = score(&healthy, &config, NOW).unwrap();
+        assert_eq!(health.score, 100);
+
+        // Too few results, or only outside the window
+        assert_eq!(score(&healthy[..2], &config, NOW), None);
+        let old: Vec<HistoryEntry> = (30..35).map(|h| entry(h, true, 100, None)).collect();
+        assert_eq!(score(&old, &config, NOW), None);
+
+        // Latency grew to 2.75× in the last 3 hours: half the latency component
+        let mut slower = healthy.clone();
+        for e in slower.iter_mut().filter(|e| e.at >= NOW - 3 * HOUR_MS) {
+            e.latency_ms = Some(275);
+        }
+        let health = score(&slower, &config, NOW).unwrap();
+        assert_eq!((health.latency, health.score), (50, 90));
+
+        // SRI flipping between valid and mismatching: still available
+        let flipping: Vec<HistoryEntry> = (0..9)
+            .rev()
+            .map(|h| entry(h, h % 2 == 0, 100, Some(h % 2 == 0)))
+            .collect();
+        let health = score(&flipping, &config, NOW).unwrap();
+        assert_eq!(
+            health,
+            Health {
+                score: 74,
+                availability: 100,
+                latency: 100,
+                integrity: 56,
+                stability: 0,
+            }
+        );
+        assert_eq!(
+            health.describe(),
+            "74/100 (availability 100%, latency 100%, integrity 56%, stability 0%)"
+        );
+    }
+
+    #[test]
+    fn test_transition() {
+        let mut degraded = BTreeMap::new();
+        let health = |score| {
+            Some(Health {
+                score,
+                availability: 100,
+                latency: 100,
+                integrity: 100,
+                stability: 100,
+            })
+        };
+        let url = "https://example.com/a.js";
+        assert_eq!(
+            transition(&mut degraded, url, health(90), true, 70, 1),
+            None
+        );
+        // Failing resources are left to the alert streams
+        assert_eq!(
+            transition(&mut degraded, url, health(40), false, 70, 2),
+            None
+        );
+        assert_eq!(
+            transition(&mut degraded, url, health(60), true, 70, 3),
+            Some(Change::Degraded)
+        );
+        assert_eq!(degraded.get(url), Some(&3));
+        assert_eq!(
+            transition(&mut degraded, url, health(50), true, 70, 4),
+            None
+        );
+        assert_eq!(transition(&mut degraded, url, None, true, 70, 5), None);
+        assert_eq!(
+            transition(&mut degraded, url, health(70), true, 70, 6),
+            Some(Change::Recovered)
+        );
+        assert!(degraded.is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(HealthConfig::default().validate().is_ok());
+        let config = |toml: &str| toml::from_str::<HealthConfig>(toml).unwrap().validate();
+        assert!(config("threshold = 70").is_ok());
+        assert!(config("threshold = 101").is_err());
+        assert!(config("window_hours = 2").is_err());
+        assert!(config("[weights]\navailability = 0").is_ok());
+        assert!(
+            config("[weights]\navailability = 0\nlatency = 0\nintegrity = 0\nstability = 0")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! Check result history
+//!
+//! The most recent results of every resource are kept in a single state document,
+//! so each run costs one read and one write regardless of the number of resources
+//! (KV writes are the scarcest resource on the free plan).
+//!
+//! Each scope (default or tenant) has its own document, served at `GET /history`
+//! and `GET /t/{tenant}/history`.
+//!
+//! `STORAGE_BACKEND = "postgres"` keeps the history in Postgres instead (see
+//! `postgres`); any other backend keeps it in the state store (see `store`).
+//! Both implement `HistoryBackend`.
+
+use crate::archive;
+use crate::checker::CheckResult;
+use crate::clock;
+use crate::config;
+use crate::console::console_error;
+use crate::metrics::{self, LatencyHistogram};
+use crate::postgres::PostgresHistory;
+use crate::store::{Store, BACKEND_SETTING};
+use crate::tenant::Scope;
+use crate::trace;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use worker::*;
+
+/// KV key of the history document, relative to the scope prefix
+const HISTORY_KEY: &str = "history";
+
+/// Number of results retained per resource
+pub const MAX_ENTRIES: usize = 50;
+
+/// Number of recent results shown in alert history lines
+pub const ALERT_CONTEXT_ENTRIES: usize = 5;
+
+/// A single stored check result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Check time in milliseconds since the Unix epoch
+    pub at: u64,
+    /// Whether the check passed (available and SRI valid)
+    pub ok: bool,
+    /// HTTP status code, if a response was received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Human-readable result description
+    pub detail: String,
+    /// Check latency in milliseconds, if a response was received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Whether the SRI hash matched, if it was verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sri_valid: Option<bool>,
+    /// ID of the run that recorded the result (see `trace`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Selected response headers, e.g. `etag` and `last-modified` (see `checker::SNAPSHOT_HEADERS`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl HistoryEntry {
+    /// Create a history entry from a check result of the current run
+    pub fn from_result(result: &CheckResult, at: u64) -> Self {
+        Self {
+            at,
+            ok: !result.has_problem(),
+            status: result.status_code,
+            detail: result.description(),
+            latency_ms: result.latency_ms,
+            sri_valid: result.sri_valid,
+            run_id: trace::current(),
+            headers: result.headers.clone(),
+        }
+    }
+}
+
+/// Recent results of all resources, keyed by URL
+///
+/// Also carries the cumulative latency histograms of `/metrics` and the archive
+/// of removed resources (see `archive`), which are updated on every run anyway,
+/// to avoid a second KV write per run.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    #[serde(default)]
+    pub resources: BTreeMap<String, Vec<HistoryEntry>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub latency: BTreeMap<String, LatencyHistogram>,
+    /// Resources removed from config, with the time of removal (milliseconds since the Unix epoch)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub archived: BTreeMap<String, u64>,
+    /// URLs configured at the last run, to detect removals
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub configured: BTreeSet<String>,
+}
+
+impl History {
+    /// Append results of a run, dropping the oldest entries beyond `MAX_ENTRIES`
+    ///
+    /// Returns the added entries with their URLs.
+    pub fn record(&mut self, results: &[CheckResult], at: u64) -> Vec<(String, HistoryEntry)> {
+        let mut added = Vec::new();
+        for result in results.iter().filter(|r| !r.is_informational()) {
+            let url = result.url.as_ref().to_string();
+            let entry = HistoryEntry::from_result(result, at);
+            let entries = self.resources.entry(url.clone()).or_default();
+            entries.push(entry.clone());
+            if entries.len() > MAX_ENTRIES {
+                let excess = entries.len() - MAX_ENTRIES;
+                entries.drain(..excess);
+            }
+            added.push((url, entry));
+        }
+        added
+    }
+
+    /// Get all retained entries of a resource, oldest first
+    pub fn entries(&self, url: &str) -> &[HistoryEntry] {
+        self.resources.get(url).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Get the most recent entry of a resource
+    pub fn latest(&self, url: &str) -> Option<&HistoryEntry> {
+        self.entries(url).last()
+    }
+
+    /// Compact line describing the recent results of a resource, for alerts
+    ///
+    /// E.g. `last 5 checks: ✓ ✓ ✗ ✗ ✗, failing for 23m`. Returns `None` if
+    /// there is no history for the resource.
+    pub fn summary(&self, url: &str, count: usize, now_ms: u64) -> Option<String> {
+        let entries = self.entries(url);
+        let recent = &entries[entries.len().saturating_sub(count)..];
+        if recent.is_empty() {
+            return None;
+        }
+
+        let marks: Vec<&str> = recent
+            .iter()
+            .map(|e| if e.ok { "✓" } else { "✗" })
+            .collect();
+        let noun = if recent.len() == 1 { "check" } else { "checks" };
+        let mut line = format!("last {} {}: {}", recent.len(), noun, marks.join(" "));
+
+        // Start of the current failure streak, searched across all retained entries
+        let failing = entries.iter().rev().take_while(|e| !e.ok).count();
+        if failing == 1 {
+            line.push_str(", new failure");
+        } else if failing > 0 {
+            let since = entries[entries.len() - failing].at;
+            line.push_str(&format!(
+                ", failing for {}",
+                format_duration(now_ms.saturating_sub(since))
+            ));
+        }
+        Some(line)
+    }
+
+    /// Load the history document of a scope (empty if none stored yet)
+    pub async fn load(store: &Store, scope: &Scope<'_>) -> Result<Self> {
+        Ok(store
+            .get(&scope.key(HISTORY_KEY))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Save the history document of a scope
+    pub async fn save(&self, store: &Store, scope: &Scope<'_>) -> Result<()> {
+        store.put(&scope.key(HISTORY_KEY), self).await
+    }
+}
+
+/// Where the history of a scope is kept
+#[allow(async_fn_in_trait)]
+pub trait HistoryBackend {
+    /// Load the retained history of a scope (empty if none stored yet)
+    async fn load(&self, scope: &Scope<'_>) -> Result<History>;
+
+    /// Store the history of a scope, given the entries added since it was loaded
+    /// and the URLs whose data was purged from it
+    async fn save(
+        &self,
+        scope: &Scope<'_>,
+        history: &History,
+        added: &[(String, HistoryEntry)],
+        purged: &[String],
+    ) -> Result<()>;
+}
+
+impl HistoryBackend for Store {
+    async fn load(&self, scope: &Scope<'_>) -> Result<History> {
+        History::load(self, scope).await
+    }
+
+    async fn save(
+        &self,
+        scope: &Scope<'_>,
+        history: &History,
+        _added: &[(String, HistoryEntry)],
+        _purged: &[String],
+    ) -> Result<()> {
+        history.save(self, scope).await
+    }
+}
+
+/// History backend selected by `STORAGE_BACKEND`
+pub enum Backend {
+    State(Store),
+    Postgres(PostgresHistory),
+}
+
+impl Backend {
+    /// Open the selected backend, or `None` if it is not available
+    ///
+    /// A missing KV binding silently disables history as before; a selected
+    /// backend that cannot be opened is logged.
+    pub fn from_env(env: &Env) -> Option<Self> {
+        if config::setting(env, BACKEND_SETTING).as_deref() != Some("postgres") {
+            return Store::from_env(env).map(Self::State);
+        }
+        Self::require(env)
+            .inspect_err(|e| console_error!("History unavailable: {}", e))
+            .ok()
+    }
+
+    /// Open the selected backend, failing with a descriptive error
+    pub fn require(env: &Env) -> Result<Self> {
+        match config::setting(env, BACKEND_SETTING).as_deref() {
+            Some("postgres") => Ok(Self::Postgres(PostgresHistory::require(env)?)),
+            _ => Ok(Self::State(Store::require(env)?)),
+        }
+    }
+}
+
+impl HistoryBackend for Backend {
+    async fn load(&self, scope: &Scope<'_>) -> Result<History> {
+        match self {
+            Self::State(store) => HistoryBackend::load(store, scope).await,
+            Self::Postgres(db) => db.load(scope).await,
+        }
+    }
+
+    async fn save(
+        &self,
+        scope: &Scope<'_>,
+        history: &History,
+        added: &[(String, HistoryEntry)],
+        purged: &[String],
+    ) -> Result<()> {
+        match self {
+            Self::State(store) => HistoryBackend::save(store, scope, history, added, purged).await,
+            Self::Postgres(db) => db.save(scope, history, added, purged).await,
+        }
+    }
+}
+
+/// Format a duration compactly, e.g. `45s`, `23m`, `2h 5m`, `3d 4h`
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+/// Load history if a backend is configured, logging read failures
+pub async fn load(env: &Env, scope: &Scope<'_>) -> History {
+    let Some(backend) = Backend::from_env(env) else {
+        return History::default();
+    };
+    backend.load(scope).await.unwrap_or_else(|e| {
+        console_error!("Failed to load history: {}", e);
+        History::default()
+    })
+}
+
+/// Append the results of a run to the stored history
+///
+/// Returns the updated history, which is empty if no backend is configured or
+/// the history could not be read.
+pub async fn record_results(env: &Env, scope: &Scope<'_>, results: &[CheckResult]) -> History {
+    let Some(backend) = Backend::from_env(env) else {
+        return History::default();
+    };
+
+    let mut history = match backend.load(scope).await {
+        Ok(h) => h,
+        Err(e) => {
+            console_error!("Failed to load history: {}", e);
+            return History::default();
+        }
+    };
+    let added = history.record(results, clock::now_ms());
+    metrics::observe(&mut history, scope, results);
+    let purged = archive::reconcile(&mut history, scope, clock::now_ms());
+    if let Err(e) = backend.save(scope, &history, &added, &purged).await {
+        console_error!("Failed to save history: {}", e);
+    }
+    if !purged.is_empty() {
+        if let Some(store) = Store::from_env(env) {
+            archive::purge_incidents(&store, scope, &purged).await;
+        }
+    }
+    history
+}
+
+/// Handle `GET /history`: stored results of the scope's resources
+///
+/// Resources removed from the scope are included until their retention ends,
+/// listed with their removal time under `archived`. An optional `url` query
+/// parameter limits the response to a single resource.
+pub async fn handle_history(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let mut history = Backend::require(env)?.load(scope).await?;
+
+    // Drop entries of pages and of resources past their retention
+    archive::reconcile(&mut history, scope, clock::now_ms());
+    history
+        .resources
+        .retain(|url, _| scope.find_resource(url).is_some() || history.archived.contains_key(url));
+    history.configured.clear();
+
+    let url = req.url()?;
+    if let Some((_, filter)) = url.query_pairs().find(|(k, _)| k == "url") {
+        let filter = scope
+            .find_resource(&filter)
+            .map_or(filter.to_string(), |r| r.url.clone());
+        history.resources.retain(|url, _| *url == filter);
+    }
+    Response::from_json(&history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::CheckError;
+
+    #[test]
+    fn test_record_and_latest() {
+        let mut history = History::default();
+        let mut a = CheckResult::success("https://example.com/a.js", 200, true);
+        a.headers.insert("etag".to_string(), "\"abc\"".to_string());
+        history.record(
+            &[
+                a,
+                CheckResult::failure("https://example.com/b.js", CheckError::HttpError(404)),
+                CheckResult::test("Synthetic notification"),
+            ],
+            1000,
+        );
+
+        let a = history.latest("https://example.com/a.js").unwrap();
+        assert!(a.ok);
+        assert_eq!(a.status, Some(200));
+        assert_eq!(a.at, 1000);
+        assert_eq!(a.headers["etag"], "\"abc\"");
+
+        let b = history.latest("https://example.com/b.js").unwrap();
+        assert!(!b.ok);
+        assert_eq!(b.detail, "Failed: HTTP error: 404");
+
+        // Test notifications are not part of history
+        assert_eq!(history.resources.len(), 2);
+        assert!(history.latest("https://example.com/missing.js").is_none());
+        assert_eq!(a.run_id, None);
+    }
+
+    #[test]
+    fn test_record_run_id() {
+        let mut history = History::default();
+        let ok = CheckResult::success("https://example.com/a.js", 200, true);
+        futures::executor::block_on(trace::in_run("18f2c".to_string(), async {
+            history.record(std::slice::from_ref(&ok), 1000)
+        }));
+        let entry = history.latest("https://example.com/a.js").unwrap();
+        assert_eq!(entry.run_id.as_deref(), Some("18f2c"));
+        let json = serde_json::to_string(entry).unwrap();
+        assert!(json.ends_with(r#""run_id":"18f2c"}"#));
+    }
+
+    #[test]
+    fn test_summary() {
+        const URL: &str = "https://example.com/a.js";
+        let mut history = History::default();
+        assert_eq!(history.summary(URL, 5, 0), None);
+
+        let ok = CheckResult::success(URL, 200, true);
+        let failed = CheckResult::failure(URL, CheckError::HttpError(503));
+        for (i, result) in [&ok, &ok, &ok, &failed].iter().enumerate() {
+            history.record(std::slice::from_ref(*result), i as u64 * 60_000);
+        }
+        assert_eq!(
+            history.summary(URL, 5, 180_000).unwrap(),
+            "last 4 checks: ✓ ✓ ✓ ✗, new failure"
+        );
+
+        history.record(std::slice::from_ref(&failed), 240_000);
+        history.record(std::slice::from_ref(&failed), 300_000);
+        assert_eq!(
+            history.summary(URL, 5, 300_000 + 23 * 60_000).unwrap(),
+            "last 5 checks: ✓ ✓ ✗ ✗ ✗, failing for 25m"
+        );
+
+        history.record(std::slice::from_ref(&ok), 360_000);
+        assert_eq!(
+            history.summary(URL, 3, 360_000).unwrap(),
+            "last 3 checks: ✗ ✗ ✓"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(45_000), "45s");
+        assert_eq!(format_duration(23 * 60_000), "23m");
+        assert_eq!(format_duration((2 * 3600 + 5 * 60) * 1000), "2h 5m");
+        assert_eq!(format_duration((3 * 86400 + 4 * 3600) * 1000), "3d 4h");
+    }
+
+    #[test]
+    fn test_record_caps_entries() {
+        let mut history = History::default();
+        for i in 0..(MAX_ENTRIES as u64 + 5) {
+            history.record(
+                &[CheckResult::success("https://example.com/a.js", 200, true)],
+                i,
+            );
+        }
+
+        let entries = history.entries("https://example.com/a.js");
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].at, 5);
+        assert_eq!(entries.last().unwrap().at, MAX_ENTRIES as u64 + 4);
+    }
+}
@@ -0,0 +1,369 @@
+//! Pre- and post-run hooks
+//!
+//! Teams can bolt custom logic onto the check pipeline without forking: a hook is
+//! a Worker of their own, reached through a service binding, or any HTTPS
+//! endpoint. It gets a JSON POST for each run of a scope.
+//!
+//! The pre-run hook (`POST /pre` on a service) receives the resources about to be
+//! checked and may answer with a decision:
+//!
+//! ```json
+//! {"skip": ["https://cdn.example.com/beta.js"]}
+//! {"veto": "Origin migration in progress"}
+//! ```
+//!
+//! `skip` leaves resources out of this run, `veto` cancels the run of the scope.
+//! An empty body or `{}` checks everything. A hook that fails or answers with
+//! anything else is ignored, so a broken hook never stops monitoring.
+//!
+//! The post-run hook (`POST /post`) receives the run summary and every result
+//! after history is recorded and alerts are sent; its answer is ignored.
+//!
+//! ```toml
+//! [hooks.pre]
+//! service = "PRE_RUN_HOOK"
+//!
+//! [hooks.post]
+//! url = "https://hooks.example.com/linkkivahti"
+//! ```
+//!
+//! Requests to URL hooks carry `Authorization: Bearer {HOOK_TOKEN}` when that
+//! secret is set.
+
+use crate::checker::CheckResult;
+use crate::clock;
+use crate::config::{self, Resource};
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse, ServiceFetcher, WorkerFetcher};
+use crate::history::HistoryEntry;
+use crate::run::RunSummary;
+use crate::tenant::Scope;
+use crate::trace;
+use serde::{Deserialize, Serialize};
+use worker::{Env, Error};
+
+/// Host of the requests sent through a service binding (not resolved)
+const SERVICE_HOST: &str = "https://hook.linkkivahti";
+
+/// Where a hook is sent
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookTarget {
+    /// Service binding of a Worker in wrangler.toml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    /// HTTPS endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl HookTarget {
+    fn validate(&self, stage: &str) -> Result<(), String> {
+        match (&self.service, &self.url) {
+            (Some(_), None) => Ok(()),
+            (None, Some(url)) if url.starts_with("https://") => {
+                config::normalize_url(url).map(|_| ())
+            }
+            (None, Some(url)) => Err(format!("Hook {} url '{}' must use https", stage, url)),
+            _ => Err(format!(
+                "Hook {} needs exactly one of 'service' and 'url'",
+                stage
+            )),
+        }
+    }
+}
+
+/// Hook settings of config.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre: Option<HookTarget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post: Option<HookTarget>,
+}
+
+impl Hooks {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(pre) = &self.pre {
+            pre.validate("pre")?;
+        }
+        if let Some(post) = &self.post {
+            post.validate("post")?;
+        }
+        Ok(())
+    }
+}
+
+/// A resource as sent to hooks
+#[derive(Debug, Serialize)]
+struct HookResource<'a> {
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<&'a str>,
+}
+
+/// Body of the pre-run hook
+#[derive(Debug, Serialize)]
+struct PreRun<'a> {
+    scope: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<String>,
+    resources: Vec<HookResource<'a>>,
+}
+
+/// Answer of the pre-run hook
+#[derive(Debug, Default, PartialEq, Deserialize)]
+struct Decision {
+    /// Reason to cancel the run of the scope
+    #[serde(default)]
+    veto: Option<String>,
+    /// Resources to leave out of this run
+    #[serde(default)]
+    skip: Vec<String>,
+}
+
+/// A result as sent to the post-run hook
+#[derive(Debug, Serialize)]
+struct HookResult<'a> {
+    url: &'a str,
+    #[serde(flatten)]
+    entry: HistoryEntry,
+}
+
+/// Summary of the run sent to the post-run hook
+#[derive(Debug, Serialize)]
+struct HookSummary<'a> {
+    checked: usize,
+    problems: usize,
+    skipped: &'a [String],
+    firing: &'a [String],
+}
+
+/// Body of the post-run hook
+#[derive(Debug, Serialize)]
+struct PostRun<'a> {
+    scope: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<String>,
+    summary: HookSummary<'a>,
+    results: Vec<HookResult<'a>>,
+}
+
+/// POST a JSON body to a hook, returning the body of a successful response
+async fn send(fetcher: &impl Fetcher, request: HttpRequest) -> worker::Result<String> {
+    let mut response = fetcher.fetch(request).await?;
+    let status = response.status_code();
+    if !(200..300).contains(&status) {
+        return Err(Error::RustError(format!("HTTP {}", status)));
+    }
+    response.text().await
+}
+
+/// Send a hook to its target
+async fn call(env: &Env, target: &HookTarget, stage: &str, body: String) -> worker::Result<String> {
+    if let Some(binding) = &target.service {
+        let url = format!("{}/{}", SERVICE_HOST, stage);
+        let service = ServiceFetcher(env.service(binding)?);
+        return send(&service, HttpRequest::post_json(url, body)).await;
+    }
+    let url = target.url.as_deref().unwrap_or_default();
+    let mut request = HttpRequest::post_json(url, body);
+    if let Some(token) = config::setting(env, "HOOK_TOKEN") {
+        request = request.with_header("Authorization", &format!("Bearer {}", token));
+    }
+    send(&WorkerFetcher, request).await
+}
+
+/// Parse the answer of the pre-run hook; an empty body checks everything
+fn parse_decision(body: &str) -> Result<Decision, String> {
+    if body.trim().is_empty() {
+        return Ok(Decision::default());
+    }
+    serde_json::from_str(body).map_err(|e| format!("Invalid decision: {}", e))
+}
+
+/// Apply a decision to the resources of a run, `None` if the run is vetoed
+fn apply<'a>(decision: &Decision, resources: Vec<&'a Resource>) -> Option<Vec<&'a Resource>> {
+    if decision.veto.is_some() {
+        return None;
+    }
+    let resources = resources
+        .into_iter()
+        .filter(|r| !decision.skip.iter().any(|url| url == &r.url))
+        .collect();
+    Some(resources)
+}
+
+/// Ask the pre-run hook which resources to check, `None` if it vetoes the run
+///
+/// Returns the resources unchanged without a hook or when the hook fails.
+pub async fn pre_run<'a>(
+    env: &Env,
+    scope: &Scope<'a>,
+    resources: Vec<&'a Resource>,
+) -> Option<Vec<&'a Resource>> {
+    let Some(target) = &scope.config().hooks.pre else {
+        return Some(resources);
+    };
+    let body = PreRun {
+        scope: scope.label(),
+        tenant: scope.id(),
+        run_id: trace::current(),
+        resources: resources
+            .iter()
+            .map(|r| HookResource {
+                url: &r.url,
+                name: r.name.as_deref(),
+                group: r.group.as_deref(),
+            })
+            .collect(),
+    };
+    let decision = match call(env, target, "pre", serde_json::to_string(&body).unwrap()).await {
+        Ok(answer) => parse_decision(&answer),
+        Err(e) => Err(e.to_string()),
+    };
+    let decision = match decision {
+        Ok(decision) => decision,
+        Err(e) => {
+            console_error!("Pre-run hook failed, checking all resources: {}", e);
+            return Some(resources);
+        }
+    };
+    if let Some(reason) = &decision.veto {
+        console_log!(
+            "⏭ Pre-run hook vetoed the run of {}: {}",
+            scope.label(),
+            reason
+        );
+    } else if !decision.skip.is_empty() {
+        console_log!(
+            "⏭ Pre-run hook skipped {} resources: {}",
+            decision.skip.len(),
+            decision.skip.join(", ")
+        );
+    }
+    apply(&decision, resources)
+}
+
+/// Hand the results of a run to the post-run hook
+pub async fn post_run(env: &Env, scope: &Scope<'_>, summary: &RunSummary, results: &[CheckResult]) {
+    let Some(target) = &scope.config().hooks.post else {
+        return;
+    };
+    let at = clock::now_ms();
+    let body = PostRun {
+        scope: scope.label(),
+        tenant: scope.id(),
+        run_id: trace::current(),
+        summary: HookSummary {
+            checked: summary.checked,
+            problems: summary.problems,
+            skipped: &summary.skipped,
+            firing: &summary.firing,
+        },
+        results: results
+            .iter()
+            .filter(|r| !r.is_informational())
+            .map(|r| HookResult {
+                url: &r.url,
+                entry: HistoryEntry::from_result(r, at),
+            })
+            .collect(),
+    };
+    if let Err(e) = call(env, target, "post", serde_json::to_string(&body).unwrap()).await {
+        console_error!("Post-run hook failed: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [hooks.pre]
+            service = "PRE_RUN_HOOK"
+
+            [hooks.post]
+            url = "https://hooks.example.com/post"
+
+            [[resources]]
+            url = "https://example.com/a.js"
+            sri = "sha384-abc"
+
+            [[resources]]
+            url = "https://example.com/b.js"
+            sri = "sha384-abc"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply() {
+        let config = config();
+        let resources = || config.resources.iter().collect::<Vec<_>>();
+
+        let decision = parse_decision("").unwrap();
+        assert_eq!(apply(&decision, resources()).unwrap().len(), 2);
+
+        let decision = parse_decision(r#"{"skip": ["https://example.com/a.js"]}"#).unwrap();
+        let kept = apply(&decision, resources()).unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].url, "https://example.com/b.js");
+
+        let decision = parse_decision(r#"{"veto": "Migration"}"#).unwrap();
+        assert!(apply(&decision, resources()).is_none());
+
+        assert!(parse_decision("skip everything").is_err());
+    }
+
+    #[test]
+    fn test_send() {
+        let fetcher = MockFetcher::new()
+            .respond(
+                "https://hooks.example.com/pre",
+                MockResponse::new(200, r#"{"skip": []}"#),
+            )
+            .respond(
+                "https://hooks.example.com/broken",
+                MockResponse::new(500, ""),
+            );
+        let request = HttpRequest::post_json("https://hooks.example.com/pre", "{}");
+        assert_eq!(
+            block_on(send(&fetcher, request)).unwrap(),
+            r#"{"skip": []}"#
+        );
+        let request = HttpRequest::post_json("https://hooks.example.com/broken", "{}");
+        assert_eq!(
+            block_on(send(&fetcher, request)).unwrap_err().to_string(),
+            "HTTP 500"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(config().hooks.validate().is_ok());
+        let hooks = |toml: &str| {
+            Config::from_toml(&format!("version = \"1.0\"\n{}", toml)).map(|c| c.hooks)
+        };
+        assert!(hooks("[hooks.pre]\nurl = \"http://hooks.example.com/\"").is_err());
+        assert!(
+            hooks("[hooks.pre]\nservice = \"A\"\nurl = \"https://hooks.example.com/\"").is_err()
+        );
+        assert!(hooks("[hooks.post]").is_err());
+    }
+}
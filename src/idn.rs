@@ -0,0 +1,157 @@
+//! Internationalized domain names
+//!
+//! Resource URLs are normalized to punycode (`xn--...`) hosts when the config is
+//! loaded, and that form is what gets fetched, fingerprinted and stored.
+//! Notifications show the Unicode form for readability, together with the
+//! punycode form and a warning when a label mixes writing systems, a common sign
+//! of a lookalike (homograph) domain such as a Cyrillic `а` in `pаypal.com`.
+//! The same check warns about such hostnames in the config itself.
+
+use std::collections::BTreeSet;
+use worker::Url;
+
+/// Writing system of a character, `None` for digits, hyphens and combining marks
+/// that go with any script
+///
+/// Only the scripts that matter for lookalike domains are told apart; the
+/// Japanese kana are counted as Han so Japanese labels are not flagged.
+fn script(c: char) -> Option<&'static str> {
+    let script = match c as u32 {
+        0x30..=0x39 | 0x2D | 0x300..=0x36F => return None,
+        0x41..=0x5A | 0x61..=0x7A | 0xC0..=0x24F | 0x1E00..=0x1EFF => "Latin",
+        0x370..=0x3FF | 0x1F00..=0x1FFF => "Greek",
+        0x400..=0x52F | 0x1C80..=0x1C8F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => "Cyrillic",
+        0x530..=0x58F => "Armenian",
+        0x590..=0x5FF => "Hebrew",
+        0x600..=0x6FF | 0x750..=0x77F | 0x8A0..=0x8FF => "Arabic",
+        0x900..=0x97F => "Devanagari",
+        0xE00..=0xE7F => "Thai",
+        0x10A0..=0x10FF => "Georgian",
+        0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF => "Han",
+        0x1100..=0x11FF | 0xAC00..=0xD7AF => "Hangul",
+        _ => "Other",
+    };
+    Some(script)
+}
+
+/// Scripts that are commonly written together (Japanese, Chinese and Korean with
+/// Latin), as in the "highly restrictive" profile of Unicode TR #39
+const COMBINABLE: &[&str] = &["Latin", "Han", "Hangul"];
+
+/// Scripts mixed in a hostname label, if any label mixes scripts that are not
+/// normally written together
+fn mixed_scripts(host: &str) -> Option<(String, Vec<&'static str>)> {
+    host.split('.').find_map(|label| {
+        let scripts: BTreeSet<&'static str> = label.chars().filter_map(script).collect();
+        let mixed = scripts.len() > 1 && !scripts.iter().all(|s| COMBINABLE.contains(s));
+        mixed.then(|| (label.to_string(), scripts.into_iter().collect()))
+    })
+}
+
+/// Unicode form of a URL's host, if it is an internationalized domain name
+fn unicode_host(url: &str) -> Option<(String, String)> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return None;
+    }
+    let (unicode, result) = idna::domain_to_unicode(host);
+    result.ok()?;
+    Some((host.to_string(), unicode))
+}
+
+/// Convert a domain name to its ASCII (punycode) form, e.g. for `crawl.allow_domains`
+pub fn to_ascii(domain: &str) -> Result<String, String> {
+    idna::domain_to_ascii(domain).map_err(|_| format!("Invalid domain name '{}'", domain))
+}
+
+/// A URL with its host in Unicode, for display
+pub fn display_url(url: &str) -> String {
+    match unicode_host(url) {
+        Some((ascii, unicode)) => url.replacen(&ascii, &unicode, 1),
+        None => url.to_string(),
+    }
+}
+
+/// Notification detail for a URL with an internationalized host, e.g.
+/// `bücher.example is xn--bcher-kva.example`, with a warning if it mixes scripts
+pub fn describe(url: &str) -> Option<String> {
+    let (ascii, unicode) = unicode_host(url)?;
+    let mut detail = format!("{} is {}", unicode, ascii);
+    if let Some((label, scripts)) = mixed_scripts(&unicode) {
+        detail.push_str(&format!(
+            " ⚠ '{}' mixes {} characters, check for a lookalike domain",
+            label,
+            scripts.join(" and ")
+        ));
+    }
+    Some(detail)
+}
+
+/// Config warning for a URL whose hostname mixes scripts
+pub fn config_warning(url: &str) -> Option<String> {
+    let (ascii, unicode) = unicode_host(url)?;
+    let (label, scripts) = mixed_scripts(&unicode)?;
+    Some(format!(
+        "Hostname {} ({}) of {} mixes {} characters in '{}', check that it is not a lookalike domain",
+        unicode,
+        ascii,
+        url,
+        scripts.join(" and "),
+        label
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `pаypal.com` with a Cyrillic `а`
+    const LOOKALIKE: &str = "https://xn--pypal-4ve.com/sdk.js";
+
+    #[test]
+    fn test_mixed_scripts() {
+        assert_eq!(mixed_scripts("cdn.example.com"), None);
+        assert_eq!(mixed_scripts("bücher.example"), None);
+        assert_eq!(mixed_scripts("пример.рф"), None);
+        assert_eq!(mixed_scripts("日本語のドメイン.jp"), None);
+        assert_eq!(mixed_scripts("cdn-한국.example"), None);
+        assert_eq!(
+            mixed_scripts("pаypal.com"),
+            Some(("pаypal".to_string(), vec!["Cyrillic", "Latin"]))
+        );
+        assert_eq!(
+            mixed_scripts("www.αpple.com").map(|(_, scripts)| scripts),
+            Some(vec!["Greek", "Latin"])
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            display_url("https://xn--bcher-kva.example/b.js"),
+            "https://bücher.example/b.js"
+        );
+        assert_eq!(
+            display_url("https://cdn.example.com/a.js"),
+            "https://cdn.example.com/a.js"
+        );
+        assert_eq!(describe("https://cdn.example.com/a.js"), None);
+        assert_eq!(
+            describe("https://xn--bcher-kva.example/b.js").as_deref(),
+            Some("bücher.example is xn--bcher-kva.example")
+        );
+        assert_eq!(
+            describe(LOOKALIKE).as_deref(),
+            Some("pаypal.com is xn--pypal-4ve.com ⚠ 'pаypal' mixes Cyrillic and Latin characters, check for a lookalike domain")
+        );
+    }
+
+    #[test]
+    fn test_config_warning() {
+        assert_eq!(config_warning("https://xn--bcher-kva.example/b.js"), None);
+        let warning = config_warning(LOOKALIKE).unwrap();
+        assert!(warning.starts_with("Hostname pаypal.com (xn--pypal-4ve.com)"));
+        assert_eq!(to_ascii("Bücher.example").unwrap(), "xn--bcher-kva.example");
+    }
+}
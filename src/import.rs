@@ -0,0 +1,403 @@
+//! Backfill of historical check results (`POST /history/import`)
+//!
+//! When monitoring moves to linkkivahti from Uptime Kuma or another system, the
+//! results recorded there can be imported, so uptime figures and SLA reports do
+//! not start from zero. The body is newline-delimited JSON, one past result per
+//! line:
+//!
+//! ```text
+//! {"url":"https://cdn.example.com/app.js","at":"2025-10-01T12:00:00Z","ok":true,"status":200,"latency_ms":84}
+//! {"url":"https://cdn.example.com/app.js","at":1759320300000,"ok":false,"status":503}
+//! ```
+//!
+//! `at` is epoch milliseconds or an RFC 3339 date-time; `status`, `latency_ms`,
+//! `detail` and `error` (an error type such as `fetch_failed`) are optional.
+//! Each run of failing results of a resource becomes an availability incident,
+//! from its first failure to the next passing result (or its last failure if
+//! the import ends failing), which is what `GET /reports/sla` computes uptime
+//! from. Incidents overlapping ones already recorded are skipped, so importing
+//! the same export twice changes nothing. The newest results also fill the
+//! retained history of `GET /history` and alert history lines.
+//!
+//! Lines about resources that are not configured in the scope, or that cannot be
+//! parsed, are reported back and skipped.
+
+use crate::alert::AlertStream;
+use crate::blackout;
+use crate::clock;
+use crate::console::console_log;
+use crate::history::{Backend, History, HistoryBackend, HistoryEntry, MAX_ENTRIES};
+use crate::incident::{Incident, IncidentLog, MAX_INCIDENTS};
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+/// Most lines accepted in one import
+const MAX_LINES: usize = 100_000;
+
+/// Most skipped lines listed in the response
+const MAX_REPORTED_SKIPS: usize = 20;
+
+/// Time of an imported result: epoch milliseconds or an RFC 3339 date-time
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Timestamp {
+    Ms(u64),
+    Text(String),
+}
+
+/// One line of an import
+#[derive(Debug, Deserialize)]
+struct ImportedResult {
+    url: String,
+    at: Timestamp,
+    ok: bool,
+    #[serde(default)]
+    status: Option<u16>,
+    #[serde(default)]
+    latency_ms: Option<u64>,
+    #[serde(default)]
+    detail: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// A parsed result of a configured resource
+#[derive(Debug, Clone, PartialEq)]
+struct Imported {
+    /// Configured URL of the resource, the key of its history
+    url: String,
+    /// Alert key of the resource, the subject of its incidents
+    subject: String,
+    entry: HistoryEntry,
+    error: String,
+}
+
+/// A line that was not imported
+#[derive(Debug, PartialEq, Serialize)]
+struct Skipped {
+    /// Line number, counting from 1
+    line: usize,
+    reason: String,
+}
+
+/// Response of `POST /history/import`
+#[derive(Debug, Serialize)]
+struct ImportSummary {
+    /// Results read from the body
+    results: usize,
+    /// Resources with imported results
+    resources: usize,
+    /// Incidents added to the incident log
+    incidents: usize,
+    /// Number of skipped lines, the first ones listed in `skipped`
+    skipped_lines: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<Skipped>,
+}
+
+/// Parse one line into a result of a configured resource
+fn parse_line(line: &str, scope: &Scope<'_>, now: u64) -> std::result::Result<Imported, String> {
+    let result: ImportedResult = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let at = match &result.at {
+        Timestamp::Ms(ms) => *ms,
+        Timestamp::Text(text) => {
+            blackout::parse_datetime(text).ok_or_else(|| format!("invalid time '{}'", text))?
+        }
+    };
+    if at > now {
+        return Err(format!("time {} is in the future", clock::format_ms(at)));
+    }
+    let resource = scope
+        .find_resource(&result.url)
+        .ok_or_else(|| format!("{} is not a configured resource", result.url))?;
+    let error = result.error.unwrap_or_else(|| match result.status {
+        Some(status) if status >= 400 => format!("http_{}", status),
+        _ => "imported".to_string(),
+    });
+    let detail = result.detail.unwrap_or_else(|| {
+        let outcome = if result.ok { "Passed" } else { "Failed" };
+        match result.status {
+            Some(status) => format!("{} (HTTP {}, imported)", outcome, status),
+            None => format!("{} (imported)", outcome),
+        }
+    });
+    Ok(Imported {
+        url: resource.url.clone(),
+        subject: resource.alert_key(),
+        entry: HistoryEntry {
+            at,
+            ok: result.ok,
+            status: result.status,
+            detail,
+            latency_ms: result.latency_ms,
+            sri_valid: None,
+            run_id: None,
+            headers: BTreeMap::new(),
+        },
+        error,
+    })
+}
+
+/// Parse an NDJSON body, returning the results and the skipped lines
+fn parse(body: &str, scope: &Scope<'_>, now: u64) -> (Vec<Imported>, Vec<Skipped>) {
+    let mut results = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, line) in body.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match parse_line(line, scope, now) {
+            Ok(result) => results.push(result),
+            Err(reason) => skipped.push(Skipped {
+                line: index + 1,
+                reason,
+            }),
+        }
+    }
+    results.sort_by_key(|r| r.entry.at);
+    (results, skipped)
+}
+
+/// Availability incidents of the failure runs of each resource
+fn incidents(results: &[Imported]) -> Vec<Incident> {
+    let mut by_subject: BTreeMap<&str, Vec<&Imported>> = BTreeMap::new();
+    for result in results {
+        by_subject.entry(&result.subject).or_default().push(result);
+    }
+    let mut incidents = Vec::new();
+    for (subject, results) in by_subject {
+        let mut open: Option<(&Imported, u64)> = None;
+        for result in results {
+            match (&mut open, result.entry.ok) {
+                (None, false) => open = Some((result, result.entry.at)),
+                (Some((_, last)), false) => *last = result.entry.at,
+                (Some((first, _)), true) => {
+                    incidents.push(incident(subject, first, result.entry.at));
+                    open = None;
+                }
+                (None, true) => {}
+            }
+        }
+        if let Some((first, last)) = open {
+            incidents.push(incident(subject, first, last));
+        }
+    }
+    incidents
+}
+
+fn incident(subject: &str, first: &Imported, resolved: u64) -> Incident {
+    Incident {
+        stream: AlertStream::Availability,
+        url: first.url.clone(),
+        started: first.entry.at,
+        resolved: Some(resolved),
+        error: first.error.clone(),
+        run_id: None,
+        key: (subject != first.url).then(|| subject.to_string()),
+    }
+}
+
+/// Add imported incidents to the log in time order, skipping those that overlap
+/// a recorded incident of the same resource and stream
+///
+/// Returns the number of incidents added.
+fn merge_incidents(log: &mut IncidentLog, imported: Vec<Incident>) -> usize {
+    let overlaps = |a: &Incident, b: &Incident| {
+        a.stream == b.stream
+            && a.subject() == b.subject()
+            && a.started <= b.resolved.unwrap_or(u64::MAX)
+            && b.started <= a.resolved.unwrap_or(u64::MAX)
+    };
+    let new: Vec<Incident> = imported
+        .into_iter()
+        .filter(|i| !log.incidents.iter().any(|recorded| overlaps(i, recorded)))
+        .collect();
+    let added = new.len();
+    log.incidents.extend(new);
+    log.incidents.sort_by_key(|i| i.started);
+    if log.incidents.len() > MAX_INCIDENTS {
+        let excess = log.incidents.len() - MAX_INCIDENTS;
+        log.incidents.drain(..excess);
+    }
+    added
+}
+
+/// Add imported results to the retained history, keeping the newest `MAX_ENTRIES`
+/// of each resource
+fn merge_history(history: &mut History, results: &[Imported]) {
+    for result in results {
+        let entries = history.resources.entry(result.url.clone()).or_default();
+        if !entries.iter().any(|e| e.at == result.entry.at) {
+            entries.push(result.entry.clone());
+        }
+    }
+    for entries in history.resources.values_mut() {
+        entries.sort_by_key(|e| e.at);
+        if entries.len() > MAX_ENTRIES {
+            let excess = entries.len() - MAX_ENTRIES;
+            entries.drain(..excess);
+        }
+    }
+}
+
+/// Handle `POST /history/import`: backfill history and incidents from NDJSON
+pub async fn handle_import(env: &Env, scope: &Scope<'_>, mut req: Request) -> Result<Response> {
+    let store = Store::require(env)?;
+    let body = req.text().await?;
+    if body.lines().count() > MAX_LINES {
+        return Response::error(format!("Import at most {} lines at a time", MAX_LINES), 413);
+    }
+    let (results, mut skipped) = parse(&body, scope, clock::now_ms());
+    if results.is_empty() {
+        let reason = skipped.first().map_or("no results".to_string(), |s| {
+            format!("line {}: {}", s.line, s.reason)
+        });
+        return Response::error(format!("Nothing to import ({})", reason), 400);
+    }
+
+    let mut log = IncidentLog::load(&store, scope).await?;
+    let added = merge_incidents(&mut log, incidents(&results));
+    if added > 0 {
+        log.save(&store, scope).await?;
+    }
+    let backend = Backend::require(env)?;
+    let mut history = backend.load(scope).await?;
+    let entries: Vec<(String, HistoryEntry)> = results
+        .iter()
+        .map(|r| (r.url.clone(), r.entry.clone()))
+        .collect();
+    merge_history(&mut history, &results);
+    backend.save(scope, &history, &entries, &[]).await?;
+
+    let resources: std::collections::BTreeSet<&str> =
+        results.iter().map(|r| r.url.as_str()).collect();
+    console_log!(
+        "Imported {} results of {} resources into {}: {} incidents, {} lines skipped",
+        results.len(),
+        resources.len(),
+        scope.label(),
+        added,
+        skipped.len()
+    );
+    let skipped_lines = skipped.len();
+    skipped.truncate(MAX_REPORTED_SKIPS);
+    Response::from_json(&ImportSummary {
+        results: results.len(),
+        resources: resources.len(),
+        incidents: added,
+        skipped_lines,
+        skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const HOUR: u64 = 3_600_000;
+    const NOW: u64 = 1_762_941_600_000;
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+version = "1.0"
+[[resources]]
+url = "https://cdn.example.com/app.js"
+expected_response_contains = "export"
+
+[[resources]]
+url = "https://cdn.example.com/lib.js"
+expected_response_contains = "export"
+name = "lib"
+dedup = "name"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = config();
+        let scope = Scope::root(&config);
+        let body = format!(
+            "{}\n\n{}\n{}\n{}\nnot json\n",
+            r#"{"url":"https://cdn.example.com/app.js","at":"2025-11-01T01:00:00Z","ok":false,"status":503}"#,
+            r#"{"url":"https://CDN.example.com/app.js","at":1761955200000,"ok":true,"latency_ms":80,"monitor":7}"#,
+            r#"{"url":"https://cdn.example.com/gone.js","at":1761955200000,"ok":true}"#,
+            r#"{"url":"https://cdn.example.com/app.js","at":"2030-01-01T00:00:00Z","ok":true}"#,
+        );
+        let (results, skipped) = parse(&body, &scope, NOW);
+
+        assert_eq!(results.len(), 2);
+        // Sorted by time; URLs in any spelling map to the configured resource
+        assert_eq!(results[0].entry.at, 1_761_955_200_000);
+        assert_eq!(results[0].entry.detail, "Passed (imported)");
+        assert_eq!(results[1].url, "https://cdn.example.com/app.js");
+        assert_eq!(results[1].entry.detail, "Failed (HTTP 503, imported)");
+        assert_eq!(results[1].error, "http_503");
+
+        let lines: Vec<usize> = skipped.iter().map(|s| s.line).collect();
+        assert_eq!(lines, [4, 5, 6]);
+        assert_eq!(
+            skipped[0].reason,
+            "https://cdn.example.com/gone.js is not a configured resource"
+        );
+        assert_eq!(
+            skipped[1].reason,
+            "time 2030-01-01T00:00:00.000Z is in the future"
+        );
+    }
+
+    #[test]
+    fn test_incidents() {
+        let config = config();
+        let scope = Scope::root(&config);
+        let line = |url: &str, hour: u64, ok: bool| {
+            format!(r#"{{"url":"{}","at":{},"ok":{}}}"#, url, hour * HOUR, ok)
+        };
+        let app = "https://cdn.example.com/app.js";
+        let lib = "https://cdn.example.com/lib.js";
+        let body = [
+            line(app, 1, true),
+            line(app, 2, false),
+            line(app, 3, false),
+            line(app, 4, true),
+            line(app, 5, false),
+            line(lib, 1, false),
+            line(lib, 2, true),
+        ]
+        .join("\n");
+        let (results, _) = parse(&body, &scope, NOW);
+        let imported = incidents(&results);
+        let spans: Vec<(&str, u64, Option<u64>)> = imported
+            .iter()
+            .map(|i| (i.subject(), i.started / HOUR, i.resolved.map(|r| r / HOUR)))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (app, 2, Some(4)),
+                (app, 5, Some(5)),
+                ("name:lib", 1, Some(2))
+            ]
+        );
+
+        // Re-importing adds nothing; incidents keep time order
+        let mut log = IncidentLog::default();
+        assert_eq!(merge_incidents(&mut log, imported.clone()), 3);
+        assert_eq!(merge_incidents(&mut log, imported), 0);
+        let starts: Vec<u64> = log.incidents.iter().map(|i| i.started / HOUR).collect();
+        assert_eq!(starts, [1, 2, 5]);
+
+        let mut history = History::default();
+        merge_history(&mut history, &results);
+        merge_history(&mut history, &results);
+        assert_eq!(history.entries(app).len(), 5);
+        assert!(!history.latest(app).unwrap().ok);
+    }
+}
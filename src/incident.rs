@@ -0,0 +1,249 @@
+//! Incident log
+//!
+//! Every alert that fires opens an incident, and the incident is closed when the
+//! alert resolves. The most recent incidents of a scope are kept in a single KV
+//! document, which feeds the alert noise analytics of `GET /analytics/alerts`.
+
+use crate::alert::{AlertStream, Transition};
+use crate::checker::CheckResult;
+use crate::store::Store;
+use crate::tenant::Scope;
+use crate::trace;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// KV key of the incident log, relative to the scope prefix
+const INCIDENTS_KEY: &str = "incidents";
+
+/// Number of incidents retained per scope
+pub const MAX_INCIDENTS: usize = 1000;
+
+/// A period during which an alert stream of a resource was firing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Incident {
+    pub stream: AlertStream,
+    pub url: String,
+    /// When the alert fired (milliseconds since the Unix epoch)
+    pub started: u64,
+    /// When the alert resolved, `None` while it is still firing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<u64>,
+    /// Error type that opened the incident, e.g. `http_503` or `sri_mismatch`
+    pub error: String,
+    /// ID of the run that opened the incident (see `trace`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Alert key of a resource deduplicated by name or group, which stays the
+    /// same when its URL changes (see `Resource::alert_key`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl Incident {
+    /// What the incident is about: the alert key, or the URL if it is the key
+    pub fn subject(&self) -> &str {
+        self.key.as_deref().unwrap_or(&self.url)
+    }
+
+    /// Time from firing to resolution
+    pub fn duration_ms(&self) -> Option<u64> {
+        self.resolved.map(|at| at.saturating_sub(self.started))
+    }
+}
+
+/// Error type of a failing result in a stream
+fn error_type(stream: AlertStream, result: &CheckResult) -> String {
+    match (stream, &result.error) {
+        (AlertStream::Integrity, _) => "sri_mismatch".to_string(),
+        (AlertStream::Availability, Some(error)) => error.code(),
+        (AlertStream::Availability, None) => "unknown".to_string(),
+    }
+}
+
+/// Recent incidents of a scope, oldest first
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IncidentLog {
+    #[serde(default)]
+    pub incidents: Vec<Incident>,
+}
+
+impl IncidentLog {
+    /// Open or close incidents for an alert transition
+    ///
+    /// `subject` is the alert key of the resource (see `Resource::alert_key`).
+    /// Returns whether the log changed; repeated and flapping notifications leave it alone.
+    pub fn apply(
+        &mut self,
+        subject: &str,
+        stream: AlertStream,
+        transition: Transition,
+        result: &CheckResult,
+        now: u64,
+    ) -> bool {
+        match transition {
+            Transition::Fired => {
+                self.incidents.push(Incident {
+                    stream,
+                    url: result.url.to_string(),
+                    started: now,
+                    resolved: None,
+                    error: error_type(stream, result),
+                    run_id: trace::current(),
+                    key: (subject != result.url.as_ref()).then(|| subject.to_string()),
+                });
+                if self.incidents.len() > MAX_INCIDENTS {
+                    let excess = self.incidents.len() - MAX_INCIDENTS;
+                    self.incidents.drain(..excess);
+                }
+                true
+            }
+            Transition::Resolved => self.close(subject, stream, now),
+            Transition::Repeated | Transition::Flapping => false,
+        }
+    }
+
+    /// Close the open incident of a stream of a resource, returning whether one was open
+    pub fn close(&mut self, subject: &str, stream: AlertStream, now: u64) -> bool {
+        let open = self
+            .incidents
+            .iter_mut()
+            .rev()
+            .find(|i| i.stream == stream && i.subject() == subject && i.resolved.is_none());
+        match open {
+            Some(incident) => {
+                incident.resolved = Some(now);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Load the incident log of a scope (empty if none stored yet)
+    pub async fn load(store: &Store, scope: &Scope<'_>) -> Result<Self> {
+        Ok(store
+            .get(&scope.key(INCIDENTS_KEY))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Save the incident log of a scope
+    pub async fn save(&self, store: &Store, scope: &Scope<'_>) -> Result<()> {
+        store.put(&scope.key(INCIDENTS_KEY), self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::CheckError;
+
+    const URL: &str = "https://example.com/a.js";
+
+    #[test]
+    fn test_apply() {
+        let mut log = IncidentLog::default();
+        let down = CheckResult::failure(URL, CheckError::HttpError(503));
+        let mismatch = CheckResult::success(URL, 200, false);
+        let ok = CheckResult::success(URL, 200, true);
+
+        assert!(log.apply(
+            URL,
+            AlertStream::Availability,
+            Transition::Fired,
+            &down,
+            1_000
+        ));
+        assert!(!log.apply(
+            URL,
+            AlertStream::Availability,
+            Transition::Repeated,
+            &down,
+            2_000
+        ));
+        assert!(log.apply(
+            URL,
+            AlertStream::Integrity,
+            Transition::Fired,
+            &mismatch,
+            3_000
+        ));
+        assert!(log.apply(
+            URL,
+            AlertStream::Availability,
+            Transition::Resolved,
+            &ok,
+            4_000
+        ));
+        // Nothing open to resolve, e.g. the alert fired before the log existed
+        assert!(!log.apply(
+            URL,
+            AlertStream::Availability,
+            Transition::Resolved,
+            &ok,
+            5_000
+        ));
+
+        assert_eq!(log.incidents.len(), 2);
+        assert_eq!(log.incidents[0].error, "http_503");
+        assert_eq!(log.incidents[0].duration_ms(), Some(3_000));
+        assert_eq!(log.incidents[1].error, "sri_mismatch");
+        assert_eq!(log.incidents[1].resolved, None);
+        assert_eq!(log.incidents[1].run_id, None);
+    }
+
+    #[test]
+    fn test_alert_key() {
+        let mut log = IncidentLog::default();
+        let v1 = CheckResult::failure(
+            "https://cdn.example.com/v1/app.js",
+            CheckError::HttpError(404),
+        );
+        let v2 = CheckResult::success("https://cdn.example.com/v2/app.js", 200, true);
+
+        log.apply(
+            "group:app",
+            AlertStream::Availability,
+            Transition::Fired,
+            &v1,
+            1_000,
+        );
+        // Resolved by the check of another URL of the group
+        assert!(log.apply(
+            "group:app",
+            AlertStream::Availability,
+            Transition::Resolved,
+            &v2,
+            2_000
+        ));
+        assert_eq!(log.incidents[0].url, "https://cdn.example.com/v1/app.js");
+        assert_eq!(log.incidents[0].subject(), "group:app");
+        assert_eq!(log.incidents[0].resolved, Some(2_000));
+    }
+
+    #[test]
+    fn test_run_id() {
+        let mut log = IncidentLog::default();
+        let down = CheckResult::failure(URL, CheckError::HttpError(503));
+        futures::executor::block_on(trace::in_run("18f2c".to_string(), async {
+            log.apply(
+                URL,
+                AlertStream::Availability,
+                Transition::Fired,
+                &down,
+                1_000,
+            )
+        }));
+        assert_eq!(log.incidents[0].run_id.as_deref(), Some("18f2c"));
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut log = IncidentLog::default();
+        let down = CheckResult::failure(URL, CheckError::FetchFailed);
+        for at in 0..MAX_INCIDENTS as u64 + 5 {
+            log.apply(URL, AlertStream::Availability, Transition::Fired, &down, at);
+        }
+        assert_eq!(log.incidents.len(), MAX_INCIDENTS);
+        assert_eq!(log.incidents[0].started, 5);
+    }
+}
@@ -3,154 +3,638 @@
 //! This worker periodically checks configured URLs for availability and verifies
 //! their Subresource Integrity (SRI) hashes, alerting on failures via webhooks.
 
+mod access;
+mod action;
+mod alert;
+mod analytics;
+mod approval;
+mod archive;
+mod artifacts;
+mod auth;
+mod blackout;
+#[cfg(test)]
+mod buildcheck;
+mod callback;
+mod canary;
 mod checker;
+mod chunks;
+mod clock;
+mod companions;
 mod config;
+mod configdb;
+mod console;
+mod control;
+mod correlation;
+mod crawl;
+mod database;
+mod deploy;
+mod discord;
+mod encoding;
+mod envconfig;
+mod environment;
+mod escalation;
+mod events;
+mod feed;
+mod fetch;
+mod gate;
+mod gosum;
+mod health;
+mod history;
+mod hooks;
+mod idn;
+mod import;
+mod incident;
+mod magic;
+mod maintenance;
+mod managed;
+mod metrics;
+mod nagios;
 mod notify;
+mod oob;
+mod origins;
+mod outbox;
+mod package;
+mod pgp;
+mod postgres;
+mod propagation;
+mod recheck;
+mod registry;
+mod report;
+mod resources;
+mod run;
+mod runbook;
+mod s3;
+mod scaffold;
+mod schema;
+mod silence;
+mod sitemap;
+mod slack;
 pub mod sri;
+mod status;
+mod store;
+mod storm;
+mod sync;
+mod template;
+mod tenant;
+mod textdiff;
+mod trace;
+mod upstream;
+mod usage;
+mod watch;
+mod zabbix;
 
-use checker::check_resource;
-use futures::future::join_all;
-use serde::Serialize;
+use auth::authorize;
+use checker::{check_resource, CheckError, CheckResult};
+use config::{Config, Resource};
+use console::{console_error, console_log};
+use events::{Event, EventKind};
+use fetch::{WithHeaders, WithTimeout, WorkerFetcher};
+use futures::stream::{self, StreamExt};
+use run::{Cursor, Deadline, RunLock, RunSummary};
+use tenant::Scope;
 use worker::*;
 
-/// Status response structure for the / endpoint
-#[derive(Serialize)]
-struct StatusResponse {
-    status: &'static str,
-    worker: &'static str,
-    version: &'static str,
-    resources: Vec<ResourceInfo>,
+/// Check the resources of every scope (default and all tenants)
+pub async fn check_all_resources(env: &Env) -> RunSummary {
+    let config = sync::active_config(env).await;
+    check_scopes(env, &config, &Scope::all(&config), None).await
 }
 
-/// Individual resource information for status endpoint
-#[derive(Serialize)]
-struct ResourceInfo {
-    url: &'static str,
-    sri: &'static str,
+/// Check the resources of the given scopes selected by a tag (see `Resource::has_tag`)
+///
+/// Tagged checks are on-demand, so they check every selected resource regardless
+/// of cron triggers and subrequest budgets. Scopes already being checked by
+/// another run are skipped like in `check_scopes`.
+pub async fn check_tagged(env: &Env, scopes: &[Scope<'_>], tag: &str) -> RunSummary {
+    let deadline = Deadline::from_env(env);
+    let mut summary = RunSummary::default();
+    for (scope, resources) in tenant::tagged(scopes, tag) {
+        let lock = match RunLock::acquire(env, &scope, &deadline).await {
+            Ok(lock) => lock,
+            Err(held) => {
+                console_log!(
+                    "Skipping {}: already being checked by run {}",
+                    scope.label(),
+                    held.run_id
+                );
+                summary
+                    .busy
+                    .push(format!("{} (run {})", scope.label(), held.run_id));
+                continue;
+            }
+        };
+        summary.merge(check_scope(env, &scope, resources, deadline).await);
+        lock.release(env, &scope).await;
+    }
+    summary
 }
 
-pub async fn check_all_resources(env: &Env) {
+/// Check the planned resources of the given scopes within one run deadline
+///
+/// A scheduled run passes its cron trigger to only check the resources due on it.
+async fn check_scopes(
+    env: &Env,
+    config: &Config,
+    scopes: &[Scope<'_>],
+    cron: Option<&str>,
+) -> RunSummary {
+    let deadline = Deadline::from_env(env);
+    let plan = run::plan(env, config, scopes, cron).await;
+    let mut summary = RunSummary::default();
+    for (scope, resources) in scopes.iter().zip(plan) {
+        // Leave the scope to a run that is already checking it
+        let lock = match RunLock::acquire(env, scope, &deadline).await {
+            Ok(lock) => lock,
+            Err(held) => {
+                console_log!(
+                    "Skipping {}: already being checked by run {}",
+                    scope.label(),
+                    held.run_id
+                );
+                summary
+                    .busy
+                    .push(format!("{} (run {})", scope.label(), held.run_id));
+                continue;
+            }
+        };
+
+        // Resources skipped near the previous deadline are checked first and
+        // this run's skipped resources are carried over to the next one
+        let previous = Cursor::load(env, scope).await;
+        let resources = previous.order(resources);
+        let scope_summary = check_scope(env, scope, resources.clone(), deadline).await;
+        let cursor = previous.advance(scope, &resources, scope_summary.skipped.clone());
+        cursor.save(env, scope, &previous).await;
+
+        // Follow up on failing resources faster than the cron cadence
+        recheck::schedule(env, scope, &scope_summary.firing).await;
+        lock.release(env, scope).await;
+        summary.merge(scope_summary);
+    }
+    summary
+}
+
+/// Check the resources of a single scope, recording history and notifying its webhook
+///
+/// Checks run with bounded concurrency and stop being launched once the deadline
+/// passes; skipped resources are reported in the summary.
+async fn check_scope<'a>(
+    env: &Env,
+    scope: &Scope<'a>,
+    resources: Vec<&'a Resource>,
+    deadline: Deadline,
+) -> RunSummary {
+    // Custom logic of the team may leave resources out or cancel the run
+    let Some(resources) = hooks::pre_run(env, scope, resources).await else {
+        return RunSummary::default();
+    };
     console_log!(
-        "🔍 Starting link checks for {} resources",
-        config::resource_count()
+        "🔍 Starting link checks for {} resources ({})",
+        resources.len(),
+        scope.label()
     );
+    let mut events = vec![Event::new(EventKind::CheckStarted, clock::now_ms())
+        .with_detail(format!("{} resources", resources.len()))];
 
-    // Check all resources in parallel
-    let check_futures: Vec<_> = config::resources()
+    // Approved SRI changes take precedence over config.toml
+    let overrides = approval::load(env, scope).await;
+    let hashes = scope.config().sri_hashes();
+
+    // Check resources concurrently, launching no new checks after the deadline.
+    // Sitemaps expand into one result per checked page, crawled pages report their
+    // broken links in a single result.
+    let concurrency = run::concurrency(env);
+    let timeout = run::check_timeout(env);
+    let headers: Vec<_> = resources
         .iter()
-        .map(|resource| check_resource(&resource.url, &resource.sri))
+        .map(|r| r.request_headers(|name| config::setting(env, name)))
         .collect();
+    let outcomes: Vec<Option<Vec<CheckResult>>> =
+        stream::iter(resources.iter().copied().zip(&headers))
+            .map(|(resource, headers)| {
+                let sri = overrides
+                    .get(&resource.url)
+                    .map_or(&resource.sri, |over| &over.sri);
+                async move {
+                    if deadline.expired() {
+                        return None;
+                    }
+                    let headers = match headers {
+                        Ok(headers) => headers.as_slice(),
+                        Err(reason) => {
+                            console_error!("✗ {} - {}", resource.url, reason);
+                            let mut result =
+                                CheckResult::failure(resource.url.clone(), CheckError::FetchFailed);
+                            result.message = Some(reason.clone());
+                            return Some(vec![result]);
+                        }
+                    };
+                    let timed = WithTimeout::new(&WorkerFetcher, resource.timeout_ms.or(timeout));
+                    let fetcher = WithHeaders::new(&timed, headers);
+                    Some(match (&resource.sitemap, &resource.crawl) {
+                        (Some(sitemap), _) => {
+                            sitemap::check(&timed, resource, sitemap, concurrency, deadline).await
+                        }
+                        (None, Some(crawl)) => {
+                            vec![crawl::check(&timed, resource, crawl, concurrency, deadline).await]
+                        }
+                        (None, None) if resource.s3.is_some() => {
+                            let object = resource.s3.as_ref().unwrap();
+                            let credentials = s3::Credentials::from_env(env, &object.credentials);
+                            vec![
+                                s3::check(
+                                    &timed,
+                                    credentials.as_ref(),
+                                    resource,
+                                    object,
+                                    sri,
+                                    hashes,
+                                )
+                                .await,
+                            ]
+                        }
+                        (None, None) if resource.registry.is_some() => {
+                            let image = resource.registry.as_ref().unwrap();
+                            let credentials = image
+                                .credentials
+                                .as_deref()
+                                .and_then(|prefix| registry::Credentials::from_env(env, prefix));
+                            vec![
+                                registry::check(&timed, credentials.as_ref(), resource, image)
+                                    .await,
+                            ]
+                        }
+                        (None, None) if resource.package.is_some() => {
+                            let package = resource.package.as_ref().unwrap();
+                            vec![package::check(&timed, resource, package).await]
+                        }
+                        (None, None) if resource.go.is_some() => {
+                            let module = resource.go.as_ref().unwrap();
+                            vec![gosum::check(&timed, resource, module).await]
+                        }
+                        (None, None) if resource.pgp.is_some() => {
+                            let key = resource.pgp.as_ref().unwrap();
+                            vec![pgp::check(&timed, resource, key).await]
+                        }
+                        (None, None) if resource.feed.is_some() => {
+                            let feed = resource.feed.as_ref().unwrap();
+                            vec![feed::check(&timed, resource, feed).await]
+                        }
+                        (None, None) if resource.chunks.is_some() => {
+                            let chunks = resource.chunks.as_ref().unwrap();
+                            vec![chunks::check(&timed, resource, chunks).await]
+                        }
+                        (None, None) if !resource.origins.is_empty() => {
+                            vec![origins::check(&fetcher, resource, sri, hashes).await]
+                        }
+                        (None, None) => vec![check_resource(&fetcher, resource, sri, hashes).await],
+                    })
+                }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
 
-    let results = join_all(check_futures).await;
-
-    // Send notifications for any problems
-    for result in &results {
-        if result.has_problem() {
-            console_error!(
-                "Problem detected: {} - {}",
-                result.url,
-                result.description()
-            );
-            if let Err(e) = notify::send_failure_notification(env, result).await {
-                console_error!("Failed to send notification: {}", e);
+    let mut results = Vec::with_capacity(outcomes.len());
+    let mut skipped = Vec::new();
+    let mut schemas = schema::Schemas::new(env, scope);
+    for ((resource, outcome), headers) in resources.iter().zip(outcomes).zip(&headers) {
+        let timed = WithTimeout::new(&WorkerFetcher, resource.timeout_ms.or(timeout));
+        let fetcher = WithHeaders::new(&timed, headers.as_deref().unwrap_or_default());
+        match outcome {
+            Some(mut checked) => {
+                // Caches may still serve the previous content while a change propagates
+                if let Some(rollout) = overrides
+                    .get(&resource.url)
+                    .and_then(|over| over.rollout.as_ref())
+                {
+                    checked.iter_mut().for_each(|result| rollout.verify(result));
+                }
+                for result in &mut checked {
+                    result.tags.clone_from(&resource.tags);
+                    oob::verify(&timed, resource, result).await;
+                    companions::check(&fetcher, resource, hashes, result).await;
+                    schemas.check(resource, result).await;
+                }
+                results.extend(checked)
             }
+            None => skipped.push(resource.url.clone()),
         }
     }
 
-    // Log summary
-    let successful = results.iter().filter(|r| !r.has_problem()).count();
-    let failed = results.len() - successful;
+    usage::checked(results.len());
+    let history = history::record_results(env, scope, &results).await;
+    watch::notify_changes(env, scope, &history, &results).await;
+    health::notify_changes(env, scope, &history, &results).await;
+    artifacts::mirror_verified(env, scope, &results).await;
 
-    console_log!(
-        "✓ Check complete: {}/{} successful, {} failed",
-        successful,
-        results.len(),
-        failed
-    );
+    // Send notifications for alert streams that fired, repeat or resolved
+    let firing = alert::process(env, scope, &results, &history, &mut events).await;
+    events::record(env, scope, events).await;
+
+    let summary = RunSummary {
+        checked: results.len(),
+        problems: results.iter().filter(|r| r.has_problem()).count(),
+        skipped,
+        firing,
+        ..Default::default()
+    };
+    hooks::post_run(env, scope, &summary, &results).await;
+    if summary.skipped.is_empty() {
+        console_log!("✓ Check complete: {}", summary.describe());
+    } else {
+        console_error!(
+            "⚠ Check truncated ({}): {} - skipped: {}",
+            scope.label(),
+            summary.describe(),
+            summary.skipped.join(", ")
+        );
+    }
+    summary
 }
 
 /// Scheduled event handler - triggered by cron
 ///
-/// This checks all configured resources and sends notifications for any failures.
+/// This syncs the remote config (if enabled), checks the resources due on the
+/// triggering cron expression, sends notifications for any failures and runs the notification canary when due.
+/// Nothing runs while monitoring is disabled (see `control`).
 #[event(scheduled)]
-async fn scheduled(_event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
-    check_all_resources(&env).await;
-}
-
-/// Check Authorization header for secured endpoints to match the access token
-/// (as configured by the ACCESS_TOKEN environment variable, with a random built-in fallback).
-/// Returns an error if the token is missing or invalid.
-/// # Arguments
-/// * `req` - The incoming HTTP request
-/// # Returns
-/// Ok(()) if authorized, Err otherwise
-fn check_auth(env: &Env, req: &Request) -> Result<()> {
-    if let Ok(access_token) = env.secret("ACCESS_TOKEN") {
-        let auth_header = req
-            .headers()
-            .get("Authorization")?
-            .ok_or_else(|| Error::RustError("Missing Authorization header".to_string()))?;
-
-        if auth_header != format!("Bearer {}", access_token) {
-            return Err(Error::RustError("Unauthorized".to_string()));
-        }
-
-        Ok(())
-    } else {
-        Err(Error::RustError("ACCESS_TOKEN not configured".to_string()))
-    }
+async fn scheduled(event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    let run_id = trace::new_id();
+    usage::start(&run_id);
+    trace::in_run(run_id.clone(), async {
+        let control = control::load(&env).await;
+        if !control.enabled {
+            console_log!("⏸ Skipping scheduled run: {}", control.describe());
+            usage::finish(&run_id);
+            return;
+        }
+        sync::run_scheduled(&env).await;
+        deploy::verify_if_new(env.clone()).await;
+        let config = sync::active_config(&env).await;
+        let cron = event.cron();
+        let summary = check_scopes(&env, &config, &Scope::all(&config), Some(&cron)).await;
+        console_log!("Run complete: {}", summary.describe());
+        canary::run_scheduled(&env).await;
+        if let Some(meter) = usage::finish(&run_id) {
+            usage::record(env.clone(), usage::Invocation::Scheduled, meter).await;
+        }
+    })
+    .await
 }
 
 /// HTTP fetch event handler
 ///
 /// Provides:
 /// - GET / - Combined health and configuration endpoint
-/// - POST /check - Trigger immediate link check (secured with access token)
-/// - POST /notify - Test notification webhook (secured with access token)
+/// - POST /check - Trigger immediate link check of all scopes (secured with access token)
+/// - GET /check/nagios - Latest results as Nagios/Icinga plugin output (secured with access token)
+/// - POST /notify - Test notification to every webhook destination, with a per-destination report (secured with access token)
+/// - GET /history - Stored check results (secured with access token)
+/// - GET /resources?include=archived - Configured resources, optionally with removed ones still retained (secured with access token)
+/// - POST /resources - Add a resource to monitor without a config change (secured with access token)
+/// - PUT /resources/{id} / DELETE /resources/{id} - Replace or remove a resource added through the API (secured with access token)
+/// - POST /history/import - Backfill past results from NDJSON for history and SLA reports (secured with access token)
+/// - GET /metrics - Prometheus metrics with latency histograms (secured with access token)
+/// - GET /stats - Request counters per endpoint and caller (secured with access token)
+/// - GET /stats/usage - Daily subrequest, KV and CPU usage against plan limits (secured with an unscoped access token)
+/// - GET /analytics/alerts - Alert noise summary over a date range (secured with access token)
+/// - GET /reports/sla - Monthly SLA report as HTML or JSON (secured with access token)
+/// - GET /zabbix/discovery - Zabbix low-level discovery of resources (secured with access token)
+/// - GET /zabbix/items - Current values of all resources for Zabbix (secured with access token)
+/// - GET /notifications/last?url= - Last notification sent about a resource (secured with access token)
+/// - GET /events/log?since= - State transitions as newline-delimited JSON (secured with access token)
+/// - GET /silences - Active silences (secured with access token)
+/// - POST /silences - Silence alerts matching label matchers (secured with access token)
+/// - DELETE /silences/{id} - Expire a silence (secured with access token)
+/// - POST /sri - Request an SRI hash change (secured with access token)
+/// - GET /sri/pending - List SRI changes awaiting approval (secured with access token)
+/// - GET /propagation?url= - Share of cached copies serving a resource's expected content (secured with access token)
+/// - POST /sri/pending/{id}/approve|reject - Decide on a pending SRI change (secured with access token)
+/// - GET /mirror/{name} - Last SRI-verified copy of a resource (public, needs the artifact bucket)
+/// - GET /actions/{token} - Re-check a resource via a signed link from a notification (public)
+/// - POST /slack/interactions - Buttons on Slack alerts (verified with SLACK_SIGNING_SECRET)
+/// - POST /slack/command - The /linkkivahti slash command (verified with SLACK_SIGNING_SECRET)
+/// - POST /discord/interactions - Buttons on Discord alerts and /linkstatus (verified with DISCORD_PUBLIC_KEY)
+/// - POST /callbacks/ack - Acknowledge or close an alert from an external alerting system (verified with CALLBACK_SIGNING_SECRET)
+/// - POST /config/sync - Sync config from CONFIG_SYNC_URL (or CONFIG_URL) now (secured with an unscoped access token)
+/// - POST /config/d1 - Store a config.toml from the body, or the current config, in the D1 config tables (secured with an unscoped access token)
+/// - GET /config/export - Effective config with the environment overrides in force (secured with an unscoped access token)
+/// - GET /config/template?urls= - `[[resources]]` entries with computed SRI hashes for new URLs (secured with an unscoped access token)
+/// - PUT /admin/monitoring - Disable monitoring or set degraded mode during an incident (secured with an unscoped access token)
+/// - POST /admin/token/rotate - Replace an access token, keeping the old one valid for a grace period (secured with an unscoped access token)
+/// - /t/{tenant}/... - The same endpoints for a tenant; `/check` only checks that tenant
 /// - Other paths return 404
+///
+/// Every response carries the request's run ID in `X-Request-Id`.
 #[event(fetch)]
-async fn fetch(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+async fn fetch(req: Request, env: Env, ctx: Context) -> Result<Response> {
+    let run_id = trace::new_id();
+    usage::start(&run_id);
+    let response = trace::in_run(run_id.clone(), route(req, env.clone(), &ctx)).await;
+    if let Some(meter) = usage::finish(&run_id) {
+        ctx.wait_until(trace::in_run(
+            run_id.clone(),
+            usage::record(env, usage::Invocation::Fetch, meter),
+        ));
+    }
+    let mut response = response?;
+    response.headers_mut().set(trace::HEADER, &run_id)?;
+    Ok(response)
+}
+
+/// Dispatch a request to its endpoint
+async fn route(req: Request, env: Env, ctx: &Context) -> Result<Response> {
     let url = req.url()?;
-    let path = url.path();
+    let config = sync::active_config(&env).await;
+
+    let (scope, path) = match tenant::split_path(url.path()) {
+        (None, path) => (Scope::root(&config), path),
+        (Some(id), path) => match Scope::tenant(&config, id) {
+            Some(scope) => (scope, path),
+            None => (Scope::root(&config), ""),
+        },
+    };
+
+    // A new version checks its bootstrap resources once the response is on its way
+    ctx.wait_until(trace::in_current_run(deploy::verify_if_new(env.clone())));
+
+    // Count the request once the response is on its way
+    let endpoint = access::endpoint(&req.method(), path);
+    ctx.wait_until(trace::in_current_run(access::record(
+        env.clone(),
+        access::key(&scope),
+        endpoint,
+        format!("{} {}", req.method(), url.path()),
+        auth::caller(&env, &req).await,
+    )));
 
-    match (req.method(), path.as_ref()) {
-        (Method::Get, "/") => handle_status(),
+    match (req.method(), path) {
+        (Method::Get, "/") => status::handle_status(&env, &scope, &req).await,
         (Method::Post, "/check") => {
-            check_auth(&env, &req)?;
-            check_all_resources(&env).await;
-            Response::from_html("Link check triggered")
+            authorize(&env, &req, &scope).await?;
+            let scopes = match scope.id() {
+                Some(_) => vec![scope],
+                None => Scope::all(&config),
+            };
+            let summary = match tenant::requested_tag(&url) {
+                Some(tag) if tenant::tagged(&scopes, &tag).is_empty() => {
+                    return Response::error(format!("No resources tagged '{}'", tag), 404);
+                }
+                Some(tag) => check_tagged(&env, &scopes, &tag).await,
+                None => check_scopes(&env, &config, &scopes, None).await,
+            };
+            Response::from_html(format!("Link check triggered: {}", summary.describe()))
+        }
+        (Method::Get, "/check/nagios") => {
+            authorize(&env, &req, &scope).await?;
+            nagios::handle_nagios(&env, &scope).await
         }
         (Method::Post, "/notify") => {
-            check_auth(&env, &req)?;
-            notify::send_test_notification(&env).await?;
-            Response::from_html("Test notification sent")
+            authorize(&env, &req, &scope).await?;
+            let report = notify::send_test_notifications(&env, &scope).await;
+            let status = if report.failed > 0 { 502 } else { 200 };
+            Ok(Response::from_json(&report)?.with_status(status))
+        }
+        (Method::Get, "/metrics") => {
+            authorize(&env, &req, &scope).await?;
+            metrics::handle_metrics(&env, &scope).await
+        }
+        (Method::Get, "/history") => {
+            authorize(&env, &req, &scope).await?;
+            history::handle_history(&env, &scope, &req).await
+        }
+        (Method::Get, "/resources") => {
+            authorize(&env, &req, &scope).await?;
+            resources::handle_resources(&env, &scope, &req).await
+        }
+        (Method::Post, "/resources") => {
+            let principal = authorize(&env, &req, &scope).await?;
+            managed::handle_create(&env, &scope, &principal, req).await
+        }
+        (Method::Put, p) if managed::parse_resource_path(p).is_some() => {
+            let principal = authorize(&env, &req, &scope).await?;
+            let id = managed::parse_resource_path(p).unwrap();
+            managed::handle_update(&env, &scope, &principal, id, req).await
+        }
+        (Method::Delete, p) if managed::parse_resource_path(p).is_some() => {
+            let principal = authorize(&env, &req, &scope).await?;
+            let id = managed::parse_resource_path(p).unwrap();
+            managed::handle_delete(&env, &scope, &principal, id).await
+        }
+        (Method::Post, "/history/import") => {
+            authorize(&env, &req, &scope).await?;
+            import::handle_import(&env, &scope, req).await
+        }
+        (Method::Get, "/stats") => {
+            authorize(&env, &req, &scope).await?;
+            access::handle_stats(&env, &scope).await
+        }
+        (Method::Get, "/stats/usage") if scope.id().is_none() => {
+            authorize(&env, &req, &scope).await?;
+            usage::handle_usage(&env, &req).await
+        }
+        (Method::Get, "/analytics/alerts") => {
+            authorize(&env, &req, &scope).await?;
+            analytics::handle_alerts(&env, &scope, &req).await
+        }
+        (Method::Get, "/reports/sla") => {
+            authorize(&env, &req, &scope).await?;
+            report::handle_sla(&env, &scope, &req).await
+        }
+        (Method::Get, "/zabbix/discovery") => {
+            authorize(&env, &req, &scope).await?;
+            zabbix::handle_discovery(&scope)
+        }
+        (Method::Get, "/zabbix/items") => {
+            authorize(&env, &req, &scope).await?;
+            zabbix::handle_items(&env, &scope).await
+        }
+        (Method::Get, "/notifications/last") => {
+            authorize(&env, &req, &scope).await?;
+            outbox::handle_last(&env, &scope, &req).await
+        }
+        (Method::Get, "/events/log") => {
+            authorize(&env, &req, &scope).await?;
+            events::handle_log(&env, &scope, &req).await
+        }
+        (Method::Get, "/silences") => {
+            authorize(&env, &req, &scope).await?;
+            silence::handle_list(&env, &scope).await
+        }
+        (Method::Post, "/silences") => {
+            let principal = authorize(&env, &req, &scope).await?;
+            silence::handle_create(&env, &scope, &principal, req).await
+        }
+        (Method::Delete, p) if silence::parse_silence_path(p).is_some() => {
+            let principal = authorize(&env, &req, &scope).await?;
+            let id = silence::parse_silence_path(p).unwrap();
+            silence::handle_delete(&env, &scope, &principal, id).await
+        }
+        (Method::Post, "/sri") => {
+            let principal = authorize(&env, &req, &scope).await?;
+            approval::handle_request(&env, &scope, &principal, req).await
+        }
+        (Method::Get, "/propagation") => {
+            authorize(&env, &req, &scope).await?;
+            propagation::handle_propagation(&env, &scope, &req).await
+        }
+        (Method::Get, "/sri/pending") => {
+            authorize(&env, &req, &scope).await?;
+            approval::handle_list_pending(&env, &scope).await
+        }
+        (Method::Post, p) if approval::parse_decision_path(p).is_some() => {
+            let principal = authorize(&env, &req, &scope).await?;
+            let (id, decision) = approval::parse_decision_path(p).unwrap();
+            approval::handle_decision(&env, &scope, &principal, id, decision).await
+        }
+        (Method::Get, p) if p.starts_with("/mirror/") => match artifacts::parse_mirror_path(p) {
+            Some(name) => artifacts::handle_mirror(&env, &scope, &req, &name).await,
+            None => Response::error("Not Found", 404),
+        },
+        (Method::Get, p) if scope.id().is_none() && action::parse_action_path(p).is_some() => {
+            let token = action::parse_action_path(p).unwrap();
+            action::handle_action(&env, &config, &req, token).await
+        }
+        (Method::Post, "/callbacks/ack") => callback::handle_ack(&env, &scope, req).await,
+        (Method::Post, "/slack/interactions") if scope.id().is_none() => {
+            slack::handle_interaction(&env, config.clone(), ctx, req).await
+        }
+        (Method::Post, "/slack/command") if scope.id().is_none() => {
+            slack::handle_command(&env, config.clone(), ctx, req).await
+        }
+        (Method::Post, "/discord/interactions") if scope.id().is_none() => {
+            discord::handle_interaction(&env, config.clone(), ctx, req).await
+        }
+        (Method::Post, "/config/sync") if scope.id().is_none() => {
+            authorize(&env, &req, &scope).await?;
+            sync::handle_sync(&env).await
+        }
+        (Method::Post, "/config/d1") if scope.id().is_none() => {
+            authorize(&env, &req, &scope).await?;
+            configdb::handle_store(&env, req).await
+        }
+        (Method::Put, "/admin/monitoring") if scope.id().is_none() => {
+            let principal = authorize(&env, &req, &scope).await?;
+            control::handle_update(&env, &principal, req).await
+        }
+        (Method::Post, "/admin/token/rotate") if scope.id().is_none() => {
+            let principal = authorize(&env, &req, &scope).await?;
+            auth::handle_rotate(&env, &principal, req).await
+        }
+        (Method::Get, "/config/export") if scope.id().is_none() => {
+            authorize(&env, &req, &scope).await?;
+            let base = sync::base_config(&env).await;
+            envconfig::handle_export(&env, &base, &config)
+        }
+        (Method::Get, "/config/template") if scope.id().is_none() => {
+            authorize(&env, &req, &scope).await?;
+            scaffold::handle_template(&scope, &req).await
         }
         _ => Response::error("Not Found", 404),
     }
 }
 
-/// Handle / (root) endpoint
-///
-/// Returns combined health status and configuration in a single response
-fn handle_status() -> Result<Response> {
-    let resources: Vec<ResourceInfo> = config::resources()
-        .iter()
-        .map(|r| ResourceInfo {
-            url: r.url,
-            sri: r.sri,
-        })
-        .collect();
-
-    let status = StatusResponse {
-        status: "healthy",
-        worker: "linkkivahti",
-        version: config::version(),
-        resources,
-    };
-
-    Response::from_json(&status)
-}
-
 #[cfg(test)]
 mod tests {
     // No tests needed for this module currently
@@ -0,0 +1,119 @@
+//! File type checks by magic number (`expected_magic`)
+//!
+//! When a CDN swaps a tarball for an HTML error page, the SRI check only says the
+//! hash does not match. Giving the leading bytes of the file names the actual
+//! problem instead:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://example.com/tool-linux-x86_64"
+//! sri = "sha384-..."
+//! expected_magic = '\x7fELF'
+//! ```
+//!
+//! The value is written as a TOML literal string (single quotes), where `\xHH`
+//! is a byte in hex and `\\` a backslash; other characters stand for their UTF-8
+//! bytes. The body is compared before it is hashed, so a mismatch fails the
+//! availability check with `WrongFileType` rather than reporting an SRI mismatch.
+
+/// Decode an `expected_magic` value into the bytes it stands for
+pub fn parse(value: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(c) = rest.chars().next() {
+        if let Some(escape) = rest.strip_prefix('\\') {
+            if let Some(escaped) = escape.strip_prefix('\\') {
+                bytes.push(b'\\');
+                rest = escaped;
+                continue;
+            }
+            let hex = escape
+                .strip_prefix('x')
+                .and_then(|hex| hex.get(..2))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("invalid escape in '{}', expected \\xHH or \\\\", value))?;
+            bytes.push(hex);
+            rest = &escape[3..];
+        } else {
+            let mut buf = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if bytes.is_empty() {
+        return Err("expected_magic must not be empty".to_string());
+    }
+    Ok(bytes)
+}
+
+/// Bytes as printable text, escaping the rest like `expected_magic` does
+pub fn display(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'\\' => "\\\\".to_string(),
+            0x20..=0x7e => (b as char).to_string(),
+            _ => format!("\\x{:02x}", b),
+        })
+        .collect()
+}
+
+/// Why a body does not start with the expected bytes, `None` if it does
+///
+/// Names the start of what was served and its `Content-Type`, e.g.
+/// `Wrong file type: expected '\x7fELF', got '<!DO' (text/html)`.
+pub fn mismatch(expected: &[u8], content: &[u8], content_type: Option<&str>) -> Option<String> {
+    if content.starts_with(expected) {
+        return None;
+    }
+    let served = &content[..content.len().min(expected.len().max(4))];
+    let served = if served.is_empty() {
+        "an empty body".to_string()
+    } else {
+        format!("'{}'", display(served))
+    };
+    Some(match content_type {
+        Some(content_type) => format!(
+            "Wrong file type: expected '{}', got {} ({})",
+            display(expected),
+            served,
+            content_type
+        ),
+        None => format!(
+            "Wrong file type: expected '{}', got {}",
+            display(expected),
+            served
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(r"\x7fELF").unwrap(), b"\x7fELF");
+        assert_eq!(parse(r"PK\x03\x04").unwrap(), b"PK\x03\x04");
+        assert_eq!(parse("%PDF").unwrap(), b"%PDF");
+        assert_eq!(parse(r"a\\b").unwrap(), b"a\\b");
+        assert_eq!(parse("\u{7f}ELF").unwrap(), b"\x7fELF");
+        assert!(parse(r"\x7").is_err());
+        assert!(parse(r"\n").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let elf = parse(r"\x7fELF").unwrap();
+        assert_eq!(mismatch(&elf, b"\x7fELF\x02\x01", None), None);
+        assert_eq!(
+            mismatch(&elf, b"<!DOCTYPE html>", Some("text/html")),
+            Some(r"Wrong file type: expected '\x7fELF', got '<!DO' (text/html)".to_string())
+        );
+        assert_eq!(
+            mismatch(b"PK\x03\x04", b"", None),
+            Some(r"Wrong file type: expected 'PK\x03\x04', got an empty body".to_string())
+        );
+    }
+}
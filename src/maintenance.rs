@@ -0,0 +1,312 @@
+//! Paused resources and per-resource maintenance windows
+//!
+//! A resource with `enabled = false` is not checked at all, by scheduled, manual,
+//! tagged or deployment runs, re-checks or re-check buttons. One with
+//! `maintenance` windows is not checked while one of them is in effect, so
+//! planned upstream maintenance raises no alerts:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://cdn.example.com/app.js"
+//! sri = "sha384-..."
+//! maintenance = [
+//!     "0 2 * * SUN 4h",                                 # Sundays 02:00-06:00 UTC
+//!     "2026-11-20T22:00:00Z/2026-11-21T02:00:00Z",     # a one-off window
+//! ]
+//! ```
+//!
+//! A recurring window is a cron expression in the format of Workers cron triggers
+//! (UTC, five fields, `*`, lists, ranges, steps and day or month names) followed
+//! by a duration of at most a day; a one-off window is a `start/end` pair of
+//! RFC 3339 date-times or `YYYY-MM-DD` dates, end exclusive.
+//!
+//! Skipped resources keep their alert state: an alert firing before the window
+//! keeps firing, and a resource still broken once the window ends alerts on its
+//! next check.
+
+use crate::blackout;
+use crate::clock;
+use crate::config::Resource;
+
+const MINUTE_MS: u64 = 60 * 1000;
+
+/// Longest recurring window, in minutes
+const MAX_WINDOW_MINUTES: u64 = 24 * 60;
+
+const MONTHS: &[&str] = &[
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Allowed values of a cron field, as a bit per value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    values: u64,
+    /// Whether the field is `*`, which matters for the day fields
+    any: bool,
+}
+
+impl Field {
+    fn contains(&self, value: u64) -> bool {
+        self.values & (1 << value) != 0
+    }
+}
+
+/// Parse one value of a cron field, a number or a name (the first name is `min`)
+fn parse_value(value: &str, names: &[&str], min: u64) -> Option<u64> {
+    value.parse().ok().or_else(|| {
+        let upper = value.to_ascii_uppercase();
+        let index = names.iter().position(|name| *name == upper)?;
+        Some(index as u64 + min)
+    })
+}
+
+/// Parse a cron field with values from `min` to `max`
+fn parse_field(field: &str, min: u64, max: u64, names: &[&str]) -> Option<Field> {
+    let mut values = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse().ok().filter(|&s: &u64| s > 0)?),
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (
+                    parse_value(start, names, min)?,
+                    parse_value(end, names, min)?,
+                ),
+                // `5/15` means from 5 to the end in steps of 15
+                None if item.contains('/') => (parse_value(range, names, min)?, max),
+                None => {
+                    let value = parse_value(range, names, min)?;
+                    (value, value)
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            values |= 1 << value;
+        }
+    }
+    Some(Field {
+        values,
+        any: field == "*",
+    })
+}
+
+/// A parsed five-field cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cron {
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl Cron {
+    fn parse(fields: &[&str]) -> Option<Self> {
+        let [minutes, hours, days, months, weekdays] = fields else {
+            return None;
+        };
+        let mut weekdays = parse_field(weekdays, 0, 7, WEEKDAYS)?;
+        // Both 0 and 7 are Sunday
+        if weekdays.contains(7) {
+            weekdays.values |= 1;
+        }
+        Some(Self {
+            minutes: parse_field(minutes, 0, 59, &[])?,
+            hours: parse_field(hours, 0, 23, &[])?,
+            days: parse_field(days, 1, 31, &[])?,
+            months: parse_field(months, 1, 12, MONTHS)?,
+            weekdays,
+        })
+    }
+
+    /// Whether the minute starting at `ms` matches
+    fn matches(&self, ms: u64) -> bool {
+        let of_day = ms % 86_400_000;
+        let days = (ms / 86_400_000) as i64;
+        let (_, month, day) = clock::civil_from_days(days);
+        // `clock::weekday` counts from Monday, cron from Sunday
+        let weekday = (clock::weekday(days) as u64 + 1) % 7;
+        let day_matches = match (self.days.any, self.weekdays.any) {
+            (true, true) => true,
+            (false, true) => self.days.contains(day as u64),
+            (true, false) => self.weekdays.contains(weekday),
+            // Like cron, a day matching either restricted field is enough
+            (false, false) => self.days.contains(day as u64) || self.weekdays.contains(weekday),
+        };
+        self.minutes.contains(of_day / MINUTE_MS % 60)
+            && self.hours.contains(of_day / 3_600_000)
+            && self.months.contains(month as u64)
+            && day_matches
+    }
+}
+
+/// A maintenance window of a resource
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Window {
+    /// A one-off window, end exclusive (milliseconds since the Unix epoch)
+    Fixed { start: u64, end: u64 },
+    /// A window starting at every match of a cron expression
+    Recurring { cron: Cron, minutes: u64 },
+}
+
+impl Window {
+    fn parse(entry: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = entry.split_whitespace().collect();
+        if let [cron @ .., duration] = fields.as_slice() {
+            if cron.len() == 5 {
+                let cron = Cron::parse(cron)
+                    .ok_or_else(|| format!("invalid cron expression in '{}'", entry))?;
+                let minutes = clock::parse_duration(duration)
+                    .filter(|&m| m <= MAX_WINDOW_MINUTES)
+                    .ok_or_else(|| {
+                        format!(
+                            "invalid duration '{}', use e.g. 30m or 4h (at most 1d)",
+                            duration
+                        )
+                    })?;
+                return Ok(Self::Recurring { cron, minutes });
+            }
+        }
+        let times = entry.split_once('/').and_then(|(start, end)| {
+            Some((
+                blackout::parse_datetime(start.trim())?,
+                blackout::parse_datetime(end.trim())?,
+            ))
+        });
+        match times {
+            Some((start, end)) if start < end => Ok(Self::Fixed { start, end }),
+            Some(_) => Err(format!("'{}' ends before it starts", entry)),
+            None => Err(format!(
+                "'{}' is neither \"<cron> <duration>\" nor \"<start>/<end>\"",
+                entry
+            )),
+        }
+    }
+
+    fn contains(&self, now: u64) -> bool {
+        match self {
+            Self::Fixed { start, end } => (*start..*end).contains(&now),
+            Self::Recurring { cron, minutes } => {
+                let minute = now - now % MINUTE_MS;
+                (0..*minutes).any(|ago| {
+                    minute
+                        .checked_sub(ago * MINUTE_MS)
+                        .is_some_and(|start| cron.matches(start))
+                })
+            }
+        }
+    }
+}
+
+/// Check a `maintenance` entry
+pub fn validate(entry: &str) -> Result<(), String> {
+    Window::parse(entry).map(|_| ())
+}
+
+/// Why a resource is not checked at `now`: disabled, or the maintenance window
+/// in effect
+pub fn paused(resource: &Resource, now: u64) -> Option<String> {
+    if resource.enabled == Some(false) {
+        return Some("disabled".to_string());
+    }
+    resource
+        .maintenance
+        .iter()
+        .find(|entry| Window::parse(entry).is_ok_and(|window| window.contains(now)))
+        .map(|entry| format!("maintenance {}", entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2025-11-12T10:00:00Z, a Wednesday
+    const NOW: u64 = 1_762_941_600_000;
+    const HOUR: u64 = 60 * MINUTE_MS;
+
+    fn cron(expression: &str) -> Cron {
+        Cron::parse(&expression.split_whitespace().collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_cron() {
+        assert!(cron("0 10 * * *").matches(NOW));
+        assert!(!cron("0 10 * * *").matches(NOW + MINUTE_MS));
+        assert!(cron("*/15 9-11 12 NOV *").matches(NOW));
+        assert!(cron("0 10 * * WED").matches(NOW));
+        assert!(cron("0 10 * * 3").matches(NOW));
+        assert!(!cron("0 10 * * SUN,0,7").matches(NOW));
+        assert!(cron("0 10 * * 0,7").matches(NOW + 4 * 24 * HOUR));
+        // Either restricted day field is enough
+        assert!(cron("0 10 1 * WED").matches(NOW));
+        assert!(!cron("0 10 1 * MON").matches(NOW));
+        assert!(!cron("0 10 * OCT,DEC *").matches(NOW));
+        assert!(cron("5/15 * * * *").matches(NOW + 20 * MINUTE_MS));
+
+        for invalid in [
+            "60 * * * *",
+            "* * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "0 0 * * FUNDAY",
+        ] {
+            let fields: Vec<&str> = invalid.split_whitespace().collect();
+            assert_eq!(Cron::parse(&fields), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        let weekly = Window::parse("0 8 * * WED 4h").unwrap();
+        assert!(weekly.contains(NOW));
+        assert!(weekly.contains(NOW + 2 * HOUR - 1));
+        assert!(!weekly.contains(NOW + 2 * HOUR));
+        assert!(!weekly.contains(NOW - 2 * HOUR - 1));
+
+        let once = Window::parse("2025-11-12T09:00:00Z/2025-11-12T10:00:00Z").unwrap();
+        assert!(!once.contains(NOW));
+        assert!(once.contains(NOW - 1));
+        assert!(Window::parse("2025-11-12/2025-11-13")
+            .unwrap()
+            .contains(NOW));
+
+        assert!(validate("0 2 * * SUN 2d").is_err());
+        assert!(validate("0 2 * * SUN").is_err());
+        assert!(validate("2025-11-13/2025-11-12").is_err());
+        assert!(validate("tonight").is_err());
+    }
+
+    #[test]
+    fn test_paused() {
+        let resource = Resource {
+            url: "https://cdn.example.com/app.js".to_string(),
+            maintenance: vec![
+                "0 2 * * SUN 4h".to_string(),
+                "2025-11-12T09:30:00Z/2025-11-12T11:00:00Z".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            paused(&resource, NOW).as_deref(),
+            Some("maintenance 2025-11-12T09:30:00Z/2025-11-12T11:00:00Z")
+        );
+        assert_eq!(paused(&resource, NOW + 2 * HOUR), None);
+
+        let disabled = Resource {
+            enabled: Some(false),
+            ..resource
+        };
+        assert_eq!(
+            paused(&disabled, NOW + 2 * HOUR).as_deref(),
+            Some("disabled")
+        );
+    }
+}
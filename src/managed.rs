@@ -0,0 +1,444 @@
+//! Resources managed over the HTTP API
+//!
+//! Automation pipelines can register newly published artifacts for monitoring
+//! without touching the repository:
+//!
+//! - `POST /resources` adds a resource, given as JSON with the fields of a
+//!   `[[resources]]` entry, and answers with its assigned `id`
+//! - `PUT /resources/{id}` replaces it
+//! - `DELETE /resources/{id}` removes it (its history is archived, see `archive`)
+//!
+//! Managed resources of all scopes are kept in a single KV document and added to
+//! the active config on every invocation (see `sync::active_config`). Each change
+//! is validated against the config.toml in effect like the file itself; an entry
+//! that no longer fits a later config.toml, e.g. because the file now lists the
+//! same URL, is skipped with a logged error until it is changed or removed.
+//! Resources of config.toml cannot be changed through the API.
+//!
+//! Any token authorized for a scope, tenant tokens included, can manage its
+//! resources, so managed resources cannot name secrets of the worker: header
+//! `{{HEADER_SECRET_...}}` placeholders, `registry.credentials` and `s3` probes,
+//! which always sign with a key pair, are rejected, as they would send the
+//! secret or a login made with it to whatever URL the caller chose.
+
+use crate::auth::Principal;
+use crate::clock;
+use crate::config::{self, Config, Resource};
+use crate::console::{console_error, console_log};
+use crate::store::Store;
+use crate::sync;
+use crate::template;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use worker::*;
+
+/// KV key of the managed resources of all scopes
+const MANAGED_KEY: &str = "resources/managed";
+
+/// Path prefix of a single managed resource
+const RESOURCE_PATH_PREFIX: &str = "/resources/";
+
+/// Config with the managed resources added, cached per isolate together with
+/// the base config and the managed resources it was built from
+#[allow(clippy::type_complexity)]
+static MERGED: Mutex<Option<(Arc<Config>, String, Arc<Config>)>> = Mutex::new(None);
+
+/// A resource added through the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManagedResource {
+    pub id: String,
+    /// Tenant the resource belongs to, `None` for the default scope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Name of the token that added or last changed the resource
+    pub updated_by: String,
+    /// When the resource was added or last changed (milliseconds since the Unix epoch)
+    pub updated_at: u64,
+    pub resource: Resource,
+}
+
+/// Managed resources of all scopes
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ManagedResources {
+    #[serde(default)]
+    pub resources: Vec<ManagedResource>,
+}
+
+impl ManagedResources {
+    /// Load the managed resources (empty if none stored yet)
+    pub async fn load(store: &Store) -> Result<Self> {
+        Ok(store.get(MANAGED_KEY).await?.unwrap_or_default())
+    }
+
+    pub async fn save(&self, store: &Store) -> Result<()> {
+        store.put(MANAGED_KEY, self).await
+    }
+
+    /// Managed resource of a scope by ID
+    fn position(&self, scope: &Scope, id: &str) -> Option<usize> {
+        self.resources
+            .iter()
+            .position(|m| m.id == id && m.tenant.as_deref() == scope.id())
+    }
+
+    /// ID of the managed resource of a scope with a URL
+    pub fn id_of(&self, scope: &Scope, url: &str) -> Option<&str> {
+        self.resources
+            .iter()
+            .find(|m| m.tenant.as_deref() == scope.id() && m.resource.url == url)
+            .map(|m| m.id.as_str())
+    }
+}
+
+/// Add managed resources to a config
+///
+/// Entries that make the config invalid are left out; their IDs are returned
+/// with the reason.
+pub fn merge(base: &Config, managed: &ManagedResources) -> (Config, Vec<(String, String)>) {
+    let mut config = base.clone();
+    let mut rejected = Vec::new();
+    for entry in &managed.resources {
+        let mut candidate = config.clone();
+        let resources = match &entry.tenant {
+            None => &mut candidate.resources,
+            Some(id) => match candidate.tenants.iter_mut().find(|t| t.id == *id) {
+                Some(tenant) => &mut tenant.resources,
+                None => {
+                    rejected.push((entry.id.clone(), format!("Unknown tenant '{}'", id)));
+                    continue;
+                }
+            },
+        };
+        if let Err(e) = check_secrets(&entry.resource) {
+            rejected.push((entry.id.clone(), e));
+            continue;
+        }
+        resources.push(entry.resource.clone());
+        match candidate
+            .revalidate()
+            .and_then(|()| sync::validate(&candidate))
+        {
+            Ok(()) => config = candidate,
+            Err(e) => rejected.push((entry.id.clone(), e)),
+        }
+    }
+    (config, rejected)
+}
+
+/// Add the managed resources to the base config
+///
+/// Returns the base config unchanged without the state store or managed resources.
+pub async fn apply(env: &Env, base: Arc<Config>) -> Arc<Config> {
+    let Some(store) = Store::from_env(env) else {
+        return base;
+    };
+    let managed = match ManagedResources::load(&store).await {
+        Ok(managed) if !managed.resources.is_empty() => managed,
+        Ok(_) => return base,
+        Err(e) => {
+            console_error!("Failed to load managed resources: {}", e);
+            return base;
+        }
+    };
+    let text = serde_json::to_string(&managed).unwrap_or_default();
+
+    let mut cached = MERGED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_base, cached_text, config)) = cached.as_ref() {
+        if Arc::ptr_eq(cached_base, &base) && *cached_text == text {
+            return Arc::clone(config);
+        }
+    }
+    let (config, rejected) = merge(&base, &managed);
+    for (id, reason) in rejected {
+        console_error!("Skipping managed resource {}: {}", id, reason);
+    }
+    let config = Arc::new(config);
+    *cached = Some((base, text, Arc::clone(&config)));
+    config
+}
+
+/// Parse `/resources/{id}`
+pub fn parse_resource_path(path: &str) -> Option<&str> {
+    path.strip_prefix(RESOURCE_PATH_PREFIX)
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+fn new_resource_id() -> String {
+    let random = (js_sys::Math::random() * f64::from(u32::MAX)) as u32;
+    format!("{:x}{:08x}", clock::now_ms(), random)
+}
+
+/// Make sure a managed resource names no secrets of the worker
+fn check_secrets(resource: &Resource) -> std::result::Result<(), String> {
+    if let Some(name) = resource
+        .headers
+        .iter()
+        .find(|(_, value)| template::placeholders(value).next().is_some())
+        .map(|(name, _)| name)
+    {
+        return Err(format!(
+            "Header {} of {}: placeholders are only allowed in config.toml",
+            name, resource.url
+        ));
+    }
+    if resource
+        .registry
+        .as_ref()
+        .is_some_and(|image| image.credentials.is_some())
+    {
+        return Err(format!(
+            "registry.credentials of {}: private images are only allowed in config.toml",
+            resource.url
+        ));
+    }
+    if resource.s3.is_some() {
+        return Err(format!(
+            "s3 of {}: bucket objects are only allowed in config.toml",
+            resource.url
+        ));
+    }
+    Ok(())
+}
+
+/// Read a resource from a request body, with its URL in canonical form
+async fn read_resource(req: &mut Request) -> std::result::Result<Resource, String> {
+    let mut resource: Resource = req
+        .json()
+        .await
+        .map_err(|e| format!("Invalid resource: {}", e))?;
+    resource.url = config::normalize_url(&resource.url)?;
+    check_secrets(&resource)?;
+    Ok(resource)
+}
+
+/// Validate the managed resources after a change to the entry with `id`
+async fn check(env: &Env, managed: &ManagedResources, id: &str) -> std::result::Result<(), String> {
+    let base = sync::base_config(env).await;
+    let (_, rejected) = merge(&base, managed);
+    match rejected.into_iter().find(|(rejected, _)| rejected == id) {
+        Some((_, reason)) => Err(reason),
+        None => Ok(()),
+    }
+}
+
+/// Handle `POST /resources`: add a resource to the scope
+pub async fn handle_create(
+    env: &Env,
+    scope: &Scope<'_>,
+    principal: &Principal,
+    mut req: Request,
+) -> Result<Response> {
+    let resource = match read_resource(&mut req).await {
+        Ok(resource) => resource,
+        Err(reason) => return Response::error(reason, 400),
+    };
+    if scope.find_resource(&resource.url).is_some() {
+        return Response::error(format!("{} is already configured", resource.url), 409);
+    }
+
+    let store = Store::require(env)?;
+    let mut managed = ManagedResources::load(&store).await?;
+    let entry = ManagedResource {
+        id: new_resource_id(),
+        tenant: scope.id().map(str::to_string),
+        updated_by: principal.name.clone(),
+        updated_at: clock::now_ms(),
+        resource,
+    };
+    managed.resources.push(entry.clone());
+    if let Err(reason) = check(env, &managed, &entry.id).await {
+        return Response::error(reason, 400);
+    }
+    managed.save(&store).await?;
+    console_log!(
+        "Resource {} ({}) added by {}",
+        entry.id,
+        entry.resource.url,
+        principal.name
+    );
+    Ok(Response::from_json(&entry)?.with_status(201))
+}
+
+/// Handle `PUT /resources/{id}`: replace a managed resource
+pub async fn handle_update(
+    env: &Env,
+    scope: &Scope<'_>,
+    principal: &Principal,
+    id: &str,
+    mut req: Request,
+) -> Result<Response> {
+    let resource = match read_resource(&mut req).await {
+        Ok(resource) => resource,
+        Err(reason) => return Response::error(reason, 400),
+    };
+    let store = Store::require(env)?;
+    let mut managed = ManagedResources::load(&store).await?;
+    let Some(index) = managed.position(scope, id) else {
+        return Response::error("Managed resource not found", 404);
+    };
+    let entry = &mut managed.resources[index];
+    entry.resource = resource;
+    entry.updated_by = principal.name.clone();
+    entry.updated_at = clock::now_ms();
+    let entry = entry.clone();
+    if let Err(reason) = check(env, &managed, id).await {
+        return Response::error(reason, 400);
+    }
+    managed.save(&store).await?;
+    console_log!(
+        "Resource {} ({}) changed by {}",
+        id,
+        entry.resource.url,
+        principal.name
+    );
+    Response::from_json(&entry)
+}
+
+/// Handle `DELETE /resources/{id}`: remove a managed resource
+pub async fn handle_delete(
+    env: &Env,
+    scope: &Scope<'_>,
+    principal: &Principal,
+    id: &str,
+) -> Result<Response> {
+    let store = Store::require(env)?;
+    let mut managed = ManagedResources::load(&store).await?;
+    let Some(index) = managed.position(scope, id) else {
+        return Response::error("Managed resource not found", 404);
+    };
+    let entry = managed.resources.remove(index);
+    managed.save(&store).await?;
+    console_log!(
+        "Resource {} ({}) removed by {}",
+        id,
+        entry.resource.url,
+        principal.name
+    );
+    Response::from_json(&entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::RegistryImage;
+    use crate::s3::S3Object;
+
+    const SRI: &str = "sha256-uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=";
+
+    fn base() -> Config {
+        Config::from_toml(&format!(
+            "version = \"1.0\"\n\
+             [[resources]]\nurl = \"https://example.com/a.js\"\nsri = \"{sri}\"\n\
+             [[tenants]]\nid = \"web\"\n\
+             [[tenants.resources]]\nurl = \"https://example.com/w.js\"\nsri = \"{sri}\"\n",
+            sri = SRI
+        ))
+        .unwrap()
+    }
+
+    fn entry(id: &str, tenant: Option<&str>, url: &str, sri: &str) -> ManagedResource {
+        ManagedResource {
+            id: id.to_string(),
+            tenant: tenant.map(str::to_string),
+            updated_by: "ci".to_string(),
+            updated_at: 0,
+            resource: Resource {
+                url: url.to_string(),
+                sri: sri.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let managed = ManagedResources {
+            resources: vec![
+                entry("1", None, "https://EXAMPLE.com/b.js", SRI),
+                entry("2", Some("web"), "https://example.com/b.js", SRI),
+                // Already in config.toml
+                entry("3", None, "https://example.com/a.js", SRI),
+                entry("4", Some("ops"), "https://example.com/c.js", SRI),
+                entry("5", None, "https://example.com/d.js", "sha384-abc"),
+            ],
+        };
+        let (config, rejected) = merge(&base(), &managed);
+        let urls: Vec<&str> = config.resources.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            ["https://example.com/a.js", "https://example.com/b.js"]
+        );
+        assert_eq!(config.tenants[0].resources.len(), 2);
+        let rejected: Vec<&str> = rejected.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(rejected, ["3", "4", "5"]);
+
+        assert_eq!(
+            managed.id_of(
+                &Scope::tenant(&config, "web").unwrap(),
+                "https://example.com/b.js"
+            ),
+            Some("2")
+        );
+        assert_eq!(managed.position(&Scope::root(&config), "2"), None);
+    }
+
+    #[test]
+    fn test_check_secrets() {
+        let mut leaky = entry("1", Some("web"), "https://attacker.example/", SRI);
+        leaky
+            .resource
+            .headers
+            .insert("X".to_string(), "{{HEADER_SECRET_CDN}}".to_string());
+        assert_eq!(
+            check_secrets(&leaky.resource).unwrap_err(),
+            "Header X of https://attacker.example/: placeholders are only allowed in config.toml"
+        );
+        let mut plain = entry("2", None, "https://example.com/b.js", SRI);
+        plain
+            .resource
+            .headers
+            .insert("Accept".to_string(), "application/javascript".to_string());
+        assert_eq!(check_secrets(&plain.resource), Ok(()));
+
+        let registry = |credentials: Option<&str>| Resource {
+            url: "https://registry.attacker.example/v2/app/manifests/1.0".to_string(),
+            registry: Some(RegistryImage {
+                digest: format!("sha256:{}", "0".repeat(64)),
+                credentials: credentials.map(str::to_string),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            check_secrets(&registry(Some("GHCR"))).unwrap_err(),
+            "registry.credentials of https://registry.attacker.example/v2/app/manifests/1.0: \
+             private images are only allowed in config.toml"
+        );
+        assert_eq!(check_secrets(&registry(None)), Ok(()));
+        let s3 = Resource {
+            url: "https://bucket.attacker.example/app.js".to_string(),
+            s3: Some(S3Object::default()),
+            ..Default::default()
+        };
+        assert_eq!(
+            check_secrets(&s3).unwrap_err(),
+            "s3 of https://bucket.attacker.example/app.js: bucket objects are only allowed in config.toml"
+        );
+
+        // Entries stored before the check are skipped too
+        let managed = ManagedResources {
+            resources: vec![leaky, plain],
+        };
+        let (_, rejected) = merge(&base(), &managed);
+        let rejected: Vec<&str> = rejected.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(rejected, ["1"]);
+    }
+
+    #[test]
+    fn test_parse_resource_path() {
+        assert_eq!(parse_resource_path("/resources/19a6b2c3"), Some("19a6b2c3"));
+        assert_eq!(parse_resource_path("/resources/"), None);
+        assert_eq!(parse_resource_path("/resources/a/b"), None);
+        assert_eq!(parse_resource_path("/resources"), None);
+    }
+}
@@ -0,0 +1,289 @@
+//! Prometheus metrics endpoint (`GET /metrics`)
+//!
+//! Check latency is exported as a proper histogram, so p95/p99 can be computed with
+//! `histogram_quantile()` in Grafana. Bucket counters are cumulative across runs:
+//! each run adds its samples to the histograms persisted in the history document,
+//! and the endpoint only renders them. Bucket boundaries come from `[metrics]` in
+//! config.toml and can differ per resource `group`; changing them resets the
+//! affected histograms, which Prometheus treats as a counter reset.
+
+use crate::checker::CheckResult;
+use crate::clock;
+use crate::health;
+use crate::history::{self, History, HistoryBackend};
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use worker::{Env, Response, Result};
+
+/// Cumulative latency histogram of a resource
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    /// Upper bucket boundaries in milliseconds
+    pub bounds_ms: Vec<u64>,
+    /// Number of samples per bucket (not cumulative), one more than `bounds_ms` for `+Inf`
+    pub counts: Vec<u64>,
+    /// Sum of all samples in milliseconds
+    pub sum_ms: u64,
+    /// Number of samples
+    pub count: u64,
+}
+
+impl LatencyHistogram {
+    /// Create an empty histogram with the given boundaries
+    pub fn new(bounds_ms: &[u64]) -> Self {
+        Self {
+            bounds_ms: bounds_ms.to_vec(),
+            counts: vec![0; bounds_ms.len() + 1],
+            sum_ms: 0,
+            count: 0,
+        }
+    }
+
+    /// Add a sample
+    pub fn observe(&mut self, latency_ms: u64) {
+        let bucket = self
+            .bounds_ms
+            .iter()
+            .position(|&bound| latency_ms <= bound)
+            .unwrap_or(self.bounds_ms.len());
+        self.counts[bucket] += 1;
+        self.sum_ms += latency_ms;
+        self.count += 1;
+    }
+}
+
+/// Add the latencies of a run to the persisted histograms of a scope
+///
+/// Histograms whose configured boundaries changed are started over.
+pub fn observe(history: &mut History, scope: &Scope, results: &[CheckResult]) {
+    let metrics = &scope.config().metrics;
+    for result in results {
+        let (Some(latency), Some(resource)) = (result.latency_ms, scope.find_resource(&result.url))
+        else {
+            continue;
+        };
+        let bounds = metrics.buckets_for(resource);
+        let histogram = history
+            .latency
+            .entry(resource.url.clone())
+            .or_insert_with(|| LatencyHistogram::new(bounds));
+        if histogram.bounds_ms != bounds {
+            *histogram = LatencyHistogram::new(bounds);
+        }
+        histogram.observe(latency);
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Format milliseconds as a Prometheus seconds value
+fn seconds(ms: u64) -> String {
+    format!("{}", ms as f64 / 1000.0)
+}
+
+/// Render the metrics of a scope in the Prometheus text exposition format
+pub fn render(scope: &Scope, history: &History, now: u64) -> String {
+    let mut labels = Vec::new();
+    for resource in scope.resources() {
+        let mut label = format!("url=\"{}\"", escape(&resource.url));
+        if let Some(name) = &resource.name {
+            let _ = write!(label, ",name=\"{}\"", escape(name));
+        }
+        if let Some(group) = &resource.group {
+            let _ = write!(label, ",group=\"{}\"", escape(group));
+        }
+        if let Some(tenant) = scope.id() {
+            let _ = write!(label, ",tenant=\"{}\"", escape(tenant));
+        }
+        labels.push((resource, label));
+    }
+
+    let mut out = String::new();
+    out.push_str(
+        "# HELP linkkivahti_resource_up Whether the latest check of the resource passed\n",
+    );
+    out.push_str("# TYPE linkkivahti_resource_up gauge\n");
+    for (resource, label) in &labels {
+        if let Some(entry) = history.latest(&resource.url) {
+            let _ = writeln!(
+                out,
+                "linkkivahti_resource_up{{{}}} {}",
+                label,
+                u8::from(entry.ok)
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP linkkivahti_resource_health_score Rolling health score of the resource (0-100)\n",
+    );
+    out.push_str("# TYPE linkkivahti_resource_health_score gauge\n");
+    let health = &scope.config().health;
+    for (resource, label) in &labels {
+        if let Some(score) = health::score(history.entries(&resource.url), health, now) {
+            let _ = writeln!(
+                out,
+                "linkkivahti_resource_health_score{{{}}} {}",
+                label, score.score
+            );
+        }
+    }
+
+    out.push_str("# HELP linkkivahti_check_duration_seconds Latency of resource checks\n");
+    out.push_str("# TYPE linkkivahti_check_duration_seconds histogram\n");
+    for (resource, label) in &labels {
+        let Some(histogram) = history.latency.get(&resource.url) else {
+            continue;
+        };
+        let mut cumulative = 0;
+        for (bound, count) in histogram.bounds_ms.iter().zip(&histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "linkkivahti_check_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                label,
+                seconds(*bound),
+                cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "linkkivahti_check_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+            label, histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "linkkivahti_check_duration_seconds_sum{{{}}} {}",
+            label,
+            seconds(histogram.sum_ms)
+        );
+        let _ = writeln!(
+            out,
+            "linkkivahti_check_duration_seconds_count{{{}}} {}",
+            label, histogram.count
+        );
+    }
+    out
+}
+
+/// Handle `GET /metrics`
+pub async fn handle_metrics(env: &Env, scope: &Scope<'_>) -> Result<Response> {
+    let history = history::Backend::require(env)?.load(scope).await?;
+    let mut response = Response::ok(render(scope, &history, clock::now_ms()))?;
+    response
+        .headers_mut()
+        .set("Content-Type", "text/plain; version=0.0.4")?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [metrics]
+            buckets_ms = [100, 500]
+
+            [[resources]]
+            url = "https://example.com/a.js"
+            sri = "sha384-abc"
+            name = "Widget \"A\""
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn result(latency_ms: u64) -> CheckResult {
+        let mut result = CheckResult::success("https://example.com/a.js", 200, true);
+        result.latency_ms = Some(latency_ms);
+        result
+    }
+
+    #[test]
+    fn test_histogram_observe() {
+        let mut histogram = LatencyHistogram::new(&[100, 500]);
+        for latency in [50, 100, 101, 2000] {
+            histogram.observe(latency);
+        }
+        assert_eq!(histogram.counts, vec![2, 1, 1]);
+        assert_eq!(histogram.sum_ms, 2251);
+        assert_eq!(histogram.count, 4);
+    }
+
+    #[test]
+    fn test_observe_resets_on_bucket_change() {
+        let config = config();
+        let scope = Scope::root(&config);
+        let mut history = History::default();
+        history.latency.insert(
+            "https://example.com/a.js".to_string(),
+            LatencyHistogram::new(&[1000]),
+        );
+
+        observe(&mut history, &scope, &[result(120)]);
+        observe(&mut history, &scope, &[result(80)]);
+        // Results without latency (e.g. fetch failures) are not observed
+        observe(
+            &mut history,
+            &scope,
+            &[CheckResult::success("https://example.com/a.js", 200, true)],
+        );
+
+        let histogram = &history.latency["https://example.com/a.js"];
+        assert_eq!(histogram.bounds_ms, vec![100, 500]);
+        assert_eq!(histogram.counts, vec![1, 1, 0]);
+        assert_eq!(histogram.count, 2);
+    }
+
+    #[test]
+    fn test_render() {
+        let config = config();
+        let scope = Scope::root(&config);
+        let mut history = History::default();
+        let results = [result(120), result(80), result(700)];
+        for r in &results {
+            history.record(std::slice::from_ref(r), 0);
+        }
+        observe(&mut history, &scope, &results);
+
+        let text = render(&scope, &history, 0);
+        assert!(text.contains(
+            r#"linkkivahti_resource_health_score{url="https://example.com/a.js",name="Widget \"A\""} 100"#
+        ));
+        let label = r#"url="https://example.com/a.js",name="Widget \"A\"""#;
+        assert!(text.contains("# TYPE linkkivahti_check_duration_seconds histogram"));
+        assert!(text.contains(&format!("linkkivahti_resource_up{{{}}} 1", label)));
+        assert!(text.contains(&format!(
+            "linkkivahti_check_duration_seconds_bucket{{{},le=\"0.1\"}} 1",
+            label
+        )));
+        assert!(text.contains(&format!(
+            "linkkivahti_check_duration_seconds_bucket{{{},le=\"0.5\"}} 2",
+            label
+        )));
+        assert!(text.contains(&format!(
+            "linkkivahti_check_duration_seconds_bucket{{{},le=\"+Inf\"}} 3",
+            label
+        )));
+        assert!(text.contains(&format!(
+            "linkkivahti_check_duration_seconds_sum{{{}}} 0.9",
+            label
+        )));
+        assert!(text.contains(&format!(
+            "linkkivahti_check_duration_seconds_count{{{}}} 3",
+            label
+        )));
+    }
+}
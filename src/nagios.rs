@@ -0,0 +1,246 @@
+//! Monitoring plugin output (`GET /check/nagios`)
+//!
+//! Renders the most recent stored results of a scope in the Nagios/Icinga plugin
+//! format, so existing setups can wrap linkkivahti as a passive check:
+//!
+//! ```text
+//! CRITICAL - 12/13 links ok, 1 failing | ok=12;;;0;13 failing=1;;;0;13 unknown=0;;;0;13 'Widget'=0.123s
+//! https://cdn.example.com/b.js - SRI mismatch (HTTP 200)
+//! ```
+//!
+//! The status is the worst configured severity of the firing alert streams
+//! (`critical` maps to CRITICAL, `warning` to WARNING, `info` to OK). It is also
+//! returned as the plugin exit code in the `X-Nagios-Status` header.
+
+use crate::alert::{self, AlertStates, AlertStream, Severity};
+use crate::config::Resource;
+use crate::history::{self, History};
+use crate::store::Store;
+use crate::tenant::Scope;
+use worker::*;
+
+/// Plugin status, in order of the exit codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginStatus {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl PluginStatus {
+    /// Plugin exit code
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Ok => 0,
+            Self::Warning => 1,
+            Self::Critical => 2,
+            Self::Unknown => 3,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Warning => "WARNING",
+            Self::Critical => "CRITICAL",
+            Self::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+impl From<Severity> for PluginStatus {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Info => Self::Ok,
+            Severity::Warning => Self::Warning,
+            Severity::Critical => Self::Critical,
+        }
+    }
+}
+
+/// Perfdata label of a resource: its name, or its URL
+fn perf_label(resource: &Resource) -> String {
+    let label = resource.name.as_deref().unwrap_or(&resource.url);
+    // Single quotes are escaped by doubling them
+    format!("'{}'", label.replace('\'', "''"))
+}
+
+/// Render the plugin output of the latest results
+///
+/// `severity` gives the configured severity of an alert stream. A failing result
+/// without a firing alert (e.g. without alert state) counts as availability.
+pub fn render(
+    resources: &[Resource],
+    history: &History,
+    states: &AlertStates,
+    severity: impl Fn(AlertStream) -> Severity,
+) -> (PluginStatus, String) {
+    let total = resources.len();
+    let mut ok = 0;
+    let mut unknown = 0;
+    let mut worst: Option<Severity> = None;
+    let mut problems = Vec::new();
+    let mut latencies = Vec::new();
+
+    for resource in resources {
+        let Some(entry) = history.latest(&resource.url) else {
+            unknown += 1;
+            continue;
+        };
+        if let Some(ms) = entry.latency_ms {
+            latencies.push(format!(
+                "{}={}.{:03}s",
+                perf_label(resource),
+                ms / 1000,
+                ms % 1000
+            ));
+        }
+        if entry.ok {
+            ok += 1;
+            continue;
+        }
+
+        let firing: Vec<AlertStream> = AlertStream::ALL
+            .into_iter()
+            .filter(|&stream| states.is_firing(stream, &resource.alert_key()))
+            .collect();
+        let streams = if firing.is_empty() {
+            vec![AlertStream::Availability]
+        } else {
+            firing
+        };
+        let resource_severity = streams.into_iter().map(&severity).max();
+        worst = worst.max(resource_severity);
+        problems.push(format!("{} - {}", resource.url, entry.detail));
+    }
+
+    let failing = problems.len();
+    let status = match worst {
+        Some(severity) => PluginStatus::from(severity),
+        None if total > 0 && unknown == total => PluginStatus::Unknown,
+        None => PluginStatus::Ok,
+    };
+
+    let mut summary = format!("{}/{} links ok", ok, total);
+    if failing > 0 {
+        summary.push_str(&format!(", {} failing", failing));
+    }
+    if unknown > 0 {
+        summary.push_str(&format!(", {} not checked yet", unknown));
+    }
+
+    let mut perfdata = vec![
+        format!("ok={};;;0;{}", ok, total),
+        format!("failing={};;;0;{}", failing, total),
+        format!("unknown={};;;0;{}", unknown, total),
+    ];
+    perfdata.extend(latencies);
+
+    let mut output = format!("{} - {} | {}", status.as_str(), summary, perfdata.join(" "));
+    for problem in problems {
+        output.push('\n');
+        output.push_str(&problem);
+    }
+    (status, output)
+}
+
+/// Handle `GET /check/nagios`
+pub async fn handle_nagios(env: &Env, scope: &Scope<'_>) -> Result<Response> {
+    let history = history::load(env, scope).await;
+    let states = match Store::from_env(env) {
+        Some(store) => AlertStates::load(&store, scope).await,
+        None => AlertStates::default(),
+    };
+    let (status, output) = render(scope.resources(), &history, &states, |stream| {
+        alert::severity(env, stream)
+    });
+
+    let mut response = Response::ok(output)?;
+    response
+        .headers_mut()
+        .set("Content-Type", "text/plain; charset=utf-8")?;
+    response
+        .headers_mut()
+        .set("X-Nagios-Status", &status.code().to_string())?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::{CheckError, CheckResult};
+
+    fn resource(url: &str, name: Option<&str>) -> Resource {
+        Resource {
+            url: url.to_string(),
+            name: name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn severity(stream: AlertStream) -> Severity {
+        match stream {
+            AlertStream::Availability => Severity::Warning,
+            AlertStream::Integrity => Severity::Critical,
+        }
+    }
+
+    #[test]
+    fn test_render_ok_and_unknown() {
+        let resources = [resource("https://example.com/a.js", Some("Widget's"))];
+        let (status, output) = render(
+            &resources,
+            &History::default(),
+            &AlertStates::default(),
+            severity,
+        );
+        assert_eq!(status, PluginStatus::Unknown);
+        assert_eq!(
+            output,
+            "UNKNOWN - 0/1 links ok, 1 not checked yet | ok=0;;;0;1 failing=0;;;0;1 unknown=1;;;0;1"
+        );
+
+        let mut history = History::default();
+        let mut result = CheckResult::success("https://example.com/a.js", 200, true);
+        result.latency_ms = Some(1234);
+        history.record(&[result], 0);
+        let (status, output) = render(&resources, &history, &AlertStates::default(), severity);
+        assert_eq!(status, PluginStatus::Ok);
+        assert_eq!(status.code(), 0);
+        assert_eq!(
+            output,
+            "OK - 1/1 links ok | ok=1;;;0;1 failing=0;;;0;1 unknown=0;;;0;1 'Widget''s'=1.234s"
+        );
+    }
+
+    #[test]
+    fn test_render_severity() {
+        let resources = [
+            resource("https://example.com/a.js", None),
+            resource("https://example.com/b.js", None),
+        ];
+        let down = CheckResult::failure("https://example.com/a.js", CheckError::HttpError(503));
+        let mismatch = CheckResult::success("https://example.com/b.js", 200, false);
+        let mut history = History::default();
+        history.record(&[down.clone(), mismatch.clone()], 0);
+
+        // Without alert state every failure counts as availability
+        let mut states = AlertStates::default();
+        let (status, output) = render(&resources, &history, &states, severity);
+        assert_eq!(status, PluginStatus::Warning);
+        assert!(output.starts_with("WARNING - 0/2 links ok, 2 failing |"));
+        assert!(output.ends_with(
+            "\nhttps://example.com/a.js - Failed: HTTP error: 503\nhttps://example.com/b.js - SRI mismatch (HTTP 200)"
+        ));
+
+        states.evaluate(&down.url, &down, 0, None, 1);
+        states.evaluate(&mismatch.url, &mismatch, 0, None, 1);
+        let (status, _) = render(&resources, &history, &states, severity);
+        assert_eq!(status, PluginStatus::Critical);
+
+        // Informational streams do not raise the status
+        let (status, _) = render(&resources, &history, &states, |_| Severity::Info);
+        assert_eq!(status, PluginStatus::Ok);
+    }
+}
@@ -1,6 +1,22 @@
 //! Notification module for sending alerts about check failures
 
+use crate::action;
+use crate::alert::{AlertStream, Severity};
 use crate::checker::{CheckResult, CheckResultKind};
+use crate::clock;
+use crate::config;
+use crate::console::{console_error, console_log};
+use crate::deploy::Deployment;
+use crate::discord;
+use crate::environment::Environment;
+use crate::fetch::{Destination, Fetcher, HttpRequest, HttpResponse};
+use crate::idn;
+use crate::origins::Breakage;
+use crate::outbox::{self, SentNotification};
+use crate::slack;
+use crate::sri;
+use crate::tenant::Scope;
+use crate::trace;
 use serde::Serialize;
 use worker::*;
 
@@ -17,25 +33,336 @@ pub enum WebhookService {
     Generic,
 }
 
-#[derive(Clone, Copy)]
+/// Schema version of generic (Alertmanager-style) payloads
+///
+/// Payloads carry their version in `schemaVersion`; `WEBHOOK_SCHEMA_VERSION` pins an
+/// older one for consumers that are not ready for the current fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaVersion {
+    /// Original payload: no `schemaVersion`, stream or flapping labels, or extra
+    /// annotations, and one fingerprint per URL
+    V1,
+    /// Alert stream labels, history/quarantine/mirror/flapping annotations and
+    /// separate integrity fingerprints
+    #[default]
+    V2,
+}
+
+impl SchemaVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::V1 => "1",
+            Self::V2 => "2",
+        }
+    }
+}
+
+impl std::str::FromStr for SchemaVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "1" => Ok(Self::V1),
+            "2" => Ok(Self::V2),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Schema version from `WEBHOOK_SCHEMA_VERSION`, the current one if unset
+fn schema_version(env: &Env) -> SchemaVersion {
+    match config::setting(env, "WEBHOOK_SCHEMA_VERSION") {
+        None => SchemaVersion::default(),
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            console_error!(
+                "Unknown WEBHOOK_SCHEMA_VERSION '{}', using the current schema",
+                value
+            );
+            SchemaVersion::default()
+        }),
+    }
+}
+
+/// How alert fingerprints of generic payloads are computed
+///
+/// Alertmanager-style receivers deduplicate alerts by fingerprint, so changing
+/// the algorithm makes open alerts look new once. `WEBHOOK_FINGERPRINT=legacy`
+/// keeps the previous fingerprints for receivers with open alerts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Fingerprint {
+    /// First 16 hex characters of the SHA-256 digest
+    #[default]
+    Sha256,
+    /// Original 64-bit multiplicative string hash, collision-prone for similar URLs
+    Legacy,
+}
+
+impl Fingerprint {
+    /// Fingerprint of an alert subject: a URL, or the alert key of a resource
+    /// deduplicated by name or group
+    ///
+    /// URLs are normalized first, so spellings of the same URL share a fingerprint.
+    fn compute(&self, subject: &str) -> String {
+        let subject = config::normalize_url(subject).unwrap_or_else(|_| subject.to_string());
+        match self {
+            Self::Sha256 => sri::sha256_hex(subject.as_bytes())[..16].to_string(),
+            Self::Legacy => {
+                let mut hash: u64 = 0;
+                for byte in subject.as_bytes() {
+                    hash = hash.wrapping_mul(31).wrapping_add(*byte as u64);
+                }
+                format!("{:016x}", hash)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Fingerprint {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "sha256" => Ok(Self::Sha256),
+            "legacy" => Ok(Self::Legacy),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Fingerprint algorithm from `WEBHOOK_FINGERPRINT`, SHA-256 if unset
+fn fingerprint_algorithm(env: &Env) -> Fingerprint {
+    match config::setting(env, "WEBHOOK_FINGERPRINT") {
+        None => Fingerprint::default(),
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            console_error!(
+                "Unknown WEBHOOK_FINGERPRINT '{}', using sha256 fingerprints",
+                value
+            );
+            Fingerprint::default()
+        }),
+    }
+}
+
+/// Fingerprint of a stream's alerts about a subject in generic payloads, as
+/// receivers know them (see `callback`)
+pub fn alert_fingerprint(env: &Env, stream: AlertStream, subject: &str) -> String {
+    let fingerprint = fingerprint_algorithm(env);
+    match (schema_version(env), stream) {
+        (SchemaVersion::V2, AlertStream::Integrity) => {
+            fingerprint.compute(&format!("{}#integrity", subject))
+        }
+        _ => fingerprint.compute(subject),
+    }
+}
+
+#[derive(Clone, Default)]
 struct NotificationContext {
     title: &'static str,
     fallback_prefix: &'static str,
     subject_label: &'static str,
+    /// Compact line of recent results, e.g. "last 5 checks: ✓ ✓ ✗ ✗ ✗, failing for 23m"
+    history: Option<String>,
+    /// Reference to the quarantined copy of mismatching content
+    quarantine: Option<String>,
+    /// Link to the last SRI-verified copy of an unavailable resource
+    mirror: Option<String>,
+    /// Flap summary, e.g. "6 state changes in the last 60m, currently failing"
+    flapping: Option<String>,
+    /// Owner of the resource, mentioned in chat messages and labelled in generic payloads
+    owner: Option<String>,
+    /// Runbook link of the resource (see `runbook`)
+    runbook: Option<String>,
+    /// Tags of the resource, comma-separated
+    tags: Option<String>,
+    /// Active incident of the upstream provider, e.g. "upstream incident in progress: <link>"
+    upstream: Option<String>,
+    /// Broken links of a crawled page, one per line
+    broken_links: Option<String>,
+    /// Comparison with the resource's alternate origins
+    origins: Option<String>,
+    /// Whether an SRI mismatch reproduces through the resource's secondary path
+    out_of_band: Option<String>,
+    /// What the failure has in common with other failures of the run (see `correlation`)
+    correlation: Option<String>,
+    /// Whether only the CDN copy or the origin is broken
+    broken_at: Option<Breakage>,
+    /// Alert stream the notification belongs to, `None` for tests and notices
+    stream: Option<AlertStream>,
+    /// Configured severity of the stream
+    severity: Option<Severity>,
+    /// Whether the notification reports a recovery
+    resolved: bool,
+    /// Schema of generic payloads
+    schema: SchemaVersion,
+    /// Fingerprint algorithm of generic payloads
+    fingerprint: Fingerprint,
+    /// ID of the run sending the notification (see `trace`)
+    run_id: Option<String>,
+    /// Deployment environment named in titles and labels (see `environment`)
+    environment: Option<Environment>,
+    /// Unicode and punycode forms of an internationalized host, with a warning if
+    /// it mixes scripts (see `idn`)
+    domain: Option<String>,
+    /// Alert key of a resource deduplicated by name or group, fingerprinted
+    /// instead of the URL
+    dedup_key: Option<String>,
+    /// Signed link re-checking the resource right away (see `action`)
+    recheck: Option<String>,
+    /// Value of the Slack buttons of a firing alert (see `slack`)
+    slack_buttons: Option<String>,
+    /// Alert reference of the Discord buttons of a firing alert (see `discord`)
+    discord_buttons: Option<String>,
+}
+
+/// Color of resolved alerts in Discord - green #57F287
+const RESOLVED_COLOR: u32 = 5763719;
+
+/// An alert of a stream about to be sent, as decided by the alert module
+pub struct AlertNotification {
+    pub stream: AlertStream,
+    pub severity: Severity,
+    pub resolved: bool,
+    /// Compact line of recent results of the resource, if known
+    pub history: Option<String>,
+    /// Reference to the quarantined content of an SRI mismatch
+    pub quarantine: Option<String>,
+    /// Link to the last SRI-verified copy of the resource
+    pub mirror: Option<String>,
+    /// Summary of a flapping stream, replacing individual alerts while it is damped
+    pub flapping: Option<String>,
+    /// Owner of the resource from config.toml
+    pub owner: Option<String>,
+    /// Runbook link of a firing alert
+    pub runbook: Option<String>,
+    /// Active incident reported by the resource's upstream status page
+    pub upstream: Option<String>,
+    /// Broken links of a crawled page
+    pub broken_links: Option<String>,
+    /// Comparison of a failing resource with its alternate origins
+    pub origins: Option<String>,
+    /// Out-of-band verification of an SRI mismatch through `verify_via`
+    pub out_of_band: Option<String>,
+    /// Hint about other resources failing in the same run
+    pub correlation: Option<String>,
+    /// Whether only the CDN copy or the origin is broken, if the resource has origins
+    pub broken_at: Option<Breakage>,
+    /// Alert key of the resource when it is deduplicated by name or group
+    pub dedup_key: Option<String>,
+}
+
+impl NotificationContext {
+    /// Optional details shown as extra fields in chat messages
+    fn details(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("Tags", &self.tags),
+            ("Domain", &self.domain),
+            ("History", &self.history),
+            ("Quarantine", &self.quarantine),
+            ("Verified copy", &self.mirror),
+            ("Flapping", &self.flapping),
+            ("Upstream", &self.upstream),
+            ("Broken links", &self.broken_links),
+            ("Origins", &self.origins),
+            ("Out-of-band", &self.out_of_band),
+            ("Correlation", &self.correlation),
+            ("Runbook", &self.runbook),
+            ("Re-check", &self.recheck),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+    }
+
+    /// Title of chat messages, prefixed with the environment if one is set
+    fn heading(&self) -> String {
+        match &self.environment {
+            Some(environment) => environment.prefix(self.title),
+            None => self.title.to_string(),
+        }
+    }
+
+    /// Mention of the owner in the chat syntax of a service
+    ///
+    /// Recoveries name the owner without pinging them.
+    fn mention(&self, service: WebhookService) -> Option<String> {
+        let owner = self.owner.as_deref()?;
+        if self.resolved {
+            return Some(plain_owner(owner).to_string());
+        }
+        Some(mention(service, owner))
+    }
+}
+
+/// Mention syntax of an owner ID, or the owner unchanged if it is not an ID
+///
+/// Slack user group IDs start with `S` and user IDs with `U` or `W`; Discord IDs
+/// are numeric and taken to be roles. Email addresses and preformatted `<...>`
+/// mentions are passed through.
+fn mention(service: WebhookService, owner: &str) -> String {
+    let is_id = |first: &[char]| {
+        owner.len() > 1
+            && owner.starts_with(first)
+            && owner
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    };
+    match service {
+        WebhookService::Slack if is_id(&['S']) => format!("<!subteam^{}>", owner),
+        WebhookService::Slack if is_id(&['U', 'W']) => format!("<@{}>", owner),
+        WebhookService::Discord if owner.chars().all(|c| c.is_ascii_digit()) => {
+            format!("<@&{}>", owner)
+        }
+        _ => owner.to_string(),
+    }
+}
+
+/// An owner as plain text, unwrapping a preformatted mention so it does not ping
+fn plain_owner(owner: &str) -> &str {
+    owner
+        .strip_prefix('<')
+        .and_then(|o| o.strip_suffix('>'))
+        .map(|o| o.trim_start_matches(['@', '!', '&', '#']))
+        .unwrap_or(owner)
 }
 
 // Discord webhook payload structures
 #[derive(Serialize)]
 struct DiscordPayload {
+    /// Owner mention; mentions inside embeds do not ping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
     embeds: Vec<DiscordEmbed>,
+    /// Action rows of buttons, shown on messages of application-owned webhooks
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    components: Vec<DiscordActionRow>,
+}
+
+#[derive(Serialize)]
+struct DiscordActionRow {
+    /// Always 1 (action row)
+    #[serde(rename = "type")]
+    row_type: u8,
+    components: Vec<DiscordButton>,
+}
+
+#[derive(Serialize)]
+struct DiscordButton {
+    /// Always 2 (button)
+    #[serde(rename = "type")]
+    button_type: u8,
+    /// 2 is the grey "secondary" style
+    style: u8,
+    label: &'static str,
+    custom_id: String,
 }
 
 #[derive(Serialize)]
 struct DiscordEmbed {
-    title: &'static str,
+    title: String,
     description: String,
     color: u32,
     fields: Vec<DiscordField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    footer: Option<DiscordFooter>,
     timestamp: String,
 }
 
@@ -46,6 +373,11 @@ struct DiscordField {
     inline: bool,
 }
 
+#[derive(Serialize)]
+struct DiscordFooter {
+    text: String,
+}
+
 // Slack webhook payload structures
 #[derive(Serialize)]
 struct SlackPayload {
@@ -60,6 +392,8 @@ enum SlackBlock {
     Header { text: SlackText },
     #[serde(rename = "section")]
     Section { fields: Vec<SlackText> },
+    #[serde(rename = "actions")]
+    Actions { elements: Vec<SlackButton> },
     #[serde(rename = "context")]
     Context { elements: Vec<SlackText> },
     #[serde(rename = "divider")]
@@ -73,10 +407,21 @@ struct SlackText {
     text: String,
 }
 
+#[derive(Serialize)]
+struct SlackButton {
+    #[serde(rename = "type")]
+    element_type: &'static str,
+    text: SlackText,
+    action_id: &'static str,
+    value: String,
+}
+
 // Alertmanager v4 webhook payload structures (for generic/observability tools)
 #[derive(Serialize)]
 struct AlertmanagerPayload {
     version: &'static str,
+    #[serde(rename = "schemaVersion", skip_serializing_if = "Option::is_none")]
+    schema_version: Option<&'static str>,
     #[serde(rename = "groupKey")]
     group_key: String,
     #[serde(rename = "truncatedAlerts")]
@@ -105,12 +450,49 @@ struct AlertmanagerLabels {
     instance: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     job: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flapping: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    broken_at: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<String>,
 }
 
 #[derive(Serialize)]
 struct AlertmanagerAnnotations {
     summary: String,
     description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quarantine: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mirror: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flapping: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    broken_links: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origins: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    out_of_band: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation: Option<String>,
+    /// Conventional Prometheus annotation of the alert's runbook
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runbook_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    domain: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -121,7 +503,7 @@ struct AlertmanagerAlert {
     #[serde(rename = "startsAt")]
     starts_at: String,
     #[serde(rename = "endsAt")]
-    ends_at: &'static str,
+    ends_at: String,
     #[serde(rename = "generatorURL")]
     generator_url: &'static str,
     fingerprint: String,
@@ -184,7 +566,7 @@ impl WebhookService {
     ) -> Result<String> {
         let json = match self {
             Self::Discord => Self::build_discord_payload(result, timestamp, context)?,
-            Self::Slack | Self::Zulip => Self::build_slack_payload(result, timestamp, context)?,
+            Self::Slack | Self::Zulip => self.build_slack_payload(result, timestamp, context)?,
             Self::Generic => Self::build_generic_payload(result, timestamp, context)?,
         };
         Ok(json)
@@ -196,20 +578,74 @@ impl WebhookService {
         timestamp: &str,
         context: &NotificationContext,
     ) -> Result<String> {
-        let color = Self::severity_color(result);
+        let color = if context.resolved {
+            RESOLVED_COLOR
+        } else {
+            Self::severity_color(result)
+        };
 
-        let payload = DiscordPayload {
-            embeds: vec![DiscordEmbed {
-                title: context.title,
-                description: format!("**{}**", result.url.as_ref()),
-                color,
-                fields: vec![DiscordField {
-                    name: "Status",
-                    value: result.description().to_string(),
-                    inline: true,
-                }],
+        let mut fields = vec![DiscordField {
+            name: "Status",
+            value: result.description().to_string(),
+            inline: true,
+        }];
+        fields.extend(context.details().map(|(name, value)| DiscordField {
+            name,
+            value: value.to_string(),
+            inline: false,
+        }));
+        let mention = context.mention(Self::Discord);
+        if let Some(owner) = &mention {
+            fields.push(DiscordField {
+                name: "Owner",
+                value: owner.clone(),
+                inline: true,
+            });
+        }
+
+        // The banner leads in the environment's color, the alert keeps its own
+        let banner = context
+            .environment
+            .as_ref()
+            .map(|environment| DiscordEmbed {
+                title: environment.banner(),
+                description: format!("linkkivahti deployment `{}`", environment.name),
+                color: environment.color,
+                fields: Vec::new(),
+                footer: None,
                 timestamp: timestamp.to_string(),
-            }],
+            });
+        let alert = DiscordEmbed {
+            title: context.heading(),
+            description: format!("**{}**", idn::display_url(result.url.as_ref())),
+            color,
+            fields,
+            footer: context.run_id.as_ref().map(|id| DiscordFooter {
+                text: format!("Run {}", id),
+            }),
+            timestamp: timestamp.to_string(),
+        };
+
+        let payload = DiscordPayload {
+            content: mention.filter(|_| !context.resolved),
+            embeds: banner.into_iter().chain([alert]).collect(),
+            components: context
+                .discord_buttons
+                .as_ref()
+                .map(|reference| DiscordActionRow {
+                    row_type: 1,
+                    components: action::BUTTONS
+                        .iter()
+                        .map(|&(action_id, label)| DiscordButton {
+                            button_type: 2,
+                            style: 2,
+                            label,
+                            custom_id: discord::custom_id(action_id, reference),
+                        })
+                        .collect(),
+                })
+                .into_iter()
+                .collect(),
         };
 
         serde_json::to_string(&payload)
@@ -220,7 +656,7 @@ impl WebhookService {
     fn severity_color(result: &CheckResult) -> u32 {
         use crate::checker::CheckError;
 
-        if result.kind == CheckResultKind::Test {
+        if result.is_informational() {
             return 3447003; // Info blue
         }
 
@@ -240,47 +676,101 @@ impl WebhookService {
 
     /// Build Slack webhook payload with Block Kit
     fn build_slack_payload(
+        &self,
         result: &CheckResult,
         timestamp: &str,
         context: &NotificationContext,
     ) -> Result<String> {
-        let fallback_text = format!(
+        let mention = context.mention(*self);
+        let display_url = idn::display_url(result.url.as_ref());
+        let mut fallback_text = format!(
             "{}: {} - {}",
             context.fallback_prefix,
-            result.url.as_ref(),
+            display_url,
             result.description()
         );
+        if let Some(environment) = &context.environment {
+            fallback_text = format!("[{}] {}", environment.label(), fallback_text);
+        }
+        if let Some(owner) = &mention {
+            fallback_text = format!("{} {}", owner, fallback_text);
+        }
 
-        let payload = SlackPayload {
-            text: fallback_text,
-            blocks: vec![
-                SlackBlock::Header {
+        let mut fields = vec![
+            SlackText {
+                text_type: "mrkdwn",
+                text: format!("*{}:*\n{}", context.subject_label, display_url),
+            },
+            SlackText {
+                text_type: "mrkdwn",
+                text: format!("*Status:*\n{}", result.description()),
+            },
+        ];
+        fields.extend(context.details().map(|(name, value)| SlackText {
+            text_type: "mrkdwn",
+            text: format!("*{}:*\n{}", name, value),
+        }));
+        fields.extend(mention.map(|owner| SlackText {
+            text_type: "mrkdwn",
+            text: format!("*Owner:*\n{}", owner),
+        }));
+
+        let mut footer = format!("Time: {} | Worker: linkkivahti", timestamp);
+        if let Some(id) = &context.run_id {
+            footer.push_str(&format!(" | Run: {}", id));
+        }
+
+        let mut blocks = Vec::new();
+        // Slack blocks have no color, so a colored marker stands in for the tint
+        if let Some(environment) = &context.environment {
+            blocks.push(SlackBlock::Context {
+                elements: vec![SlackText {
+                    text_type: "mrkdwn",
+                    text: format!(
+                        "{} *{}* environment",
+                        environment.marker(),
+                        environment.label()
+                    ),
+                }],
+            });
+        }
+        blocks.extend([
+            SlackBlock::Header {
+                text: SlackText {
+                    text_type: "plain_text",
+                    text: context.heading(),
+                },
+            },
+            SlackBlock::Divider,
+            SlackBlock::Section { fields },
+        ]);
+        // Zulip's Slack-compatible endpoint has no interactivity
+        if let (Self::Slack, Some(value)) = (self, &context.slack_buttons) {
+            let elements = action::BUTTONS
+                .iter()
+                .map(|&(action_id, label)| SlackButton {
+                    element_type: "button",
                     text: SlackText {
                         text_type: "plain_text",
-                        text: context.title.to_string(),
+                        text: label.to_string(),
                     },
-                },
-                SlackBlock::Divider,
-                SlackBlock::Section {
-                    fields: vec![
-                        SlackText {
-                            text_type: "mrkdwn",
-                            text: format!("*{}:*\n{}", context.subject_label, result.url.as_ref()),
-                        },
-                        SlackText {
-                            text_type: "mrkdwn",
-                            text: format!("*Status:*\n{}", result.description()),
-                        },
-                    ],
-                },
-                SlackBlock::Divider,
-                SlackBlock::Context {
-                    elements: vec![SlackText {
-                        text_type: "mrkdwn",
-                        text: format!("Time: {} | Worker: linkkivahti", timestamp),
-                    }],
-                },
-            ],
+                    action_id,
+                    value: value.clone(),
+                })
+                .collect();
+            blocks.push(SlackBlock::Actions { elements });
+        }
+        blocks.push(SlackBlock::Divider);
+        blocks.push(SlackBlock::Context {
+            elements: vec![SlackText {
+                text_type: "mrkdwn",
+                text: footer,
+            }],
+        });
+
+        let payload = SlackPayload {
+            text: fallback_text,
+            blocks,
         };
 
         serde_json::to_string(&payload)
@@ -293,39 +783,84 @@ impl WebhookService {
         timestamp: &str,
         context: &NotificationContext,
     ) -> Result<String> {
-        let severity = if result.kind == CheckResultKind::Test {
+        let severity = if let Some(severity) = context.severity {
+            severity.as_str()
+        } else if result.is_informational() {
             "info"
         } else if result.sri_valid == Some(false) {
             "critical" // SRI mismatch is a security issue
         } else {
             "warning" // Other failures are warnings
         };
+        let integrity = context.stream == Some(AlertStream::Integrity);
 
-        let summary = if result.kind == CheckResultKind::Test {
+        let flapping = context.flapping.is_some();
+        let summary = if result.is_informational() || context.resolved || integrity || flapping {
             format!("{}: {}", context.fallback_prefix, result.url.as_ref())
         } else {
             format!("Link check failed for {}", result.url.as_ref())
         };
         let description = result.description();
-        let fingerprint = Self::compute_fingerprint(result.url.as_ref());
+        let subject = context.dedup_key.as_deref().unwrap_or(result.url.as_ref());
+        // Integrity alerts resolve independently, so they need their own identity
+        let fingerprint = if integrity {
+            context
+                .fingerprint
+                .compute(&format!("{}#integrity", subject))
+        } else {
+            context.fingerprint.compute(subject)
+        };
         let group_key = format!("linkkivahti/{}", fingerprint);
+        let (status, ends_at) = if context.resolved {
+            ("resolved", timestamp.to_string())
+        } else {
+            ("firing", "0001-01-01T00:00:00Z".to_string()) // Zero value indicates ongoing
+        };
+        let stream = context.stream.map(|s| s.as_str());
+        let environment = context.environment.as_ref().map(|e| e.name.clone());
 
         let (common_summary, common_description) = match result.kind {
             CheckResultKind::Test => (
                 "Synthetic notification dispatch".to_string(),
                 "Test notification generated by linkkivahti".to_string(),
             ),
-            CheckResultKind::Real => (
-                "Link availability check failed".to_string(),
-                "External resource check detected a failure".to_string(),
+            CheckResultKind::Notice => (
+                context.fallback_prefix.to_string(),
+                "Informational notice generated by linkkivahti".to_string(),
             ),
+            CheckResultKind::Real => {
+                let (summary, description) = match (integrity, context.resolved) {
+                    _ if flapping => (
+                        "Resource check flapping",
+                        "External resource check alternates between failing and passing",
+                    ),
+                    (false, false) => (
+                        "Link availability check failed",
+                        "External resource check detected a failure",
+                    ),
+                    (false, true) => (
+                        "Link availability restored",
+                        "External resource check passed again",
+                    ),
+                    (true, false) => (
+                        "Resource integrity check failed",
+                        "External resource content does not match its SRI hash",
+                    ),
+                    (true, true) => (
+                        "Resource integrity restored",
+                        "External resource content matches its SRI hash again",
+                    ),
+                };
+                (summary.to_string(), description.to_string())
+            }
         };
 
-        let payload = AlertmanagerPayload {
+        let mut payload = AlertmanagerPayload {
             version: "4",
+            schema_version: Some(context.schema.as_str()),
             group_key,
             truncated_alerts: 0,
-            status: "firing",
+            status,
             receiver: "webhook",
             group_labels: AlertmanagerLabels {
                 alertname: "LinkCheckFailed",
@@ -333,6 +868,12 @@ impl WebhookService {
                 service: None,
                 instance: None,
                 job: None,
+                stream: None,
+                flapping: None,
+                owner: None,
+                tags: None,
+                broken_at: None,
+                environment: None,
             },
             common_labels: AlertmanagerLabels {
                 alertname: "LinkCheckFailed",
@@ -340,45 +881,110 @@ impl WebhookService {
                 service: Some("linkkivahti"),
                 instance: None,
                 job: None,
+                stream,
+                flapping: flapping.then_some("true"),
+                owner: context.owner.clone(),
+                tags: context.tags.clone(),
+                broken_at: context.broken_at.map(|b| b.as_str()),
+                environment: environment.clone(),
             },
             common_annotations: AlertmanagerAnnotations {
                 summary: common_summary,
                 description: common_description,
+                history: None,
+                quarantine: None,
+                mirror: None,
+                flapping: None,
+                upstream: None,
+                broken_links: None,
+                origins: None,
+                out_of_band: None,
+                correlation: None,
+                runbook_url: None,
+                run_id: None,
+                domain: None,
             },
             external_url: "https://linkkivahti.workers.dev",
             alerts: vec![AlertmanagerAlert {
-                status: "firing",
+                status,
                 labels: AlertmanagerLabels {
                     alertname: "LinkCheckFailed",
                     severity: Some(severity),
                     service: Some("linkkivahti"),
                     instance: Some(result.url.as_ref().to_string()),
                     job: Some("link-checker"),
+                    stream,
+                    flapping: flapping.then_some("true"),
+                    owner: context.owner.clone(),
+                    tags: context.tags.clone(),
+                    broken_at: context.broken_at.map(|b| b.as_str()),
+                    environment: environment.clone(),
                 },
                 annotations: AlertmanagerAnnotations {
                     summary,
                     description,
+                    history: context.history.clone(),
+                    quarantine: context.quarantine.clone(),
+                    mirror: context.mirror.clone(),
+                    flapping: context.flapping.clone(),
+                    upstream: context.upstream.clone(),
+                    broken_links: context.broken_links.clone(),
+                    origins: context.origins.clone(),
+                    out_of_band: context.out_of_band.clone(),
+                    correlation: context.correlation.clone(),
+                    runbook_url: context.runbook.clone(),
+                    run_id: context.run_id.clone(),
+                    domain: context.domain.clone(),
                 },
                 starts_at: timestamp.to_string(),
-                ends_at: "0001-01-01T00:00:00Z", // Zero value indicates ongoing
+                ends_at,
                 generator_url: "https://linkkivahti.workers.dev/",
                 fingerprint,
             }],
         };
 
+        if context.schema == SchemaVersion::V1 {
+            Self::downgrade_v1(&mut payload, &context.fingerprint.compute(subject));
+        }
+
         serde_json::to_string(&payload).map_err(|e| {
             Error::RustError(format!("Failed to serialize Alertmanager payload: {}", e))
         })
     }
 
-    /// Compute a fingerprint hash for an alert based on the URL
-    fn compute_fingerprint(url: &str) -> String {
-        // Simple hash computation - use first 16 chars of hex representation
-        let mut hash: u64 = 0;
-        for byte in url.as_bytes() {
-            hash = hash.wrapping_mul(31).wrapping_add(*byte as u64);
+    /// Reduce a generic payload to schema version 1
+    fn downgrade_v1(payload: &mut AlertmanagerPayload, fingerprint: &str) {
+        payload.schema_version = None;
+        for labels in [&mut payload.group_labels, &mut payload.common_labels] {
+            labels.stream = None;
+            labels.flapping = None;
+            labels.owner = None;
+            labels.tags = None;
+            labels.broken_at = None;
+            labels.environment = None;
+        }
+        payload.group_key = format!("linkkivahti/{}", fingerprint);
+        for alert in &mut payload.alerts {
+            alert.labels.stream = None;
+            alert.labels.flapping = None;
+            alert.labels.owner = None;
+            alert.labels.tags = None;
+            alert.labels.broken_at = None;
+            alert.labels.environment = None;
+            alert.annotations.history = None;
+            alert.annotations.quarantine = None;
+            alert.annotations.mirror = None;
+            alert.annotations.flapping = None;
+            alert.annotations.upstream = None;
+            alert.annotations.broken_links = None;
+            alert.annotations.origins = None;
+            alert.annotations.out_of_band = None;
+            alert.annotations.correlation = None;
+            alert.annotations.runbook_url = None;
+            alert.annotations.run_id = None;
+            alert.annotations.domain = None;
+            alert.fingerprint = fingerprint.to_string();
         }
-        format!("{:016x}", hash)
     }
 }
 
@@ -407,115 +1013,583 @@ impl std::str::FromStr for WebhookService {
     }
 }
 
-pub async fn send_test_notification(env: &Env) -> Result<()> {
-    let timestamp = get_timestamp();
+/// Delivery of the test notification to one destination
+#[derive(Debug, Serialize)]
+pub struct DeliveryReport {
+    /// Secret configuring the destination; the URL itself is not reported
+    pub secret: String,
+    pub service: String,
+    pub delivered: bool,
+    /// HTTP status of the webhook's response, if it answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DeliveryReport {
+    fn failed(target: &WebhookTarget, error: impl ToString) -> Self {
+        Self {
+            secret: target.secret.clone(),
+            service: target.service.to_string().to_lowercase(),
+            delivered: false,
+            status: None,
+            latency_ms: 0,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Result of `POST /notify`
+#[derive(Debug, Serialize)]
+pub struct TestReport {
+    pub delivered: usize,
+    pub failed: usize,
+    pub destinations: Vec<DeliveryReport>,
+}
+
+impl TestReport {
+    fn new(destinations: Vec<DeliveryReport>) -> Self {
+        let delivered = destinations.iter().filter(|d| d.delivered).count();
+        Self {
+            delivered,
+            failed: destinations.len() - delivered,
+            destinations,
+        }
+    }
+}
+
+/// Send a test notification to every destination of a scope
+///
+/// Unlike alerts, which go to one destination per stream, the test covers the
+/// scope's webhook and every stream-specific one, so a changed secret can be
+/// verified without waiting for an incident.
+pub async fn send_test_notifications(env: &Env, scope: &Scope<'_>) -> TestReport {
+    let timestamp = clock::timestamp();
     let message = format!("Synthetic test notification at {}", timestamp);
     let test_result = CheckResult::test(message);
-
-    send_notification(
+    let context = complete_context(
         env,
         &test_result,
         NotificationContext {
             title: "🔔 Test Notification",
             fallback_prefix: "Test Notification",
             subject_label: "Message",
+            history: None,
+            ..Default::default()
+        },
+    );
+
+    let mut reports = Vec::new();
+    for target in destinations(env, scope) {
+        console_log!(
+            "Sending test notification to {} via {}",
+            target.secret,
+            target.service
+        );
+        let payload = match target
+            .service
+            .build_payload(&test_result, &timestamp, &context)
+        {
+            Ok(payload) => payload,
+            Err(e) => {
+                reports.push(DeliveryReport::failed(&target, e));
+                continue;
+            }
+        };
+        let report = match Destination::resolve(env, &target.url) {
+            Ok((fetcher, url)) => send_test(&fetcher, &target, &url, &payload).await,
+            Err(e) => DeliveryReport::failed(&target, e),
+        };
+        reports.push(report);
+    }
+    TestReport::new(reports)
+}
+
+/// Deliver a test payload to one destination, timing the webhook's response
+async fn send_test(
+    fetcher: &impl Fetcher,
+    target: &WebhookTarget,
+    url: &str,
+    payload: &str,
+) -> DeliveryReport {
+    let started = clock::now_ms();
+    let outcome = post_webhook(fetcher, url, payload).await;
+    let latency_ms = clock::now_ms().saturating_sub(started);
+    let (status, error) = match outcome {
+        Ok(WebhookResponse::Accepted(status)) => (Some(status), None),
+        Ok(WebhookResponse::Rejected { status, body }) => {
+            (Some(status), Some(format!("HTTP {}: {}", status, body)))
+        }
+        Err(e) => (None, Some(e.to_string())),
+    };
+    DeliveryReport {
+        secret: target.secret.clone(),
+        service: target.service.to_string().to_lowercase(),
+        delivered: error.is_none(),
+        status,
+        latency_ms,
+        error,
+    }
+}
+
+/// Send the periodic canary notification, proving the channel still delivers
+pub async fn send_canary_notification(env: &Env, scope: &Scope<'_>) -> Result<()> {
+    let message = format!("Notification canary at {}", clock::timestamp());
+
+    send_notification(
+        env,
+        scope,
+        &CheckResult::test(message),
+        NotificationContext {
+            title: "🐤 Notification Canary",
+            fallback_prefix: "Notification Canary",
+            subject_label: "Message",
+            history: None,
+            ..Default::default()
         },
     )
     .await
 }
 
-/// Send a notification about a failed check to the configured webhook
+/// Send an alert of a stream to the configured webhook
 ///
 /// This function retrieves the webhook configuration from environment variables,
 /// auto-detects the webhook service type (or uses an override), formats the
-/// appropriate payload, and sends the notification.
+/// appropriate payload, and sends the notification. Each stream can have its own
+/// destination (`AVAILABILITY_WEBHOOK_URL`, `INTEGRITY_WEBHOOK_URL`), falling back to
+/// the scope's webhook.
 ///
 /// # Arguments
-/// * `env` - Worker environment to access WEBHOOK_URL secret and optional WEBHOOK_SERVICE override
+/// * `env` - Worker environment to access webhook secrets and optional WEBHOOK_SERVICE override
+/// * `scope` - Scope of the resource, which selects the webhook destination
 /// * `result` - The check result to report
+/// * `alert` - Stream, severity and state of the alert
 ///
 /// # Returns
 /// * `Ok(())` if notification was sent successfully or webhook is not configured
 /// * `Err` if webhook is configured but sending failed
-pub async fn send_failure_notification(env: &Env, result: &CheckResult) -> Result<()> {
+pub async fn send_alert_notification(
+    env: &Env,
+    scope: &Scope<'_>,
+    result: &CheckResult,
+    alert: AlertNotification,
+) -> Result<()> {
+    let (title, fallback_prefix) = match (alert.stream, alert.resolved) {
+        (AlertStream::Availability, _) if alert.flapping.is_some() => {
+            ("🔁 Link Check Flapping", "Link Check Flapping")
+        }
+        (AlertStream::Integrity, _) if alert.flapping.is_some() => {
+            ("🔁 SRI Check Flapping", "SRI Check Flapping")
+        }
+        (AlertStream::Availability, false) => ("🔗 Link Check Failed", "Link Check Failed"),
+        (AlertStream::Availability, true) => ("✅ Link Check Recovered", "Link Check Recovered"),
+        (AlertStream::Integrity, false) => ("🛡️ SRI Mismatch Detected", "SRI Mismatch Detected"),
+        (AlertStream::Integrity, true) => ("✅ SRI Mismatch Resolved", "SRI Mismatch Resolved"),
+    };
+
     send_notification(
         env,
+        scope,
         result,
         NotificationContext {
-            title: "🔗 Link Check Failed",
-            fallback_prefix: "Link Check Failed",
+            title,
+            fallback_prefix,
             subject_label: "URL",
+            history: alert.history,
+            quarantine: alert.quarantine,
+            mirror: alert.mirror,
+            flapping: alert.flapping,
+            owner: alert.owner,
+            runbook: alert.runbook,
+            upstream: alert.upstream,
+            broken_links: alert.broken_links,
+            origins: alert.origins,
+            out_of_band: alert.out_of_band,
+            correlation: alert.correlation,
+            broken_at: alert.broken_at,
+            dedup_key: alert.dedup_key,
+            recheck: if alert.resolved {
+                None
+            } else {
+                action::recheck_link(env, scope, result.url.as_ref())
+            },
+            slack_buttons: if alert.resolved {
+                None
+            } else {
+                slack::button_value(env, scope, result.url.as_ref(), alert.stream)
+            },
+            discord_buttons: if alert.resolved {
+                None
+            } else {
+                discord::button_ref(env, scope, result.url.as_ref(), alert.stream)
+            },
+            stream: Some(alert.stream),
+            severity: Some(alert.severity),
+            resolved: alert.resolved,
+            ..Default::default()
         },
     )
     .await
 }
 
-/// Send a notification about a check result to the configured webhook
-/// This is a generic function used by both test and failure notifications.
+/// Send a notification asking a second token holder to approve a pending SRI change
+///
 /// # Arguments
 /// * `env` - Worker environment to access WEBHOOK_URL secret and optional WEBHOOK_SERVICE override
-/// * `result` - The check result to report
-/// * `context` - Notification context with titles and labels
-/// # Returns
-/// * `Ok(())` if notification was sent successfully or webhook is not configured
-/// * `Err` if webhook is configured but sending failed
-async fn send_notification(
+/// * `scope` - Scope of the resource, which selects the webhook destination
+/// * `url` - The resource whose SRI hash is being changed
+/// * `message` - Human-readable summary of the change and how to approve it
+pub async fn send_approval_request_notification(
     env: &Env,
-    result: &CheckResult,
-    context: NotificationContext,
+    scope: &Scope<'_>,
+    url: &str,
+    message: String,
 ) -> Result<()> {
-    let timestamp = get_timestamp();
-    let result: &CheckResult = result;
-    let webhook_url = match env.secret("WEBHOOK_URL") {
-        Ok(secret) => secret.to_string(),
-        Err(_) => {
-            console_log!("WEBHOOK_URL not configured, skipping notification");
-            return Ok(());
-        }
-    };
-    if webhook_url.is_empty() {
-        console_log!("WEBHOOK_URL is empty, skipping notification");
-        return Ok(());
-    }
-    let service = detect_webhook_service(env, &webhook_url);
-    console_log!(
-        "Sending webhook notification for: {} via {}",
-        result.url.as_ref(),
-        service
-    );
-    let payload = service.build_payload(result, &timestamp, &context)?;
-    send_webhook(&webhook_url, &payload, service).await
+    let notice = CheckResult::notice(url.to_string(), message);
+
+    send_notification(
+        env,
+        scope,
+        &notice,
+        NotificationContext {
+            title: "📝 SRI Change Pending Approval",
+            fallback_prefix: "SRI Change Pending Approval",
+            subject_label: "URL",
+            history: None,
+            ..Default::default()
+        },
+    )
+    .await
 }
 
-/// Detect webhook service type from URL and environment variables
-///
-/// First checks for an explicit `WEBHOOK_SERVICE` environment variable override.
-/// If not set, performs auto-detection based on the webhook URL domain.
-///
-/// # Arguments
-/// * `env` - Worker environment to check for WEBHOOK_SERVICE override
-/// * `webhook_url` - The webhook URL to analyze for auto-detection
-///
-/// # Returns
-/// Detected or configured WebhookService type
-fn detect_webhook_service(env: &Env, webhook_url: &str) -> WebhookService {
-    use std::str::FromStr;
+/// Report the outcome of a re-check requested through an action link
+pub async fn send_recheck_notification(
+    env: &Env,
+    scope: &Scope<'_>,
+    url: &str,
+    message: &str,
+) -> Result<()> {
+    let notice = CheckResult::notice(url.to_string(), message.to_string());
 
-    // Check for explicit override via WEBHOOK_SERVICE environment variable
-    if let Ok(override_service) = env.var("WEBHOOK_SERVICE") {
-        let service_str = override_service.to_string();
-        console_log!("WEBHOOK_SERVICE override detected: {}", service_str);
+    send_notification(
+        env,
+        scope,
+        &notice,
+        NotificationContext {
+            title: "🔁 Re-check Requested",
+            fallback_prefix: "Re-check Requested",
+            subject_label: "URL",
+            ..Default::default()
+        },
+    )
+    .await
+}
 
-        match WebhookService::from_str(&service_str) {
-            Ok(service) => return service,
-            Err(_) => {
-                console_log!(
-                    "Unknown WEBHOOK_SERVICE value '{}', falling back to auto-detection",
-                    service_str
-                );
-            }
-        }
-    }
+/// Announce changed response headers or content of a resource with `watch_headers`
+pub async fn send_header_change_notification(
+    env: &Env,
+    scope: &Scope<'_>,
+    url: &str,
+    message: &str,
+) -> Result<()> {
+    let notice = CheckResult::notice(url.to_string(), message.to_string());
+
+    send_notification(
+        env,
+        scope,
+        &notice,
+        NotificationContext {
+            title: "🔄 Resource Changed",
+            fallback_prefix: "Resource Changed",
+            subject_label: "URL",
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Announce a resource whose health score fell below or rose back to the threshold
+pub async fn send_health_notification(
+    env: &Env,
+    scope: &Scope<'_>,
+    url: &str,
+    message: &str,
+    degraded: bool,
+) -> Result<()> {
+    let notice = CheckResult::notice(url.to_string(), message.to_string());
+    let (title, fallback_prefix) = if degraded {
+        ("📉 Health Degraded", "Health Degraded")
+    } else {
+        ("📈 Health Recovered", "Health Recovered")
+    };
+
+    send_notification(
+        env,
+        scope,
+        &notice,
+        NotificationContext {
+            title,
+            fallback_prefix,
+            subject_label: "URL",
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Report a new deployment with the outcome of its bootstrap resource checks
+pub async fn send_deployment_notification(
+    env: &Env,
+    scope: &Scope<'_>,
+    deployment: &Deployment,
+    message: &str,
+    failed: bool,
+) -> Result<()> {
+    let notice = CheckResult::notice(deployment.label(), message.to_string());
+    let (title, fallback_prefix) = if failed {
+        (
+            "🚀 Deployment: Bootstrap Check Failed",
+            "Deployment: Bootstrap Check Failed",
+        )
+    } else {
+        ("🚀 Deployment Verified", "Deployment Verified")
+    };
+
+    send_notification(
+        env,
+        scope,
+        &notice,
+        NotificationContext {
+            title,
+            fallback_prefix,
+            subject_label: "Version",
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Send the alerts held back during blackouts as one notification
+pub async fn send_digest_notification(
+    env: &Env,
+    scope: &Scope<'_>,
+    blackouts: &str,
+    message: &str,
+) -> Result<()> {
+    let notice = CheckResult::notice(blackouts.to_string(), message.to_string());
+
+    send_notification(
+        env,
+        scope,
+        &notice,
+        NotificationContext {
+            title: "🗓️ Blackout Digest",
+            fallback_prefix: "Blackout Digest",
+            subject_label: "Blackout",
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Send the summary of the alerts of a stream held back during an alert storm
+pub async fn send_storm_notification(
+    env: &Env,
+    scope: &Scope<'_>,
+    stream: AlertStream,
+    subject: &str,
+    message: &str,
+) -> Result<()> {
+    let notice = CheckResult::notice(subject.to_string(), message.to_string());
+
+    send_notification(
+        env,
+        scope,
+        &notice,
+        NotificationContext {
+            title: "🌩️ Alert Storm",
+            fallback_prefix: "Alert Storm",
+            subject_label: "Status",
+            stream: Some(stream),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Send a notification about a check result to the configured webhook
+/// This is a generic function used by both test and failure notifications.
+/// # Arguments
+/// * `env` - Worker environment to access WEBHOOK_URL secret and optional WEBHOOK_SERVICE override
+/// * `scope` - Scope whose webhook secret (`WEBHOOK_URL` or the tenant's) receives the notification
+/// * `result` - The check result to report
+/// * `context` - Notification context with titles and labels
+/// # Returns
+/// * `Ok(())` if notification was sent successfully or webhook is not configured
+/// * `Err` if webhook is configured but sending failed
+async fn send_notification(
+    env: &Env,
+    scope: &Scope<'_>,
+    result: &CheckResult,
+    context: NotificationContext,
+) -> Result<()> {
+    let timestamp = clock::timestamp();
+    let result: &CheckResult = result;
+    let Some(webhook_url) = webhook_url(env, scope, context.stream) else {
+        return Ok(());
+    };
+    let service = detect_webhook_service(env, scope, &webhook_url);
+    console_log!(
+        "Sending webhook notification for: {} via {}",
+        result.url.as_ref(),
+        service
+    );
+    let context = complete_context(env, result, context);
+    let payload = service.build_payload(result, &timestamp, &context)?;
+    let webhook_url = match (service, &context.discord_buttons) {
+        (WebhookService::Discord, Some(_)) => discord::with_components(&webhook_url),
+        _ => webhook_url,
+    };
+    let outcome = match Destination::resolve(env, &webhook_url) {
+        Ok((fetcher, url)) => send_webhook(&fetcher, &url, &payload, service).await,
+        Err(e) => Err(e),
+    };
+    if result.kind != CheckResultKind::Test {
+        let sent = SentNotification {
+            url: result.url.to_string(),
+            at: timestamp,
+            title: context.title.to_string(),
+            service: service.to_string().to_lowercase(),
+            stream: context.stream,
+            resolved: context.resolved,
+            delivered: false,
+            error: None,
+            run_id: context.run_id,
+            payload,
+        };
+        outbox::record(env, scope, &sent.with_outcome(&outcome)).await;
+    }
+    outcome
+}
+
+/// Fill in the parts of a notification context that come from the environment
+/// and the result
+fn complete_context(
+    env: &Env,
+    result: &CheckResult,
+    context: NotificationContext,
+) -> NotificationContext {
+    NotificationContext {
+        schema: schema_version(env),
+        fingerprint: fingerprint_algorithm(env),
+        run_id: trace::current(),
+        environment: Environment::from_env(env),
+        domain: idn::describe(result.url.as_ref()),
+        tags: (!result.tags.is_empty()).then(|| result.tags.join(", ")),
+        ..context
+    }
+}
+
+/// Look up the webhook URL of a scope, preferring the stream-specific secret
+///
+/// For a stream, `<STREAM>_<SECRET>` (e.g. `INTEGRITY_WEBHOOK_URL` or
+/// `INTEGRITY_WEBHOOK_URL_WEB`) is tried before the scope's own secret. Instead
+/// of a URL, a secret may name a service binding as `service:BINDING[/path]`
+/// (see `fetch::Destination`).
+fn webhook_url(env: &Env, scope: &Scope, stream: Option<AlertStream>) -> Option<String> {
+    webhook_secret(env, scope, stream).map(|(_, url)| url)
+}
+
+/// Like `webhook_url`, together with the name of the secret it came from
+fn webhook_secret(
+    env: &Env,
+    scope: &Scope,
+    stream: Option<AlertStream>,
+) -> Option<(String, String)> {
+    let secret_name = scope.webhook_secret();
+    let stream_secret = stream.map(|s| stream_secret_name(s, &secret_name));
+    for name in stream_secret.iter().chain(std::iter::once(&secret_name)) {
+        match env.secret(name) {
+            Ok(secret) if !secret.to_string().is_empty() => {
+                return Some((name.clone(), secret.to_string()))
+            }
+            _ => {}
+        }
+    }
+    console_log!("{} not configured, skipping notification", secret_name);
+    None
+}
+
+/// A configured webhook destination
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookTarget {
+    /// Secret holding the destination, e.g. `WEBHOOK_URL` or `INTEGRITY_WEBHOOK_URL`
+    pub secret: String,
+    /// Webhook URL or `service:BINDING[/path]`
+    pub url: String,
+    pub service: WebhookService,
+}
+
+/// Distinct webhook destinations of a scope (its own and stream-specific ones)
+pub fn destinations(env: &Env, scope: &Scope) -> Vec<WebhookTarget> {
+    let mut destinations: Vec<WebhookTarget> = Vec::new();
+    let streams = std::iter::once(None).chain(AlertStream::ALL.into_iter().map(Some));
+    for stream in streams {
+        if let Some((secret, url)) = webhook_secret(env, scope, stream) {
+            if !destinations.iter().any(|known| known.url == url) {
+                let service = detect_webhook_service(env, scope, &url);
+                destinations.push(WebhookTarget {
+                    secret,
+                    url,
+                    service,
+                });
+            }
+        }
+    }
+    destinations
+}
+
+/// Name of the stream-specific variant of a webhook secret
+fn stream_secret_name(stream: AlertStream, secret_name: &str) -> String {
+    format!("{}_{}", stream.setting_prefix(), secret_name)
+}
+
+/// Detect webhook service type from URL and environment variables
+///
+/// First checks for an explicit override: the tenant's `webhook_service` for tenant
+/// scopes, the `WEBHOOK_SERVICE` environment variable for the default scope.
+/// If not set, performs auto-detection based on the webhook URL domain.
+///
+/// # Arguments
+/// * `env` - Worker environment to check for WEBHOOK_SERVICE override
+/// * `scope` - Scope whose webhook is being used
+/// * `webhook_url` - The webhook URL to analyze for auto-detection
+///
+/// # Returns
+/// Detected or configured WebhookService type
+fn detect_webhook_service(env: &Env, scope: &Scope, webhook_url: &str) -> WebhookService {
+    use std::str::FromStr;
+
+    // Check for an explicit override from tenant config or WEBHOOK_SERVICE
+    let override_service = match scope.id() {
+        Some(_) => scope.webhook_service().map(str::to_string),
+        None => env.var("WEBHOOK_SERVICE").ok().map(|v| v.to_string()),
+    };
+    if let Some(service_str) = override_service {
+        console_log!("WEBHOOK_SERVICE override detected: {}", service_str);
+
+        match WebhookService::from_str(&service_str) {
+            Ok(service) => return service,
+            Err(_) => {
+                console_log!(
+                    "Unknown WEBHOOK_SERVICE value '{}', falling back to auto-detection",
+                    service_str
+                );
+            }
+        }
+    }
 
     // Auto-detect from URL
     WebhookService::from_url(webhook_url)
@@ -534,53 +1608,59 @@ fn detect_webhook_service(env: &Env, webhook_url: &str) -> WebhookService {
 /// # Returns
 /// * `Ok(())` if sent successfully (HTTP 2xx status)
 /// * `Err` if request failed or returned non-2xx status
-async fn send_webhook(webhook_url: &str, payload: &str, _service: WebhookService) -> Result<()> {
-    // Build headers
-    let headers = Headers::new();
-    headers.set("Content-Type", "application/json")?;
-
-    // Build request
-    let mut init = RequestInit::new();
-    init.with_method(Method::Post);
-    init.with_headers(headers);
-    init.with_body(Some(payload.into()));
-
-    let request = Request::new_with_init(webhook_url, &init)?;
-    let mut response = Fetch::Request(request).send().await?;
-
-    let status_code = response.status_code();
-    if !(200..300).contains(&status_code) {
-        // Log response body for debugging
-        let error_body = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "<unable to read response>".to_string());
-
-        console_error!("Webhook error (HTTP {}): {}", status_code, error_body);
-
-        return Err(Error::RustError(format!(
+async fn send_webhook(
+    fetcher: &impl Fetcher,
+    webhook_url: &str,
+    payload: &str,
+    _service: WebhookService,
+) -> Result<()> {
+    match post_webhook(fetcher, webhook_url, payload).await? {
+        WebhookResponse::Accepted(_) => {
+            console_log!("Webhook notification sent successfully");
+            Ok(())
+        }
+        WebhookResponse::Rejected { status, body } => Err(Error::RustError(format!(
             "Webhook returned HTTP {}: {}",
-            status_code, error_body
-        )));
+            status, body
+        ))),
     }
-
-    console_log!("Webhook notification sent successfully");
-    Ok(())
 }
 
-/// Get current timestamp as ISO string
-#[cfg(not(test))]
-fn get_timestamp() -> String {
-    js_sys::Date::new_0()
-        .to_iso_string()
-        .as_string()
-        .unwrap_or_else(|| "unknown".to_string())
+/// Answer of a webhook that was reached
+#[derive(Debug, PartialEq)]
+enum WebhookResponse {
+    /// 2xx status
+    Accepted(u16),
+    /// Any other status, with the response body
+    Rejected { status: u16, body: String },
 }
 
-/// Mock timestamp for tests
-#[cfg(test)]
-fn get_timestamp() -> String {
-    "2025-11-12T10:00:00Z".to_string()
+/// POST a payload to a webhook
+///
+/// Fails only if the webhook cannot be reached; rejections are logged with
+/// their body.
+async fn post_webhook(
+    fetcher: &impl Fetcher,
+    webhook_url: &str,
+    payload: &str,
+) -> Result<WebhookResponse> {
+    let mut request = HttpRequest::post_json(webhook_url, payload);
+    if let Some(id) = trace::current() {
+        request = request.with_header(trace::HEADER, &id);
+    }
+    let mut response = fetcher.fetch(request).await?;
+
+    let status = response.status_code();
+    if (200..300).contains(&status) {
+        return Ok(WebhookResponse::Accepted(status));
+    }
+    // Log response body for debugging
+    let body = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "<unable to read response>".to_string());
+    console_error!("Webhook error (HTTP {}): {}", status, body);
+    Ok(WebhookResponse::Rejected { status, body })
 }
 
 #[cfg(test)]
@@ -673,6 +1753,8 @@ mod tests {
             title: "🔗 Link Check Failed",
             fallback_prefix: "Link Check Failed",
             subject_label: "URL",
+            history: None,
+            ..Default::default()
         };
 
         let payload = WebhookService::Discord
@@ -720,6 +1802,107 @@ mod tests {
         let test_notification = CheckResult::test("Synthetic notification");
         let color = WebhookService::severity_color(&test_notification);
         assert_eq!(color, 3447003);
+
+        let notice = CheckResult::notice("https://example.com/test.js", "SRI change requested");
+        let color = WebhookService::severity_color(&notice);
+        assert_eq!(color, 3447003);
+    }
+
+    #[test]
+    fn test_build_webhook_payload_notice() {
+        let notice = CheckResult::notice(
+            "https://example.com/test.js",
+            "SRI change abc requested by alice",
+        );
+        let context = NotificationContext {
+            title: "📝 SRI Change Pending Approval",
+            fallback_prefix: "SRI Change Pending Approval",
+            subject_label: "URL",
+            history: None,
+            ..Default::default()
+        };
+
+        let payload = WebhookService::Slack
+            .build_payload(&notice, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains("SRI change abc requested by alice"));
+        assert!(payload.contains(r#""text":"SRI Change Pending Approval:"#));
+
+        let payload = WebhookService::Generic
+            .build_payload(&notice, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""severity":"info""#));
+        assert!(payload.contains("SRI Change Pending Approval: https://example.com/test.js"));
+    }
+
+    #[test]
+    fn test_build_webhook_payload_history() {
+        use crate::checker::CheckError;
+
+        let result =
+            CheckResult::failure("https://example.com/test.js", CheckError::HttpError(503));
+        let history = "last 5 checks: ✓ ✓ ✗ ✗ ✗, failing for 23m";
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            history: Some(history.to_string()),
+            ..Default::default()
+        };
+
+        for service in [
+            WebhookService::Discord,
+            WebhookService::Slack,
+            WebhookService::Zulip,
+            WebhookService::Generic,
+        ] {
+            let payload = service
+                .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+                .unwrap();
+            assert!(
+                payload.contains(history),
+                "{} payload lacks history",
+                service
+            );
+        }
+
+        // Without history no empty field is emitted
+        let context = NotificationContext {
+            history: None,
+            ..context
+        };
+        let payload = WebhookService::Discord
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(!payload.contains("History"));
+
+        // Quarantine references of SRI mismatches are further details
+        let reference = "quarantine/example.com/test.js/2025-11-12T10:00:00Z (12 bytes, sha384-x)";
+        let context = NotificationContext {
+            quarantine: Some(reference.to_string()),
+            ..context
+        };
+        for service in [WebhookService::Discord, WebhookService::Slack] {
+            let payload = service
+                .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+                .unwrap();
+            assert!(payload.contains("Quarantine"));
+            assert!(payload.contains(reference));
+        }
+        let payload = WebhookService::Generic
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""quarantine":"quarantine/example.com"#));
+
+        let context = NotificationContext {
+            quarantine: None,
+            mirror: Some("https://artifacts.example.com/mirror/example.com/test.js".to_string()),
+            ..context
+        };
+        let payload = WebhookService::Slack
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r"*Verified copy:*\nhttps://artifacts.example.com/mirror/"));
     }
 
     #[test]
@@ -734,6 +1917,8 @@ mod tests {
             title: "🔗 Link Check Failed",
             fallback_prefix: "Link Check Failed",
             subject_label: "URL",
+            history: None,
+            ..Default::default()
         };
 
         let payload = WebhookService::Slack
@@ -763,6 +1948,8 @@ mod tests {
             title: "🔗 Link Check Failed",
             fallback_prefix: "Link Check Failed",
             subject_label: "URL",
+            history: None,
+            ..Default::default()
         };
 
         let payload = WebhookService::Zulip
@@ -789,6 +1976,8 @@ mod tests {
             title: "🔗 Link Check Failed",
             fallback_prefix: "Link Check Failed",
             subject_label: "URL",
+            history: None,
+            ..Default::default()
         };
 
         let payload = WebhookService::Generic
@@ -819,6 +2008,8 @@ mod tests {
             title: "🔗 Link Check Failed",
             fallback_prefix: "Link Check Failed",
             subject_label: "URL",
+            history: None,
+            ..Default::default()
         };
 
         let payload = WebhookService::Generic
@@ -835,19 +2026,725 @@ mod tests {
         assert!(payload.contains(r#""severity":"warning""#));
     }
 
+    #[test]
+    fn test_alert_streams_generic_payload() {
+        use crate::checker::CheckError;
+
+        let down = CheckResult::failure("https://example.com/test.js", CheckError::HttpError(503));
+        let mismatch = CheckResult::success("https://example.com/test.js", 200, false);
+        let availability = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            stream: Some(AlertStream::Availability),
+            severity: Some(Severity::Critical),
+            ..Default::default()
+        };
+        let integrity = NotificationContext {
+            title: "🛡️ SRI Mismatch Detected",
+            fallback_prefix: "SRI Mismatch Detected",
+            stream: Some(AlertStream::Integrity),
+            severity: Some(Severity::Warning),
+            ..availability.clone()
+        };
+
+        // Configured severities take precedence over the derived ones
+        let payload = WebhookService::Generic
+            .build_payload(&down, "2025-11-12T10:00:00Z", &availability)
+            .unwrap();
+        assert!(payload.contains(r#""severity":"critical""#));
+        assert!(payload.contains(r#""stream":"availability""#));
+        let availability_fingerprint = Fingerprint::Sha256.compute("https://example.com/test.js");
+        assert!(payload.contains(&availability_fingerprint));
+
+        let payload = WebhookService::Generic
+            .build_payload(&mismatch, "2025-11-12T10:00:00Z", &integrity)
+            .unwrap();
+        assert!(payload.contains(r#""severity":"warning""#));
+        assert!(payload.contains(r#""stream":"integrity""#));
+        assert!(payload.contains("SRI Mismatch Detected: https://example.com/test.js"));
+        assert!(!payload.contains(&availability_fingerprint));
+
+        // Resolved alerts carry an end time
+        let resolved = NotificationContext {
+            title: "✅ SRI Mismatch Resolved",
+            fallback_prefix: "SRI Mismatch Resolved",
+            resolved: true,
+            ..integrity
+        };
+        let ok = CheckResult::success("https://example.com/test.js", 200, true);
+        let payload = WebhookService::Generic
+            .build_payload(&ok, "2025-11-12T10:00:00Z", &resolved)
+            .unwrap();
+        assert!(payload.contains(r#""status":"resolved""#));
+        assert!(!payload.contains(r#""status":"firing""#));
+        assert!(payload.contains(r#""endsAt":"2025-11-12T10:00:00Z""#));
+
+        let payload = WebhookService::Discord
+            .build_payload(&ok, "2025-11-12T10:00:00Z", &resolved)
+            .unwrap();
+        assert!(payload.contains(&RESOLVED_COLOR.to_string()));
+
+        // Flap summaries are labelled and carry the summary line
+        let flapping = NotificationContext {
+            title: "🔁 Link Check Flapping",
+            fallback_prefix: "Link Check Flapping",
+            flapping: Some("6 state changes in the last 60m, currently failing".to_string()),
+            ..availability
+        };
+        let payload = WebhookService::Generic
+            .build_payload(&down, "2025-11-12T10:00:00Z", &flapping)
+            .unwrap();
+        assert!(payload.contains(r#""flapping":"true""#));
+        assert!(payload.contains("Link Check Flapping: https://example.com/test.js"));
+        assert!(payload.contains("6 state changes in the last 60m"));
+        let payload = WebhookService::Slack
+            .build_payload(&down, "2025-11-12T10:00:00Z", &flapping)
+            .unwrap();
+        assert!(payload.contains("*Flapping:*"));
+    }
+
+    #[test]
+    fn test_send_webhook() {
+        use crate::fetch::mock::{MockFetcher, MockResponse};
+        use futures::executor::block_on;
+
+        const HOOK: &str = "https://hooks.example.com/alert";
+        let fetcher = MockFetcher::new().respond(HOOK, MockResponse::new(204, ""));
+        block_on(send_webhook(
+            &fetcher,
+            HOOK,
+            r#"{"text":"hi"}"#,
+            WebhookService::Generic,
+        ))
+        .unwrap();
+        let request = &fetcher.requests()[0];
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.body.as_deref(), Some(r#"{"text":"hi"}"#));
+        assert!(!request
+            .headers
+            .iter()
+            .any(|(name, _)| name == trace::HEADER));
+
+        // Requests sent during a run carry its ID
+        block_on(trace::in_run(
+            "18f2c".to_string(),
+            send_webhook(&fetcher, HOOK, "{}", WebhookService::Generic),
+        ))
+        .unwrap();
+        let request = &fetcher.requests()[1];
+        assert!(request
+            .headers
+            .contains(&(trace::HEADER.to_string(), "18f2c".to_string())));
+
+        let fetcher = MockFetcher::new().respond(HOOK, MockResponse::new(400, "invalid_payload"));
+        let error = block_on(send_webhook(&fetcher, HOOK, "{}", WebhookService::Slack))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("HTTP 400: invalid_payload"));
+    }
+
+    #[test]
+    fn test_send_test() {
+        use crate::fetch::mock::{MockFetcher, MockResponse};
+        use futures::executor::block_on;
+
+        const SLACK: &str = "https://hooks.slack.com/services/T00/B00/xxx";
+        const DISCORD: &str = "https://discord.com/api/webhooks/123/abc";
+        let target = |secret: &str, url: &str, service| WebhookTarget {
+            secret: secret.to_string(),
+            url: url.to_string(),
+            service,
+        };
+        let fetcher = MockFetcher::new()
+            .respond(SLACK, MockResponse::new(200, "ok"))
+            .respond(DISCORD, MockResponse::new(404, "Unknown Webhook"));
+
+        let slack = target("WEBHOOK_URL", SLACK, WebhookService::Slack);
+        let discord = target("INTEGRITY_WEBHOOK_URL", DISCORD, WebhookService::Discord);
+        let gone = target(
+            "AVAILABILITY_WEBHOOK_URL",
+            "https://gone.example.com/hook",
+            WebhookService::Generic,
+        );
+        let report = TestReport::new(vec![
+            block_on(send_test(&fetcher, &slack, SLACK, "{}")),
+            block_on(send_test(&fetcher, &discord, DISCORD, "{}")),
+            block_on(send_test(&fetcher, &gone, &gone.url, "{}")),
+        ]);
+        assert_eq!((report.delivered, report.failed), (1, 2));
+        let [slack, discord, gone] = &report.destinations[..] else {
+            panic!("expected 3 reports");
+        };
+        assert!(slack.delivered);
+        assert_eq!(slack.status, Some(200));
+        assert_eq!(discord.status, Some(404));
+        assert_eq!(discord.error.as_deref(), Some("HTTP 404: Unknown Webhook"));
+        assert_eq!(gone.status, None);
+        assert!(gone.error.as_deref().unwrap().contains("no route"));
+
+        // The report names the secret, never the webhook URL
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains(
+            r#""secret":"INTEGRITY_WEBHOOK_URL","service":"discord","delivered":false,"status":404"#
+        ));
+        assert!(!json.contains("hooks.slack.com"));
+    }
+
+    #[test]
+    fn test_schema_versions() {
+        let mismatch = CheckResult::success("https://example.com/test.js", 200, false);
+        let context = NotificationContext {
+            title: "🛡️ SRI Mismatch Detected",
+            fallback_prefix: "SRI Mismatch Detected",
+            subject_label: "URL",
+            history: Some("last 1 check: ✗, new failure".to_string()),
+            stream: Some(AlertStream::Integrity),
+            severity: Some(Severity::Critical),
+            ..Default::default()
+        };
+
+        let payload = WebhookService::Generic
+            .build_payload(&mismatch, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""schemaVersion":"2""#));
+        assert!(payload.contains(r#""stream":"integrity""#));
+        assert!(payload.contains(r#""history":"#));
+
+        let legacy = NotificationContext {
+            schema: SchemaVersion::V1,
+            ..context
+        };
+        let payload = WebhookService::Generic
+            .build_payload(&mismatch, "2025-11-12T10:00:00Z", &legacy)
+            .unwrap();
+        assert!(!payload.contains("schemaVersion"));
+        assert!(!payload.contains(r#""stream""#));
+        assert!(!payload.contains(r#""history""#));
+        // Version 1 identifies alerts by URL only
+        assert!(payload.contains(&Fingerprint::Sha256.compute("https://example.com/test.js")));
+
+        assert_eq!("1".parse(), Ok(SchemaVersion::V1));
+        assert_eq!("3".parse::<SchemaVersion>(), Err(()));
+    }
+
+    #[test]
+    fn test_run_id() {
+        let down = CheckResult::failure(
+            "https://example.com/test.js",
+            crate::checker::CheckError::HttpError(503),
+        );
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            stream: Some(AlertStream::Availability),
+            run_id: Some("18f2c".to_string()),
+            ..Default::default()
+        };
+        let build = |service: WebhookService, context: &NotificationContext| {
+            service
+                .build_payload(&down, "2025-11-12T10:00:00Z", context)
+                .unwrap()
+        };
+
+        assert!(build(WebhookService::Slack, &context)
+            .contains("Time: 2025-11-12T10:00:00Z | Worker: linkkivahti | Run: 18f2c"));
+        assert!(
+            build(WebhookService::Discord, &context).contains(r#""footer":{"text":"Run 18f2c"}"#)
+        );
+        assert!(build(WebhookService::Generic, &context).contains(r#""run_id":"18f2c""#));
+
+        let legacy = NotificationContext {
+            schema: SchemaVersion::V1,
+            ..context.clone()
+        };
+        assert!(!build(WebhookService::Generic, &legacy).contains("run_id"));
+
+        // Outside of a run
+        let context = NotificationContext {
+            run_id: None,
+            ..context
+        };
+        assert!(!build(WebhookService::Discord, &context).contains("footer"));
+        assert!(build(WebhookService::Slack, &context).contains("Worker: linkkivahti\""));
+    }
+
+    #[test]
+    fn test_environment_banner() {
+        let down = CheckResult::failure(
+            "https://example.com/test.js",
+            crate::checker::CheckError::HttpError(503),
+        );
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            environment: Some(Environment::new("staging")),
+            ..Default::default()
+        };
+        let build = |service: WebhookService, context: &NotificationContext| {
+            let payload = service
+                .build_payload(&down, "2025-11-12T10:00:00Z", context)
+                .unwrap();
+            serde_json::from_str::<serde_json::Value>(&payload).unwrap()
+        };
+
+        let discord = build(WebhookService::Discord, &context);
+        assert_eq!(discord["embeds"][0]["title"], "🟡 STAGING");
+        assert_eq!(discord["embeds"][0]["color"], 0xFEE75C);
+        assert_eq!(
+            discord["embeds"][1]["title"],
+            "[STAGING] 🔗 Link Check Failed"
+        );
+        assert_eq!(discord["embeds"][1]["color"], 15548997);
+
+        let slack = build(WebhookService::Slack, &context);
+        assert_eq!(
+            slack["text"],
+            "[STAGING] Link Check Failed: https://example.com/test.js - Failed: HTTP error: 503"
+        );
+        assert_eq!(
+            slack["blocks"][0]["elements"][0]["text"],
+            "🟡 *STAGING* environment"
+        );
+        assert_eq!(
+            slack["blocks"][1]["text"]["text"],
+            "[STAGING] 🔗 Link Check Failed"
+        );
+
+        let generic = build(WebhookService::Generic, &context);
+        assert_eq!(generic["commonLabels"]["environment"], "staging");
+        assert_eq!(generic["alerts"][0]["labels"]["environment"], "staging");
+        let legacy = NotificationContext {
+            schema: SchemaVersion::V1,
+            ..context.clone()
+        };
+        assert!(build(WebhookService::Generic, &legacy)["commonLabels"]["environment"].is_null());
+
+        // Without a name, payloads stay as they were
+        let context = NotificationContext {
+            environment: None,
+            ..context
+        };
+        let discord = build(WebhookService::Discord, &context);
+        assert_eq!(discord["embeds"].as_array().unwrap().len(), 1);
+        assert_eq!(discord["embeds"][0]["title"], "🔗 Link Check Failed");
+    }
+
+    #[test]
+    fn test_internationalized_domain() {
+        let down = CheckResult::failure(
+            "https://xn--pypal-4ve.com/sdk.js",
+            crate::checker::CheckError::HttpError(503),
+        );
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            domain: idn::describe(down.url.as_ref()),
+            ..Default::default()
+        };
+        let build = |service: WebhookService| {
+            service
+                .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+                .unwrap()
+        };
+
+        // Chat messages show the Unicode host and the lookalike warning
+        let discord = build(WebhookService::Discord);
+        assert!(discord.contains("**https://pаypal.com/sdk.js**"));
+        assert!(discord.contains(r#""name":"Domain""#));
+        assert!(discord.contains("mixes Cyrillic and Latin characters"));
+        let slack = build(WebhookService::Slack);
+        assert!(slack.contains("Link Check Failed: https://pаypal.com/sdk.js"));
+
+        // Generic payloads keep the punycode URL as the instance
+        let generic = build(WebhookService::Generic);
+        assert!(generic.contains(r#""instance":"https://xn--pypal-4ve.com/sdk.js""#));
+        assert!(generic.contains(r#""domain":"pаypal.com is xn--pypal-4ve.com"#));
+    }
+
+    #[test]
+    fn test_owner_mentions() {
+        use crate::checker::CheckError;
+
+        assert_eq!(
+            mention(WebhookService::Slack, "S0123ABC"),
+            "<!subteam^S0123ABC>"
+        );
+        assert_eq!(mention(WebhookService::Slack, "U042"), "<@U042>");
+        assert_eq!(
+            mention(WebhookService::Discord, "1234567890"),
+            "<@&1234567890>"
+        );
+        assert_eq!(
+            mention(WebhookService::Slack, "web@example.com"),
+            "web@example.com"
+        );
+        assert_eq!(mention(WebhookService::Zulip, "S0123ABC"), "S0123ABC");
+        assert_eq!(plain_owner("<!subteam^S0123ABC>"), "subteam^S0123ABC");
+
+        let result = CheckResult::failure("https://example.com/a.js", CheckError::FetchFailed);
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            owner: Some("1234567890".to_string()),
+            ..Default::default()
+        };
+        let payload = WebhookService::Discord
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.starts_with(r#"{"content":"<@&1234567890>","embeds""#));
+
+        let context = NotificationContext {
+            owner: Some("S0123ABC".to_string()),
+            ..context
+        };
+        let payload = WebhookService::Slack
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""text":"<!subteam^S0123ABC> Link Check Failed:"#));
+        assert!(payload.contains(r#"*Owner:*\n<!subteam^S0123ABC>"#));
+
+        let payload = WebhookService::Generic
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""owner":"S0123ABC""#));
+
+        // Upstream incidents are another detail
+        let upstream = "upstream incident in progress: https://stspg.io/abc123 (CDN outage)";
+        let context = NotificationContext {
+            upstream: Some(upstream.to_string()),
+            ..context
+        };
+        for service in [WebhookService::Slack, WebhookService::Generic] {
+            let payload = service
+                .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+                .unwrap();
+            assert!(
+                payload.contains(upstream),
+                "{} payload lacks upstream",
+                service
+            );
+        }
+
+        // Recoveries name the owner without a ping
+        let context = NotificationContext {
+            resolved: true,
+            owner: Some("1234567890".to_string()),
+            ..context
+        };
+        let payload = WebhookService::Discord
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(!payload.contains("content"));
+        assert!(payload.contains(r#""name":"Owner","value":"1234567890""#));
+    }
+
+    #[test]
+    fn test_tags() {
+        use crate::checker::CheckError;
+
+        let result = CheckResult::failure("https://example.com/a.js", CheckError::FetchFailed);
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            tags: Some("cdn, prod".to_string()),
+            ..Default::default()
+        };
+        let payload = WebhookService::Slack
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#"*Tags:*\ncdn, prod"#));
+        let payload = WebhookService::Discord
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""name":"Tags","value":"cdn, prod""#));
+        let payload = WebhookService::Generic
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""tags":"cdn, prod""#));
+
+        let context = NotificationContext {
+            schema: SchemaVersion::V1,
+            ..context
+        };
+        let payload = WebhookService::Generic
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(!payload.contains("tags"));
+    }
+
+    #[test]
+    fn test_origin_comparison() {
+        use crate::checker::CheckError;
+
+        let result =
+            CheckResult::failure("https://cdn.example.com/a.js", CheckError::HttpError(502));
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            origins: Some("the origin is broken too".to_string()),
+            broken_at: Some(Breakage::Origin),
+            ..Default::default()
+        };
+        let payload = WebhookService::Generic
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""broken_at":"origin""#));
+        assert!(payload.contains(r#""origins":"the origin is broken too""#));
+
+        let payload = WebhookService::Discord
+            .build_payload(&result, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(payload.contains(r#""name":"Origins","value":"the origin is broken too""#));
+
+        let v1 = NotificationContext {
+            schema: SchemaVersion::V1,
+            ..context
+        };
+        let payload = WebhookService::Generic
+            .build_payload(&result, "2025-11-12T10:00:00Z", &v1)
+            .unwrap();
+        assert!(!payload.contains("broken_at"));
+        assert!(!payload.contains("origins"));
+    }
+
+    #[test]
+    fn test_stream_secret_name() {
+        assert_eq!(
+            stream_secret_name(AlertStream::Integrity, "WEBHOOK_URL"),
+            "INTEGRITY_WEBHOOK_URL"
+        );
+        assert_eq!(
+            stream_secret_name(AlertStream::Availability, "WEBHOOK_URL_WEB"),
+            "AVAILABILITY_WEBHOOK_URL_WEB"
+        );
+    }
+
+    #[test]
+    fn test_recheck_link() {
+        let down = CheckResult::failure(
+            "https://example.com/test.js",
+            crate::checker::CheckError::HttpError(503),
+        );
+        let link = "https://linkkivahti.example.com/actions/eyJpZCI6IjEifQ.c2ln";
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            stream: Some(AlertStream::Availability),
+            recheck: Some(link.to_string()),
+            ..Default::default()
+        };
+        for service in [WebhookService::Discord, WebhookService::Slack] {
+            let payload = service
+                .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+                .unwrap();
+            assert!(payload.contains("Re-check"), "{}", service);
+            assert!(payload.contains(link), "{}", service);
+        }
+        // Generic receivers act through their own tooling
+        let payload = WebhookService::Generic
+            .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(!payload.contains(link));
+    }
+
+    #[test]
+    fn test_runbook_link() {
+        let down = CheckResult::failure(
+            "https://cdn.example.com/app.js",
+            crate::checker::CheckError::HttpError(503),
+        );
+        let link = "https://wiki.corp/runbooks/cdn-app-js";
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            stream: Some(AlertStream::Availability),
+            runbook: Some(link.to_string()),
+            ..Default::default()
+        };
+        for service in [WebhookService::Discord, WebhookService::Slack] {
+            let payload = service
+                .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+                .unwrap();
+            assert!(payload.contains("Runbook"), "{}", service);
+            assert!(payload.contains(link), "{}", service);
+        }
+        let payload = WebhookService::Generic
+            .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(json["alerts"][0]["annotations"]["runbook_url"], link);
+
+        // Schema v1 receivers only get the original annotations
+        let v1 = NotificationContext {
+            schema: SchemaVersion::V1,
+            ..context
+        };
+        let payload = WebhookService::Generic
+            .build_payload(&down, "2025-11-12T10:00:00Z", &v1)
+            .unwrap();
+        assert!(!payload.contains("runbook_url"));
+    }
+
+    #[test]
+    fn test_slack_buttons() {
+        let down = CheckResult::failure(
+            "https://example.com/test.js",
+            crate::checker::CheckError::HttpError(503),
+        );
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            stream: Some(AlertStream::Availability),
+            slack_buttons: Some(
+                r#"{"url":"https://example.com/test.js","stream":"availability"}"#.to_string(),
+            ),
+            ..Default::default()
+        };
+        let payload = WebhookService::Slack
+            .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        let actions = &json["blocks"][3];
+        assert_eq!(actions["type"], "actions");
+        assert_eq!(actions["elements"][0]["action_id"], "recheck");
+        assert_eq!(actions["elements"][1]["text"]["text"], "Silence 1h");
+        assert_eq!(actions["elements"][2]["action_id"], "ack");
+        assert!(actions["elements"][2]["value"]
+            .as_str()
+            .unwrap()
+            .contains("availability"));
+
+        // Zulip renders Slack blocks but cannot send the clicks back
+        let payload = WebhookService::Zulip
+            .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(!payload.contains(r#""type":"actions""#));
+    }
+
+    #[test]
+    fn test_discord_buttons() {
+        let down = CheckResult::failure(
+            "https://example.com/test.js",
+            crate::checker::CheckError::HttpError(503),
+        );
+        let mut context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            stream: Some(AlertStream::Availability),
+            discord_buttons: Some("availability|ce824cbaa0a1d85b".to_string()),
+            ..Default::default()
+        };
+        let payload = WebhookService::Discord
+            .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        let row = &json["components"][0];
+        assert_eq!(row["type"], 1);
+        assert_eq!(row["components"][1]["label"], "Silence 1h");
+        assert_eq!(
+            row["components"][0]["custom_id"],
+            "recheck|availability|ce824cbaa0a1d85b"
+        );
+
+        context.discord_buttons = None;
+        let payload = WebhookService::Discord
+            .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+            .unwrap();
+        assert!(!payload.contains("components"));
+    }
+
+    #[test]
+    fn test_dedup_key_fingerprint() {
+        use crate::checker::CheckError;
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            stream: Some(AlertStream::Availability),
+            dedup_key: Some("name:app".to_string()),
+            ..Default::default()
+        };
+        let fingerprint = Fingerprint::Sha256.compute("name:app");
+        // Versions of the resource share the fingerprint of its name
+        for url in [
+            "https://cdn.example.com/v1.2.3/app.js",
+            "https://cdn.example.com/v1.2.4/app.js",
+        ] {
+            let down = CheckResult::failure(url, CheckError::HttpError(404));
+            let payload = WebhookService::Generic
+                .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+                .unwrap();
+            assert!(payload.contains(&format!(r#""fingerprint":"{}""#, fingerprint)));
+            assert!(payload.contains(url));
+        }
+    }
+
     #[test]
     fn test_compute_fingerprint() {
         // Same URL should produce same fingerprint
-        let fp1 = WebhookService::compute_fingerprint("https://example.com/test.js");
-        let fp2 = WebhookService::compute_fingerprint("https://example.com/test.js");
+        let fp1 = Fingerprint::Sha256.compute("https://example.com/test.js");
+        let fp2 = Fingerprint::Sha256.compute("https://example.com/test.js");
         assert_eq!(fp1, fp2);
 
         // Different URLs should produce different fingerprints
-        let fp3 = WebhookService::compute_fingerprint("https://example.com/other.js");
+        let fp3 = Fingerprint::Sha256.compute("https://example.com/other.js");
         assert_ne!(fp1, fp3);
 
-        // Fingerprint should be 16 hex chars
-        assert_eq!(fp1.len(), 16);
-        assert!(fp1.chars().all(|c| c.is_ascii_hexdigit()));
+        // Spellings of the same URL share a fingerprint
+        let fp4 = Fingerprint::Sha256.compute("HTTPS://Example.com:443/./test.js");
+        assert_eq!(fp1, fp4);
+
+        // First 16 hex chars of the SHA-256 digest
+        assert_eq!(fp1, "ce824cbaa0a1d85b");
+        assert_eq!(fp1, sri::sha256_hex(b"https://example.com/test.js")[..16]);
+
+        // Legacy fingerprints stay what they were for existing receivers
+        let legacy = Fingerprint::Legacy.compute("https://example.com/test.js");
+        assert_eq!(legacy, "aafe52f4439367e4");
+        assert_eq!(
+            legacy,
+            Fingerprint::Legacy.compute("HTTPS://Example.com:443/./test.js")
+        );
+        assert_eq!("legacy".parse(), Ok(Fingerprint::Legacy));
+        assert_eq!("SHA256".parse(), Ok(Fingerprint::Sha256));
+        assert_eq!("md5".parse::<Fingerprint>(), Err(()));
+    }
+
+    #[test]
+    fn test_legacy_fingerprint_payload() {
+        let down = CheckResult::failure(
+            "https://example.com/test.js",
+            crate::checker::CheckError::HttpError(503),
+        );
+        let context = NotificationContext {
+            title: "🔗 Link Check Failed",
+            fallback_prefix: "Link Check Failed",
+            subject_label: "URL",
+            stream: Some(AlertStream::Availability),
+            fingerprint: Fingerprint::Legacy,
+            ..Default::default()
+        };
+        for schema in [SchemaVersion::V2, SchemaVersion::V1] {
+            let context = NotificationContext {
+                schema,
+                ..context.clone()
+            };
+            let payload = WebhookService::Generic
+                .build_payload(&down, "2025-11-12T10:00:00Z", &context)
+                .unwrap();
+            assert!(payload.contains(r#""groupKey":"linkkivahti/aafe52f4439367e4""#));
+            assert!(payload.contains(r#""fingerprint":"aafe52f4439367e4""#));
+        }
     }
 }
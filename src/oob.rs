@@ -0,0 +1,187 @@
+//! Out-of-band verification of SRI mismatches
+//!
+//! A resource can name a second route to the same content, such as a mirror or
+//! another gateway:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://cdn.example.com/widget.js"
+//! sri = "sha384-..."
+//! verify_via = "https://mirror.example.net/widget.js"
+//! ```
+//!
+//! When the content does not match its hash, it is fetched again through
+//! `verify_via`. If the secondary path serves the expected content, the mismatch is
+//! local to the primary path, pointing at targeted tampering or a poisoned cache;
+//! if it serves the same mismatching content, the content changed upstream. The
+//! outcome is attached to the result and shown in the integrity alert.
+
+use crate::checker::CheckResult;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use crate::sri::SriHash;
+use worker::*;
+
+/// Whether a mismatch reproduces through the secondary path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The secondary path serves the expected content
+    NotReproduced,
+    /// The secondary path serves the same mismatching content
+    Reproduced,
+    /// The secondary path serves other content that does not match the hash either
+    Diverged,
+    /// The secondary path could not be fetched
+    Unavailable(String),
+}
+
+/// Outcome of re-fetching a mismatching resource through `verify_via`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    pub url: String,
+    pub verdict: Verdict,
+}
+
+impl Verdict {
+    fn summary(&self) -> String {
+        match self {
+            Self::NotReproduced => "mismatch does not reproduce, the secondary path serves the \
+                expected content: suspect tampering or cache poisoning on the primary path"
+                .to_string(),
+            Self::Reproduced => "mismatch reproduces with identical content on the secondary \
+                path: the content changed upstream"
+                .to_string(),
+            Self::Diverged => {
+                "the secondary path serves different content that does not match either".to_string()
+            }
+            Self::Unavailable(reason) => format!("secondary path unavailable ({})", reason),
+        }
+    }
+}
+
+/// Re-fetch a resource with an SRI mismatch through its `verify_via` URL
+///
+/// Does nothing for results without a mismatch or resources without `verify_via`.
+pub async fn verify(fetcher: &impl Fetcher, resource: &Resource, result: &mut CheckResult) {
+    let Some(url) = &resource.verify_via else {
+        return;
+    };
+    if result.sri_valid != Some(false) {
+        return;
+    }
+    let Some(artifact) = &result.artifact else {
+        return;
+    };
+    let Ok(expected) = SriHash::parse(&artifact.expected_sri) else {
+        return;
+    };
+
+    let verdict = match fetcher.fetch(HttpRequest::new(Method::Get, url)).await {
+        Err(_) => Verdict::Unavailable("fetch failed".to_string()),
+        Ok(response) if !(200..300).contains(&response.status_code()) => {
+            Verdict::Unavailable(format!("HTTP {}", response.status_code()))
+        }
+        Ok(mut response) => match response.bytes().await {
+            Err(_) => Verdict::Unavailable("body read failed".to_string()),
+            Ok(content) if expected.verify(&content) => Verdict::NotReproduced,
+            Ok(content) if content == artifact.content => Verdict::Reproduced,
+            Ok(_) => Verdict::Diverged,
+        },
+    };
+    match verdict {
+        Verdict::NotReproduced => console_error!(
+            "✗ {} - mismatch not reproduced via {}, suspect tampering",
+            result.url,
+            url
+        ),
+        _ => console_log!("{} via {}: {}", result.url, url, verdict.summary()),
+    }
+    result.out_of_band = Some(Verification {
+        url: url.clone(),
+        verdict,
+    });
+}
+
+/// Outcome of the out-of-band verification of a result, for notifications
+pub fn details(result: &CheckResult) -> Option<String> {
+    let verification = result.out_of_band.as_ref()?;
+    Some(format!(
+        "{}\n{}",
+        verification.verdict.summary(),
+        verification.url
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Artifact;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use crate::sri;
+    use futures::executor::block_on;
+
+    const VIA: &str = "https://mirror.example.net/widget.js";
+
+    fn resource() -> Resource {
+        toml::from_str(&format!(
+            "url = \"https://cdn.example.com/widget.js\"\nsri = \"{}\"\nverify_via = \"{}\"",
+            sri::sha384(b"widget"),
+            VIA
+        ))
+        .unwrap()
+    }
+
+    fn mismatch(content: &[u8]) -> CheckResult {
+        let mut result = CheckResult::success("https://cdn.example.com/widget.js", 200, false);
+        result.artifact = Some(Artifact {
+            content: content.to_vec(),
+            content_type: None,
+            expected_sri: sri::sha384(b"widget"),
+        });
+        result
+    }
+
+    fn verdict(secondary: MockResponse) -> Option<Verdict> {
+        let fetcher = MockFetcher::new().respond(VIA, secondary);
+        let mut result = mismatch(b"tampered");
+        block_on(verify(&fetcher, &resource(), &mut result));
+        result.out_of_band.map(|v| v.verdict)
+    }
+
+    #[test]
+    fn test_verdicts() {
+        assert_eq!(
+            verdict(MockResponse::new(200, "widget")),
+            Some(Verdict::NotReproduced)
+        );
+        assert_eq!(
+            verdict(MockResponse::new(200, "tampered")),
+            Some(Verdict::Reproduced)
+        );
+        assert_eq!(
+            verdict(MockResponse::new(200, "other")),
+            Some(Verdict::Diverged)
+        );
+        assert_eq!(
+            verdict(MockResponse::new(502, "")),
+            Some(Verdict::Unavailable("HTTP 502".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_only_mismatches_are_verified() {
+        let fetcher = MockFetcher::new().respond(VIA, MockResponse::new(200, "widget"));
+        let mut valid = CheckResult::success("https://cdn.example.com/widget.js", 200, true);
+        block_on(verify(&fetcher, &resource(), &mut valid));
+        assert!(valid.out_of_band.is_none());
+        assert!(fetcher.requests().is_empty());
+        assert!(details(&valid).is_none());
+
+        let mut result = mismatch(b"tampered");
+        block_on(verify(&fetcher, &resource(), &mut result));
+        assert!(details(&result)
+            .unwrap()
+            .starts_with("mismatch does not reproduce"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Comparative checks of a resource through alternate origins
+//!
+//! A resource served through a CDN can list the origins behind it:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://cdn.example.com/widget.js"
+//! sri = "sha384-..."
+//! origins = ["https://origin.example.com", "https://backup.example.com/static"]
+//! owner = "S0CDNTEAM"
+//! origin_owner = "S0APPTEAM"
+//! ```
+//!
+//! Each run also fetches the same path (and query) from every origin and verifies it
+//! the same way. When the resource fails, its alert says whether only the CDN copy
+//! is broken or the origins are broken too, which decides who is paged: `owner` for
+//! CDN-only breakage, `origin_owner` (falling back to `owner`) when the origin is at
+//! fault. Origin results are attached to the resource's result; they do not have
+//! alert streams or history of their own.
+
+use crate::checker::{check_resource, CheckResult, OriginCheck};
+use crate::config::Resource;
+use crate::console::console_error;
+use crate::fetch::Fetcher;
+use crate::sri::SriCache;
+use worker::*;
+
+/// Where a failing resource is broken, judging by its origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakage {
+    /// Every origin serves the resource correctly, only the CDN copy is broken
+    Cdn,
+    /// At least one origin is broken too
+    Origin,
+}
+
+impl Breakage {
+    /// Label value of generic payloads
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cdn => "cdn",
+            Self::Origin => "origin",
+        }
+    }
+
+    fn summary(&self) -> &'static str {
+        match self {
+            Self::Cdn => "only the CDN copy is broken, the origins serve it correctly",
+            Self::Origin => "the origin is broken too",
+        }
+    }
+}
+
+/// URL of a resource's path and query on another origin
+///
+/// An origin may carry a path prefix, e.g. `https://origin.example.com/static`.
+pub fn origin_url(url: &str, origin: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path = format!("{}?{}", path, query);
+    }
+    Some(format!("{}{}", origin.trim_end_matches('/'), path))
+}
+
+/// Where a result's failure lies, if it failed and has origin results
+pub fn breakage(result: &CheckResult) -> Option<Breakage> {
+    if !result.has_problem() || result.origins.is_empty() {
+        return None;
+    }
+    if result.origins.iter().all(|origin| origin.ok) {
+        Some(Breakage::Cdn)
+    } else {
+        Some(Breakage::Origin)
+    }
+}
+
+/// Comparison of a failing result with its origins, for notifications
+pub fn details(result: &CheckResult) -> Option<String> {
+    let breakage = breakage(result)?;
+    let lines = result
+        .origins
+        .iter()
+        .map(|origin| format!("{}: {}", origin.url, origin.detail));
+    Some(
+        std::iter::once(breakage.summary().to_string())
+            .chain(lines)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+/// Who to page about a failing result: `origin_owner` when the origin is at fault
+pub fn owner(resource: &Resource, result: &CheckResult) -> Option<String> {
+    match breakage(result) {
+        Some(Breakage::Origin) => resource
+            .origin_owner
+            .clone()
+            .or_else(|| resource.owner.clone()),
+        _ => resource.owner.clone(),
+    }
+}
+
+/// Check a resource and the same path on each of its origins
+pub async fn check(
+    fetcher: &impl Fetcher,
+    resource: &Resource,
+    expected_sri: &str,
+    hashes: &SriCache,
+) -> CheckResult {
+    let mut result = check_resource(fetcher, resource, expected_sri, hashes).await;
+    for origin in &resource.origins {
+        let Some(url) = origin_url(&resource.url, origin) else {
+            continue;
+        };
+        let copy = Resource {
+            url,
+            ..resource.clone()
+        };
+        let checked = check_resource(fetcher, &copy, expected_sri, hashes).await;
+        if checked.has_problem() && !result.has_problem() {
+            console_error!(
+                "✗ {} - origin copy broken: {}",
+                copy.url,
+                checked.description()
+            );
+        }
+        result.origins.push(OriginCheck {
+            url: copy.url,
+            ok: !checked.has_problem(),
+            detail: checked.description(),
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::CheckError;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use crate::sri;
+    use futures::executor::block_on;
+
+    const CDN: &str = "https://cdn.example.com/widget.js?v=2";
+    const ORIGIN: &str = "https://origin.example.com/widget.js?v=2";
+    const BACKUP: &str = "https://backup.example.com/static/widget.js?v=2";
+
+    fn resource() -> Resource {
+        Resource {
+            url: CDN.to_string(),
+            sri: sri::sha384(b"widget"),
+            origins: vec![
+                "https://origin.example.com/".to_string(),
+                "https://backup.example.com/static".to_string(),
+            ],
+            owner: Some("S0CDNTEAM".to_string()),
+            origin_owner: Some("S0APPTEAM".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn check_with(cdn: MockResponse, origin: MockResponse) -> CheckResult {
+        let fetcher = MockFetcher::new()
+            .respond(CDN, cdn)
+            .respond(ORIGIN, origin)
+            .respond(BACKUP, MockResponse::new(200, "widget"));
+        let resource = resource();
+        block_on(check(
+            &fetcher,
+            &resource,
+            &resource.sri,
+            &SriCache::default(),
+        ))
+    }
+
+    #[test]
+    fn test_origin_url() {
+        assert_eq!(
+            origin_url(CDN, "https://origin.example.com/").as_deref(),
+            Some(ORIGIN)
+        );
+        assert_eq!(
+            origin_url(CDN, "https://backup.example.com/static").as_deref(),
+            Some(BACKUP)
+        );
+        assert_eq!(origin_url("not a url", "https://origin.example.com"), None);
+    }
+
+    #[test]
+    fn test_cdn_only() {
+        let result = check_with(
+            MockResponse::new(200, "tampered"),
+            MockResponse::new(200, "widget"),
+        );
+        assert_eq!(breakage(&result), Some(Breakage::Cdn));
+        assert_eq!(owner(&resource(), &result).as_deref(), Some("S0CDNTEAM"));
+        assert_eq!(
+            details(&result).unwrap(),
+            format!(
+                "only the CDN copy is broken, the origins serve it correctly\n{}: OK (HTTP 200)\n{}: OK (HTTP 200)",
+                ORIGIN, BACKUP
+            )
+        );
+    }
+
+    #[test]
+    fn test_origin_broken() {
+        let result = check_with(MockResponse::new(502, ""), MockResponse::new(404, ""));
+        assert_eq!(result.error, Some(CheckError::HttpError(502)));
+        assert_eq!(breakage(&result), Some(Breakage::Origin));
+        assert_eq!(owner(&resource(), &result).as_deref(), Some("S0APPTEAM"));
+        let without_origin_owner = Resource {
+            origin_owner: None,
+            ..resource()
+        };
+        assert_eq!(
+            owner(&without_origin_owner, &result).as_deref(),
+            Some("S0CDNTEAM")
+        );
+    }
+
+    #[test]
+    fn test_passing_resource_has_no_breakage() {
+        let result = check_with(MockResponse::new(200, "widget"), MockResponse::new(503, ""));
+        assert!(!result.has_problem());
+        assert!(!result.origins[0].ok);
+        assert_eq!(breakage(&result), None);
+        assert_eq!(details(&result), None);
+    }
+}
@@ -0,0 +1,129 @@
+//! Last notification sent per resource (`GET /notifications/last`)
+//!
+//! Every alert or notice about a resource that goes out to a webhook is kept in
+//! KV with its rendered payload and delivery outcome, replacing the previous one,
+//! so "did we alert on this?" can be answered from the API instead of the chat
+//! history. Synthetic test notifications are not kept.
+
+use crate::alert::AlertStream;
+use crate::console::console_error;
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+/// KV key prefix of the last notifications, relative to the scope prefix
+const LAST_PREFIX: &str = "notifications/last/";
+
+/// Entries of resources that stop getting notifications expire after 90 days
+const LAST_TTL_SECS: u64 = 90 * 24 * 60 * 60;
+
+/// A notification as it was sent to the webhook
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SentNotification {
+    pub url: String,
+    /// When it was sent (RFC 3339)
+    pub at: String,
+    /// Title of the notification, e.g. `🚨 Link Check Failed`
+    pub title: String,
+    /// Webhook format, e.g. `slack`
+    pub service: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<AlertStream>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub resolved: bool,
+    /// Whether the webhook accepted the notification
+    pub delivered: bool,
+    /// Why the delivery failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// ID of the run that sent it (see `trace`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Rendered webhook payload
+    pub payload: String,
+}
+
+impl SentNotification {
+    /// Set the delivery outcome from the result of sending the webhook
+    pub fn with_outcome(mut self, outcome: &Result<()>) -> Self {
+        self.delivered = outcome.is_ok();
+        self.error = outcome.as_ref().err().map(ToString::to_string);
+        self
+    }
+}
+
+fn key(scope: &Scope<'_>, url: &str) -> String {
+    scope.key(&format!("{}{}", LAST_PREFIX, url))
+}
+
+/// Keep a sent notification as the last one of its resource, logging failures
+pub async fn record(env: &Env, scope: &Scope<'_>, sent: &SentNotification) {
+    let Some(store) = Store::from_env(env) else {
+        return;
+    };
+    if let Err(e) = store
+        .put_with_ttl(&key(scope, &sent.url), sent, LAST_TTL_SECS)
+        .await
+    {
+        console_error!("Failed to store last notification: {}", e);
+    }
+}
+
+/// Handle `GET /notifications/last?url=...`
+pub async fn handle_last(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let store = Store::require(env)?;
+    let url = req.url()?;
+    let query: BTreeMap<String, String> = url.query_pairs().into_owned().collect();
+    let Some(url) = query.get("url") else {
+        return Response::error("Expected a 'url' query parameter", 400);
+    };
+    // Key the lookup by the configured spelling of the URL
+    let url = scope
+        .find_resource(url)
+        .map_or(url.as_str(), |r| r.url.as_str());
+    match store.get::<SentNotification>(&key(scope, url)).await? {
+        Some(sent) => Response::from_json(&sent),
+        None => Response::error(format!("No notification sent for {}", url), 404),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent() -> SentNotification {
+        SentNotification {
+            url: "https://example.com/a.js".to_string(),
+            at: "2025-11-12T10:00:00Z".to_string(),
+            title: "🚨 Link Check Failed".to_string(),
+            service: "slack".to_string(),
+            stream: Some(AlertStream::Availability),
+            resolved: false,
+            delivered: false,
+            error: None,
+            run_id: None,
+            payload: "{}".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_with_outcome() {
+        let ok = sent().with_outcome(&Ok(()));
+        assert!(ok.delivered);
+        assert_eq!(ok.error, None);
+
+        let failed = sent().with_outcome(&Err(Error::RustError(
+            "Webhook returned HTTP 404: no_service".to_string(),
+        )));
+        assert!(!failed.delivered);
+        assert_eq!(
+            failed.error.as_deref(),
+            Some("Webhook returned HTTP 404: no_service")
+        );
+        let json = serde_json::to_string(&failed).unwrap();
+        assert!(json.contains(r#""stream":"availability""#));
+        assert!(!json.contains("resolved"));
+    }
+}
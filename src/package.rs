@@ -0,0 +1,477 @@
+//! Pinned package versions checked against their registry's published checksums
+//!
+//! A resource with a `package` table is the index metadata URL of a released
+//! package version:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://pypi.org/pypi/requests/2.32.3/json"
+//! package = { index = "pypi", sha256 = "...", file = "requests-2.32.3-py3-none-any.whl" }
+//!
+//! [[resources]]
+//! url = "https://index.crates.io/se/rd/serde"
+//! package = { index = "crates", version = "1.0.210", sha256 = "..." }
+//! ```
+//!
+//! Each check reads the checksum the index publishes for the pinned file (PyPI JSON
+//! API `digests.sha256`, crates.io sparse index `cksum`), compares it with the
+//! pinned `sha256`, then downloads the artifact and hashes it. Both a registry
+//! whose published checksum no longer matches the pin and an artifact that no
+//! longer matches its checksum are integrity failures. Without `file`, a PyPI
+//! release passes when any of its files is published with the pinned hash.
+//!
+//! Crates are downloaded from `download`, a sparse registry `dl` template
+//! (`{crate}`, `{version}`, `{sha256-checksum}` markers, or a base URL followed by
+//! `/{crate}/{version}/download`), defaulting to crates.io's static host. Downloaded
+//! artifacts are not kept for the mirror.
+
+use crate::checker::{CheckError, CheckResult};
+use crate::clock;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use crate::sri;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use worker::*;
+
+/// Download location of crates.io, in the sparse registry `dl` format
+const CRATES_IO_DOWNLOAD: &str = "https://static.crates.io/crates/{crate}/{crate}-{version}.crate";
+
+/// Package index a probe reads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Index {
+    /// PyPI JSON API (`/pypi/<name>/<version>/json`)
+    #[default]
+    Pypi,
+    /// Cargo sparse registry index file of a crate
+    Crates,
+}
+
+/// Settings of a package probe (`package = { ... }` on a resource)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Package {
+    pub index: Index,
+    /// Pinned SHA-256 of the artifact, in hex
+    pub sha256: String,
+    /// Version of a crate; PyPI versions are part of the URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// File name of a PyPI release file, e.g. the wheel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Download URL template of a crate registry (default crates.io)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download: Option<String>,
+}
+
+impl Package {
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        let Some(path) = Url::parse(url)
+            .ok()
+            .filter(|u| u.host_str().is_some())
+            .map(|u| u.path().to_string())
+        else {
+            return Err(format!("Package index URL {} is not absolute", url));
+        };
+        if self.sha256.len() != 64 || !self.sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("package.sha256 of {} must be 64 hex digits", url));
+        }
+        match self.index {
+            Index::Pypi => {
+                if !(path.starts_with("/pypi/") && path.trim_end_matches('/').ends_with("/json")) {
+                    return Err(format!(
+                        "{} is not a PyPI release URL (https://pypi.org/pypi/<name>/<version>/json)",
+                        url
+                    ));
+                }
+                if self.version.is_some() || self.download.is_some() {
+                    return Err(format!(
+                        "package.version and package.download are for crates ({})",
+                        url
+                    ));
+                }
+            }
+            Index::Crates => {
+                if self.version.as_deref().is_none_or(str::is_empty) {
+                    return Err(format!("Crate {} needs package.version", url));
+                }
+                if self.file.is_some() {
+                    return Err(format!("package.file is for PyPI releases ({})", url));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A downloadable file and the checksum its index publishes
+#[derive(Debug, PartialEq, Eq)]
+struct Published {
+    download_url: String,
+    sha256: String,
+}
+
+#[derive(Deserialize)]
+struct PypiRelease {
+    urls: Vec<PypiFile>,
+}
+
+#[derive(Deserialize)]
+struct PypiFile {
+    filename: String,
+    url: String,
+    digests: PypiDigests,
+}
+
+#[derive(Deserialize)]
+struct PypiDigests {
+    sha256: String,
+}
+
+/// The release file of a PyPI JSON API response to verify
+///
+/// Without a file name, the file published with the pinned hash is picked, or the
+/// first file when none is.
+fn pypi_file(body: &str, package: &Package) -> std::result::Result<Published, String> {
+    let release: PypiRelease =
+        serde_json::from_str(body).map_err(|e| format!("invalid release metadata: {}", e))?;
+    let file = match &package.file {
+        Some(name) => release.urls.into_iter().find(|f| &f.filename == name),
+        None => {
+            let pinned = release
+                .urls
+                .iter()
+                .position(|f| f.digests.sha256.eq_ignore_ascii_case(&package.sha256));
+            release.urls.into_iter().nth(pinned.unwrap_or(0))
+        }
+    };
+    let file = file.ok_or_else(|| match &package.file {
+        Some(name) => format!("release has no file {}", name),
+        None => "release has no files".to_string(),
+    })?;
+    Ok(Published {
+        download_url: file.url,
+        sha256: file.digests.sha256.to_ascii_lowercase(),
+    })
+}
+
+#[derive(Deserialize)]
+struct CrateVersion {
+    vers: String,
+    cksum: String,
+}
+
+/// Download URL of a crate version from a `dl` template
+fn crate_download_url(template: &str, name: &str, version: &str, sha256: &str) -> String {
+    const MARKERS: &[&str] = &[
+        "{crate}",
+        "{version}",
+        "{sha256-checksum}",
+        "{prefix}",
+        "{lowerprefix}",
+    ];
+    if !MARKERS.iter().any(|m| template.contains(m)) {
+        return format!(
+            "{}/{}/{}/download",
+            template.trim_end_matches('/'),
+            name,
+            version
+        );
+    }
+    let prefix = match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    };
+    template
+        .replace("{crate}", name)
+        .replace("{version}", version)
+        .replace("{sha256-checksum}", sha256)
+        .replace("{lowerprefix}", &prefix.to_ascii_lowercase())
+        .replace("{prefix}", &prefix)
+}
+
+/// The version entry of a sparse index file (one JSON object per line)
+fn crate_version(
+    body: &str,
+    url: &str,
+    package: &Package,
+) -> std::result::Result<Published, String> {
+    let version = package.version.as_deref().unwrap_or_default();
+    let entry = body
+        .lines()
+        .filter_map(|line| serde_json::from_str::<CrateVersion>(line).ok())
+        .find(|entry| entry.vers == version)
+        .ok_or_else(|| format!("index has no version {}", version))?;
+    let name = url
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let template = package.download.as_deref().unwrap_or(CRATES_IO_DOWNLOAD);
+    Ok(Published {
+        download_url: crate_download_url(template, name, version, &entry.cksum),
+        sha256: entry.cksum.to_ascii_lowercase(),
+    })
+}
+
+/// Check a pinned package version against its index and its downloadable artifact
+pub async fn check(fetcher: &impl Fetcher, resource: &Resource, package: &Package) -> CheckResult {
+    let started = clock::now_ms();
+    let mut result = run_check(fetcher, resource, package).await;
+    if result.error != Some(CheckError::FetchFailed) {
+        result.latency_ms = Some(clock::now_ms().saturating_sub(started));
+    }
+    result
+}
+
+async fn run_check(fetcher: &impl Fetcher, resource: &Resource, package: &Package) -> CheckResult {
+    let url = resource.url.as_str();
+    console_log!("Checking package: {}", url);
+    let mut response = match fetcher.fetch(HttpRequest::new(Method::Get, url)).await {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    let status_code = response.status_code();
+    if !(200..300).contains(&status_code) {
+        return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+    }
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_body(&e)),
+    };
+    let published = match package.index {
+        Index::Pypi => pypi_file(&body, package),
+        Index::Crates => crate_version(&body, url, package),
+    };
+    let published = match published {
+        Ok(p) => p,
+        Err(e) => {
+            console_error!("✗ {} - {}", url, e);
+            return CheckResult::failure(url.to_string(), CheckError::UnexpectedResponse);
+        }
+    };
+    let pinned = package.sha256.to_ascii_lowercase();
+    if published.sha256 != pinned {
+        console_error!(
+            "✗ {} - registry publishes sha256 {}, pinned {}",
+            url,
+            published.sha256,
+            pinned
+        );
+        return CheckResult::success(url.to_string(), status_code, false);
+    }
+
+    let request = HttpRequest::new(Method::Get, published.download_url.as_str());
+    let mut response = match fetcher.fetch(request).await {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    let status_code = response.status_code();
+    if !(200..300).contains(&status_code) {
+        console_error!(
+            "✗ {} - download HTTP {}",
+            published.download_url,
+            status_code
+        );
+        return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+    }
+    let content = match response.bytes().await {
+        Ok(content) => content,
+        Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_body(&e)),
+    };
+    let valid = sri::encode_hex(&Sha256::digest(&content)) == pinned;
+    if !valid {
+        console_error!(
+            "✗ {} - {} does not match its published sha256",
+            url,
+            published.download_url
+        );
+    }
+    CheckResult::success(url.to_string(), status_code, valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    const PYPI: &str = "https://pypi.org/pypi/widget/1.0.0/json";
+    const WHEEL: &str =
+        "https://files.pythonhosted.org/packages/ab/cd/widget-1.0.0-py3-none-any.whl";
+    const SDIST: &str = "https://files.pythonhosted.org/packages/ef/01/widget-1.0.0.tar.gz";
+    const CRATE_INDEX: &str = "https://index.crates.io/wi/dg/widget";
+    const CRATE: &str = "https://static.crates.io/crates/widget/widget-1.0.0.crate";
+
+    fn sha256(content: &[u8]) -> String {
+        sri::encode_hex(&Sha256::digest(content))
+    }
+
+    fn pypi_metadata() -> String {
+        serde_json::json!({
+            "info": { "name": "widget", "version": "1.0.0" },
+            "urls": [
+                { "filename": "widget-1.0.0.tar.gz", "url": SDIST, "digests": { "sha256": sha256(b"sdist") } },
+                { "filename": "widget-1.0.0-py3-none-any.whl", "url": WHEEL, "digests": { "sha256": sha256(b"wheel") } },
+            ]
+        })
+        .to_string()
+    }
+
+    fn crate_index() -> String {
+        [
+            format!(r#"{{"name":"widget","vers":"0.9.0","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#, sha256(b"old")),
+            format!(r#"{{"name":"widget","vers":"1.0.0","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#, sha256(b"crate")),
+        ]
+        .join("\n")
+    }
+
+    fn run(fetcher: &MockFetcher, url: &str, package: Package) -> CheckResult {
+        let resource = Resource {
+            url: url.to_string(),
+            package: Some(package.clone()),
+            ..Default::default()
+        };
+        block_on(check(fetcher, &resource, &package))
+    }
+
+    fn pypi(sha256: &str, file: Option<&str>) -> Package {
+        Package {
+            index: Index::Pypi,
+            sha256: sha256.to_string(),
+            file: file.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn krate(sha256: &str) -> Package {
+        Package {
+            index: Index::Crates,
+            sha256: sha256.to_string(),
+            version: Some("1.0.0".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pypi() {
+        let fetcher = MockFetcher::new()
+            .respond(PYPI, MockResponse::new(200, pypi_metadata()))
+            .respond(WHEEL, MockResponse::new(200, "wheel"))
+            .respond(SDIST, MockResponse::new(200, "tampered"));
+
+        let wheel = pypi(&sha256(b"wheel"), Some("widget-1.0.0-py3-none-any.whl"));
+        let result = run(&fetcher, PYPI, wheel);
+        assert_eq!(result.sri_valid, Some(true));
+        assert_eq!(fetcher.requests()[1].url, WHEEL);
+
+        // Picked by hash without a file name
+        assert_eq!(
+            run(&fetcher, PYPI, pypi(&sha256(b"wheel"), None)).sri_valid,
+            Some(true)
+        );
+
+        // The hosted file no longer matches the published digest
+        let result = run(&fetcher, PYPI, pypi(&sha256(b"sdist"), None));
+        assert_eq!(result.sri_valid, Some(false));
+
+        // The registry publishes another hash than the pinned one
+        let result = run(
+            &fetcher,
+            PYPI,
+            pypi(&sha256(b"other"), Some("widget-1.0.0.tar.gz")),
+        );
+        assert_eq!(result.sri_valid, Some(false));
+        // Not downloaded
+        assert_eq!(fetcher.requests().len(), 7);
+
+        let result = run(&fetcher, PYPI, pypi(&sha256(b"other"), None));
+        assert_eq!(result.sri_valid, Some(false));
+        let result = run(&fetcher, PYPI, pypi(&sha256(b"wheel"), Some("missing.whl")));
+        assert_eq!(result.error, Some(CheckError::UnexpectedResponse));
+    }
+
+    #[test]
+    fn test_crates() {
+        let fetcher = MockFetcher::new()
+            .respond(CRATE_INDEX, MockResponse::new(200, crate_index()))
+            .respond(CRATE, MockResponse::new(200, "crate"));
+        let result = run(&fetcher, CRATE_INDEX, krate(&sha256(b"crate")));
+        assert_eq!(result.sri_valid, Some(true));
+        assert_eq!(fetcher.requests()[1].url, CRATE);
+
+        assert_eq!(
+            run(&fetcher, CRATE_INDEX, krate(&sha256(b"old"))).sri_valid,
+            Some(false)
+        );
+        let unknown = Package {
+            version: Some("2.0.0".to_string()),
+            ..krate(&sha256(b"crate"))
+        };
+        assert_eq!(
+            run(&fetcher, CRATE_INDEX, unknown).error,
+            Some(CheckError::UnexpectedResponse)
+        );
+
+        let fetcher = MockFetcher::new()
+            .respond(CRATE_INDEX, MockResponse::new(200, crate_index()))
+            .respond(CRATE, MockResponse::new(404, ""));
+        assert_eq!(
+            run(&fetcher, CRATE_INDEX, krate(&sha256(b"crate"))).error,
+            Some(CheckError::HttpError(404))
+        );
+    }
+
+    #[test]
+    fn test_crate_download_url() {
+        assert_eq!(
+            crate_download_url(CRATES_IO_DOWNLOAD, "widget", "1.0.0", "ab"),
+            CRATE
+        );
+        assert_eq!(
+            crate_download_url(
+                "https://crates.example.com/api/v1/crates",
+                "widget",
+                "1.0.0",
+                "ab"
+            ),
+            "https://crates.example.com/api/v1/crates/widget/1.0.0/download"
+        );
+        assert_eq!(
+            crate_download_url(
+                "https://dl.example.com/{prefix}/{crate}/{sha256-checksum}",
+                "Abc",
+                "1.0.0",
+                "ab"
+            ),
+            "https://dl.example.com/3/A/Abc/ab"
+        );
+        assert_eq!(
+            crate_download_url(
+                "https://dl.example.com/{lowerprefix}/{crate}",
+                "Widget",
+                "1.0.0",
+                "ab"
+            ),
+            "https://dl.example.com/wi/dg/Widget"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let hash = sha256(b"wheel");
+        assert!(pypi(&hash, None).validate(PYPI).is_ok());
+        assert!(pypi(&hash, None).validate(CRATE_INDEX).is_err());
+        assert!(pypi("abc", None).validate(PYPI).is_err());
+        assert!(krate(&hash).validate(CRATE_INDEX).is_ok());
+        let no_version = Package {
+            version: None,
+            ..krate(&hash)
+        };
+        assert!(no_version.validate(CRATE_INDEX).is_err());
+    }
+}
@@ -0,0 +1,589 @@
+//! Published OpenPGP keys: fingerprint pinning and expiry warnings
+//!
+//! A resource with a `pgp` table is a URL serving a public key, ASCII-armored or
+//! binary, e.g. a release-signing key at a well-known location:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://kalapaja.fi/.well-known/release-key.asc"
+//! pgp = { fingerprint = "0123 4567 89AB CDEF ...", warn_days = 30 }
+//! ```
+//!
+//! The key is parsed just enough to find the primary key with the pinned
+//! fingerprint (v4, v5 and v6 keys) and its expiry from the newest self-signature.
+//! A file without that key is an integrity failure (the published key was
+//! replaced); a key expiring within `warn_days`, or already expired, fails with
+//! `KeyExpiring`; a missing file fails like any unavailable resource. Subkey expiry
+//! and revocations are not evaluated.
+
+use crate::checker::{CheckError, CheckResult};
+use crate::clock;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use crate::sri;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use worker::*;
+
+/// Days before expiry a key is reported by default
+const DEFAULT_WARN_DAYS: u32 = 30;
+
+const ARMOR_BEGIN: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----";
+
+/// Packet tags this module reads
+const TAG_SIGNATURE: u8 = 2;
+const TAG_PUBLIC_KEY: u8 = 6;
+const TAG_PUBLIC_SUBKEY: u8 = 14;
+
+/// Signature subpacket types this module reads
+const SUBPACKET_CREATED: u8 = 2;
+const SUBPACKET_KEY_EXPIRATION: u8 = 9;
+const SUBPACKET_ISSUER: u8 = 16;
+const SUBPACKET_ISSUER_FINGERPRINT: u8 = 33;
+
+/// Settings of a published key probe (`pgp = { ... }` on a resource)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PgpKey {
+    /// Pinned fingerprint of the primary key, in hex (spaces allowed)
+    pub fingerprint: String,
+    /// Report the key this many days before it expires
+    #[serde(default = "default_warn_days")]
+    pub warn_days: u32,
+}
+
+fn default_warn_days() -> u32 {
+    DEFAULT_WARN_DAYS
+}
+
+impl PgpKey {
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        let fingerprint = self.normalized_fingerprint();
+        if !matches!(fingerprint.len(), 40 | 64)
+            || !fingerprint.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Err(format!(
+                "pgp.fingerprint of {} must be 40 (v4) or 64 (v5/v6) hex digits",
+                url
+            ));
+        }
+        if self.warn_days == 0 {
+            return Err(format!("pgp.warn_days of {} must be at least 1", url));
+        }
+        Ok(())
+    }
+
+    /// Fingerprint without spaces, uppercase
+    fn normalized_fingerprint(&self) -> String {
+        self.fingerprint
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_uppercase()
+    }
+}
+
+/// A primary key found in a key file
+#[derive(Debug, PartialEq, Eq)]
+struct PublicKey {
+    /// Uppercase hex fingerprint
+    fingerprint: String,
+    /// Creation time, seconds since the epoch
+    created: u64,
+    /// Expiry time, seconds since the epoch, if the key expires
+    expires: Option<u64>,
+}
+
+impl PublicKey {
+    /// Key ID, as found in issuer subpackets
+    fn key_id(&self) -> &str {
+        match self.fingerprint.len() {
+            40 => &self.fingerprint[24..],
+            _ => &self.fingerprint[..16],
+        }
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+/// Binary packets of an ASCII-armored key block (the CRC line is not checked)
+fn dearmor(text: &str) -> Option<Vec<u8>> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .skip_while(|l| *l != ARMOR_BEGIN);
+    lines.next()?;
+    let base64: String = lines
+        .skip_while(|l| l.contains(": "))
+        .take_while(|l| !l.starts_with('=') && !l.starts_with("-----"))
+        .collect();
+    BASE64.decode(base64).ok()
+}
+
+/// Split binary data into `(tag, body)` packets, stopping at anything unreadable
+fn packets(mut data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut packets = Vec::new();
+    while let Some(&header) = data.first() {
+        if header & 0x80 == 0 {
+            break;
+        }
+        let (tag, len, offset) = if header & 0x40 != 0 {
+            // New format
+            let tag = header & 0x3f;
+            match data.get(1).copied() {
+                Some(o @ 0..=191) => (tag, o as usize, 2),
+                Some(o @ 192..=223) => match data.get(2) {
+                    Some(&o2) => (tag, ((o as usize - 192) << 8) + o2 as usize + 192, 3),
+                    None => break,
+                },
+                Some(255) => match data.get(2..).and_then(be_u32) {
+                    Some(len) => (tag, len as usize, 6),
+                    None => break,
+                },
+                // Partial body lengths are not used for keys and signatures
+                _ => break,
+            }
+        } else {
+            // Old format
+            let tag = (header >> 2) & 0x0f;
+            match header & 0x03 {
+                0 => match data.get(1) {
+                    Some(&len) => (tag, len as usize, 2),
+                    None => break,
+                },
+                1 => match data.get(1..3) {
+                    Some(len) => (tag, u16::from_be_bytes([len[0], len[1]]) as usize, 3),
+                    None => break,
+                },
+                2 => match data.get(1..).and_then(be_u32) {
+                    Some(len) => (tag, len as usize, 5),
+                    None => break,
+                },
+                _ => (tag, data.len() - 1, 1),
+            }
+        };
+        let Some(body) = data.get(offset..offset + len) else {
+            break;
+        };
+        packets.push((tag, body));
+        data = &data[offset + len..];
+    }
+    packets
+}
+
+/// Fingerprint and creation time of a key packet body
+fn key_fingerprint(body: &[u8]) -> Option<(String, u64)> {
+    let created = be_u32(body.get(1..)?)? as u64;
+    let fingerprint = match body.first()? {
+        4 => {
+            let mut hasher = Sha1::new();
+            hasher.update([0x99]);
+            hasher.update((body.len() as u16).to_be_bytes());
+            hasher.update(body);
+            sri::encode_hex(&hasher.finalize()).to_ascii_uppercase()
+        }
+        version @ (5 | 6) => {
+            let mut hasher = Sha256::new();
+            hasher.update([if *version == 5 { 0x9a } else { 0x9b }]);
+            hasher.update((body.len() as u32).to_be_bytes());
+            hasher.update(body);
+            sri::encode_hex(&hasher.finalize()).to_ascii_uppercase()
+        }
+        _ => return None,
+    };
+    Some((fingerprint, created))
+}
+
+/// Subpacket values of a signature that matter for key expiry
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SelfSignature {
+    sig_type: u8,
+    created: Option<u64>,
+    /// Seconds after key creation
+    key_expiration: Option<u64>,
+    /// Issuer key ID or fingerprint, uppercase hex
+    issuer: Option<String>,
+}
+
+fn read_subpackets(mut data: &[u8], signature: &mut SelfSignature) {
+    while let Some(&first) = data.first() {
+        let (len, offset) = match first {
+            0..=191 => (first as usize, 1),
+            192..=254 => match data.get(1) {
+                Some(&second) => (((first as usize - 192) << 8) + second as usize + 192, 2),
+                None => return,
+            },
+            255 => match data.get(1..).and_then(be_u32) {
+                Some(len) => (len as usize, 5),
+                None => return,
+            },
+        };
+        let Some(subpacket) = data.get(offset..offset + len) else {
+            return;
+        };
+        data = &data[offset + len..];
+        let Some((&kind, value)) = subpacket.split_first() else {
+            continue;
+        };
+        match kind & 0x7f {
+            SUBPACKET_CREATED => signature.created = be_u32(value).map(u64::from),
+            SUBPACKET_KEY_EXPIRATION => signature.key_expiration = be_u32(value).map(u64::from),
+            SUBPACKET_ISSUER if value.len() == 8 => {
+                signature
+                    .issuer
+                    .get_or_insert_with(|| sri::encode_hex(value).to_ascii_uppercase());
+            }
+            SUBPACKET_ISSUER_FINGERPRINT if value.len() > 1 => {
+                signature.issuer = Some(sri::encode_hex(&value[1..]).to_ascii_uppercase());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Subpackets of a v4 or v6 signature packet body
+fn parse_signature(body: &[u8]) -> Option<SelfSignature> {
+    let mut signature = SelfSignature {
+        sig_type: *body.get(1)?,
+        ..Default::default()
+    };
+    // Hashed and unhashed subpacket areas, with 2 (v4) or 4 (v6) byte lengths
+    let size = match body.first()? {
+        4 => 2,
+        6 => 4,
+        _ => return None,
+    };
+    let read_len = |at: usize| -> Option<usize> {
+        let bytes = body.get(at..at + size)?;
+        Some(bytes.iter().fold(0usize, |len, b| len << 8 | *b as usize))
+    };
+    let hashed_len = read_len(4)?;
+    let hashed = body.get(4 + size..4 + size + hashed_len)?;
+    let unhashed_at = 4 + size + hashed_len;
+    let unhashed_len = read_len(unhashed_at)?;
+    let unhashed = body.get(unhashed_at + size..unhashed_at + size + unhashed_len)?;
+    // Issuer subpackets may be unhashed; creation and expiry times must be hashed
+    read_subpackets(unhashed, &mut signature);
+    signature.created = None;
+    signature.key_expiration = None;
+    read_subpackets(hashed, &mut signature);
+    Some(signature)
+}
+
+/// Primary keys of a key file, with their expiry from the newest self-signature
+fn parse_keys(data: &[u8]) -> Vec<PublicKey> {
+    let mut keys: Vec<PublicKey> = Vec::new();
+    // Creation time of the self-signature the current key's expiry comes from
+    let mut newest: Option<u64> = None;
+    let mut in_primary = false;
+    for (tag, body) in packets(data) {
+        match tag {
+            TAG_PUBLIC_KEY => {
+                in_primary = false;
+                if let Some((fingerprint, created)) = key_fingerprint(body) {
+                    keys.push(PublicKey {
+                        fingerprint,
+                        created,
+                        expires: None,
+                    });
+                    newest = None;
+                    in_primary = true;
+                }
+            }
+            TAG_PUBLIC_SUBKEY => in_primary = false,
+            TAG_SIGNATURE if in_primary => {
+                let Some(key) = keys.last_mut() else {
+                    continue;
+                };
+                let Some(signature) = parse_signature(body) else {
+                    continue;
+                };
+                // User ID certifications and direct-key signatures by the key itself
+                let is_self = signature
+                    .issuer
+                    .as_deref()
+                    .is_none_or(|issuer| issuer == key.key_id() || issuer == key.fingerprint);
+                let relevant = matches!(signature.sig_type, 0x10..=0x13 | 0x1f);
+                let created = signature.created.unwrap_or(0);
+                if is_self && relevant && newest.is_none_or(|n| created >= n) {
+                    newest = Some(created);
+                    key.expires = signature
+                        .key_expiration
+                        .filter(|secs| *secs > 0)
+                        .map(|secs| key.created + secs);
+                }
+            }
+            _ => {}
+        }
+    }
+    keys
+}
+
+/// Whole days from `now` until `expires` (negative once expired)
+fn days_until(expires: u64, now: u64) -> i64 {
+    (expires as i64 - now as i64).div_euclid(86_400)
+}
+
+/// Check a published key: presence, pinned fingerprint and expiry
+pub async fn check(fetcher: &impl Fetcher, resource: &Resource, pgp: &PgpKey) -> CheckResult {
+    let started = clock::now_ms();
+    let mut result = run_check(fetcher, resource, pgp).await;
+    if result.error != Some(CheckError::FetchFailed) {
+        result.latency_ms = Some(clock::now_ms().saturating_sub(started));
+    }
+    result
+}
+
+async fn run_check(fetcher: &impl Fetcher, resource: &Resource, pgp: &PgpKey) -> CheckResult {
+    let url = resource.url.as_str();
+    console_log!("Checking PGP key: {}", url);
+    let mut response = match fetcher.fetch(HttpRequest::new(Method::Get, url)).await {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    let status_code = response.status_code();
+    if !(200..300).contains(&status_code) {
+        return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+    }
+    let content = match response.bytes().await {
+        Ok(content) => content,
+        Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_body(&e)),
+    };
+    let data = match std::str::from_utf8(&content) {
+        Ok(text) if text.contains(ARMOR_BEGIN) => dearmor(text).unwrap_or_default(),
+        _ => content,
+    };
+    let keys = parse_keys(&data);
+    if keys.is_empty() {
+        console_error!("✗ {} - no OpenPGP public key found", url);
+        return CheckResult::failure(url.to_string(), CheckError::UnexpectedResponse);
+    }
+
+    let pinned = pgp.normalized_fingerprint();
+    let Some(key) = keys.iter().find(|k| k.fingerprint == pinned) else {
+        let found: Vec<&str> = keys.iter().map(|k| k.fingerprint.as_str()).collect();
+        console_error!(
+            "✗ {} - key {} not published, found {}",
+            url,
+            pinned,
+            found.join(", ")
+        );
+        return CheckResult::success(url.to_string(), status_code, false);
+    };
+    if let Some(expires) = key.expires {
+        let days = days_until(expires, clock::now_ms() / 1000);
+        if days < pgp.warn_days as i64 {
+            console_error!("✗ {} - key {} expires in {} days", url, pinned, days);
+            let mut result = CheckResult::failure(url.to_string(), CheckError::KeyExpiring(days));
+            result.status_code = Some(status_code);
+            return result;
+        }
+    }
+    CheckResult::success(url.to_string(), status_code, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    const URL: &str = "https://kalapaja.fi/.well-known/release-key.asc";
+    /// 2025-11-12T10:00:00Z, the mocked clock
+    const NOW: u64 = 1_762_941_600;
+    const DAY: u64 = 86_400;
+
+    fn new_packet(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0xc0 | tag];
+        if body.len() < 192 {
+            packet.push(body.len() as u8);
+        } else {
+            packet.push(255);
+            packet.extend((body.len() as u32).to_be_bytes());
+        }
+        packet.extend(body);
+        packet
+    }
+
+    /// v4 Ed25519-like key body (the key material is not interpreted)
+    fn key_body(created: u64, material: u8) -> Vec<u8> {
+        let mut body = vec![4];
+        body.extend((created as u32).to_be_bytes());
+        body.push(22);
+        body.extend([material; 34]);
+        body
+    }
+
+    fn subpacket(kind: u8, value: &[u8]) -> Vec<u8> {
+        let mut subpacket = vec![value.len() as u8 + 1, kind];
+        subpacket.extend(value);
+        subpacket
+    }
+
+    fn signature(sig_type: u8, created: u64, expiration: Option<u64>, issuer: &[u8]) -> Vec<u8> {
+        let mut hashed = subpacket(SUBPACKET_CREATED, &(created as u32).to_be_bytes());
+        if let Some(secs) = expiration {
+            hashed.extend(subpacket(
+                SUBPACKET_KEY_EXPIRATION,
+                &(secs as u32).to_be_bytes(),
+            ));
+        }
+        let unhashed = subpacket(SUBPACKET_ISSUER, issuer);
+        let mut body = vec![4, sig_type, 22, 8];
+        body.extend((hashed.len() as u16).to_be_bytes());
+        body.extend(hashed);
+        body.extend((unhashed.len() as u16).to_be_bytes());
+        body.extend(unhashed);
+        body.extend([0; 4]);
+        body
+    }
+
+    fn key_id(body: &[u8]) -> Vec<u8> {
+        let (fingerprint, _) = key_fingerprint(body).unwrap();
+        (24..40)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&fingerprint[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// A key created 700 days ago whose newest self-signature extends it to `lifetime`
+    fn key_file(lifetime: u64) -> (Vec<u8>, String) {
+        let created = NOW - 700 * DAY;
+        let key = key_body(created, 7);
+        let id = key_id(&key);
+        let mut data = new_packet(TAG_PUBLIC_KEY, &key);
+        data.extend(new_packet(13, b"Release Signing <release@kalapaja.fi>"));
+        data.extend(new_packet(
+            TAG_SIGNATURE,
+            &signature(0x13, created, Some(365 * DAY), &id),
+        ));
+        data.extend(new_packet(
+            TAG_SIGNATURE,
+            &signature(0x13, NOW - DAY, Some(lifetime), &id),
+        ));
+        // Someone else's certification, and a subkey binding
+        data.extend(new_packet(
+            TAG_SIGNATURE,
+            &signature(0x10, NOW, None, &[1; 8]),
+        ));
+        data.extend(new_packet(TAG_PUBLIC_SUBKEY, &key_body(created, 8)));
+        data.extend(new_packet(
+            TAG_SIGNATURE,
+            &signature(0x18, NOW, Some(DAY), &id),
+        ));
+        let (fingerprint, _) = key_fingerprint(&key).unwrap();
+        (data, fingerprint)
+    }
+
+    fn armor(data: &[u8]) -> String {
+        format!(
+            "{}\nComment: test key\n\n{}\n=abcd\n-----END PGP PUBLIC KEY BLOCK-----\n",
+            ARMOR_BEGIN,
+            BASE64.encode(data)
+        )
+    }
+
+    fn run(body: impl Into<Vec<u8>>, fingerprint: &str) -> CheckResult {
+        let fetcher = MockFetcher::new().respond(URL, MockResponse::new(200, body));
+        let pgp = PgpKey {
+            fingerprint: fingerprint.to_string(),
+            warn_days: DEFAULT_WARN_DAYS,
+        };
+        let resource = Resource {
+            url: URL.to_string(),
+            pgp: Some(pgp.clone()),
+            ..Default::default()
+        };
+        block_on(check(&fetcher, &resource, &pgp))
+    }
+
+    #[test]
+    fn test_fingerprint_matches_rfc_example() {
+        // Sample v4 Ed25519Legacy key of RFC 9580, appendix A.3
+        let data = BASE64
+            .decode("xjMEU/NfCxYJKwYBBAHaRw8BAQdAPwmJlL3ZFu1AUxl5NOSofIBzOhKA1i+AEJkuQ+47JAY=")
+            .unwrap();
+        let keys = parse_keys(&data);
+        assert_eq!(
+            keys,
+            vec![PublicKey {
+                fingerprint: "C959BDBAFA32A2F89A153B678CFDE12197965A9A".to_string(),
+                created: 1_408_458_507,
+                expires: None,
+            }]
+        );
+        assert_eq!(keys[0].key_id(), "8CFDE12197965A9A");
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let (data, fingerprint) = key_file(800 * DAY);
+        let keys = parse_keys(&data);
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].fingerprint, fingerprint);
+        // The newest self-signature wins; the subkey binding is ignored
+        assert_eq!(keys[0].expires, Some(NOW + 100 * DAY));
+
+        assert_eq!(dearmor(&armor(&data)), Some(data.clone()));
+        assert!(parse_keys(b"not a key").is_empty());
+    }
+
+    #[test]
+    fn test_check() {
+        let (data, fingerprint) = key_file(800 * DAY);
+        let spaced = fingerprint
+            .as_bytes()
+            .chunks(4)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let result = run(armor(&data), &spaced.to_lowercase());
+        assert_eq!(result.sri_valid, Some(true));
+        // Binary keys work too
+        assert_eq!(run(data.clone(), &fingerprint).sri_valid, Some(true));
+
+        // Another key was published
+        let result = run(armor(&data), &"A".repeat(40));
+        assert_eq!(result.sri_valid, Some(false));
+
+        assert_eq!(
+            run("<html>moved</html>", &fingerprint).error,
+            Some(CheckError::UnexpectedResponse)
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        let (data, fingerprint) = key_file(710 * DAY);
+        let result = run(armor(&data), &fingerprint);
+        assert_eq!(result.error, Some(CheckError::KeyExpiring(10)));
+        assert_eq!(result.status_code, Some(200));
+        assert!(result.has_problem());
+
+        let (data, fingerprint) = key_file(690 * DAY);
+        assert_eq!(
+            run(armor(&data), &fingerprint).error,
+            Some(CheckError::KeyExpiring(-10))
+        );
+        // Without an expiration subpacket the key never expires
+        let (data, fingerprint) = key_file(0);
+        assert_eq!(run(armor(&data), &fingerprint).sri_valid, Some(true));
+    }
+
+    #[test]
+    fn test_validate() {
+        let pgp = |fingerprint: &str, warn_days| PgpKey {
+            fingerprint: fingerprint.to_string(),
+            warn_days,
+        };
+        assert!(pgp(&"AB12 ".repeat(10), 30).validate(URL).is_ok());
+        assert!(pgp(&"AB".repeat(32), 30).validate(URL).is_ok());
+        assert!(pgp("AB12", 30).validate(URL).is_err());
+        assert!(pgp(&"AB".repeat(20), 0).validate(URL).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Check result history in Postgres over Hyperdrive
+//!
+//! Teams that already run Postgres can keep results in their own database, next
+//! to their dashboards, by setting `STORAGE_BACKEND = "postgres"` and binding the
+//! database through Hyperdrive as `LINKKIVAHTI_POSTGRES`:
+//!
+//! ```toml
+//! [[hyperdrive]]
+//! binding = "LINKKIVAHTI_POSTGRES"
+//! id = "<hyperdrive config id>"
+//! ```
+//!
+//! Every result is a row of `linkkivahti_results` and is never trimmed, so the
+//! table can be queried over any time range; runs and alerts read the latest
+//! `history::MAX_ENTRIES` results per resource. The rest of the history document
+//! (latency histograms and archive bookkeeping) is a JSON row per scope in
+//! `linkkivahti_history`. Both tables are created on first use.
+
+use crate::console::console_error;
+use crate::history::{History, HistoryBackend, HistoryEntry, MAX_ENTRIES};
+use crate::tenant::Scope;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio_postgres::Client;
+use worker::postgres_tls::PassthroughTls;
+use worker::*;
+
+/// Name of the Hyperdrive binding in wrangler.toml
+pub const BINDING: &str = "LINKKIVAHTI_POSTGRES";
+
+/// Tables of the history, created if missing
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS linkkivahti_results (
+    tenant text NOT NULL DEFAULT '',
+    url text NOT NULL,
+    at timestamptz NOT NULL,
+    ok boolean NOT NULL,
+    status integer,
+    detail text NOT NULL,
+    latency_ms bigint,
+    sri_valid boolean,
+    run_id text,
+    headers jsonb NOT NULL DEFAULT '{}',
+    PRIMARY KEY (tenant, url, at)
+);
+CREATE TABLE IF NOT EXISTS linkkivahti_history (
+    tenant text PRIMARY KEY,
+    document jsonb NOT NULL
+);
+";
+
+/// Latest results of each resource of a scope, oldest first
+const SELECT_RESULTS: &str = "
+SELECT url, (extract(epoch FROM at) * 1000)::int8, ok, status, detail, latency_ms,
+       sri_valid, run_id, headers::text
+FROM (
+    SELECT *, row_number() OVER (PARTITION BY url ORDER BY at DESC) AS n
+    FROM linkkivahti_results WHERE tenant = $1
+) recent
+WHERE n <= $2
+ORDER BY url, at
+";
+
+const INSERT_RESULTS: &str = "
+INSERT INTO linkkivahti_results
+    (tenant, url, at, ok, status, detail, latency_ms, sri_valid, run_id, headers)
+SELECT $1, url, 'epoch'::timestamptz + at * interval '1 millisecond', ok, status, detail,
+       latency_ms, sri_valid, run_id, headers::jsonb
+FROM unnest($2::text[], $3::int8[], $4::bool[], $5::int4[], $6::text[], $7::int8[],
+            $8::bool[], $9::text[], $10::text[])
+    AS r(url, at, ok, status, detail, latency_ms, sri_valid, run_id, headers)
+ON CONFLICT DO NOTHING
+";
+
+const DELETE_RESULTS: &str = "DELETE FROM linkkivahti_results WHERE tenant = $1 AND url = ANY($2)";
+
+const SELECT_DOCUMENT: &str = "SELECT document::text FROM linkkivahti_history WHERE tenant = $1";
+
+const UPSERT_DOCUMENT: &str = "
+INSERT INTO linkkivahti_history (tenant, document) VALUES ($1, $2::text::jsonb)
+ON CONFLICT (tenant) DO UPDATE SET document = excluded.document
+";
+
+/// Whether the tables were created by this isolate
+static SCHEMA_READY: AtomicBool = AtomicBool::new(false);
+
+fn db_error(e: tokio_postgres::Error) -> Error {
+    Error::RustError(format!("Postgres: {}", e))
+}
+
+/// Value of the `tenant` column of a scope (empty for the default scope)
+fn tenant(scope: &Scope) -> String {
+    scope.id().unwrap_or_default().to_string()
+}
+
+/// History entries as columns for `INSERT_RESULTS`
+#[derive(Debug, Default, PartialEq)]
+struct Columns {
+    url: Vec<String>,
+    at: Vec<i64>,
+    ok: Vec<bool>,
+    status: Vec<Option<i32>>,
+    detail: Vec<String>,
+    latency_ms: Vec<Option<i64>>,
+    sri_valid: Vec<Option<bool>>,
+    run_id: Vec<Option<String>>,
+    headers: Vec<String>,
+}
+
+impl Columns {
+    fn new(entries: &[(String, HistoryEntry)]) -> Self {
+        let mut columns = Self::default();
+        for (url, entry) in entries {
+            columns.url.push(url.clone());
+            columns.at.push(entry.at as i64);
+            columns.ok.push(entry.ok);
+            columns.status.push(entry.status.map(i32::from));
+            columns.detail.push(entry.detail.clone());
+            columns
+                .latency_ms
+                .push(entry.latency_ms.map(|ms| ms as i64));
+            columns.sri_valid.push(entry.sri_valid);
+            columns.run_id.push(entry.run_id.clone());
+            columns
+                .headers
+                .push(serde_json::to_string(&entry.headers).unwrap_or_default());
+        }
+        columns
+    }
+}
+
+/// The history document without results, which are kept as rows
+fn document(history: &History) -> Result<String> {
+    let mut document = serde_json::to_value(history)?;
+    if let Some(fields) = document.as_object_mut() {
+        fields.remove("resources");
+    }
+    Ok(document.to_string())
+}
+
+/// History in a Postgres database reached through Hyperdrive
+pub struct PostgresHistory {
+    hyperdrive: Hyperdrive,
+}
+
+impl PostgresHistory {
+    /// Open the database, failing with a descriptive error if it is not bound
+    pub fn require(env: &Env) -> Result<Self> {
+        let hyperdrive = env.hyperdrive(BINDING).map_err(|_| {
+            Error::RustError(format!("{} Hyperdrive binding not configured", BINDING))
+        })?;
+        Ok(Self { hyperdrive })
+    }
+
+    async fn connect(&self) -> Result<Client> {
+        let config: tokio_postgres::Config = self
+            .hyperdrive
+            .connection_string()
+            .parse()
+            .map_err(db_error)?;
+        let socket = Socket::builder()
+            .secure_transport(SecureTransport::StartTls)
+            .connect(self.hyperdrive.host(), self.hyperdrive.port())?;
+        let (client, connection) = config
+            .connect_raw(socket, PassthroughTls)
+            .await
+            .map_err(db_error)?;
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = connection.await {
+                console_error!("Postgres connection failed: {}", e);
+            }
+        });
+        if !SCHEMA_READY.load(Ordering::Relaxed) {
+            client.batch_execute(SCHEMA).await.map_err(db_error)?;
+            SCHEMA_READY.store(true, Ordering::Relaxed);
+        }
+        Ok(client)
+    }
+}
+
+impl HistoryBackend for PostgresHistory {
+    async fn load(&self, scope: &Scope<'_>) -> Result<History> {
+        let client = self.connect().await?;
+        let tenant = tenant(scope);
+        let mut history: History = match client
+            .query_opt(SELECT_DOCUMENT, &[&tenant])
+            .await
+            .map_err(db_error)?
+        {
+            Some(row) => serde_json::from_str(row.get::<_, &str>(0))?,
+            None => History::default(),
+        };
+        let rows = client
+            .query(SELECT_RESULTS, &[&tenant, &(MAX_ENTRIES as i64)])
+            .await
+            .map_err(db_error)?;
+        for row in rows {
+            let entry = HistoryEntry {
+                at: row.get::<_, i64>(1) as u64,
+                ok: row.get(2),
+                status: row.get::<_, Option<i32>>(3).map(|s| s as u16),
+                detail: row.get(4),
+                latency_ms: row.get::<_, Option<i64>>(5).map(|ms| ms as u64),
+                sri_valid: row.get(6),
+                run_id: row.get(7),
+                headers: serde_json::from_str(row.get::<_, &str>(8)).unwrap_or_default(),
+            };
+            history.resources.entry(row.get(0)).or_default().push(entry);
+        }
+        Ok(history)
+    }
+
+    async fn save(
+        &self,
+        scope: &Scope<'_>,
+        history: &History,
+        added: &[(String, HistoryEntry)],
+        purged: &[String],
+    ) -> Result<()> {
+        let mut client = self.connect().await?;
+        let tenant = tenant(scope);
+        let document = document(history)?;
+        let columns = Columns::new(added);
+        let transaction = client.transaction().await.map_err(db_error)?;
+        if !added.is_empty() {
+            transaction
+                .execute(
+                    INSERT_RESULTS,
+                    &[
+                        &tenant,
+                        &columns.url,
+                        &columns.at,
+                        &columns.ok,
+                        &columns.status,
+                        &columns.detail,
+                        &columns.latency_ms,
+                        &columns.sri_valid,
+                        &columns.run_id,
+                        &columns.headers,
+                    ],
+                )
+                .await
+                .map_err(db_error)?;
+        }
+        if !purged.is_empty() {
+            transaction
+                .execute(DELETE_RESULTS, &[&tenant, &purged])
+                .await
+                .map_err(db_error)?;
+        }
+        transaction
+            .execute(UPSERT_DOCUMENT, &[&tenant, &document])
+            .await
+            .map_err(db_error)?;
+        transaction.commit().await.map_err(db_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::{CheckError, CheckResult};
+
+    #[test]
+    fn test_columns() {
+        let mut history = History::default();
+        let mut ok = CheckResult::success("https://example.com/a.js", 200, true);
+        ok.headers.insert("etag".to_string(), "\"abc\"".to_string());
+        let added = history.record(
+            &[
+                ok,
+                CheckResult::failure("https://example.com/b.js", CheckError::FetchFailed),
+            ],
+            1000,
+        );
+        let columns = Columns::new(&added);
+        assert_eq!(
+            columns.url,
+            ["https://example.com/a.js", "https://example.com/b.js"]
+        );
+        assert_eq!(columns.at, [1000, 1000]);
+        assert_eq!(columns.ok, [true, false]);
+        assert_eq!(columns.status, [Some(200), None]);
+        assert_eq!(columns.sri_valid, [Some(true), None]);
+        assert_eq!(columns.headers, [r#"{"etag":"\"abc\""}"#, "{}"]);
+    }
+
+    #[test]
+    fn test_document() {
+        let mut history = History::default();
+        history.record(
+            &[CheckResult::success("https://example.com/a.js", 200, true)],
+            1000,
+        );
+        history
+            .archived
+            .insert("https://example.com/gone.js".to_string(), 500);
+        let document = document(&history).unwrap();
+        assert!(!document.contains("resources"));
+
+        let loaded: History = serde_json::from_str(&document).unwrap();
+        assert!(loaded.resources.is_empty());
+        assert_eq!(loaded.archived, history.archived);
+    }
+}
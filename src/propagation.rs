@@ -0,0 +1,254 @@
+//! Per-POP consistency of a resource after a release
+//!
+//! `GET /propagation?url=...` estimates how far a new version of a resource has
+//! spread through the edge caches. One fetch with a cache-busting query parameter
+//! shows what the origin serves; then the plain URL is sampled several times and
+//! each response is compared against the expected SRI hash, noting its
+//! `cf-cache-status` and the POP from `cf-ray`. The result is a propagation
+//! percentage rather than a pass or fail, so a release that is still rolling out
+//! through the caches can be told apart from a broken one.
+
+use crate::approval;
+use crate::console::console_log;
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse, WorkerFetcher};
+use crate::sri::SriHash;
+use crate::tenant::Scope;
+use crate::trace;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use worker::*;
+
+/// Query parameter that makes a request miss every cache
+const CACHE_BUSTER: &str = "linkkivahti-bust";
+
+/// Samples of the plain URL unless `samples` is given
+const DEFAULT_SAMPLES: usize = 10;
+
+/// Upper bound of `samples`, keeping a request well within the subrequest limit
+const MAX_SAMPLES: usize = 40;
+
+/// Fresh and stale samples served by one POP
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct PopCount {
+    pub fresh: usize,
+    pub stale: usize,
+}
+
+/// Propagation of a resource's expected content through the caches
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub url: String,
+    pub expected_sri: String,
+    /// Whether the origin serves the expected content; `None` if it could not be fetched
+    pub origin_fresh: Option<bool>,
+    pub samples: usize,
+    pub fresh: usize,
+    pub stale: usize,
+    /// Samples that failed or answered with a non-2xx status
+    pub failed: usize,
+    /// Share of answered samples serving the expected content
+    pub propagation_percent: f64,
+    /// Samples per `cf-cache-status` value
+    pub cache_status: BTreeMap<String, usize>,
+    /// Samples per POP, from the `cf-ray` suffix
+    pub pops: BTreeMap<String, PopCount>,
+}
+
+/// Fetch a URL and tell whether its content matches the expected hash
+///
+/// Returns `None` for failed fetches and non-2xx responses.
+async fn fetch_fresh<F: Fetcher>(
+    fetcher: &F,
+    url: &str,
+    expected: &SriHash,
+) -> Option<(bool, F::Response)> {
+    let mut response = fetcher
+        .fetch(HttpRequest::new(Method::Get, url))
+        .await
+        .ok()?;
+    if !(200..300).contains(&response.status_code()) {
+        return None;
+    }
+    let content = response.bytes().await.ok()?;
+    Some((expected.verify(&content), response))
+}
+
+/// URL that bypasses the caches with a unique query parameter
+fn bust(url: &str, nonce: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) => {
+            parsed.query_pairs_mut().append_pair(CACHE_BUSTER, nonce);
+            parsed.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
+
+/// POP code of a `cf-ray` header such as `8a1b2c3d4e5f6789-HEL`
+fn pop(ray: Option<&str>) -> String {
+    ray.and_then(|ray| ray.rsplit_once('-'))
+        .map_or_else(|| "unknown".to_string(), |(_, pop)| pop.to_string())
+}
+
+/// Measure the propagation of the content matching `expected_sri` at `url`
+pub async fn measure(
+    fetcher: &impl Fetcher,
+    url: &str,
+    expected_sri: &str,
+    expected: &SriHash,
+    samples: usize,
+    nonce: &str,
+) -> Report {
+    let origin_fresh = fetch_fresh(fetcher, &bust(url, nonce), expected)
+        .await
+        .map(|(fresh, _)| fresh);
+
+    let mut report = Report {
+        url: url.to_string(),
+        expected_sri: expected_sri.to_string(),
+        origin_fresh,
+        samples,
+        fresh: 0,
+        stale: 0,
+        failed: 0,
+        propagation_percent: 0.0,
+        cache_status: BTreeMap::new(),
+        pops: BTreeMap::new(),
+    };
+    for _ in 0..samples {
+        let Some((fresh, response)) = fetch_fresh(fetcher, url, expected).await else {
+            report.failed += 1;
+            continue;
+        };
+        let status = response
+            .header("cf-cache-status")
+            .unwrap_or_else(|| "NONE".to_string());
+        *report.cache_status.entry(status).or_default() += 1;
+        let count = report
+            .pops
+            .entry(pop(response.header("cf-ray").as_deref()))
+            .or_default();
+        if fresh {
+            report.fresh += 1;
+            count.fresh += 1;
+        } else {
+            report.stale += 1;
+            count.stale += 1;
+        }
+    }
+    let answered = report.fresh + report.stale;
+    if answered > 0 {
+        report.propagation_percent =
+            (report.fresh as f64 * 1000.0 / answered as f64).round() / 10.0;
+    }
+    report
+}
+
+/// Handle `GET /propagation?url=...&samples=N`
+pub async fn handle_propagation(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let url = req.url()?;
+    let query: BTreeMap<String, String> = url.query_pairs().into_owned().collect();
+    let Some(resource) = query.get("url").and_then(|u| scope.find_resource(u)) else {
+        return Response::error("Expected the 'url' of a configured resource", 400);
+    };
+    let samples = query
+        .get("samples")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_SAMPLES)
+        .clamp(1, MAX_SAMPLES);
+
+    // Approved SRI changes take precedence over config.toml
+    let sri = approval::load_overrides(env, scope)
+        .await
+        .remove(&resource.url)
+        .unwrap_or_else(|| resource.sri.to_string());
+    if sri.is_empty() {
+        return Response::error("Resource has no SRI hash to compare against", 400);
+    }
+    let Ok(expected) = scope.config().sri_hashes().parse(&sri) else {
+        return Response::error("Resource has an invalid SRI hash", 400);
+    };
+
+    let report = measure(
+        &WorkerFetcher,
+        &resource.url,
+        &sri,
+        &expected,
+        samples,
+        &trace::new_id(),
+    )
+    .await;
+    console_log!(
+        "Propagation of {}: {}% of {} samples fresh",
+        report.url,
+        report.propagation_percent,
+        report.samples
+    );
+    Response::from_json(&report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use crate::sri;
+    use futures::executor::block_on;
+
+    const URL: &str = "https://cdn.example.com/app.js";
+
+    #[test]
+    fn test_bust_and_pop() {
+        assert_eq!(
+            bust(URL, "abc"),
+            "https://cdn.example.com/app.js?linkkivahti-bust=abc"
+        );
+        assert_eq!(
+            bust("https://cdn.example.com/app.js?v=2", "abc"),
+            "https://cdn.example.com/app.js?v=2&linkkivahti-bust=abc"
+        );
+        assert_eq!(pop(Some("8a1b2c3d4e5f6789-HEL")), "HEL");
+        assert_eq!(pop(None), "unknown");
+    }
+
+    #[test]
+    fn test_measure_stale_cache() {
+        let expected_sri = sri::sha384(b"v2");
+        let expected = SriHash::parse(&expected_sri).unwrap();
+        let fetcher = MockFetcher::new()
+            .respond(
+                &bust(URL, "n"),
+                MockResponse::new(200, "v2").with_header("cf-cache-status", "MISS"),
+            )
+            .respond(
+                URL,
+                MockResponse::new(200, "v1")
+                    .with_header("cf-cache-status", "HIT")
+                    .with_header("cf-ray", "8a1b2c3d4e5f6789-ARN"),
+            );
+
+        let report = block_on(measure(&fetcher, URL, &expected_sri, &expected, 3, "n"));
+        assert_eq!(report.origin_fresh, Some(true));
+        assert_eq!((report.fresh, report.stale, report.failed), (0, 3, 0));
+        assert_eq!(report.propagation_percent, 0.0);
+        assert_eq!(report.cache_status["HIT"], 3);
+        assert_eq!(report.pops["ARN"], PopCount { fresh: 0, stale: 3 });
+        assert_eq!(fetcher.requests().len(), 4);
+    }
+
+    #[test]
+    fn test_measure_fresh_and_failed() {
+        let expected_sri = sri::sha384(b"v2");
+        let expected = SriHash::parse(&expected_sri).unwrap();
+        let fresh = MockFetcher::new().respond(URL, MockResponse::new(200, "v2"));
+        let report = block_on(measure(&fresh, URL, &expected_sri, &expected, 2, "n"));
+        // The busted URL has no canned response, like an unreachable origin
+        assert_eq!(report.origin_fresh, None);
+        assert_eq!(report.propagation_percent, 100.0);
+        assert_eq!(report.cache_status["NONE"], 2);
+
+        let down = MockFetcher::new().respond(URL, MockResponse::new(503, "down"));
+        let report = block_on(measure(&down, URL, &expected_sri, &expected, 2, "n"));
+        assert_eq!(report.failed, 2);
+        assert_eq!(report.propagation_percent, 0.0);
+    }
+}
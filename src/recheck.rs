@@ -0,0 +1,237 @@
+//! Fast follow-up checks of failing resources until they recover
+//!
+//! When a run leaves alerts firing, the failing URLs are handed to a per-scope
+//! Durable Object (`LINKKIVAHTI_RECHECK`). Its alarm re-checks just those resources
+//! every `RECHECK_INTERVAL_SECONDS` (default 90 s) through the regular check
+//! pipeline, so recovery notifications are not delayed until the next cron tick.
+//! The alarm stops once nothing is firing any more.
+//!
+//! Without the binding, failing resources are only re-checked by the cron trigger.
+
+use crate::clock;
+use crate::config::{self, Resource};
+use crate::console::{console_error, console_log};
+use crate::control;
+use crate::maintenance;
+use crate::run::Deadline;
+use crate::sync;
+use crate::tenant::Scope;
+use crate::trace;
+use crate::usage;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use worker::*;
+
+/// Durable Object binding scheduling the re-checks
+const BINDING: &str = "LINKKIVAHTI_RECHECK";
+
+/// Storage key of the pending re-check inside the object
+const STATE_KEY: &str = "state";
+
+/// Default delay between re-checks
+const DEFAULT_INTERVAL_SECONDS: u64 = 90;
+
+/// Bounds of `RECHECK_INTERVAL_SECONDS`
+const MIN_INTERVAL_SECONDS: u64 = 30;
+const MAX_INTERVAL_SECONDS: u64 = 600;
+
+/// Failing resources of a scope awaiting a re-check
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Pending {
+    /// Tenant id, or `None` for the default scope
+    #[serde(default)]
+    pub tenant: Option<String>,
+    pub urls: Vec<String>,
+}
+
+impl Pending {
+    /// Resources of the scope that are still pending and not paused
+    fn resources<'a>(&self, scope: &Scope<'a>) -> Vec<&'a Resource> {
+        scope
+            .resources()
+            .iter()
+            .filter(|r| self.urls.contains(&r.url))
+            .filter(|r| maintenance::paused(r, clock::now_ms()).is_none())
+            .collect()
+    }
+}
+
+/// Delay between re-checks from `RECHECK_INTERVAL_SECONDS`
+fn interval(env: &Env) -> Duration {
+    let seconds = config::setting(env, "RECHECK_INTERVAL_SECONDS").and_then(|v| v.parse().ok());
+    Duration::from_secs(clamp_interval(seconds))
+}
+
+fn clamp_interval(seconds: Option<u64>) -> u64 {
+    seconds
+        .unwrap_or(DEFAULT_INTERVAL_SECONDS)
+        .clamp(MIN_INTERVAL_SECONDS, MAX_INTERVAL_SECONDS)
+}
+
+/// Ask the scope's re-check object to follow up on the failing resources
+///
+/// Does nothing when nothing is firing or the binding is not configured.
+pub async fn schedule(env: &Env, scope: &Scope<'_>, firing: &[String]) {
+    if firing.is_empty() {
+        return;
+    }
+    let Ok(namespace) = env.durable_object(BINDING) else {
+        return;
+    };
+    let pending = Pending {
+        tenant: scope.id().map(str::to_string),
+        urls: firing.to_vec(),
+    };
+    if let Err(e) = post(&namespace, scope, &pending).await {
+        console_error!("Failed to schedule re-check ({}): {}", scope.label(), e);
+    }
+}
+
+async fn post(namespace: &ObjectNamespace, scope: &Scope<'_>, pending: &Pending) -> Result<()> {
+    let stub = namespace.id_from_name(&scope.key("recheck"))?.get_stub()?;
+
+    let headers = Headers::new();
+    headers.set("Content-Type", "application/json")?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post);
+    init.with_headers(headers);
+    init.with_body(Some(serde_json::to_string(pending)?.into()));
+
+    let request = Request::new_with_init("https://recheck/", &init)?;
+    let response = stub.fetch_with_request(request).await?;
+    if response.status_code() != 204 {
+        return Err(Error::RustError(format!(
+            "re-check object returned {}",
+            response.status_code()
+        )));
+    }
+    Ok(())
+}
+
+/// Durable Object re-checking the failing resources of one scope
+#[durable_object]
+pub struct Rechecker {
+    state: State,
+    env: Env,
+}
+
+impl Rechecker {
+    /// Store the pending set and make sure an alarm is scheduled
+    async fn arm(&self, pending: &Pending) -> Result<()> {
+        let storage = self.state.storage();
+        storage.put(STATE_KEY, pending).await?;
+        if storage.get_alarm().await?.is_none() {
+            storage.set_alarm(interval(&self.env)).await?;
+        }
+        Ok(())
+    }
+
+    async fn disarm(&self) -> Result<()> {
+        self.state.storage().delete(STATE_KEY).await?;
+        Ok(())
+    }
+
+    /// Re-check the pending resources, re-arming the alarm while any is still failing
+    async fn recheck(&self) -> Result<Response> {
+        let Ok(pending) = self.state.storage().get::<Pending>(STATE_KEY).await else {
+            return Response::ok("Nothing to re-check");
+        };
+        let control = control::load(&self.env).await;
+        if !control.enabled {
+            // The next scheduled run after re-enabling arms the alarm again
+            console_log!("⏸ Dropping pending re-check: {}", control.describe());
+            self.disarm().await?;
+            return Response::ok("Monitoring disabled");
+        }
+
+        let config = sync::active_config(&self.env).await;
+        let scope = match pending.tenant.as_deref() {
+            Some(id) => Scope::tenant(&config, id),
+            None => Some(Scope::root(&config)),
+        };
+        let resources = scope.as_ref().map(|s| pending.resources(s));
+        let (Some(scope), Some(resources)) = (scope, resources) else {
+            // The tenant was removed from the config
+            self.disarm().await?;
+            return Response::ok("Scope no longer configured");
+        };
+        if resources.is_empty() {
+            self.disarm().await?;
+            return Response::ok("Resources no longer configured or paused");
+        }
+
+        console_log!(
+            "🔁 Re-checking {} failing resources ({})",
+            resources.len(),
+            scope.label()
+        );
+        let summary =
+            crate::check_scope(&self.env, &scope, resources, Deadline::from_env(&self.env)).await;
+
+        if summary.firing.is_empty() {
+            console_log!("✓ All re-checked resources recovered ({})", scope.label());
+            self.disarm().await?;
+        } else {
+            self.state
+                .storage()
+                .put(
+                    STATE_KEY,
+                    &Pending {
+                        tenant: pending.tenant,
+                        urls: summary.firing,
+                    },
+                )
+                .await?;
+            self.state.storage().set_alarm(interval(&self.env)).await?;
+        }
+        Response::ok("Re-check complete")
+    }
+}
+
+impl DurableObject for Rechecker {
+    fn new(state: State, env: Env) -> Self {
+        Self { state, env }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        if req.method() != Method::Post {
+            return Response::error("Method Not Allowed", 405);
+        }
+        let pending: Pending = req.json().await?;
+        self.arm(&pending).await?;
+        Response::empty().map(|r| r.with_status(204))
+    }
+
+    /// Each alarm is a run of its own (see `trace`)
+    async fn alarm(&self) -> Result<Response> {
+        let run_id = trace::new_id();
+        usage::start(&run_id);
+        let response = trace::in_run(run_id.clone(), self.recheck()).await;
+        if let Some(meter) = usage::finish(&run_id) {
+            let record = usage::record(self.env.clone(), usage::Invocation::Recheck, meter);
+            trace::in_run(run_id, record).await;
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_interval() {
+        assert_eq!(clamp_interval(None), 90);
+        assert_eq!(clamp_interval(Some(60)), 60);
+        assert_eq!(clamp_interval(Some(5)), 30);
+        assert_eq!(clamp_interval(Some(3600)), 600);
+    }
+
+    #[test]
+    fn test_pending_round_trip() {
+        let pending: Pending =
+            serde_json::from_str(r#"{"urls": ["https://example.com/a.js"]}"#).unwrap();
+        assert_eq!(pending.tenant, None);
+        assert_eq!(pending.urls, vec!["https://example.com/a.js"]);
+    }
+}
@@ -0,0 +1,534 @@
+//! Container image tags pinned to a manifest digest
+//!
+//! A resource with a `registry` table is the manifest URL of an image tag in a
+//! registry speaking the Docker Registry HTTP API v2 (Docker Hub, GHCR, ECR, ...):
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://registry-1.docker.io/v2/library/nginx/manifests/1.27.3"
+//! registry = { digest = "sha256:..." }
+//! ```
+//!
+//! Each check resolves the tag with a HEAD request and compares the
+//! `Docker-Content-Digest` of the manifest with the pinned digest, so a tag that is
+//! supposed to be immutable but gets repointed raises an integrity alert, like an
+//! SRI mismatch. Registries that do not return the header are answered with a GET
+//! and the digest of the manifest body.
+//!
+//! Registries that answer `401` with a `Bearer` challenge are sent to their token
+//! service first (anonymously, which is enough for public images on Docker Hub and
+//! GHCR). Private images set `credentials`, the prefix of the secrets
+//! `<credentials>_USERNAME` and `<credentials>_PASSWORD`, which are sent to the
+//! token service, or directly to registries using `Basic` authentication.
+
+use crate::checker::{CheckError, CheckResult};
+use crate::clock;
+use crate::config::{self, Resource};
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use crate::sri;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use worker::*;
+
+/// Manifest media types accepted when resolving a tag: multi-platform indexes
+/// first, so the digest is that of the tag itself rather than one platform's image
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+     application/vnd.docker.distribution.manifest.list.v2+json, \
+     application/vnd.docker.distribution.manifest.v2+json, \
+     application/vnd.oci.image.manifest.v1+json";
+
+/// Settings of a registry manifest probe (`registry = { ... }` on a resource)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryImage {
+    /// Pinned manifest digest, e.g. `sha256:...`
+    pub digest: String,
+    /// Prefix of the secrets holding the registry username and password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<String>,
+}
+
+impl RegistryImage {
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        let is_manifest = Url::parse(url).is_ok_and(|u| {
+            u.host_str().is_some()
+                && u.path().starts_with("/v2/")
+                && u.path().contains("/manifests/")
+        });
+        if !is_manifest {
+            return Err(format!(
+                "{} is not a registry manifest URL (https://<registry>/v2/<name>/manifests/<tag>)",
+                url
+            ));
+        }
+        let valid_digest = match self.digest.split_once(':') {
+            Some(("sha256", hex)) => is_hex(hex, 64),
+            Some(("sha512", hex)) => is_hex(hex, 128),
+            _ => false,
+        };
+        if !valid_digest {
+            return Err(format!(
+                "Invalid registry.digest '{}' of {}: expected sha256:<64 hex digits>",
+                self.digest, url
+            ));
+        }
+        if self.credentials.as_deref() == Some("") {
+            return Err(format!("registry.credentials of {} cannot be empty", url));
+        }
+        Ok(())
+    }
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// A registry username and password
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Read `<prefix>_USERNAME` and `<prefix>_PASSWORD`
+    pub fn from_env(env: &Env, prefix: &str) -> Option<Self> {
+        Some(Self {
+            username: config::setting(env, &format!("{}_USERNAME", prefix))?,
+            password: config::setting(env, &format!("{}_PASSWORD", prefix))?,
+        })
+    }
+
+    fn basic(&self) -> String {
+        format!(
+            "Basic {}",
+            BASE64.encode(format!("{}:{}", self.username, self.password))
+        )
+    }
+}
+
+/// Authentication a registry asks for in `WWW-Authenticate`
+#[derive(Debug, PartialEq, Eq)]
+enum Challenge {
+    Basic,
+    Bearer {
+        realm: String,
+        service: Option<String>,
+        scope: Option<String>,
+    },
+}
+
+/// Parse a `WWW-Authenticate` header, e.g.
+/// `Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/nginx:pull"`
+fn parse_challenge(header: &str) -> Option<Challenge> {
+    let (scheme, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    if scheme.eq_ignore_ascii_case("basic") {
+        return Some(Challenge::Basic);
+    }
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    // Comma-separated key="value" pairs; quoted values may contain commas
+    let mut pairs = Vec::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_ascii_lowercase();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => after.split_at(after.find(',').unwrap_or(after.len())),
+        };
+        pairs.push((key, value.trim().to_string()));
+        rest = after.trim_start_matches([',', ' ']);
+    }
+    let get = |name: &str| {
+        pairs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+    Some(Challenge::Bearer {
+        realm: get("realm")?,
+        service: get("service"),
+        scope: get("scope"),
+    })
+}
+
+/// `repository:<name>:pull` scope of a manifest URL
+fn pull_scope(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let path = url.path().strip_prefix("/v2/")?;
+    let (name, _) = path.rsplit_once("/manifests/")?;
+    Some(format!("repository:{}:pull", name))
+}
+
+/// Fetch a bearer token from the token service of a challenge
+async fn fetch_token(
+    fetcher: &impl Fetcher,
+    url: &str,
+    realm: &str,
+    service: Option<&str>,
+    scope: Option<String>,
+    credentials: Option<&Credentials>,
+) -> std::result::Result<String, String> {
+    let mut token_url = Url::parse(realm).map_err(|e| format!("invalid token realm: {}", e))?;
+    {
+        let mut query = token_url.query_pairs_mut();
+        if let Some(service) = service {
+            query.append_pair("service", service);
+        }
+        if let Some(scope) = scope.or_else(|| pull_scope(url)) {
+            query.append_pair("scope", &scope);
+        }
+    }
+    let mut request = HttpRequest::new(Method::Get, token_url.as_str());
+    if let Some(credentials) = credentials {
+        request = request.with_header("Authorization", &credentials.basic());
+    }
+    let mut response = fetcher
+        .fetch(request)
+        .await
+        .map_err(|e| format!("token request failed: {}", e))?;
+    if !(200..300).contains(&response.status_code()) {
+        return Err(format!(
+            "token service returned HTTP {}",
+            response.status_code()
+        ));
+    }
+
+    #[derive(Deserialize)]
+    struct TokenResponse {
+        token: Option<String>,
+        access_token: Option<String>,
+    }
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("token response unreadable: {}", e))?;
+    let parsed: TokenResponse =
+        serde_json::from_str(&body).map_err(|e| format!("invalid token response: {}", e))?;
+    parsed
+        .token
+        .or(parsed.access_token)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| "token response has no token".to_string())
+}
+
+fn manifest_request(method: Method, url: &str, authorization: Option<&str>) -> HttpRequest {
+    let request = HttpRequest::new(method, url).with_header("Accept", MANIFEST_TYPES);
+    match authorization {
+        Some(value) => request.with_header("Authorization", value),
+        None => request,
+    }
+}
+
+/// Digest of a manifest body in the algorithm of the pinned digest
+fn manifest_digest(pinned: &str, body: &[u8]) -> String {
+    if pinned.starts_with("sha512:") {
+        format!("sha512:{}", sri::encode_hex(&Sha512::digest(body)))
+    } else {
+        format!("sha256:{}", sri::encode_hex(&Sha256::digest(body)))
+    }
+}
+
+/// Check that an image tag still resolves to its pinned manifest digest
+///
+/// Returns a `FetchFailed` result when configured credentials are missing.
+pub async fn check(
+    fetcher: &impl Fetcher,
+    credentials: Option<&Credentials>,
+    resource: &Resource,
+    image: &RegistryImage,
+) -> CheckResult {
+    let started = clock::now_ms();
+    let mut result = run_check(fetcher, credentials, resource, image).await;
+    if result.error != Some(CheckError::FetchFailed) {
+        result.latency_ms = Some(clock::now_ms().saturating_sub(started));
+    }
+    result
+}
+
+async fn run_check(
+    fetcher: &impl Fetcher,
+    credentials: Option<&Credentials>,
+    resource: &Resource,
+    image: &RegistryImage,
+) -> CheckResult {
+    let url = resource.url.as_str();
+    console_log!("Checking registry manifest: {}", url);
+    if let (Some(prefix), None) = (&image.credentials, credentials) {
+        console_error!(
+            "✗ {} - {}_USERNAME / {}_PASSWORD not set",
+            url,
+            prefix,
+            prefix
+        );
+        return CheckResult::failure(url.to_string(), CheckError::FetchFailed);
+    }
+
+    let mut authorization = None;
+    let mut response = match fetcher
+        .fetch(manifest_request(Method::Head, url, None))
+        .await
+    {
+        Ok(r) => r,
+        Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+    };
+    if response.status_code() == 401 {
+        let challenge = response
+            .header("WWW-Authenticate")
+            .and_then(|h| parse_challenge(&h));
+        let value = match (challenge, credentials) {
+            (
+                Some(Challenge::Bearer {
+                    realm,
+                    service,
+                    scope,
+                }),
+                _,
+            ) => {
+                match fetch_token(fetcher, url, &realm, service.as_deref(), scope, credentials)
+                    .await
+                {
+                    Ok(token) => format!("Bearer {}", token),
+                    Err(e) => {
+                        console_error!("✗ {} - {}", url, e);
+                        return CheckResult::failure(url.to_string(), CheckError::HttpError(401));
+                    }
+                }
+            }
+            (Some(Challenge::Basic), Some(credentials)) => credentials.basic(),
+            _ => return CheckResult::failure(url.to_string(), CheckError::HttpError(401)),
+        };
+        response = match fetcher
+            .fetch(manifest_request(Method::Head, url, Some(&value)))
+            .await
+        {
+            Ok(r) => r,
+            Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+        };
+        authorization = Some(value);
+    }
+    let status_code = response.status_code();
+    if !(200..300).contains(&status_code) {
+        return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+    }
+
+    let digest = match response.header("Docker-Content-Digest") {
+        Some(digest) => digest.trim().to_ascii_lowercase(),
+        // No digest header, hash the manifest itself
+        None => {
+            let request = manifest_request(Method::Get, url, authorization.as_deref());
+            let mut response = match fetcher.fetch(request).await {
+                Ok(r) => r,
+                Err(_) => return CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+            };
+            let status_code = response.status_code();
+            if !(200..300).contains(&status_code) {
+                return CheckResult::failure(url.to_string(), CheckError::HttpError(status_code));
+            }
+            match response.bytes().await {
+                Ok(body) => manifest_digest(&image.digest, &body),
+                Err(e) => return CheckResult::failure(url.to_string(), CheckError::from_body(&e)),
+            }
+        }
+    };
+    let valid = digest == image.digest.to_ascii_lowercase();
+    if !valid {
+        console_error!(
+            "✗ {} - tag repointed: expected {}, got {}",
+            url,
+            image.digest,
+            digest
+        );
+    }
+    CheckResult::success(url.to_string(), status_code, valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    const URL: &str = "https://registry-1.docker.io/v2/library/nginx/manifests/1.27.3";
+    const TOKEN_URL: &str = "https://auth.docker.io/token?service=registry.docker.io&scope=repository%3Alibrary%2Fnginx%3Apull";
+    const CHALLENGE: &str = "Bearer realm=\"https://auth.docker.io/token\",service=\"registry.docker.io\",scope=\"repository:library/nginx:pull\"";
+
+    fn digest_of(body: &[u8]) -> String {
+        format!("sha256:{}", sri::encode_hex(&Sha256::digest(body)))
+    }
+
+    fn image(digest: &str) -> RegistryImage {
+        RegistryImage {
+            digest: digest.to_string(),
+            credentials: None,
+        }
+    }
+
+    fn resource(image: &RegistryImage) -> Resource {
+        Resource {
+            url: URL.to_string(),
+            registry: Some(image.clone()),
+            ..Default::default()
+        }
+    }
+
+    fn run(
+        fetcher: &MockFetcher,
+        image: &RegistryImage,
+        credentials: Option<&Credentials>,
+    ) -> CheckResult {
+        block_on(check(fetcher, credentials, &resource(image), image))
+    }
+
+    fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+        request
+            .headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        assert_eq!(
+            parse_challenge(CHALLENGE),
+            Some(Challenge::Bearer {
+                realm: "https://auth.docker.io/token".to_string(),
+                service: Some("registry.docker.io".to_string()),
+                scope: Some("repository:library/nginx:pull".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_challenge(
+                "Bearer realm=\"https://ghcr.io/token\", scope=\"repository:a/b:pull,push\""
+            ),
+            Some(Challenge::Bearer {
+                realm: "https://ghcr.io/token".to_string(),
+                service: None,
+                scope: Some("repository:a/b:pull,push".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_challenge("Basic realm=\"Registry\""),
+            Some(Challenge::Basic)
+        );
+        assert_eq!(parse_challenge("Bearer service=\"x\""), None);
+        assert_eq!(parse_challenge("Negotiate"), None);
+        assert_eq!(
+            pull_scope(URL).as_deref(),
+            Some("repository:library/nginx:pull")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let digest = digest_of(b"manifest");
+        assert!(image(&digest).validate(URL).is_ok());
+        assert!(image(&digest)
+            .validate("https://registry-1.docker.io/library/nginx:1.27.3")
+            .is_err());
+        assert!(image("sha256:abc").validate(URL).is_err());
+        assert!(image(&digest.replace("sha256", "md5"))
+            .validate(URL)
+            .is_err());
+    }
+
+    #[test]
+    fn test_token_flow() {
+        let pinned = digest_of(b"manifest");
+        let fetcher = MockFetcher::new()
+            .respond(
+                URL,
+                MockResponse::new(401, "").with_header("WWW-Authenticate", CHALLENGE),
+            )
+            .respond(TOKEN_URL, MockResponse::new(200, r#"{"token":"t0k3n"}"#))
+            .respond_authorized(
+                URL,
+                "Bearer t0k3n",
+                MockResponse::new(200, "").with_header("Docker-Content-Digest", &pinned),
+            );
+        let result = run(&fetcher, &image(&pinned), None);
+        assert_eq!(result.sri_valid, Some(true));
+        assert!(result.latency_ms.is_some());
+
+        let requests = fetcher.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].method, Method::Head);
+        assert!(header(&requests[0], "Accept")
+            .unwrap()
+            .starts_with("application/vnd.oci.image.index.v1+json"));
+        // Anonymous token request
+        assert_eq!(header(&requests[1], "Authorization"), None);
+        assert_eq!(header(&requests[2], "Authorization"), Some("Bearer t0k3n"));
+
+        // The tag was repointed
+        let result = run(&fetcher, &image(&digest_of(b"other")), None);
+        assert_eq!(result.sri_valid, Some(false));
+        assert!(result.has_problem());
+    }
+
+    #[test]
+    fn test_credentials() {
+        let pinned = digest_of(b"manifest");
+        let credentials = Credentials {
+            username: "bot".to_string(),
+            password: "secret".to_string(),
+        };
+        let basic = format!("Basic {}", BASE64.encode("bot:secret"));
+        let fetcher = MockFetcher::new()
+            .respond(
+                URL,
+                MockResponse::new(401, "").with_header("WWW-Authenticate", CHALLENGE),
+            )
+            .respond(TOKEN_URL, MockResponse::new(401, ""))
+            .respond_authorized(
+                TOKEN_URL,
+                &basic,
+                MockResponse::new(200, r#"{"access_token":"private"}"#),
+            )
+            .respond_authorized(
+                URL,
+                "Bearer private",
+                MockResponse::new(200, "").with_header("Docker-Content-Digest", &pinned),
+            );
+        let private = RegistryImage {
+            credentials: Some("REGISTRY".to_string()),
+            ..image(&pinned)
+        };
+        assert_eq!(
+            run(&fetcher, &private, Some(&credentials)).sri_valid,
+            Some(true)
+        );
+
+        // Missing secrets, or a token service refusing anonymous access
+        let result = run(&fetcher, &private, None);
+        assert_eq!(result.error, Some(CheckError::FetchFailed));
+        let result = run(&fetcher, &image(&pinned), None);
+        assert_eq!(result.error, Some(CheckError::HttpError(401)));
+    }
+
+    #[test]
+    fn test_digest_from_manifest_body() {
+        let manifest = br#"{"schemaVersion":2}"#;
+        let fetcher = MockFetcher::new().respond(URL, MockResponse::new(200, manifest.to_vec()));
+        let result = run(&fetcher, &image(&digest_of(manifest)), None);
+        assert_eq!(result.sri_valid, Some(true));
+        let requests = fetcher.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].method, Method::Get);
+
+        let fetcher = MockFetcher::new().respond(URL, MockResponse::new(404, ""));
+        let result = run(&fetcher, &image(&digest_of(manifest)), None);
+        assert_eq!(result.error, Some(CheckError::HttpError(404)));
+    }
+}
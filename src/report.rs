@@ -0,0 +1,431 @@
+//! Monthly SLA report (`GET /reports/sla`)
+//!
+//! Renders a print-friendly HTML page for a calendar month (`?month=2025-11`,
+//! default the previous month) with the uptime of every resource, its incidents,
+//! mean time to recovery and whether it met the SLO target. Uptime is derived from
+//! the availability incidents of the incident log: the time a resource spent with
+//! a firing availability alert within the month counts as downtime. Integrity
+//! incidents are listed and counted but do not affect uptime.
+//!
+//! `?format=json` returns the same data as JSON. The SLO target comes from
+//! `SLO_TARGET_PERCENT` (default 99.9).
+
+use crate::alert::AlertStream;
+use crate::clock;
+use crate::config;
+use crate::history;
+use crate::incident::{Incident, IncidentLog};
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use worker::*;
+
+/// SLO target unless `SLO_TARGET_PERCENT` is set
+const DEFAULT_SLO_PERCENT: f64 = 99.9;
+
+const DAY_MS: u64 = 86_400_000;
+
+/// SLA figures of one resource over the report period
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ResourceSla {
+    /// URL, or the alert key of a resource deduplicated by name or group
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub uptime_percent: f64,
+    pub downtime_ms: u64,
+    /// Availability incidents overlapping the period
+    pub incidents: usize,
+    pub integrity_incidents: usize,
+    /// Mean time to recovery of the incidents resolved within the period
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mttr_ms: Option<u64>,
+    pub meets_slo: bool,
+}
+
+/// SLA report of a month
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SlaReport {
+    /// `YYYY-MM`
+    pub month: String,
+    /// Start of the period (milliseconds since the Unix epoch, inclusive)
+    pub from: u64,
+    /// End of the period (exclusive); now for the current month
+    pub to: u64,
+    pub slo_percent: f64,
+    pub resources: Vec<ResourceSla>,
+    /// Resources meeting the SLO target
+    pub compliant: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mttr_ms: Option<u64>,
+    /// Incidents overlapping the period, oldest first
+    pub incidents: Vec<Incident>,
+}
+
+/// Parse `YYYY-MM` into the start and end of the month
+fn parse_month(month: &str) -> Option<(u64, u64)> {
+    let (year, month) = month.split_once('-')?;
+    let year: i64 = year.parse().ok()?;
+    let month: i64 = month.parse().ok()?;
+    if !(1..=12).contains(&month) || year < 1970 {
+        return None;
+    }
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    let start = clock::days_from_civil(year, month, 1) as u64 * DAY_MS;
+    let end = clock::days_from_civil(next_year, next_month, 1) as u64 * DAY_MS;
+    Some((start, end))
+}
+
+/// `YYYY-MM` of the month before the one containing `now`
+fn previous_month(now: u64) -> String {
+    let (year, month, _) = clock::civil_from_days((now / DAY_MS) as i64);
+    match month {
+        1 => format!("{}-12", year - 1),
+        _ => format!("{}-{:02}", year, month - 1),
+    }
+}
+
+/// Time an incident was open within `[from, to)`
+fn overlap(incident: &Incident, from: u64, to: u64) -> u64 {
+    let end = incident.resolved.unwrap_or(to).min(to);
+    end.saturating_sub(incident.started.max(from))
+}
+
+/// Mean duration of the incidents resolved within `[from, to)`
+fn mean_recovery<'a>(
+    incidents: impl Iterator<Item = &'a Incident>,
+    from: u64,
+    to: u64,
+) -> Option<u64> {
+    let durations: Vec<u64> = incidents
+        .filter(|i| i.resolved.is_some_and(|at| (from..to).contains(&at)))
+        .filter_map(Incident::duration_ms)
+        .collect();
+    (!durations.is_empty()).then(|| durations.iter().sum::<u64>() / durations.len() as u64)
+}
+
+impl SlaReport {
+    /// Compute the report of `[from, to)` for resources given as `(subject, name)`
+    ///
+    /// Subjects with incidents that are no longer configured are included as well.
+    pub fn compute(
+        log: &IncidentLog,
+        resources: &[(String, Option<String>)],
+        month: &str,
+        from: u64,
+        to: u64,
+        slo_percent: f64,
+    ) -> Self {
+        let incidents: Vec<&Incident> = log
+            .incidents
+            .iter()
+            .filter(|i| i.started < to && i.resolved.is_none_or(|at| at > from))
+            .collect();
+
+        let mut subjects: BTreeMap<&str, Option<&str>> = resources
+            .iter()
+            .map(|(subject, name)| (subject.as_str(), name.as_deref()))
+            .collect();
+        for incident in &incidents {
+            subjects.entry(incident.subject()).or_insert(None);
+        }
+
+        let period = to.saturating_sub(from).max(1);
+        let resources: Vec<ResourceSla> = subjects
+            .into_iter()
+            .map(|(subject, name)| {
+                let own = || incidents.iter().copied().filter(|i| i.subject() == subject);
+                let availability = || own().filter(|i| i.stream == AlertStream::Availability);
+                let downtime_ms = availability()
+                    .map(|i| overlap(i, from, to))
+                    .sum::<u64>()
+                    .min(period);
+                let uptime_percent =
+                    ((period - downtime_ms) as f64 * 100_000.0 / period as f64).round() / 1000.0;
+                ResourceSla {
+                    subject: subject.to_string(),
+                    name: name.map(str::to_string),
+                    uptime_percent,
+                    downtime_ms,
+                    incidents: availability().count(),
+                    integrity_incidents: own()
+                        .filter(|i| i.stream == AlertStream::Integrity)
+                        .count(),
+                    mttr_ms: mean_recovery(availability(), from, to),
+                    meets_slo: uptime_percent >= slo_percent,
+                }
+            })
+            .collect();
+
+        Self {
+            month: month.to_string(),
+            from,
+            to,
+            slo_percent,
+            compliant: resources.iter().filter(|r| r.meets_slo).count(),
+            resources,
+            mttr_ms: mean_recovery(incidents.iter().copied(), from, to),
+            incidents: incidents.into_iter().cloned().collect(),
+        }
+    }
+
+    /// Render the report as a standalone, print-friendly HTML page
+    pub fn html(&self, scope_label: &str) -> String {
+        let mttr = |ms: Option<u64>| ms.map_or("–".to_string(), history::format_duration);
+        let mut rows = String::new();
+        for r in &self.resources {
+            rows.push_str(&format!(
+                "<tr class=\"{}\"><td>{}</td><td class=\"num\">{:.3}%</td><td class=\"num\">{}</td>\
+                 <td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td>{}</td></tr>\n",
+                if r.meets_slo { "ok" } else { "miss" },
+                escape(r.name.as_deref().unwrap_or(&r.subject)),
+                r.uptime_percent,
+                history::format_duration(r.downtime_ms),
+                r.incidents,
+                r.integrity_incidents,
+                mttr(r.mttr_ms),
+                if r.meets_slo { "✓ met" } else { "✗ missed" },
+            ));
+        }
+        let mut incidents = String::new();
+        for i in &self.incidents {
+            incidents.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                date_time(i.started),
+                i.resolved.map_or("ongoing".to_string(), date_time),
+                escape(i.subject()),
+                i.stream,
+                escape(&i.error),
+            ));
+        }
+        if incidents.is_empty() {
+            incidents.push_str("<tr><td colspan=\"5\">No incidents</td></tr>\n");
+        }
+
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>SLA report {month} – {scope}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; width: 100%; margin-bottom: 2em; }}
+th, td {{ border-bottom: 1px solid #ddd; padding: 0.3em 0.6em; text-align: left; }}
+td.num {{ text-align: right; font-variant-numeric: tabular-nums; }}
+tr.miss td {{ background: #fdecea; }}
+.summary {{ color: #555; }}
+@media print {{ body {{ margin: 0; font-size: 10pt; }} tr {{ page-break-inside: avoid; }} }}
+</style>
+</head>
+<body>
+<h1>SLA report {month}</h1>
+<p class="summary">{scope} · {from} to {to} · SLO target {slo}% · {compliant} of {total} resources compliant · MTTR {mttr}</p>
+<h2>Uptime</h2>
+<table>
+<tr><th>Resource</th><th>Uptime</th><th>Downtime</th><th>Incidents</th><th>Integrity</th><th>MTTR</th><th>SLO</th></tr>
+{rows}</table>
+<h2>Incidents</h2>
+<table>
+<tr><th>Started</th><th>Resolved</th><th>Resource</th><th>Stream</th><th>Error</th></tr>
+{incidents}</table>
+</body>
+</html>
+"#,
+            month = escape(&self.month),
+            scope = escape(scope_label),
+            from = date_time(self.from),
+            to = date_time(self.to),
+            slo = self.slo_percent,
+            compliant = self.compliant,
+            total = self.resources.len(),
+            mttr = mttr(self.mttr_ms),
+            rows = rows,
+            incidents = incidents,
+        )
+    }
+}
+
+/// `YYYY-MM-DD HH:MM UTC` of a time in milliseconds since the Unix epoch
+fn date_time(ms: u64) -> String {
+    let (year, month, day) = clock::civil_from_days((ms / DAY_MS) as i64);
+    let minutes = ms % DAY_MS / 60_000;
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Escape text for HTML
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// SLO target from `SLO_TARGET_PERCENT`
+fn slo_percent(env: &Env) -> f64 {
+    config::setting(env, "SLO_TARGET_PERCENT")
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|p| (0.0..=100.0).contains(p))
+        .unwrap_or(DEFAULT_SLO_PERCENT)
+}
+
+/// Handle `GET /reports/sla?month=YYYY-MM[&format=json]`
+pub async fn handle_sla(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let store = Store::require(env)?;
+    let url = req.url()?;
+    let query: BTreeMap<String, String> = url.query_pairs().into_owned().collect();
+    let now = clock::now_ms();
+    let month = query
+        .get("month")
+        .cloned()
+        .unwrap_or_else(|| previous_month(now));
+    let Some((from, end)) = parse_month(&month) else {
+        return Response::error(format!("Invalid month '{}', use YYYY-MM", month), 400);
+    };
+    if from >= now {
+        return Response::error(format!("Month {} has not started yet", month), 400);
+    }
+
+    let resources: Vec<(String, Option<String>)> = scope
+        .resources()
+        .iter()
+        .map(|r| (r.alert_key(), r.name.clone()))
+        .collect();
+    let log = IncidentLog::load(&store, scope).await?;
+    let report = SlaReport::compute(
+        &log,
+        &resources,
+        &month,
+        from,
+        end.min(now),
+        slo_percent(env),
+    );
+    match query.get("format").map(String::as_str) {
+        Some("json") => Response::from_json(&report),
+        _ => Response::from_html(report.html(scope.label())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3_600_000;
+
+    fn incident(url: &str, stream: AlertStream, started: u64, resolved: Option<u64>) -> Incident {
+        Incident {
+            stream,
+            url: url.to_string(),
+            started,
+            resolved,
+            error: "http_503".to_string(),
+            run_id: None,
+            key: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_month() {
+        let (from, to) = parse_month("2025-11").unwrap();
+        assert_eq!(from, 1_761_955_200_000);
+        assert_eq!(to - from, 30 * DAY_MS);
+        let (from, to) = parse_month("2025-12").unwrap();
+        assert_eq!(to - from, 31 * DAY_MS);
+        assert!(parse_month("2025-13").is_none());
+        assert!(parse_month("November").is_none());
+        assert_eq!(previous_month(clock::now_ms()), "2025-10");
+        assert_eq!(previous_month(parse_month("2026-01").unwrap().0), "2025-12");
+    }
+
+    #[test]
+    fn test_compute() {
+        let (from, to) = parse_month("2025-11").unwrap();
+        let log = IncidentLog {
+            incidents: vec![
+                // Started in October, counts from the first of November
+                incident(
+                    "https://a.example/x.js",
+                    AlertStream::Availability,
+                    from - HOUR,
+                    Some(from + HOUR),
+                ),
+                incident(
+                    "https://a.example/x.js",
+                    AlertStream::Availability,
+                    from + 10 * HOUR,
+                    Some(from + 12 * HOUR),
+                ),
+                incident(
+                    "https://a.example/x.js",
+                    AlertStream::Integrity,
+                    from + 20 * HOUR,
+                    Some(from + 21 * HOUR),
+                ),
+                incident(
+                    "https://gone.example/y.js",
+                    AlertStream::Availability,
+                    to - HOUR,
+                    None,
+                ),
+                incident(
+                    "https://a.example/x.js",
+                    AlertStream::Availability,
+                    to + HOUR,
+                    None,
+                ),
+            ],
+        };
+        let resources = vec![
+            (
+                "https://a.example/x.js".to_string(),
+                Some("app".to_string()),
+            ),
+            ("https://b.example/z.js".to_string(), None),
+        ];
+        let report = SlaReport::compute(&log, &resources, "2025-11", from, to, 99.9);
+
+        assert_eq!(report.incidents.len(), 4);
+        let a = &report.resources[0];
+        assert_eq!(a.subject, "https://a.example/x.js");
+        assert_eq!(a.downtime_ms, 3 * HOUR);
+        assert_eq!(a.uptime_percent, 99.583);
+        assert_eq!(a.incidents, 2);
+        assert_eq!(a.integrity_incidents, 1);
+        assert_eq!(a.mttr_ms, Some(2 * HOUR));
+        assert!(!a.meets_slo);
+
+        let b = &report.resources[1];
+        assert_eq!(b.uptime_percent, 100.0);
+        assert!(b.meets_slo);
+        // Subjects that are no longer configured still show up
+        assert_eq!(report.resources[2].subject, "https://gone.example/y.js");
+        assert_eq!(report.resources[2].downtime_ms, HOUR);
+        assert_eq!(report.compliant, 1);
+
+        let html = report.html("default");
+        assert!(html.contains("<h1>SLA report 2025-11</h1>"));
+        assert!(html.contains("<td>app</td><td class=\"num\">99.583%</td>"));
+        assert!(html.contains("2025-11-01 10:00 UTC"));
+        assert!(html.contains("ongoing"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Resource listing (`GET /resources`)
+//!
+//! Lists the configured resources of a scope; those added through the API carry
+//! the `id` to change them with (see `managed`). With `include=archived`,
+//! resources removed from config whose data is still retained (see `archive`)
+//! are listed too, with their removal and purge times and how much of their
+//! history and incident log is left.
+
+use crate::clock;
+use crate::config::Resource;
+use crate::history::{Backend, History, HistoryBackend};
+use crate::incident::IncidentLog;
+use crate::managed::ManagedResources;
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::Serialize;
+use worker::*;
+
+/// A configured or archived resource
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ResourceEntry {
+    /// ID of a resource added through the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub archived: bool,
+    /// When the resource was removed from config (milliseconds since the Unix epoch)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed_at: Option<u64>,
+    /// When its history and incidents are purged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge_at: Option<u64>,
+    /// Retained history entries of an archived resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checks: Option<usize>,
+    /// Time of the last check of an archived resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_checked: Option<u64>,
+    /// Logged incidents of an archived resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incidents: Option<usize>,
+}
+
+impl ResourceEntry {
+    fn configured(resource: &Resource) -> Self {
+        Self {
+            id: None,
+            url: resource.url.clone(),
+            name: resource.name.clone(),
+            group: resource.group.clone(),
+            archived: false,
+            removed_at: None,
+            purge_at: None,
+            checks: None,
+            last_checked: None,
+            incidents: None,
+        }
+    }
+}
+
+/// Response of `GET /resources`
+#[derive(Debug, Serialize)]
+pub struct ResourceList {
+    pub resources: Vec<ResourceEntry>,
+}
+
+/// Archived resources of a scope that are still retained
+fn archived(scope: &Scope, history: &History, log: &IncidentLog, now: u64) -> Vec<ResourceEntry> {
+    let config = &scope.config().archive;
+    history
+        .archived
+        .iter()
+        .filter(|(url, &removed_at)| {
+            scope.find_resource(url).is_none() && now < config.purge_at(removed_at)
+        })
+        .map(|(url, &removed_at)| ResourceEntry {
+            id: None,
+            url: url.clone(),
+            name: None,
+            group: None,
+            archived: true,
+            removed_at: Some(removed_at),
+            purge_at: Some(config.purge_at(removed_at)),
+            checks: Some(history.entries(url).len()),
+            last_checked: history.latest(url).map(|e| e.at),
+            incidents: Some(log.incidents.iter().filter(|i| i.url == *url).count()),
+        })
+        .collect()
+}
+
+/// Whether the `include` query parameters ask for archived resources
+fn include_archived(url: &Url) -> std::result::Result<bool, String> {
+    let mut archived = false;
+    for (_, value) in url.query_pairs().filter(|(k, _)| k == "include") {
+        for item in value.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            match item {
+                "archived" => archived = true,
+                other => return Err(format!("Unknown include '{}'", other)),
+            }
+        }
+    }
+    Ok(archived)
+}
+
+/// Handle `GET /resources`
+pub async fn handle_resources(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let include = match include_archived(&req.url()?) {
+        Ok(include) => include,
+        Err(message) => return Response::error(message, 400),
+    };
+    let mut resources: Vec<ResourceEntry> = scope
+        .resources()
+        .iter()
+        .map(ResourceEntry::configured)
+        .collect();
+    if let Some(store) = Store::from_env(env) {
+        let managed = ManagedResources::load(&store).await?;
+        for entry in &mut resources {
+            entry.id = managed.id_of(scope, &entry.url).map(str::to_string);
+        }
+    }
+    if include {
+        let store = Store::require(env)?;
+        let history = Backend::require(env)?.load(scope).await?;
+        let log = IncidentLog::load(&store, scope).await?;
+        resources.extend(archived(scope, &history, &log, clock::now_ms()));
+    }
+    Response::from_json(&ResourceList { resources })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::{AlertStream, Transition};
+    use crate::archive;
+    use crate::checker::{CheckError, CheckResult};
+    use crate::config::Config;
+
+    const DAY_MS: u64 = 86_400_000;
+
+    #[test]
+    fn test_archived() {
+        let gone = "https://example.com/gone.js";
+        let before = Config::from_toml(&format!(
+            "version = \"1.0\"\n\
+             [[resources]]\nurl = \"https://example.com/a.js\"\nsri = \"sha384-abc\"\n\
+             [[resources]]\nurl = \"{}\"\nsri = \"sha384-abc\"\nname = \"Gone\"\n",
+            gone
+        ))
+        .unwrap();
+        let after = Config::from_toml(
+            "version = \"1.0\"\n\
+             [[resources]]\nurl = \"https://example.com/a.js\"\nsri = \"sha384-abc\"\n",
+        )
+        .unwrap();
+
+        let mut history = History::default();
+        let failure = CheckResult::failure(gone, CheckError::HttpError(404));
+        history.record(std::slice::from_ref(&failure), 1000);
+        archive::reconcile(&mut history, &Scope::root(&before), 1000);
+        archive::reconcile(&mut history, &Scope::root(&after), DAY_MS);
+        let mut log = IncidentLog::default();
+        log.apply(
+            gone,
+            AlertStream::Availability,
+            Transition::Fired,
+            &failure,
+            1000,
+        );
+
+        let scope = Scope::root(&after);
+        let entries = archived(&scope, &history, &log, 2 * DAY_MS);
+        assert_eq!(
+            entries,
+            [ResourceEntry {
+                id: None,
+                url: gone.to_string(),
+                name: None,
+                group: None,
+                archived: true,
+                removed_at: Some(DAY_MS),
+                purge_at: Some(31 * DAY_MS),
+                checks: Some(1),
+                last_checked: Some(1000),
+                incidents: Some(1),
+            }]
+        );
+        // Past the retention, before the next run purges it
+        assert!(archived(&scope, &history, &log, 31 * DAY_MS).is_empty());
+        // Listed as configured again once it is back
+        assert!(archived(&Scope::root(&before), &history, &log, 2 * DAY_MS).is_empty());
+    }
+
+    #[test]
+    fn test_include_archived() {
+        let parse = |query: &str| {
+            include_archived(&Url::parse(&format!("https://w.example/resources{}", query)).unwrap())
+        };
+        assert_eq!(parse(""), Ok(false));
+        assert_eq!(parse("?include=archived"), Ok(true));
+        assert_eq!(parse("?include=,archived"), Ok(true));
+        assert_eq!(
+            parse("?include=deleted"),
+            Err("Unknown include 'deleted'".to_string())
+        );
+    }
+}
@@ -0,0 +1,657 @@
+//! Run planning: subrequest batches, deadline tracking and carry-over of skipped resources
+//!
+//! Scheduled handlers have a limited time budget, and large resource lists could
+//! exceed it and be cut off silently. Checks are therefore launched with bounded
+//! concurrency, and no new check starts once the run deadline (`RUN_DEADLINE_MS`,
+//! default 25 s) has passed. Resources skipped this way are stored in a per-scope
+//! KV cursor and checked first on the next invocation.
+//!
+//! Invocations are also capped in subrequests. With `[schedule] max_subrequests`,
+//! each run only checks as many resources as the cap allows, picking the ones
+//! checked longest ago according to the stored history, so consecutive cron ticks
+//! work through the list and every resource is checked within `cycle_minutes`.
+//! A sitemap resource counts one subrequest per page it may check.
+//!
+//! With several cron triggers in wrangler.toml, a resource's `cron` (or
+//! `[schedule] default_cron`) names the trigger that checks it, e.g. critical
+//! bundles every 5 minutes and large tarballs hourly. Scheduled runs only plan
+//! the resources due on their trigger; manual checks plan everything.
+//!
+//! A run holds a per-scope lock in KV while it checks the scope, so a manual
+//! `/check` during a cron run (or two overlapping cron ticks) does not race on the
+//! stored state: the later trigger leaves the scope to the run in progress and
+//! reports it as busy. The lock expires with the holder's deadline, so a crashed
+//! run does not block the next one. KV is eventually consistent, which makes this
+//! a best-effort guard against overlaps rather than strict mutual exclusion.
+
+use crate::clock;
+use crate::config::{self, Config, Resource};
+use crate::console::{console_error, console_log};
+use crate::history;
+use crate::maintenance;
+use crate::store::Store;
+use crate::tenant::Scope;
+use crate::trace;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// KV key of the carry-over cursor, relative to the scope prefix
+const CURSOR_KEY: &str = "run/cursor";
+
+/// KV key of the run lock, relative to the scope prefix
+const LOCK_KEY: &str = "run/lock";
+
+/// Time a lock outlives the deadline of its run, covering the work after the checks
+const LOCK_GRACE_MS: u64 = 30_000;
+
+/// Default time budget of a run, leaving a margin below the 30 s limit
+const DEFAULT_DEADLINE_MS: u64 = 25_000;
+
+/// Default number of checks in flight (Workers allow 6 simultaneous connections)
+const DEFAULT_CONCURRENCY: usize = 6;
+
+/// Default time a check waits for a response, well within the run deadline
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+
+/// Time budget of a run
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    started_ms: u64,
+    budget_ms: u64,
+}
+
+impl Deadline {
+    pub fn new(started_ms: u64, budget_ms: u64) -> Self {
+        Self {
+            started_ms,
+            budget_ms,
+        }
+    }
+
+    /// Start a run now with the budget from `RUN_DEADLINE_MS`
+    pub fn from_env(env: &Env) -> Self {
+        let budget_ms = config::setting(env, "RUN_DEADLINE_MS")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_DEADLINE_MS);
+        Self::new(clock::now_ms(), budget_ms)
+    }
+
+    /// Whether the budget is used up at the given time
+    pub fn expired_at(&self, now_ms: u64) -> bool {
+        now_ms.saturating_sub(self.started_ms) >= self.budget_ms
+    }
+
+    /// Whether the budget is used up
+    pub fn expired(&self) -> bool {
+        self.expired_at(clock::now_ms())
+    }
+
+    /// When the budget is used up
+    pub fn ends_at(&self) -> u64 {
+        self.started_ms + self.budget_ms
+    }
+}
+
+/// Number of checks in flight from `CHECK_CONCURRENCY`
+pub fn concurrency(env: &Env) -> usize {
+    config::setting(env, "CHECK_CONCURRENCY")
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_CONCURRENCY)
+}
+
+/// Default request timeout of checks from `CHECK_TIMEOUT_MS`, `None` if `0`
+pub fn check_timeout(env: &Env) -> Option<u64> {
+    let timeout_ms = config::setting(env, "CHECK_TIMEOUT_MS")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TIMEOUT_MS);
+    (timeout_ms > 0).then_some(timeout_ms)
+}
+
+/// A resource that may be checked in this run, with its last check time
+struct Candidate<'a> {
+    scope: usize,
+    resource: &'a Resource,
+    last_checked: Option<u64>,
+}
+
+/// Pick the candidates checked longest ago that fit in `budget` subrequests, grouped by scope
+///
+/// Resources never checked come first; ties keep config order. A resource that
+/// does not fit (e.g. a large sitemap) is passed over for smaller ones, and comes
+/// first next time as the one checked longest ago.
+fn select_oldest<'a>(
+    mut candidates: Vec<Candidate<'a>>,
+    budget: usize,
+    scopes: usize,
+) -> Vec<Vec<&'a Resource>> {
+    candidates.sort_by_key(|c| c.last_checked);
+    let mut plan = vec![Vec::new(); scopes];
+    let mut used = 0;
+    for candidate in candidates {
+        let cost = candidate.resource.subrequests();
+        if used + cost <= budget {
+            used += cost;
+            plan[candidate.scope].push(candidate.resource);
+        }
+    }
+    plan
+}
+
+/// Resources of a scope due on the cron trigger of a scheduled run, or all of
+/// them without a trigger, leaving out disabled ones and those in maintenance
+fn due<'a>(config: &Config, scope: &Scope<'a>, cron: Option<&str>, now: u64) -> Vec<&'a Resource> {
+    scope
+        .resources()
+        .iter()
+        .filter(|r| cron.is_none_or(|cron| config.schedule.is_due(r, cron)))
+        .filter(|r| match maintenance::paused(r, now) {
+            Some(reason) => {
+                console_log!("Skipping {} ({}): {}", r.url, scope.label(), reason);
+                false
+            }
+            None => true,
+        })
+        .collect()
+}
+
+/// Select the resources each scope checks in this invocation
+///
+/// Only resources due on the triggering `cron` are considered. Without a
+/// subrequest cap all of them are checked. With one, the latest history entry of
+/// each resource serves as persisted progress: the resources checked longest ago
+/// fill the budget.
+pub async fn plan<'a>(
+    env: &Env,
+    config: &Config,
+    scopes: &[Scope<'a>],
+    cron: Option<&str>,
+) -> Vec<Vec<&'a Resource>> {
+    let now = clock::now_ms();
+    let due: Vec<Vec<&'a Resource>> = scopes.iter().map(|s| due(config, s, cron, now)).collect();
+    if let Some(cron) = cron {
+        let total: usize = scopes.iter().map(|s| s.resources().len()).sum();
+        let planned: usize = due.iter().map(Vec::len).sum();
+        if planned < total {
+            console_log!(
+                "Checking {} of {} resources on cron trigger '{}'",
+                planned,
+                total,
+                cron
+            );
+        }
+    }
+    let Some(budget) = config.schedule.check_budget() else {
+        return due;
+    };
+
+    let mut candidates = Vec::new();
+    for (index, (scope, resources)) in scopes.iter().zip(due).enumerate() {
+        let history = history::load(env, scope).await;
+        candidates.extend(resources.into_iter().map(|resource| Candidate {
+            scope: index,
+            resource,
+            last_checked: history.latest(&resource.url).map(|e| e.at),
+        }));
+    }
+
+    if let Some(cycle) = config.schedule.cycle_minutes {
+        let now = clock::now_ms();
+        let overdue = candidates
+            .iter()
+            .filter(|c| {
+                c.last_checked
+                    .is_some_and(|at| now.saturating_sub(at) > cycle * 60_000)
+            })
+            .count();
+        if overdue > 0 {
+            console_error!(
+                "⚠ {} resources were not checked within {} minutes",
+                overdue,
+                cycle
+            );
+        }
+    }
+
+    let total = candidates.len();
+    let plan = select_oldest(candidates, budget, scopes.len());
+    let planned: usize = plan.iter().map(Vec::len).sum();
+    if planned < total {
+        console_log!(
+            "Checking {} of {} resources this run (subrequest cap)",
+            planned,
+            total
+        );
+    }
+    plan
+}
+
+/// Resources skipped by the previous run of a scope
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cursor {
+    #[serde(default)]
+    pub carried_over: Vec<String>,
+}
+
+impl Cursor {
+    pub async fn load(env: &Env, scope: &Scope<'_>) -> Self {
+        let Some(store) = Store::from_env(env) else {
+            return Self::default();
+        };
+        match store.get(&scope.key(CURSOR_KEY)).await {
+            Ok(cursor) => cursor.unwrap_or_default(),
+            Err(e) => {
+                console_error!("Failed to load run cursor: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Store the skipped resources, or clear the cursor if there are none
+    pub async fn save(&self, env: &Env, scope: &Scope<'_>, previous: &Self) {
+        // Nothing to write on the common path of two complete runs in a row
+        if self.carried_over.is_empty() && previous.carried_over.is_empty() {
+            return;
+        }
+        let Some(store) = Store::from_env(env) else {
+            return;
+        };
+        let key = scope.key(CURSOR_KEY);
+        let saved = if self.carried_over.is_empty() {
+            store.delete(&key).await
+        } else {
+            store.put(&key, self).await
+        };
+        if let Err(e) = saved {
+            console_error!("Failed to save run cursor: {}", e);
+        }
+    }
+
+    /// Cursor after a run of `scope` that was given the `planned` resources and
+    /// skipped some of them
+    ///
+    /// Carried-over resources the run did not include, e.g. because they are not
+    /// due on its cron trigger, stay carried over until a run checks them;
+    /// resources no longer configured are dropped.
+    pub fn advance(&self, scope: &Scope<'_>, planned: &[&Resource], skipped: Vec<String>) -> Self {
+        let mut carried_over = skipped;
+        for url in &self.carried_over {
+            let configured = scope.resources().iter().any(|r| &r.url == url);
+            let included = planned.iter().any(|r| &r.url == url);
+            if configured && !included && !carried_over.contains(url) {
+                carried_over.push(url.clone());
+            }
+        }
+        Self { carried_over }
+    }
+
+    /// Order resources so that the ones carried over are checked first
+    pub fn order<'a>(
+        &self,
+        resources: impl IntoIterator<Item = &'a Resource>,
+    ) -> Vec<&'a Resource> {
+        let (mut first, rest): (Vec<_>, Vec<_>) = resources
+            .into_iter()
+            .partition(|r| self.carried_over.contains(&r.url));
+        first.extend(rest);
+        first
+    }
+}
+
+/// Marker of a run checking a scope
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunLock {
+    pub run_id: String,
+    pub started_at: u64,
+    pub expires_at: u64,
+}
+
+impl RunLock {
+    fn new(deadline: &Deadline) -> Self {
+        Self {
+            run_id: trace::current().unwrap_or_else(trace::new_id),
+            started_at: clock::now_ms(),
+            expires_at: deadline.ends_at() + LOCK_GRACE_MS,
+        }
+    }
+
+    /// Whether the lock still keeps other runs out at the given time
+    fn held_at(&self, now_ms: u64) -> bool {
+        now_ms < self.expires_at
+    }
+
+    /// Take the lock of a scope for a run with the given deadline
+    ///
+    /// Returns the lock of the run already checking the scope if there is one.
+    /// Without KV, or if the lock cannot be read or written, the run goes ahead.
+    pub async fn acquire(
+        env: &Env,
+        scope: &Scope<'_>,
+        deadline: &Deadline,
+    ) -> std::result::Result<Self, Self> {
+        let lock = Self::new(deadline);
+        let Some(store) = Store::from_env(env) else {
+            return Ok(lock);
+        };
+        let key = scope.key(LOCK_KEY);
+        match store.get::<Self>(&key).await {
+            Ok(Some(held)) if held.held_at(lock.started_at) => return Err(held),
+            Ok(_) => {}
+            Err(e) => console_error!("Failed to read run lock: {}", e),
+        }
+        let ttl_secs = lock.expires_at.saturating_sub(lock.started_at) / 1000;
+        if let Err(e) = store.put_with_ttl(&key, &lock, ttl_secs).await {
+            console_error!("Failed to write run lock: {}", e);
+            return Ok(lock);
+        }
+        // Two runs that both found the scope free: the last write wins
+        match store.get::<Self>(&key).await {
+            Ok(Some(held)) if held.run_id != lock.run_id => Err(held),
+            _ => Ok(lock),
+        }
+    }
+
+    /// Release the lock, unless another run has taken it over since
+    pub async fn release(&self, env: &Env, scope: &Scope<'_>) {
+        let Some(store) = Store::from_env(env) else {
+            return;
+        };
+        let key = scope.key(LOCK_KEY);
+        let released = match store.get::<Self>(&key).await {
+            Ok(Some(held)) if held.run_id == self.run_id => store.delete(&key).await,
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = released {
+            console_error!("Failed to release run lock: {}", e);
+        }
+    }
+}
+
+/// Outcome of a run of one or more scopes
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub checked: usize,
+    pub problems: usize,
+    /// Resources not checked because the deadline passed
+    pub skipped: Vec<String>,
+    /// Resources with alerts still firing after the run
+    pub firing: Vec<String>,
+    /// Scopes left to another run in progress, as `label (run ID)`
+    pub busy: Vec<String>,
+}
+
+impl RunSummary {
+    pub fn merge(&mut self, other: RunSummary) {
+        self.checked += other.checked;
+        self.problems += other.problems;
+        self.skipped.extend(other.skipped);
+        self.firing.extend(other.firing);
+        self.busy.extend(other.busy);
+    }
+
+    /// One-line summary for logs and the `/check` response
+    pub fn describe(&self) -> String {
+        let mut line = format!(
+            "{}/{} successful, {} failed",
+            self.checked - self.problems,
+            self.checked,
+            self.problems
+        );
+        if !self.skipped.is_empty() {
+            line.push_str(&format!(
+                "; truncated: {} skipped near the deadline, carried over to the next run",
+                self.skipped.len()
+            ));
+        }
+        if !self.busy.is_empty() {
+            line.push_str(&format!(
+                "; already being checked: {}",
+                self.busy.join(", ")
+            ));
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sitemap::Sitemap;
+
+    fn resource(url: &str) -> Resource {
+        Resource {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        let deadline = Deadline::new(1_000, 25_000);
+        assert!(!deadline.expired_at(1_000));
+        assert!(!deadline.expired_at(25_999));
+        assert!(deadline.expired_at(26_000));
+        // A clock that did not advance never expires the budget early
+        assert!(!deadline.expired_at(0));
+    }
+
+    #[test]
+    fn test_cursor_order() {
+        let resources = [resource("a"), resource("b"), resource("c")];
+        let cursor = Cursor {
+            carried_over: vec!["c".to_string(), "gone".to_string()],
+        };
+        let order: Vec<_> = cursor
+            .order(&resources)
+            .iter()
+            .map(|r| r.url.as_str())
+            .collect();
+        assert_eq!(order, vec!["c", "a", "b"]);
+
+        let order: Vec<_> = Cursor::default()
+            .order(&resources)
+            .iter()
+            .map(|r| r.url.as_str())
+            .collect();
+        assert_eq!(order, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_cursor_advance() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [schedule]
+            default_cron = "*/5 * * * *"
+
+            [[resources]]
+            url = "https://example.com/app.js"
+            sri = "sha384-abc"
+
+            [[resources]]
+            url = "https://example.com/release.tar.gz"
+            sri = "sha384-abc"
+            cron = "0 * * * *"
+            "#,
+        )
+        .unwrap();
+        let scope = Scope::root(&config);
+        let now = clock::now_ms();
+        let cursor = Cursor {
+            carried_over: vec![
+                "https://example.com/release.tar.gz".to_string(),
+                "https://example.com/app.js".to_string(),
+                "https://example.com/removed.js".to_string(),
+            ],
+        };
+
+        // The frequent trigger checks app.js; the hourly resource stays carried over
+        let planned = cursor.order(due(&config, &scope, Some("*/5 * * * *"), now));
+        let cursor = cursor.advance(&scope, &planned, Vec::new());
+        assert_eq!(cursor.carried_over, ["https://example.com/release.tar.gz"]);
+
+        // ...until the hourly trigger checks it first
+        let planned = cursor.order(due(&config, &scope, Some("0 * * * *"), now));
+        assert_eq!(planned[0].url, "https://example.com/release.tar.gz");
+        let cursor = cursor.advance(&scope, &planned, Vec::new());
+        assert!(cursor.carried_over.is_empty());
+
+        // Resources skipped at the deadline are carried over as before
+        let planned = cursor.order(due(&config, &scope, Some("*/5 * * * *"), now));
+        let cursor = cursor.advance(&scope, &planned, vec![planned[0].url.clone()]);
+        assert_eq!(cursor.carried_over, ["https://example.com/app.js"]);
+    }
+
+    #[test]
+    fn test_select_oldest() {
+        let resources = [resource("a"), resource("b"), resource("c"), resource("d")];
+        let candidates = vec![
+            Candidate {
+                scope: 0,
+                resource: &resources[0],
+                last_checked: Some(300),
+            },
+            Candidate {
+                scope: 0,
+                resource: &resources[1],
+                last_checked: Some(100),
+            },
+            Candidate {
+                scope: 1,
+                resource: &resources[2],
+                last_checked: None,
+            },
+            Candidate {
+                scope: 1,
+                resource: &resources[3],
+                last_checked: Some(200),
+            },
+        ];
+        let plan = select_oldest(candidates, 3, 2);
+        let urls: Vec<Vec<&str>> = plan
+            .iter()
+            .map(|scope| scope.iter().map(|r| r.url.as_str()).collect())
+            .collect();
+        assert_eq!(urls, vec![vec!["b"], vec!["c", "d"]]);
+
+        // A sitemap costs a subrequest per page and is passed over when it does not fit
+        let sitemap = Resource {
+            sitemap: Some(Sitemap { max_urls: 2 }),
+            ..resource("sitemap")
+        };
+        let candidate = |resource, last_checked| Candidate {
+            scope: 0,
+            resource,
+            last_checked,
+        };
+        let plan = select_oldest(
+            vec![
+                candidate(&resources[0], Some(100)),
+                candidate(&sitemap, Some(200)),
+                candidate(&resources[1], Some(300)),
+            ],
+            3,
+            1,
+        );
+        let urls: Vec<&str> = plan[0].iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(urls, vec!["a", "b"]);
+        let plan = select_oldest(vec![candidate(&sitemap, None)], 3, 1);
+        assert_eq!(plan[0].len(), 1);
+    }
+
+    #[test]
+    fn test_due() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [schedule]
+            default_cron = "*/5 * * * *"
+
+            [[resources]]
+            url = "https://example.com/app.js"
+            sri = "sha384-abc"
+
+            [[resources]]
+            url = "https://example.com/release.tar.gz"
+            sri = "sha384-abc"
+            cron = "0  * * * *"
+            "#,
+        )
+        .unwrap();
+        let scope = Scope::root(&config);
+        let urls = |cron| -> Vec<&str> {
+            due(&config, &scope, cron, clock::now_ms())
+                .iter()
+                .map(|r| r.url.as_str())
+                .collect()
+        };
+        assert_eq!(urls(Some("*/5 * * * *")), ["https://example.com/app.js"]);
+        assert_eq!(
+            urls(Some("0 * * * *")),
+            ["https://example.com/release.tar.gz"]
+        );
+        assert!(urls(Some("0 0 * * *")).is_empty());
+        // Manual checks cover every resource
+        assert_eq!(urls(None).len(), 2);
+
+        // Without default_cron, resources without their own run on every trigger
+        let mut config = config.clone();
+        config.schedule.default_cron = None;
+        let scope = Scope::root(&config);
+        let now = clock::now_ms();
+        assert_eq!(due(&config, &scope, Some("0 * * * *"), now).len(), 2);
+        assert_eq!(due(&config, &scope, Some("*/5 * * * *"), now).len(), 1);
+
+        // Disabled resources and those in maintenance are left out, manual runs too
+        config.resources[0].enabled = Some(false);
+        config.resources[1].maintenance = vec!["0 9 * * * 2h".to_string()];
+        let scope = Scope::root(&config);
+        assert!(due(&config, &scope, None, now).is_empty());
+        assert_eq!(due(&config, &scope, None, now + 2 * 3_600_000).len(), 1);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut summary = RunSummary {
+            checked: 3,
+            problems: 1,
+            skipped: vec![],
+            firing: vec![],
+            busy: vec![],
+        };
+        assert_eq!(summary.describe(), "2/3 successful, 1 failed");
+
+        summary.merge(RunSummary {
+            checked: 1,
+            problems: 0,
+            skipped: vec!["https://example.com/a.js".to_string()],
+            firing: vec![],
+            busy: vec![],
+        });
+        assert_eq!(
+            summary.describe(),
+            "3/4 successful, 1 failed; truncated: 1 skipped near the deadline, carried over to the next run"
+        );
+
+        summary.merge(RunSummary {
+            busy: vec!["web (run abc)".to_string()],
+            ..Default::default()
+        });
+        assert!(summary
+            .describe()
+            .ends_with("; already being checked: web (run abc)"));
+    }
+
+    #[test]
+    fn test_run_lock_expiry() {
+        let deadline = Deadline::new(1_000, 25_000);
+        let lock = RunLock::new(&deadline);
+        assert_eq!(lock.expires_at, 56_000);
+        assert!(lock.held_at(1_000));
+        assert!(lock.held_at(55_999));
+        assert!(!lock.held_at(56_000));
+    }
+}
@@ -0,0 +1,104 @@
+//! Runbook links of alerts
+//!
+//! A resource names its runbook by a slug, and `RUNBOOK_BASE_URL` turns it into
+//! the link shown in its alerts, so a wiki move is one variable change instead
+//! of an edit of every config entry:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://cdn.example.com/app.js"
+//! runbook = "cdn-app-js"  # https://wiki.corp/runbooks/cdn-app-js
+//! ```
+//!
+//! The slug is appended to the base URL, or replaces `{{slug}}` in it for wikis
+//! that take the page as a query parameter. A full `https://` URL is used as it
+//! is, without the base.
+
+use crate::config::{self, Resource};
+use crate::template;
+use worker::Env;
+
+/// Placeholder of the slug in `RUNBOOK_BASE_URL`
+const SLUG_PLACEHOLDER: &str = "slug";
+
+/// Whether a runbook reference is a complete link rather than a slug
+fn is_link(runbook: &str) -> bool {
+    runbook.starts_with("https://") || runbook.starts_with("http://")
+}
+
+/// Check a resource's `runbook`: a link, or a slug of URL path characters
+pub fn validate(runbook: &str) -> Result<(), String> {
+    if is_link(runbook) {
+        return Ok(());
+    }
+    let slug = |c: char| c.is_ascii_alphanumeric() || "-._~/".contains(c);
+    if runbook.is_empty() || !runbook.chars().all(slug) {
+        return Err(format!(
+            "runbook '{}' is neither a link nor a slug of letters, digits, '-', '.', '_', '~' and '/'",
+            runbook
+        ));
+    }
+    Ok(())
+}
+
+/// Link of a runbook reference against a base URL
+fn resolve(base: Option<&str>, runbook: &str) -> Option<String> {
+    if is_link(runbook) {
+        return Some(runbook.to_string());
+    }
+    let base = base?;
+    if template::placeholders(base).any(|p| p == SLUG_PLACEHOLDER) {
+        return Some(template::render(base, |name| {
+            (name == SLUG_PLACEHOLDER).then(|| runbook.to_string())
+        }));
+    }
+    Some(format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        runbook.trim_start_matches('/')
+    ))
+}
+
+/// Runbook link of a resource, if it names one and it can be resolved
+pub fn link(env: &Env, resource: &Resource) -> Option<String> {
+    let runbook = resource.runbook.as_deref()?;
+    resolve(config::setting(env, "RUNBOOK_BASE_URL").as_deref(), runbook)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let base = Some("https://wiki.corp/runbooks/");
+        assert_eq!(
+            resolve(base, "cdn-app-js").as_deref(),
+            Some("https://wiki.corp/runbooks/cdn-app-js")
+        );
+        assert_eq!(
+            resolve(
+                Some("https://wiki.corp/view?page={{ slug }}&lang=en"),
+                "cdn/app.js"
+            )
+            .as_deref(),
+            Some("https://wiki.corp/view?page=cdn/app.js&lang=en")
+        );
+        assert_eq!(
+            resolve(base, "https://docs.example.com/app").as_deref(),
+            Some("https://docs.example.com/app")
+        );
+        // A slug needs the base
+        assert_eq!(resolve(None, "cdn-app-js"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("cdn-app-js").is_ok());
+        assert!(validate("team/cdn_app.v2").is_ok());
+        assert!(validate("https://docs.example.com/app").is_ok());
+        assert!(validate("").is_err());
+        assert!(validate("cdn app").is_err());
+        assert!(validate("a?b=c").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Config snippets for new resources (`GET /config/template?urls=`)
+//!
+//! Onboarding a batch of assets means fetching each one, hashing it and typing
+//! out its `[[resources]]` entry. The endpoint does that for up to `MAX_URLS`
+//! URLs, separated by commas or given as repeated `urls` parameters, and returns
+//! a block ready to paste into config.toml:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://cdn.example.com/widget.js"
+//! sri = "sha384-..."
+//! ```
+//!
+//! URLs that cannot be fetched, answer with an error status or are already
+//! configured are listed as comments instead, so the block can be pasted as is.
+
+use crate::config::{self, Resource};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse, WorkerFetcher};
+use crate::sri;
+use crate::tenant::Scope;
+use worker::*;
+
+/// Most URLs per request, keeping well below the subrequest limit
+const MAX_URLS: usize = 20;
+
+/// Quote a string as a TOML basic string (JSON string escapes are valid TOML)
+fn quote(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+/// Entry of one URL: a `[[resources]]` block or a comment explaining why there is none
+async fn entry(fetcher: &impl Fetcher, url: &str, configured: &[Resource]) -> String {
+    let url = match config::normalize_url(url) {
+        Ok(url) => url,
+        Err(e) => return format!("# {}", e),
+    };
+    let body = async {
+        let mut response = fetcher.fetch(HttpRequest::new(Method::Get, &url)).await?;
+        let status = response.status_code();
+        if !(200..300).contains(&status) {
+            return Err(Error::RustError(format!("HTTP {}", status)));
+        }
+        response.bytes().await
+    };
+    let integrity = match body.await {
+        Ok(body) => sri::sha384(&body),
+        Err(e) => return format!("# {}: {}", url, e),
+    };
+    match configured.iter().find(|r| r.url == url) {
+        Some(r) if r.sri == integrity => format!("# {}: already configured", url),
+        Some(_) => format!(
+            "# {}: already configured with a different SRI hash, now {}",
+            url, integrity
+        ),
+        None => format!(
+            "[[resources]]\nurl = {}\nsri = {}",
+            quote(&url),
+            quote(&integrity)
+        ),
+    }
+}
+
+/// TOML block with an entry per URL, in the order given
+async fn generate(fetcher: &impl Fetcher, urls: &[String], configured: &[Resource]) -> String {
+    let mut entries = Vec::with_capacity(urls.len());
+    for url in urls {
+        entries.push(entry(fetcher, url, configured).await);
+    }
+    entries.join("\n\n") + "\n"
+}
+
+/// URLs of the `urls` query parameters, split at commas
+fn requested_urls(url: &Url) -> Vec<String> {
+    url.query_pairs()
+        .filter(|(k, _)| k == "urls")
+        .flat_map(|(_, v)| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|u| !u.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Handle `GET /config/template?urls=`
+pub async fn handle_template(scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let urls = requested_urls(&req.url()?);
+    if urls.is_empty() {
+        return Response::error("Expected 'urls' to generate resources for", 400);
+    }
+    if urls.len() > MAX_URLS {
+        return Response::error(format!("At most {} URLs per request", MAX_URLS), 400);
+    }
+    let toml = generate(&WorkerFetcher, &urls, scope.resources()).await;
+    let mut response = Response::ok(toml)?;
+    response
+        .headers_mut()
+        .set("Content-Type", "application/toml; charset=utf-8")?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_generate() {
+        let config = Config::from_toml(&format!(
+            r#"
+version = "1.0"
+[[resources]]
+url = "https://cdn.example.com/a.js"
+sri = "{}"
+"#,
+            sri::sha384(b"a")
+        ))
+        .unwrap();
+        let fetcher = MockFetcher::new()
+            .respond("https://cdn.example.com/a.js", MockResponse::new(200, "a"))
+            .respond(
+                "https://cdn.example.com/new.js",
+                MockResponse::new(200, "new"),
+            )
+            .respond(
+                "https://cdn.example.com/gone.js",
+                MockResponse::new(404, ""),
+            );
+        let urls: Vec<String> = [
+            "https://CDN.example.com/new.js",
+            "https://cdn.example.com/a.js",
+            "https://cdn.example.com/gone.js",
+            "not a url",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        let toml = block_on(generate(&fetcher, &urls, &config.resources));
+        assert_eq!(
+            toml,
+            format!(
+                "[[resources]]\nurl = \"https://cdn.example.com/new.js\"\nsri = \"{}\"\n\n\
+                 # https://cdn.example.com/a.js: already configured\n\n\
+                 # https://cdn.example.com/gone.js: HTTP 404\n\n\
+                 # Invalid URL 'not a url': relative URL without a base\n",
+                sri::sha384(b"new")
+            )
+        );
+        // The generated block is valid config
+        let block = toml.split("\n\n").next().unwrap();
+        let parsed = Config::from_toml(&format!("version = \"1.0\"\n{}", block)).unwrap();
+        assert_eq!(parsed.resources[0].sri, sri::sha384(b"new"));
+    }
+
+    #[test]
+    fn test_requested_urls() {
+        let url = Url::parse(
+            "https://w.example/config/template?urls=https://a.example/x.js,%20https://a.example/y.js&urls=https://b.example/z.js",
+        )
+        .unwrap();
+        assert_eq!(
+            requested_urls(&url),
+            [
+                "https://a.example/x.js",
+                "https://a.example/y.js",
+                "https://b.example/z.js"
+            ]
+        );
+    }
+}
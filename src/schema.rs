@@ -0,0 +1,514 @@
+//! JSON Schema validation of JSON documents
+//!
+//! Config and manifest URLs that change legitimately cannot be pinned with an
+//! SRI hash, but a malformed publish can still be caught by validating the
+//! document against a schema. The schema is given inline or by the name of a KV
+//! entry, stored with `wrangler kv key put` under `schemas/{name}`:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://example.com/manifest.json"
+//! expected_response_contains = "\"version\""
+//! json_schema = { type = "object", required = ["version", "files"] }
+//!
+//! [[resources]]
+//! url = "https://example.com/feed.json"
+//! expected_response_contains = "\"items\""
+//! json_schema = "feed"
+//! ```
+//!
+//! Only a subset of JSON Schema is understood, enough for structural checks:
+//! `type`, `enum`, `const`, `required`, `properties`, `additionalProperties`,
+//! `items`, `minItems`, `maxItems`, `minLength`, `maxLength`, `pattern`,
+//! `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`, `allOf` and
+//! `anyOf`. Other keywords, such as `$ref` or `format`, are ignored. A document
+//! that does not validate fails its availability check with the first violated
+//! constraint, e.g. `/files/0/size: expected integer, got string`.
+
+use crate::checker::{CheckError, CheckResult};
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::store::Store;
+use crate::tenant::Scope;
+use regex_lite::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use worker::*;
+
+/// KV key prefix of stored schemas, relative to the scope prefix
+const SCHEMA_PREFIX: &str = "schemas/";
+
+/// Schema a resource's document must match (`json_schema`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SchemaSource {
+    /// Name of a schema stored in KV under `schemas/{name}`
+    Stored(String),
+    /// Schema written out in the config
+    Inline(Map<String, Value>),
+}
+
+impl SchemaSource {
+    /// Check the constraints the TOML schema cannot express
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        match self {
+            Self::Stored(name) if name.is_empty() || name.contains('/') => {
+                Err(format!("Invalid json_schema name '{}' of {}", name, url))
+            }
+            Self::Stored(_) => Ok(()),
+            Self::Inline(schema) => check_schema(&Value::Object(schema.clone()))
+                .map_err(|e| format!("Invalid json_schema of {}: {}", url, e)),
+        }
+    }
+}
+
+/// A constraint the document does not satisfy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// JSON Pointer to the offending value, empty for the document itself
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Name of a JSON value's type, as used by `type`
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Whether a value has a type named in a schema
+fn has_type(value: &Value, name: &str) -> bool {
+    let actual = type_name(value);
+    actual == name
+        || (name == "number" && actual == "integer")
+        || (name == "integer" && value.as_f64().is_some_and(|n| n.fract() == 0.0))
+}
+
+/// Escape a key for a JSON Pointer
+fn pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Check that a schema only uses keywords with values of the right shape
+///
+/// Catches mistakes such as an invalid `pattern` when the config is loaded
+/// rather than on every run.
+pub fn check_schema(schema: &Value) -> std::result::Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        return match schema {
+            Value::Bool(_) => Ok(()),
+            _ => Err("schema must be an object".to_string()),
+        };
+    };
+    if let Some(pattern) = schema.get("pattern") {
+        let pattern = pattern.as_str().ok_or("pattern must be a string")?;
+        Regex::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+    }
+    if let Some(properties) = schema.get("properties") {
+        let properties = properties
+            .as_object()
+            .ok_or("properties must be an object")?;
+        properties.values().try_for_each(check_schema)?;
+    }
+    for keyword in ["items", "additionalProperties"] {
+        if let Some(sub) = schema.get(keyword) {
+            check_schema(sub)?;
+        }
+    }
+    for keyword in ["allOf", "anyOf"] {
+        if let Some(subs) = schema.get(keyword) {
+            let subs = subs
+                .as_array()
+                .ok_or_else(|| format!("{} must be an array", keyword))?;
+            subs.iter().try_for_each(check_schema)?;
+        }
+    }
+    Ok(())
+}
+
+/// Validate a document against a schema, returning the first violation
+pub fn validate(schema: &Value, value: &Value) -> std::result::Result<(), Violation> {
+    validate_at(schema, value, "")
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str) -> std::result::Result<(), Violation> {
+    let fail = |message: String| {
+        Err(Violation {
+            path: path.to_string(),
+            message,
+        })
+    };
+    let schema = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return fail("no value is allowed here".to_string()),
+        Value::Object(schema) => schema,
+        _ => return Ok(()),
+    };
+    let number = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+    let count = |keyword: &str| schema.get(keyword).and_then(Value::as_u64);
+
+    match schema.get("type") {
+        Some(Value::String(name)) if !has_type(value, name) => {
+            return fail(format!("expected {}, got {}", name, type_name(value)));
+        }
+        Some(Value::Array(names)) => {
+            let names: Vec<&str> = names.iter().filter_map(Value::as_str).collect();
+            if !names.iter().any(|name| has_type(value, name)) {
+                return fail(format!(
+                    "expected {}, got {}",
+                    names.join(" or "),
+                    type_name(value)
+                ));
+            }
+        }
+        _ => {}
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return fail(format!("{} is not one of the allowed values", value));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return fail(format!("expected {}, got {}", expected, value));
+        }
+    }
+
+    match value {
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = number("minimum").filter(|&min| n < min) {
+                return fail(format!("{} is less than the minimum {}", n, min));
+            }
+            if let Some(max) = number("maximum").filter(|&max| n > max) {
+                return fail(format!("{} is greater than the maximum {}", n, max));
+            }
+            if let Some(min) = number("exclusiveMinimum").filter(|&min| n <= min) {
+                return fail(format!("{} is not greater than {}", n, min));
+            }
+            if let Some(max) = number("exclusiveMaximum").filter(|&max| n >= max) {
+                return fail(format!("{} is not less than {}", n, max));
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = count("minLength").filter(|&min| len < min) {
+                return fail(format!(
+                    "string of {} characters, minLength is {}",
+                    len, min
+                ));
+            }
+            if let Some(max) = count("maxLength").filter(|&max| len > max) {
+                return fail(format!(
+                    "string of {} characters, maxLength is {}",
+                    len, max
+                ));
+            }
+            if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                if Regex::new(pattern).is_ok_and(|re| !re.is_match(s)) {
+                    return fail(format!("string does not match pattern '{}'", pattern));
+                }
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as u64;
+            if let Some(min) = count("minItems").filter(|&min| len < min) {
+                return fail(format!("array of {} items, minItems is {}", len, min));
+            }
+            if let Some(max) = count("maxItems").filter(|&max| len > max) {
+                return fail(format!("array of {} items, maxItems is {}", len, max));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}/{}", path, index))?;
+                }
+            }
+        }
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                if let Some(missing) = required
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .find(|key| !object.contains_key(*key))
+                {
+                    return fail(format!("missing required property '{}'", missing));
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, item) in object {
+                let item_path = format!("{}/{}", path, pointer_token(key));
+                match properties.and_then(|p| p.get(key)) {
+                    Some(property) => validate_at(property, item, &item_path)?,
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            return fail(format!("unexpected property '{}'", key));
+                        }
+                        Some(additional) => validate_at(additional, item, &item_path)?,
+                        None => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(Value::Array(subs)) = schema.get("allOf") {
+        for sub in subs {
+            validate_at(sub, value, path)?;
+        }
+    }
+    if let Some(Value::Array(subs)) = schema.get("anyOf") {
+        if !subs.iter().any(|sub| validate_at(sub, value, path).is_ok()) {
+            if let Some(Err(first)) = subs.first().map(|sub| validate_at(sub, value, path)) {
+                return fail(format!("matches none of anyOf, e.g. {}", first.message));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate a response body against a schema, `Err` with the failure description
+pub fn validate_body(schema: &Value, body: &str) -> std::result::Result<(), String> {
+    let document: Value =
+        serde_json::from_str(body).map_err(|e| format!("Response is not valid JSON: {}", e))?;
+    validate(schema, &document).map_err(|v| format!("Schema violation at {}", v))
+}
+
+/// Schemas used by a run, loading stored ones once per run
+pub struct Schemas<'a> {
+    store: Option<Store>,
+    scope: &'a Scope<'a>,
+    stored: HashMap<String, Option<Value>>,
+}
+
+impl<'a> Schemas<'a> {
+    pub fn new(env: &Env, scope: &'a Scope<'a>) -> Self {
+        Self {
+            store: Store::from_env(env),
+            scope,
+            stored: HashMap::new(),
+        }
+    }
+
+    /// Schema of a resource, `None` if it has none or the stored one is missing
+    async fn schema(&mut self, source: &SchemaSource) -> Option<Value> {
+        let name = match source {
+            SchemaSource::Inline(schema) => return Some(Value::Object(schema.clone())),
+            SchemaSource::Stored(name) => name,
+        };
+        if let Some(schema) = self.stored.get(name) {
+            return schema.clone();
+        }
+        let key = self.scope.key(&format!("{}{}", SCHEMA_PREFIX, name));
+        let schema = match &self.store {
+            Some(store) => match store.get::<Value>(&key).await {
+                Ok(Some(schema)) => match check_schema(&schema) {
+                    Ok(()) => Some(schema),
+                    Err(e) => {
+                        console_error!("Ignoring invalid schema '{}': {}", name, e);
+                        None
+                    }
+                },
+                Ok(None) => {
+                    console_error!("Schema '{}' not found in KV ({})", name, key);
+                    None
+                }
+                Err(e) => {
+                    console_error!("Failed to load schema '{}': {}", name, e);
+                    None
+                }
+            },
+            None => {
+                console_error!("Schema '{}' needs the LINKKIVAHTI_STATE KV namespace", name);
+                None
+            }
+        };
+        self.stored.insert(name.clone(), schema.clone());
+        schema
+    }
+
+    /// Validate the body of a result against its resource's schema
+    ///
+    /// A result that already failed is left alone; a schema that cannot be
+    /// loaded is logged and skipped rather than failing the resource.
+    pub async fn check(&mut self, resource: &Resource, result: &mut CheckResult) {
+        let Some(source) = &resource.json_schema else {
+            return;
+        };
+        if result.has_problem() {
+            return;
+        }
+        let Some(schema) = self.schema(source).await else {
+            return;
+        };
+        let outcome = match &result.body {
+            Some(body) => validate_body(&schema, body),
+            None => Err("Response is not UTF-8 text".to_string()),
+        };
+        match outcome {
+            Ok(()) => console_log!("✓ {} - matches its schema", result.url),
+            Err(detail) => {
+                console_error!("✗ {} - {}", result.url, detail);
+                result.success = false;
+                result.error = Some(CheckError::SchemaViolation);
+                result.message = Some(detail);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["version", "files"],
+            "additionalProperties": false,
+            "properties": {
+                "version": { "type": "string", "pattern": "^\\d+\\.\\d+\\.\\d+$" },
+                "files": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "required": ["path"],
+                        "properties": {
+                            "path": { "type": "string", "minLength": 1 },
+                            "size": { "type": "integer", "minimum": 0 }
+                        }
+                    }
+                },
+                "channel": { "enum": ["stable", "beta"] },
+                "a/b": { "type": ["string", "null"] }
+            }
+        })
+    }
+
+    fn violation(document: Value) -> String {
+        validate(&schema(), &document).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = json!({
+            "version": "1.2.3",
+            "files": [{ "path": "app.js", "size": 10 }],
+            "channel": "beta",
+            "a/b": null
+        });
+        assert_eq!(validate(&schema(), &valid), Ok(()));
+
+        assert_eq!(violation(json!([])), "/: expected object, got array");
+        assert_eq!(
+            violation(json!({ "version": "1.2.3" })),
+            "/: missing required property 'files'"
+        );
+        assert_eq!(
+            violation(json!({ "version": "1.2", "files": [{ "path": "a" }] })),
+            "/version: string does not match pattern '^\\d+\\.\\d+\\.\\d+$'"
+        );
+        assert_eq!(
+            violation(json!({ "version": "1.2.3", "files": [] })),
+            "/files: array of 0 items, minItems is 1"
+        );
+        assert_eq!(
+            violation(json!({ "version": "1.2.3", "files": [{ "path": "a", "size": "1" }] })),
+            "/files/0/size: expected integer, got string"
+        );
+        assert_eq!(
+            violation(json!({ "version": "1.2.3", "files": [{ "path": "a", "size": -1 }] })),
+            "/files/0/size: -1 is less than the minimum 0"
+        );
+        assert_eq!(
+            violation(json!({ "version": "1.2.3", "files": [{ "path": "a" }], "a/b": 1 })),
+            "/a~1b: expected string or null, got integer"
+        );
+        assert_eq!(
+            violation(json!({ "version": "1.2.3", "files": [{ "path": "a" }], "channel": "x" })),
+            "/channel: \"x\" is not one of the allowed values"
+        );
+        assert_eq!(
+            violation(json!({ "version": "1.2.3", "files": [{ "path": "a" }], "extra": 1 })),
+            "/: unexpected property 'extra'"
+        );
+    }
+
+    #[test]
+    fn test_any_of_and_numbers() {
+        let schema = json!({ "anyOf": [{ "type": "string" }, { "type": "number", "exclusiveMaximum": 10 }] });
+        assert!(validate(&schema, &json!("a")).is_ok());
+        assert!(validate(&schema, &json!(9.5)).is_ok());
+        assert_eq!(
+            validate(&schema, &json!(10)).unwrap_err().message,
+            "matches none of anyOf, e.g. expected string, got integer"
+        );
+        assert!(validate(&json!({ "type": "integer" }), &json!(2.0)).is_ok());
+    }
+
+    #[test]
+    fn test_validate_body() {
+        assert!(validate_body(&schema(), "{")
+            .unwrap_err()
+            .starts_with("Response is not valid JSON"));
+        assert_eq!(
+            validate_body(
+                &schema(),
+                "{\"version\": 1, \"files\": [{\"path\": \"a\"}]}"
+            ),
+            Err("Schema violation at /version: expected string, got integer".to_string())
+        );
+    }
+
+    #[test]
+    fn test_schema_source() {
+        let resource: Resource = toml::from_str(
+            "url = \"https://example.com/m.json\"\njson_schema = { type = \"object\", required = [\"v\"] }",
+        )
+        .unwrap();
+        let Some(SchemaSource::Inline(inline)) = &resource.json_schema else {
+            panic!("expected an inline schema");
+        };
+        assert_eq!(inline["required"], json!(["v"]));
+
+        let resource: Resource =
+            toml::from_str("url = \"https://example.com/m.json\"\njson_schema = \"manifest\"")
+                .unwrap();
+        assert_eq!(
+            resource.json_schema,
+            Some(SchemaSource::Stored("manifest".to_string()))
+        );
+
+        let url = "https://example.com/m.json";
+        assert!(SchemaSource::Stored("a/b".to_string())
+            .validate(url)
+            .is_err());
+        let bad = json!({ "properties": { "v": { "pattern": "(" } } });
+        let Value::Object(bad) = bad else {
+            unreachable!()
+        };
+        assert!(SchemaSource::Inline(bad).validate(url).is_err());
+    }
+}
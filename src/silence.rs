@@ -0,0 +1,450 @@
+//! Silences with Alertmanager-style label matchers
+//!
+//! A silence suppresses the notifications of every alert whose labels match all of
+//! its matchers until it expires, e.g. a whole CDN during a known migration:
+//!
+//! ```text
+//! POST /silences {"matchers": ["group=cdn", "url=~https://cdn\\.example\\.com/.*"], "duration_minutes": 120}
+//! ```
+//!
+//! Matchers use the Alertmanager syntax `name=value`, `name!=value`, `name=~regex`
+//! and `name!~regex`; regexes are anchored and a missing label matches as empty.
+//! Alert labels are `url`, `name`, `group`, `owner`, `stream`, `tenant` and the
+//! resource's own `labels`. Alert state and incidents are still tracked while
+//! silenced, only the notifications are dropped.
+
+use crate::alert::AlertStream;
+use crate::auth::Principal;
+use crate::clock;
+use crate::config::Resource;
+use crate::console::{console_error, console_log};
+use crate::store::Store;
+use crate::tenant::Scope;
+use regex_lite::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+/// KV key of the silences, relative to the scope prefix
+const SILENCES_KEY: &str = "silences";
+
+const SILENCES_PATH_PREFIX: &str = "/silences/";
+
+const DEFAULT_DURATION_MINUTES: u64 = 60;
+
+/// Longest allowed silence
+const MAX_DURATION_MINUTES: u64 = 30 * 24 * 60;
+
+/// How a matcher compares a label value
+#[derive(Debug, Clone)]
+enum MatchOp {
+    Equal,
+    NotEqual,
+    Regex(Regex),
+    NotRegex(Regex),
+}
+
+/// A label matcher such as `group=cdn` or `url=~.*\.js`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Matcher {
+    name: String,
+    op: MatchOp,
+    value: String,
+}
+
+impl Matcher {
+    /// Whether a label value (empty if the label is missing) matches
+    pub fn matches(&self, value: &str) -> bool {
+        match &self.op {
+            MatchOp::Equal => value == self.value,
+            MatchOp::NotEqual => value != self.value,
+            MatchOp::Regex(re) => re.is_match(value),
+            MatchOp::NotRegex(re) => !re.is_match(value),
+        }
+    }
+
+    /// Whether the matcher's label in a label set matches
+    pub fn matches_labels(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.matches(labels.get(&self.name).map(String::as_str).unwrap_or(""))
+    }
+}
+
+impl std::str::FromStr for Matcher {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let name_len = s
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(s.len());
+        let (name, rest) = s.split_at(name_len);
+        let rest = rest.trim_start();
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(format!("Invalid matcher '{}': expected a label name", s));
+        }
+
+        let (op, raw) = ["=~", "!~", "!=", "="]
+            .into_iter()
+            .find_map(|op| Some((op, rest.strip_prefix(op)?)))
+            .ok_or_else(|| format!("Invalid matcher '{}': expected =, !=, =~ or !~", s))?;
+        let raw = raw.trim();
+        let value = match raw.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+            None => raw.to_string(),
+        };
+
+        let regex = || {
+            Regex::new(&format!("^(?:{})$", value))
+                .map_err(|e| format!("Invalid regex in matcher '{}': {}", s, e))
+        };
+        let op = match op {
+            "=" => MatchOp::Equal,
+            "!=" => MatchOp::NotEqual,
+            "=~" => MatchOp::Regex(regex()?),
+            _ => MatchOp::NotRegex(regex()?),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            op,
+            value,
+        })
+    }
+}
+
+impl std::fmt::Display for Matcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self.op {
+            MatchOp::Equal => "=",
+            MatchOp::NotEqual => "!=",
+            MatchOp::Regex(_) => "=~",
+            MatchOp::NotRegex(_) => "!~",
+        };
+        write!(f, "{}{}{}", self.name, op, self.value)
+    }
+}
+
+impl TryFrom<String> for Matcher {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Matcher> for String {
+    fn from(matcher: Matcher) -> Self {
+        matcher.to_string()
+    }
+}
+
+/// Labels of an alert, as seen by silence matchers
+pub fn labels(
+    scope: &Scope<'_>,
+    resource: Option<&Resource>,
+    url: &str,
+    stream: AlertStream,
+) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    if let Some(resource) = resource {
+        labels.extend(resource.labels.clone());
+        let optional = [
+            ("name", &resource.name),
+            ("group", &resource.group),
+            ("owner", &resource.owner),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                labels.insert(name.to_string(), value.clone());
+            }
+        }
+    }
+    labels.insert("url".to_string(), url.to_string());
+    labels.insert("stream".to_string(), stream.as_str().to_string());
+    if let Some(tenant) = scope.id() {
+        labels.insert("tenant".to_string(), tenant.to_string());
+    }
+    labels
+}
+
+/// A silence of all alerts matching its matchers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Silence {
+    pub id: String,
+    pub matchers: Vec<Matcher>,
+    /// Start and end (milliseconds since the Unix epoch)
+    pub starts_at: u64,
+    pub ends_at: u64,
+    pub created_by: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl Silence {
+    /// Whether the silence is in effect for alerts with these labels
+    pub fn matches(&self, labels: &BTreeMap<String, String>, now: u64) -> bool {
+        (self.starts_at..self.ends_at).contains(&now)
+            && self.matchers.iter().all(|m| m.matches_labels(labels))
+    }
+}
+
+/// Silences of a scope
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Silences {
+    #[serde(default)]
+    pub silences: Vec<Silence>,
+}
+
+impl Silences {
+    /// Load the silences of a scope (empty if none stored yet or unreadable)
+    pub async fn load(store: &Store, scope: &Scope<'_>) -> Self {
+        store
+            .get(&scope.key(SILENCES_KEY))
+            .await
+            .unwrap_or_else(|e| {
+                console_error!("Failed to load silences: {}", e);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    async fn save(&self, store: &Store, scope: &Scope<'_>) -> Result<()> {
+        store.put(&scope.key(SILENCES_KEY), self).await
+    }
+
+    /// First silence in effect for alerts with these labels
+    pub fn find(&self, labels: &BTreeMap<String, String>, now: u64) -> Option<&Silence> {
+        self.silences.iter().find(|s| s.matches(labels, now))
+    }
+
+    /// Drop expired silences
+    fn prune(&mut self, now: u64) {
+        self.silences.retain(|s| s.ends_at > now);
+    }
+}
+
+/// Body of a `POST /silences` request
+#[derive(Debug, Deserialize)]
+pub struct SilenceRequest {
+    #[serde(default)]
+    pub matchers: Vec<Matcher>,
+    /// Shorthand for a `url=<url>` matcher
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub duration_minutes: Option<u64>,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+impl SilenceRequest {
+    /// Build the silence, rejecting requests that would silence everything
+    pub fn into_silence(
+        self,
+        id: String,
+        created_by: &str,
+        now: u64,
+    ) -> std::result::Result<Silence, String> {
+        let mut matchers = self.matchers;
+        if let Some(url) = self.url {
+            matchers.push(Matcher {
+                name: "url".to_string(),
+                op: MatchOp::Equal,
+                value: url,
+            });
+        }
+        // Like Alertmanager, at least one matcher must not match a missing label
+        if !matchers.iter().any(|m| !m.matches("")) {
+            return Err("At least one matcher must require a non-empty label".to_string());
+        }
+        let minutes = self.duration_minutes.unwrap_or(DEFAULT_DURATION_MINUTES);
+        if minutes == 0 || minutes > MAX_DURATION_MINUTES {
+            return Err(format!(
+                "duration_minutes must be between 1 and {}",
+                MAX_DURATION_MINUTES
+            ));
+        }
+        Ok(Silence {
+            id,
+            matchers,
+            starts_at: now,
+            ends_at: now + minutes * 60_000,
+            created_by: created_by.to_string(),
+            comment: self.comment,
+        })
+    }
+}
+
+/// Generate a short, unique-enough identifier for a silence
+pub fn new_silence_id() -> String {
+    let random = (js_sys::Math::random() * f64::from(u32::MAX)) as u32;
+    format!("{:x}{:08x}", clock::now_ms(), random)
+}
+
+/// Parse `/silences/{id}`
+pub fn parse_silence_path(path: &str) -> Option<&str> {
+    path.strip_prefix(SILENCES_PATH_PREFIX)
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+/// Handle `GET /silences`: list silences that have not expired
+pub async fn handle_list(env: &Env, scope: &Scope<'_>) -> Result<Response> {
+    let store = Store::require(env)?;
+    let mut silences = Silences::load(&store, scope).await;
+    silences.prune(clock::now_ms());
+    Response::from_json(&silences.silences)
+}
+
+/// Handle `POST /silences`: silence alerts matching the given matchers
+pub async fn handle_create(
+    env: &Env,
+    scope: &Scope<'_>,
+    principal: &Principal,
+    mut req: Request,
+) -> Result<Response> {
+    let request: SilenceRequest = match req.json().await {
+        Ok(r) => r,
+        Err(e) => return Response::error(format!("Invalid silence: {}", e), 400),
+    };
+    let now = clock::now_ms();
+    let silence = match request.into_silence(new_silence_id(), &principal.name, now) {
+        Ok(silence) => silence,
+        Err(reason) => return Response::error(reason, 400),
+    };
+
+    let store = Store::require(env)?;
+    add(&store, scope, silence.clone()).await?;
+    Ok(Response::from_json(&silence)?.with_status(201))
+}
+
+/// Store a new silence of a scope, dropping expired ones
+pub async fn add(store: &Store, scope: &Scope<'_>, silence: Silence) -> Result<()> {
+    let mut silences = Silences::load(store, scope).await;
+    silences.prune(silence.starts_at);
+    let matchers: Vec<String> = silence.matchers.iter().map(Matcher::to_string).collect();
+    console_log!(
+        "Silence {} ({}) created by {}",
+        silence.id,
+        matchers.join(", "),
+        silence.created_by
+    );
+    silences.silences.push(silence);
+    silences.save(store, scope).await
+}
+
+/// Handle `DELETE /silences/{id}`: expire a silence now
+pub async fn handle_delete(
+    env: &Env,
+    scope: &Scope<'_>,
+    principal: &Principal,
+    id: &str,
+) -> Result<Response> {
+    let store = Store::require(env)?;
+    let mut silences = Silences::load(&store, scope).await;
+    let now = clock::now_ms();
+    silences.prune(now);
+    let Some(index) = silences.silences.iter().position(|s| s.id == id) else {
+        return Response::error("Silence not found", 404);
+    };
+    let silence = silences.silences.remove(index);
+    silences.save(&store, scope).await?;
+    console_log!("Silence {} expired by {}", silence.id, principal.name);
+    Response::from_json(&silence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn matcher(s: &str) -> Matcher {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_matchers() {
+        assert!(matcher("team=web").matches("web"));
+        assert!(!matcher("team=web").matches("ops"));
+        assert!(matcher("team != web").matches(""));
+        assert!(matcher(r"url=~https://cdn\..*").matches("https://cdn.example.com/a.js"));
+        // Regexes are anchored
+        assert!(!matcher(r"url=~cdn\..*").matches("https://cdn.example.com/a.js"));
+        assert!(matcher(r"url!~.*\.css").matches("https://cdn.example.com/a.js"));
+        assert!(matcher(r#"name="Widget \"v2\"""#).matches(r#"Widget "v2""#));
+        assert_eq!(matcher("url=~a|b").to_string(), "url=~a|b");
+
+        for invalid in ["", "=web", "1team=web", "team", "team~web", "url=~("] {
+            assert!(invalid.parse::<Matcher>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_silence_matches() {
+        let config = config::compiled();
+        let scope = Scope::root(&config);
+        let resource = Resource {
+            url: "https://cdn.example.com/a.js".to_string(),
+            group: Some("cdn".to_string()),
+            labels: BTreeMap::from([("team".to_string(), "web".to_string())]),
+            ..Default::default()
+        };
+        let labels = labels(
+            &scope,
+            Some(&resource),
+            &resource.url,
+            AlertStream::Availability,
+        );
+
+        let request: SilenceRequest = serde_json::from_str(
+            r#"{"matchers": ["team=web", "url=~https://cdn\\..*"], "duration_minutes": 10}"#,
+        )
+        .unwrap();
+        let silence = request
+            .into_silence("s1".to_string(), "ops", 1_000)
+            .unwrap();
+        assert!(silence.matches(&labels, 1_000));
+        assert!(!silence.matches(&labels, 1_000 + 10 * 60_000));
+
+        let silences = Silences {
+            silences: vec![silence],
+        };
+        let mut other = labels.clone();
+        other.insert("team".to_string(), "ops".to_string());
+        assert!(silences.find(&other, 2_000).is_none());
+
+        let json = serde_json::to_string(&silences).unwrap();
+        assert!(json.contains(r#""matchers":["team=web","url=~https://cdn\\..*"]"#));
+        let loaded: Silences = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.find(&labels, 2_000).unwrap().id, "s1");
+    }
+
+    #[test]
+    fn test_silence_request() {
+        let request = |json: &str| serde_json::from_str::<SilenceRequest>(json).unwrap();
+
+        let silence = request(r#"{"url": "https://example.com/a.js"}"#)
+            .into_silence("s1".to_string(), "ops", 0)
+            .unwrap();
+        assert_eq!(
+            silence.matchers[0].to_string(),
+            "url=https://example.com/a.js"
+        );
+        assert_eq!(silence.ends_at, DEFAULT_DURATION_MINUTES * 60_000);
+
+        // Matchers that also match missing labels would silence everything
+        assert!(request(r#"{"matchers": ["url=~.*"]}"#)
+            .into_silence("s2".to_string(), "ops", 0)
+            .is_err());
+        assert!(request(r#"{"matchers": []}"#)
+            .into_silence("s3".to_string(), "ops", 0)
+            .is_err());
+        assert!(request(r#"{"url": "x", "duration_minutes": 0}"#)
+            .into_silence("s4".to_string(), "ops", 0)
+            .is_err());
+        assert!(serde_json::from_str::<SilenceRequest>(r#"{"matchers": ["bad"]}"#).is_err());
+
+        assert_eq!(parse_silence_path("/silences/abc"), Some("abc"));
+        assert_eq!(parse_silence_path("/silences/"), None);
+        assert_eq!(parse_silence_path("/silences/a/b"), None);
+    }
+}
@@ -0,0 +1,408 @@
+//! Sitemap discovery probes
+//!
+//! A resource with a `sitemap` table points at a `sitemap.xml` (or a JSON index of
+//! URLs) instead of a single asset:
+//!
+//! ```toml
+//! [[resources]]
+//! url = "https://docs.example.com/sitemap.xml"
+//! sitemap = { max_urls = 100 }
+//! ```
+//!
+//! Each run fetches the sitemap, expands it into the listed pages (the first
+//! `max_urls`) and checks their availability. Every page has alert streams of its
+//! own, so only newly broken pages notify, and pages dropped from the sitemap
+//! simply stop being checked. The sitemap itself is checked like any resource: an
+//! unreachable or empty sitemap alerts on its URL.
+//!
+//! Nested sitemap indexes are not followed; their `<loc>` entries are checked as pages.
+
+use crate::checker::{check_availability, CheckError, CheckResult};
+use crate::clock;
+use crate::config::{self, Resource};
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use crate::run::Deadline;
+use futures::future;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use worker::*;
+
+/// Default number of pages checked per sitemap
+const DEFAULT_MAX_URLS: usize = 50;
+
+/// Upper bound of `max_urls`, the subrequest cap of the paid Workers plan
+const MAX_URLS_LIMIT: usize = 1000;
+
+/// Settings of a sitemap probe (`sitemap = { ... }` on a resource)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sitemap {
+    /// Number of listed pages checked per run, in sitemap order
+    #[serde(default = "default_max_urls")]
+    pub max_urls: usize,
+}
+
+fn default_max_urls() -> usize {
+    DEFAULT_MAX_URLS
+}
+
+impl Default for Sitemap {
+    fn default() -> Self {
+        Self {
+            max_urls: DEFAULT_MAX_URLS,
+        }
+    }
+}
+
+impl Sitemap {
+    pub fn validate(&self, url: &str) -> std::result::Result<(), String> {
+        if !(1..=MAX_URLS_LIMIT).contains(&self.max_urls) {
+            return Err(format!(
+                "sitemap.max_urls of {} must be between 1 and {}",
+                url, MAX_URLS_LIMIT
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Entry of a JSON index: a bare URL or an object with a `url` (or `loc`) field
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonEntry {
+    Url(String),
+    Entry {
+        #[serde(alias = "loc")]
+        url: String,
+    },
+}
+
+/// JSON index: an array of entries or an object with a `urls` array
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonIndex {
+    List(Vec<JsonEntry>),
+    Object { urls: Vec<JsonEntry> },
+}
+
+/// Decode the predefined XML entities and strip a CDATA wrapper
+fn xml_text(raw: &str) -> String {
+    let raw = raw.trim();
+    if let Some(data) = raw
+        .strip_prefix("<![CDATA[")
+        .and_then(|r| r.strip_suffix("]]>"))
+    {
+        return data.trim().to_string();
+    }
+    raw.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Contents of the `<loc>` elements of a sitemap or sitemap index
+fn xml_locs(body: &str) -> Vec<String> {
+    let mut locs = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("<loc>") {
+        rest = &rest[start + "<loc>".len()..];
+        let Some(end) = rest.find("</loc>") else {
+            break;
+        };
+        locs.push(xml_text(&rest[..end]));
+        rest = &rest[end..];
+    }
+    locs
+}
+
+/// Page URLs listed by a sitemap, normalized, in order and without duplicates
+///
+/// Bodies starting with `[` or `{` are read as a JSON index, anything else as XML.
+/// Only absolute http(s) URLs are kept.
+pub fn parse_urls(body: &str) -> std::result::Result<Vec<String>, String> {
+    let body = body.trim_start_matches('\u{feff}').trim();
+    let urls = if body.starts_with('[') || body.starts_with('{') {
+        let index: JsonIndex =
+            serde_json::from_str(body).map_err(|e| format!("Invalid JSON index: {}", e))?;
+        let entries = match index {
+            JsonIndex::List(entries) | JsonIndex::Object { urls: entries } => entries,
+        };
+        entries
+            .into_iter()
+            .map(|entry| match entry {
+                JsonEntry::Url(url) | JsonEntry::Entry { url } => url.trim().to_string(),
+            })
+            .collect()
+    } else {
+        xml_locs(body)
+    };
+
+    let mut seen = HashSet::new();
+    Ok(urls
+        .into_iter()
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+        .filter_map(|url| config::normalize_url(&url).ok())
+        .filter(|url| seen.insert(url.clone()))
+        .collect())
+}
+
+/// Fetch a sitemap and list its pages
+///
+/// Returns the sitemap's own check result, and the listed pages if it passed.
+async fn expand(fetcher: &impl Fetcher, resource: &Resource) -> (CheckResult, Vec<String>) {
+    let url = resource.url.as_str();
+    let started = clock::now_ms();
+    let request = HttpRequest::new(Method::Get, url)
+        .with_header("Accept", "application/xml, text/xml, application/json");
+    let mut response = match fetcher.fetch(request).await {
+        Ok(r) => r,
+        Err(_) => {
+            return (
+                CheckResult::failure(url.to_string(), CheckError::FetchFailed),
+                vec![],
+            )
+        }
+    };
+    let status_code = response.status_code();
+    let latency_ms = Some(clock::now_ms().saturating_sub(started));
+    let failure = |error| {
+        let mut result = CheckResult::failure(url.to_string(), error);
+        result.latency_ms = latency_ms;
+        (result, vec![])
+    };
+    if !(200..300).contains(&status_code) {
+        return failure(CheckError::HttpError(status_code));
+    }
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => return failure(CheckError::from_body(&e)),
+    };
+    let urls = match parse_urls(&String::from_utf8_lossy(&body)) {
+        Ok(urls) if !urls.is_empty() => urls,
+        Ok(_) => {
+            console_error!("✗ {} - sitemap lists no pages", url);
+            return failure(CheckError::UnexpectedResponse);
+        }
+        Err(e) => {
+            console_error!("✗ {} - {}", url, e);
+            return failure(CheckError::UnexpectedResponse);
+        }
+    };
+
+    let mut result = CheckResult::success(url.to_string(), status_code, true);
+    result.latency_ms = latency_ms;
+    (result, urls)
+}
+
+/// Check a sitemap and the availability of its first `max_urls` pages
+///
+/// The sitemap's result comes first. Pages are checked with the given concurrency
+/// and are skipped once the deadline passes; they are not carried over, the next
+/// run expands the sitemap again.
+pub async fn check(
+    fetcher: &impl Fetcher,
+    resource: &Resource,
+    sitemap: &Sitemap,
+    concurrency: usize,
+    deadline: Deadline,
+) -> Vec<CheckResult> {
+    let (result, mut urls) = expand(fetcher, resource).await;
+    if !resource.allow_http {
+        let listed = urls.len();
+        urls.retain(|url| !config::is_plain_http(url));
+        if urls.len() < listed {
+            console_error!(
+                "Sitemap {} lists {} plain HTTP pages, skipping them (allow_http is not set)",
+                resource.url,
+                listed - urls.len()
+            );
+        }
+    }
+    if urls.len() > sitemap.max_urls {
+        console_log!(
+            "Sitemap {} lists {} pages, checking the first {}",
+            resource.url,
+            urls.len(),
+            sitemap.max_urls
+        );
+        urls.truncate(sitemap.max_urls);
+    }
+
+    let pages: Vec<CheckResult> = stream::iter(urls)
+        .map(|url| async move {
+            if deadline.expired() {
+                return None;
+            }
+            Some(check_availability(fetcher, &url, Method::Get).await)
+        })
+        .buffered(concurrency)
+        .filter_map(future::ready)
+        .collect()
+        .await;
+    std::iter::once(result).chain(pages).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    const SITEMAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://docs.example.com/</loc><lastmod>2025-11-01</lastmod></url>
+  <url>
+    <loc>
+      https://docs.example.com/search?q=a&amp;page=2
+    </loc>
+  </url>
+  <url><loc><![CDATA[https://docs.example.com/guide]]></loc></url>
+  <url><loc>https://DOCS.example.com:443/</loc></url>
+  <url><loc>/relative</loc></url>
+</urlset>"#;
+
+    fn resource() -> Resource {
+        Resource {
+            url: "https://docs.example.com/sitemap.xml".to_string(),
+            sitemap: Some(Sitemap::default()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        assert_eq!(
+            parse_urls(SITEMAP).unwrap(),
+            vec![
+                "https://docs.example.com/",
+                "https://docs.example.com/search?q=a&page=2",
+                "https://docs.example.com/guide",
+            ]
+        );
+        assert!(parse_urls("<html><body>Moved</body></html>")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let expected = vec!["https://docs.example.com/a", "https://docs.example.com/b"];
+        assert_eq!(
+            parse_urls(r#"["https://docs.example.com/a", "https://docs.example.com/b"]"#).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_urls(
+                r#"{"urls": [{"url": "https://docs.example.com/a"}, {"loc": "https://docs.example.com/b"}]}"#
+            )
+            .unwrap(),
+            expected
+        );
+        assert!(parse_urls(r#"{"pages": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let fetcher = MockFetcher::new()
+            .respond(
+                "https://docs.example.com/sitemap.xml",
+                MockResponse::new(200, SITEMAP),
+            )
+            .respond("https://docs.example.com/", MockResponse::new(200, "home"))
+            .respond(
+                "https://docs.example.com/search?q=a&page=2",
+                MockResponse::new(200, "results"),
+            )
+            .respond("https://docs.example.com/guide", MockResponse::new(404, ""));
+        let deadline = Deadline::new(clock::now_ms(), 25_000);
+
+        let results = block_on(check(
+            &fetcher,
+            &resource(),
+            &Sitemap::default(),
+            2,
+            deadline,
+        ));
+        let outcomes: Vec<(&str, bool)> = results
+            .iter()
+            .map(|r| (r.url.as_ref(), r.has_problem()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("https://docs.example.com/sitemap.xml", false),
+                ("https://docs.example.com/", false),
+                ("https://docs.example.com/search?q=a&page=2", false),
+                ("https://docs.example.com/guide", true),
+            ]
+        );
+        assert_eq!(results[3].error, Some(CheckError::HttpError(404)));
+
+        // Bounded by max_urls
+        let limited = Sitemap { max_urls: 1 };
+        let results = block_on(check(&fetcher, &resource(), &limited, 2, deadline));
+        assert_eq!(results.len(), 2);
+
+        // No pages are checked after the deadline
+        let expired = Deadline::new(0, 1);
+        let results = block_on(check(&fetcher, &resource(), &limited, 2, expired));
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_plain_http_pages() {
+        let sitemap = r#"["https://docs.example.com/a", "http://docs.example.com/b"]"#;
+        let fetcher = MockFetcher::new()
+            .respond(
+                "https://docs.example.com/sitemap.xml",
+                MockResponse::new(200, sitemap),
+            )
+            .respond("https://docs.example.com/a", MockResponse::new(200, ""))
+            .respond("http://docs.example.com/b", MockResponse::new(200, ""));
+        let deadline = Deadline::new(clock::now_ms(), 25_000);
+        let run = |resource: &Resource| {
+            block_on(check(&fetcher, resource, &Sitemap::default(), 2, deadline))
+        };
+
+        // Plain HTTP pages are only checked when the resource allows them
+        assert_eq!(run(&resource()).len(), 2);
+        let allowed = Resource {
+            allow_http: true,
+            ..resource()
+        };
+        assert_eq!(run(&allowed).len(), 3);
+    }
+
+    #[test]
+    fn test_broken_sitemap() {
+        let fetcher = MockFetcher::new()
+            .respond(
+                "https://docs.example.com/sitemap.xml",
+                MockResponse::new(200, "<urlset></urlset>"),
+            )
+            .respond(
+                "https://old.example.com/sitemap.xml",
+                MockResponse::new(410, ""),
+            );
+        let deadline = Deadline::new(clock::now_ms(), 25_000);
+
+        let results = block_on(check(
+            &fetcher,
+            &resource(),
+            &Sitemap::default(),
+            2,
+            deadline,
+        ));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].error, Some(CheckError::UnexpectedResponse));
+
+        let gone = Resource {
+            url: "https://old.example.com/sitemap.xml".to_string(),
+            ..resource()
+        };
+        let results = block_on(check(&fetcher, &gone, &Sitemap::default(), 2, deadline));
+        assert_eq!(results[0].error, Some(CheckError::HttpError(410)));
+    }
+}
@@ -0,0 +1,574 @@
+//! Slack interactivity: buttons on alert messages (`POST /slack/interactions`)
+//! and the `/linkkivahti` slash command (`POST /slack/command`)
+//!
+//! With `SLACK_SIGNING_SECRET` set, firing alerts sent to Slack carry "Re-check
+//! now", "Silence 1h" and "Ack" buttons. They need a Slack app whose incoming
+//! webhook posts the alerts and whose Interactivity request URL points at
+//! `/slack/interactions`. Slack signs every request with the app's signing
+//! secret; requests with a bad signature or a timestamp more than five minutes
+//! off are refused.
+//!
+//! Slack expects an answer within three seconds, so the endpoint acknowledges
+//! the click right away and runs the action afterwards: a re-check of the
+//! resource, a one-hour silence of the alert, or an acknowledgement that stops
+//! its reminders (see `action::run_button`). The outcome is
+//! appended to the original message through its `response_url`.
+//!
+//! The slash command lets the team operate the monitor from Slack without an
+//! access token, with answers only shown to whoever ran it:
+//! - `check [tag]` checks all resources, or those whose group or a label matches
+//!   the tag (`frontend`, `team=web`), and reports the outcome when done
+//! - `status` lists the latest results of every scope
+//! - `silence <url> <duration>` silences the alerts of a resource, e.g. for `2h`
+
+use crate::action::{self, Actor, AlertRef};
+use crate::alert::AlertStream;
+use crate::clock;
+use crate::config::{self, Config};
+use crate::console::{console_error, console_log};
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse, WorkerFetcher};
+use crate::silence::{self, SilenceRequest};
+use crate::sri;
+use crate::status;
+use crate::store::Store;
+use crate::tenant::{self, Scope};
+use crate::trace;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use std::sync::Arc;
+use worker::*;
+
+/// Largest accepted difference between Slack's request timestamp and our clock
+const MAX_CLOCK_SKEW_SECS: u64 = 5 * 60;
+
+const USAGE: &str = "Usage: `/linkkivahti check [tag]`, `/linkkivahti status` or \
+                     `/linkkivahti silence <url> <duration>` (e.g. `30m`, `2h`, `1d`)";
+
+/// Value of the buttons of an alert, if Slack interactivity is configured
+pub fn button_value(
+    env: &Env,
+    scope: &Scope<'_>,
+    url: &str,
+    stream: AlertStream,
+) -> Option<String> {
+    signing_secret(env)?;
+    serde_json::to_string(&AlertRef::new(scope, url, stream)).ok()
+}
+
+/// Signing secret of the Slack app from `SLACK_SIGNING_SECRET`
+fn signing_secret(env: &Env) -> Option<String> {
+    config::setting(env, "SLACK_SIGNING_SECRET")
+}
+
+/// Verify Slack's `v0=<hex HMAC-SHA256 of "v0:{timestamp}:{body}">` request signature
+pub fn verify_signature(
+    secret: &str,
+    timestamp: &str,
+    body: &str,
+    signature: &str,
+    now_secs: u64,
+) -> bool {
+    let Ok(sent) = timestamp.parse::<u64>() else {
+        return false;
+    };
+    if now_secs.abs_diff(sent) > MAX_CLOCK_SKEW_SECS {
+        return false;
+    }
+    let Some(signature) = signature.strip_prefix("v0=").and_then(sri::decode_hex) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Read the body of a request from Slack, or `None` if it is not validly signed
+pub async fn verified_body(env: &Env, req: &mut Request) -> Result<Option<String>> {
+    let Some(secret) = signing_secret(env) else {
+        return Ok(None);
+    };
+    let timestamp = req.headers().get("X-Slack-Request-Timestamp")?;
+    let signature = req.headers().get("X-Slack-Signature")?;
+    let body = req.text().await?;
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        return Ok(None);
+    };
+    let valid = verify_signature(
+        &secret,
+        &timestamp,
+        &body,
+        &signature,
+        clock::now_ms() / 1000,
+    );
+    Ok(valid.then_some(body))
+}
+
+/// A `block_actions` interaction payload, reduced to what the buttons need
+#[derive(Debug, Deserialize)]
+struct Interaction {
+    #[serde(rename = "type")]
+    kind: String,
+    user: SlackUser,
+    #[serde(default)]
+    actions: Vec<BlockAction>,
+    #[serde(default)]
+    response_url: Option<String>,
+    #[serde(default)]
+    message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlackUser {
+    id: String,
+    #[serde(default)]
+    username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockAction {
+    action_id: String,
+    #[serde(default)]
+    value: String,
+}
+
+/// The message the button was clicked on
+#[derive(Debug, Default, Deserialize)]
+struct Message {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    blocks: Vec<serde_json::Value>,
+}
+
+/// Parse the form-encoded `payload` field of an interaction request
+fn parse_interaction(body: &str) -> Option<Interaction> {
+    let (_, payload) = form_urlencoded::parse(body.as_bytes()).find(|(key, _)| key == "payload")?;
+    serde_json::from_str(&payload).ok()
+}
+
+/// The original message with a context line appended, replacing it in Slack
+fn updated_message(message: &Message, line: &str) -> serde_json::Value {
+    let mut blocks = message.blocks.clone();
+    blocks.push(json!({
+        "type": "context",
+        "elements": [{"type": "mrkdwn", "text": line}],
+    }));
+    json!({
+        "replace_original": true,
+        "text": message.text,
+        "blocks": blocks,
+    })
+}
+
+/// Post a message update to an interaction's `response_url`
+async fn post_update(
+    fetcher: &impl Fetcher,
+    response_url: &str,
+    update: &serde_json::Value,
+) -> Result<()> {
+    let response = fetcher
+        .fetch(HttpRequest::post_json(response_url, update.to_string()))
+        .await?;
+    match response.status_code() {
+        200..=299 => Ok(()),
+        status => Err(Error::RustError(format!(
+            "Slack response URL returned HTTP {}",
+            status
+        ))),
+    }
+}
+
+/// Name of a Slack user as recorded in silences and acknowledgements
+fn slack_principal(user: &SlackUser) -> String {
+    format!("slack:{}", user.username.as_deref().unwrap_or(&user.id))
+}
+
+/// Run a clicked button's action and update the message
+async fn respond(env: Env, config: Arc<Config>, interaction: Interaction) {
+    let Some(action) = interaction.actions.first() else {
+        return;
+    };
+    console_log!(
+        "Slack action {} by {}",
+        action.action_id,
+        slack_principal(&interaction.user)
+    );
+    let line = match serde_json::from_str::<AlertRef>(&action.value) {
+        Ok(alert) => {
+            let actor = Actor {
+                mention: format!("<@{}>", interaction.user.id),
+                principal: slack_principal(&interaction.user),
+                service: "Slack",
+            };
+            action::run_button(&env, &config, &action.action_id, &alert, &actor).await
+        }
+        Err(_) => format!("⚠️ Unknown alert for <@{}>", interaction.user.id),
+    };
+    let Some(response_url) = &interaction.response_url else {
+        return;
+    };
+    let update = updated_message(&interaction.message.unwrap_or_default(), &line);
+    if let Err(e) = post_update(&WorkerFetcher, response_url, &update).await {
+        console_error!("Failed to update Slack message: {}", e);
+    }
+}
+
+/// Handle `POST /slack/interactions`: verify the request and run the button's action
+/// after responding
+pub async fn handle_interaction(
+    env: &Env,
+    config: Arc<Config>,
+    ctx: &Context,
+    mut req: Request,
+) -> Result<Response> {
+    let Some(body) = verified_body(env, &mut req).await? else {
+        return Response::error("Invalid Slack signature", 401);
+    };
+    let Some(interaction) = parse_interaction(&body) else {
+        return Response::error("Invalid interaction payload", 400);
+    };
+    if interaction.kind == "block_actions" {
+        ctx.wait_until(trace::in_current_run(respond(
+            env.clone(),
+            config,
+            interaction,
+        )));
+    }
+    Response::empty()
+}
+
+/// A slash command request, reduced to what the commands need
+#[derive(Debug, Default)]
+struct SlashCommand {
+    text: String,
+    user_id: String,
+    user_name: String,
+    response_url: String,
+}
+
+/// Parse the form-encoded fields of a slash command request
+fn parse_command(body: &str) -> SlashCommand {
+    let mut command = SlashCommand::default();
+    for (key, value) in form_urlencoded::parse(body.as_bytes()) {
+        let field = match key.as_ref() {
+            "text" => &mut command.text,
+            "user_id" => &mut command.user_id,
+            "user_name" => &mut command.user_name,
+            "response_url" => &mut command.response_url,
+            _ => continue,
+        };
+        *field = value.into_owned();
+    }
+    command
+}
+
+/// A `/linkkivahti` subcommand
+#[derive(Debug, PartialEq, Eq)]
+enum Subcommand {
+    Check { tag: Option<String> },
+    Status,
+    Silence { url: String, minutes: u64 },
+}
+
+/// Parse the text of a slash command, or explain its usage
+fn parse_subcommand(text: &str) -> std::result::Result<Subcommand, String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    match words.as_slice() {
+        ["check"] => Ok(Subcommand::Check { tag: None }),
+        ["check", tag] => Ok(Subcommand::Check {
+            tag: Some(tag.to_string()),
+        }),
+        ["status"] => Ok(Subcommand::Status),
+        ["silence", url, duration] => {
+            let minutes = clock::parse_duration(duration).ok_or_else(|| {
+                format!(
+                    "Invalid duration '{}', use e.g. `30m`, `2h` or `1d`",
+                    duration
+                )
+            })?;
+            // Slack may send links escaped as `<url>` or `<url|label>`
+            let url = url.trim_start_matches('<').trim_end_matches('>');
+            let url = url.split('|').next().unwrap_or(url);
+            Ok(Subcommand::Silence {
+                url: url.to_string(),
+                minutes,
+            })
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Slash command response only shown to whoever ran the command
+fn ephemeral(text: &str) -> serde_json::Value {
+    json!({"response_type": "ephemeral", "text": text})
+}
+
+/// Run `/linkkivahti check` and post the outcome to the command's `response_url`
+async fn run_check(env: Env, config: Arc<Config>, tag: Option<String>, response_url: String) {
+    let summary = match &tag {
+        None => crate::check_all_resources(&env).await,
+        Some(tag) => crate::check_tagged(&env, &Scope::all(&config), tag).await,
+    };
+    let text = format!("🔁 Link check finished: {}", summary.describe());
+    if let Err(e) = post_update(&WorkerFetcher, &response_url, &ephemeral(&text)).await {
+        console_error!("Failed to post Slack command result: {}", e);
+    }
+}
+
+/// Silence all alerts of a resource, describing the outcome
+async fn silence_resource(
+    env: &Env,
+    config: &Config,
+    url: &str,
+    minutes: u64,
+    principal: &str,
+) -> Result<String> {
+    let found = Scope::all(config)
+        .into_iter()
+        .find_map(|scope| Some((scope, scope.find_resource(url)?)));
+    let Some((scope, resource)) = found else {
+        return Ok(format!("⚠️ {} is not a monitored resource", url));
+    };
+    let store = Store::require(env)?;
+    let request = SilenceRequest {
+        matchers: Vec::new(),
+        url: Some(resource.url.clone()),
+        duration_minutes: Some(minutes),
+        comment: Some("Silenced from Slack".to_string()),
+    };
+    let silence = match request.into_silence(silence::new_silence_id(), principal, clock::now_ms())
+    {
+        Ok(silence) => silence,
+        Err(e) => return Ok(format!("⚠️ {}", e)),
+    };
+    let id = silence.id.clone();
+    silence::add(&store, &scope, silence).await?;
+    console_log!("🔕 Alerts of {} silenced by {}", resource.url, principal);
+    Ok(format!(
+        "🔕 Alerts of {} silenced for {} minutes (silence {})",
+        resource.url, minutes, id
+    ))
+}
+
+/// Handle `POST /slack/command`: verify the request and run the `/linkkivahti`
+/// subcommand, answering ephemerally
+pub async fn handle_command(
+    env: &Env,
+    config: Arc<Config>,
+    ctx: &Context,
+    mut req: Request,
+) -> Result<Response> {
+    let Some(body) = verified_body(env, &mut req).await? else {
+        return Response::error("Invalid Slack signature", 401);
+    };
+    let command = parse_command(&body);
+    let principal = slack_principal(&SlackUser {
+        id: command.user_id,
+        username: Some(command.user_name).filter(|name| !name.is_empty()),
+    });
+    let text = match parse_subcommand(&command.text) {
+        Err(usage) => usage,
+        Ok(Subcommand::Status) => {
+            let mut summaries = Vec::new();
+            for scope in Scope::all(&config) {
+                summaries.push(status::chat_summary(env, &scope).await);
+            }
+            summaries.join("\n\n")
+        }
+        Ok(Subcommand::Check { tag }) => {
+            let started = match &tag {
+                None => Some("🔁 Checking all resources, the outcome follows".to_string()),
+                Some(tag) => {
+                    let count: usize = tenant::tagged(&Scope::all(&config), tag)
+                        .iter()
+                        .map(|(_, r)| r.len())
+                        .sum();
+                    (count > 0).then(|| {
+                        format!(
+                            "🔁 Checking {} resources tagged '{}', the outcome follows",
+                            count, tag
+                        )
+                    })
+                }
+            };
+            match started {
+                Some(started) => {
+                    console_log!("Slack check requested by {}", principal);
+                    ctx.wait_until(trace::in_current_run(run_check(
+                        env.clone(),
+                        config,
+                        tag,
+                        command.response_url,
+                    )));
+                    started
+                }
+                None => format!("⚠️ No resources tagged '{}'", tag.unwrap_or_default()),
+            }
+        }
+        Ok(Subcommand::Silence { url, minutes }) => {
+            silence_resource(env, &config, &url, minutes, &principal)
+                .await
+                .unwrap_or_else(|e| format!("⚠️ Silence failed: {}", e))
+        }
+    };
+    Response::from_json(&ephemeral(&text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+
+    const SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+
+    fn sign(timestamp: &str, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+        format!("v0={}", sri::encode_hex(&mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let body = "payload=%7B%7D";
+        let signature = sign("1531420618", body);
+        assert!(verify_signature(
+            SECRET,
+            "1531420618",
+            body,
+            &signature,
+            1531420618 + 60
+        ));
+
+        // Replayed too late, tampered, or signed with another secret
+        assert!(!verify_signature(
+            SECRET,
+            "1531420618",
+            body,
+            &signature,
+            1531420618 + 600
+        ));
+        assert!(!verify_signature(
+            SECRET,
+            "1531420618",
+            "payload=x",
+            &signature,
+            1531420618
+        ));
+        assert!(!verify_signature(
+            "other",
+            "1531420618",
+            body,
+            &signature,
+            1531420618
+        ));
+        assert!(!verify_signature(
+            SECRET,
+            "1531420618",
+            body,
+            "v0=zz",
+            1531420618
+        ));
+        assert!(!verify_signature(
+            SECRET, "soon", body, &signature, 1531420618
+        ));
+    }
+
+    #[test]
+    fn test_parse_interaction() {
+        let value = AlertRef {
+            tenant: Some("web".to_string()),
+            url: "https://example.com/a.js".to_string(),
+            stream: AlertStream::Integrity,
+        };
+        let payload = json!({
+            "type": "block_actions",
+            "user": {"id": "U123", "username": "alice"},
+            "actions": [{"action_id": "ack", "value": serde_json::to_string(&value).unwrap()}],
+            "response_url": "https://hooks.slack.com/actions/T1/1/abc",
+            "message": {"text": "SRI Mismatch Detected", "blocks": [{"type": "divider"}]},
+        });
+        let body: String = form_urlencoded::Serializer::new(String::new())
+            .append_pair("payload", &payload.to_string())
+            .finish();
+
+        let interaction = parse_interaction(&body).unwrap();
+        assert_eq!(interaction.kind, "block_actions");
+        assert_eq!(slack_principal(&interaction.user), "slack:alice");
+        let action = &interaction.actions[0];
+        assert_eq!(action.action_id, "ack");
+        assert_eq!(
+            serde_json::from_str::<AlertRef>(&action.value).unwrap(),
+            value
+        );
+        assert!(parse_interaction("payload=not-json").is_none());
+    }
+
+    #[test]
+    fn test_update_message() {
+        let message = Message {
+            text: "Link Check Failed".to_string(),
+            blocks: vec![json!({"type": "divider"})],
+        };
+        let update = updated_message(&message, "👀 Acknowledged by <@U123>");
+        assert_eq!(update["replace_original"], true);
+        assert_eq!(update["blocks"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            update["blocks"][1]["elements"][0]["text"],
+            "👀 Acknowledged by <@U123>"
+        );
+
+        let response_url = "https://hooks.slack.com/actions/T1/1/abc";
+        let fetcher = MockFetcher::new().respond(response_url, MockResponse::new(200, "ok"));
+        futures::executor::block_on(post_update(&fetcher, response_url, &update)).unwrap();
+        let requests = fetcher.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0]
+            .body
+            .as_deref()
+            .unwrap()
+            .contains("Acknowledged"));
+
+        let failing =
+            MockFetcher::new().respond(response_url, MockResponse::new(404, "expired_url"));
+        assert!(futures::executor::block_on(post_update(&failing, response_url, &update)).is_err());
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let body = "token=x&team_id=T1&command=%2Flinkkivahti&text=silence+%3Chttps%3A%2F%2Fexample.com%2Fa.js%3E+2h\
+                    &user_id=U123&user_name=alice&response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2F1";
+        let command = parse_command(body);
+        assert_eq!(command.user_name, "alice");
+        assert_eq!(command.response_url, "https://hooks.slack.com/commands/1");
+        assert_eq!(
+            parse_subcommand(&command.text),
+            Ok(Subcommand::Silence {
+                url: "https://example.com/a.js".to_string(),
+                minutes: 120
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_subcommand() {
+        assert_eq!(
+            parse_subcommand("check"),
+            Ok(Subcommand::Check { tag: None })
+        );
+        assert_eq!(
+            parse_subcommand(" check  team=web "),
+            Ok(Subcommand::Check {
+                tag: Some("team=web".to_string())
+            })
+        );
+        assert_eq!(parse_subcommand("status"), Ok(Subcommand::Status));
+        assert!(parse_subcommand("silence https://example.com/a.js soon")
+            .unwrap_err()
+            .contains("Invalid duration"));
+        assert_eq!(parse_subcommand(""), Err(USAGE.to_string()));
+        assert_eq!(parse_subcommand("reboot now"), Err(USAGE.to_string()));
+    }
+}
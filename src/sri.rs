@@ -11,6 +11,13 @@
 use base64::engine::general_purpose::STANDARD as BASE64;
 use base64::Engine;
 use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::HashMap;
+
+/// Longest accepted SRI string
+///
+/// A SHA-512 hash is 95 characters; anything much longer is rejected before
+/// decoding, since SRI strings may come from runtime config and webhooks.
+pub const MAX_SRI_LEN: usize = 128;
 
 /// SRI hash with algorithm-specific type
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -66,6 +73,10 @@ impl SriHash {
     /// assert!(sri.is_ok());
     /// ```
     pub fn parse(s: &str) -> Result<Self, SriError> {
+        if s.len() > MAX_SRI_LEN {
+            return Err(SriError::InvalidFormat);
+        }
+
         // Split on first '-' to separate algorithm from hash
         let (algorithm, base64_hash) = s.split_once('-').ok_or(SriError::InvalidFormat)?;
 
@@ -151,6 +162,119 @@ impl SriHash {
             SriHash::Sha512(_) => "sha512",
         }
     }
+
+    /// Raw hash bytes
+    pub fn digest(&self) -> &[u8] {
+        match self {
+            SriHash::Sha256(hash) => hash,
+            SriHash::Sha384(hash) => hash,
+            SriHash::Sha512(hash) => hash,
+        }
+    }
+}
+
+/// Parsed SRI strings of a config
+///
+/// Decoding every hash on every run is wasted work while the config stays the
+/// same, so the hashes of a config are parsed once (see `Config::sri_hashes`).
+/// Strings that were not preloaded, such as approved overrides, are parsed on
+/// demand.
+#[derive(Debug, Clone, Default)]
+pub struct SriCache {
+    parsed: HashMap<String, Result<SriHash, SriError>>,
+}
+
+impl SriCache {
+    /// Parse the given SRI strings, skipping empty ones
+    pub fn new<'a>(sris: impl IntoIterator<Item = &'a str>) -> Self {
+        let parsed = sris
+            .into_iter()
+            .filter(|sri| !sri.is_empty())
+            .map(|sri| (sri.to_string(), SriHash::parse(sri)))
+            .collect();
+        Self { parsed }
+    }
+
+    /// Parsed form of an SRI string, from the cache if it was preloaded
+    pub fn parse(&self, sri: &str) -> Result<SriHash, SriError> {
+        match self.parsed.get(sri) {
+            Some(parsed) => parsed.clone(),
+            None => SriHash::parse(sri),
+        }
+    }
+
+    /// Number of preloaded SRI strings
+    pub fn len(&self) -> usize {
+        self.parsed.len()
+    }
+
+    /// Whether no SRI strings were preloaded
+    pub fn is_empty(&self) -> bool {
+        self.parsed.is_empty()
+    }
+}
+
+/// Canonical `algorithm-base64hash` form, which `SriHash::parse` accepts
+impl std::fmt::Display for SriHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.algorithm(), BASE64.encode(self.digest()))
+    }
+}
+
+/// Compute the SHA-384 SRI string of content
+///
+/// # Examples
+/// ```
+/// use linkkivahti::sri::{sha384, SriHash};
+///
+/// let integrity = sha384(b"hello world");
+/// assert!(SriHash::parse(&integrity).unwrap().verify(b"hello world"));
+/// ```
+pub fn sha384(content: &[u8]) -> String {
+    format!("sha384-{}", BASE64.encode(Sha384::digest(content)))
+}
+
+/// Compute the lowercase hex SHA-256 digest of content
+///
+/// # Examples
+/// ```
+/// use linkkivahti::sri::sha256_hex;
+///
+/// assert!(sha256_hex(b"hello world").starts_with("b94d27b9934d3e08"));
+/// ```
+pub fn sha256_hex(content: &[u8]) -> String {
+    encode_hex(&Sha256::digest(content))
+}
+
+/// Encode bytes as lowercase hex, e.g. a digest or a request signature
+///
+/// # Examples
+/// ```
+/// use linkkivahti::sri::encode_hex;
+///
+/// assert_eq!(encode_hex(&[0x00, 0xff]), "00ff");
+/// ```
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex string of either case, e.g. a request signature
+///
+/// # Examples
+/// ```
+/// use linkkivahti::sri::decode_hex;
+///
+/// assert_eq!(decode_hex("00fF"), Some(vec![0x00, 0xff]));
+/// assert_eq!(decode_hex("0g"), None);
+/// ```
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
 }
 
 #[cfg(test)]
@@ -252,6 +376,32 @@ mod tests {
         assert!(!sri.verify(b"wrong content"));
     }
 
+    #[test]
+    fn test_parse_too_long() {
+        let sri = format!("sha384-{}", "A".repeat(MAX_SRI_LEN));
+        assert_eq!(SriHash::parse(&sri), Err(SriError::InvalidFormat));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let sri = "sha512-MJ7MSJwS1utMxA9QyQLytNDtd+5RGnx6m808qG1M2G+YndNbxf9JlnDaNCVbRbDP2DDoH2Bdz33FVC6TrpzXbw==";
+        assert_eq!(SriHash::parse(sri).unwrap().to_string(), sri);
+    }
+
+    #[test]
+    fn test_cache() {
+        let valid = sha384(b"widget");
+        let cache = SriCache::new([valid.as_str(), "sha384-abc", ""]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.parse(&valid).unwrap().verify(b"widget"));
+        assert_eq!(cache.parse("sha384-abc"), Err(SriError::InvalidBase64));
+
+        // Strings that were not preloaded are parsed on demand
+        let other = sha384(b"other");
+        assert!(cache.parse(&other).unwrap().verify(b"other"));
+        assert_eq!(cache.len(), 2);
+    }
+
     #[test]
     fn test_error_descriptions() {
         assert!(!SriError::InvalidFormat.description().is_empty());
@@ -259,4 +409,62 @@ mod tests {
         assert!(!SriError::InvalidBase64.description().is_empty());
         assert!(!SriError::InvalidHashLength.description().is_empty());
     }
+
+    mod properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// A valid SRI string and its algorithm's digest length
+        fn valid_sri() -> impl Strategy<Value = String> {
+            prop_oneof![
+                prop::collection::vec(any::<u8>(), 32),
+                prop::collection::vec(any::<u8>(), 48),
+                prop::collection::vec(any::<u8>(), 64),
+            ]
+            .prop_map(|digest| {
+                let algorithm = match digest.len() {
+                    32 => "sha256",
+                    48 => "sha384",
+                    _ => "sha512",
+                };
+                format!("{}-{}", algorithm, BASE64.encode(digest))
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn parse_never_panics(s in any::<String>()) {
+                let _ = SriHash::parse(&s);
+            }
+
+            #[test]
+            fn parse_never_panics_on_prefixed_input(
+                algorithm in "sha(256|384|512)|[a-z0-9]{0,8}",
+                hash in "[A-Za-z0-9+/=\\-_ \\PC]{0,140}",
+            ) {
+                let _ = SriHash::parse(&format!("{}-{}", algorithm, hash));
+            }
+
+            #[test]
+            fn valid_strings_round_trip(sri in valid_sri()) {
+                let parsed = SriHash::parse(&sri).unwrap();
+                prop_assert_eq!(parsed.to_string(), sri);
+            }
+
+            #[test]
+            fn truncated_strings_are_rejected(sri in valid_sri(), cut in 0usize..95) {
+                prop_assume!(cut < sri.len());
+                prop_assert!(SriHash::parse(&sri[..cut]).is_err());
+            }
+
+            #[test]
+            fn computed_hashes_verify(content in prop::collection::vec(any::<u8>(), 0..512)) {
+                let sri = SriHash::parse(&sha384(&content)).unwrap();
+                prop_assert!(sri.verify(&content));
+                let mut tampered = content.clone();
+                tampered.push(0);
+                prop_assert!(!sri.verify(&tampered));
+            }
+        }
+    }
 }
@@ -0,0 +1,834 @@
+//! Status endpoint (`GET /`)
+//!
+//! What the endpoint reveals to unauthenticated callers is controlled by the
+//! `PUBLIC_STATUS` setting:
+//! - `full` (default): URLs, SRI hashes and current state of every resource
+//! - `minimal`: only resource names and up/down state, for public status feeds
+//! - `off`: the endpoint returns 404
+//!
+//! Requests with a valid access token always get the full view.
+//!
+//! Tenants have their own status at `GET /t/{tenant}/`, governed by the same setting.
+//! `?tag=prod` limits the listing and the `overall` state to the resources with
+//! that tag (see `Resource::has_tag`).
+//!
+//! The `Accept` header selects the representation (see `Format`): JSON by
+//! default, an HTML table for browsers, a one-line summary for `text/plain`, and
+//! for authorized callers asking for `text/plain; version=0.0.4` the Prometheus
+//! exposition of `GET /metrics`.
+//!
+//! Chat commands (Slack's `/linkkivahti status`, Discord's `/linkstatus`) get a
+//! short text summary of the same results instead.
+
+use crate::alert::{AlertStates, AlertStream};
+use crate::approval;
+use crate::auth;
+use crate::canary::{self, ChannelHealth};
+use crate::clock;
+use crate::config::{self, Resource};
+use crate::console::console_error;
+use crate::control::{self, Control};
+use crate::environment::Environment;
+use crate::health::{self, Health, HealthConfig};
+use crate::history::{self, History};
+use crate::metrics;
+use crate::report;
+use crate::store::Store;
+use crate::tenant::{self, Scope};
+use serde::Serialize;
+use std::collections::HashMap;
+use worker::*;
+
+/// Number of failing resources listed in chat summaries
+const MAX_LISTED: usize = 15;
+
+/// How much of the status is shown to unauthenticated callers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Full,
+    Minimal,
+    Off,
+}
+
+impl std::str::FromStr for Visibility {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(Self::Full),
+            "minimal" => Ok(Self::Minimal),
+            "off" => Ok(Self::Off),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Representation of the status selected by the `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Html,
+    Text,
+    /// Prometheus text exposition (`text/plain; version=0.0.4`)
+    Prometheus,
+}
+
+impl Format {
+    /// Pick the supported media range with the highest quality, the first one on
+    /// ties; JSON without a header, for `*/*` and when nothing is supported
+    fn negotiate(accept: Option<&str>) -> Self {
+        let mut best = (Self::Json, 0.0_f32);
+        for range in accept.unwrap_or_default().split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let media = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let mut quality = 1.0;
+            let mut version = None;
+            for param in parts {
+                match param.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                    Some((key, value)) if key.eq_ignore_ascii_case("q") => {
+                        quality = value.parse().unwrap_or(0.0)
+                    }
+                    Some((key, value)) if key.eq_ignore_ascii_case("version") => {
+                        version = Some(value)
+                    }
+                    _ => {}
+                }
+            }
+            let format = match (media.as_str(), version) {
+                ("text/plain", Some("0.0.4")) => Self::Prometheus,
+                ("text/plain" | "text/*", _) => Self::Text,
+                ("text/html", _) => Self::Html,
+                ("application/json" | "application/*" | "*/*", _) => Self::Json,
+                _ => continue,
+            };
+            if quality > best.1 {
+                best = (format, quality);
+            }
+        }
+        best.0
+    }
+}
+
+/// Current state of a resource, derived from its most recent stored result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceState {
+    Up,
+    Down,
+    /// No stored result yet (or state storage not configured)
+    Unknown,
+}
+
+impl ResourceState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// Traffic-light state of a whole scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverallState {
+    /// No resource is down
+    Ok,
+    /// Some resources are down
+    Degraded,
+    /// Every resource with a stored result is down
+    Down,
+}
+
+impl OverallState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Degraded => "degraded",
+            Self::Down => "down",
+        }
+    }
+}
+
+/// Aggregate of the resource states, for pollers that read a single field
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Overall {
+    state: OverallState,
+    up: usize,
+    down: usize,
+    unknown: usize,
+}
+
+impl Overall {
+    fn from_resources(resources: &[ResourceInfo]) -> Self {
+        let count = |state| resources.iter().filter(|r| r.state == state).count();
+        let (up, down, unknown) = (
+            count(ResourceState::Up),
+            count(ResourceState::Down),
+            count(ResourceState::Unknown),
+        );
+        let state = match (up, down) {
+            (_, 0) => OverallState::Ok,
+            (0, _) => OverallState::Down,
+            _ => OverallState::Degraded,
+        };
+        Self {
+            state,
+            up,
+            down,
+            unknown,
+        }
+    }
+}
+
+/// Status response structure for the / endpoint
+#[derive(Serialize)]
+struct StatusResponse<'a> {
+    status: &'static str,
+    worker: &'static str,
+    version: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<&'a str>,
+    /// Deployment environment from `ENVIRONMENT_NAME`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<Environment>,
+    overall: Overall,
+    /// Whether the results are not to be relied on: degraded mode is on or
+    /// monitoring is disabled (see `control`)
+    degraded: bool,
+    /// Kill switch and degraded flag, if either is set; the reason only in the
+    /// full view
+    #[serde(skip_serializing_if = "Option::is_none")]
+    control: Option<Control>,
+    /// Latest notification canary outcome (full view only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notifications: Option<ChannelHealth>,
+    /// Config warnings about the scope's resources (full view only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+    resources: Vec<ResourceInfo>,
+}
+
+impl StatusResponse<'_> {
+    /// Worker name, with the tenant for tenant scopes
+    fn label(&self) -> String {
+        match self.tenant {
+            Some(tenant) => format!("{}/{}", self.worker, tenant),
+            None => self.worker.to_string(),
+        }
+    }
+
+    /// One-line summary, e.g. `linkkivahti degraded: 2 up, 1 down, 0 unknown`,
+    /// followed by the kill switch or degraded flag if set
+    fn to_text(&self) -> String {
+        let notice = self
+            .control
+            .as_ref()
+            .map_or(String::new(), |c| format!(" ({})", c.describe()));
+        format!(
+            "{} {}: {} up, {} down, {} unknown{}\n",
+            self.label(),
+            self.overall.state.as_str(),
+            self.overall.up,
+            self.overall.down,
+            self.overall.unknown,
+            notice
+        )
+    }
+
+    /// Minimal HTML page with a table of the resources
+    fn to_html(&self) -> String {
+        let mut rows = String::new();
+        for r in &self.resources {
+            let resource = match (&r.name, &r.url) {
+                (Some(name), Some(url)) => format!(
+                    "{}<br><small>{}</small>",
+                    report::escape(name),
+                    report::escape(url)
+                ),
+                (Some(name), None) => report::escape(name),
+                (None, url) => report::escape(url.as_deref().unwrap_or_default()),
+            };
+            rows.push_str(&format!(
+                "<tr class=\"{state}\"><td>{}</td><td>{state}</td><td class=\"num\">{}</td></tr>\n",
+                resource,
+                r.health.map_or(String::new(), |h| h.score.to_string()),
+                state = r.state.as_str(),
+            ));
+        }
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{label} status</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border-bottom: 1px solid #ddd; padding: 0.3em 0.6em; text-align: left; }}
+td.num {{ text-align: right; font-variant-numeric: tabular-nums; }}
+tr.down td {{ background: #fdecea; }}
+tr.unknown td {{ color: #777; }}
+p.notice {{ background: #fff4e5; padding: 0.5em; }}
+</style>
+</head>
+<body>
+<h1>{label}: {state}</h1>
+{notice}<p>{up} up · {down} down · {unknown} unknown · config {version}</p>
+<table>
+<tr><th>Resource</th><th>State</th><th>Health</th></tr>
+{rows}</table>
+</body>
+</html>
+"#,
+            label = report::escape(&self.label()),
+            state = self.overall.state.as_str(),
+            up = self.overall.up,
+            down = self.overall.down,
+            unknown = self.overall.unknown,
+            version = report::escape(self.version),
+            notice = self.control.as_ref().map_or(String::new(), |c| format!(
+                "<p class=\"notice\">⚠ {}</p>\n",
+                report::escape(&c.describe())
+            )),
+            rows = rows,
+        )
+    }
+}
+
+/// Individual resource information for status endpoint
+#[derive(Debug, Serialize)]
+struct ResourceInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sri: Option<String>,
+    /// Tags of the resource (full view only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    state: ResourceState,
+    /// Rolling health score (full view only, see `health`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    health: Option<Health>,
+}
+
+/// Get the configured visibility for unauthenticated callers
+///
+/// Unknown values fall back to `minimal` so a typo never exposes more than intended.
+fn visibility(env: &Env) -> Visibility {
+    match config::setting(env, "PUBLIC_STATUS") {
+        None => Visibility::Full,
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            console_error!("Unknown PUBLIC_STATUS value '{}', using minimal", value);
+            Visibility::Minimal
+        }),
+    }
+}
+
+/// Derive the state of a resource from stored history
+fn resource_state(history: &History, url: &str) -> ResourceState {
+    match history.latest(url) {
+        Some(entry) if entry.ok => ResourceState::Up,
+        Some(_) => ResourceState::Down,
+        None => ResourceState::Unknown,
+    }
+}
+
+/// Build per-resource status entries for the given visibility, limited to the
+/// resources selected by `tag` if given
+fn build_resources(
+    resources: &[Resource],
+    tag: Option<&str>,
+    overrides: &HashMap<String, String>,
+    history: &History,
+    health: &HealthConfig,
+    now: u64,
+    visibility: Visibility,
+) -> Vec<ResourceInfo> {
+    resources
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| tag.is_none_or(|tag| r.has_tag(tag)))
+        .map(|(index, r)| {
+            let state = resource_state(history, &r.url);
+            match visibility {
+                Visibility::Minimal => ResourceInfo {
+                    // Unnamed resources get a positional name instead of leaking the URL
+                    name: Some(
+                        r.name
+                            .clone()
+                            .unwrap_or_else(|| format!("resource-{}", index + 1)),
+                    ),
+                    url: None,
+                    sri: None,
+                    tags: Vec::new(),
+                    state,
+                    health: None,
+                },
+                Visibility::Full | Visibility::Off => ResourceInfo {
+                    name: r.name.clone(),
+                    url: Some(r.url.clone()),
+                    sri: Some(overrides.get(&r.url).unwrap_or(&r.sri).clone()),
+                    tags: r.tags.clone(),
+                    state,
+                    health: health::score(history.entries(&r.url), health, now),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Handle / (root) endpoint of a scope
+///
+/// Returns combined health status and configuration in a single response, in
+/// the representation the `Accept` header asks for. SRI hashes reflect approved
+/// runtime changes where present.
+pub async fn handle_status(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let mut response = negotiated_status(env, scope, req).await?;
+    response.headers_mut().set("Vary", "Accept")?;
+    Ok(response)
+}
+
+async fn negotiated_status(env: &Env, scope: &Scope<'_>, req: &Request) -> Result<Response> {
+    let authorized = auth::check_auth(env, req)
+        .await
+        .is_ok_and(|p| p.can_access(scope));
+    let visibility = if authorized {
+        Visibility::Full
+    } else {
+        visibility(env)
+    };
+
+    if visibility == Visibility::Off {
+        return Response::error("Not Found", 404);
+    }
+    let tag = tenant::requested_tag(&req.url()?);
+    if let Some(tag) = &tag {
+        if !scope.resources().iter().any(|r| r.has_tag(tag)) {
+            return Response::error(format!("No resources tagged '{}'", tag), 404);
+        }
+    }
+    let format = Format::negotiate(req.headers().get("Accept")?.as_deref());
+    if format == Format::Prometheus {
+        // Same exposition as the secured `GET /metrics`
+        if !authorized {
+            return Response::error("Unauthorized", 401);
+        }
+        return metrics::handle_metrics(env, scope).await;
+    }
+
+    let overrides = match visibility {
+        Visibility::Full => approval::load_overrides(env, scope).await,
+        _ => HashMap::new(),
+    };
+    let history = history::load(env, scope).await;
+    let notifications = match visibility {
+        Visibility::Full => canary::load(env, scope).await,
+        _ => None,
+    };
+
+    let warnings = match visibility {
+        Visibility::Full => {
+            let mut warnings: Vec<String> = scope
+                .resources()
+                .iter()
+                .flat_map(Resource::warnings)
+                .collect();
+            warnings.extend(config::sri_cross_use(scope.resources()));
+            warnings
+        }
+        _ => Vec::new(),
+    };
+
+    let resources = build_resources(
+        scope.resources(),
+        tag.as_deref(),
+        &overrides,
+        &history,
+        &scope.config().health,
+        clock::now_ms(),
+        visibility,
+    );
+    let control = control::load(env).await;
+    let degraded = control.is_degraded();
+    let control = match visibility {
+        _ if control.is_normal() => None,
+        Visibility::Full => Some(control),
+        _ => Some(Control {
+            enabled: control.enabled,
+            degraded: control.degraded,
+            ..Control::default()
+        }),
+    };
+    let status = StatusResponse {
+        status: "healthy",
+        worker: "linkkivahti",
+        version: &scope.config().version,
+        tenant: scope.id(),
+        environment: Environment::from_env(env),
+        overall: Overall::from_resources(&resources),
+        degraded,
+        control,
+        notifications,
+        warnings,
+        resources,
+    };
+
+    match format {
+        Format::Html => Response::from_html(status.to_html()),
+        Format::Text => Response::ok(status.to_text()),
+        Format::Json | Format::Prometheus => Response::from_json(&status),
+    }
+}
+
+/// Latest results of a scope's resources as a chat message: a count line, then
+/// the failing resources with their firing alert streams
+fn summary_message(
+    label: &str,
+    resources: &[Resource],
+    history: &History,
+    states: &AlertStates,
+) -> String {
+    let mut ok = 0;
+    let mut unknown = 0;
+    let mut failing = Vec::new();
+    for resource in resources {
+        match history.latest(&resource.url) {
+            None => unknown += 1,
+            Some(entry) if entry.ok => ok += 1,
+            Some(entry) => {
+                let firing: Vec<&str> = AlertStream::ALL
+                    .into_iter()
+                    .filter(|&stream| states.is_firing(stream, &resource.alert_key()))
+                    .map(|stream| stream.as_str())
+                    .collect();
+                let mut line = format!("• {} - {}", resource.url, entry.detail);
+                if !firing.is_empty() {
+                    line.push_str(&format!(" (firing: {})", firing.join(", ")));
+                }
+                failing.push(line);
+            }
+        }
+    }
+
+    let icon = if !failing.is_empty() {
+        "🔴"
+    } else if unknown > 0 {
+        "⚪"
+    } else {
+        "🟢"
+    };
+    let mut message = format!("{} {}: {}/{} links ok", icon, label, ok, resources.len());
+    if !failing.is_empty() {
+        message.push_str(&format!(", {} failing", failing.len()));
+    }
+    if unknown > 0 {
+        message.push_str(&format!(", {} not checked yet", unknown));
+    }
+    for line in failing.iter().take(MAX_LISTED) {
+        message.push('\n');
+        message.push_str(line);
+    }
+    if failing.len() > MAX_LISTED {
+        message.push_str(&format!("\n…and {} more", failing.len() - MAX_LISTED));
+    }
+    message
+}
+
+/// Chat summary of the latest results of a scope
+pub async fn chat_summary(env: &Env, scope: &Scope<'_>) -> String {
+    let history = history::load(env, scope).await;
+    let states = match Store::from_env(env) {
+        Some(store) => AlertStates::load(&store, scope).await,
+        None => AlertStates::default(),
+    };
+    summary_message(scope.label(), scope.resources(), &history, &states)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::{CheckError, CheckResult};
+
+    fn resources() -> Vec<Resource> {
+        vec![
+            Resource {
+                url: "https://cdn.example.com/a.js".to_string(),
+                sri: "sha384-aaa".to_string(),
+                name: Some("Widget".to_string()),
+                ..Default::default()
+            },
+            Resource {
+                url: "https://cdn.example.com/b.js".to_string(),
+                sri: "sha384-bbb".to_string(),
+                tags: vec!["prod".to_string()],
+                ..Default::default()
+            },
+            Resource {
+                url: "https://cdn.example.com/c.js".to_string(),
+                sri: "sha384-ccc".to_string(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn history() -> History {
+        let mut history = History::default();
+        history.record(
+            &[
+                CheckResult::success("https://cdn.example.com/a.js", 200, true),
+                CheckResult::failure("https://cdn.example.com/b.js", CheckError::FetchFailed),
+            ],
+            1000,
+        );
+        history
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Format::negotiate(None), Format::Json);
+        // curl
+        assert_eq!(Format::negotiate(Some("*/*")), Format::Json);
+        assert_eq!(Format::negotiate(Some("application/json")), Format::Json);
+        assert_eq!(
+            Format::negotiate(Some(
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+            )),
+            Format::Html
+        );
+        assert_eq!(Format::negotiate(Some("text/plain")), Format::Text);
+        assert_eq!(
+            Format::negotiate(Some("text/plain; version=0.0.4")),
+            Format::Prometheus
+        );
+        // Prometheus scrapers prefer OpenMetrics, which is not supported
+        assert_eq!(
+            Format::negotiate(Some(
+                "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5,*/*;q=0.1"
+            )),
+            Format::Prometheus
+        );
+        assert_eq!(
+            Format::negotiate(Some("text/html;q=0.5, application/json")),
+            Format::Json
+        );
+        assert_eq!(
+            Format::negotiate(Some("text/plain;q=0, image/png")),
+            Format::Json
+        );
+    }
+
+    #[test]
+    fn test_text_and_html() {
+        let resources = build_resources(
+            &resources(),
+            None,
+            &HashMap::new(),
+            &history(),
+            &HealthConfig::default(),
+            2000,
+            Visibility::Minimal,
+        );
+        let mut status = StatusResponse {
+            status: "healthy",
+            worker: "linkkivahti",
+            version: "1.0",
+            tenant: Some("shop"),
+            environment: None,
+            overall: Overall::from_resources(&resources),
+            degraded: false,
+            control: None,
+            notifications: None,
+            warnings: Vec::new(),
+            resources,
+        };
+        assert_eq!(
+            status.to_text(),
+            "linkkivahti/shop degraded: 1 up, 1 down, 1 unknown\n"
+        );
+        let html = status.to_html();
+        assert!(html.contains("<h1>linkkivahti/shop: degraded</h1>"));
+        assert!(html.contains("<tr class=\"up\"><td>Widget</td><td>up</td>"));
+        assert!(html.contains("<td>resource-2</td><td>down</td>"));
+        assert!(!html.contains("cdn.example.com"));
+        assert!(!html.contains("class=\"notice\""));
+
+        // The kill switch is called out in every format
+        status.degraded = true;
+        status.control = Some(Control {
+            enabled: false,
+            reason: Some("CDN <incident>".into()),
+            ..Control::default()
+        });
+        assert_eq!(
+            status.to_text(),
+            "linkkivahti/shop degraded: 1 up, 1 down, 1 unknown (monitoring disabled: CDN <incident>)\n"
+        );
+        assert!(status
+            .to_html()
+            .contains("<p class=\"notice\">⚠ monitoring disabled: CDN &lt;incident&gt;</p>"));
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["degraded"], true);
+        assert_eq!(json["control"]["enabled"], false);
+    }
+
+    #[test]
+    fn test_visibility_from_str() {
+        assert_eq!("full".parse(), Ok(Visibility::Full));
+        assert_eq!("Minimal".parse(), Ok(Visibility::Minimal));
+        assert_eq!("OFF".parse(), Ok(Visibility::Off));
+        assert_eq!("public".parse::<Visibility>(), Err(()));
+    }
+
+    #[test]
+    fn test_build_resources_full() {
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "https://cdn.example.com/b.js".to_string(),
+            "sha384-new".to_string(),
+        );
+
+        let infos = build_resources(
+            &resources(),
+            None,
+            &overrides,
+            &history(),
+            &HealthConfig::default(),
+            2000,
+            Visibility::Full,
+        );
+        assert_eq!(
+            infos[0].url.as_deref(),
+            Some("https://cdn.example.com/a.js")
+        );
+        assert_eq!(infos[0].sri.as_deref(), Some("sha384-aaa"));
+        assert_eq!(infos[0].state, ResourceState::Up);
+        assert_eq!(infos[1].sri.as_deref(), Some("sha384-new"));
+        assert_eq!(infos[1].state, ResourceState::Down);
+        assert_eq!(infos[2].state, ResourceState::Unknown);
+        // A single result is too little for a health score
+        assert_eq!(infos[0].health, None);
+
+        let mut history = history();
+        for at in [2000, 3000] {
+            history.record(
+                &[
+                    CheckResult::success("https://cdn.example.com/a.js", 200, true),
+                    CheckResult::failure("https://cdn.example.com/b.js", CheckError::FetchFailed),
+                ],
+                at,
+            );
+        }
+        let health = HealthConfig::default();
+        let infos = build_resources(
+            &resources(),
+            None,
+            &overrides,
+            &history,
+            &health,
+            3000,
+            Visibility::Full,
+        );
+        assert_eq!(infos[0].health.map(|h| h.score), Some(100));
+        assert_eq!(infos[1].health.map(|h| h.availability), Some(0));
+        assert_eq!(infos[2].health, None);
+    }
+
+    #[test]
+    fn test_build_resources_tagged() {
+        let full = build_resources(
+            &resources(),
+            Some("prod"),
+            &HashMap::new(),
+            &history(),
+            &HealthConfig::default(),
+            2000,
+            Visibility::Full,
+        );
+        assert_eq!(full.len(), 1);
+        assert_eq!(full[0].url.as_deref(), Some("https://cdn.example.com/b.js"));
+        assert_eq!(full[0].tags, ["prod"]);
+        assert_eq!(Overall::from_resources(&full).state, OverallState::Down);
+
+        // Positional names stay those of the unfiltered listing
+        let minimal = build_resources(
+            &resources(),
+            Some("prod"),
+            &HashMap::new(),
+            &history(),
+            &HealthConfig::default(),
+            2000,
+            Visibility::Minimal,
+        );
+        assert_eq!(minimal[0].name.as_deref(), Some("resource-2"));
+    }
+
+    #[test]
+    fn test_overall() {
+        let infos = build_resources(
+            &resources(),
+            None,
+            &HashMap::new(),
+            &history(),
+            &HealthConfig::default(),
+            2000,
+            Visibility::Full,
+        );
+        let overall = Overall::from_resources(&infos);
+        assert_eq!(
+            serde_json::to_string(&overall).unwrap(),
+            r#"{"state":"degraded","up":1,"down":1,"unknown":1}"#
+        );
+
+        assert_eq!(Overall::from_resources(&infos[..1]).state, OverallState::Ok);
+        assert_eq!(
+            Overall::from_resources(&infos[1..]).state,
+            OverallState::Down
+        );
+        assert_eq!(Overall::from_resources(&infos[2..]).state, OverallState::Ok);
+        assert_eq!(Overall::from_resources(&[]).state, OverallState::Ok);
+    }
+
+    #[test]
+    fn test_build_resources_minimal_hides_urls_and_hashes() {
+        let infos = build_resources(
+            &resources(),
+            None,
+            &HashMap::new(),
+            &history(),
+            &HealthConfig::default(),
+            2000,
+            Visibility::Minimal,
+        );
+
+        assert_eq!(infos[0].name.as_deref(), Some("Widget"));
+        assert_eq!(infos[1].name.as_deref(), Some("resource-2"));
+        assert_eq!(infos[1].state, ResourceState::Down);
+        for info in &infos {
+            assert!(info.url.is_none());
+            assert!(info.sri.is_none());
+            assert!(info.tags.is_empty());
+        }
+
+        let json = serde_json::to_string(&infos).unwrap();
+        assert!(!json.contains("cdn.example.com"));
+        assert!(!json.contains("sha384"));
+    }
+
+    #[test]
+    fn test_summary_message() {
+        let states = AlertStates::default();
+        assert_eq!(
+            summary_message("default", &resources(), &History::default(), &states),
+            "⚪ default: 0/3 links ok, 3 not checked yet"
+        );
+        assert_eq!(
+            summary_message("default", &resources(), &history(), &states),
+            "🔴 default: 1/3 links ok, 1 failing, 1 not checked yet\n\
+             • https://cdn.example.com/b.js - Failed: Fetch failed"
+        );
+    }
+}
@@ -0,0 +1,512 @@
+//! Persistent state
+//!
+//! All stateful features share a single key-value store of JSON documents. By
+//! default it is the KV namespace bound as `LINKKIVAHTI_STATE`; `STORAGE_BACKEND`
+//! selects another implementation of `StateStore` that fits the plan and scale
+//! of the deployment:
+//!
+//! - `kv` (default): Workers KV, eventually consistent, cheap reads
+//! - `d1`: a D1 database bound as `LINKKIVAHTI_DB`, one row per key
+//! - `durable`: a Durable Object bound as `LINKKIVAHTI_STATE_OBJECT` (class
+//!   `StateObject`), strongly consistent and without KV's daily write limit
+//!
+//! (`postgres` moves only the history to Postgres, see `history`; the rest stays
+//! in KV.) The store is optional: without it, features that need state are
+//! reported as unavailable while plain link checking keeps working.
+
+use crate::clock;
+use crate::config;
+use crate::console::console_error;
+use crate::database;
+use crate::usage;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use worker::kv::KvStore;
+use worker::wasm_bindgen::JsValue;
+use worker::*;
+
+/// Name of the KV namespace binding in wrangler.toml
+pub const BINDING: &str = "LINKKIVAHTI_STATE";
+
+/// Name of the Durable Object binding in wrangler.toml
+pub const OBJECT_BINDING: &str = "LINKKIVAHTI_STATE_OBJECT";
+
+/// Setting selecting the backend: `kv` (default), `d1`, `durable` or `postgres`
+pub const BACKEND_SETTING: &str = "STORAGE_BACKEND";
+
+/// Key-value storage of JSON documents
+#[allow(async_fn_in_trait)]
+pub trait StateStore {
+    /// Read a value, `None` if it does not exist or has expired
+    async fn get_text(&self, key: &str) -> Result<Option<String>>;
+
+    /// Store a value, expiring after `ttl_secs` seconds if given
+    async fn put_text(&self, key: &str, value: String, ttl_secs: Option<u64>) -> Result<()>;
+
+    /// Delete a key (no-op if it does not exist)
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// List all key names starting with `prefix`, in lexicographic order
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// State in Workers KV
+pub struct KvState(KvStore);
+
+impl StateStore for KvState {
+    async fn get_text(&self, key: &str) -> Result<Option<String>> {
+        usage::kv_read();
+        Ok(self.0.get(key).text().await?)
+    }
+
+    /// KV enforces a minimum TTL of 60 seconds.
+    async fn put_text(&self, key: &str, value: String, ttl_secs: Option<u64>) -> Result<()> {
+        usage::kv_write();
+        let put = self.0.put(key, value)?;
+        match ttl_secs {
+            Some(ttl) => put.expiration_ttl(ttl.max(60)).execute().await?,
+            None => put.execute().await?,
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        usage::kv_delete();
+        Ok(self.0.delete(key).await?)
+    }
+
+    /// Follows pagination cursors.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut builder = self.0.list().prefix(prefix.to_string());
+            if let Some(c) = cursor.take() {
+                builder = builder.cursor(c);
+            }
+            usage::kv_list();
+            let page = builder.execute().await?;
+            names.extend(page.keys.into_iter().map(|k| k.name));
+
+            match page.cursor {
+                Some(c) if !page.list_complete => cursor = Some(c),
+                _ => break,
+            }
+        }
+
+        Ok(names)
+    }
+}
+
+/// Expiry time of a value stored now with a TTL (milliseconds since the Unix epoch)
+fn expires_at(ttl_secs: Option<u64>) -> Option<u64> {
+    ttl_secs.map(|ttl| clock::now_ms().saturating_add(ttl.saturating_mul(1000)))
+}
+
+/// Whether a value with the given expiry time has expired
+fn expired(expires_at: Option<u64>, now_ms: u64) -> bool {
+    expires_at.is_some_and(|at| at <= now_ms)
+}
+
+#[derive(Deserialize)]
+struct KeyRow {
+    key: String,
+}
+
+/// State in a D1 database, one row of `linkkivahti_state` per key
+pub struct D1State(D1Database);
+
+impl D1State {
+    async fn ready(&self) -> Result<&D1Database> {
+        database::migrate(&self.0).await?;
+        Ok(&self.0)
+    }
+}
+
+impl StateStore for D1State {
+    async fn get_text(&self, key: &str) -> Result<Option<String>> {
+        self.ready()
+            .await?
+            .prepare(
+                "SELECT value FROM linkkivahti_state \
+                 WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+            )
+            .bind(&[key.into(), (clock::now_ms() as f64).into()])?
+            .first::<String>(Some("value"))
+            .await
+    }
+
+    /// Expired rows are deleted along with every write that has a TTL.
+    async fn put_text(&self, key: &str, value: String, ttl_secs: Option<u64>) -> Result<()> {
+        let db = self.ready().await?;
+        let expires_at = expires_at(ttl_secs).map_or(JsValue::NULL, |at| (at as f64).into());
+        let upsert = db
+            .prepare(
+                "INSERT INTO linkkivahti_state (key, value, expires_at) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value, expires_at = excluded.expires_at",
+            )
+            .bind(&[key.into(), value.into(), expires_at])?;
+        if ttl_secs.is_none() {
+            upsert.run().await?;
+            return Ok(());
+        }
+        let purge = db
+            .prepare("DELETE FROM linkkivahti_state WHERE expires_at <= ?1")
+            .bind(&[(clock::now_ms() as f64).into()])?;
+        db.batch(vec![purge, upsert]).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.ready()
+            .await?
+            .prepare("DELETE FROM linkkivahti_state WHERE key = ?1")
+            .bind(&[key.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let rows = self
+            .ready()
+            .await?
+            .prepare(
+                "SELECT key FROM linkkivahti_state \
+                 WHERE substr(key, 1, length(?1)) = ?1 AND (expires_at IS NULL OR expires_at > ?2) \
+                 ORDER BY key",
+            )
+            .bind(&[prefix.into(), (clock::now_ms() as f64).into()])?
+            .all()
+            .await?
+            .results::<KeyRow>()?;
+        Ok(rows.into_iter().map(|row| row.key).collect())
+    }
+}
+
+/// Operation sent to the state object
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum StateOp {
+    Get {
+        key: String,
+    },
+    Put {
+        key: String,
+        value: String,
+        #[serde(default)]
+        expires_at: Option<u64>,
+    },
+    Delete {
+        key: String,
+    },
+    List {
+        prefix: String,
+    },
+}
+
+/// A value as kept in the storage of the state object
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredValue {
+    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+impl StoredValue {
+    /// Parse a stored value, `None` if it is unreadable or has expired
+    fn live(json: &str, now_ms: u64) -> Option<Self> {
+        serde_json::from_str::<Self>(json)
+            .ok()
+            .filter(|v| !expired(v.expires_at, now_ms))
+    }
+}
+
+/// State in a single Durable Object (`StateObject`)
+pub struct ObjectState(Stub);
+
+impl ObjectState {
+    fn open(namespace: ObjectNamespace) -> Result<Self> {
+        Ok(Self(namespace.id_from_name("state")?.get_stub()?))
+    }
+
+    async fn call<T: DeserializeOwned>(&self, op: &StateOp) -> Result<T> {
+        let headers = Headers::new();
+        headers.set("Content-Type", "application/json")?;
+        let mut init = RequestInit::new();
+        init.with_method(Method::Post);
+        init.with_headers(headers);
+        init.with_body(Some(serde_json::to_string(op)?.into()));
+
+        let request = Request::new_with_init("https://state.linkkivahti/", &init)?;
+        usage::subrequest();
+        let mut response = self.0.fetch_with_request(request).await?;
+        if response.status_code() != 200 {
+            return Err(Error::RustError(format!(
+                "State object returned {}",
+                response.status_code()
+            )));
+        }
+        response.json().await
+    }
+}
+
+impl StateStore for ObjectState {
+    async fn get_text(&self, key: &str) -> Result<Option<String>> {
+        let key = key.to_string();
+        self.call(&StateOp::Get { key }).await
+    }
+
+    async fn put_text(&self, key: &str, value: String, ttl_secs: Option<u64>) -> Result<()> {
+        let key = key.to_string();
+        let expires_at = expires_at(ttl_secs);
+        self.call(&StateOp::Put {
+            key,
+            value,
+            expires_at,
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let key = key.to_string();
+        self.call(&StateOp::Delete { key }).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let prefix = prefix.to_string();
+        self.call(&StateOp::List { prefix }).await
+    }
+}
+
+/// Durable Object holding the state with `STORAGE_BACKEND = "durable"`
+///
+/// Values are kept as JSON strings with their expiry time; expired values are
+/// skipped on read and deleted when listed.
+#[durable_object]
+pub struct StateObject {
+    state: State,
+}
+
+impl StateObject {
+    async fn apply(&self, op: StateOp) -> Result<Response> {
+        let storage = self.state.storage();
+        let now = clock::now_ms();
+        match op {
+            StateOp::Get { key } => {
+                let stored = storage.get::<String>(&key).await.ok();
+                let value = stored
+                    .and_then(|json| StoredValue::live(&json, now))
+                    .map(|v| v.value);
+                Response::from_json(&value)
+            }
+            StateOp::Put {
+                key,
+                value,
+                expires_at,
+            } => {
+                let stored = serde_json::to_string(&StoredValue { value, expires_at })?;
+                storage.put(&key, stored).await?;
+                Response::from_json(&())
+            }
+            StateOp::Delete { key } => {
+                storage.delete(&key).await?;
+                Response::from_json(&())
+            }
+            StateOp::List { prefix } => {
+                let entries = storage
+                    .list_with_options(ListOptions::new().prefix(&prefix))
+                    .await?;
+                let mut keys = Vec::new();
+                let mut stale = Vec::new();
+                entries.for_each(&mut |value, key| {
+                    let Some(key) = key.as_string() else {
+                        return;
+                    };
+                    let json = value.as_string().unwrap_or_default();
+                    match StoredValue::live(&json, now) {
+                        Some(_) => keys.push(key),
+                        None => stale.push(key),
+                    }
+                });
+                for key in stale {
+                    storage.delete(&key).await?;
+                }
+                Response::from_json(&keys)
+            }
+        }
+    }
+}
+
+impl DurableObject for StateObject {
+    fn new(state: State, _env: Env) -> Self {
+        Self { state }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        if req.method() != Method::Post {
+            return Response::error("Method Not Allowed", 405);
+        }
+        let op: StateOp = req.json().await?;
+        self.apply(op).await
+    }
+}
+
+/// The backend selected by `STORAGE_BACKEND`
+enum Backend {
+    Kv(KvState),
+    D1(D1State),
+    Object(ObjectState),
+}
+
+/// Typed access to the state store of the deployment
+pub struct Store {
+    backend: Backend,
+}
+
+impl Store {
+    /// Open the state store, or `None` if it is not configured
+    ///
+    /// A missing KV binding silently leaves state unavailable; a selected
+    /// backend that cannot be opened is logged.
+    pub fn from_env(env: &Env) -> Option<Self> {
+        match config::setting(env, BACKEND_SETTING).as_deref() {
+            None | Some("kv") | Some("postgres") => env.kv(BINDING).ok().map(|kv| Self {
+                backend: Backend::Kv(KvState(kv)),
+            }),
+            _ => Self::require(env)
+                .inspect_err(|e| console_error!("State store unavailable: {}", e))
+                .ok(),
+        }
+    }
+
+    /// Open the state store, failing with a descriptive error if it is not configured
+    pub fn require(env: &Env) -> Result<Self> {
+        let missing = |binding: &str, kind: &str| {
+            Error::RustError(format!("{} {} binding not configured", binding, kind))
+        };
+        let backend = match config::setting(env, BACKEND_SETTING).as_deref() {
+            None | Some("kv") | Some("postgres") => Backend::Kv(KvState(
+                env.kv(BINDING).map_err(|_| missing(BINDING, "KV"))?,
+            )),
+            Some("d1") => Backend::D1(D1State(database::require(env)?)),
+            Some("durable") => Backend::Object(ObjectState::open(
+                env.durable_object(OBJECT_BINDING)
+                    .map_err(|_| missing(OBJECT_BINDING, "Durable Object"))?,
+            )?),
+            Some(other) => {
+                return Err(Error::RustError(format!(
+                    "Unknown {} '{}' (expected kv, d1, durable or postgres)",
+                    BACKEND_SETTING, other
+                )))
+            }
+        };
+        Ok(Self { backend })
+    }
+
+    /// Read and deserialize a JSON value
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.get_text(key).await? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Serialize and store a JSON value
+    pub async fn put<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let json = serde_json::to_string(value)?;
+        self.put_text(key, json, None).await
+    }
+
+    /// Serialize and store a JSON value that expires after `ttl_secs` seconds
+    ///
+    /// KV enforces a minimum TTL of 60 seconds.
+    pub async fn put_with_ttl<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl_secs: u64,
+    ) -> Result<()> {
+        let json = serde_json::to_string(value)?;
+        self.put_text(key, json, Some(ttl_secs)).await
+    }
+
+    /// Read a raw value, `None` if it does not exist or has expired
+    pub async fn get_text(&self, key: &str) -> Result<Option<String>> {
+        match &self.backend {
+            Backend::Kv(kv) => kv.get_text(key).await,
+            Backend::D1(d1) => d1.get_text(key).await,
+            Backend::Object(object) => object.get_text(key).await,
+        }
+    }
+
+    /// Store a raw value, expiring after `ttl_secs` seconds if given
+    pub async fn put_text(&self, key: &str, value: String, ttl_secs: Option<u64>) -> Result<()> {
+        match &self.backend {
+            Backend::Kv(kv) => kv.put_text(key, value, ttl_secs).await,
+            Backend::D1(d1) => d1.put_text(key, value, ttl_secs).await,
+            Backend::Object(object) => object.put_text(key, value, ttl_secs).await,
+        }
+    }
+
+    /// Delete a key (no-op if it does not exist)
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        match &self.backend {
+            Backend::Kv(kv) => kv.delete(key).await,
+            Backend::D1(d1) => d1.delete(key).await,
+            Backend::Object(object) => object.delete(key).await,
+        }
+    }
+
+    /// List all key names starting with `prefix`
+    pub async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        match &self.backend {
+            Backend::Kv(kv) => kv.list(prefix).await,
+            Backend::D1(d1) => d1.list(prefix).await,
+            Backend::Object(object) => object.list(prefix).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry() {
+        assert!(!expired(None, 1000));
+        assert!(!expired(Some(1001), 1000));
+        assert!(expired(Some(1000), 1000));
+
+        let now = clock::now_ms();
+        assert_eq!(expires_at(None), None);
+        assert_eq!(expires_at(Some(60)), Some(now + 60_000));
+
+        let json = r#"{"value":"{\"a\":1}","expires_at":2000}"#;
+        assert_eq!(StoredValue::live(json, 1000).unwrap().value, r#"{"a":1}"#);
+        assert!(StoredValue::live(json, 2000).is_none());
+        assert!(StoredValue::live("not json", 1000).is_none());
+    }
+
+    #[test]
+    fn test_state_op() {
+        let op = StateOp::Put {
+            key: "silences".to_string(),
+            value: "[]".to_string(),
+            expires_at: None,
+        };
+        let json = serde_json::to_string(&op).unwrap();
+        assert_eq!(
+            json,
+            r#"{"op":"put","key":"silences","value":"[]","expires_at":null}"#
+        );
+        assert_eq!(serde_json::from_str::<StateOp>(&json).unwrap(), op);
+        assert_eq!(
+            serde_json::from_str::<StateOp>(r#"{"op":"list","prefix":"sri/"}"#).unwrap(),
+            StateOp::List {
+                prefix: "sri/".to_string()
+            }
+        );
+    }
+}
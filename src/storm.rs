@@ -0,0 +1,215 @@
+//! Storm mode for very large failure storms
+//!
+//! A full CDN outage fails hundreds of resources in the same run, and one webhook
+//! per alert floods the channel and runs into the webhook's rate limits. When more
+//! than `STORM_THRESHOLD` (default 20, `0` disables storm mode) alerts of a scope
+//! are due in one run, only the first `STORM_SAMPLE` (default 3) firing alerts are
+//! sent as usual. The others, resolved ones included, are listed in one summary
+//! notification per alert stream, which links to the scope's status page
+//! (`PUBLIC_URL`) for the full list of failing resources.
+//!
+//! Alert state, events and incidents are unaffected: summarized alerts fire and
+//! resolve like the sampled ones.
+
+use crate::alert::AlertStream;
+use crate::config;
+use crate::console::console_error;
+use crate::events::{Event, EventKind};
+use crate::notify;
+use crate::tenant::Scope;
+use worker::*;
+
+const DEFAULT_THRESHOLD: usize = 20;
+
+const DEFAULT_SAMPLE: usize = 3;
+
+/// Alerts listed in a summary notification
+const MAX_SUMMARY_LINES: usize = 20;
+
+/// When a run's alerts are summarized, and how many are still sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StormPolicy {
+    /// Due alerts above which a run is a storm
+    pub threshold: usize,
+    /// Firing alerts of a storm sent individually
+    pub sample: usize,
+}
+
+/// Storm policy from `STORM_THRESHOLD` and `STORM_SAMPLE`, `None` if disabled
+pub fn policy(env: &Env) -> Option<StormPolicy> {
+    let number = |name: &str, default: usize| {
+        config::setting(env, name)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    let threshold = number("STORM_THRESHOLD", DEFAULT_THRESHOLD);
+    (threshold > 0).then(|| StormPolicy {
+        threshold,
+        sample: number("STORM_SAMPLE", DEFAULT_SAMPLE),
+    })
+}
+
+impl StormPolicy {
+    /// Which of a run's due alerts, given whether each is resolved, are sent
+    /// individually: all of them, unless they are a storm
+    pub fn sampled(&self, resolved: &[bool]) -> Vec<bool> {
+        if resolved.len() <= self.threshold {
+            return vec![true; resolved.len()];
+        }
+        let mut remaining = self.sample;
+        resolved
+            .iter()
+            .map(|&resolved| {
+                let sampled = !resolved && remaining > 0;
+                remaining -= usize::from(sampled);
+                sampled
+            })
+            .collect()
+    }
+}
+
+/// An alert of a storm listed in its summary instead of being sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summarized {
+    pub stream: AlertStream,
+    pub resolved: bool,
+    pub url: String,
+    pub detail: String,
+}
+
+/// Status page of a scope, where the full list of failing resources is
+fn status_url(env: &Env, scope: &Scope<'_>) -> Option<String> {
+    let base = config::setting(env, "PUBLIC_URL")?;
+    Some(format!("{}{}", base.trim_end_matches('/'), scope.path("/")))
+}
+
+/// Summary of the alerts of one stream that a storm held back
+fn message(due: usize, sent: usize, alerts: &[&Summarized], status_url: Option<&str>) -> String {
+    let resolved = alerts.iter().filter(|a| a.resolved).count();
+    let mut message = format!(
+        "{} alerts due in one run: {} sent individually, {} summarized here ({} failing, {} resolved)",
+        due,
+        sent,
+        alerts.len(),
+        alerts.len() - resolved,
+        resolved
+    );
+    for alert in alerts.iter().take(MAX_SUMMARY_LINES) {
+        let state = if alert.resolved {
+            "resolved"
+        } else {
+            "failing"
+        };
+        message.push_str(&format!("\n• {} {} - {}", alert.url, state, alert.detail));
+    }
+    if alerts.len() > MAX_SUMMARY_LINES {
+        message.push_str(&format!("\n…and {} more", alerts.len() - MAX_SUMMARY_LINES));
+    }
+    if let Some(url) = status_url {
+        message.push_str(&format!("\nFull list: {}", url));
+    }
+    message
+}
+
+/// Send the summaries of a storm, one per alert stream, and log each summarized
+/// alert as notified (or not) with its summary
+pub async fn send_summaries(
+    env: &Env,
+    scope: &Scope<'_>,
+    due: usize,
+    alerts: Vec<Summarized>,
+    now: u64,
+    events: &mut Vec<Event>,
+) {
+    let sent = due - alerts.len();
+    let status_url = status_url(env, scope);
+    console_error!(
+        "Alert storm ({}): {} alerts due, {} sent, {} summarized",
+        scope.label(),
+        due,
+        sent,
+        alerts.len()
+    );
+    for stream in [AlertStream::Availability, AlertStream::Integrity] {
+        let of_stream: Vec<&Summarized> = alerts.iter().filter(|a| a.stream == stream).collect();
+        if of_stream.is_empty() {
+            continue;
+        }
+        let text = message(due, sent, &of_stream, status_url.as_deref());
+        let subject = status_url.as_deref().unwrap_or(scope.label());
+        let outcome = notify::send_storm_notification(env, scope, stream, subject, &text).await;
+        if let Err(e) = &outcome {
+            console_error!("Failed to send storm summary ({}): {}", stream, e);
+        }
+        for alert in of_stream {
+            events.push(match &outcome {
+                Ok(()) => Event::alert(EventKind::Notified, now, &alert.url, stream)
+                    .with_detail("storm summary".to_string()),
+                Err(e) => Event::alert(EventKind::NotificationFailed, now, &alert.url, stream)
+                    .with_detail(e.to_string()),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summarized(url: &str, resolved: bool) -> Summarized {
+        Summarized {
+            stream: AlertStream::Availability,
+            resolved,
+            url: url.to_string(),
+            detail: "Failed: Fetch failed".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sampled() {
+        let policy = StormPolicy {
+            threshold: 4,
+            sample: 2,
+        };
+        // Up to the threshold, everything is sent
+        assert_eq!(policy.sampled(&[false, true, false]), vec![true; 3]);
+
+        // A storm sends only the first firing alerts
+        assert_eq!(
+            policy.sampled(&[true, false, false, true, false]),
+            vec![false, true, true, false, false]
+        );
+
+        let none = StormPolicy {
+            threshold: 1,
+            sample: 0,
+        };
+        assert_eq!(none.sampled(&[false, false]), vec![false, false]);
+    }
+
+    #[test]
+    fn test_message() {
+        let alerts: Vec<Summarized> = (0..22)
+            .map(|i| summarized(&format!("https://cdn.example.com/{}.js", i), i == 21))
+            .collect();
+        let refs: Vec<&Summarized> = alerts.iter().collect();
+        let text = message(25, 3, &refs, Some("https://linkkivahti.example.com/t/web/"));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "25 alerts due in one run: 3 sent individually, 22 summarized here (21 failing, 1 resolved)"
+        );
+        assert_eq!(
+            lines[1],
+            "• https://cdn.example.com/0.js failing - Failed: Fetch failed"
+        );
+        assert_eq!(lines[21], "…and 2 more");
+        assert_eq!(
+            lines[22],
+            "Full list: https://linkkivahti.example.com/t/web/"
+        );
+
+        let text = message(25, 3, &refs[..1], None);
+        assert_eq!(text.lines().count(), 2);
+    }
+}
@@ -0,0 +1,360 @@
+//! Config sync from a remote Git repository
+//!
+//! When `CONFIG_SYNC_URL` (or `CONFIG_URL`) points at a raw config.toml, or the
+//! same config as JSON (e.g. on GitHub), every cron run fetches it with
+//! `If-None-Match`, validates it and stores it in the state store, where it
+//! replaces the compiled-in config. Monitored resources can then be managed
+//! through pull requests without redeploying the worker.
+//!
+//! If `CONFIG_SYNC_PUBLIC_KEY` (base64 Ed25519 public key) is set, the fetched file
+//! must come with a detached base64 signature at `CONFIG_SYNC_SIGNATURE_URL`
+//! (default: `{CONFIG_SYNC_URL}.sig`). Configs that fail to download, verify or
+//! validate are rejected and the previous config stays active.
+
+use crate::clock;
+use crate::config::{self, Config};
+use crate::console::{console_error, console_log};
+use crate::envconfig;
+use crate::managed;
+use crate::sri::SriHash;
+use crate::store::Store;
+use crate::usage;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use worker::*;
+
+/// KV key of the synced config document
+const SYNCED_KEY: &str = "config/synced";
+
+/// Settings naming the remote config, in order of precedence
+const SOURCE_SETTINGS: &[&str] = &["CONFIG_SYNC_URL", "CONFIG_URL"];
+
+/// Parsed synced config, cached per isolate together with its source text
+static ACTIVE: Mutex<Option<(String, Arc<Config>)>> = Mutex::new(None);
+
+/// A fetched and validated config, as stored in the state store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedConfig {
+    /// URL the config was fetched from
+    pub source: String,
+    /// ETag of the fetched file, used for conditional requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// When the config was fetched
+    pub fetched_at: String,
+    /// Raw config.toml contents, or the config as JSON
+    pub toml: String,
+}
+
+/// Result of a sync attempt
+#[derive(Debug, Serialize)]
+#[serde(tag = "result", rename_all = "lowercase")]
+pub enum SyncOutcome {
+    /// `CONFIG_SYNC_URL` is not set
+    Disabled,
+    /// The remote file has not changed since the last sync
+    Unchanged,
+    /// A new config was stored and is now active
+    Updated {
+        version: String,
+        resources: usize,
+        /// Config warnings, e.g. lookalike hostnames (see `Config::warnings`)
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<String>,
+    },
+}
+
+/// Check a fetched or stored config beyond what parsing guarantees
+///
+/// A broken remote config must never replace a working one, so every hash and URL
+/// is checked up front instead of failing on each run.
+pub fn validate(config: &Config) -> std::result::Result<(), String> {
+    let tenant_resources = config.tenants.iter().flat_map(|t| &t.resources);
+    let mut count = 0;
+    for resource in config.resources.iter().chain(tenant_resources) {
+        count += 1;
+        Url::parse(&resource.url).map_err(|e| format!("Invalid URL '{}': {}", resource.url, e))?;
+        if !resource.sri.is_empty() {
+            SriHash::parse(&resource.sri)
+                .map_err(|e| format!("Invalid SRI for {}: {}", resource.url, e.description()))?;
+        }
+    }
+    if count == 0 {
+        return Err("Config has no resources".to_string());
+    }
+    Ok(())
+}
+
+/// URL of the remote config, if sync is enabled
+fn source(env: &Env) -> Option<String> {
+    SOURCE_SETTINGS
+        .iter()
+        .find_map(|name| config::setting(env, name))
+}
+
+/// Verify a detached base64 Ed25519 signature over the fetched bytes
+fn verify_signature(
+    public_key: &str,
+    body: &[u8],
+    signature: &str,
+) -> std::result::Result<(), String> {
+    let key_bytes: [u8; 32] = BASE64
+        .decode(public_key.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or("CONFIG_SYNC_PUBLIC_KEY is not a base64 Ed25519 public key")?;
+    let key = VerifyingKey::from_bytes(&key_bytes)
+        .map_err(|_| "CONFIG_SYNC_PUBLIC_KEY is not a valid Ed25519 public key")?;
+
+    let signature_bytes: [u8; 64] = BASE64
+        .decode(signature.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or("Signature is not a base64 Ed25519 signature")?;
+
+    key.verify(body, &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| "Config signature verification failed".to_string())
+}
+
+/// Get the config that is currently in effect
+///
+/// This is the base config (see `base_config`) with the resources added through
+/// the API (see `managed`) and the emergency overrides from the environment
+/// applied (see `envconfig`).
+pub async fn active_config(env: &Env) -> Arc<Config> {
+    let config = managed::apply(env, base_config(env).await).await;
+    envconfig::effective(env, config)
+}
+
+/// Get the config before environment overrides
+///
+/// Returns the synced config if sync is enabled and a config has been synced from
+/// the configured URL, otherwise the runtime config (see `config::runtime`).
+pub async fn base_config(env: &Env) -> Arc<Config> {
+    match synced_config(env).await {
+        Some(config) => config,
+        None => config::runtime(env).await,
+    }
+}
+
+/// Get the config synced from `CONFIG_SYNC_URL`, if any
+async fn synced_config(env: &Env) -> Option<Arc<Config>> {
+    let source = source(env)?;
+    let store = Store::from_env(env)?;
+
+    let synced = match store.get::<SyncedConfig>(SYNCED_KEY).await {
+        Ok(Some(synced)) if synced.source == source => synced,
+        Ok(_) => return None,
+        Err(e) => {
+            console_error!("Failed to load synced config: {}", e);
+            return None;
+        }
+    };
+
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((text, config)) = active.as_ref() {
+        if *text == synced.toml {
+            return Some(Arc::clone(config));
+        }
+    }
+    match Config::from_text(&synced.toml) {
+        Ok(config) => {
+            let config = Arc::new(config);
+            *active = Some((synced.toml, Arc::clone(&config)));
+            Some(config)
+        }
+        Err(e) => {
+            // Only validated configs are stored, so this means a schema change
+            console_error!("Stored synced config is invalid, ignoring it: {}", e);
+            None
+        }
+    }
+}
+
+/// Fetch a URL, optionally as a conditional request
+async fn fetch(url: &str, etag: Option<&str>) -> Result<Response> {
+    let headers = Headers::new();
+    if let Some(etag) = etag {
+        headers.set("If-None-Match", etag)?;
+    }
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get);
+    init.with_headers(headers);
+    usage::subrequest();
+    Fetch::Request(Request::new_with_init(url, &init)?)
+        .send()
+        .await
+}
+
+/// Fetch the remote config and store it if it changed and passes verification
+pub async fn run(env: &Env) -> Result<SyncOutcome> {
+    let Some(source) = source(env) else {
+        return Ok(SyncOutcome::Disabled);
+    };
+    let store = Store::require(env)?;
+
+    // A changed source URL invalidates the stored ETag
+    let previous = store
+        .get::<SyncedConfig>(SYNCED_KEY)
+        .await?
+        .filter(|s| s.source == source);
+    let etag = previous.as_ref().and_then(|s| s.etag.as_deref());
+
+    let mut response = fetch(&source, etag).await?;
+    match response.status_code() {
+        304 => return Ok(SyncOutcome::Unchanged),
+        200..=299 => {}
+        status => {
+            return Err(Error::RustError(format!(
+                "Config sync fetch returned HTTP {}",
+                status
+            )))
+        }
+    }
+    let new_etag = response.headers().get("ETag")?;
+    let body = response.bytes().await?;
+    usage::downloaded(body.len());
+
+    if let Some(public_key) = config::setting(env, "CONFIG_SYNC_PUBLIC_KEY") {
+        let signature_url = config::setting(env, "CONFIG_SYNC_SIGNATURE_URL")
+            .unwrap_or_else(|| format!("{}.sig", source));
+        let mut signature_response = fetch(&signature_url, None).await?;
+        if !(200..300).contains(&signature_response.status_code()) {
+            return Err(Error::RustError(format!(
+                "Config signature fetch returned HTTP {}",
+                signature_response.status_code()
+            )));
+        }
+        let signature = signature_response.text().await?;
+        verify_signature(&public_key, &body, &signature).map_err(Error::RustError)?;
+    }
+
+    let text = String::from_utf8(body)
+        .map_err(|_| Error::RustError("Synced config is not valid UTF-8".to_string()))?;
+    let config = Config::from_text(&text)
+        .map_err(|e| Error::RustError(format!("Synced config does not parse: {}", e)))?;
+    validate(&config).map_err(Error::RustError)?;
+
+    // Servers without ETag support re-serve the same file; skip the KV write then
+    if previous.as_ref().is_some_and(|p| p.toml == text) {
+        return Ok(SyncOutcome::Unchanged);
+    }
+
+    let outcome = SyncOutcome::Updated {
+        version: config.version.clone(),
+        resources: config.resources.len()
+            + config
+                .tenants
+                .iter()
+                .map(|t| t.resources.len())
+                .sum::<usize>(),
+        warnings: config.warnings(),
+    };
+    store
+        .put(
+            SYNCED_KEY,
+            &SyncedConfig {
+                source,
+                etag: new_etag,
+                fetched_at: clock::timestamp(),
+                toml: text,
+            },
+        )
+        .await?;
+    console_log!("Config synced: {:?}", outcome);
+    Ok(outcome)
+}
+
+/// Sync the config at the start of a scheduled run, logging failures
+pub async fn run_scheduled(env: &Env) {
+    if let Err(e) = run(env).await {
+        console_error!("Config sync failed, keeping previous config: {}", e);
+    }
+}
+
+/// Handle `POST /config/sync`: sync immediately and report the outcome
+pub async fn handle_sync(env: &Env) -> Result<Response> {
+    match run(env).await {
+        Ok(outcome) => Response::from_json(&outcome),
+        Err(e) => Response::error(format!("Config sync failed: {}", e), 502),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const VALID_SRI: &str = "sha256-uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=";
+
+    fn parse(text: &str) -> Config {
+        Config::from_toml(text).unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = format!(
+            "version = \"2.0\"\n[[resources]]\nurl = \"https://example.com/a.js\"\nsri = \"{}\"",
+            VALID_SRI
+        );
+        assert!(validate(&parse(&valid)).is_ok());
+
+        let bad_sri = "version = \"2.0\"\n[[resources]]\nurl = \"https://example.com/a.js\"\nsri = \"sha384-abc\"";
+        assert!(validate(&parse(bad_sri))
+            .unwrap_err()
+            .contains("Invalid SRI"));
+
+        let bad_url = format!(
+            "version = \"2.0\"\n[[tenants]]\nid = \"web\"\n[[tenants.resources]]\nurl = \"not a url\"\nsri = \"{}\"",
+            VALID_SRI
+        );
+        assert!(Config::from_toml(&bad_url)
+            .unwrap_err()
+            .contains("Invalid URL"));
+
+        assert!(validate(&parse("version = \"2.0\"")).is_err());
+    }
+
+    #[test]
+    fn test_json_config() {
+        let toml = format!(
+            "version = \"2.0\"\n[schedule]\nmax_subrequests = 50\n[[resources]]\nurl = \"HTTPS://Example.com/a.js\"\nsri = \"{}\"",
+            VALID_SRI
+        );
+        let from_toml = Config::from_text(&toml).unwrap();
+        let json = serde_json::to_string_pretty(&from_toml).unwrap();
+        let from_json = Config::from_text(&format!("\n  {}", json)).unwrap();
+        assert_eq!(from_json.resources[0].url, "https://example.com/a.js");
+        assert_eq!(from_json.schedule.max_subrequests, Some(50));
+        assert!(validate(&from_json).is_ok());
+
+        // JSON is validated like TOML
+        let invalid = json.replace("https://example.com/a.js", "not a url");
+        assert!(Config::from_text(&invalid)
+            .unwrap_err()
+            .contains("Invalid URL"));
+        assert!(Config::from_text("{\"resources\": []}").is_err());
+    }
+
+    #[test]
+    fn test_compiled_config_passes_sync_validation() {
+        assert!(validate(&config::compiled()).is_ok());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let public_key = BASE64.encode(signing_key.verifying_key().as_bytes());
+        let body = b"version = \"2.0\"";
+        let signature = BASE64.encode(signing_key.sign(body).to_bytes());
+
+        assert!(verify_signature(&public_key, body, &signature).is_ok());
+        assert!(verify_signature(&public_key, body, &format!("{}\n", signature)).is_ok());
+        assert!(verify_signature(&public_key, b"version = \"3.0\"", &signature).is_err());
+        assert!(verify_signature(&public_key, body, "bm90IGEgc2lnbmF0dXJl").is_err());
+        assert!(verify_signature("c2hvcnQ=", body, &signature).is_err());
+    }
+}
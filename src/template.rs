@@ -0,0 +1,86 @@
+//! Minimal `{{name}}` placeholder templates for strings in config.toml
+//!
+//! Only plain variable substitution is supported; whitespace inside the braces is
+//! ignored. Placeholders are checked when the config is loaded, so rendering never
+//! fails at check time.
+
+/// Iterate over the placeholder names used in a template
+pub fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    let mut rest = template;
+    std::iter::from_fn(move || {
+        let start = rest.find("{{")?;
+        let end = rest[start..].find("}}")? + start;
+        let name = rest[start + 2..end].trim();
+        rest = &rest[end + 2..];
+        Some(name)
+    })
+}
+
+/// Check that a template only uses the given placeholder names
+pub fn validate(template: &str, known: &[&str]) -> Result<(), String> {
+    match placeholders(template).find(|name| !known.contains(name)) {
+        Some(name) => Err(format!(
+            "Unknown placeholder '{{{{{}}}}}' (available: {})",
+            name,
+            known.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Replace placeholders with the values returned by `lookup`
+///
+/// Placeholders without a value are left as they are.
+pub fn render(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|e| e + start) else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        match lookup(rest[start + 2..end].trim()) {
+            Some(value) => output.push_str(&value),
+            None => output.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "name" => Some("api".to_string()),
+            "now_ms" => Some("1000".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(r#"{"id": {{ now_ms }}, "src": "{{name}}"}"#, lookup),
+            r#"{"id": 1000, "src": "api"}"#
+        );
+        assert_eq!(render("{{unknown}} {{name", lookup), "{{unknown}} {{name");
+        assert_eq!(render("no placeholders", lookup), "no placeholders");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(
+            placeholders("{{a}} and {{ b }}").collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert!(validate("{{name}}", &["name", "url"]).is_ok());
+        assert_eq!(
+            validate("{{nmae}}", &["name", "url"]).unwrap_err(),
+            "Unknown placeholder '{{nmae}}' (available: name, url)"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! Tenant scoping
+//!
+//! One deployment can serve several teams. The top-level `[[resources]]` form the
+//! default scope; each `[[tenants]]` section defines an isolated scope with its own
+//! resources, webhook destination, state namespace (`t/{id}/` KV prefix) and
+//! endpoints under `/t/{id}/...`.
+
+use crate::clock;
+use crate::config::{self, Config, Resource, Tenant};
+use crate::maintenance;
+use worker::Url;
+
+/// URL path prefix of tenant endpoints
+const TENANT_PATH_PREFIX: &str = "/t/";
+
+/// A set of resources sharing state, notifications and endpoints
+#[derive(Debug, Clone, Copy)]
+pub struct Scope<'a> {
+    config: &'a Config,
+    tenant: Option<&'a Tenant>,
+}
+
+impl<'a> Scope<'a> {
+    /// The default scope made of the top-level resources
+    pub fn root(config: &'a Config) -> Self {
+        Self {
+            config,
+            tenant: None,
+        }
+    }
+
+    /// Look up a tenant scope by id
+    pub fn tenant(config: &'a Config, id: &str) -> Option<Self> {
+        config
+            .tenants
+            .iter()
+            .find(|t| t.id == id)
+            .map(|tenant| Self {
+                config,
+                tenant: Some(tenant),
+            })
+    }
+
+    /// The default scope followed by all tenant scopes
+    pub fn all(config: &'a Config) -> Vec<Self> {
+        std::iter::once(Self::root(config))
+            .chain(config.tenants.iter().map(|tenant| Self {
+                config,
+                tenant: Some(tenant),
+            }))
+            .collect()
+    }
+
+    /// The configuration this scope belongs to
+    pub fn config(&self) -> &'a Config {
+        self.config
+    }
+
+    /// Tenant id, or `None` for the default scope
+    pub fn id(&self) -> Option<&'a str> {
+        self.tenant.map(|t| t.id.as_str())
+    }
+
+    /// Short label for logs
+    pub fn label(&self) -> &'a str {
+        self.id().unwrap_or("default")
+    }
+
+    /// Resources monitored in this scope
+    pub fn resources(&self) -> &'a [Resource] {
+        match self.tenant {
+            Some(t) => &t.resources,
+            None => &self.config.resources,
+        }
+    }
+
+    /// Find a resource of this scope by its URL, in any spelling that normalizes
+    /// to the configured one
+    pub fn find_resource(&self, url: &str) -> Option<&'a Resource> {
+        let find = |url: &str| self.resources().iter().find(|r| r.url == url);
+        find(url).or_else(|| find(&config::normalize_url(url).ok()?))
+    }
+
+    /// Namespace a state store key for this scope
+    ///
+    /// Default scope keys are unprefixed, so single-tenant deployments keep their state.
+    pub fn key(&self, key: &str) -> String {
+        match self.id() {
+            Some(id) => format!("t/{}/{}", id, key),
+            None => key.to_string(),
+        }
+    }
+
+    /// Full endpoint path of a path within this scope
+    pub fn path(&self, path: &str) -> String {
+        match self.id() {
+            Some(id) => format!("{}{}{}", TENANT_PATH_PREFIX, id, path),
+            None => path.to_string(),
+        }
+    }
+
+    /// Name of the secret holding this scope's webhook URL
+    ///
+    /// Tenants use their configured `webhook_secret`, or `WEBHOOK_URL_<ID>` by default.
+    pub fn webhook_secret(&self) -> String {
+        match self.tenant {
+            None => "WEBHOOK_URL".to_string(),
+            Some(t) => t.webhook_secret.clone().unwrap_or_else(|| {
+                let suffix: String =
+                    t.id.chars()
+                        .map(|c| {
+                            if c.is_ascii_alphanumeric() {
+                                c.to_ascii_uppercase()
+                            } else {
+                                '_'
+                            }
+                        })
+                        .collect();
+                format!("WEBHOOK_URL_{}", suffix)
+            }),
+        }
+    }
+
+    /// Optional webhook service override configured for this scope's tenant
+    pub fn webhook_service(&self) -> Option<&'a str> {
+        self.tenant.and_then(|t| t.webhook_service.as_deref())
+    }
+}
+
+/// Resources of each scope selected by a tag (see `Resource::has_tag`), leaving
+/// out paused resources (see `maintenance`) and scopes without any
+pub fn tagged<'a>(scopes: &[Scope<'a>], tag: &str) -> Vec<(Scope<'a>, Vec<&'a Resource>)> {
+    let now = clock::now_ms();
+    scopes
+        .iter()
+        .map(|scope| {
+            let resources = scope
+                .resources()
+                .iter()
+                .filter(|r| r.has_tag(tag) && maintenance::paused(r, now).is_none());
+            (*scope, resources.collect::<Vec<_>>())
+        })
+        .filter(|(_, resources)| !resources.is_empty())
+        .collect()
+}
+
+/// Tag selecting resources with a `?tag=` query parameter, if given
+pub fn requested_tag(url: &Url) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == "tag")
+        .map(|(_, tag)| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+}
+
+/// Split a request path into an optional tenant id and the path within the scope
+///
+/// `/t/web/check` becomes `(Some("web"), "/check")` and `/t/web` becomes `(Some("web"), "/")`.
+pub fn split_path(path: &str) -> (Option<&str>, &str) {
+    let Some(rest) = path.strip_prefix(TENANT_PATH_PREFIX) else {
+        return (None, path);
+    };
+    match rest.find('/') {
+        Some(idx) => (Some(&rest[..idx]), &rest[idx..]),
+        None => (Some(rest), "/"),
+    }
+}
+
+/// Check that a tenant id is usable in paths, KV keys and secret names
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://example.com/root.js"
+            sri = "sha384-root"
+
+            [[tenants]]
+            id = "web"
+
+            [[tenants.resources]]
+            url = "https://example.com/web.js"
+            sri = "sha384-web"
+
+            [[tenants]]
+            id = "data-platform"
+            webhook_secret = "DATA_HOOK"
+            webhook_service = "slack"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_scopes() {
+        let config = config();
+        let scopes = Scope::all(&config);
+        assert_eq!(scopes.len(), 3);
+        assert_eq!(scopes[0].id(), None);
+        assert_eq!(scopes[0].label(), "default");
+        assert_eq!(scopes[1].id(), Some("web"));
+
+        let web = Scope::tenant(&config, "web").unwrap();
+        assert_eq!(web.resources().len(), 1);
+        assert!(web.find_resource("https://example.com/web.js").is_some());
+        assert_eq!(
+            web.find_resource("HTTPS://Example.com:443/./web.js")
+                .map(|r| r.url.as_str()),
+            Some("https://example.com/web.js")
+        );
+        assert!(web.find_resource("https://example.com/root.js").is_none());
+        assert!(Scope::tenant(&config, "unknown").is_none());
+    }
+
+    #[test]
+    fn test_scope_keys_and_paths() {
+        let config = config();
+        assert_eq!(Scope::root(&config).key("history"), "history");
+        assert_eq!(
+            Scope::tenant(&config, "web").unwrap().key("history"),
+            "t/web/history"
+        );
+        assert_eq!(Scope::root(&config).path("/check"), "/check");
+        assert_eq!(
+            Scope::tenant(&config, "web").unwrap().path("/check"),
+            "/t/web/check"
+        );
+    }
+
+    #[test]
+    fn test_webhook_destination() {
+        let config = config();
+        assert_eq!(Scope::root(&config).webhook_secret(), "WEBHOOK_URL");
+        assert_eq!(
+            Scope::tenant(&config, "web").unwrap().webhook_secret(),
+            "WEBHOOK_URL_WEB"
+        );
+
+        let data = Scope::tenant(&config, "data-platform").unwrap();
+        assert_eq!(data.webhook_secret(), "DATA_HOOK");
+        assert_eq!(data.webhook_service(), Some("slack"));
+        assert_eq!(Scope::root(&config).webhook_service(), None);
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(split_path("/"), (None, "/"));
+        assert_eq!(split_path("/check"), (None, "/check"));
+        assert_eq!(split_path("/t/web"), (Some("web"), "/"));
+        assert_eq!(split_path("/t/web/"), (Some("web"), "/"));
+        assert_eq!(split_path("/t/web/check"), (Some("web"), "/check"));
+        assert_eq!(
+            split_path("/t/web/sri/pending/x/approve"),
+            (Some("web"), "/sri/pending/x/approve")
+        );
+    }
+
+    #[test]
+    fn test_is_valid_id() {
+        assert!(is_valid_id("web"));
+        assert!(is_valid_id("data-platform_2"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id("Web"));
+        assert!(!is_valid_id("a/b"));
+    }
+
+    #[test]
+    fn test_tagged() {
+        let config = Config::from_toml(
+            r#"
+            version = "1.0"
+
+            [[resources]]
+            url = "https://example.com/a.js"
+            sri = "sha384-a"
+            group = "frontend"
+
+            [[resources]]
+            url = "https://example.com/b.js"
+            sri = "sha384-b"
+            tags = ["prod"]
+
+            [[tenants]]
+            id = "web"
+
+            [[tenants.resources]]
+            url = "https://example.com/c.js"
+            sri = "sha384-c"
+            labels = { team = "frontend" }
+            "#,
+        )
+        .unwrap();
+        let selected = tagged(&Scope::all(&config), "frontend");
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected[0].1[0].url, "https://example.com/a.js");
+        assert_eq!(selected[1].0.id(), Some("web"));
+        assert!(tagged(&Scope::all(&config), "backend").is_empty());
+        let selected = tagged(&Scope::all(&config), "prod");
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].1[0].url, "https://example.com/b.js");
+        // Only the given scopes are searched
+        let web = Scope::tenant(&config, "web").unwrap();
+        assert!(tagged(&[web], "prod").is_empty());
+
+        // Paused resources are not checked by tag either
+        let mut config = config.clone();
+        config.resources[0].enabled = Some(false);
+        config.tenants[0].resources[0].maintenance = vec!["0 9 * * * 2h".to_string()];
+        assert!(tagged(&Scope::all(&config), "frontend").is_empty());
+    }
+
+    #[test]
+    fn test_requested_tag() {
+        let tag = |query: &str| {
+            requested_tag(&Url::parse(&format!("https://w.example/check{}", query)).unwrap())
+        };
+        assert_eq!(tag("?tag=prod"), Some("prod".to_string()));
+        assert_eq!(tag("?tag=team%3Dweb"), Some("team=web".to_string()));
+        assert_eq!(tag("?tag="), None);
+        assert_eq!(tag(""), None);
+    }
+}
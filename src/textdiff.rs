@@ -0,0 +1,198 @@
+//! Line-based unified diffs of text bodies
+//!
+//! A small diff for the content of watched resources (see `watch`), kept here to
+//! stay clear of diff crates in the Worker bundle. Lines shared at the start and
+//! end are skipped first; the rest is compared with a longest common subsequence
+//! table, which is bounded in size, so a large rewrite shows up as all old lines
+//! removed and all new lines added.
+
+/// Largest number of differing lines on either side compared line by line
+const MAX_COMPARED_LINES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Edit script turning `old` into `new`
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<(Op, &str)> = old[..prefix].iter().map(|&l| (Op::Equal, l)).collect();
+    if a.len() > MAX_COMPARED_LINES || b.len() > MAX_COMPARED_LINES {
+        ops.extend(a.iter().map(|&l| (Op::Delete, l)));
+        ops.extend(b.iter().map(|&l| (Op::Insert, l)));
+    } else {
+        // lcs[i][j]: length of the longest common subsequence of a[i..] and b[j..]
+        let width = b.len() + 1;
+        let mut lcs = vec![0u16; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * width + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                ops.push((Op::Equal, a[i]));
+                i += 1;
+                j += 1;
+            } else if j == b.len()
+                || (i < a.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+            {
+                ops.push((Op::Delete, a[i]));
+                i += 1;
+            } else {
+                ops.push((Op::Insert, b[j]));
+                j += 1;
+            }
+        }
+    }
+    ops.extend(old[old.len() - suffix..].iter().map(|&l| (Op::Equal, l)));
+    ops
+}
+
+/// Unified diff of two texts with `context` unchanged lines around each change
+///
+/// Returns only the hunks (`@@ -1,3 +1,4 @@` headers and prefixed lines), or an
+/// empty string if the texts have the same lines.
+pub fn unified(old: &str, new: &str, context: usize) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let ops = edits(&old, &new);
+    let changes: Vec<usize> = (0..ops.len()).filter(|&i| ops[i].0 != Op::Equal).collect();
+
+    // Ranges of ops shown per hunk, merging changes whose context overlaps
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changes {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    for (start, end) in hunks {
+        // Line numbers of the hunk's first line in both texts
+        let old_start = ops[..start]
+            .iter()
+            .filter(|(op, _)| *op != Op::Insert)
+            .count();
+        let new_start = ops[..start]
+            .iter()
+            .filter(|(op, _)| *op != Op::Delete)
+            .count();
+        let hunk = &ops[start..end];
+        let old_len = hunk.iter().filter(|(op, _)| *op != Op::Insert).count();
+        let new_len = hunk.iter().filter(|(op, _)| *op != Op::Delete).count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_len > 0),
+            old_len,
+            new_start + usize::from(new_len > 0),
+            new_len
+        ));
+        for (op, line) in hunk {
+            let marker = match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            };
+            out.push(marker);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Added and removed lines of a unified diff
+pub fn stats(diff: &str) -> (usize, usize) {
+    diff.lines().fold((0, 0), |(added, removed), line| {
+        match line.as_bytes().first() {
+            Some(b'+') => (added + 1, removed),
+            Some(b'-') => (added, removed + 1),
+            _ => (added, removed),
+        }
+    })
+}
+
+/// First `max_lines` lines of a diff, noting how many were left out
+pub fn excerpt(diff: &str, max_lines: usize) -> String {
+    let total = diff.lines().count();
+    let mut lines: Vec<&str> = diff.lines().take(max_lines).collect();
+    let note;
+    if total > max_lines {
+        note = format!("… {} more lines", total - max_lines);
+        lines.push(&note);
+    }
+    lines.join("\n")
+}
+
+/// Cut text to at most `max_bytes`, at a character boundary
+pub fn truncate(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nb\nC\nd\ne\nf\ng\nh\ni\n";
+        assert_eq!(
+            unified(old, new, 1),
+            "@@ -2,3 +2,3 @@\n b\n-c\n+C\n d\n@@ -8,1 +8,2 @@\n h\n+i\n"
+        );
+        // Nearby changes share a hunk
+        assert_eq!(
+            unified("a\nb\nc\n", "x\nb\ny\n", 1),
+            "@@ -1,3 +1,3 @@\n-a\n+x\n b\n-c\n+y\n"
+        );
+        assert_eq!(unified("same\n", "same", 3), "");
+        assert_eq!(unified("", "new\n", 3), "@@ -0,0 +1,1 @@\n+new\n");
+    }
+
+    #[test]
+    fn test_large_rewrites_are_bounded() {
+        let old: String = (0..600).map(|i| format!("old {}\n", i)).collect();
+        let new: String = (0..600).map(|i| format!("new {}\n", i)).collect();
+        let diff = unified(&old, &new, 0);
+        assert_eq!(stats(&diff), (600, 600));
+    }
+
+    #[test]
+    fn test_excerpt_and_truncate() {
+        let diff = "@@ -1,2 +1,2 @@\n-a\n+b\n c\n";
+        assert_eq!(stats(diff), (1, 1));
+        assert_eq!(excerpt(diff, 2), "@@ -1,2 +1,2 @@\n-a\n… 2 more lines");
+        assert_eq!(excerpt(diff, 10), diff.trim_end());
+        assert_eq!(truncate("häh", 2), "h");
+        assert_eq!(truncate("abc", 10), "abc");
+    }
+}
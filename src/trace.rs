@@ -0,0 +1,142 @@
+//! Run IDs for correlating logs, notifications and stored results
+//!
+//! Every fetch and scheduled invocation gets a short random ID. Log lines are
+//! prefixed with it (see `console`), notifications carry it in their metadata, and
+//! history entries and incidents record the run that produced them, so an alert can
+//! be traced back to the log lines of the exact run in Workers Logs or
+//! `wrangler tail`. HTTP responses return it in the `X-Request-Id` header.
+//!
+//! Invocations share an isolate and interleave at `.await` points, so the ID is
+//! not a plain global: `in_run` sets it around every poll of the invocation's
+//! future and restores the previous one afterwards.
+
+use crate::clock;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Response header carrying the run ID of an HTTP request
+pub const HEADER: &str = "X-Request-Id";
+
+thread_local! {
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Generate a new run ID
+pub fn new_id() -> String {
+    format!("{:x}{:08x}", clock::now_ms(), random())
+}
+
+#[cfg(not(test))]
+fn random() -> u32 {
+    (worker::js_sys::Math::random() * f64::from(u32::MAX)) as u32
+}
+
+/// Sequential values for tests, where `Math.random` is unavailable
+#[cfg(test)]
+fn random() -> u32 {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// ID of the run being polled, `None` outside of `in_run`
+pub fn current() -> Option<String> {
+    CURRENT.with(|id| id.borrow().clone())
+}
+
+/// A future polled with a run ID set
+pub struct InRun<F> {
+    id: String,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for InRun<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let previous = CURRENT.with(|id| id.replace(Some(self.id.clone())));
+        let poll = self.future.as_mut().poll(cx);
+        CURRENT.with(|id| *id.borrow_mut() = previous);
+        poll
+    }
+}
+
+/// Run a future with the given run ID
+pub fn in_run<F: Future>(id: String, future: F) -> InRun<F> {
+    InRun {
+        id,
+        future: Box::pin(future),
+    }
+}
+
+/// Run a future with the current run ID, for work that outlives the invocation
+/// (`Context::wait_until`); outside of a run a new ID is generated
+pub fn in_current_run<F: Future>(future: F) -> InRun<F> {
+    in_run(current().unwrap_or_else(new_id), future)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::future;
+
+    #[test]
+    fn test_in_run() {
+        assert_eq!(current(), None);
+        let id = block_on(in_run("abc".to_string(), async { current() }));
+        assert_eq!(id.as_deref(), Some("abc"));
+        assert_eq!(current(), None);
+
+        // Nested runs restore the outer ID
+        let ids = block_on(in_run("outer".to_string(), async {
+            let inner = in_run("inner".to_string(), async { current() }).await;
+            (inner, current())
+        }));
+        assert_eq!(ids.0.as_deref(), Some("inner"));
+        assert_eq!(ids.1.as_deref(), Some("outer"));
+    }
+
+    #[test]
+    fn test_interleaved_runs() {
+        // Two runs polled alternately each see their own ID after every await
+        let run = |id: &'static str| {
+            in_run(id.to_string(), async move {
+                let mut seen = Vec::new();
+                for _ in 0..3 {
+                    seen.push(current().unwrap());
+                    yield_now().await;
+                }
+                seen
+            })
+        };
+        let (a, b) = block_on(future::join(run("a"), run("b")));
+        assert_eq!(a, ["a", "a", "a"]);
+        assert_eq!(b, ["b", "b", "b"]);
+    }
+
+    #[test]
+    fn test_new_id() {
+        let (a, b) = (new_id(), new_id());
+        assert_ne!(a, b);
+        assert!(a.starts_with(&format!("{:x}", clock::now_ms())));
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    /// Yield once to the executor
+    async fn yield_now() {
+        let mut yielded = false;
+        future::poll_fn(|cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
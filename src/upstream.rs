@@ -0,0 +1,173 @@
+//! Upstream provider status correlation
+//!
+//! A resource can name the status page of the provider it is served from
+//! (`upstream_status`), e.g. `https://www.githubstatus.com` or
+//! `https://www.cloudflarestatus.com`. When one of its alerts fires, the page's
+//! Statuspage API (`/api/v2/incidents/unresolved.json`) is queried and an active
+//! incident is added to the alert as "upstream incident in progress: <link>".
+//! With `UPSTREAM_INCIDENT_SEVERITY` set, such alerts are also downgraded to at
+//! most that severity.
+//!
+//! Feeds are only fetched for alerts being sent, at most once per feed and run.
+
+use crate::alert::Severity;
+use crate::config;
+use crate::console::console_error;
+use crate::fetch::{Fetcher, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::collections::HashMap;
+use worker::*;
+
+/// Path of the unresolved incidents of a Statuspage page
+const UNRESOLVED_PATH: &str = "/api/v2/incidents/unresolved.json";
+
+/// An unresolved incident reported by a provider
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UpstreamIncident {
+    pub name: String,
+    /// Link to the incident page
+    #[serde(default)]
+    pub shortlink: Option<String>,
+}
+
+impl UpstreamIncident {
+    /// Annotation for alerts of affected resources
+    pub fn annotation(&self) -> String {
+        match &self.shortlink {
+            Some(link) => format!("upstream incident in progress: {} ({})", link, self.name),
+            None => format!("upstream incident in progress: {}", self.name),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UnresolvedIncidents {
+    #[serde(default)]
+    incidents: Vec<UpstreamIncident>,
+}
+
+/// API URL of a status page; JSON URLs are used as given
+fn feed_url(status_page: &str) -> String {
+    if status_page.ends_with(".json") {
+        status_page.to_string()
+    } else {
+        format!("{}{}", status_page.trim_end_matches('/'), UNRESOLVED_PATH)
+    }
+}
+
+/// Most recent unresolved incident of a status page, if any
+async fn fetch_incident(
+    fetcher: &impl Fetcher,
+    status_page: &str,
+) -> Result<Option<UpstreamIncident>> {
+    let request = HttpRequest::new(Method::Get, feed_url(status_page))
+        .with_header("Accept", "application/json");
+    let mut response = fetcher.fetch(request).await?;
+    if !(200..300).contains(&response.status_code()) {
+        return Err(Error::RustError(format!(
+            "Status page returned HTTP {}",
+            response.status_code()
+        )));
+    }
+    let feed: UnresolvedIncidents = serde_json::from_slice(&response.bytes().await?)?;
+    Ok(feed.incidents.into_iter().next())
+}
+
+/// Upstream incidents looked up during a run, one request per status page
+#[derive(Default)]
+pub struct UpstreamStatus {
+    incidents: HashMap<String, Option<UpstreamIncident>>,
+}
+
+impl UpstreamStatus {
+    /// Active incident of a status page; unreachable pages count as no incident
+    pub async fn incident(
+        &mut self,
+        fetcher: &impl Fetcher,
+        status_page: &str,
+    ) -> Option<UpstreamIncident> {
+        if let Some(known) = self.incidents.get(status_page) {
+            return known.clone();
+        }
+        let incident = fetch_incident(fetcher, status_page)
+            .await
+            .unwrap_or_else(|e| {
+                console_error!("Failed to read status page {}: {}", status_page, e);
+                None
+            });
+        self.incidents
+            .insert(status_page.to_string(), incident.clone());
+        incident
+    }
+}
+
+/// Highest severity of alerts during an upstream incident, from `UPSTREAM_INCIDENT_SEVERITY`
+pub fn incident_severity(env: &Env) -> Option<Severity> {
+    let value = config::setting(env, "UPSTREAM_INCIDENT_SEVERITY")?;
+    match value.parse() {
+        Ok(severity) => Some(severity),
+        Err(_) => {
+            console_error!(
+                "Unknown UPSTREAM_INCIDENT_SEVERITY '{}', keeping severities",
+                value
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::mock::{MockFetcher, MockResponse};
+    use futures::executor::block_on;
+
+    const FEED: &str = r#"{
+        "page": {"id": "kctbh9vrtdwd", "name": "GitHub"},
+        "incidents": [{
+            "id": "p3f9v2xq",
+            "name": "Disruption with some GitHub services",
+            "status": "investigating",
+            "impact": "minor",
+            "shortlink": "https://stspg.io/abc123"
+        }]
+    }"#;
+
+    #[test]
+    fn test_feed_url() {
+        assert_eq!(
+            feed_url("https://www.githubstatus.com/"),
+            "https://www.githubstatus.com/api/v2/incidents/unresolved.json"
+        );
+        assert_eq!(
+            feed_url("https://status.example.com/feed.json"),
+            "https://status.example.com/feed.json"
+        );
+    }
+
+    #[test]
+    fn test_incident_lookup() {
+        let github = "https://www.githubstatus.com";
+        let cloudflare = "https://www.cloudflarestatus.com";
+        let fetcher = MockFetcher::new()
+            .respond(&feed_url(github), MockResponse::new(200, FEED))
+            .respond(
+                &feed_url(cloudflare),
+                MockResponse::new(200, r#"{"incidents": []}"#),
+            );
+        let mut status = UpstreamStatus::default();
+
+        let incident = block_on(status.incident(&fetcher, github)).unwrap();
+        assert_eq!(
+            incident.annotation(),
+            "upstream incident in progress: https://stspg.io/abc123 (Disruption with some GitHub services)"
+        );
+        assert!(block_on(status.incident(&fetcher, cloudflare)).is_none());
+        assert!(block_on(status.incident(&fetcher, "https://down.example.com")).is_none());
+
+        // Each page is fetched once per run
+        assert!(block_on(status.incident(&fetcher, github)).is_some());
+        assert!(block_on(status.incident(&fetcher, "https://down.example.com")).is_none());
+        assert_eq!(fetcher.requests().len(), 3);
+    }
+}
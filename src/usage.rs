@@ -0,0 +1,442 @@
+//! Usage accounting against Workers plan limits (`GET /stats/usage`)
+//!
+//! Each invocation meters its outgoing fetches (subrequests), the response bytes it
+//! read, its KV operations and the resources it checked. When the invocation ends,
+//! the meter is added to the totals of the day in a single KV document, next to the
+//! largest invocation of the day, so growth of the resource list can be compared
+//! with the Workers Free plan limits before they are hit.
+//!
+//! Invocations share an isolate, so meters are kept per run ID (see `trace`), like
+//! the run ID itself. Only runs started with `start` are metered; work that
+//! outlives its invocation (`Context::wait_until`) is not.
+//!
+//! The Workers runtime does not expose CPU time (`Date.now()` only advances across
+//! I/O), so CPU time is estimated from the metered work; wall time is the elapsed
+//! time of the invocation including I/O waits. HTTP requests that made no
+//! subrequests are not recorded, to avoid a KV write per status poll; they are
+//! counted in the access log (`GET /stats`) instead.
+
+use crate::clock;
+use crate::console::console_error;
+use crate::store::Store;
+use crate::trace;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use worker::*;
+
+/// KV key of the daily usage totals (not scoped: plan limits apply to the account)
+const USAGE_KEY: &str = "usage";
+
+/// Days of totals kept
+const MAX_DAYS: usize = 90;
+
+/// Days returned by `GET /stats/usage` by default
+const DEFAULT_DAYS: usize = 30;
+
+/// Estimated CPU time of an invocation apart from its subrequests and bytes
+const CPU_MS_BASE: f64 = 1.0;
+
+/// Estimated CPU time of sending a subrequest and parsing its response headers
+const CPU_MS_PER_SUBREQUEST: f64 = 0.2;
+
+/// Response bytes read and hashed per estimated CPU millisecond
+const BYTES_PER_CPU_MS: f64 = 64.0 * 1024.0;
+
+thread_local! {
+    static METERS: RefCell<HashMap<String, Meter>> = RefCell::new(HashMap::new());
+}
+
+/// Work of one invocation so far
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Meter {
+    /// Start of the invocation (milliseconds since the Unix epoch)
+    pub started: u64,
+    pub subrequests: u64,
+    /// Response body bytes read
+    pub bytes: u64,
+    pub kv_reads: u64,
+    pub kv_writes: u64,
+    pub kv_deletes: u64,
+    pub kv_lists: u64,
+    /// Check results produced
+    pub checks: u64,
+}
+
+impl Meter {
+    /// Estimated CPU time in milliseconds
+    pub fn cpu_ms(&self) -> u64 {
+        let estimate = CPU_MS_BASE
+            + self.subrequests as f64 * CPU_MS_PER_SUBREQUEST
+            + self.bytes as f64 / BYTES_PER_CPU_MS;
+        estimate.ceil() as u64
+    }
+}
+
+/// Start metering the run with this ID
+pub fn start(run_id: &str) {
+    let meter = Meter {
+        started: clock::now_ms(),
+        ..Default::default()
+    };
+    METERS.with(|meters| meters.borrow_mut().insert(run_id.to_string(), meter));
+}
+
+/// Stop metering a run and return its meter
+pub fn finish(run_id: &str) -> Option<Meter> {
+    METERS.with(|meters| meters.borrow_mut().remove(run_id))
+}
+
+/// Update the meter of the current run, if it is metered
+fn add(update: impl FnOnce(&mut Meter)) {
+    let Some(id) = trace::current() else {
+        return;
+    };
+    METERS.with(|meters| {
+        if let Some(meter) = meters.borrow_mut().get_mut(&id) {
+            update(meter);
+        }
+    });
+}
+
+/// Count an outgoing fetch
+pub fn subrequest() {
+    add(|m| m.subrequests += 1);
+}
+
+/// Count response body bytes read
+pub fn downloaded(bytes: usize) {
+    add(|m| m.bytes += bytes as u64);
+}
+
+pub fn kv_read() {
+    add(|m| m.kv_reads += 1);
+}
+
+pub fn kv_write() {
+    add(|m| m.kv_writes += 1);
+}
+
+pub fn kv_delete() {
+    add(|m| m.kv_deletes += 1);
+}
+
+pub fn kv_list() {
+    add(|m| m.kv_lists += 1);
+}
+
+/// Count check results
+pub fn checked(count: usize) {
+    add(|m| m.checks += count as u64);
+}
+
+/// Kind of a metered invocation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invocation {
+    Scheduled,
+    Fetch,
+    /// Alarm of the re-check Durable Object
+    Recheck,
+}
+
+impl Invocation {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Scheduled => "scheduled",
+            Self::Fetch => "fetch",
+            Self::Recheck => "recheck",
+        }
+    }
+}
+
+/// Usage totals of a day
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyUsage {
+    /// Recorded invocations by kind (`scheduled`, `fetch`, `recheck`)
+    #[serde(default)]
+    pub invocations: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub subrequests: u64,
+    #[serde(default)]
+    pub bytes: u64,
+    #[serde(default)]
+    pub kv_reads: u64,
+    #[serde(default)]
+    pub kv_writes: u64,
+    #[serde(default)]
+    pub kv_deletes: u64,
+    #[serde(default)]
+    pub kv_lists: u64,
+    #[serde(default)]
+    pub checks: u64,
+    /// Estimated CPU time in milliseconds
+    #[serde(default)]
+    pub cpu_ms: u64,
+    #[serde(default)]
+    pub wall_ms: u64,
+    /// Most subrequests of a single invocation
+    #[serde(default)]
+    pub peak_subrequests: u64,
+    /// Most estimated CPU time of a single invocation
+    #[serde(default)]
+    pub peak_cpu_ms: u64,
+}
+
+impl DailyUsage {
+    /// Add an invocation that took `wall_ms`
+    pub fn add(&mut self, kind: Invocation, meter: &Meter, wall_ms: u64) {
+        *self
+            .invocations
+            .entry(kind.as_str().to_string())
+            .or_default() += 1;
+        self.subrequests += meter.subrequests;
+        self.bytes += meter.bytes;
+        self.kv_reads += meter.kv_reads;
+        self.kv_writes += meter.kv_writes;
+        self.kv_deletes += meter.kv_deletes;
+        self.kv_lists += meter.kv_lists;
+        self.checks += meter.checks;
+        self.cpu_ms += meter.cpu_ms();
+        self.wall_ms += wall_ms;
+        self.peak_subrequests = self.peak_subrequests.max(meter.subrequests);
+        self.peak_cpu_ms = self.peak_cpu_ms.max(meter.cpu_ms());
+    }
+
+    /// Add the totals of another day, keeping the larger peaks
+    fn merge(&mut self, other: &DailyUsage) {
+        for (kind, count) in &other.invocations {
+            *self.invocations.entry(kind.clone()).or_default() += count;
+        }
+        self.subrequests += other.subrequests;
+        self.bytes += other.bytes;
+        self.kv_reads += other.kv_reads;
+        self.kv_writes += other.kv_writes;
+        self.kv_deletes += other.kv_deletes;
+        self.kv_lists += other.kv_lists;
+        self.checks += other.checks;
+        self.cpu_ms += other.cpu_ms;
+        self.wall_ms += other.wall_ms;
+        self.peak_subrequests = self.peak_subrequests.max(other.peak_subrequests);
+        self.peak_cpu_ms = self.peak_cpu_ms.max(other.peak_cpu_ms);
+    }
+}
+
+/// Daily totals by UTC date (`YYYY-MM-DD`)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UsageLog {
+    #[serde(default)]
+    pub days: BTreeMap<String, DailyUsage>,
+}
+
+/// UTC date of a time, e.g. `2025-11-12`
+fn date(ms: u64) -> String {
+    clock::format_ms(ms)[..10].to_string()
+}
+
+impl UsageLog {
+    /// Add an invocation to the day it ended, dropping days beyond `MAX_DAYS`
+    pub fn add(&mut self, kind: Invocation, meter: &Meter, now: u64) {
+        let wall_ms = now.saturating_sub(meter.started);
+        self.days
+            .entry(date(now))
+            .or_default()
+            .add(kind, meter, wall_ms);
+        while self.days.len() > MAX_DAYS {
+            self.days.pop_first();
+        }
+    }
+}
+
+/// Record the meter of a finished invocation
+///
+/// Failures are only logged. The read and write of the usage document itself are
+/// counted too.
+pub async fn record(env: Env, kind: Invocation, mut meter: Meter) {
+    if kind == Invocation::Fetch && meter.subrequests == 0 {
+        return;
+    }
+    let Some(store) = Store::from_env(&env) else {
+        return;
+    };
+    meter.kv_reads += 1;
+    meter.kv_writes += 1;
+    let updated = async {
+        let mut log: UsageLog = store.get(USAGE_KEY).await?.unwrap_or_default();
+        log.add(kind, &meter, clock::now_ms());
+        store.put(USAGE_KEY, &log).await
+    };
+    if let Err(e) = updated.await {
+        console_error!("Failed to update usage totals: {}", e);
+    }
+}
+
+/// Limits of the Workers Free plan that the totals can be compared with
+#[derive(Debug, Serialize)]
+struct Limits {
+    requests_per_day: u64,
+    subrequests_per_invocation: u64,
+    cpu_ms_per_invocation: u64,
+    kv_reads_per_day: u64,
+    kv_writes_per_day: u64,
+    kv_deletes_per_day: u64,
+    kv_lists_per_day: u64,
+}
+
+const FREE_PLAN: Limits = Limits {
+    requests_per_day: 100_000,
+    subrequests_per_invocation: 50,
+    cpu_ms_per_invocation: 10,
+    kv_reads_per_day: 100_000,
+    kv_writes_per_day: 1_000,
+    kv_deletes_per_day: 1_000,
+    kv_lists_per_day: 1_000,
+};
+
+/// Average cost of one checked resource, for forecasting a longer resource list
+#[derive(Debug, PartialEq, Serialize)]
+struct PerCheck {
+    subrequests: f64,
+    bytes: f64,
+    cpu_ms: f64,
+    kv_operations: f64,
+}
+
+impl PerCheck {
+    fn of(totals: &DailyUsage) -> Option<Self> {
+        if totals.checks == 0 {
+            return None;
+        }
+        let checks = totals.checks as f64;
+        let average = |value: u64| (value as f64 / checks * 100.0).round() / 100.0;
+        let kv = totals.kv_reads + totals.kv_writes + totals.kv_deletes + totals.kv_lists;
+        Some(Self {
+            subrequests: average(totals.subrequests),
+            bytes: average(totals.bytes),
+            cpu_ms: average(totals.cpu_ms),
+            kv_operations: average(kv),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Day<'a> {
+    date: &'a str,
+    #[serde(flatten)]
+    usage: &'a DailyUsage,
+}
+
+#[derive(Debug, Serialize)]
+struct UsageResponse<'a> {
+    /// Most recent days first
+    days: Vec<Day<'a>>,
+    totals: DailyUsage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    per_check: Option<PerCheck>,
+    free_plan: Limits,
+}
+
+/// Handle `GET /stats/usage?days=30`
+pub async fn handle_usage(env: &Env, req: &Request) -> Result<Response> {
+    let store = Store::require(env)?;
+    let count = req
+        .url()?
+        .query_pairs()
+        .find(|(k, _)| k == "days")
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_DAYS)
+        .clamp(1, MAX_DAYS);
+    let log: UsageLog = store.get(USAGE_KEY).await?.unwrap_or_default();
+
+    let days: Vec<Day> = log
+        .days
+        .iter()
+        .rev()
+        .take(count)
+        .map(|(date, usage)| Day { date, usage })
+        .collect();
+    let mut totals = DailyUsage::default();
+    days.iter().for_each(|day| totals.merge(day.usage));
+    Response::from_json(&UsageResponse {
+        days,
+        per_check: PerCheck::of(&totals),
+        totals,
+        free_plan: FREE_PLAN,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_meter() {
+        start("run-a");
+        let unmetered = block_on(trace::in_run("run-b".to_string(), async {
+            subrequest();
+            finish("run-b")
+        }));
+        assert_eq!(unmetered, None);
+
+        block_on(trace::in_run("run-a".to_string(), async {
+            subrequest();
+            subrequest();
+            downloaded(200_000);
+            kv_read();
+            kv_write();
+            checked(2);
+        }));
+        let meter = finish("run-a").unwrap();
+        assert_eq!(
+            (meter.subrequests, meter.bytes, meter.kv_reads, meter.checks),
+            (2, 200_000, 1, 2)
+        );
+        // 1 + 2 × 0.2 + 200000 / 65536 = 4.45
+        assert_eq!(meter.cpu_ms(), 5);
+        assert_eq!(finish("run-a"), None);
+    }
+
+    #[test]
+    fn test_usage_log() {
+        let meter = Meter {
+            started: 1_762_941_600_000,
+            subrequests: 12,
+            checks: 10,
+            kv_reads: 4,
+            kv_writes: 2,
+            ..Default::default()
+        };
+        let mut log = UsageLog::default();
+        log.add(Invocation::Scheduled, &meter, 1_762_941_603_000);
+        log.add(
+            Invocation::Fetch,
+            &Meter {
+                subrequests: 30,
+                ..meter.clone()
+            },
+            1_762_941_601_000,
+        );
+        let day = &log.days["2025-11-12"];
+        assert_eq!(day.invocations["scheduled"], 1);
+        assert_eq!(day.invocations["fetch"], 1);
+        assert_eq!(day.subrequests, 42);
+        assert_eq!(day.peak_subrequests, 30);
+        assert_eq!(day.wall_ms, 4_000);
+        assert_eq!(
+            PerCheck::of(day),
+            Some(PerCheck {
+                subrequests: 2.1,
+                bytes: 0.0,
+                cpu_ms: 0.55,
+                kv_operations: 0.6,
+            })
+        );
+
+        for day in 0..100 {
+            log.add(Invocation::Scheduled, &meter, day * 86_400_000);
+        }
+        assert_eq!(log.days.len(), MAX_DAYS);
+        assert!(log.days.contains_key("2025-11-12"));
+        assert!(!log.days.contains_key("1970-01-01"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Informational notices about changing response headers
+//!
+//! Some resources legitimately change while their URL stays the same, such as a
+//! rotating JSON document. With `watch_headers = true`, the `ETag`,
+//! `Last-Modified` and `Content-Length` of each response are compared with the
+//! previous response recorded in the history, and a change is announced as a
+//! notice, independently of SRI verification and the alert streams.
+//!
+//! For watched resources without an SRI hash, the text of the body is kept too:
+//! the last one is stored in KV, and when it changes, a unified diff against it
+//! is stored alongside and the start of the diff is added to the notice.
+
+use crate::checker::CheckResult;
+use crate::clock;
+use crate::console::{console_error, console_log};
+use crate::history::{History, HistoryEntry};
+use crate::notify;
+use crate::store::Store;
+use crate::tenant::Scope;
+use crate::textdiff;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Headers compared between runs
+const WATCHED_HEADERS: &[&str] = &["etag", "last-modified", "content-length"];
+
+/// KV key prefix of the stored bodies, relative to the scope prefix
+const BODY_PREFIX: &str = "watch/body/";
+
+/// Longest body text stored for comparison; longer bodies are cut
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Longest diff stored with a body
+const MAX_DIFF_BYTES: usize = 16 * 1024;
+
+/// Unchanged lines shown around each change
+const DIFF_CONTEXT: usize = 2;
+
+/// Diff lines included in a notice
+const EXCERPT_LINES: usize = 15;
+
+/// Last known body of a watched resource, with the diff from the one before
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BodySnapshot {
+    /// When the body was first seen (milliseconds since the Unix epoch)
+    pub at: u64,
+    pub body: String,
+    /// Unified diff from the previous body, if there was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// Diff between the stored body and a new one, `None` if the lines are the same
+///
+/// The diff is cut to `MAX_DIFF_BYTES`.
+pub fn body_diff(previous: &str, current: &str) -> Option<String> {
+    let diff = textdiff::unified(previous, current, DIFF_CONTEXT);
+    (!diff.is_empty()).then(|| textdiff::truncate(&diff, MAX_DIFF_BYTES).to_string())
+}
+
+/// Notice text about a changed body: line counts and the start of the diff
+pub fn describe_diff(diff: &str) -> String {
+    let (added, removed) = textdiff::stats(diff);
+    format!(
+        "Content changed (+{} -{} lines):\n{}",
+        added,
+        removed,
+        textdiff::excerpt(diff, EXCERPT_LINES)
+    )
+}
+
+/// Compare the body of a result with the stored one and store it if it changed
+///
+/// Returns the diff when there was a previous body to compare with.
+async fn update_body(store: &Store, scope: &Scope<'_>, url: &str, body: &str) -> Option<String> {
+    let key = scope.key(&format!("{}{}", BODY_PREFIX, url));
+    let previous = match store.get::<BodySnapshot>(&key).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            console_error!("Failed to load body snapshot of {}: {}", url, e);
+            return None;
+        }
+    };
+    let diff = match &previous {
+        Some(previous) => Some(body_diff(&previous.body, body)?),
+        None => None,
+    };
+    let snapshot = BodySnapshot {
+        at: clock::now_ms(),
+        body: body.to_string(),
+        diff: diff.clone(),
+    };
+    if let Err(e) = store.put(&key, &snapshot).await {
+        console_error!("Failed to save body snapshot of {}: {}", url, e);
+    }
+    diff
+}
+
+/// Watched headers that differ between the latest response of a resource and the one before
+///
+/// Entries without a response (e.g. fetch failures) are skipped, so a change is
+/// reported once the resource answers again. Returns lines like `etag: "a" → "b"`.
+pub fn changes(entries: &[HistoryEntry]) -> Vec<String> {
+    let Some((latest, earlier)) = entries.split_last() else {
+        return Vec::new();
+    };
+    let Some(previous) = earlier.iter().rev().find(|e| !e.headers.is_empty()) else {
+        return Vec::new();
+    };
+    if latest.headers.is_empty() {
+        return Vec::new();
+    }
+    WATCHED_HEADERS
+        .iter()
+        .filter_map(|&name| {
+            let before = previous.headers.get(name);
+            let after = latest.headers.get(name);
+            (before != after).then(|| {
+                format!(
+                    "{}: {} → {}",
+                    name,
+                    before.map_or("(none)", String::as_str),
+                    after.map_or("(none)", String::as_str)
+                )
+            })
+        })
+        .collect()
+}
+
+/// Send a notice for every watched resource whose headers or body changed in this run
+pub async fn notify_changes(
+    env: &Env,
+    scope: &Scope<'_>,
+    history: &History,
+    results: &[CheckResult],
+) {
+    let store = Store::from_env(env);
+    for result in results {
+        let Some(resource) = scope.find_resource(&result.url).filter(|r| r.watch_headers) else {
+            continue;
+        };
+        let changed = changes(history.entries(&result.url));
+        let diff = match (&result.body, &store) {
+            (Some(body), Some(store)) if resource.sri.is_empty() => {
+                let body = textdiff::truncate(body, MAX_BODY_BYTES);
+                update_body(store, scope, &result.url, body).await
+            }
+            _ => None,
+        };
+        let mut sections = Vec::new();
+        if !changed.is_empty() {
+            console_log!("Headers changed: {} - {}", result.url, changed.join(", "));
+            sections.push(format!("Watched headers changed:\n{}", changed.join("\n")));
+        }
+        if let Some(diff) = &diff {
+            console_log!("Content changed: {}", result.url);
+            sections.push(describe_diff(diff));
+        }
+        if sections.is_empty() {
+            continue;
+        }
+        let message = sections.join("\n\n");
+        if let Err(e) =
+            notify::send_header_change_notification(env, scope, &result.url, &message).await
+        {
+            console_error!("Failed to send header change notification: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn entry(at: u64, headers: &[(&str, &str)]) -> HistoryEntry {
+        HistoryEntry {
+            at,
+            ok: true,
+            status: Some(200),
+            detail: "OK".to_string(),
+            latency_ms: None,
+            sri_valid: None,
+            run_id: None,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        let v1 = entry(1, &[("etag", "\"a\""), ("content-length", "10")]);
+        let v2 = entry(3, &[("etag", "\"b\""), ("server", "nginx")]);
+        assert_eq!(
+            changes(&[v1.clone(), v2.clone()]),
+            vec!["etag: \"a\" → \"b\"", "content-length: 10 → (none)"]
+        );
+        assert!(changes(&[
+            v1.clone(),
+            entry(2, &[("etag", "\"a\""), ("content-length", "10")])
+        ])
+        .is_empty());
+        assert!(changes(std::slice::from_ref(&v1)).is_empty());
+
+        // A failed fetch in between is skipped, and only the run with a response reports
+        let failed = entry(2, &[]);
+        assert_eq!(changes(&[v1.clone(), failed.clone(), v2]).len(), 2);
+        assert!(changes(&[v1, failed]).is_empty());
+    }
+
+    #[test]
+    fn test_body_diff() {
+        assert_eq!(body_diff("a\nb\n", "a\nb"), None);
+        let diff = body_diff("{\n\"v\": 1\n}\n", "{\n\"v\": 2\n}\n").unwrap();
+        assert_eq!(diff, "@@ -1,3 +1,3 @@\n {\n-\"v\": 1\n+\"v\": 2\n }\n");
+        assert_eq!(
+            describe_diff(&diff),
+            "Content changed (+1 -1 lines):\n@@ -1,3 +1,3 @@\n {\n-\"v\": 1\n+\"v\": 2\n }"
+        );
+
+        let long: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let diff = body_diff("", &long).unwrap();
+        assert!(describe_diff(&diff).ends_with("… 86 more lines"));
+    }
+}
@@ -0,0 +1,175 @@
+//! Zabbix low-level discovery and item values
+//!
+//! `GET /zabbix/discovery` lists the scope's resources in the LLD JSON format, so
+//! a Zabbix template can create items per resource through discovery rules.
+//! `GET /zabbix/items` returns the current values of all resources in one
+//! document, keyed by URL, for a master HTTP agent item with dependent items
+//! using JSONPath such as `$["{#URL}"].ok`.
+
+use crate::alert::{AlertStates, AlertStream};
+use crate::config::Resource;
+use crate::history::{self, History};
+use crate::store::Store;
+use crate::tenant::Scope;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use worker::*;
+
+/// A discovered resource with its LLD macros
+#[derive(Debug, Serialize, PartialEq)]
+struct DiscoveryEntry<'a> {
+    #[serde(rename = "{#URL}")]
+    url: &'a str,
+    /// Name of the resource, or its URL
+    #[serde(rename = "{#NAME}")]
+    name: &'a str,
+    #[serde(rename = "{#GROUP}")]
+    group: &'a str,
+}
+
+/// LLD document, in the `data` wrapper understood by all Zabbix versions
+#[derive(Debug, Serialize)]
+struct Discovery<'a> {
+    data: Vec<DiscoveryEntry<'a>>,
+}
+
+/// Current values of a resource
+///
+/// Values are omitted until the resource has a stored result.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ItemValues {
+    /// 1 once the resource has a stored result
+    pub checked: u8,
+    /// 1 if the latest check passed (available and SRI valid)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ok: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Time of the latest check in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_check: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// 1 while the availability alert is firing
+    pub availability_alert: u8,
+    /// 1 while the integrity (SRI mismatch) alert is firing
+    pub integrity_alert: u8,
+}
+
+fn discovery(resources: &[Resource]) -> Discovery<'_> {
+    Discovery {
+        data: resources
+            .iter()
+            .map(|r| DiscoveryEntry {
+                url: &r.url,
+                name: r.name.as_deref().unwrap_or(&r.url),
+                group: r.group.as_deref().unwrap_or(""),
+            })
+            .collect(),
+    }
+}
+
+/// Build the item values of every resource, keyed by URL
+pub fn items(
+    resources: &[Resource],
+    history: &History,
+    states: &AlertStates,
+) -> BTreeMap<String, ItemValues> {
+    resources
+        .iter()
+        .map(|r| {
+            let firing = |stream| u8::from(states.is_firing(stream, &r.alert_key()));
+            let mut values = ItemValues {
+                availability_alert: firing(AlertStream::Availability),
+                integrity_alert: firing(AlertStream::Integrity),
+                ..Default::default()
+            };
+            if let Some(entry) = history.latest(&r.url) {
+                values.checked = 1;
+                values.ok = Some(u8::from(entry.ok));
+                values.status = entry.status;
+                values.latency_ms = entry.latency_ms;
+                values.last_check = Some(entry.at / 1000);
+                values.detail = Some(entry.detail.clone());
+            }
+            (r.url.clone(), values)
+        })
+        .collect()
+}
+
+/// Handle `GET /zabbix/discovery`
+pub fn handle_discovery(scope: &Scope<'_>) -> Result<Response> {
+    Response::from_json(&discovery(scope.resources()))
+}
+
+/// Handle `GET /zabbix/items`
+pub async fn handle_items(env: &Env, scope: &Scope<'_>) -> Result<Response> {
+    let history = history::load(env, scope).await;
+    let states = match Store::from_env(env) {
+        Some(store) => AlertStates::load(&store, scope).await,
+        None => AlertStates::default(),
+    };
+    Response::from_json(&items(scope.resources(), &history, &states))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::CheckResult;
+
+    fn resources() -> Vec<Resource> {
+        vec![
+            Resource {
+                url: "https://example.com/a.js".to_string(),
+                name: Some("Widget".to_string()),
+                group: Some("cdn".to_string()),
+                ..Default::default()
+            },
+            Resource {
+                url: "https://example.com/b.js".to_string(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_discovery() {
+        let resources = resources();
+        let json = serde_json::to_string(&discovery(&resources)).unwrap();
+        assert_eq!(
+            json,
+            r##"{"data":[{"{#URL}":"https://example.com/a.js","{#NAME}":"Widget","{#GROUP}":"cdn"},{"{#URL}":"https://example.com/b.js","{#NAME}":"https://example.com/b.js","{#GROUP}":""}]}"##
+        );
+    }
+
+    #[test]
+    fn test_items() {
+        let resources = resources();
+        let mismatch = CheckResult::success("https://example.com/a.js", 200, false);
+        let mut history = History::default();
+        history.record(std::slice::from_ref(&mismatch), 1_762_941_600_000);
+        let mut states = AlertStates::default();
+        states.evaluate(&mismatch.url, &mismatch, 0, None, 1);
+
+        let items = items(&resources, &history, &states);
+        assert_eq!(
+            items["https://example.com/a.js"],
+            ItemValues {
+                checked: 1,
+                ok: Some(0),
+                status: Some(200),
+                latency_ms: None,
+                last_check: Some(1_762_941_600),
+                detail: Some("SRI mismatch (HTTP 200)".to_string()),
+                availability_alert: 0,
+                integrity_alert: 1,
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&items["https://example.com/b.js"]).unwrap(),
+            r#"{"checked":0,"availability_alert":0,"integrity_alert":0}"#
+        );
+    }
+}